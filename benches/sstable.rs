@@ -0,0 +1,160 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use nulldb::{
+    bench::{Index, Table},
+    DbOptions, MmapOptions,
+};
+use tokio::runtime::Runtime;
+
+const ENTRIES: u64 = 100_000;
+const CONCURRENT_GETS: usize = 64;
+
+fn key(i: u64) -> Vec<u8> {
+    format!("key{:010}", i).into_bytes()
+}
+
+fn entries() -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> {
+    (0..ENTRIES).map(|i| (key(i * 2), vec![b'v'; 100]))
+}
+
+// A cheap, deterministic stand-in for random keys.
+fn next_index(state: &mut u64) -> u64 {
+    *state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+    (*state >> 33) % ENTRIES
+}
+
+fn build(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let options = DbOptions::default();
+
+    let mut group = c.benchmark_group("sstable_build");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(ENTRIES));
+    group.bench_function("100k", |b| {
+        b.to_async(&rt).iter_batched(
+            || tempfile::tempdir().unwrap(),
+            |dir| {
+                let options = &options;
+                async move {
+                    Table::build(dir.path(), options, entries()).await.unwrap();
+                    dir
+                }
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+// The ways of reading a table to compare lookups across, and the name of
+// the group each one's benchmarks are in.
+fn read_options() -> Vec<(&'static str, DbOptions)> {
+    let blocking = ("sstable_get", DbOptions::default());
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    let io_uring = (
+        "sstable_get_io_uring",
+        DbOptions {
+            io_uring: true,
+            ..DbOptions::default()
+        },
+    );
+    let mmap = (
+        "sstable_get_mmap",
+        DbOptions {
+            mmap: Some(MmapOptions::default()),
+            ..DbOptions::default()
+        },
+    );
+    let in_memory = ("sstable_get_in_memory", DbOptions::in_memory());
+    vec![
+        blocking,
+        mmap,
+        in_memory,
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        io_uring,
+    ]
+}
+
+fn lookup(c: &mut Criterion) {
+    for (name, options) in read_options() {
+        lookup_with(c, name, &options);
+    }
+}
+
+fn lookup_with(c: &mut Criterion, name: &str, options: &DbOptions) {
+    let rt = Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let table = rt
+        .block_on(Table::build(dir.path(), options, entries()))
+        .unwrap();
+
+    let mut group = c.benchmark_group(name);
+    let mut state = 1;
+    group.bench_function("hit", |b| {
+        b.to_async(&rt).iter(|| {
+            let key = key(next_index(&mut state) * 2);
+            let table = &table;
+            async move { assert!(table.get(&key).await.unwrap().is_some()) }
+        })
+    });
+    group.bench_function("miss", |b| {
+        b.to_async(&rt).iter(|| {
+            let key = key(next_index(&mut state) * 2 + 1);
+            let table = &table;
+            async move { assert!(table.get(&key).await.unwrap().is_none()) }
+        })
+    });
+    // Many lookups at once, as a server handling many clients would make.
+    group.throughput(Throughput::Elements(CONCURRENT_GETS as u64));
+    group.bench_function("hit_concurrent", |b| {
+        b.to_async(&rt).iter(|| {
+            let keys: Vec<_> = (0..CONCURRENT_GETS)
+                .map(|_| key(next_index(&mut state) * 2))
+                .collect();
+            let table = &table;
+            async move {
+                let gets = keys.iter().map(|key| table.get(key));
+                for value in futures::future::join_all(gets).await {
+                    assert!(value.unwrap().is_some());
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+// The number of blocks in a table of a few hundred MB.
+const BLOCKS: u64 = 65_536;
+
+fn index_search(c: &mut Criterion) {
+    // Big-endian integer keys, evenly spread, which the adaptive search can
+    // interpolate over; and the decimal keys above, which it leaves to the
+    // binary search.
+    let indexes = [
+        (
+            "integer",
+            Index::new((0..BLOCKS).map(|i| (i * 1000).to_be_bytes().to_vec())),
+            (|i: u64| (i * 1000 + 500).to_be_bytes().to_vec()) as fn(u64) -> Vec<u8>,
+        ),
+        (
+            "decimal",
+            Index::new((0..BLOCKS).map(|i| key(i * 1000))),
+            |i| key(i * 1000 + 500),
+        ),
+    ];
+    assert!(indexes[0].1.is_adaptive());
+
+    let mut group = c.benchmark_group("index_search");
+    for (name, index, target) in &indexes {
+        let mut state = 1;
+        group.bench_function(format!("{}/binary", name), |b| {
+            b.iter(|| index.binary(&target(next_index(&mut state) % BLOCKS)))
+        });
+        group.bench_function(format!("{}/adaptive", name), |b| {
+            b.iter(|| index.adaptive(&target(next_index(&mut state) % BLOCKS)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, build, lookup, index_search);
+criterion_main!(benches);
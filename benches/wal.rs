@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nulldb::bench::{encode_binary, encode_json, Wal};
+use tokio::runtime::Runtime;
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wal_encode");
+    let key = b"user:000000001234";
+    for value_size in [16, 256, 4096] {
+        let value = vec![b'x'; value_size];
+        group.throughput(Throughput::Bytes((key.len() + value_size) as u64));
+        group.bench_with_input(BenchmarkId::new("json", value_size), &value, |b, value| {
+            b.iter(|| encode_json(key, value))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("binary", value_size),
+            &value,
+            |b, value| b.iter(|| encode_binary(key, value)),
+        );
+    }
+    group.finish();
+}
+
+fn append(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = rt.block_on(Wal::open(dir.path().join("log"))).unwrap();
+
+    let mut group = c.benchmark_group("wal_append");
+    // Every append is an fsync, so keep the sample count down.
+    group.sample_size(20);
+    for value_size in [16, 4096] {
+        let value = vec![b'x'; value_size];
+        group.bench_with_input(BenchmarkId::new("fsync", value_size), &value, |b, value| {
+            b.iter(|| {
+                rt.block_on(wal.append(b"user:000000001234", value))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, append);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+// Generates the `grpc` feature's service and client from proto/nulldb.proto.
+// It's compiled with protox rather than protoc, so building with the feature
+// doesn't need protoc installed.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/nulldb.proto");
+        let files = protox::compile(["proto/nulldb.proto"], ["proto"])
+            .unwrap_or_else(|err| panic!("proto/nulldb.proto: {}", err));
+        tonic_prost_build::configure()
+            .compile_fds(files)
+            .unwrap_or_else(|err| panic!("generating the gRPC code: {}", err));
+    }
+}
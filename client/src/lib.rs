@@ -0,0 +1,512 @@
+//! A client for `ndb-server`, so applications don't each have to speak its
+//! protocol themselves.
+//!
+//! A [`NulldbClient`] keeps a pool of connections and spreads requests over
+//! them. Each connection carries any number of requests at once, sending
+//! them without waiting for the responses to earlier ones. Requests that
+//! fail with a transient error, a broken connection or a timeout, are tried
+//! again on a new connection, which is safe because every request is
+//! idempotent.
+//!
+//! With the `tls` feature, it can connect over TLS, with the [`rustls`]
+//! re-exported here.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    net::TcpStream,
+    sync::{mpsc, oneshot, Mutex},
+    task::JoinHandle,
+    time,
+};
+#[cfg(feature = "tls")]
+pub use tokio_rustls::rustls;
+
+const GET: u8 = 1;
+const PUT: u8 = 2;
+const DELETE: u8 = 3;
+const SCAN: u8 = 4;
+const AUTH: u8 = 5;
+
+const OK: u8 = 0;
+const NOT_FOUND: u8 = 1;
+const ERROR: u8 = 2;
+
+const HAS_START: u8 = 1;
+const HAS_END: u8 = 2;
+
+pub type KeyValue = (Vec<u8>, Vec<u8>);
+
+pub struct ClientOptions {
+    /// How many connections requests are spread over.
+    pub connections: usize,
+    /// How long to wait for a connection to be made.
+    pub connect_timeout: Duration,
+    /// How long to wait for a response before giving up on it, and on the
+    /// connection it was to come back on.
+    pub timeout: Duration,
+    /// How many more times a request is tried after a transient error.
+    pub retries: u32,
+    /// How long to wait before the first retry. It doubles for each one
+    /// after that.
+    pub retry_backoff: Duration,
+    /// The biggest response accepted.
+    pub max_frame_bytes: u32,
+    /// The token to authenticate with, for a server started with one.
+    pub token: Option<String>,
+    /// Connects over TLS, with this configuration, checking the server's
+    /// certificate is for the host in the address connected to.
+    #[cfg(feature = "tls")]
+    pub tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> ClientOptions {
+        ClientOptions {
+            connections: 4,
+            connect_timeout: Duration::from_secs(5),
+            timeout: Duration::from_secs(10),
+            retries: 3,
+            retry_backoff: Duration::from_millis(50),
+            max_frame_bytes: 64 << 20,
+            token: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The connection couldn't be made, or broke.
+    Io(io::Error),
+    /// No response came within [`ClientOptions::timeout`], or no connection
+    /// within [`ClientOptions::connect_timeout`].
+    Timeout,
+    /// The server couldn't carry out the request, and said why.
+    Server(String),
+    /// The server's response didn't make sense.
+    Protocol(String),
+}
+
+impl ClientError {
+    /// Whether trying again might work. Requests are retried on these
+    /// errors automatically, up to [`ClientOptions::retries`] times.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ClientError::Io(_) | ClientError::Timeout)
+    }
+
+    fn closed() -> ClientError {
+        ClientError::Io(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "connection closed",
+        ))
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(err) => write!(f, "{}", err),
+            ClientError::Timeout => write!(f, "timed out"),
+            ClientError::Server(message) => write!(f, "server error: {}", message),
+            ClientError::Protocol(message) => write!(f, "bad response: {}", message),
+        }
+    }
+}
+
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClientError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> ClientError {
+        ClientError::Io(err)
+    }
+}
+
+/// A page of a scan, from [`NulldbClient::scan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scan {
+    pub entries: Vec<KeyValue>,
+    /// Whether the range has entries past these.
+    pub more: bool,
+}
+
+/// A client for an `ndb-server`. Clones share the same connections.
+#[derive(Clone)]
+pub struct NulldbClient {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    addr: String,
+    options: ClientOptions,
+    // `None` until a connection's needed, and after one's broken.
+    pool: Vec<Mutex<Option<Arc<Connection>>>>,
+    next: AtomicUsize,
+}
+
+impl NulldbClient {
+    pub async fn connect(addr: impl Into<String>) -> Result<NulldbClient, ClientError> {
+        NulldbClient::connect_with(addr, ClientOptions::default()).await
+    }
+
+    /// Connects to the server at `addr`. One connection is made straight
+    /// away, to check the server's there, and the rest as they're needed.
+    pub async fn connect_with(
+        addr: impl Into<String>,
+        options: ClientOptions,
+    ) -> Result<NulldbClient, ClientError> {
+        let pool = (0..options.connections.max(1))
+            .map(|_| Mutex::new(None))
+            .collect();
+        let inner = Inner {
+            addr: addr.into(),
+            options,
+            pool,
+            next: AtomicUsize::new(0),
+        };
+        inner.connection(&inner.pool[0]).await?;
+        Ok(NulldbClient {
+            inner: Arc::new(inner),
+        })
+    }
+
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, ClientError> {
+        let mut request = vec![GET];
+        put_bytes(&mut request, key);
+        let response = self.inner.call(request).await?;
+        let (status, mut fields) = decode(&response)?;
+        match status {
+            OK => Ok(Some(fields.bytes()?)),
+            NOT_FOUND => Ok(None),
+            status => Err(ClientError::Protocol(format!("unknown status {}", status))),
+        }
+    }
+
+    pub async fn put(&self, key: &[u8], value: &[u8]) -> Result<(), ClientError> {
+        let mut request = vec![PUT];
+        put_bytes(&mut request, key);
+        put_bytes(&mut request, value);
+        expect_ok(&self.inner.call(request).await?)
+    }
+
+    pub async fn delete(&self, key: &[u8]) -> Result<(), ClientError> {
+        let mut request = vec![DELETE];
+        put_bytes(&mut request, key);
+        expect_ok(&self.inner.call(request).await?)
+    }
+
+    /// Up to `limit` entries from `start`, included, to `end`, excluded,
+    /// either of which can be left open. The server returns fewer than
+    /// `limit` if that's over its maximum, or if `limit` is 0.
+    pub async fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: u32,
+    ) -> Result<Scan, ClientError> {
+        let mut request = vec![SCAN, 0];
+        if let Some(start) = start {
+            request[1] |= HAS_START;
+            put_bytes(&mut request, start);
+        }
+        if let Some(end) = end {
+            request[1] |= HAS_END;
+            put_bytes(&mut request, end);
+        }
+        request.extend_from_slice(&limit.to_le_bytes());
+        let response = self.inner.call(request).await?;
+        let (status, mut fields) = decode(&response)?;
+        if status != OK {
+            return Err(ClientError::Protocol(format!("unknown status {}", status)));
+        }
+        let more = fields.byte()? == 1;
+        let count = fields.u32()?;
+        let mut entries = Vec::with_capacity(count.min(1024) as usize);
+        for _ in 0..count {
+            entries.push((fields.bytes()?, fields.bytes()?));
+        }
+        Ok(Scan { entries, more })
+    }
+
+    /// Every entry from `start` to `end`, scanned a page at a time.
+    pub async fn scan_all(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<KeyValue>, ClientError> {
+        let mut entries: Vec<KeyValue> = Vec::new();
+        let mut next = start.map(<[u8]>::to_vec);
+        loop {
+            let page = self.scan(next.as_deref(), end, 0).await?;
+            entries.extend(page.entries);
+            match entries.last() {
+                Some((key, _)) if page.more => {
+                    let mut after = key.clone();
+                    after.push(0);
+                    next = Some(after);
+                }
+                _ => return Ok(entries),
+            }
+        }
+    }
+}
+
+impl Inner {
+    async fn call(&self, request: Vec<u8>) -> Result<Vec<u8>, ClientError> {
+        let mut attempt = 0;
+        loop {
+            match self.try_call(&request).await {
+                Err(err) if err.is_transient() && attempt < self.options.retries => {
+                    let backoff = self.options.retry_backoff * (1 << attempt.min(16));
+                    time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_call(&self, request: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let slot = &self.pool[self.next.fetch_add(1, Ordering::Relaxed) % self.pool.len()];
+        let connection = self.connection(slot).await?;
+        let result = connection.call(request, self.options.timeout).await;
+        // After anything but an answer from the server, there's no knowing
+        // what state the connection's in, so the next request gets a new one.
+        if let Err(ClientError::Io(_) | ClientError::Timeout | ClientError::Protocol(_)) = result {
+            let mut slot = slot.lock().await;
+            if slot.as_ref().is_some_and(|c| Arc::ptr_eq(c, &connection)) {
+                *slot = None;
+            }
+        }
+        result
+    }
+
+    async fn connection(
+        &self,
+        slot: &Mutex<Option<Arc<Connection>>>,
+    ) -> Result<Arc<Connection>, ClientError> {
+        let mut slot = slot.lock().await;
+        match &*slot {
+            Some(connection) if !connection.requests.is_closed() => Ok(connection.clone()),
+            _ => {
+                let connection = Arc::new(Connection::open(&self.addr, &self.options).await?);
+                *slot = Some(connection.clone());
+                Ok(connection)
+            }
+        }
+    }
+}
+
+type Reply = oneshot::Sender<Result<Vec<u8>, ClientError>>;
+
+// A connection, with a task sending requests on it and another reading the
+// responses. It's closed once it's dropped.
+struct Connection {
+    requests: mpsc::UnboundedSender<(Vec<u8>, Reply)>,
+    tasks: [JoinHandle<()>; 2],
+}
+
+impl Connection {
+    // Connects, and authenticates if there's a token to.
+    async fn open(addr: &str, options: &ClientOptions) -> Result<Connection, ClientError> {
+        let connect = async {
+            let stream = TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            #[cfg(feature = "tls")]
+            if let Some(config) = &options.tls {
+                let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                let name = rustls::pki_types::ServerName::try_from(host.to_string())
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                let stream = tokio_rustls::TlsConnector::from(config.clone())
+                    .connect(name, stream)
+                    .await?;
+                return Ok(Connection::start(stream, options));
+            }
+            Ok::<_, io::Error>(Connection::start(stream, options))
+        };
+        let connection = time::timeout(options.connect_timeout, connect)
+            .await
+            .map_err(|_| ClientError::Timeout)??;
+        if let Some(token) = &options.token {
+            let mut request = vec![AUTH];
+            put_bytes(&mut request, token.as_bytes());
+            expect_ok(&connection.call(&request, options.timeout).await?)?;
+        }
+        Ok(connection)
+    }
+
+    fn start(
+        stream: impl AsyncRead + AsyncWrite + Send + 'static,
+        options: &ClientOptions,
+    ) -> Connection {
+        let (reader, writer) = tokio::io::split(stream);
+        let (requests, to_send) = mpsc::unbounded_channel();
+        let (sent, to_read) = mpsc::unbounded_channel();
+        let tasks = [
+            tokio::spawn(send_requests(writer, to_send, sent)),
+            tokio::spawn(read_responses(reader, to_read, options.max_frame_bytes)),
+        ];
+        Connection { requests, tasks }
+    }
+
+    async fn call(&self, request: &[u8], timeout: Duration) -> Result<Vec<u8>, ClientError> {
+        let (reply, response) = oneshot::channel();
+        if self.requests.send((request.to_vec(), reply)).is_err() {
+            return Err(ClientError::closed());
+        }
+        match time::timeout(timeout, response).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(ClientError::closed()),
+            Err(_) => Err(ClientError::Timeout),
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+// Sends requests as they come in, and hands each one's reply on to
+// `read_responses`, in the order they were sent. Requests that come in
+// together go out together.
+async fn send_requests(
+    writer: impl AsyncWrite + Unpin,
+    mut requests: mpsc::UnboundedReceiver<(Vec<u8>, Reply)>,
+    sent: mpsc::UnboundedSender<Reply>,
+) {
+    let mut writer = BufWriter::new(writer);
+    while let Some(mut request) = requests.recv().await {
+        loop {
+            let (frame, reply) = request;
+            if sent.send(reply).is_err() {
+                return;
+            }
+            if writer.write_u32_le(frame.len() as u32).await.is_err()
+                || writer.write_all(&frame).await.is_err()
+            {
+                return;
+            }
+            match requests.try_recv() {
+                Ok(next) => request = next,
+                Err(_) => break,
+            }
+        }
+        if writer.flush().await.is_err() {
+            return;
+        }
+    }
+}
+
+// Reads a response for each request sent. Once the connection breaks, the
+// requests still waiting get an error.
+async fn read_responses(
+    reader: impl AsyncRead + Unpin,
+    mut sent: mpsc::UnboundedReceiver<Reply>,
+    max_bytes: u32,
+) {
+    let mut reader = BufReader::new(reader);
+    while let Some(reply) = sent.recv().await {
+        let response = read_frame(&mut reader, max_bytes).await;
+        let broken = response.is_err();
+        let _ = reply.send(response);
+        if broken {
+            return;
+        }
+    }
+}
+
+async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+    max_bytes: u32,
+) -> Result<Vec<u8>, ClientError> {
+    let len = reader.read_u32_le().await?;
+    if len > max_bytes {
+        return Err(ClientError::Protocol(format!(
+            "response of {} bytes is over the limit of {}",
+            len, max_bytes
+        )));
+    }
+    let mut frame = vec![0; len as usize];
+    reader.read_exact(&mut frame).await?;
+    Ok(frame)
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+// Takes fields off the front of a response.
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    fn byte(&mut self) -> Result<u8, ClientError> {
+        let (&byte, rest) = self.0.split_first().ok_or_else(cut_short)?;
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    fn u32(&mut self) -> Result<u32, ClientError> {
+        let (n, rest) = self.0.split_first_chunk().ok_or_else(cut_short)?;
+        self.0 = rest;
+        Ok(u32::from_le_bytes(*n))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, ClientError> {
+        let len = self.u32()? as usize;
+        if len > self.0.len() {
+            return Err(cut_short());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes.to_vec())
+    }
+}
+
+fn cut_short() -> ClientError {
+    ClientError::Protocol("response cut short".into())
+}
+
+// A response's status and the fields after it, or the server's error.
+fn decode(response: &[u8]) -> Result<(u8, Fields<'_>), ClientError> {
+    let mut fields = Fields(response);
+    match fields.byte()? {
+        ERROR => {
+            let message = fields.bytes()?;
+            Err(ClientError::Server(
+                String::from_utf8_lossy(&message).into_owned(),
+            ))
+        }
+        status => Ok((status, fields)),
+    }
+}
+
+fn expect_ok(response: &[u8]) -> Result<(), ClientError> {
+    match decode(response)? {
+        (OK, _) => Ok(()),
+        (status, _) => Err(ClientError::Protocol(format!("unknown status {}", status))),
+    }
+}
@@ -0,0 +1,40 @@
+//! Taking incremental backups of a database and restoring one.
+//!
+//! Run with `cargo run --example backups`.
+
+use nulldb::{BackupEngine, Db, NdbError};
+use tempfile::TempDir;
+
+#[tokio::main]
+pub async fn main() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let backups = TempDir::new()?;
+    let engine = BackupEngine::open(backups.path()).await?;
+
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"config", b"v1").await?;
+    let first = engine.create_backup(&mut db).await?;
+    db.put(b"config", b"v2").await?;
+    // Only what's changed since the last backup is copied.
+    let second = engine.create_backup(&mut db).await?;
+    for backup in engine.list_backups().await? {
+        println!(
+            "backup {}: {} tables, up to sequence {}",
+            backup.id, backup.tables, backup.sequence
+        );
+    }
+    assert_eq!(engine.list_backups().await?, vec![first.clone(), second]);
+
+    // Oops.
+    db.delete(b"config").await?;
+    drop(db);
+
+    let restored = TempDir::new()?;
+    engine.restore_backup_to(first.id, restored.path()).await?;
+    let db = Db::new(restored.path()).await?;
+    assert_eq!(db.get(b"config").await?, Some(b"v1".to_vec()));
+
+    engine.purge_old(1).await?;
+    assert_eq!(engine.list_backups().await?.len(), 1);
+    Ok(())
+}
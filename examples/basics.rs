@@ -0,0 +1,46 @@
+//! Puts, gets, deletes and scans, surviving a flush and a reopen.
+//!
+//! Run with `cargo run --example basics`.
+
+use nulldb::{Db, NdbError, WriteBatch};
+use tempfile::TempDir;
+
+#[tokio::main]
+pub async fn main() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let dir = dir.path();
+
+    let mut db = Db::new(dir).await?;
+    db.put(b"fruit/apple", b"red").await?;
+    db.put(b"fruit/banana", b"yellow").await?;
+    db.put(b"veg/carrot", b"orange").await?;
+    assert_eq!(db.get(b"fruit/apple").await?, Some(b"red".to_vec()));
+
+    // Everything in a batch is applied together, or not at all.
+    let mut batch = WriteBatch::new();
+    batch.put(b"fruit/cherry", b"red");
+    batch.delete(b"fruit/banana");
+    db.write(batch).await?;
+
+    // Move what's been written so far into a table, then keep writing on
+    // top of it.
+    db.flush_memtable().await?;
+    db.put(b"fruit/apple", b"green").await?;
+    db.delete_range(b"veg/", b"veg0").await?;
+    drop(db);
+
+    let db = Db::new(dir).await?;
+    let mut fruit = Vec::new();
+    let mut iter = db.scan(b"fruit/".to_vec()..b"fruit0".to_vec()).await?;
+    while let Some((key, value)) = iter.next().await? {
+        println!(
+            "{} = {}",
+            String::from_utf8_lossy(&key),
+            String::from_utf8_lossy(&value)
+        );
+        fruit.push(key);
+    }
+    assert_eq!(fruit, [b"fruit/apple".to_vec(), b"fruit/cherry".to_vec()]);
+    assert_eq!(db.get(b"veg/carrot").await?, None);
+    Ok(())
+}
@@ -0,0 +1,39 @@
+//! Counters kept with a merge operator, and watching them change.
+//!
+//! Run with `cargo run --example counters`.
+
+use futures::StreamExt;
+use nulldb::{counter_merge_operator, Db, DbOptions, NdbError, WatchEvent};
+use tempfile::TempDir;
+
+#[tokio::main]
+pub async fn main() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        merge_operator: Some(counter_merge_operator()),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    let events = db.watch(b"hits/");
+
+    for page in ["home", "about", "home", "home"] {
+        db.increment(format!("hits/{}", page).as_bytes(), 1).await?;
+    }
+    // Merges keep working across tables: the total is folded together when
+    // it's read.
+    db.flush_memtable().await?;
+    assert_eq!(db.increment(b"hits/home", 1).await?, 4);
+    assert_eq!(db.increment(b"hits/about", -1).await?, 0);
+    drop(db);
+
+    // Each increment is written as an operand, not the new total.
+    let events: Vec<_> = events.collect().await;
+    assert_eq!(events.len(), 6);
+    for event in events {
+        if let WatchEvent::Merge { key, operand, .. } = event {
+            let delta = i64::from_le_bytes(operand.try_into().unwrap());
+            println!("{} {:+}", String::from_utf8_lossy(&key), delta);
+        }
+    }
+    Ok(())
+}
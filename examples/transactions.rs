@@ -0,0 +1,61 @@
+//! Read-modify-write transactions from several tasks at once, retrying
+//! when they conflict.
+//!
+//! Run with `cargo run --example transactions`.
+
+use std::sync::Arc;
+
+use nulldb::{Db, NdbError};
+use tempfile::TempDir;
+
+// Moves `amount` from one account to another, if there's enough in it.
+async fn transfer(db: &Db, from: &[u8], to: &[u8], amount: u64) -> Result<bool, NdbError> {
+    let balance =
+        |value: Option<Vec<u8>>| value.map_or(0, |v| u64::from_le_bytes(v.try_into().unwrap()));
+    loop {
+        let mut txn = db.transaction();
+        let from_balance = balance(txn.get(from).await?);
+        if from_balance < amount {
+            return Ok(false);
+        }
+        let to_balance = balance(txn.get(to).await?);
+        txn.put(from, &(from_balance - amount).to_le_bytes());
+        txn.put(to, &(to_balance + amount).to_le_bytes());
+        match txn.commit().await {
+            Ok(()) => return Ok(true),
+            // Something it read changed before it committed.
+            Err(NdbError::Conflict) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[tokio::main]
+pub async fn main() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Arc::new(Db::new(dir.path()).await?);
+    db.put(b"alice", &100u64.to_le_bytes()).await?;
+    db.put(b"bob", &100u64.to_le_bytes()).await?;
+
+    let tasks: Vec<_> = (0..10)
+        .map(|i| {
+            let db = db.clone();
+            let (from, to) = if i % 2 == 0 {
+                (&b"alice"[..], &b"bob"[..])
+            } else {
+                (&b"bob"[..], &b"alice"[..])
+            };
+            tokio::spawn(async move { transfer(&db, from, to, 10 + i).await })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap()?;
+    }
+
+    let alice = u64::from_le_bytes(db.get(b"alice").await?.unwrap().try_into().unwrap());
+    let bob = u64::from_le_bytes(db.get(b"bob").await?.unwrap().try_into().unwrap());
+    println!("alice has {}, bob has {}", alice, bob);
+    // Money moved around, but none was made or lost.
+    assert_eq!(alice + bob, 200);
+    Ok(())
+}
@@ -0,0 +1,13 @@
+// A block's contents, compressed or not, without its checksum, decoded both
+// as data and as an index.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nulldb::fuzz;
+
+fuzz_target!(|data: &[u8]| {
+    if let Err(err) = fuzz::block(data) {
+        assert!(fuzz::is_clean(&err), "{}", err);
+    }
+});
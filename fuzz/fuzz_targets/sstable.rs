@@ -0,0 +1,12 @@
+// A whole table file: its footer, index, filter and every data block.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nulldb::fuzz;
+
+fuzz_target!(|data: &[u8]| {
+    if let Err(err) = fuzz::sstable(data) {
+        assert!(fuzz::is_clean(&err), "{}", err);
+    }
+});
@@ -0,0 +1,18 @@
+// A log replayed with each kind of recovery.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nulldb::{fuzz, WalRecovery};
+
+fuzz_target!(|data: &[u8]| {
+    for recovery in [
+        WalRecovery::TolerateCorruptedTailRecords,
+        WalRecovery::AbsoluteConsistency,
+        WalRecovery::SkipAnyCorruptedRecords,
+    ] {
+        if let Err(err) = fuzz::wal(data, recovery) {
+            assert!(fuzz::is_clean(&err), "{}", err);
+        }
+    }
+});
@@ -0,0 +1,62 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+use bytes::{Bytes, BytesMut};
+
+// Memtable keys and values are copied into large blocks rather than each
+// getting an allocation of its own. The pieces handed out share their block,
+// which is freed once the last of them is dropped, i.e. with the memtable.
+const BLOCK_SIZE: usize = 64 << 10;
+
+// The first block is small, and each one after is as big as all those before
+// it up to `BLOCK_SIZE`, so a memtable holding a few writes doesn't take up a
+// whole block.
+const FIRST_BLOCK_SIZE: usize = 1 << 10;
+
+// Anything bigger than this gets an allocation of its own, so it doesn't
+// waste most of a block.
+const MAX_SHARED: usize = BLOCK_SIZE / 4;
+
+pub(crate) struct Arena {
+    // What's left of the block being filled.
+    block: Mutex<BytesMut>,
+    // Bytes allocated for blocks so far.
+    blocks: AtomicU64,
+    // Bytes allocated, used or not.
+    allocated: AtomicU64,
+}
+
+impl Arena {
+    pub(crate) fn new() -> Arena {
+        Arena {
+            block: Mutex::new(BytesMut::new()),
+            blocks: AtomicU64::new(0),
+            allocated: AtomicU64::new(0),
+        }
+    }
+
+    // A copy of `data`, in the arena.
+    pub(crate) fn alloc(&self, data: &[u8]) -> Bytes {
+        if data.len() > MAX_SHARED {
+            self.allocated
+                .fetch_add(data.len() as u64, Ordering::Relaxed);
+            return Bytes::copy_from_slice(data);
+        }
+        let mut block = self.block.lock().unwrap();
+        if block.capacity() < data.len() {
+            let blocks = self.blocks.load(Ordering::Relaxed) as usize;
+            let size = blocks.clamp(FIRST_BLOCK_SIZE, BLOCK_SIZE).max(data.len());
+            *block = BytesMut::with_capacity(size);
+            self.blocks.fetch_add(size as u64, Ordering::Relaxed);
+            self.allocated.fetch_add(size as u64, Ordering::Relaxed);
+        }
+        block.extend_from_slice(data);
+        block.split().freeze()
+    }
+
+    pub(crate) fn allocated(&self) -> u64 {
+        self.allocated.load(Ordering::Relaxed)
+    }
+}
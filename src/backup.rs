@@ -0,0 +1,251 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    db::DbMeta,
+    manifest::{self, ManifestWriter},
+    sstable::SSTableMetadata,
+    storage::write_synced,
+    Db, DbOptions, LocalStorage, NdbError, Storage,
+};
+use serde::{Deserialize, Serialize};
+
+// A backup directory holds:
+//
+//   shared/<table>-<size>-<checksum>.sst  table data files, shared between
+//                                         the backups that include them
+//   private/<id>/log                      each backup's WAL, if it has one
+//   backups/<id>.json                     what each backup is made of
+//
+// A backup's file in `backups/` is written last, so one that didn't finish
+// isn't listed, and its files are cleaned up by the next `purge_old`.
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    id: u64,
+    timestamp: u64,
+    sequence: u64,
+    pruned_through: u64,
+    // With data paths relative to the backup directory.
+    tables: Vec<SSTableMetadata>,
+    #[serde(default)]
+    wal: Option<PathBuf>,
+}
+
+/// A backup made by [`BackupEngine::create_backup`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    pub id: u64,
+    /// When the backup was made, in unix seconds by the database's clock.
+    pub timestamp: u64,
+    /// The sequence number of the last write included.
+    pub sequence: u64,
+    /// The total size of the backup's files, counting shared ones in full.
+    pub size: u64,
+    pub tables: usize,
+}
+
+/// Keeps backups of a database in a directory. Backups share any tables
+/// they have in common, so backing up a database that has only had a few
+/// flushes since the last backup only copies the new tables. Backups are
+/// kept on the local file system, and can only be made of databases there.
+pub struct BackupEngine {
+    dir: PathBuf,
+}
+
+impl BackupEngine {
+    pub async fn open(dir: impl AsRef<Path>) -> Result<BackupEngine, NdbError> {
+        let dir = dir.as_ref();
+        for sub in ["shared", "private", "backups"] {
+            tokio::fs::create_dir_all(dir.join(sub)).await?;
+        }
+        Ok(BackupEngine { dir: dir.into() })
+    }
+
+    /// Backs up `db`, flushing its memtable first. See
+    /// [`Db::checkpoint`] for what's included.
+    pub async fn create_backup(&self, db: &mut Db) -> Result<BackupInfo, NdbError> {
+        let id = self.manifests().await?.last().map_or(1, |m| m.id + 1);
+        let private = self.dir.join("private").join(id.to_string());
+        if private.exists() {
+            tokio::fs::remove_dir_all(&private).await?;
+        }
+        db.checkpoint(&private).await?;
+
+        let Some(meta) = manifest::load(&LocalStorage, &private).await? else {
+            return Err(NdbError::NotFound(format!(
+                "checkpoint in {}",
+                private.display()
+            )));
+        };
+        let mut tables = Vec::new();
+        for mut table in meta.sstables {
+            let size = tokio::fs::metadata(&table.data_path).await?.len();
+            let stem = table.data_path.file_stem().unwrap().to_string_lossy();
+            let name = format!("{}-{}-{:08x}.sst", stem, size, table.checksum.unwrap_or(0));
+            let shared = Path::new("shared").join(name);
+            if self.dir.join(&shared).exists() {
+                tokio::fs::remove_file(&table.data_path).await?;
+            } else {
+                tokio::fs::rename(&table.data_path, self.dir.join(&shared)).await?;
+            }
+            table.data_path = shared;
+            tables.push(table);
+        }
+        // Only the log is kept; the tables are listed in the backup's file.
+        let mut entries = tokio::fs::read_dir(&private).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name() != "log" {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+        let wal = Path::new("private").join(id.to_string()).join("log");
+        let wal = self.dir.join(&wal).exists().then_some(wal);
+
+        let manifest = Manifest {
+            id,
+            timestamp: db.options().clock.unix_secs(),
+            sequence: db.latest_sequence(),
+            pruned_through: meta.pruned_through,
+            tables,
+            wal,
+        };
+        let path = self.dir.join("backups").join(format!("{}.json", id));
+        write_synced(&LocalStorage, &path, &serde_json::to_vec(&manifest)?).await?;
+        self.info(&manifest).await
+    }
+
+    /// Every finished backup, oldest first.
+    pub async fn list_backups(&self) -> Result<Vec<BackupInfo>, NdbError> {
+        let mut infos = Vec::new();
+        for manifest in self.manifests().await? {
+            infos.push(self.info(&manifest).await?);
+        }
+        Ok(infos)
+    }
+
+    /// Deletes all but the newest `keep` backups, and any files that only
+    /// they used.
+    pub async fn purge_old(&self, keep: usize) -> Result<(), NdbError> {
+        let mut manifests = self.manifests().await?;
+        let purged = manifests.len().saturating_sub(keep);
+        for manifest in manifests.drain(..purged) {
+            let path = self
+                .dir
+                .join("backups")
+                .join(format!("{}.json", manifest.id));
+            tokio::fs::remove_file(path).await?;
+        }
+
+        let ids: BTreeSet<_> = manifests.iter().map(|m| m.id.to_string()).collect();
+        let mut private = tokio::fs::read_dir(self.dir.join("private")).await?;
+        while let Some(entry) = private.next_entry().await? {
+            if !ids.contains(&*entry.file_name().to_string_lossy()) {
+                tokio::fs::remove_dir_all(entry.path()).await?;
+            }
+        }
+        let used: BTreeSet<_> = manifests
+            .iter()
+            .flat_map(|m| &m.tables)
+            .map(|table| self.dir.join(&table.data_path))
+            .collect();
+        let mut shared = tokio::fs::read_dir(self.dir.join("shared")).await?;
+        while let Some(entry) = shared.next_entry().await? {
+            if !used.contains(&entry.path()) {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores the newest backup into `dir`, which must be empty or not
+    /// exist yet.
+    pub async fn restore_to(&self, dir: impl AsRef<Path>) -> Result<(), NdbError> {
+        let Some(latest) = self.manifests().await?.pop() else {
+            return Err(NdbError::NotFound("there are no backups".into()));
+        };
+        self.restore(&latest, dir.as_ref()).await
+    }
+
+    /// Restores the backup with the given id into `dir`, which must be empty
+    /// or not exist yet.
+    pub async fn restore_backup_to(&self, id: u64, dir: impl AsRef<Path>) -> Result<(), NdbError> {
+        let manifests = self.manifests().await?;
+        let Some(manifest) = manifests.iter().find(|m| m.id == id) else {
+            return Err(NdbError::NotFound(format!("backup {}", id)));
+        };
+        self.restore(manifest, dir.as_ref()).await
+    }
+
+    async fn restore(&self, manifest: &Manifest, dir: &Path) -> Result<(), NdbError> {
+        if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+            return Err(NdbError::InvalidArgument(format!(
+                "restore directory {} is not empty",
+                dir.display()
+            )));
+        }
+        tokio::fs::create_dir_all(dir).await?;
+        let mut sstables = Vec::new();
+        for table in &manifest.tables {
+            let mut table = table.clone();
+            let shared = self.dir.join(&table.data_path);
+            // Shared files are named `<table>-<size>-<checksum>.sst`.
+            let name = shared.file_name().unwrap().to_string_lossy();
+            let stem = name.split('-').next().unwrap();
+            table.data_path = dir.join(format!("{}.sst", stem));
+            tokio::fs::copy(&shared, &table.data_path).await?;
+            sstables.push(table);
+        }
+        let wal = dir.join("log");
+        if let Some(backed_up) = &manifest.wal {
+            tokio::fs::copy(self.dir.join(backed_up), &wal).await?;
+        }
+        let meta = DbMeta {
+            sstables,
+            wal,
+            wal_segments: Vec::new(),
+            retained_wals: Vec::new(),
+            pruned_through: manifest.pruned_through,
+        };
+        let storage: Arc<dyn Storage> = Arc::new(LocalStorage);
+        let max_bytes = DbOptions::default().max_manifest_bytes;
+        ManifestWriter::create(&storage, dir, &meta, max_bytes).await?;
+        Ok(())
+    }
+
+    async fn manifests(&self) -> Result<Vec<Manifest>, NdbError> {
+        let mut manifests = Vec::new();
+        let mut entries = tokio::fs::read_dir(self.dir.join("backups")).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.path().extension().is_some_and(|ext| ext == "json") {
+                manifests.push(read_json::<Manifest>(&entry.path()).await?);
+            }
+        }
+        manifests.sort_by_key(|m| m.id);
+        Ok(manifests)
+    }
+
+    async fn info(&self, manifest: &Manifest) -> Result<BackupInfo, NdbError> {
+        let mut size = 0;
+        let files = manifest.tables.iter().map(|t| &t.data_path);
+        for path in files.chain(&manifest.wal) {
+            size += tokio::fs::metadata(self.dir.join(path)).await?.len();
+        }
+        Ok(BackupInfo {
+            id: manifest.id,
+            timestamp: manifest.timestamp,
+            sequence: manifest.sequence,
+            size,
+            tables: manifest.tables.len(),
+        })
+    }
+}
+
+async fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, NdbError> {
+    Ok(serde_json::from_str(
+        &tokio::fs::read_to_string(path).await?,
+    )?)
+}
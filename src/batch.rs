@@ -0,0 +1,214 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::{
+    clock,
+    log::{LogEntry, LogRecord},
+    NdbError,
+};
+
+/// A set of writes that are applied atomically by [`Db::write`](crate::Db::write).
+/// Later writes in the batch win over earlier ones to the same key.
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    pub(crate) entries: Vec<LogEntry>,
+}
+
+impl WriteBatch {
+    pub fn new() -> WriteBatch {
+        WriteBatch::default()
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.entries.push(LogEntry::Put {
+            key: key.into(),
+            value: value.into(),
+        });
+    }
+
+    /// Puts a value that reads as deleted from `expires_at` on, as told by
+    /// the database's [`Clock`](crate::Clock).
+    pub fn put_until(&mut self, key: &[u8], value: &[u8], expires_at: SystemTime) {
+        self.entries.push(LogEntry::PutUntil {
+            key: key.into(),
+            value: value.into(),
+            expires_at: clock::unix_millis(expires_at),
+        });
+    }
+
+    /// Adds `operand` to the value of `key` with the database's merge
+    /// operator.
+    pub fn merge(&mut self, key: &[u8], operand: &[u8]) {
+        self.entries.push(LogEntry::Merge {
+            key: key.into(),
+            operand: operand.into(),
+        });
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.entries.push(LogEntry::Delete { key: key.into() });
+    }
+
+    /// Deletes every key from `start` up to, but not including, `end`.
+    pub fn delete_range(&mut self, start: &[u8], end: &[u8]) {
+        self.entries.push(LogEntry::DeleteRange {
+            start: start.into(),
+            end: end.into(),
+        });
+    }
+
+    /// The number of writes in the batch.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The writes in the batch, in order.
+    pub fn ops(&self) -> impl Iterator<Item = BatchOp<'_>> {
+        self.entries.iter().map(|entry| match entry {
+            LogEntry::Put { key, value } => BatchOp::Put { key, value },
+            LogEntry::PutUntil {
+                key,
+                value,
+                expires_at,
+            } => BatchOp::PutUntil {
+                key,
+                value,
+                expires_at: UNIX_EPOCH + Duration::from_millis(*expires_at),
+            },
+            LogEntry::Merge { key, operand } => BatchOp::Merge { key, operand },
+            LogEntry::Delete { key } => BatchOp::Delete { key },
+            LogEntry::DeleteRange { start, end } => BatchOp::DeleteRange { start, end },
+            LogEntry::Batch(_)
+            | LogEntry::Prepare { .. }
+            | LogEntry::Commit { .. }
+            | LogEntry::Rollback { .. } => unreachable!("batches hold single writes"),
+        })
+    }
+
+    /// Encodes the batch exactly as it's written to the WAL, minus the
+    /// sequence number, to be sent elsewhere and decoded with
+    /// [`WriteBatch::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Serializes just like `LogEntry::Batch`, without copying it.
+        #[derive(Serialize)]
+        enum Borrowed<'a> {
+            Batch(&'a [LogEntry]),
+        }
+        let json = match self.entries.as_slice() {
+            [entry] => serde_json::to_vec(entry),
+            entries => serde_json::to_vec(&Borrowed::Batch(entries)),
+        };
+        json.expect("log entries always serialize")
+    }
+
+    /// Decodes a batch encoded by [`WriteBatch::to_bytes`]. A line of the WAL
+    /// decodes too, ignoring its sequence number.
+    pub fn from_bytes(bytes: &[u8]) -> Result<WriteBatch, NdbError> {
+        let record: LogRecord<LogEntry> = serde_json::from_slice(bytes)?;
+        let batch = WriteBatch::from_entry(record.entry);
+        if batch.entries.iter().any(|e| {
+            matches!(
+                e,
+                LogEntry::Batch(_)
+                    | LogEntry::Prepare { .. }
+                    | LogEntry::Commit { .. }
+                    | LogEntry::Rollback { .. }
+            )
+        }) {
+            return Err(NdbError::corruption("batch nested in a batch"));
+        }
+        Ok(batch)
+    }
+
+    pub(crate) fn from_entry(entry: LogEntry) -> WriteBatch {
+        let entries = match entry {
+            LogEntry::Batch(entries) | LogEntry::Commit { ops: entries, .. } => entries,
+            LogEntry::Prepare { .. } | LogEntry::Rollback { .. } => Vec::new(),
+            entry => vec![entry],
+        };
+        WriteBatch { entries }
+    }
+
+    // The batch as a single log entry.
+    pub(crate) fn into_entry(mut self) -> LogEntry {
+        if self.entries.len() == 1 {
+            self.entries.pop().unwrap()
+        } else {
+            LogEntry::Batch(self.entries)
+        }
+    }
+}
+
+/// Names a batch prepared with [`Db::prepare`](crate::Db::prepare), to
+/// commit or roll back later. Whatever's coordinating the commit can keep its
+/// [id](PreparedToken::id), and get the token back with
+/// [`PreparedToken::from_id`], e.g. after restarting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PreparedToken(pub(crate) u64);
+
+impl PreparedToken {
+    pub fn id(self) -> u64 {
+        self.0
+    }
+
+    pub fn from_id(id: u64) -> PreparedToken {
+        PreparedToken(id)
+    }
+}
+
+/// One write in a [`WriteBatch`], as returned by [`WriteBatch::ops`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchOp<'a> {
+    Put {
+        key: &'a [u8],
+        value: &'a [u8],
+    },
+    PutUntil {
+        key: &'a [u8],
+        value: &'a [u8],
+        expires_at: SystemTime,
+    },
+    Merge {
+        key: &'a [u8],
+        operand: &'a [u8],
+    },
+    Delete {
+        key: &'a [u8],
+    },
+    /// Deletes every key from `start` up to, but not including, `end`.
+    DeleteRange {
+        start: &'a [u8],
+        end: &'a [u8],
+    },
+}
+
+/// Per-write durability settings.
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// Sync the WAL before the write returns. Without this, the write is
+    /// handed to the OS, so it survives the process crashing but not the
+    /// machine. [`Db::sync_wal`](crate::Db::sync_wal) syncs everything
+    /// written so far.
+    pub sync: bool,
+    /// Skip the WAL entirely. The write is lost if the database isn't
+    /// flushed before it's closed.
+    pub disable_wal: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            sync: true,
+            disable_wal: false,
+        }
+    }
+}
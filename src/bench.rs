@@ -0,0 +1,142 @@
+// Thin wrappers around internals for the benchmarks in benches/. Not a stable
+// API.
+
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    block::BlockHandle,
+    coding::{put_fixed32, put_varint32},
+    index::IndexSearch,
+    log::{Log, LogEntry, LogRecord},
+    pread::FileIo,
+    sstable::{SSTable, SSTableWriter},
+    stats::IoCounters,
+    table_cache::TableCache,
+    value::Value,
+    DbOptions, KeyValue, LocalStorage, NdbError, Queryable,
+};
+
+// The log, and the last sequence number written to it.
+pub struct Wal(Log, u64);
+
+impl Wal {
+    pub async fn open(path: impl AsRef<Path>) -> Result<Wal, NdbError> {
+        Ok(Wal(
+            Log::open(&LocalStorage, path, &FileIo::Blocking).await?,
+            0,
+        ))
+    }
+
+    // Includes the fsync.
+    pub async fn append(&mut self, key: &[u8], value: &[u8]) -> Result<(), NdbError> {
+        let entry = LogEntry::Put {
+            key: key.into(),
+            value: value.into(),
+        };
+        self.1 += 1;
+        let record = LogRecord {
+            seq: self.1,
+            entry: &entry,
+        };
+        self.0.append([record], true).await
+    }
+}
+
+// The record encoding the WAL uses today.
+pub fn encode_json(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut buf = serde_json::to_vec(&LogRecord {
+        seq: 1,
+        entry: LogEntry::Put {
+            key: key.into(),
+            value: value.into(),
+        },
+    })
+    .unwrap();
+    buf.push(b'\n');
+    buf
+}
+
+// A candidate binary encoding to compare against: a length and CRC32C header
+// followed by a type byte and length-prefixed key and value.
+pub fn encode_binary(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(key.len() + value.len() + 11);
+    payload.push(0);
+    put_varint32(&mut payload, key.len() as u32);
+    payload.extend_from_slice(key);
+    put_varint32(&mut payload, value.len() as u32);
+    payload.extend_from_slice(value);
+
+    let mut buf = Vec::with_capacity(payload.len() + 8);
+    put_fixed32(&mut buf, payload.len() as u32);
+    put_fixed32(&mut buf, crc32c::crc32c(&payload));
+    buf.extend_from_slice(&payload);
+    buf
+}
+
+pub struct Table(SSTable);
+
+impl Table {
+    // `entries` must be ordered by key.
+    pub async fn build(
+        dir: impl AsRef<Path>,
+        options: &DbOptions,
+        entries: impl Iterator<Item = KeyValue>,
+    ) -> Result<Table, NdbError> {
+        let io = Arc::new(IoCounters::default());
+        let file_io = FileIo::new(options)?;
+        let storage = options.storage.clone();
+        let files = Arc::new(TableCache::new(
+            options.max_open_files,
+            io.clone(),
+            storage,
+            file_io,
+        ));
+        let timestamp = options.clock.unix_secs();
+        let mut writer = SSTableWriter::create(dir, options, &io, &files, timestamp).await?;
+        for (key, value) in entries {
+            writer.add(&key, &Value::Put(value)).await?;
+        }
+        Ok(Table(writer.finish().await?))
+    }
+
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, NdbError> {
+        // Nothing expires in the benchmarks.
+        Ok(self.0.get(key).await?.and_then(|value| value.live(0)))
+    }
+}
+
+// A table index on its own, with the search the table would pick for it.
+pub struct Index {
+    entries: Vec<(Vec<u8>, BlockHandle)>,
+    search: IndexSearch,
+}
+
+impl Index {
+    // `keys` are the last keys of each block, in order.
+    pub fn new(keys: impl Iterator<Item = Vec<u8>>) -> Index {
+        let entries: Vec<_> = keys
+            .enumerate()
+            .map(|(i, key)| {
+                let handle = BlockHandle {
+                    offset: i as u64 * 4096,
+                    size: 4096,
+                };
+                (key, handle)
+            })
+            .collect();
+        let search = IndexSearch::choose(&entries);
+        Index { entries, search }
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        self.search != IndexSearch::Binary
+    }
+
+    pub fn binary(&self, key: &[u8]) -> usize {
+        IndexSearch::Binary.find(&self.entries, key)
+    }
+
+    pub fn adaptive(&self, key: &[u8]) -> usize {
+        self.search.find(&self.entries, key)
+    }
+}
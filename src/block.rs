@@ -0,0 +1,253 @@
+use crate::{
+    coding::{decode_fixed32, get_varint32, get_varint64, put_fixed32, put_varint32, put_varint64},
+    compression, KeyValue, NdbError,
+};
+
+// A block is a run of prefix-compressed entries followed by the offsets of its
+// restart points and the number of restart points:
+//
+//   entry: shared key len (varint) | unshared key len (varint) |
+//          value len (varint) | unshared key bytes | value bytes
+//   restarts: restart offset (fixed32) * num_restarts
+//   num_restarts: fixed32
+//
+// Every `RESTART_INTERVAL`th entry stores its full key, so a reader can binary
+// search the restart points and only has to scan a few entries linearly.
+//
+// On disk each block is stored compressed or not, as its trailer's type byte
+// says, followed by a CRC32C of the stored bytes and the type byte. A block
+// handle's size is the stored size.
+
+pub(crate) const RESTART_INTERVAL: usize = 16;
+pub(crate) const BLOCK_TRAILER_SIZE: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BlockHandle {
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+}
+
+impl BlockHandle {
+    pub(crate) fn encode_to(&self, buf: &mut Vec<u8>) {
+        put_varint64(buf, self.offset);
+        put_varint64(buf, self.size);
+    }
+
+    pub(crate) fn decode_from(buf: &mut &[u8]) -> Option<BlockHandle> {
+        let offset = get_varint64(buf)?;
+        let size = get_varint64(buf)?;
+        Some(BlockHandle { offset, size })
+    }
+}
+
+pub(crate) struct BlockBuilder {
+    buf: Vec<u8>,
+    restarts: Vec<u32>,
+    counter: usize,
+    last_key: Vec<u8>,
+}
+
+impl Default for BlockBuilder {
+    fn default() -> BlockBuilder {
+        BlockBuilder {
+            buf: Vec::new(),
+            restarts: vec![0],
+            counter: 0,
+            last_key: Vec::new(),
+        }
+    }
+}
+
+impl BlockBuilder {
+    // Keys must be added in sorted order.
+    pub(crate) fn add(&mut self, key: &[u8], value: &[u8]) {
+        let shared = if self.counter < RESTART_INTERVAL {
+            key.iter()
+                .zip(self.last_key.iter())
+                .take_while(|(a, b)| a == b)
+                .count()
+        } else {
+            self.restarts.push(self.buf.len() as u32);
+            self.counter = 0;
+            0
+        };
+
+        put_varint32(&mut self.buf, shared as u32);
+        put_varint32(&mut self.buf, (key.len() - shared) as u32);
+        put_varint32(&mut self.buf, value.len() as u32);
+        self.buf.extend_from_slice(&key[shared..]);
+        self.buf.extend_from_slice(value);
+
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.counter += 1;
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub(crate) fn estimated_size(&self) -> usize {
+        self.buf.len() + self.restarts.len() * 4 + 4
+    }
+
+    pub(crate) fn last_key(&self) -> &[u8] {
+        &self.last_key
+    }
+
+    // Returns the finished block contents and resets the builder.
+    pub(crate) fn finish(&mut self) -> Vec<u8> {
+        let mut builder = std::mem::take(self);
+        for restart in &builder.restarts {
+            put_fixed32(&mut builder.buf, *restart);
+        }
+        put_fixed32(&mut builder.buf, builder.restarts.len() as u32);
+        builder.buf
+    }
+}
+
+// Appends the trailer for a block with the given contents.
+pub(crate) fn write_trailer(buf: &mut Vec<u8>, contents: &[u8], compression: u8) {
+    let crc = crc32c::crc32c_append(crc32c::crc32c(contents), &[compression]);
+    buf.push(compression);
+    put_fixed32(buf, crc);
+}
+
+// Checks the trailer at the end of `raw` and returns the block contents,
+// decompressed.
+pub(crate) fn verify_trailer(mut raw: Vec<u8>) -> Result<Vec<u8>, NdbError> {
+    if raw.len() < BLOCK_TRAILER_SIZE {
+        return Err(NdbError::corruption("block too short"));
+    }
+    let contents_len = raw.len() - BLOCK_TRAILER_SIZE;
+    let compression = raw[contents_len];
+    let expected = decode_fixed32(&raw[contents_len + 1..]);
+    let actual = crc32c::crc32c_append(crc32c::crc32c(&raw[..contents_len]), &[compression]);
+    if expected != actual {
+        return Err(NdbError::corruption(format!(
+            "block checksum mismatch: expected {:#x}, got {:#x}",
+            expected, actual
+        )));
+    }
+    raw.truncate(contents_len);
+    compression::decompress(compression, raw)
+}
+
+pub(crate) struct Block {
+    data: Vec<u8>,
+    restarts_offset: usize,
+    num_restarts: usize,
+}
+
+impl Block {
+    pub(crate) fn new(data: Vec<u8>) -> Result<Block, NdbError> {
+        if data.len() < 4 {
+            return Err(NdbError::corruption("block too short"));
+        }
+        let num_restarts = decode_fixed32(&data[data.len() - 4..]) as usize;
+        let restarts_offset = num_restarts
+            .checked_mul(4)
+            .and_then(|len| (data.len() - 4).checked_sub(len))
+            .ok_or_else(|| NdbError::corruption("bad block restart count"))?;
+        Ok(Block {
+            data,
+            restarts_offset,
+            num_restarts,
+        })
+    }
+
+    // The block's contents, e.g. to reuse the buffer.
+    pub(crate) fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    fn restart_point(&self, i: usize) -> usize {
+        decode_fixed32(&self.data[self.restarts_offset + i * 4..]) as usize
+    }
+
+    pub(crate) fn iter(&self) -> BlockIter<'_> {
+        BlockIter {
+            block: self,
+            offset: 0,
+            key: Vec::new(),
+        }
+    }
+
+    // Returns an iterator positioned at or shortly before the first entry
+    // with a key >= `target`. Callers skip entries that are still too small.
+    pub(crate) fn iter_from(&self, target: &[u8]) -> Result<BlockIter<'_>, NdbError> {
+        // Find the last restart point whose key is < target.
+        let (mut lo, mut hi) = (0, self.num_restarts);
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            let mut iter = BlockIter {
+                block: self,
+                offset: self.restart_point(mid),
+                key: Vec::new(),
+            };
+            match iter.next().transpose()? {
+                Some((key, _)) if key.as_slice() < target => lo = mid,
+                _ => hi = mid,
+            }
+        }
+        let offset = if self.num_restarts == 0 {
+            self.restarts_offset
+        } else {
+            self.restart_point(lo)
+        };
+        Ok(BlockIter {
+            block: self,
+            offset,
+            key: Vec::new(),
+        })
+    }
+
+    // Returns the first entry with a key >= `target`.
+    pub(crate) fn seek(&self, target: &[u8]) -> Result<Option<KeyValue>, NdbError> {
+        for entry in self.iter_from(target)? {
+            let (key, value) = entry?;
+            if key.as_slice() >= target {
+                return Ok(Some((key, value.to_vec())));
+            }
+        }
+        Ok(None)
+    }
+}
+
+pub(crate) struct BlockIter<'a> {
+    block: &'a Block,
+    offset: usize,
+    key: Vec<u8>,
+}
+
+impl<'a> Iterator for BlockIter<'a> {
+    type Item = Result<(Vec<u8>, &'a [u8]), NdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.block.restarts_offset {
+            return None;
+        }
+        let block: &'a Block = self.block;
+        let corrupt = || NdbError::corruption("bad block entry");
+        let mut buf = &block.data[self.offset..block.restarts_offset];
+        let header = (|| {
+            let shared = get_varint32(&mut buf)? as usize;
+            let unshared = get_varint32(&mut buf)? as usize;
+            let value_len = get_varint32(&mut buf)? as usize;
+            Some((shared, unshared, value_len))
+        })();
+        let Some((shared, unshared, value_len)) = header else {
+            self.offset = self.block.restarts_offset;
+            return Some(Err(corrupt()));
+        };
+        if shared > self.key.len() || unshared + value_len > buf.len() {
+            self.offset = self.block.restarts_offset;
+            return Some(Err(corrupt()));
+        }
+        self.key.truncate(shared);
+        self.key.extend_from_slice(&buf[..unshared]);
+        let value = &buf[unshared..unshared + value_len];
+        self.offset = self.block.restarts_offset - buf.len() + unshared + value_len;
+        Some(Ok((self.key.clone(), value)))
+    }
+}
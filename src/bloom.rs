@@ -0,0 +1,68 @@
+// A standard bloom filter, stored as the filter block of an SSTable. The last
+// byte of the encoded filter is the number of probes, the rest is the bit
+// array.
+
+pub(crate) const BITS_PER_KEY: usize = 10;
+
+pub(crate) fn hash(key: &[u8]) -> u32 {
+    // FNV-1a. This is persisted, so it can't change.
+    let mut h: u32 = 0x811c9dc5;
+    for b in key {
+        h ^= *b as u32;
+        h = h.wrapping_mul(0x01000193);
+    }
+    h
+}
+
+#[derive(Default)]
+pub(crate) struct BloomFilterBuilder {
+    hashes: Vec<u32>,
+}
+
+impl BloomFilterBuilder {
+    pub(crate) fn add(&mut self, key: &[u8]) {
+        self.hashes.push(hash(key));
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        // ln(2) * bits per key probes minimises the false positive rate.
+        let probes = ((BITS_PER_KEY as f64 * 0.69) as u8).clamp(1, 30);
+        let bits = (self.hashes.len() * BITS_PER_KEY).max(64);
+        let bytes = bits.div_ceil(8);
+        let bits = bytes * 8;
+
+        let mut filter = vec![0; bytes];
+        for h in self.hashes {
+            let mut h = h;
+            let delta = h.rotate_right(17);
+            for _ in 0..probes {
+                let bit = h as usize % bits;
+                filter[bit / 8] |= 1 << (bit % 8);
+                h = h.wrapping_add(delta);
+            }
+        }
+        filter.push(probes);
+        filter
+    }
+}
+
+pub(crate) fn may_contain(filter: &[u8], key: &[u8]) -> bool {
+    let Some((&probes, filter)) = filter.split_last() else {
+        return true;
+    };
+    if filter.is_empty() || probes > 30 {
+        // Treat anything we don't understand as a match.
+        return true;
+    }
+    let bits = filter.len() * 8;
+    let mut h = hash(key);
+    let delta = h.rotate_right(17);
+    for _ in 0..probes {
+        let bit = h as usize % bits;
+        if filter[bit / 8] & (1 << (bit % 8)) == 0 {
+            return false;
+        }
+        h = h.wrapping_add(delta);
+    }
+    true
+}
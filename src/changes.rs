@@ -0,0 +1,251 @@
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use futures::{Stream, StreamExt};
+use tokio::sync::{broadcast, Notify};
+
+use crate::{
+    db::DbMeta,
+    log::{LogEntry, LogRecord},
+    storage::{self, LineReader},
+    version, BatchOp, NdbError, Storage, WriteBatch,
+};
+
+// How many writes a watcher can fall behind by before it misses some.
+pub(crate) const WATCH_BUFFER: usize = 4096;
+
+/// A committed write, as returned by [`Db::updates_since`](crate::Db::updates_since).
+#[derive(Debug, Clone)]
+pub struct Update {
+    /// The write's sequence number.
+    pub seq: u64,
+    /// Everything the write did, which applies atomically.
+    pub batch: WriteBatch,
+}
+
+// Reads the logs in `paths`, oldest first, and then keeps following the last
+// one as it's written to, until the database goes away.
+struct Tail<'a> {
+    storage: &'a dyn Storage,
+    // For following a database as it's written to: notified on every commit,
+    // and the metadata naming the log segment being written to.
+    following: Option<(&'a Notify, &'a Mutex<DbMeta>)>,
+    paths: VecDeque<PathBuf>,
+    reader: Option<LineReader>,
+    // A line read so far, which might not be complete yet.
+    line: Vec<u8>,
+    since: u64,
+    // The sequence number of the last record read.
+    seq: u64,
+}
+
+impl Tail<'_> {
+    async fn next(&mut self) -> Result<Option<Update>, NdbError> {
+        loop {
+            // Register for wakeups before reading, so a commit in between
+            // isn't missed.
+            let committed = self.following.map(|(committed, _)| committed.notified());
+            tokio::pin!(committed);
+            if let Some(committed) = committed.as_mut().as_pin_mut() {
+                committed.enable();
+            }
+
+            let Some(path) = self.paths.front() else {
+                return Ok(None);
+            };
+            if self.reader.is_none() {
+                match self.storage.open(path).await {
+                    Ok(file) => self.reader = Some(LineReader::new(file)),
+                    // A log that hasn't been created yet is empty.
+                    Err(err) if storage::is_not_found(&err) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            if let Some(reader) = &mut self.reader {
+                reader.read_line(&mut self.line).await?;
+            }
+
+            if self.line.ends_with(b"\n") {
+                let mut line = std::mem::take(&mut self.line);
+                line.pop();
+                let line = &line[..];
+                if version::parse_header(line, path)?.is_some() {
+                    continue;
+                }
+                let record: LogRecord<LogEntry> = serde_json::from_slice(line)?;
+                // Records from before sequence numbers were logged are
+                // numbered in order.
+                self.seq = if record.seq == 0 {
+                    self.seq + 1
+                } else {
+                    record.seq
+                };
+                let batch = WriteBatch::from_entry(record.entry);
+                // Preparing or rolling back a batch doesn't write anything.
+                if self.seq > self.since && !batch.is_empty() {
+                    return Ok(Some(Update {
+                        seq: self.seq,
+                        batch,
+                    }));
+                }
+                continue;
+            }
+
+            // Older logs are finished, but the last one is still being
+            // written to unless the database is read-only, or has moved on
+            // to a new segment since.
+            match committed.as_pin_mut() {
+                Some(committed) if self.paths.len() == 1 => {
+                    let newer = self.newer_segments();
+                    if newer.is_empty() {
+                        committed.await;
+                    } else {
+                        // This one is only finished once it's been read to
+                        // the end again, since it could have been written to
+                        // after the read above.
+                        self.paths.extend(newer);
+                    }
+                }
+                _ => {
+                    self.paths.pop_front();
+                    self.reader = None;
+                    self.line.clear();
+                }
+            }
+        }
+    }
+}
+
+impl Tail<'_> {
+    // The segments of the log started after the last one in `paths`.
+    fn newer_segments(&self) -> Vec<PathBuf> {
+        let Some((_, meta)) = self.following else {
+            return Vec::new();
+        };
+        let wals = meta.lock().unwrap().memtable_wals();
+        match wals.iter().position(|wal| Some(wal) == self.paths.back()) {
+            Some(i) => wals[i + 1..].to_vec(),
+            None => Vec::new(),
+        }
+    }
+}
+
+// Streams the writes after `since` from the logs in `paths`, oldest first.
+// With `following`, the commit notifier and metadata of a database that's
+// still being written to, the stream follows the log forever; otherwise it
+// ends with the last one.
+pub(crate) fn updates<'a>(
+    storage: &'a dyn Storage,
+    paths: Vec<PathBuf>,
+    since: u64,
+    following: Option<(&'a Notify, &'a Mutex<DbMeta>)>,
+) -> impl Stream<Item = Result<Update, NdbError>> + 'a {
+    let tail = Tail {
+        storage,
+        following,
+        paths: paths.into(),
+        reader: None,
+        line: Vec::new(),
+        since,
+        seq: 0,
+    };
+    futures::stream::try_unfold(tail, |mut tail| async move {
+        Ok(tail.next().await?.map(|update| (update, tail)))
+    })
+}
+
+/// A change to a key under the prefix passed to [`Db::watch`](crate::Db::watch).
+/// `seq` is the sequence number of the write that made it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// A put, with or without an expiry.
+    Put {
+        seq: u64,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Merge {
+        seq: u64,
+        key: Vec<u8>,
+        operand: Vec<u8>,
+    },
+    Delete {
+        seq: u64,
+        key: Vec<u8>,
+    },
+    /// A deleted range with some keys under the prefix in it. The range is
+    /// as written, so it can reach outside the prefix.
+    DeleteRange {
+        seq: u64,
+        start: Vec<u8>,
+        end: Vec<u8>,
+    },
+    /// The watcher fell behind and missed this many writes, so anything
+    /// under the prefix may have changed.
+    Lagged(u64),
+}
+
+// Whether some key in `start..end` starts with `prefix`.
+fn overlaps(start: &[u8], end: &[u8], prefix: &[u8]) -> bool {
+    // Past `prefix` itself, the keys starting with it are contiguous, so
+    // either `start` is one of them or they're all before it.
+    start < end && end > prefix && (start <= prefix || start.starts_with(prefix))
+}
+
+fn events(update: &Update, prefix: &[u8]) -> Vec<WatchEvent> {
+    let seq = update.seq;
+    let event = |op: BatchOp| match op {
+        BatchOp::Put { key, value } | BatchOp::PutUntil { key, value, .. }
+            if key.starts_with(prefix) =>
+        {
+            Some(WatchEvent::Put {
+                seq,
+                key: key.into(),
+                value: value.into(),
+            })
+        }
+        BatchOp::Merge { key, operand } if key.starts_with(prefix) => Some(WatchEvent::Merge {
+            seq,
+            key: key.into(),
+            operand: operand.into(),
+        }),
+        BatchOp::Delete { key } if key.starts_with(prefix) => Some(WatchEvent::Delete {
+            seq,
+            key: key.into(),
+        }),
+        BatchOp::DeleteRange { start, end } if overlaps(start, end, prefix) => {
+            Some(WatchEvent::DeleteRange {
+                seq,
+                start: start.into(),
+                end: end.into(),
+            })
+        }
+        _ => None,
+    };
+    update.batch.ops().filter_map(event).collect()
+}
+
+pub(crate) fn watch(
+    receiver: broadcast::Receiver<Arc<Update>>,
+    prefix: Vec<u8>,
+) -> impl Stream<Item = WatchEvent> {
+    let updates = futures::stream::unfold(receiver, |mut receiver| async move {
+        let update = match receiver.recv().await {
+            Ok(update) => Ok(update),
+            Err(broadcast::error::RecvError::Lagged(missed)) => Err(missed),
+            // The database is gone.
+            Err(broadcast::error::RecvError::Closed) => return None,
+        };
+        Some((update, receiver))
+    });
+    updates.flat_map(move |update| {
+        let events = match update {
+            Ok(update) => events(&update, &prefix),
+            Err(missed) => vec![WatchEvent::Lagged(missed)],
+        };
+        futures::stream::iter(events)
+    })
+}
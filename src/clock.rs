@@ -0,0 +1,176 @@
+use std::{
+    fmt::Debug,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::warn;
+
+use crate::{DbOptions, NdbError};
+
+/// A source of time for everything in the database that cares about it: file
+/// naming, flush triggers, TTLs, leases.
+///
+/// Production code uses [`SystemClock`]. Tests can hand a [`ManualClock`] to
+/// [`DbOptions`](crate::DbOptions) and advance it explicitly instead of
+/// sleeping.
+pub trait Clock: Debug + Send + Sync {
+    /// Wall-clock time, used for anything that gets persisted.
+    fn now(&self) -> SystemTime;
+
+    /// Monotonic time, used for measuring durations within a process.
+    fn instant(&self) -> Instant;
+
+    fn unix_secs(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    fn unix_millis(&self) -> u64 {
+        unix_millis(self.now())
+    }
+}
+
+// Saturates rather than wrapping, for times too far off to store.
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    let millis = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    millis.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+// When a value written now with `ttl` expires, or `None` if that's too far
+// off to store, which is as good as never.
+pub(crate) fn expiry(clock: &dyn Clock, ttl: Duration) -> Option<SystemTime> {
+    let expires_at = clock.now().checked_add(ttl)?;
+    (unix_millis(expires_at) < u64::MAX).then_some(expires_at)
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock {
+    base_instant: Instant,
+    state: Mutex<ManualClockState>,
+}
+
+#[derive(Debug)]
+struct ManualClockState {
+    now: SystemTime,
+    elapsed: Duration,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> ManualClock {
+        ManualClock {
+            base_instant: Instant::now(),
+            state: Mutex::new(ManualClockState {
+                now: start,
+                elapsed: Duration::ZERO,
+            }),
+        }
+    }
+
+    /// Moves both the wall clock and the monotonic clock forward.
+    pub fn advance(&self, by: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now += by;
+        state.elapsed += by;
+    }
+
+    /// Sets the wall clock without touching the monotonic clock. This is
+    /// allowed to go backwards, the same way a real system clock can.
+    pub fn set(&self, now: SystemTime) {
+        self.state.lock().unwrap().now = now;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new(UNIX_EPOCH)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        self.state.lock().unwrap().now
+    }
+
+    fn instant(&self) -> Instant {
+        self.base_instant + self.state.lock().unwrap().elapsed
+    }
+}
+
+/// What to do on finding that the wall clock has gone backwards.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClockSkewAction {
+    /// Log it and carry on counting up from the latest time seen, so newer
+    /// files still sort after older ones.
+    #[default]
+    Warn,
+    /// Fail whatever needed the time with [`NdbError::ClockSkew`].
+    Error,
+}
+
+// Hands out the unix timestamps that files are named and ordered by. They only
+// ever go up, even if the clock doesn't.
+#[derive(Debug)]
+pub(crate) struct Timestamps {
+    state: Mutex<TimestampState>,
+}
+
+#[derive(Debug)]
+struct TimestampState {
+    // The last timestamp handed out.
+    issued: u64,
+    // The latest clock reading, to tell a clock that went backwards apart from
+    // one that's just behind timestamps that were bumped to stay unique.
+    seen: u64,
+}
+
+impl Timestamps {
+    // `issued` is the newest timestamp already in use.
+    pub(crate) fn new(issued: u64) -> Timestamps {
+        Timestamps {
+            state: Mutex::new(TimestampState { issued, seen: 0 }),
+        }
+    }
+
+    // Returns a timestamp later than any handed out before, and no earlier
+    // than the clock.
+    pub(crate) fn next(&self, options: &DbOptions) -> Result<u64, NdbError> {
+        let now = options.clock.unix_secs();
+        let mut state = self.state.lock().unwrap();
+        if now < state.seen {
+            match options.on_clock_skew {
+                ClockSkewAction::Warn => warn!(
+                    previous = state.seen,
+                    now,
+                    issued = state.issued,
+                    "clock went backwards"
+                ),
+                ClockSkewAction::Error => {
+                    return Err(NdbError::ClockSkew {
+                        previous: state.seen,
+                        now,
+                    })
+                }
+            }
+        }
+        state.seen = state.seen.max(now);
+        state.issued = now.max(state.issued + 1);
+        Ok(state.issued)
+    }
+}
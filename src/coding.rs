@@ -0,0 +1,48 @@
+// Little helpers for the binary formats. Fixed-width integers are little
+// endian, varints are LEB128.
+
+pub(crate) fn put_fixed32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+pub(crate) fn put_fixed64(buf: &mut Vec<u8>, v: u64) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+pub(crate) fn decode_fixed32(buf: &[u8]) -> u32 {
+    u32::from_le_bytes(buf[..4].try_into().unwrap())
+}
+
+pub(crate) fn decode_fixed64(buf: &[u8]) -> u64 {
+    u64::from_le_bytes(buf[..8].try_into().unwrap())
+}
+
+pub(crate) fn put_varint32(buf: &mut Vec<u8>, v: u32) {
+    put_varint64(buf, v as u64)
+}
+
+pub(crate) fn put_varint64(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+// Returns `None` if `buf` ends in the middle of a varint or the varint is
+// too long to be a u64.
+pub(crate) fn get_varint64(buf: &mut &[u8]) -> Option<u64> {
+    let mut result = 0u64;
+    for (i, byte) in buf.iter().enumerate().take(10) {
+        result |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            *buf = &buf[i + 1..];
+            return Some(result);
+        }
+    }
+    None
+}
+
+pub(crate) fn get_varint32(buf: &mut &[u8]) -> Option<u32> {
+    get_varint64(buf).and_then(|v| u32::try_from(v).ok())
+}
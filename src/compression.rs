@@ -0,0 +1,94 @@
+use std::borrow::Cow;
+
+use crate::NdbError;
+
+/// How SSTable blocks are compressed when they're written. Each block records
+/// its own codec, so changing this only affects new tables, and tables
+/// written with different codecs can be read side by side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Snappy,
+    Lz4,
+    Zstd {
+        level: i32,
+    },
+}
+
+// Codec ids, as stored in the block trailer's type byte.
+pub(crate) const NONE: u8 = 0;
+pub(crate) const SNAPPY: u8 = 1;
+pub(crate) const LZ4: u8 = 2;
+pub(crate) const ZSTD: u8 = 3;
+
+// Neither snappy nor lz4 gets more than this many bytes out of each byte it
+// stores, so a block claiming to decompress to more is corrupt, and the
+// claimed size is never allocated.
+const MAX_EXPANSION: usize = 255;
+
+impl Compression {
+    // Returns the codec id and the contents to store for a block. A block
+    // that doesn't shrink by at least an eighth is stored as is, since it
+    // isn't worth decompressing on every read.
+    pub(crate) fn compress<'a>(&self, contents: &'a [u8]) -> Result<(u8, Cow<'a, [u8]>), NdbError> {
+        let (id, compressed) = match *self {
+            Compression::None => return Ok((NONE, Cow::Borrowed(contents))),
+            Compression::Snappy => (
+                SNAPPY,
+                snap::raw::Encoder::new()
+                    .compress_vec(contents)
+                    .map_err(|err| NdbError::Io(err.into()))?,
+            ),
+            Compression::Lz4 => (LZ4, lz4_flex::compress_prepend_size(contents)),
+            Compression::Zstd { level } => (ZSTD, zstd::bulk::compress(contents, level)?),
+        };
+        if compressed.len() < contents.len() - contents.len() / 8 {
+            Ok((id, Cow::Owned(compressed)))
+        } else {
+            Ok((NONE, Cow::Borrowed(contents)))
+        }
+    }
+}
+
+pub(crate) fn decompress(id: u8, stored: Vec<u8>) -> Result<Vec<u8>, NdbError> {
+    let corrupt = |err: &dyn std::fmt::Display| {
+        NdbError::corruption(format!("couldn't decompress block: {}", err))
+    };
+    let check_len = |len: usize| {
+        if len > stored.len().saturating_mul(MAX_EXPANSION) {
+            return Err(corrupt(&format!(
+                "{} bytes can't decompress to {}",
+                stored.len(),
+                len
+            )));
+        }
+        Ok(len)
+    };
+    match id {
+        NONE => Ok(stored),
+        SNAPPY => {
+            snap::raw::decompress_len(&stored)
+                .map_err(|err| corrupt(&err))
+                .and_then(check_len)?;
+            snap::raw::Decoder::new()
+                .decompress_vec(&stored)
+                .map_err(|err| corrupt(&err))
+        }
+        LZ4 => {
+            let (len, compressed) = match stored.split_first_chunk::<4>() {
+                Some((len, compressed)) => (u32::from_le_bytes(*len) as usize, compressed),
+                None => return Err(corrupt(&"missing decompressed size")),
+            };
+            check_len(len)?;
+            lz4_flex::decompress(compressed, len).map_err(|err| corrupt(&err))
+        }
+        // Decompressed as a stream, so memory only grows as far as there's
+        // really data.
+        ZSTD => zstd::decode_all(stored.as_slice()).map_err(|err| corrupt(&err)),
+        _ => Err(NdbError::corruption(format!(
+            "unknown block compression type {}",
+            id
+        ))),
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{log::LogEntry, stats::ConflictStats};
+
+// Rough bookkeeping cost of each key or range remembered, on top of its bytes.
+const ENTRY_OVERHEAD: usize = 48;
+
+// The keys written by the last `window` commits, indexed by the sequence
+// number of the commit that last wrote them, so checking whether a read has
+// been overwritten since some point is a lookup per key rather than a search of
+// the memtable.
+pub(crate) struct RecentWrites {
+    window: usize,
+    // Every commit up to and including this one has been forgotten.
+    forgotten: u64,
+    commits: VecDeque<Commit>,
+    keys: HashMap<Vec<u8>, u64>,
+    stats: ConflictStats,
+}
+
+struct Commit {
+    seq: u64,
+    keys: Vec<Vec<u8>>,
+    ranges: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl RecentWrites {
+    pub(crate) fn new(window: usize) -> RecentWrites {
+        RecentWrites {
+            window,
+            forgotten: 0,
+            commits: VecDeque::new(),
+            keys: HashMap::new(),
+            stats: ConflictStats::default(),
+        }
+    }
+
+    // Remembers what `entry`, committed at sequence number `seq`, wrote.
+    pub(crate) fn record(&mut self, entry: &LogEntry, seq: u64) {
+        let mut commit = Commit {
+            seq,
+            keys: Vec::new(),
+            ranges: Vec::new(),
+        };
+        for op in entry.ops() {
+            match op {
+                LogEntry::DeleteRange { start, end } => {
+                    self.stats.memory_bytes += (start.len() + end.len() + ENTRY_OVERHEAD) as u64;
+                    commit.ranges.push((start.clone(), end.clone()));
+                }
+                op => {
+                    let (key, _) = op.stats_key();
+                    if self.keys.insert(key.to_vec(), commit.seq).is_none() {
+                        self.stats.memory_bytes += (key.len() + ENTRY_OVERHEAD) as u64;
+                    }
+                    commit.keys.push(key.to_vec());
+                }
+            }
+        }
+        self.commits.push_back(commit);
+
+        while self.commits.len() > self.window {
+            let oldest = self.commits.pop_front().unwrap();
+            for key in oldest.keys {
+                // Only forget the key if nothing newer has written it since.
+                if self.keys.get(&key) == Some(&oldest.seq) {
+                    self.keys.remove(&key);
+                    self.stats.memory_bytes -= (key.len() + ENTRY_OVERHEAD) as u64;
+                }
+            }
+            for (start, end) in oldest.ranges {
+                self.stats.memory_bytes -= (start.len() + end.len() + ENTRY_OVERHEAD) as u64;
+            }
+            self.forgotten = oldest.seq;
+        }
+        self.stats.tracked_commits = self.commits.len() as u64;
+    }
+
+    // Whether any of `keys` was written by a commit after `since`. If that
+    // reaches back past what's remembered, it can't be ruled out, so it counts
+    // as written.
+    pub(crate) fn written_since<'a>(
+        &mut self,
+        since: u64,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+    ) -> bool {
+        self.stats.checks += 1;
+        if since < self.forgotten {
+            self.stats.conflicts += 1;
+            self.stats.window_conflicts += 1;
+            return true;
+        }
+        let newer = self.commits.iter().rev().take_while(|c| c.seq > since);
+        let ranges: Vec<_> = newer.flat_map(|commit| &commit.ranges).collect();
+        let written = keys.into_iter().any(|key| {
+            self.keys.get(key).is_some_and(|&seq| seq > since)
+                || ranges
+                    .iter()
+                    .any(|(start, end)| start.as_slice() <= key && key < end.as_slice())
+        });
+        self.stats.conflicts += written as u64;
+        written
+    }
+
+    pub(crate) fn stats(&self) -> ConflictStats {
+        self.stats.clone()
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    storage::{self, write_synced},
+    NdbError, Storage,
+};
+
+// The position each named change-feed consumer has acknowledged, kept in
+// `consumers.json` next to the database's meta file.
+pub(crate) struct Consumers {
+    storage: Arc<dyn Storage>,
+    path: PathBuf,
+    acked: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Consumers {
+    pub(crate) async fn load(
+        storage: Arc<dyn Storage>,
+        dir: impl AsRef<Path>,
+    ) -> Result<Consumers, NdbError> {
+        let path = dir.as_ref().join("consumers.json");
+        let acked = match storage.read(&path).await {
+            Ok(contents) => serde_json::from_slice(&contents)?,
+            Err(err) if storage::is_not_found(&err) => BTreeMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Consumers {
+            storage,
+            path,
+            acked: Mutex::new(acked),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Positions only move forward, so a late or repeated ack is ignored.
+    pub(crate) async fn ack(&self, consumer: &str, seq: u64) -> Result<(), NdbError> {
+        let mut acked = self.acked.lock().await;
+        if acked.get(consumer).is_some_and(|&prev| prev >= seq) {
+            return Ok(());
+        }
+        let mut updated = acked.clone();
+        updated.insert(consumer.into(), seq);
+
+        // Write the whole set alongside and swap it in, so a crash leaves
+        // either the old acks or the new ones.
+        let tmp = self.path.with_extension("json.tmp");
+        let contents = serde_json::to_vec(&updated)?;
+        write_synced(&*self.storage, &tmp, &contents).await?;
+        self.storage.rename(&tmp, &self.path).await?;
+        if let Some(dir) = self.path.parent() {
+            self.storage.sync_dir(dir).await?;
+        }
+
+        *acked = updated;
+        Ok(())
+    }
+
+    pub(crate) async fn acked(&self, consumer: &str) -> Option<u64> {
+        self.acked.lock().await.get(consumer).copied()
+    }
+
+    pub(crate) async fn min_acked(&self) -> Option<u64> {
+        self.acked.lock().await.values().min().copied()
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{log::LogEntry, MergeOperator, NdbError};
+
+// How many counters `CounterCache` holds before it starts over.
+const MAX_CACHED_COUNTERS: usize = 100_000;
+
+/// A merge operator for the counters [`Db::increment`](crate::Db::increment)
+/// writes: 8-byte little-endian `i64`s, which operands are added to,
+/// wrapping on overflow. A missing value, or one that isn't 8 bytes, counts
+/// as 0.
+pub fn counter_merge_operator() -> MergeOperator {
+    Arc::new(|_key, existing, operands| {
+        let total = existing
+            .into_iter()
+            .chain(operands.iter().map(Vec::as_slice))
+            .map(|bytes| bytes.try_into().map_or(0, i64::from_le_bytes))
+            .fold(0i64, i64::wrapping_add);
+        total.to_le_bytes().to_vec()
+    })
+}
+
+pub(crate) fn decode(key: &[u8], value: Option<&[u8]>) -> Result<i64, NdbError> {
+    match value.map(<[u8; 8]>::try_from) {
+        None => Ok(0),
+        Some(Ok(bytes)) => Ok(i64::from_le_bytes(bytes)),
+        Some(Err(_)) => Err(NdbError::InvalidArgument(format!(
+            "{:?} is not a counter",
+            String::from_utf8_lossy(key)
+        ))),
+    }
+}
+
+// The current values of recently incremented counters, so incrementing them
+// again doesn't have to read them. Any other write to a counter drops it.
+#[derive(Default)]
+pub(crate) struct CounterCache {
+    values: BTreeMap<Vec<u8>, i64>,
+}
+
+impl CounterCache {
+    pub(crate) fn get(&self, key: &[u8]) -> Option<i64> {
+        self.values.get(key).copied()
+    }
+
+    pub(crate) fn insert(&mut self, key: &[u8], value: i64) {
+        if self.values.len() >= MAX_CACHED_COUNTERS {
+            self.values.clear();
+        }
+        self.values.insert(key.to_vec(), value);
+    }
+
+    pub(crate) fn forget(&mut self, entry: &LogEntry) {
+        if self.values.is_empty() {
+            return;
+        }
+        for op in entry.ops() {
+            match op {
+                LogEntry::DeleteRange { start, end } => {
+                    let mut rest = self.values.split_off(start);
+                    self.values.append(&mut rest.split_off(end));
+                }
+                op => {
+                    self.values.remove(op.stats_key().0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::ops::{Bound, RangeBounds};
+
+use serde::{Deserialize, Serialize};
+
+use crate::NdbError;
+
+/// How far a scan has got, for carrying on with it later through
+/// [`Db::scan_from_token`](crate::Db::scan_from_token), e.g. after a crash.
+/// The whole scan reads the database as of the sequence number the token
+/// was made at. Store it with [`ScanToken::to_bytes`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanToken {
+    sequence: u64,
+    // What's left of the range: the start moves past each key returned.
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+impl ScanToken {
+    pub(crate) fn new(sequence: u64, range: impl RangeBounds<Vec<u8>>) -> ScanToken {
+        ScanToken {
+            sequence,
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+        }
+    }
+
+    /// The sequence number the scan reads as of.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Records that the scan has returned `key`, so it carries on after it.
+    pub fn advance(&mut self, key: &[u8]) {
+        self.start = Bound::Excluded(key.to_vec());
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("scan tokens always serialize")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ScanToken, NdbError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+impl RangeBounds<Vec<u8>> for ScanToken {
+    fn start_bound(&self) -> Bound<&Vec<u8>> {
+        self.start.as_ref()
+    }
+
+    fn end_bound(&self) -> Bound<&Vec<u8>> {
+        self.end.as_ref()
+    }
+}
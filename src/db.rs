@@ -0,0 +1,3156 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    ops::{Bound, RangeBounds},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures::{stream::FuturesUnordered, Stream, StreamExt, TryStreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    sync::{
+        broadcast,
+        oneshot::{self, error::TryRecvError},
+        Notify,
+    },
+    task::{JoinError, JoinHandle},
+};
+use tracing::{info, instrument, warn, Instrument};
+
+use crate::{
+    changes::{self, Update, WatchEvent},
+    clock::{self, Timestamps},
+    conflict::RecentWrites,
+    consumers::Consumers,
+    counter::{self, CounterCache},
+    cursor::ScanToken,
+    handles::{HandleKind, HandleWarning, Handles},
+    iter::{DbIterator, Source},
+    locks::KeyLocks,
+    log::{Log, LogEntry, LogRecord},
+    manifest::{self, ManifestWriter},
+    memtable::Memtable,
+    merge::{self, MergeOperator},
+    metrics,
+    pread::FileIo,
+    repair::{self, RepairReport},
+    secondary_index::{self, IndexIterator, SecondaryIndex},
+    sstable::{self, SSTable, SSTableMetadata, SSTableWriter},
+    stats::{
+        DbStats, IoCounters, IoStats, LevelStats, MemtableStats, OpenStats, TableOpenTiming,
+        TierStats, WriteStallStats, WriteStats,
+    },
+    storage::{self, write_synced},
+    table_cache::TableCache,
+    tables::Tables,
+    tier::{StorageTier, TieredStorage},
+    transform::{self, ValueTransform},
+    vacuum::{self, VacuumOptions},
+    value::Value,
+    verify::{self, VerifyProblem, VerifyReport},
+    version::FORMAT_VERSION,
+    view::{self, Layer},
+    watchdog::{self, JobKind},
+    write_buffer::WriteBuffer,
+    write_stall::{Stall, WriteStallState},
+    Clock, ClockSkewAction, ColdTier, Compression, CorruptBlock, DbView, FileKind, LiveFile,
+    LocalStorage, MemStorage, MmapOptions, NdbError, PreparedToken, RateLimiter, SstInfo,
+    SstWriter, Storage, SystemClock, Table, Transaction, Validator, WalRecovery, WatchdogOptions,
+    WriteBatch, WriteBufferManager, WriteOptions, WriteStallOptions,
+};
+
+const READ_ONLY_OPEN_ATTEMPTS: usize = 5;
+
+#[derive(Clone)]
+pub struct DbOptions {
+    pub clock: Arc<dyn Clock>,
+    /// Where the database's files are kept. The local file system by
+    /// default.
+    pub storage: Arc<dyn Storage>,
+    /// Target size of an SSTable data block, before its trailer.
+    pub block_size: usize,
+    /// How new SSTable blocks are compressed.
+    pub compression: Compression,
+    /// Cut SSTable index keys down to at most this many bytes, so very long
+    /// keys don't bloat the in-memory index. Full keys are still kept in the
+    /// data blocks; blocks that end up sharing an index key are told apart
+    /// by reading them, which makes lookups near them slower.
+    pub max_index_key_len: Option<usize>,
+    /// Run against every write before it is logged.
+    pub validator: Option<Arc<dyn Validator>>,
+    /// How many SSTables, or databases in [`Db::open_many`], to open at once.
+    pub max_open_parallelism: usize,
+    /// Keyspace prefixes to break write counts down by in [`Db::stats`].
+    pub metric_prefixes: Vec<Vec<u8>>,
+    /// Drop a single put whose value is already the newest one in the
+    /// memtable, rather than logging it again. Counted in
+    /// [`WriteStats::skipped_puts`](crate::WriteStats::skipped_puts). A
+    /// skipped put is only as durable as the write it duplicates.
+    pub skip_identical_puts: bool,
+    /// What to do if the clock goes backwards. Files are named and ordered by
+    /// the time they're written, which carries on counting up either way.
+    pub on_clock_skew: ClockSkewAction,
+    /// Folds the operands written by [`Db::merge`] into values. Merging
+    /// without one is an error.
+    pub merge_operator: Option<MergeOperator>,
+    /// Watches flushes and compactions for stalls. Off by default.
+    pub watchdog: Option<WatchdogOptions>,
+    /// How many recent commits to remember the keys of, for
+    /// [`Db::written_since`]. Checks reaching back further than this always
+    /// report a conflict.
+    pub conflict_window: usize,
+    /// How long [`Transaction::get_for_update`] waits for another
+    /// transaction's lock before giving up with [`NdbError::LockTimeout`].
+    /// This is also how deadlocks are broken.
+    pub lock_timeout: Duration,
+    /// Reject writes with [`NdbError::QuotaExceeded`] once the database's
+    /// tables and logs add up to more than this many bytes. A write can go
+    /// over the limit, but nothing after it will until the database shrinks
+    /// again: flushing the memtable usually saves some space, since tables
+    /// are more compact than the log, and compacting an overlay into its base
+    /// empties it.
+    pub max_total_bytes: Option<u64>,
+    /// Remember a table block that fails its checksum or can't be decoded,
+    /// so reads that need it fail with [`NdbError::Corruption`] straight
+    /// away rather than reading it again. Reads, scans and vacuums that
+    /// touch it fail either way, since serving around it would bring back
+    /// what it replaced; the rest of the table is still served. Corrupt
+    /// blocks are listed by [`Db::verify_integrity`].
+    pub skip_corrupt_blocks: bool,
+    /// Merge runs of small tables after each flush, in the background for
+    /// those made as the memtable fills up. Off by default.
+    pub vacuum: Option<VacuumOptions>,
+    /// Move tables to a second tier once they've gone cold, whenever the
+    /// database is vacuumed. Off by default.
+    pub cold_tier: Option<ColdTier>,
+    /// Transforms the values of keys starting with each prefix, the longest
+    /// matching one winning. Merges aren't allowed under a prefix with a
+    /// transform, and values written under it before it had one can't be
+    /// read back.
+    pub value_transforms: Vec<(Vec<u8>, Arc<dyn ValueTransform>)>,
+    /// Indexes over the values of keys, kept up to date with every write to
+    /// them and read with [`Db::index_scan`].
+    pub secondary_indexes: Vec<SecondaryIndex>,
+    /// Log a warning through `tracing` about a view or iterator that's old
+    /// enough, and is keeping enough deleted tables from being freed, that
+    /// it was likely leaked. Checked whenever tables are deleted, by [`Db::vacuum`] and
+    /// [`Db::compact_into_base`]. See
+    /// [`DbStats::handles`](crate::DbStats::handles).
+    pub handle_warning: Option<HandleWarning>,
+    /// Sync a table being written every time this many more bytes of it
+    /// have been written, rather than only once it's finished. This spreads
+    /// the writes of a big flush or vacuum out, instead of leaving them all
+    /// for the OS to write back at once at the end. Off by default.
+    pub table_sync_bytes: Option<u64>,
+    /// Start a new segment of the WAL once the current one has grown to this
+    /// many bytes, rather than writing to one log until the memtable is
+    /// flushed. Segments are retired together when it is, and kept for as
+    /// long as change-feed consumers need them, like any other log. Off by
+    /// default.
+    pub wal_segment_bytes: Option<u64>,
+    /// Move logs into this directory once they're no longer needed, rather
+    /// than deleting them, e.g. for a downstream system to pick up at its own
+    /// pace. The database never reads or cleans up anything in it.
+    pub wal_archive_dir: Option<PathBuf>,
+    /// What to do about corrupt records in the log when opening the
+    /// database. By default, a record cut off by a crash at the end of the
+    /// log is dropped, and anything else fails the open.
+    pub wal_recovery: WalRecovery,
+    /// Start a new MANIFEST once the current one has grown to this many
+    /// bytes, beginning with a snapshot of the database, so that replaying
+    /// it on open stays quick.
+    pub max_manifest_bytes: u64,
+    /// Once the memtable takes up this much memory, the write that filled it
+    /// freezes it and starts writing it to a table in the background, and
+    /// later writes go to a fresh memtable and log in the meantime. Off by
+    /// default, which leaves flushing to [`Db::flush_memtable`].
+    pub write_buffer_size: Option<u64>,
+    /// A memory budget shared with other databases, which flushes the
+    /// largest of their memtables once they add up to more than it allows.
+    /// Can be used with or without `write_buffer_size`.
+    pub write_buffer_manager: Option<Arc<WriteBufferManager>>,
+    /// How many tables' data files to keep open at once. Past this, the
+    /// one read least recently is closed, and opened again when it's next
+    /// read. A table being read, or deleted while a view still reads it,
+    /// holds its file open regardless.
+    pub max_open_files: usize,
+    /// Map tables' data files into memory and serve reads from the mapping,
+    /// rather than reading the file. This saves a system call per block
+    /// read, which adds up for read-mostly workloads whose tables fit in
+    /// the page cache. Mapped tables don't count towards `max_open_files`.
+    /// Off by default.
+    pub mmap: Option<MmapOptions>,
+    /// Write the tables made by flushes, vacuums and compactions with
+    /// `O_DIRECT`, around the page cache, so that writing a big one doesn't
+    /// push out the pages foreground reads are using. Tables are written as
+    /// usual on file systems, and platforms, without direct I/O.
+    pub direct_io_writes: bool,
+    /// Caps how fast tables are written, and can be shared with other
+    /// databases to cap them all together. Flushes, vacuums and compactions
+    /// take longer under it, so writes that wait for a flush do too.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Delay, and then stop, writes once tables pile up. Off by default.
+    pub write_stall: Option<WriteStallOptions>,
+    /// Read tables and append to the WAL through an io_uring, rather than on
+    /// tokio's blocking pool and through its buffered files. Opening the
+    /// database fails if the kernel doesn't support it. Needs the
+    /// `io-uring` feature, on Linux.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub io_uring: bool,
+}
+
+impl Default for DbOptions {
+    fn default() -> DbOptions {
+        DbOptions {
+            clock: Arc::new(SystemClock),
+            storage: Arc::new(LocalStorage),
+            block_size: 4096,
+            compression: Compression::None,
+            max_index_key_len: None,
+            validator: None,
+            max_open_parallelism: 16,
+            metric_prefixes: Vec::new(),
+            skip_identical_puts: false,
+            on_clock_skew: ClockSkewAction::Warn,
+            merge_operator: None,
+            watchdog: None,
+            conflict_window: 1024,
+            lock_timeout: Duration::from_secs(1),
+            max_total_bytes: None,
+            skip_corrupt_blocks: false,
+            vacuum: None,
+            cold_tier: None,
+            value_transforms: Vec::new(),
+            secondary_indexes: Vec::new(),
+            handle_warning: Some(HandleWarning::default()),
+            table_sync_bytes: None,
+            wal_segment_bytes: None,
+            wal_archive_dir: None,
+            wal_recovery: WalRecovery::default(),
+            max_manifest_bytes: 4 << 20,
+            write_buffer_size: None,
+            write_buffer_manager: None,
+            max_open_files: 512,
+            mmap: None,
+            direct_io_writes: false,
+            rate_limiter: None,
+            write_stall: None,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            io_uring: false,
+        }
+    }
+}
+
+// The hooks and storage are trait objects without a `Debug` of their own,
+// so they're left out.
+impl fmt::Debug for DbOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut options = f.debug_struct("DbOptions");
+        options
+            .field("clock", &self.clock)
+            .field("block_size", &self.block_size)
+            .field("compression", &self.compression)
+            .field("max_index_key_len", &self.max_index_key_len)
+            .field("max_open_parallelism", &self.max_open_parallelism)
+            .field("metric_prefixes", &self.metric_prefixes)
+            .field("skip_identical_puts", &self.skip_identical_puts)
+            .field("on_clock_skew", &self.on_clock_skew)
+            .field("watchdog", &self.watchdog)
+            .field("conflict_window", &self.conflict_window)
+            .field("lock_timeout", &self.lock_timeout)
+            .field("max_total_bytes", &self.max_total_bytes)
+            .field("skip_corrupt_blocks", &self.skip_corrupt_blocks)
+            .field("vacuum", &self.vacuum)
+            .field("handle_warning", &self.handle_warning)
+            .field("table_sync_bytes", &self.table_sync_bytes)
+            .field("wal_segment_bytes", &self.wal_segment_bytes)
+            .field("wal_archive_dir", &self.wal_archive_dir)
+            .field("wal_recovery", &self.wal_recovery)
+            .field("max_manifest_bytes", &self.max_manifest_bytes)
+            .field("write_buffer_size", &self.write_buffer_size)
+            .field("write_buffer_manager", &self.write_buffer_manager)
+            .field("max_open_files", &self.max_open_files)
+            .field("mmap", &self.mmap)
+            .field("direct_io_writes", &self.direct_io_writes)
+            .field("rate_limiter", &self.rate_limiter)
+            .field("write_stall", &self.write_stall);
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        options.field("io_uring", &self.io_uring);
+        options.finish_non_exhaustive()
+    }
+}
+
+impl DbOptions {
+    /// Options for a database kept wholly in memory, in a [`MemStorage`] of
+    /// its own. The directory it's opened in is only a name, and everything
+    /// in it is gone once the database is dropped. To reopen one, open both
+    /// with the same [`storage`](DbOptions::storage) instead.
+    pub fn in_memory() -> DbOptions {
+        DbOptions {
+            storage: Arc::new(MemStorage::new()),
+            ..DbOptions::default()
+        }
+    }
+}
+
+/// The answer from [`Db::key_may_exist`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MayExist {
+    DefinitelyNot,
+    /// The key might exist. If its value was found in memory, it's included.
+    Maybe(Option<Vec<u8>>),
+}
+
+// The database's tables and logs, as recorded in its manifest.
+#[derive(Clone)]
+pub(crate) struct DbMeta {
+    pub(crate) sstables: Vec<SSTableMetadata>,
+    pub(crate) wal: PathBuf,
+    // Earlier segments of the log the memtable is replayed from, oldest
+    // first, if it's been rotated since the memtable was last flushed.
+    pub(crate) wal_segments: Vec<RetainedWal>,
+    // Older logs kept for the change feed, oldest first.
+    pub(crate) retained_wals: Vec<RetainedWal>,
+    // The last sequence number in any log that has been deleted, so the
+    // change feed can't start before it.
+    pub(crate) pruned_through: u64,
+}
+
+impl DbMeta {
+    // An empty database, logging to `wal`.
+    pub(crate) fn new(wal: PathBuf) -> DbMeta {
+        DbMeta {
+            sstables: Vec::new(),
+            wal,
+            wal_segments: Vec::new(),
+            retained_wals: Vec::new(),
+            pruned_through: 0,
+        }
+    }
+
+    // Every segment of the log the memtable is replayed from, oldest first.
+    pub(crate) fn memtable_wals(&self) -> Vec<PathBuf> {
+        let segments = self.wal_segments.iter().map(|wal| wal.path.clone());
+        segments.chain([self.wal.clone()]).collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct RetainedWal {
+    pub(crate) path: PathBuf,
+    // `None` if nothing was logged to it.
+    pub(crate) last_seq: Option<u64>,
+}
+
+pub struct Db {
+    dir: PathBuf,
+    options: DbOptions,
+    // `None` for a database that was opened without a log, which can't be
+    // written to.
+    log: Option<tokio::sync::Mutex<Log>>,
+    // Writes waiting to be logged. Whoever takes the log lock next commits
+    // all of them at once.
+    pending: Mutex<Vec<PendingWrite>>,
+    // Why the log can't be written to any more, once appending to it has
+    // failed.
+    poisoned: Mutex<Option<String>>,
+    // Shared with any views frozen since the last write.
+    memtable: RwLock<Arc<Memtable>>,
+    // The memtable before `memtable`, while it's written to a table.
+    flushing: Mutex<Option<Flushing>>,
+    // Merges runs of small tables in the background, once a flush has added
+    // enough of them. See `DbOptions::vacuum`.
+    vacuuming: Mutex<Option<Vacuuming>>,
+    // Replaced whole whenever a table is added or removed.
+    sstables: RwLock<Arc<Tables>>,
+    // Locked so a write can rotate the log.
+    meta: Mutex<DbMeta>,
+    // `None` until the database is first changed, if it was opened without a
+    // log, e.g. as an overlay's base.
+    manifest: tokio::sync::Mutex<Option<ManifestWriter>>,
+    // For an overlay, the sealed database underneath this one.
+    base: Option<Box<Db>>,
+    open_stats: OpenStats,
+    write_stats: Mutex<WriteStats>,
+    recent_writes: Mutex<RecentWrites>,
+    // The sequence number of the last write applied to the memtable.
+    sequence: AtomicU64,
+    // Notified whenever writes are applied, for the change feed.
+    committed: Notify,
+    // Every write as it's applied, for `watch`.
+    watchers: broadcast::Sender<Arc<Update>>,
+    // The size of the memtable's logs, and of the tables and retained logs,
+    // which only change when the memtable is flushed or compacted.
+    wal_bytes: AtomicU64,
+    file_bytes: AtomicU64,
+    io: Arc<IoCounters>,
+    // Where the tables open their data files.
+    table_cache: Arc<TableCache>,
+    locks: KeyLocks,
+    timestamps: Arc<Timestamps>,
+    consumers: Consumers,
+    // Held for as long as the database is open for writing.
+    _lock: Option<std::fs::File>,
+    counters: Mutex<CounterCache>,
+    // Every view and iterator open on the database.
+    handles: Arc<Handles>,
+    // How often writes have been held back by `DbOptions::write_stall`. The
+    // state and reasons are filled in by `stats`.
+    stall_stats: Mutex<WriteStallStats>,
+    // This database's share of `DbOptions::write_buffer_manager`, if it's
+    // writable and has one.
+    write_buffer: Option<WriteBuffer>,
+    // How far `catch_up` has read, for a database opened without a log.
+    tail: tokio::sync::Mutex<Option<Tail>>,
+}
+
+// Where a read-only database has caught up to with the one it follows.
+struct Tail {
+    // The manifest when it was last read, to tell whether the tables or logs
+    // have changed since. `None` if it changed while it was read.
+    fingerprint: Option<Vec<u8>>,
+    // The newest log, and how much of it has been applied.
+    wal: PathBuf,
+    offset: u64,
+}
+
+struct PendingWrite {
+    entry: LogEntry,
+    sync: bool,
+    done: oneshot::Sender<Result<(), NdbError>>,
+}
+
+// A frozen memtable on its way to becoming a table. Its writes are in the
+// first `segments` of `DbMeta::wal_segments` until it gets there.
+struct Flushing {
+    memtable: Arc<Memtable>,
+    segments: usize,
+    // Writes the table in the background. `None` once it's failed, until the
+    // flush is tried again.
+    task: Option<JoinHandle<Result<SSTable, NdbError>>>,
+    started: Instant,
+}
+
+// A run of tables merged by a vacuum, and the tables it was merged into.
+type Merged = (Vec<Arc<SSTable>>, Vec<SSTable>);
+
+// A vacuum running in the background, merging each run in turn.
+type Vacuuming = JoinHandle<Result<Vec<Merged>, NdbError>>;
+
+impl Db {
+    pub async fn new(db_dir: impl AsRef<Path>) -> Result<Db, NdbError> {
+        Db::with_options(db_dir, DbOptions::default()).await
+    }
+
+    pub async fn with_options(
+        db_dir: impl AsRef<Path>,
+        options: DbOptions,
+    ) -> Result<Db, NdbError> {
+        Db::open(db_dir, options, true).await
+    }
+
+    /// Opens `delta_dir` as a writable database layered on top of the sealed
+    /// database in `base_dir`. Reads see both, with the delta winning, and
+    /// writes only ever go to the delta. The base is not modified until
+    /// [`Db::compact_into_base`] is called.
+    pub async fn open_overlay(
+        base_dir: impl AsRef<Path>,
+        delta_dir: impl AsRef<Path>,
+        options: DbOptions,
+    ) -> Result<Db, NdbError> {
+        let (base, mut db) = futures::try_join!(
+            Db::open(base_dir, options.clone(), false),
+            Db::open(delta_dir, options.clone(), true),
+        )?;
+        // The delta's sequence numbers carry on from the base's, so they
+        // keep going up once it's compacted into it.
+        db.sequence
+            .fetch_max(base.latest_sequence(), Ordering::Relaxed);
+        db.base = Some(Box::new(base));
+        Ok(db)
+    }
+
+    /// Opens the database in `db_dir` without writing anything to it, e.g.
+    /// to read one another process has open. It sees the database as it was
+    /// when opened, until [`Db::catch_up`] is called, and writes to it fail
+    /// with [`NdbError::ReadOnly`].
+    pub async fn open_read_only(
+        db_dir: impl AsRef<Path>,
+        options: DbOptions,
+    ) -> Result<Db, NdbError> {
+        let dir = db_dir.as_ref();
+        // A flush in the other process can delete the log or tables this is
+        // reading, so it starts over if the metadata changes in the meantime.
+        let storage = &*options.storage;
+        for _ in 1..READ_ONLY_OPEN_ATTEMPTS {
+            let before = manifest::fingerprint(storage, dir).await;
+            let opened = Db::open(dir, options.clone(), false).await;
+            if manifest::fingerprint(storage, dir).await == before {
+                let mut db = opened?;
+                if let Some(tail) = db.tail.get_mut() {
+                    tail.fingerprint = before;
+                }
+                return Ok(db);
+            }
+        }
+        Db::open(db_dir, options, false).await
+    }
+
+    /// Brings a database opened with [`Db::open_read_only`] up to date with
+    /// the process writing to it. Writes logged since it was opened, or last
+    /// caught up, are applied, and if the writer has flushed or vacuumed in
+    /// the meantime, the manifest is read again and the new tables opened.
+    /// A writer that's busy enough to keep changing the manifest while it's
+    /// read can leave the database where it was, to be caught up next time.
+    pub async fn catch_up(&self) -> Result<(), NdbError> {
+        let mut tail = self.tail.lock().await;
+        let Some(tail) = tail.as_mut() else {
+            return Err(NdbError::InvalidArgument(
+                "only a database opened read-only can catch up".into(),
+            ));
+        };
+        let storage = &*self.options.storage;
+        for _ in 0..READ_ONLY_OPEN_ATTEMPTS {
+            let before = manifest::fingerprint(storage, &self.dir).await;
+            if before.is_some() && before == tail.fingerprint {
+                // Only the newest log can have changed. Its records are
+                // published together, once they've all been applied.
+                let memtable = self.memtable.read().unwrap().clone();
+                let mut seq = self.latest_sequence();
+                let tailed = memtable
+                    .tail(storage, &tail.wal, &mut tail.offset, &mut seq)
+                    .await;
+                self.sequence.fetch_max(seq, Ordering::AcqRel);
+                return tailed;
+            }
+            // A flush or vacuum can delete the logs or tables being read, so
+            // what's read is only used if the manifest didn't change.
+            let reloaded = self.reload().await;
+            if manifest::fingerprint(storage, &self.dir).await != before {
+                continue;
+            }
+            let (meta, tables, memtable, offset) = reloaded?;
+            let sequence = newest_sequence(&tables, &memtable);
+            let live: HashSet<_> = meta.sstables.iter().map(|t| &t.data_path).collect();
+            for table in self.tables().iter() {
+                if !live.contains(&table.meta.data_path) {
+                    self.table_cache.remove(&table.meta.data_path);
+                }
+            }
+            {
+                let _flushing = self.flushing.lock().unwrap();
+                *self.memtable.write().unwrap() = Arc::new(memtable);
+                *self.sstables.write().unwrap() = Arc::new(Tables::new(tables));
+                self.sequence.fetch_max(sequence, Ordering::AcqRel);
+            }
+            *tail = Tail {
+                fingerprint: before,
+                wal: meta.wal.clone(),
+                offset,
+            };
+            *self.meta.lock().unwrap() = meta;
+            return self.count_file_bytes().await;
+        }
+        Ok(())
+    }
+
+    /// Catches `db` up with [`Db::catch_up`] every `interval`, in the
+    /// background, until it's dropped: a replica serving reads that are at
+    /// most about `interval` behind the process writing to the database.
+    /// Errors are logged, and it tries again next time.
+    pub fn follow(db: &Arc<Db>, interval: Duration) -> JoinHandle<()> {
+        let db = Arc::downgrade(db);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(db) = db.upgrade() else {
+                    return;
+                };
+                if let Err(err) = db.catch_up().await {
+                    warn!(%err, "couldn't catch up");
+                }
+            }
+        })
+    }
+
+    /// Flushes `db`'s memtable in the background whenever its
+    /// [`WriteBufferManager`](crate::WriteBufferManager) asks it to, until
+    /// it's dropped, rather than waiting for the next write to it. That way
+    /// an idle database can't hold the memory the busy ones need. Errors are
+    /// logged. Does nothing without a write buffer manager.
+    pub fn flush_when_asked(db: &Arc<Db>) -> JoinHandle<()> {
+        let asked = db.write_buffer.as_ref().map(WriteBuffer::asked);
+        let db = Arc::downgrade(db);
+        tokio::spawn(async move {
+            let Some(asked) = asked else {
+                return;
+            };
+            loop {
+                asked.notified().await;
+                let Some(db) = db.upgrade() else {
+                    return;
+                };
+                let flushed = async {
+                    let mut log = db.writable_log()?.lock().await;
+                    db.flush_in_background(&mut log).await?;
+                    drop(log);
+                    // Until it's installed, the frozen memtable still
+                    // counts. Writers are held off meanwhile, since one
+                    // finding the flush's task gone would start it again.
+                    db.wait_for_flush().await
+                };
+                if let Err(err) = flushed.await {
+                    warn!(%err, "couldn't flush the memtable");
+                }
+            }
+        })
+    }
+
+    // The database as its manifest has it now, reusing the tables that are
+    // already open, and where the records in its newest log end.
+    async fn reload(&self) -> Result<(DbMeta, Vec<Arc<SSTable>>, Memtable, u64), NdbError> {
+        let storage = &*self.options.storage;
+        let Some(meta) = manifest::load(storage, &self.dir).await? else {
+            return Err(NdbError::NotFound(format!(
+                "{} is not a database",
+                self.dir.display()
+            )));
+        };
+        let open: HashMap<_, _> = (self.tables().iter())
+            .map(|table| (table.meta.data_path.clone(), table.clone()))
+            .collect();
+        let open = &open;
+        let tables = futures::stream::iter(meta.sstables.clone())
+            .map(|table| async move {
+                match open.get(&table.data_path) {
+                    Some(sstable) => Ok(sstable.clone()),
+                    None => {
+                        let sstable =
+                            SSTable::open(table, &self.options, &self.io, &self.table_cache)
+                                .await?;
+                        Ok(Arc::new(sstable))
+                    }
+                }
+            })
+            .buffer_unordered(self.options.max_open_parallelism.max(1))
+            .try_collect::<Vec<_>>();
+        let wals = meta.memtable_wals();
+        let merge_operator = self.options.merge_operator.clone();
+        let clock = self.options.clock.clone();
+        let recovery = self.options.wal_recovery;
+        let replay = Memtable::hydrate(storage, &wals, merge_operator, clock, recovery);
+        let (mut tables, (memtable, replayed)) = futures::try_join!(tables, replay)?;
+        tables.sort();
+        Ok((meta, tables, memtable, replayed.end))
+    }
+
+    async fn open(
+        db_dir: impl AsRef<Path>,
+        mut options: DbOptions,
+        writable: bool,
+    ) -> Result<Db, NdbError> {
+        secondary_index::validate(&options.secondary_indexes)?;
+        // Tables are found in either tier by their paths.
+        if let Some(cold) = options.cold_tier.clone() {
+            if writable {
+                cold.storage.create_dir_all(&cold.dir).await?;
+            }
+            options.storage = Arc::new(TieredStorage::new(options.storage, cold));
+        }
+        let storage = &options.storage;
+        if !writable && !manifest::exists(&**storage, db_dir.as_ref()).await? {
+            return Err(NdbError::NotFound(format!(
+                "{} is not a database",
+                db_dir.as_ref().display()
+            )));
+        }
+        storage.create_dir_all(db_dir.as_ref()).await?;
+        // Only local files can be locked against other processes.
+        let lock = if writable && storage.is_local() {
+            Some(lock_dir(db_dir.as_ref())?)
+        } else {
+            None
+        };
+        let meta = match manifest::load(&**storage, db_dir.as_ref()).await? {
+            Some(meta) => meta,
+            None => DbMeta::new(db_dir.as_ref().join("log")),
+        };
+        // Every writable open starts a new manifest, which drops anything
+        // cut off at the end of the old one.
+        let max_manifest_bytes = options.max_manifest_bytes;
+        let manifest = if writable {
+            Some(ManifestWriter::create(storage, db_dir.as_ref(), &meta, max_manifest_bytes).await?)
+        } else {
+            None
+        };
+
+        let clock = &options.clock;
+        let table_options = &options;
+        let io = Arc::new(IoCounters::default());
+        let table_io = &io;
+        let file_io = FileIo::new(&options)?;
+        let table_cache = Arc::new(TableCache::new(
+            options.max_open_files,
+            io.clone(),
+            storage.clone(),
+            file_io.clone(),
+        ));
+        let table_files = &table_cache;
+        let open_started = clock.instant();
+        let replay = async {
+            let started = clock.instant();
+            let wals = meta.memtable_wals();
+            let merge_operator = options.merge_operator.clone();
+            let (memtable, replayed) = Memtable::hydrate(
+                &**storage,
+                &wals,
+                merge_operator,
+                clock.clone(),
+                options.wal_recovery,
+            )
+            .await?;
+            Ok::<_, NdbError>((memtable, replayed, clock.instant() - started))
+        };
+        let open_tables = futures::stream::iter(&meta.sstables)
+            .map(|table| async move {
+                let started = clock.instant();
+                let path = table.data_path.clone();
+                let sstable =
+                    SSTable::open(table.clone(), table_options, table_io, table_files).await?;
+                let timing = TableOpenTiming {
+                    path,
+                    duration: clock.instant() - started,
+                };
+                Ok::<_, NdbError>((Arc::new(sstable), timing))
+            })
+            .buffer_unordered(options.max_open_parallelism.max(1))
+            .try_collect::<Vec<_>>();
+        let ((memtable, replayed, wal_replay), tables) = futures::try_join!(replay, open_tables)?;
+
+        let log = if writable {
+            if let Some(len) = replayed.truncate_at {
+                storage.truncate(&meta.wal, len).await?;
+            }
+            Some(Log::open(&**storage, &meta.wal, &file_io).await?)
+        } else {
+            None
+        };
+        let mut wal_bytes = log.as_ref().map_or(0, Log::size);
+        if log.is_some() {
+            for segment in &meta.wal_segments {
+                wal_bytes += storage.size(&segment.path).await?;
+            }
+        }
+        let wal_bytes = AtomicU64::new(wal_bytes);
+        let tail = log.is_none().then(|| Tail {
+            fingerprint: None,
+            wal: meta.wal.clone(),
+            offset: replayed.end,
+        });
+        let log = log.map(tokio::sync::Mutex::new);
+
+        let (mut sstables, sstable_timings): (Vec<_>, Vec<_>) = tables.into_iter().unzip();
+        sstables.sort();
+        let open_stats = OpenStats {
+            total: clock.instant() - open_started,
+            wal_replay,
+            sstables: sstable_timings,
+            wal_records_dropped: replayed.dropped,
+            outdated_tables: sstables
+                .iter()
+                .filter(|t| t.version < FORMAT_VERSION)
+                .count(),
+        };
+
+        let write_stats = Mutex::new(WriteStats::new(&options.metric_prefixes));
+        let recent_writes = Mutex::new(RecentWrites::new(options.conflict_window));
+        let locks = KeyLocks::new(options.lock_timeout);
+        let sequence = newest_sequence(&sstables, &memtable);
+        let newest_table = sstables.first().map_or(0, |t| t.meta.written_timestamp);
+        let consumers = Consumers::load(storage.clone(), &db_dir).await?;
+        let handles = Arc::new(Handles::new(options.clock.clone()));
+        let write_buffer = (options.write_buffer_manager.as_ref())
+            .filter(|_| log.is_some())
+            .map(|manager| manager.register());
+        let db = Db {
+            dir: db_dir.as_ref().into(),
+            options,
+            log,
+            pending: Mutex::new(Vec::new()),
+            poisoned: Mutex::new(None),
+            memtable: RwLock::new(Arc::new(memtable)),
+            flushing: Mutex::new(None),
+            vacuuming: Mutex::new(None),
+            sstables: RwLock::new(Arc::new(Tables::new(sstables))),
+            meta: Mutex::new(meta),
+            manifest: tokio::sync::Mutex::new(manifest),
+            base: None,
+            open_stats,
+            write_stats,
+            recent_writes,
+            sequence: AtomicU64::new(sequence),
+            committed: Notify::new(),
+            watchers: broadcast::channel(changes::WATCH_BUFFER).0,
+            wal_bytes,
+            io,
+            table_cache,
+            file_bytes: AtomicU64::new(0),
+            locks,
+            timestamps: Arc::new(Timestamps::new(newest_table)),
+            consumers,
+            _lock: lock,
+            counters: Mutex::new(CounterCache::default()),
+            handles,
+            write_buffer,
+            stall_stats: Mutex::default(),
+            tail: tokio::sync::Mutex::new(tail),
+        };
+        db.count_file_bytes().await?;
+        Ok(db)
+    }
+
+    /// Deletes the database in `db_dir`: its tables, logs, metadata and
+    /// lock file, and then the directory too if nothing else is left in it.
+    /// Fails without deleting anything if the directory doesn't hold a
+    /// database, or someone has it open for writing. Only for databases on
+    /// the local file system.
+    pub async fn destroy(db_dir: impl AsRef<Path>) -> Result<(), NdbError> {
+        let dir = db_dir.as_ref();
+        if manifest::load(&LocalStorage, dir).await?.is_none() {
+            return Err(NdbError::NotFound(format!(
+                "{} is not a database",
+                dir.display()
+            )));
+        }
+        let lock = lock_dir(dir)?;
+
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            // The lock goes last, once everything else is gone.
+            if name != "LOCK" && is_db_file(&name.to_string_lossy()) {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+        drop(lock);
+        tokio::fs::remove_file(dir.join("LOCK")).await?;
+        match tokio::fs::remove_dir(dir).await {
+            Err(err) if err.kind() == std::io::ErrorKind::DirectoryNotEmpty => Ok(()),
+            removed => Ok(removed?),
+        }
+    }
+
+    /// Rebuilds the manifest of the database in `db_dir` from the files
+    /// there, for when it's been lost or corrupted. Every table is read
+    /// through, and ones that can't be are moved into a `lost` directory
+    /// beside them, as are the old manifest files. Log records that can't be
+    /// read are dropped.
+    ///
+    /// What can be read of the old manifest is used to order the tables and
+    /// pick the logs to replay, and tables it doesn't list are moved into
+    /// `lost` too, since a vacuum may have merged them away. Without it,
+    /// every readable table is kept, ordered by when it was written, which
+    /// can put a vacuum's output ahead of newer tables, and ranges deleted
+    /// with [`Db::delete_range`] come back.
+    pub async fn repair(
+        db_dir: impl AsRef<Path>,
+        options: DbOptions,
+    ) -> Result<RepairReport, NdbError> {
+        let dir = db_dir.as_ref();
+        let _lock = match options.storage.is_local() {
+            true => Some(lock_dir(dir)?),
+            false => None,
+        };
+        repair::repair(dir, &options).await
+    }
+
+    /// Opens several databases at once, e.g. the shards of a larger dataset,
+    /// at most `options.max_open_parallelism` at a time. The results are in
+    /// the same order as `dirs`.
+    pub async fn open_many<P: AsRef<Path>>(
+        dirs: impl IntoIterator<Item = P>,
+        options: DbOptions,
+    ) -> Result<Vec<Db>, NdbError> {
+        let parallelism = options.max_open_parallelism.max(1);
+        futures::stream::iter(dirs)
+            .map(|dir| Db::with_options(dir, options.clone()))
+            .buffered(parallelism)
+            .try_collect()
+            .await
+    }
+
+    pub fn stats(&self) -> DbStats {
+        let (layers, _) = self.read_layers();
+        let tables = || layers.iter().flat_map(|layer| layer.sstables.iter());
+        let mut memtable = MemtableStats::default();
+        let mut io = IoStats::default();
+        for (db, layer) in self.layers().zip(&layers) {
+            for layer in &layer.memtables {
+                memtable.keys += layer.len() as u64;
+                memtable.bytes += layer.bytes();
+                memtable.memory_bytes += layer.memory_usage();
+            }
+            let counters = db.io.snapshot();
+            io.table_bytes_read += counters.table_bytes_read;
+            io.table_bytes_written += counters.table_bytes_written;
+            io.table_syncs += counters.table_syncs;
+            io.wal_bytes_written += counters.wal_bytes_written;
+            io.bloom_checks += counters.bloom_checks;
+            io.bloom_negatives += counters.bloom_negatives;
+            io.bloom_false_positives += counters.bloom_false_positives;
+            io.table_file_opens += counters.table_file_opens;
+            io.table_files_open += db.table_cache.len() as u64;
+        }
+        io.tables_mapped = tables().filter(|t| t.is_mapped()).count() as u64;
+        let level = LevelStats {
+            level: 0,
+            tables: tables().count() as u64,
+            bytes: tables().map(|t| t.size).sum(),
+        };
+        let levels = if level.tables > 0 {
+            vec![level]
+        } else {
+            vec![]
+        };
+
+        let tiers = match &self.options.cold_tier {
+            Some(cold) => [StorageTier::Hot, StorageTier::Cold]
+                .map(|tier| {
+                    let in_tier =
+                        || tables().filter(move |t| cold.tier_of(&t.meta.data_path) == tier);
+                    TierStats {
+                        tier,
+                        tables: in_tier().count() as u64,
+                        bytes: in_tier().map(|t| t.size).sum(),
+                    }
+                })
+                .to_vec(),
+            None => Vec::new(),
+        };
+
+        let sizes: Vec<_> = layers[0].sstables.iter().map(|t| t.size).collect();
+        let options = self.options.vacuum.clone().unwrap_or_default();
+        let mut vacuum_backlog = LevelStats::default();
+        for run in vacuum::small_runs(&sizes, &options) {
+            vacuum_backlog.tables += run.len() as u64;
+            vacuum_backlog.bytes += sizes[run].iter().sum::<u64>();
+        }
+
+        let writes = self.write_stats.lock().unwrap().clone();
+        let written = io.wal_bytes_written + io.table_bytes_written;
+        let write_amplification = match writes.total.bytes {
+            0 => 0.0,
+            bytes => written as f64 / bytes as f64,
+        };
+        DbStats {
+            open: self.open_stats.clone(),
+            writes,
+            conflicts: self.recent_writes.lock().unwrap().stats(),
+            handles: self.handles.stats(tables()),
+            memtable,
+            levels,
+            tiers,
+            io,
+            write_amplification,
+            vacuum_backlog,
+            write_stall: self.write_stall_stats(),
+        }
+    }
+
+    fn write_stall_stats(&self) -> WriteStallStats {
+        let mut stats = self.stall_stats.lock().unwrap().clone();
+        if let Some(options) = &self.options.write_stall {
+            let stall = self.stall(options);
+            stats.state = stall.state;
+            stats.reasons = stall.reasons;
+        }
+        stats
+    }
+
+    /// Reads every block of every table, including an overlay's base's, and
+    /// returns the ones that are corrupt. Corruption only comes back as an
+    /// error from here if it's somewhere other than a data block, e.g. in a
+    /// table's footer.
+    pub async fn verify_integrity(&self) -> Result<Vec<CorruptBlock>, NdbError> {
+        let mut corrupt = Vec::new();
+        for sstable in self.layers().flat_map(|db| db.tables().to_vec()) {
+            for i in 0..sstable.block_count() {
+                match sstable.block_entries(i).await {
+                    Ok(_) => {}
+                    Err(NdbError::Corruption { detail, .. }) => corrupt.push(CorruptBlock {
+                        path: sstable.meta.data_path.clone(),
+                        offset: sstable.block_offset(i),
+                        reason: detail,
+                    }),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(corrupt)
+    }
+
+    /// Checks every file the database depends on, including an overlay's
+    /// base's: every block of every table against its checksum, each
+    /// table's data file against the checksum recorded for it, that keys are
+    /// in order, and every log record. Then compares the manifest against
+    /// the files that are there. Unlike [`Db::verify_integrity`], anything
+    /// found wrong is reported rather than returned as an error.
+    pub async fn verify_checksums(&self) -> Result<VerifyReport, NdbError> {
+        let storage = &*self.options.storage;
+        let mut report = VerifyReport::default();
+        for db in self.layers() {
+            let meta = db.meta.lock().unwrap().clone();
+            let mut listed = HashSet::new();
+            for path in manifest::files(storage, &db.dir, &meta).await? {
+                if !storage.exists(&path).await? {
+                    let kind = match path.extension() {
+                        Some(ext) if ext == "meta" => FileKind::TableMeta,
+                        _ => FileKind::DbMeta,
+                    };
+                    let path = path.clone();
+                    report
+                        .problems
+                        .push(VerifyProblem::MissingFile { path, kind });
+                }
+                listed.insert(path);
+            }
+            let wals = meta.memtable_wals();
+            for (i, wal) in wals.iter().enumerate() {
+                let newest = i + 1 == wals.len();
+                verify::verify_log(storage, wal, newest, &mut report).await?;
+            }
+            for wal in &meta.retained_wals {
+                verify::verify_log(storage, &wal.path, false, &mut report).await?;
+            }
+            listed.extend(wals);
+            listed.extend(meta.retained_wals.into_iter().map(|wal| wal.path));
+            for sstable in db.tables().iter() {
+                let path = &sstable.meta.data_path;
+                listed.insert(path.clone());
+                if !storage.exists(path).await? {
+                    report.problems.push(VerifyProblem::MissingFile {
+                        path: path.clone(),
+                        kind: FileKind::Table,
+                    });
+                    continue;
+                }
+                verify::verify_table(sstable, &mut report).await?;
+            }
+
+            let cold = db.options.cold_tier.as_ref().map(|cold| cold.dir.clone());
+            for dir in std::iter::once(db.dir.clone()).chain(cold) {
+                let files = match storage.list(&dir).await {
+                    Err(err) if storage::is_not_found(&err) => continue,
+                    files => files?,
+                };
+                for path in files {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    if is_table_or_log(&name) && !listed.contains(&path) {
+                        report.problems.push(VerifyProblem::OrphanFile(path));
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Lists every file the database currently depends on, including those of
+    /// an overlay's base. Copying them all gives a consistent copy, as long
+    /// as nothing is written in the meantime.
+    pub async fn live_files(&self) -> Result<Vec<LiveFile>, NdbError> {
+        let mut files = Vec::new();
+        let storage = &*self.options.storage;
+        for db in self.layers() {
+            let meta = db.meta.lock().unwrap().clone();
+            for path in manifest::files(storage, &db.dir, &meta).await? {
+                let kind = match path.extension() {
+                    Some(ext) if ext == "meta" => FileKind::TableMeta,
+                    _ => FileKind::DbMeta,
+                };
+                files.push(LiveFile::other(storage, path, kind).await?);
+            }
+            for wal in meta.memtable_wals() {
+                // A read-only layer might never have had a log created.
+                if storage.exists(&wal).await? {
+                    files.push(LiveFile::other(storage, wal, FileKind::Wal).await?);
+                }
+            }
+            for wal in meta.retained_wals {
+                files.push(LiveFile::other(storage, wal.path, FileKind::Wal).await?);
+            }
+            let consumers = db.consumers.path();
+            if storage.exists(consumers).await? {
+                let kind = FileKind::Consumers;
+                files.push(LiveFile::other(storage, consumers.into(), kind).await?);
+            }
+            for sstable in db.tables().iter() {
+                let meta = &sstable.meta;
+                files.push(LiveFile {
+                    path: meta.data_path.clone(),
+                    kind: FileKind::Table,
+                    level: Some(0),
+                    size: storage.size(&meta.data_path).await?,
+                    smallest_key: meta.smallest_key.clone(),
+                    largest_key: meta.largest_key.clone(),
+                    checksum: meta.checksum,
+                    sequence_range: meta.sequence_range,
+                });
+            }
+        }
+
+        Ok(files)
+    }
+
+    // This database followed by everything underneath it, newest first.
+    fn layers(&self) -> impl Iterator<Item = &Db> {
+        std::iter::successors(Some(self), |db| db.base.as_deref())
+    }
+
+    // The database's tables as they are now.
+    fn tables(&self) -> Arc<Tables> {
+        self.sstables.read().unwrap().clone()
+    }
+
+    /// Puts `value` at `key`, replacing whatever was there, merge operands
+    /// included: reads stop at the newest put of a key without looking at
+    /// older tables, and a vacuum keeps only that version.
+    pub async fn put(&self, key: &[u8], value: &[u8]) -> Result<(), NdbError> {
+        self.put_opt(key, value, &WriteOptions::default()).await
+    }
+
+    pub async fn put_opt(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        options: &WriteOptions,
+    ) -> Result<(), NdbError> {
+        let mut batch = WriteBatch::new();
+        batch.put(key, value);
+        self.write_opt(batch, options).await
+    }
+
+    /// Puts a value that reads as deleted once `ttl` has passed. Expired
+    /// values are dropped when they're compacted. A `ttl` too long to store,
+    /// like [`Duration::MAX`], never expires.
+    pub async fn put_with_ttl(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<(), NdbError> {
+        let mut batch = WriteBatch::new();
+        match clock::expiry(&*self.options.clock, ttl) {
+            Some(expires_at) => batch.put_until(key, value, expires_at),
+            None => batch.put(key, value),
+        }
+        self.write(batch).await
+    }
+
+    /// Adds `operand` to the value of `key` with the configured
+    /// [`DbOptions::merge_operator`], without reading it first.
+    pub async fn merge(&self, key: &[u8], operand: &[u8]) -> Result<(), NdbError> {
+        let mut batch = WriteBatch::new();
+        batch.merge(key, operand);
+        self.write(batch).await
+    }
+
+    pub async fn delete(&self, key: &[u8]) -> Result<(), NdbError> {
+        let mut batch = WriteBatch::new();
+        batch.delete(key);
+        self.write(batch).await
+    }
+
+    /// Deletes every key from `start` up to, but not including, `end`.
+    pub async fn delete_range(&self, start: &[u8], end: &[u8]) -> Result<(), NdbError> {
+        let mut batch = WriteBatch::new();
+        batch.delete_range(start, end);
+        self.write(batch).await
+    }
+
+    pub async fn write(&self, batch: WriteBatch) -> Result<(), NdbError> {
+        self.write_opt(batch, &WriteOptions::default()).await
+    }
+
+    /// Applies every write in `batch` atomically: after a crash, either all
+    /// of them are there or none are.
+    pub async fn write_opt(
+        &self,
+        mut batch: WriteBatch,
+        options: &WriteOptions,
+    ) -> Result<(), NdbError> {
+        metrics::timed(metrics::WRITE_SECONDS, async {
+            self.prepare_batch(&mut batch).await?;
+            let log = self.writable_log()?;
+            if batch.is_empty() {
+                return Ok(());
+            }
+            // Only lone puts, since a batch could change the key before its put.
+            if let [LogEntry::Put { key, value }] = batch.entries.as_slice() {
+                if self.options.skip_identical_puts
+                    && self.memtable.read().unwrap().holds(key, value)
+                {
+                    self.write_stats.lock().unwrap().skipped_puts += 1;
+                    return Ok(());
+                }
+            }
+
+            let mut entry = batch.into_entry();
+            if options.disable_wal {
+                // Still under the log lock, so it gets its sequence number in
+                // order with everything else.
+                let mut log = log.lock().await;
+                self.index(&mut entry, &mut HashMap::new()).await?;
+                self.apply([entry], self.latest_sequence() + 1);
+                // It fills the memtable all the same. As for a commit, a
+                // failed flush is tried again on the next write.
+                if let Err(err) = self.flush_in_background(&mut log).await {
+                    warn!(%err, "couldn't flush the memtable");
+                }
+                return Ok(());
+            }
+            self.commit(log, entry, options.sync).await
+        })
+        .await
+    }
+
+    // Checks that `batch` can be written, and encodes its values with any
+    // transforms.
+    async fn prepare_batch(&self, batch: &mut WriteBatch) -> Result<(), NdbError> {
+        let transforms = &self.options.value_transforms;
+        for entry in &batch.entries {
+            secondary_index::check(&self.options.secondary_indexes, entry)?;
+            match entry {
+                LogEntry::Put { key, value } | LogEntry::PutUntil { key, value, .. } => {
+                    if let Some(validator) = &self.options.validator {
+                        validator.validate(key, value)?;
+                    }
+                }
+                LogEntry::DeleteRange { start, end } if start > end => {
+                    return Err(NdbError::InvalidArgument(
+                        "delete_range start is after its end".into(),
+                    ));
+                }
+                LogEntry::Merge { .. } if self.options.merge_operator.is_none() => {
+                    return Err(merge::no_operator());
+                }
+                LogEntry::Merge { key, .. } if transform::for_key(transforms, key).is_some() => {
+                    return Err(NdbError::InvalidArgument(
+                        "merge under a prefix with a value transform".into(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        if let Some(limit) = self.options.max_total_bytes {
+            let used = self.total_bytes();
+            if used > limit {
+                return Err(NdbError::QuotaExceeded { used, limit });
+            }
+        }
+        self.throttle().await?;
+        if !transforms.is_empty() {
+            for entry in &mut batch.entries {
+                if let LogEntry::Put { key, value } | LogEntry::PutUntil { key, value, .. } = entry
+                {
+                    *value = transform::encode(transforms, key, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Holds a write back while tables are piling up, or the memtable's full
+    // and the one before it is still being flushed. See
+    // `DbOptions::write_stall`.
+    async fn throttle(&self) -> Result<(), NdbError> {
+        let Some(options) = &self.options.write_stall else {
+            return Ok(());
+        };
+        loop {
+            let stall = self.stall(options);
+            if stall.rejects() {
+                if self.wait_for_vacuum().await? {
+                    self.stall_stats.lock().unwrap().vacuum_waits += 1;
+                    continue;
+                }
+                self.stall_stats.lock().unwrap().stopped_writes += 1;
+                return Err(NdbError::WritesStopped(stall.reasons));
+            }
+            match stall.state {
+                WriteStallState::Normal => return Ok(()),
+                WriteStallState::Delayed => {
+                    {
+                        let mut stats = self.stall_stats.lock().unwrap();
+                        stats.delayed_writes += 1;
+                        stats.delay += stall.delay;
+                    }
+                    tokio::time::sleep(stall.delay).await;
+                    return Ok(());
+                }
+                WriteStallState::Stopped => {
+                    self.stall_stats.lock().unwrap().flush_waits += 1;
+                    self.wait_for_flush().await?;
+                }
+            }
+        }
+    }
+
+    // Waits for a vacuum to merge tables, starting one if the database
+    // vacuums and there's a run to merge, and installs what it merged.
+    // Returns whether that merged any tables away.
+    async fn wait_for_vacuum(&self) -> Result<bool, NdbError> {
+        let _log = self.writable_log()?.lock().await;
+        self.vacuum_in_background();
+        let Some(task) = self.vacuuming.lock().unwrap().take() else {
+            return Ok(false);
+        };
+        let merged = task
+            .await
+            .map_err(|err| NdbError::Io(std::io::Error::other(err)))??;
+        let merged = self.install_vacuum(merged).await?;
+        self.count_file_bytes().await?;
+        Ok(merged > 0)
+    }
+
+    fn stall(&self, options: &WriteStallOptions) -> Stall {
+        let tables = self.tables();
+        let bytes = tables.iter().map(|t| t.size).sum();
+        let full = self
+            .options
+            .write_buffer_size
+            .is_some_and(|limit| self.memtable.read().unwrap().memory_usage() >= limit);
+        let flushing = self.flushing.lock().unwrap().is_some();
+        Stall::check(options, tables.len() as u64, bytes, full && flushing)
+    }
+
+    // Waits for the frozen memtable's flush and installs it, retrying it if
+    // it last failed. Unlike `finish_flush`, the log lock is held
+    // throughout, so nothing else can install it in the meantime.
+    async fn wait_for_flush(&self) -> Result<(), NdbError> {
+        let log = self.writable_log()?.lock().await;
+        let task = {
+            let mut flushing = self.flushing.lock().unwrap();
+            let Some(flushing) = flushing.as_mut() else {
+                return Ok(());
+            };
+            match flushing.task.take() {
+                Some(task) => task,
+                None => self.spawn_flush(flushing.memtable.clone())?,
+            }
+        };
+        self.install_flush(task.await, &log).await
+    }
+
+    // Decodes a value read back from the database.
+    fn decode(&self, key: &[u8], value: Option<Vec<u8>>) -> Result<Option<Vec<u8>>, NdbError> {
+        let transforms = &self.options.value_transforms;
+        value
+            .map(|value| transform::decode(transforms, key, value))
+            .transpose()
+    }
+
+    /// The size of the database's own tables and logs, which is what
+    /// [`DbOptions::max_total_bytes`] limits. An overlay's base isn't
+    /// included.
+    pub fn total_bytes(&self) -> u64 {
+        self.file_bytes.load(Ordering::Relaxed) + self.wal_bytes.load(Ordering::Relaxed)
+    }
+
+    async fn count_file_bytes(&self) -> Result<(), NdbError> {
+        let meta = self.meta.lock().unwrap().clone();
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for sstable in self.tables().iter() {
+            paths.push(sstable.meta.data_path.clone());
+        }
+        paths.extend(meta.retained_wals.into_iter().map(|wal| wal.path));
+        let mut bytes = 0;
+        for path in paths {
+            bytes += self.options.storage.size(&path).await?;
+        }
+        self.file_bytes.store(bytes, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // Sets the size of the memtable's logs, after they've changed.
+    async fn count_wal_bytes(&self, log: &Log) -> Result<(), NdbError> {
+        let mut bytes = log.size();
+        let segments = self.meta.lock().unwrap().wal_segments.clone();
+        for segment in segments {
+            bytes += self.options.storage.size(&segment.path).await?;
+        }
+        self.wal_bytes.store(bytes, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Sets `key` to `new` if its current value is `expected`, and returns
+    /// whether it did. `None` stands for the key not existing, on either
+    /// side. The read and the write happen under the log lock, so no other
+    /// write can get in between them.
+    pub async fn compare_and_swap(
+        &self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, NdbError> {
+        let mut batch = WriteBatch::new();
+        match new {
+            Some(value) => batch.put(key, value),
+            None => batch.delete(key),
+        }
+        self.prepare_batch(&mut batch).await?;
+        let log = self.writable_log()?;
+
+        let mut log = log.lock().await;
+        if self.get(key).await?.as_deref() != expected {
+            return Ok(false);
+        }
+        self.commit_locked(&mut log, batch.into_entry()).await?;
+        Ok(true)
+    }
+
+    /// Adds `delta` to the counter at `key` and returns its new value. See
+    /// [`counter_merge_operator`](crate::counter_merge_operator) for how
+    /// counters are stored, which [`DbOptions::merge_operator`] has to
+    /// agree with. Only the delta is written, as a merge operand, and
+    /// counters incremented recently are cached, so bumping a busy counter
+    /// doesn't read it first.
+    pub async fn increment(&self, key: &[u8], delta: i64) -> Result<i64, NdbError> {
+        let mut batch = WriteBatch::new();
+        batch.merge(key, &delta.to_le_bytes());
+        self.prepare_batch(&mut batch).await?;
+        let log = self.writable_log()?;
+
+        // Nothing else is applied while the log lock is held, so the cache
+        // can't go stale in between.
+        let mut log = log.lock().await;
+        let cached = self.counters.lock().unwrap().get(key);
+        let (current, cache) = match cached {
+            Some(current) => (current, true),
+            None => {
+                let value = self.get_value(key).await?;
+                // A counter with a TTL would go stale in the cache once it
+                // expired.
+                let expires = matches!(value, Some(Value::PutUntil { .. }));
+                let now = self.options.clock.unix_millis();
+                let value = value.and_then(|value| value.live(now));
+                (counter::decode(key, value.as_deref())?, !expires)
+            }
+        };
+        let new = current.wrapping_add(delta);
+        self.commit_locked(&mut log, batch.into_entry()).await?;
+        if cache {
+            self.counters.lock().unwrap().insert(key, new);
+        }
+        Ok(new)
+    }
+
+    /// Starts an optimistic [`Transaction`].
+    pub fn transaction(&self) -> Transaction<'_> {
+        Transaction::new(self)
+    }
+
+    /// Returns an iterator over the keys whose values have index keys in
+    /// `range` in the secondary index `name`, with their values, in order of
+    /// index key and then key. The index and the values are read from the
+    /// same [view](Db::freeze_view) of the database.
+    pub async fn index_scan(
+        &self,
+        name: &str,
+        range: impl RangeBounds<Vec<u8>>,
+    ) -> Result<IndexIterator, NdbError> {
+        let index = secondary_index::find(&self.options.secondary_indexes, name)?;
+        IndexIterator::new(index.clone(), self.freeze_view(), range).await
+    }
+
+    /// Typed access to the keys under `prefix`, with keys of type `K` and
+    /// values of type `V`. See [`Table`].
+    pub fn table<K, V>(&self, prefix: &[u8]) -> Table<'_, K, V>
+    where
+        K: Serialize + DeserializeOwned,
+        V: Serialize + DeserializeOwned,
+    {
+        Table::new(self, prefix)
+    }
+
+    pub(crate) fn locks(&self) -> &KeyLocks {
+        &self.locks
+    }
+
+    // Commits `batch` unless one of `reads` has been written since the
+    // sequence number it maps to, checking under the log lock.
+    pub(crate) async fn commit_unless_written(
+        &self,
+        reads: &BTreeMap<Vec<u8>, u64>,
+        mut batch: WriteBatch,
+    ) -> Result<(), NdbError> {
+        self.prepare_batch(&mut batch).await?;
+        let log = self.writable_log()?;
+
+        let mut by_since: BTreeMap<u64, Vec<&[u8]>> = BTreeMap::new();
+        for (key, &since) in reads {
+            by_since.entry(since).or_default().push(key);
+        }
+        let mut log = log.lock().await;
+        if by_since
+            .into_iter()
+            .any(|(since, keys)| self.written_since(since, keys))
+        {
+            return Err(NdbError::Conflict);
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.commit_locked(&mut log, batch.into_entry()).await
+    }
+
+    // Commits `entry` on its own, straight away. The caller must hold the log
+    // lock.
+    async fn commit_locked(&self, log: &mut Log, entry: LogEntry) -> Result<(), NdbError> {
+        let (done, result) = oneshot::channel();
+        self.pending.lock().unwrap().push(PendingWrite {
+            entry,
+            sync: WriteOptions::default().sync,
+            done,
+        });
+        self.commit_pending(log).await;
+        result.await.unwrap()
+    }
+
+    /// Logs `batch` without applying it, as the first phase of a commit that
+    /// some other system is coordinating across several stores. It's checked
+    /// just as [`Db::write`] would check it, and isn't seen by reads until
+    /// it's committed with [`Db::commit_prepared`], or dropped with
+    /// [`Db::rollback_prepared`]. Until then it stays prepared, through
+    /// crashes and reopening; [`Db::prepared`] lists what's waiting.
+    ///
+    /// Preparing a batch doesn't lock its keys. Other writes to them go ahead,
+    /// and the batch's writes land on top of them when it's committed.
+    pub async fn prepare(&self, mut batch: WriteBatch) -> Result<PreparedToken, NdbError> {
+        self.prepare_batch(&mut batch).await?;
+        let mut log = self.writable_log()?.lock().await;
+        // No more than the sequence number it's logged at, so it's never
+        // reused, even after reopening.
+        let id = self.latest_sequence() + 1;
+        let ops = batch.entries;
+        self.commit_locked(&mut log, LogEntry::Prepare { id, ops })
+            .await?;
+        Ok(PreparedToken(id))
+    }
+
+    /// Applies the writes prepared as `token` atomically, as a new write.
+    /// Returns [`NdbError::NotFound`] if nothing is prepared as `token`,
+    /// e.g. because it's already been committed or rolled back.
+    pub async fn commit_prepared(&self, token: PreparedToken) -> Result<(), NdbError> {
+        let mut log = self.writable_log()?.lock().await;
+        let ops = self.prepared_batch(token)?;
+        let id = token.0;
+        self.commit_locked(&mut log, LogEntry::Commit { id, ops })
+            .await
+    }
+
+    /// Drops the writes prepared as `token` without applying them. Returns
+    /// [`NdbError::NotFound`] if nothing is prepared as `token`.
+    pub async fn rollback_prepared(&self, token: PreparedToken) -> Result<(), NdbError> {
+        let mut log = self.writable_log()?.lock().await;
+        self.prepared_batch(token)?;
+        self.commit_locked(&mut log, LogEntry::Rollback { id: token.0 })
+            .await
+    }
+
+    /// The batches that have been prepared and not yet committed or rolled
+    /// back, oldest first.
+    pub fn prepared(&self) -> Vec<PreparedToken> {
+        let prepared = self.memtable.read().unwrap().prepared();
+        prepared.into_keys().map(PreparedToken).collect()
+    }
+
+    // The writes prepared as `token`. The caller must hold the log lock, so
+    // that it isn't committed or rolled back in the meantime.
+    fn prepared_batch(&self, token: PreparedToken) -> Result<Vec<LogEntry>, NdbError> {
+        let batch = self.memtable.read().unwrap().prepared_batch(token.0);
+        batch.ok_or_else(|| NdbError::NotFound(format!("no batch prepared as {}", token.0)))
+    }
+
+    /// The options the database was opened with, e.g. for its
+    /// [`clock`](DbOptions::clock).
+    pub fn options(&self) -> &DbOptions {
+        &self.options
+    }
+
+    /// The sequence number of the last write. Every write, including each
+    /// batch as a whole, gets the next one, which is logged with it.
+    pub fn latest_sequence(&self) -> u64 {
+        self.sequence.load(Ordering::Acquire)
+    }
+
+    /// Whether any of `keys` has been written since sequence number `since`,
+    /// e.g. to check whether values read at [`Db::latest_sequence`] are still
+    /// current. Only the last [`DbOptions::conflict_window`] writes are
+    /// remembered; reaching back past them returns true.
+    pub fn written_since<'a>(&self, since: u64, keys: impl IntoIterator<Item = &'a [u8]>) -> bool {
+        self.recent_writes
+            .lock()
+            .unwrap()
+            .written_since(since, keys)
+    }
+
+    /// Syncs everything written to the WAL so far, including writes made
+    /// without [`WriteOptions::sync`].
+    pub async fn sync_wal(&self) -> Result<(), NdbError> {
+        let log = self.writable_log()?;
+        log.lock().await.sync().await?;
+        self.write_stats.lock().unwrap().wal_syncs += 1;
+        Ok(())
+    }
+
+    /// Records that `consumer` has processed the change feed up to and
+    /// including `seq`, durably. A consumer that restarts picks up from
+    /// [`Db::acked`], so it sees every change at least once. Acks never move a
+    /// consumer backwards.
+    pub async fn ack(&self, consumer: &str, seq: u64) -> Result<(), NdbError> {
+        if self.log.is_none() {
+            return Err(NdbError::ReadOnly);
+        }
+        self.consumers.ack(consumer, seq).await
+    }
+
+    /// Where `consumer` last acknowledged, or `None` if it never has.
+    pub async fn acked(&self, consumer: &str) -> Option<u64> {
+        self.consumers.acked(consumer).await
+    }
+
+    /// The lowest position acknowledged by any consumer. Log retention keeps
+    /// everything after it: when the memtable is flushed, older logs are
+    /// deleted once every consumer has acknowledged all of their writes, or
+    /// straight away if there are no consumers.
+    pub async fn min_acked(&self) -> Option<u64> {
+        self.consumers.min_acked().await
+    }
+
+    /// Streams every write logged after sequence number `seq`, in order, and
+    /// then each new one as it commits, so that another system can follow
+    /// along. The stream never ends unless the database is read-only, in
+    /// which case it ends with the last logged write. A consumer that
+    /// [acks](Db::ack) what it has processed can pick up again from
+    /// [`Db::acked`].
+    ///
+    /// Only this database's own logged writes are included: not those made
+    /// with [`WriteOptions::disable_wal`], nor an overlay's base. Returns
+    /// [`NdbError::InvalidArgument`] if the logs after `seq` have already
+    /// been deleted.
+    pub fn updates_since(
+        &self,
+        seq: u64,
+    ) -> Result<impl Stream<Item = Result<Update, NdbError>> + '_, NdbError> {
+        let meta = self.meta.lock().unwrap().clone();
+        if seq < meta.pruned_through {
+            return Err(NdbError::InvalidArgument(format!(
+                "the log before sequence {} has been deleted",
+                meta.pruned_through
+            )));
+        }
+        let paths = meta.retained_wals.iter().map(|wal| wal.path.clone());
+        let paths = paths.chain(meta.memtable_wals()).collect();
+        let following = self.log.as_ref().map(|_| (&self.committed, &self.meta));
+        let storage = &*self.options.storage;
+        Ok(changes::updates(storage, paths, seq, following))
+    }
+
+    // Logs `entry` and then applies it to the memtable. Concurrent writes are
+    // committed in groups: the first writer to get the log lock logs
+    // everything that's waiting, syncing once if any of them asked for it,
+    // and the rest find their writes already done when they get the lock.
+    async fn commit(
+        &self,
+        log: &tokio::sync::Mutex<Log>,
+        entry: LogEntry,
+        sync: bool,
+    ) -> Result<(), NdbError> {
+        let (done, mut result) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .push(PendingWrite { entry, sync, done });
+
+        let mut log = log.lock().await;
+        match result.try_recv() {
+            Ok(result) => return result,
+            // The leader that took this write was cancelled partway through.
+            Err(TryRecvError::Closed) => {
+                return Err(NdbError::Io(std::io::Error::new(
+                    std::io::ErrorKind::Interrupted,
+                    "write was cancelled",
+                )))
+            }
+            Err(TryRecvError::Empty) => {}
+        }
+        self.commit_pending(&mut log).await;
+        drop(log);
+
+        // This write was part of the group.
+        result.await.unwrap()
+    }
+
+    // The log, unless the database is read-only or an append to it failed.
+    fn writable_log(&self) -> Result<&tokio::sync::Mutex<Log>, NdbError> {
+        let log = self.log.as_ref().ok_or(NdbError::ReadOnly)?;
+        match &*self.poisoned.lock().unwrap() {
+            Some(cause) => Err(NdbError::Poisoned(cause.clone())),
+            None => Ok(log),
+        }
+    }
+
+    // Logs and applies every write that's waiting, and tells each of them how
+    // it went. The caller must hold the log lock.
+    async fn commit_pending(&self, log: &mut Log) {
+        let mut group = std::mem::take(&mut *self.pending.lock().unwrap());
+        if let Some(cause) = &*self.poisoned.lock().unwrap() {
+            for write in group {
+                let _ = write.done.send(Err(NdbError::Poisoned(cause.clone())));
+            }
+            return;
+        }
+        if !self.options.secondary_indexes.is_empty() {
+            group = self.index_group(group).await;
+            if group.is_empty() {
+                return;
+            }
+        }
+        let first = self.latest_sequence() + 1;
+        let records: Vec<_> = (first..)
+            .zip(&group)
+            .map(|(seq, write)| LogRecord {
+                seq,
+                entry: &write.entry,
+            })
+            .collect();
+        let sync = group.iter().any(|w| w.sync);
+        let size = log.size();
+        let logged = log.append(records, sync).await;
+        let written = log.size() - size;
+        self.wal_bytes.fetch_add(written, Ordering::Relaxed);
+        self.io
+            .wal_bytes_written
+            .fetch_add(written, Ordering::Relaxed);
+        match logged {
+            Ok(()) => {
+                self.write_stats.lock().unwrap().wal_syncs += sync as u64;
+                let full = self.options.wal_segment_bytes;
+                if full.is_some_and(|limit| log.size() >= limit) {
+                    // The writes are logged either way, so if a new segment
+                    // can't be started now it's tried again on the next
+                    // commit.
+                    let last = first + group.len() as u64 - 1;
+                    if let Err(err) = self.rotate_wal(log, Some(last)).await {
+                        warn!(%err, "couldn't start a new log segment");
+                    }
+                }
+                let (entries, done): (Vec<_>, Vec<_>) =
+                    group.into_iter().map(|w| (w.entry, w.done)).unzip();
+                self.apply(entries, first);
+                for done in done {
+                    let _ = done.send(Ok(()));
+                }
+                // Like rotating the log, this is tried again on the next
+                // commit if it fails.
+                if let Err(err) = self.flush_in_background(log).await {
+                    warn!(%err, "couldn't flush the memtable");
+                }
+            }
+            Err(err) => {
+                *self.poisoned.lock().unwrap() = Some(err.to_string());
+                for write in group {
+                    let _ = write.done.send(Err(shared_error(&err)));
+                }
+            }
+        }
+    }
+
+    // Adds the writes that keep the secondary indexes up to date to each of
+    // `group`'s, dropping any that can't be indexed and telling them why.
+    async fn index_group(&self, group: Vec<PendingWrite>) -> Vec<PendingWrite> {
+        let mut indexed = HashMap::new();
+        let mut ok = Vec::with_capacity(group.len());
+        for mut write in group {
+            match self.index(&mut write.entry, &mut indexed).await {
+                Ok(()) => ok.push(write),
+                Err(err) => {
+                    let _ = write.done.send(Err(err));
+                }
+            }
+        }
+        ok
+    }
+
+    // Adds the writes that keep the secondary indexes up to date to `entry`:
+    // removing the entries for the index keys its keys had, and adding ones
+    // for those they'll have. `indexed` has the index keys left by the writes
+    // ahead of it that haven't been applied yet, and gets `entry`'s added if
+    // it succeeds. The caller must hold the log lock.
+    async fn index(
+        &self,
+        entry: &mut LogEntry,
+        indexed: &mut HashMap<(usize, Vec<u8>), Option<Vec<u8>>>,
+    ) -> Result<(), NdbError> {
+        let indexes = &self.options.secondary_indexes;
+        let mut seen = HashMap::new();
+        let mut writes = Vec::new();
+        for op in entry.ops() {
+            let (key, value) = match op {
+                LogEntry::Put { key, value } | LogEntry::PutUntil { key, value, .. } => {
+                    (key, Some(value))
+                }
+                LogEntry::Delete { key } => (key, None),
+                _ => continue,
+            };
+            if !indexes.iter().any(|index| index.covers(key)) {
+                continue;
+            }
+            let value = self.decode(key, value.cloned())?;
+            for (i, index) in indexes.iter().enumerate() {
+                if !index.covers(key) {
+                    continue;
+                }
+                let new = value.as_deref().and_then(|value| (index.extract)(value));
+                let id = (i, key.clone());
+                let old = match seen.get(&id).or_else(|| indexed.get(&id)) {
+                    Some(old) => old.clone(),
+                    None => self.index_key(index, key).await?,
+                };
+                if old != new {
+                    if let Some(old) = &old {
+                        let key = index.entry(old, key);
+                        writes.push(LogEntry::Delete { key });
+                    }
+                    if let Some(new) = &new {
+                        let key = index.entry(new, key);
+                        writes.push(LogEntry::Put {
+                            key,
+                            value: Vec::new(),
+                        });
+                    }
+                }
+                seen.insert(id, new);
+            }
+        }
+        indexed.extend(seen);
+        if writes.is_empty() {
+            return Ok(());
+        }
+        match entry {
+            LogEntry::Batch(ops) | LogEntry::Commit { ops, .. } => ops.extend(writes),
+            _ => {
+                let op = std::mem::replace(entry, LogEntry::Batch(Vec::new()));
+                writes.insert(0, op);
+                *entry = LogEntry::Batch(writes);
+            }
+        }
+        Ok(())
+    }
+
+    // The index key `index` has an entry for `key` under, going by its newest
+    // value. An expired value still has its entry, until it's overwritten.
+    async fn index_key(
+        &self,
+        index: &SecondaryIndex,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, NdbError> {
+        let value = match self.get_value(key).await? {
+            Some(Value::Put(value) | Value::PutUntil { value, .. }) => value,
+            _ => return Ok(None),
+        };
+        let value = transform::decode(&self.options.value_transforms, key, value)?;
+        Ok((index.extract)(&value))
+    }
+
+    // Moves on to a new segment of the log, once everything up to `last_seq`
+    // has been logged to the current one. The caller must hold the log lock.
+    async fn rotate_wal(&self, log: &mut Log, last_seq: Option<u64>) -> Result<(), NdbError> {
+        let path = self.dir.join(self.get_filename("log").await?);
+        let next = Log::open(&*self.options.storage, &path, &self.table_cache.file_io).await?;
+        self.wal_bytes.fetch_add(next.size(), Ordering::Relaxed);
+        // Syncing the log from then on only syncs the new segment.
+        log.sync().await?;
+        let mut meta = self.meta.lock().unwrap().clone();
+        let full = std::mem::replace(&mut meta.wal, path);
+        meta.wal_segments.push(RetainedWal {
+            path: full,
+            last_seq,
+        });
+        self.update_meta(meta).await?;
+        *log = next;
+        Ok(())
+    }
+
+    // The last write in the memtable that's only in the current log, rather
+    // than an older segment.
+    fn unrotated_seq(&self, meta: &DbMeta) -> Option<u64> {
+        let rotated_through = meta.wal_segments.last().and_then(|wal| wal.last_seq);
+        self.memtable
+            .read()
+            .unwrap()
+            .sequences()
+            .map(|(_, last)| last)
+            .filter(|&last| Some(last) > rotated_through)
+    }
+
+    // Once the memtable has grown past `DbOptions::write_buffer_size`, or the
+    // write buffer manager wants it flushed, sets it aside to be written to a
+    // table in the background, and installs the table once it's been
+    // written. The caller must hold the log lock.
+    async fn flush_in_background(&self, log: &mut Log) -> Result<(), NdbError> {
+        let finished = self
+            .flushing
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|f| f.task.take_if(|task| task.is_finished()));
+        if let Some(task) = finished {
+            self.install_flush(task.await, log).await?;
+            self.vacuum_in_background();
+        }
+        let vacuumed = (self.vacuuming.lock().unwrap()).take_if(|task| task.is_finished());
+        if let Some(task) = vacuumed {
+            // A failed vacuum leaves the tables as they were, for the next
+            // one to try again.
+            match task
+                .await
+                .map_err(|err| NdbError::Io(std::io::Error::other(err)))
+            {
+                Ok(Ok(merged)) => {
+                    self.install_vacuum(merged).await?;
+                    self.count_file_bytes().await?;
+                }
+                Ok(Err(err)) | Err(err) => warn!(%err, "background vacuum failed"),
+            }
+            self.vacuum_in_background();
+        }
+        let memory = self.memtable.read().unwrap().memory_usage();
+        let full = (self.options.write_buffer_size).is_some_and(|limit| memory >= limit);
+        // Reported whether or not it's full, to keep the manager's total
+        // up to date.
+        let over_budget = self.report_memory();
+        if !full && !over_budget {
+            return Ok(());
+        }
+        let failed = match &*self.flushing.lock().unwrap() {
+            Some(flushing) if flushing.task.is_some() => return Ok(()),
+            Some(flushing) => Some(flushing.memtable.clone()),
+            None => None,
+        };
+        match failed {
+            // The last try failed, so the same memtable is tried again.
+            Some(memtable) => {
+                let task = self.spawn_flush(memtable)?;
+                self.flushing.lock().unwrap().as_mut().unwrap().task = Some(task);
+                Ok(())
+            }
+            None => self.freeze(log).await,
+        }
+    }
+
+    // Sets the memtable aside and starts writing it to a table, with later
+    // writes going to a fresh memtable and log segment. The caller must hold
+    // the log lock.
+    async fn freeze(&self, log: &mut Log) -> Result<(), NdbError> {
+        let last_seq = self.unrotated_seq(&self.meta.lock().unwrap());
+        self.rotate_wal(log, last_seq).await?;
+        let segments = self.meta.lock().unwrap().wal_segments.len();
+        let memtable = self.memtable.read().unwrap().clone();
+        let fresh = Memtable::new(
+            self.options.merge_operator.clone(),
+            self.options.clock.clone(),
+        );
+        self.relog_prepared(log, &memtable, &fresh).await?;
+        let task = self.spawn_flush(memtable.clone())?;
+        // Readers take the memtables and tables together under this lock.
+        let mut flushing = self.flushing.lock().unwrap();
+        *flushing = Some(Flushing {
+            memtable,
+            segments,
+            task: Some(task),
+            started: self.options.clock.instant(),
+        });
+        *self.memtable.write().unwrap() = Arc::new(fresh);
+        drop(flushing);
+        if let Some(write_buffer) = &self.write_buffer {
+            write_buffer.froze();
+        }
+        self.report_memory();
+        Ok(())
+    }
+
+    // Logs the batches still prepared in `frozen` again, at the start of the
+    // log segment `freeze` has just moved on to, so they outlive the older
+    // segments once those are retired, and applies them to `fresh`, the
+    // memtable taking over. The caller must hold the log lock.
+    async fn relog_prepared(
+        &self,
+        log: &mut Log,
+        frozen: &Memtable,
+        fresh: &Memtable,
+    ) -> Result<(), NdbError> {
+        let entries: Vec<_> = (frozen.prepared().into_iter())
+            .map(|(id, ops)| LogEntry::Prepare { id, ops })
+            .collect();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let first = self.latest_sequence() + 1;
+        let records = (first..)
+            .zip(&entries)
+            .map(|(seq, entry)| LogRecord { seq, entry });
+        let size = log.size();
+        let logged = log.append(records, true).await;
+        let written = log.size() - size;
+        self.wal_bytes.fetch_add(written, Ordering::Relaxed);
+        self.io
+            .wal_bytes_written
+            .fetch_add(written, Ordering::Relaxed);
+        if let Err(err) = logged {
+            *self.poisoned.lock().unwrap() = Some(err.to_string());
+            return Err(err);
+        }
+        let mut seq = first;
+        for entry in entries {
+            fresh.apply(entry, seq);
+            seq += 1;
+        }
+        self.sequence.store(seq - 1, Ordering::Release);
+        Ok(())
+    }
+
+    // Tells the write buffer manager, if there is one, how much memory the
+    // memtables take up, and returns whether it wants the memtable flushed.
+    fn report_memory(&self) -> bool {
+        let Some(write_buffer) = &self.write_buffer else {
+            return false;
+        };
+        let active = self.memtable.read().unwrap().memory_usage();
+        let flushing = self.flushing.lock().unwrap();
+        let frozen = flushing.as_ref().map_or(0, |f| f.memtable.memory_usage());
+        drop(flushing);
+        write_buffer.update(active, frozen)
+    }
+
+    fn spawn_flush(
+        &self,
+        memtable: Arc<Memtable>,
+    ) -> Result<JoinHandle<Result<SSTable, NdbError>>, NdbError> {
+        let timestamp = self.timestamps.next(&self.options)?;
+        let (dir, options) = (self.dir.clone(), self.options.clone());
+        let (io, files) = (self.io.clone(), self.table_cache.clone());
+        let write =
+            async move { write_memtable(&dir, &options, &io, &files, timestamp, &memtable).await };
+        Ok(tokio::spawn(write.in_current_span()))
+    }
+
+    // Adds the table written from the frozen memtable, and retires the logs
+    // its writes were in. If writing it failed, the memtable stays frozen
+    // until the flush is tried again. The caller must hold the log lock.
+    async fn install_flush(
+        &self,
+        written: Result<Result<SSTable, NdbError>, JoinError>,
+        log: &Log,
+    ) -> Result<(), NdbError> {
+        let sstable = written.map_err(|err| NdbError::Io(std::io::Error::other(err)))??;
+        let (segments, keys, started) = {
+            let flushing = self.flushing.lock().unwrap();
+            let flushing = flushing.as_ref().unwrap();
+            (flushing.segments, flushing.memtable.len(), flushing.started)
+        };
+        let mut meta = self.meta.lock().unwrap().clone();
+        meta.sstables.push(sstable.meta.clone());
+        let wals = meta.wal_segments.drain(..segments).collect();
+        let pruned = self.retire_wals(&mut meta, wals).await;
+        self.update_meta(meta).await?;
+
+        metrics::count(metrics::FLUSHES, 1);
+        metrics::count(metrics::FLUSH_BYTES, sstable.size);
+        info!(
+            table = %sstable.meta.data_path.display(),
+            keys,
+            bytes = sstable.size,
+            duration = ?(self.options.clock.instant() - started),
+            "flushed memtable",
+        );
+        let mut sstables = self.tables().to_vec();
+        sstables.insert(0, Arc::new(sstable));
+        {
+            let mut flushing = self.flushing.lock().unwrap();
+            *self.sstables.write().unwrap() = Arc::new(Tables::new(sstables));
+            *flushing = None;
+        }
+        self.report_memory();
+
+        self.discard_wals(pruned).await?;
+        self.count_wal_bytes(log).await?;
+        self.count_file_bytes().await
+    }
+
+    // Waits for the frozen memtable, if there is one, to be written to a
+    // table and installed, retrying the flush if it last failed. Only for
+    // callers with `&mut self`: a write in the meantime would take the
+    // missing task for a failed flush. Others use `wait_for_flush`.
+    async fn finish_flush(&self) -> Result<(), NdbError> {
+        let task = {
+            let mut flushing = self.flushing.lock().unwrap();
+            let Some(flushing) = flushing.as_mut() else {
+                return Ok(());
+            };
+            match flushing.task.take() {
+                Some(task) => task,
+                None => self.spawn_flush(flushing.memtable.clone())?,
+            }
+        };
+        // Without the log lock, so writes can go on in the meantime.
+        let written = task.await;
+        let log = self.writable_log()?.lock().await;
+        self.install_flush(written, &log).await
+    }
+
+    // Applies `entries` to the memtable, numbering them in order from
+    // `first`. The caller must hold the log lock.
+    fn apply(&self, entries: impl IntoIterator<Item = LogEntry>, first: u64) {
+        let watched = self.watchers.receiver_count() > 0;
+        let mut updates = Vec::new();
+        {
+            let mut stats = self.write_stats.lock().unwrap();
+            let mut recent_writes = self.recent_writes.lock().unwrap();
+            let mut counters = self.counters.lock().unwrap();
+            let memtable = self.memtable.read().unwrap().clone();
+            for (seq, entry) in (first..).zip(entries) {
+                stats.record_batch(entry.ops().iter().map(LogEntry::stats_key));
+                recent_writes.record(&entry, seq);
+                counters.forget(&entry);
+                // Preparing or rolling back a batch doesn't write anything.
+                if watched && !entry.ops().is_empty() {
+                    let batch = WriteBatch::from_entry(entry.clone());
+                    updates.push(Arc::new(Update { seq, batch }));
+                }
+                memtable.apply(entry, seq);
+                // Reads are as of this, so they see all of the entry or
+                // none of it.
+                self.sequence.store(seq, Ordering::Release);
+            }
+        }
+        // Only once the writes can be read, so watchers see what they were
+        // told about.
+        for update in updates {
+            let _ = self.watchers.send(update);
+        }
+        self.committed.notify_waiters();
+    }
+
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, NdbError> {
+        metrics::timed(metrics::GET_SECONDS, async {
+            let now = self.options.clock.unix_millis();
+            let value = self.get_value(key).await?.and_then(|value| value.live(now));
+            self.decode(key, value)
+        })
+        .await
+    }
+
+    /// Like [`Db::get`], and also says when the value expires, if it was put
+    /// with a TTL.
+    pub async fn get_with_expiry(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<(Vec<u8>, Option<SystemTime>)>, NdbError> {
+        let now = self.options.clock.unix_millis();
+        let (value, expires_at) = match self.get_value(key).await? {
+            Some(value) if !value.is_live(now) => return Ok(None),
+            Some(Value::Put(value)) => (value, None),
+            Some(Value::PutUntil { value, expires_at }) => {
+                (value, Some(UNIX_EPOCH + Duration::from_millis(expires_at)))
+            }
+            _ => return Ok(None),
+        };
+        let value = self.decode(key, Some(value))?;
+        Ok(value.map(|value| (value, expires_at)))
+    }
+
+    // Returns the newest version of `key`, which may be a tombstone, with any
+    // merges folded in.
+    async fn get_value(&self, key: &[u8]) -> Result<Option<Value>, NdbError> {
+        let merge = self.options.merge_operator.as_ref();
+        let (layers, seq) = self.read_layers();
+        let now = self.options.clock.unix_millis();
+        view::get_value(&layers, key, seq, merge, now).await
+    }
+
+    /// Reads `key` as it was just after the write with sequence number
+    /// `seq`. Only the history since the memtable was last flushed is kept,
+    /// so `seq` can't be older than [`Db::oldest_readable_sequence`].
+    pub async fn get_at(&self, key: &[u8], seq: u64) -> Result<Option<Vec<u8>>, NdbError> {
+        let (layers, _) = self.read_layers();
+        self.check_readable(seq)?;
+        let now = self.options.clock.unix_millis();
+        let merge = self.options.merge_operator.as_ref();
+        let value = view::get_value(&layers, key, seq, merge, now).await?;
+        self.decode(key, value.and_then(|value| value.live(now)))
+    }
+
+    /// Closes the database, syncing its log so every write made so far is
+    /// on disk, waiting for any vacuum running in the background, and
+    /// releasing its lock. Dropping a `Db` doesn't lose any
+    /// write that returned, since each one reaches the OS before it does,
+    /// but only writes made with [`WriteOptions::sync`] are sure to survive
+    /// a crash of the machine.
+    pub async fn close(mut self) -> Result<(), NdbError> {
+        self.finish_flush().await?;
+        self.finish_vacuum().await?;
+        if let Some(log) = self.log.take() {
+            log.into_inner().sync().await?;
+        }
+        Ok(())
+    }
+
+    /// Starts a [`ScanToken`] over `range`, for [`Db::scan_from_token`] to
+    /// scan as of the latest write.
+    pub fn scan_token(&self, range: impl RangeBounds<Vec<u8>>) -> ScanToken {
+        ScanToken::new(self.latest_sequence(), range)
+    }
+
+    /// Scans what's left of `token`'s range in ascending order, as of its
+    /// sequence number, so a long scan can pick up exactly where it stopped
+    /// even after the process restarts. Call [`ScanToken::advance`] with
+    /// each key returned to keep the token up to date. Like [`Db::get_at`],
+    /// this fails once the sequence number is older than
+    /// [`Db::oldest_readable_sequence`].
+    pub async fn scan_from_token(&self, token: &ScanToken) -> Result<DbIterator, NdbError> {
+        let seq = token.sequence();
+        let (layers, _) = self.read_layers();
+        self.check_readable(seq)?;
+        let end = token.end_bound().cloned();
+        let now = self.options.clock.unix_millis();
+        let merge = self.options.merge_operator.clone();
+        let sources = view::sources(&layers, token, seq, false).await?;
+        let iter = DbIterator::new(sources, end, now, merge);
+        Ok(self.open_iter(iter))
+    }
+
+    fn check_readable(&self, seq: u64) -> Result<(), NdbError> {
+        let oldest = self.oldest_readable_sequence();
+        let latest = self.latest_sequence();
+        if seq < oldest || seq > latest {
+            return Err(NdbError::InvalidArgument(format!(
+                "sequence {} is outside the readable range {}..={}",
+                seq, oldest, latest
+            )));
+        }
+        Ok(())
+    }
+
+    /// The oldest sequence number [`Db::get_at`] can read as of: the last one
+    /// written to a table, since tables only keep the newest version of each
+    /// key.
+    pub fn oldest_readable_sequence(&self) -> u64 {
+        // Tables that don't say, like ones rebuilt by `Db::repair` without a
+        // manifest, could hold any write older than the memtables.
+        let before_memtables = {
+            let flushing = self.flushing.lock().unwrap();
+            let memtable = self.memtable.read().unwrap();
+            std::iter::once(&*memtable)
+                .chain(flushing.as_ref().map(|f| &f.memtable))
+                .filter_map(|memtable| memtable.sequences())
+                .map(|(lowest, _)| lowest.saturating_sub(1))
+                .min()
+                .unwrap_or_else(|| self.latest_sequence())
+        };
+        self.layers()
+            .flat_map(|db| db.tables().to_vec())
+            .map(|sstable| match sstable.meta.sequence_range {
+                Some((_, highest)) => highest,
+                None => before_memtables,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Every layer's memtables and tables as they are now, newest first, and
+    // the sequence number to read them as of.
+    fn read_layers(&self) -> (Vec<Layer>, u64) {
+        let mut seq = 0;
+        let layers = self
+            .layers()
+            .map(|db| {
+                // Flushes move memtables into tables under this lock, so
+                // every write up to `seq` is in exactly one of them, and the
+                // tables have nothing newer.
+                let flushing = db.flushing.lock().unwrap();
+                if std::ptr::eq(db, self) {
+                    seq = self.latest_sequence();
+                }
+                let mut memtables = vec![db.memtable.read().unwrap().clone()];
+                memtables.extend(flushing.as_ref().map(|f| f.memtable.clone()));
+                Layer {
+                    memtables,
+                    sstables: db.tables(),
+                }
+            })
+            .collect();
+        (layers, seq)
+    }
+
+    /// Captures the database as it is now in a [`DbView`], which later
+    /// writes, flushes and compactions don't affect. This doesn't copy any
+    /// data: the view shares the memtable with later writes, and reads it as
+    /// of the last write before it was frozen.
+    pub fn freeze_view(&self) -> DbView {
+        let (layers, seq) = self.read_layers();
+        DbView::new(
+            layers,
+            seq,
+            self.options.clock.clone(),
+            self.options.merge_operator.clone(),
+            self.options.value_transforms.clone(),
+            self.handles.clone(),
+        )
+    }
+
+    /// Checks whether `key` might exist using only what's in memory: the
+    /// memtables, and the key ranges and bloom filters of the tables. This
+    /// never touches the disk, so a `DefinitelyNot` is cheap. A value found
+    /// in memory that can't be decoded is an error, as it is for
+    /// [`Db::get`].
+    pub fn key_may_exist(&self, key: &[u8]) -> Result<MayExist, NdbError> {
+        let (layers, seq) = self.read_layers();
+        for layer in layers {
+            for memtable in &layer.memtables {
+                let value = memtable.lookup(key, seq);
+                // A merge always leaves a value, but finding it needs the disk.
+                if let Some(Value::Merge(_)) = value {
+                    return Ok(MayExist::Maybe(None));
+                }
+                if let Some(value) = value {
+                    return match value.live(self.options.clock.unix_millis()) {
+                        Some(value) => Ok(MayExist::Maybe(self.decode(key, Some(value))?)),
+                        None => Ok(MayExist::DefinitelyNot),
+                    };
+                }
+            }
+            for sstable in layer.sstables.iter() {
+                if sstable.may_have_entry(key) {
+                    return Ok(MayExist::Maybe(None));
+                }
+                if sstable.meta.range_tombstones.covers(key) {
+                    return Ok(MayExist::DefinitelyNot);
+                }
+            }
+        }
+
+        Ok(MayExist::DefinitelyNot)
+    }
+
+    /// Looks up several keys at once. Keys that aren't in the memtable are
+    /// looked up in the SSTables concurrently. The results are in the same
+    /// order as `keys`.
+    pub async fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, NdbError> {
+        let now = self.options.clock.unix_millis();
+        let mut results = vec![None; keys.len()];
+        let mut lookups = FuturesUnordered::new();
+        let seq = self.latest_sequence();
+        for (i, key) in keys.iter().enumerate() {
+            let value = self.memtable.read().unwrap().lookup(key, seq);
+            match value {
+                Some(Value::Merge(_)) | None => {
+                    lookups.push(async move { (i, self.get_value(key).await) })
+                }
+                Some(value) => results[i] = value.live(now),
+            }
+        }
+        while let Some((i, value)) = lookups.next().await {
+            results[i] = value?.and_then(|value| value.live(now));
+        }
+
+        let values = keys.iter().zip(results);
+        values.map(|(key, value)| self.decode(key, value)).collect()
+    }
+
+    /// Returns an iterator over the keys in `range`, in ascending order.
+    pub async fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Result<DbIterator, NdbError> {
+        let end = range.end_bound().cloned();
+        let now = self.options.clock.unix_millis();
+        let merge = self.options.merge_operator.clone();
+        let iter = DbIterator::new(self.sources(&range, false).await?, end, now, merge);
+        Ok(self.open_iter(iter))
+    }
+
+    /// Returns an iterator over the keys in `range`, in descending order.
+    pub async fn scan_rev(&self, range: impl RangeBounds<Vec<u8>>) -> Result<DbIterator, NdbError> {
+        let start = range.start_bound().cloned();
+        let now = self.options.clock.unix_millis();
+        let merge = self.options.merge_operator.clone();
+        let iter = DbIterator::new_rev(self.sources(&range, true).await?, start, now, merge);
+        Ok(self.open_iter(iter))
+    }
+
+    // Sets `iter`, over this database, up to decode values and to be tracked
+    // until it's dropped.
+    fn open_iter(&self, iter: DbIterator) -> DbIterator {
+        let tables: Vec<_> = self.layers().flat_map(|db| db.tables().to_vec()).collect();
+        iter.with_transforms(self.options.value_transforms.clone())
+            .tracked(self.handles.track(HandleKind::Iterator, &tables))
+    }
+
+    async fn sources(
+        &self,
+        range: &impl RangeBounds<Vec<u8>>,
+        reverse: bool,
+    ) -> Result<Vec<Source>, NdbError> {
+        let (layers, seq) = self.read_layers();
+        view::sources(&layers, range, seq, reverse).await
+    }
+
+    async fn update_meta(&self, meta: DbMeta) -> Result<(), NdbError> {
+        let mut manifest = self.manifest.lock().await;
+        let old = self.meta.lock().unwrap().clone();
+        match &mut *manifest {
+            Some(manifest) => manifest.record(&old, &meta).await?,
+            manifest => {
+                let max_bytes = self.options.max_manifest_bytes;
+                let storage = &self.options.storage;
+                *manifest =
+                    Some(ManifestWriter::create(storage, &self.dir, &meta, max_bytes).await?);
+            }
+        }
+        *self.meta.lock().unwrap() = meta;
+        Ok(())
+    }
+
+    /// Streams every change to keys starting with `prefix` as it commits,
+    /// e.g. to invalidate a cache. Writes made before the call aren't
+    /// included. Unlike [`Db::updates_since`], nothing is read back from the
+    /// log: a watcher that falls more than 4096 writes behind misses
+    /// some, and is told so with [`WatchEvent::Lagged`].
+    pub fn watch(&self, prefix: &[u8]) -> impl Stream<Item = WatchEvent> + 'static {
+        changes::watch(self.watchers.subscribe(), prefix.to_vec())
+    }
+
+    // Moves `wals`, whose writes are now in tables, onto the retained list in
+    // `meta`, and drops any retained logs the consumers are done with from
+    // it. Returns those, to be deleted once `meta` is written.
+    async fn retire_wals(&self, meta: &mut DbMeta, mut wals: Vec<RetainedWal>) -> Vec<PathBuf> {
+        meta.retained_wals.append(&mut wals);
+        let min_acked = self.consumers.min_acked().await;
+        let needed = |wal: &RetainedWal| matches!((wal.last_seq, min_acked), (Some(last), Some(acked)) if last > acked);
+        let keep = meta.retained_wals.iter().position(needed);
+        let keep = keep.unwrap_or(meta.retained_wals.len());
+        let pruned: Vec<_> = meta.retained_wals.drain(..keep).collect();
+        for wal in &pruned {
+            meta.pruned_through = meta.pruned_through.max(wal.last_seq.unwrap_or(0));
+        }
+        pruned.into_iter().map(|wal| wal.path).collect()
+    }
+
+    // Deletes logs that are no longer needed, or moves them into
+    // `DbOptions::wal_archive_dir`.
+    async fn discard_wals(&self, paths: Vec<PathBuf>) -> Result<(), NdbError> {
+        let storage = &*self.options.storage;
+        for path in paths {
+            if !storage.exists(&path).await? {
+                continue;
+            }
+            let Some(archive) = &self.options.wal_archive_dir else {
+                storage.remove(&path).await?;
+                continue;
+            };
+            storage.create_dir_all(archive).await?;
+            let archived = archive.join(path.file_name().unwrap());
+            match storage.rename(&path, &archived).await {
+                Err(NdbError::Io(err)) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+                    write_synced(storage, &archived, &storage.read(&path).await?).await?;
+                    storage.remove(&path).await?;
+                }
+                moved => moved?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_filename(&self, prefix: &str) -> Result<String, NdbError> {
+        let mut now = self.timestamps.next(&self.options)?;
+        let storage = &self.options.storage;
+        while storage
+            .exists(&self.dir.join(format!("{}-{}", prefix, now)))
+            .await?
+        {
+            now += 1;
+        }
+        Ok(format!("{}-{}", prefix, now))
+    }
+
+    #[instrument(skip_all, fields(dir = %self.dir.display()))]
+    pub async fn flush_memtable(&mut self) -> Result<(), NdbError> {
+        self.finish_flush().await?;
+        let mut log = self.writable_log()?.lock().await;
+        self.freeze(&mut log).await?;
+        drop(log);
+        self.finish_flush().await?;
+        if self.options.vacuum.is_some() {
+            self.vacuum().await?;
+        }
+        Ok(())
+    }
+
+    /// Folds an overlay's delta into its base: everything visible through the
+    /// overlay is rewritten into a single new table in the base, and the
+    /// delta is emptied.
+    #[instrument(skip_all, fields(dir = %self.dir.display()))]
+    pub async fn compact_into_base(&mut self) -> Result<(), NdbError> {
+        let Some(base) = self.base.as_deref() else {
+            return Err(NdbError::InvalidArgument(
+                "compact_into_base requires an overlay database".into(),
+            ));
+        };
+        // They're only in the delta's logs, which are about to go.
+        if !self.prepared().is_empty() {
+            return Err(NdbError::InvalidArgument(
+                "compact_into_base with batches still prepared".into(),
+            ));
+        }
+        self.finish_flush().await?;
+        self.finish_vacuum().await?;
+        let started = self.options.clock.instant();
+
+        // The base is the bottom layer, so tombstones can be dropped here.
+        let steps = ["merge into base table", "sync table"];
+        let db = &*self;
+        let sstable = watchdog::watch(
+            &self.options,
+            JobKind::CompactIntoBase,
+            &steps,
+            |progress| async move {
+                let now = db.options.clock.unix_millis();
+                let sources = db.sources(&.., false).await?;
+                let merge = db.options.merge_operator.clone();
+                let mut iter = DbIterator::new(sources, Bound::Unbounded, now, merge);
+                let timestamp = base.timestamps.next(&db.options)?;
+                let mut writer = SSTableWriter::create(
+                    &base.dir,
+                    &db.options,
+                    &base.io,
+                    &base.table_cache,
+                    timestamp,
+                )
+                .await?;
+                progress.set_file(writer.data_path().into());
+                for layer in db.read_layers().0 {
+                    for memtable in &layer.memtables {
+                        writer.add_sequences(memtable.sequences());
+                    }
+                    for sstable in layer.sstables.iter() {
+                        writer.add_sequences(sstable.meta.sequence_range);
+                    }
+                }
+                while let Some((key, value)) = iter.next_value().await? {
+                    writer.add(&key, &value).await?;
+                    progress.advance(writer.offset());
+                }
+                drop(iter);
+                progress.next_step();
+                writer.finish().await
+            },
+        )
+        .await?;
+
+        let base = self.base.as_deref_mut().unwrap();
+        let old_base_wals = base.meta.get_mut().unwrap().memtable_wals();
+        let mut new_meta = base.meta.get_mut().unwrap().clone();
+        new_meta.sstables = vec![sstable.meta.clone()];
+        new_meta.wal = base.dir.join(base.get_filename("log").await?);
+        new_meta.wal_segments = Vec::new();
+        base.update_meta(new_meta).await?;
+        let memtable = Memtable::new(
+            base.options.merge_operator.clone(),
+            base.options.clock.clone(),
+        );
+        base.memtable = RwLock::new(Arc::new(memtable));
+        base.sequence
+            .fetch_max(self.sequence.load(Ordering::Relaxed), Ordering::Relaxed);
+        metrics::count(metrics::COMPACTION_BYTES, sstable.size);
+        info!(
+            table = %sstable.meta.data_path.display(),
+            bytes = sstable.size,
+            duration = ?(self.options.clock.instant() - started),
+            "compacted into base",
+        );
+        let new_tables = Arc::new(Tables::new(vec![Arc::new(sstable)]));
+        for old in std::mem::replace(base.sstables.get_mut().unwrap(), new_tables).iter() {
+            old.remove_file().await?;
+        }
+        base.discard_wals(old_base_wals).await?;
+        base.count_file_bytes().await?;
+
+        // Now the delta is redundant. If we crash before getting here it
+        // just gets applied to the base a second time, which is harmless.
+        let log_path = self.dir.join(self.get_filename("log").await?);
+        let storage = &*self.options.storage;
+        self.log = Some(tokio::sync::Mutex::new(
+            Log::open(storage, &log_path, &self.table_cache.file_io).await?,
+        ));
+        let mut new_meta = self.meta.get_mut().unwrap().clone();
+        new_meta.sstables = Vec::new();
+        let mut wals = std::mem::take(&mut new_meta.wal_segments);
+        wals.push(RetainedWal {
+            path: std::mem::replace(&mut new_meta.wal, log_path),
+            last_seq: self.unrotated_seq(&new_meta),
+        });
+        let pruned = self.retire_wals(&mut new_meta, wals).await;
+        self.update_meta(new_meta).await?;
+        let memtable = Memtable::new(
+            self.options.merge_operator.clone(),
+            self.options.clock.clone(),
+        );
+        self.memtable = RwLock::new(Arc::new(memtable));
+        for old in std::mem::take(self.sstables.get_mut().unwrap()).iter() {
+            old.remove_file().await?;
+        }
+        self.discard_wals(pruned).await?;
+        let log_size = self.log.as_mut().map_or(0, |log| log.get_mut().size());
+        self.wal_bytes.store(log_size, Ordering::Relaxed);
+        self.count_file_bytes().await?;
+        self.warn_about_handles();
+
+        Ok(())
+    }
+
+    /// Merges runs of small tables next to each other into fewer, bigger
+    /// ones, as set by [`DbOptions::vacuum`], or the defaults if that's
+    /// off. If it's on, this also happens in the background whenever a
+    /// flush leaves a run to merge, and after every
+    /// [`Db::flush_memtable`]. Returns how many tables were merged away,
+    /// including by a vacuum that was running in the background.
+    #[instrument(skip_all, fields(dir = %self.dir.display()))]
+    pub async fn vacuum(&mut self) -> Result<usize, NdbError> {
+        if self.log.is_none() {
+            return Err(NdbError::ReadOnly);
+        }
+        self.finish_flush().await?;
+        let mut merged = self.finish_vacuum().await?;
+        let runs = self.vacuum_runs();
+        let (dir, options, timestamps) = (&self.dir, &self.options, &self.timestamps);
+        let runs = merge_runs(dir, options, &self.io, &self.table_cache, timestamps, runs).await?;
+        merged += self.install_vacuum(runs).await?;
+        let demoted = self.demote_cold_tables().await?;
+        self.count_file_bytes().await?;
+        if demoted > 0 {
+            self.warn_about_handles();
+        }
+        Ok(merged)
+    }
+
+    // The runs of small tables a vacuum would merge.
+    fn vacuum_runs(&self) -> Vec<Vec<Arc<SSTable>>> {
+        let options = self.options.vacuum.clone().unwrap_or_default();
+        let tables = self.tables();
+        let sizes: Vec<_> = tables.iter().map(|t| t.size).collect();
+        let runs = vacuum::small_runs(&sizes, &options);
+        runs.into_iter().map(|run| tables[run].to_vec()).collect()
+    }
+
+    // Starts merging the runs of small tables there are in the background,
+    // if the database vacuums and isn't already. The caller must hold the
+    // log lock.
+    fn vacuum_in_background(&self) {
+        let mut vacuuming = self.vacuuming.lock().unwrap();
+        if self.options.vacuum.is_none() || vacuuming.is_some() {
+            return;
+        }
+        let runs = self.vacuum_runs();
+        if runs.is_empty() {
+            return;
+        }
+        let (dir, options) = (self.dir.clone(), self.options.clone());
+        let (io, files) = (self.io.clone(), self.table_cache.clone());
+        let timestamps = self.timestamps.clone();
+        let merge = async move { merge_runs(&dir, &options, &io, &files, &timestamps, runs).await };
+        *vacuuming = Some(tokio::spawn(merge.in_current_span()));
+    }
+
+    // Waits for the vacuum running in the background, if there is one, and
+    // installs what it merged. Returns how many tables were merged away.
+    async fn finish_vacuum(&self) -> Result<usize, NdbError> {
+        let Some(task) = self.vacuuming.lock().unwrap().take() else {
+            return Ok(0);
+        };
+        let merged = task
+            .await
+            .map_err(|err| NdbError::Io(std::io::Error::other(err)))??;
+        self.install_vacuum(merged).await
+    }
+
+    // Replaces each run of tables a vacuum merged with the tables it was
+    // merged into, and returns how many tables that merged away. Tables
+    // flushed in the meantime are left where they are. The caller must hold
+    // the log lock, or have the database to itself.
+    async fn install_vacuum(&self, merged: Vec<Merged>) -> Result<usize, NdbError> {
+        if merged.is_empty() {
+            return Ok(0);
+        }
+        let mut sstables = self.tables().to_vec();
+        let (mut count, mut old) = (0, Vec::new());
+        for (run, tables) in merged {
+            count += run.len() - tables.len();
+            sstables.retain(|table| !run.iter().any(|merged| Arc::ptr_eq(merged, table)));
+            sstables.extend(tables.into_iter().map(Arc::new));
+            old.extend(run);
+        }
+        sstables.sort();
+        let mut new_meta = self.meta.lock().unwrap().clone();
+        new_meta.sstables = sstables.iter().map(|t| t.meta.clone()).collect();
+        self.update_meta(new_meta).await?;
+        *self.sstables.write().unwrap() = Arc::new(Tables::new(sstables));
+        for old in old {
+            old.remove_file().await?;
+        }
+        self.warn_about_handles();
+        Ok(count)
+    }
+
+    // Moves the tables that have gone cold since the last vacuum to
+    // `DbOptions::cold_tier`, and returns how many there were.
+    async fn demote_cold_tables(&mut self) -> Result<usize, NdbError> {
+        let Some(cold) = self.options.cold_tier.clone() else {
+            return Ok(0);
+        };
+        let current = self.tables();
+        let mut sstables = current.to_vec();
+        let mut old = Vec::new();
+        for table in sstables.iter_mut() {
+            let meta = &table.meta;
+            if cold.tier_of(&meta.data_path) == StorageTier::Cold
+                || !cold.is_cold(&self.options, meta)
+            {
+                continue;
+            }
+            let mut moved = meta.clone();
+            moved.data_path = cold.dir.join(meta.data_path.file_name().unwrap());
+            link_or_copy(&*self.options.storage, &meta.data_path, &moved.data_path).await?;
+            let moved = SSTable::open(moved, &self.options, &self.io, &self.table_cache).await?;
+            old.push(std::mem::replace(table, Arc::new(moved)));
+        }
+        if old.is_empty() {
+            return Ok(0);
+        }
+        info!(tables = old.len(), dir = %cold.dir.display(), "moved tables to the cold tier");
+
+        sstables.sort();
+        let mut new_meta = self.meta.get_mut().unwrap().clone();
+        new_meta.sstables = sstables.iter().map(|t| t.meta.clone()).collect();
+        self.update_meta(new_meta).await?;
+        *self.sstables.get_mut().unwrap() = Arc::new(Tables::new(sstables));
+        for old in &old {
+            old.remove_file().await?;
+        }
+        Ok(old.len())
+    }
+
+    /// Rewrites the tables written in an older format version than
+    /// [`FORMAT_VERSION`](crate::FORMAT_VERSION) in the current one, and
+    /// flushes the memtable so the log it was replayed from is replaced too.
+    /// Older formats are still read, so this is only needed before the files
+    /// are handed to something that reads only the current one. Returns how
+    /// many tables were rewritten.
+    #[instrument(skip_all, fields(dir = %self.dir.display()))]
+    pub async fn upgrade(&mut self) -> Result<usize, NdbError> {
+        if self.log.is_none() {
+            return Err(NdbError::ReadOnly);
+        }
+        if self.memtable.get_mut().unwrap().sequences().is_some() {
+            self.flush_memtable().await?;
+        }
+        self.finish_flush().await?;
+        self.finish_vacuum().await?;
+        let outdated: Vec<_> = (self.tables().iter().enumerate())
+            .filter(|(_, table)| table.version < FORMAT_VERSION)
+            .map(|(i, _)| i)
+            .collect();
+        for &i in &outdated {
+            let steps = ["rewrite table", "sync table"];
+            let current = self.tables();
+            let old = &current[i..=i];
+            let db = &*self;
+            let tables = watchdog::watch(&self.options, JobKind::Upgrade, &steps, |p| async move {
+                let (dir, options, io) = (&db.dir, &db.options, &db.io);
+                vacuum::merge_run(dir, options, io, &db.table_cache, &db.timestamps, old, &p).await
+            })
+            .await?;
+            info!(
+                table = %old[0].meta.data_path.display(),
+                from = old[0].version,
+                "upgraded table",
+            );
+
+            let mut sstables = current.to_vec();
+            let old: Vec<_> = sstables
+                .splice(i..=i, tables.into_iter().map(Arc::new))
+                .collect();
+            sstables.sort();
+            let mut new_meta = self.meta.get_mut().unwrap().clone();
+            new_meta.sstables = sstables.iter().map(|t| t.meta.clone()).collect();
+            self.update_meta(new_meta).await?;
+            *self.sstables.get_mut().unwrap() = Arc::new(Tables::new(sstables));
+            for old in old {
+                old.remove_file().await?;
+            }
+        }
+        self.count_file_bytes().await?;
+        if !outdated.is_empty() {
+            self.warn_about_handles();
+        }
+        Ok(outdated.len())
+    }
+
+    /// Adds table files written by [`SstWriter`](crate::SstWriter) to the
+    /// database all at once, as if everything in them had just been written,
+    /// without going through the log or the memtable. Each file is read
+    /// through first to check it, and they mustn't overlap each other, though
+    /// they can overlap the database's tables, which they then win over. The
+    /// memtable is flushed first if there's anything in it. The files are
+    /// hard linked into the database's directory where possible and copied
+    /// otherwise, and are left where they were. If the database
+    /// [vacuums](DbOptions::vacuum), it then starts merging them with its
+    /// other small tables in the background.
+    ///
+    /// Ingested keys aren't checked by [`DbOptions::validator`] or seen by
+    /// [`Db::watch`], [`Db::updates_since`] or transactions' conflict checks.
+    #[instrument(skip_all, fields(dir = %self.dir.display()))]
+    pub async fn ingest_sst<P: AsRef<Path>>(
+        &mut self,
+        paths: impl IntoIterator<Item = P>,
+    ) -> Result<(), NdbError> {
+        if self.log.is_none() {
+            return Err(NdbError::ReadOnly);
+        }
+        let mut external = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let meta =
+                SSTable::open_external(path, &self.options, &self.io, &self.table_cache).await?;
+            if meta.smallest_key.is_none() {
+                return Err(NdbError::InvalidArgument(format!(
+                    "{} is empty",
+                    path.display()
+                )));
+            }
+            external.push(meta);
+        }
+        external.sort_by(|a, b| a.smallest_key.cmp(&b.smallest_key));
+        if let Some(pair) = external
+            .windows(2)
+            .find(|pair| pair[1].smallest_key <= pair[0].largest_key)
+        {
+            return Err(NdbError::InvalidArgument(format!(
+                "{} and {} overlap",
+                pair[0].data_path.display(),
+                pair[1].data_path.display()
+            )));
+        }
+        if external.is_empty() {
+            return Ok(());
+        }
+        if self.memtable.get_mut().unwrap().sequences().is_some() {
+            self.flush_memtable().await?;
+        }
+        self.finish_flush().await?;
+
+        // Everything in the files is one write after the latest, so nothing
+        // reading as of an earlier one sees them.
+        let log = self.writable_log()?.lock().await;
+        let seq = self.latest_sequence() + 1;
+        let mut added = Vec::new();
+        let mut linked = Vec::new();
+        let adopted = async {
+            for meta in external {
+                let timestamp = self.timestamps.next(&self.options)?;
+                let storage = &*self.options.storage;
+                let (data_path, timestamp) =
+                    sstable::free_table_path(&self.dir, storage, timestamp).await?;
+                link_or_copy(storage, &meta.data_path, &data_path).await?;
+                linked.push(data_path.clone());
+                let meta = SSTableMetadata {
+                    data_path,
+                    written_timestamp: timestamp,
+                    sequence_range: Some((seq, seq)),
+                    ..meta
+                };
+                let sstable = SSTable::open(meta, &self.options, &self.io, &self.table_cache);
+                added.push(Arc::new(sstable.await?));
+            }
+            Ok::<_, NdbError>(())
+        };
+        if let Err(err) = adopted.await {
+            for path in linked {
+                storage::remove_if_exists(&*self.options.storage, &path).await?;
+            }
+            return Err(err);
+        }
+        self.options.storage.sync_dir(&self.dir).await?;
+        info!(tables = added.len(), "ingested tables");
+
+        let mut sstables = self.tables().to_vec();
+        sstables.extend(added);
+        sstables.sort();
+        let mut new_meta = self.meta.lock().unwrap().clone();
+        new_meta.sstables = sstables.iter().map(|t| t.meta.clone()).collect();
+        self.update_meta(new_meta).await?;
+        *self.sstables.write().unwrap() = Arc::new(Tables::new(sstables));
+        self.sequence.store(seq, Ordering::Release);
+        self.vacuum_in_background();
+        drop(log);
+        self.count_file_bytes().await
+    }
+
+    /// Writes the keys from `start` up to but not including `end`, as they
+    /// are now, to a table file at `path` that another database can add with
+    /// [`Db::ingest_sst`], e.g. to move a shard. Values keep their expiry
+    /// times, and are written as they're stored, so the other database needs
+    /// the same [`DbOptions::value_transforms`].
+    pub async fn export_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        path: impl AsRef<Path>,
+    ) -> Result<SstInfo, NdbError> {
+        let mut iter = self.scan(start.to_vec()..end.to_vec()).await?;
+        let mut writer = SstWriter::create(path, &self.options).await?;
+        while let Some((key, value)) = iter.next_value().await? {
+            writer.add(&key, value).await?;
+        }
+        writer.finish().await
+    }
+
+    // Warns about views and iterators that look leaked, now that they may be
+    // keeping more deleted tables around.
+    fn warn_about_handles(&self) {
+        if let Some(warning) = &self.options.handle_warning {
+            let tables: Vec<_> = self.layers().flat_map(|db| db.tables().to_vec()).collect();
+            self.handles.warn(&tables, warning);
+        }
+    }
+
+    /// Writes a consistent copy of the database into `dir`, which must be
+    /// empty or not exist yet, and can be opened like any other database.
+    /// The memtable is flushed first, and then the tables are hard-linked
+    /// rather than copied where the filesystem allows, so this is cheap
+    /// enough to do often, e.g. for backups. Change-feed consumers and the
+    /// logs kept for them aren't included. Not supported for overlays.
+    pub async fn checkpoint(&mut self, dir: impl AsRef<Path>) -> Result<(), NdbError> {
+        let dir = dir.as_ref();
+        if self.base.is_some() {
+            return Err(NdbError::InvalidArgument(
+                "checkpoint of an overlay database".into(),
+            ));
+        }
+        let storage = self.options.storage.clone();
+        let empty = match storage.list(dir).await {
+            Ok(files) => files.is_empty(),
+            Err(err) if storage::is_not_found(&err) => true,
+            Err(err) => return Err(err),
+        };
+        if !empty {
+            return Err(NdbError::InvalidArgument(format!(
+                "checkpoint directory {} is not empty",
+                dir.display()
+            )));
+        }
+        storage.create_dir_all(dir).await?;
+        let flushed = self.memtable.get_mut().unwrap().sequences().is_none();
+        if self.log.is_some() && !flushed {
+            self.flush_memtable().await?;
+        }
+        self.finish_flush().await?;
+
+        let mut sstables = Vec::new();
+        for sstable in self.tables().iter() {
+            let mut meta = sstable.meta.clone();
+            meta.data_path = dir.join(meta.data_path.file_name().unwrap());
+            link_or_copy(&*storage, &sstable.meta.data_path, &meta.data_path).await?;
+            sstables.push(meta);
+        }
+        // Only a read-only database can still have anything in its log. Its
+        // segments are copied into one.
+        let wal = dir.join("log");
+        let mut copied = Vec::new();
+        for segment in self.meta.get_mut().unwrap().memtable_wals() {
+            if storage.exists(&segment).await? {
+                copied.extend(storage.read(&segment).await?);
+            }
+        }
+        if !copied.is_empty() {
+            write_synced(&*storage, &wal, &copied).await?;
+        }
+
+        // Written last, so a checkpoint that didn't finish can't be opened.
+        let meta = DbMeta {
+            sstables,
+            wal,
+            wal_segments: Vec::new(),
+            retained_wals: Vec::new(),
+            pruned_through: self.oldest_readable_sequence(),
+        };
+        ManifestWriter::create(&storage, dir, &meta, self.options.max_manifest_bytes).await?;
+        Ok(())
+    }
+}
+
+// Merges each of `runs`, tables next to each other, into fewer, bigger ones,
+// in `dir` or, for a run that's gone cold all over, straight into the cold
+// tier.
+async fn merge_runs(
+    dir: &Path,
+    options: &DbOptions,
+    io: &Arc<IoCounters>,
+    files: &Arc<TableCache>,
+    timestamps: &Timestamps,
+    runs: Vec<Vec<Arc<SSTable>>>,
+) -> Result<Vec<Merged>, NdbError> {
+    let mut merged = Vec::new();
+    for run in runs {
+        let started = options.clock.instant();
+        let steps = ["merge tables", "sync tables"];
+        let dir = match &options.cold_tier {
+            Some(cold) if run.iter().all(|t| cold.is_cold(options, &t.meta)) => &cold.dir,
+            _ => dir,
+        };
+        let old = &run[..];
+        let tables = watchdog::watch(options, JobKind::Vacuum, &steps, |p| async move {
+            vacuum::merge_run(dir, options, io, files, timestamps, old, &p).await
+        })
+        .await?;
+        let written = tables.iter().map(|t| t.size).sum();
+        metrics::count(metrics::COMPACTION_BYTES, written);
+        info!(
+            merged = run.len(),
+            into = tables.len(),
+            bytes = written,
+            duration = ?(options.clock.instant() - started),
+            "vacuumed tables",
+        );
+        merged.push((run, tables));
+    }
+    Ok(merged)
+}
+
+// Writes `memtable` to a new table in `dir`.
+async fn write_memtable(
+    dir: &Path,
+    options: &DbOptions,
+    io: &Arc<IoCounters>,
+    files: &Arc<TableCache>,
+    timestamp: u64,
+    memtable: &Memtable,
+) -> Result<SSTable, NdbError> {
+    let steps = ["write table", "sync table"];
+    watchdog::watch(options, JobKind::Flush, &steps, |progress| async move {
+        let mut writer = SSTableWriter::create(dir, options, io, files, timestamp).await?;
+        progress.set_file(writer.data_path().into());
+        writer.add_range_tombstones(&memtable.range_tombstones(u64::MAX));
+        writer.add_sequences(memtable.sequences());
+        for (key, value) in memtable.entries(u64::MAX, &..) {
+            writer.add(&key, &value).await?;
+            progress.advance(writer.offset());
+        }
+        progress.next_step();
+        writer.finish().await
+    })
+    .await
+}
+
+// The sequence number of the newest write in `sstables` or `memtable`.
+fn newest_sequence(sstables: &[Arc<SSTable>], memtable: &Memtable) -> u64 {
+    sstables
+        .iter()
+        .filter_map(|sstable| sstable.meta.sequence_range)
+        .chain(memtable.sequences())
+        .map(|(_, highest)| highest)
+        .max()
+        .unwrap_or(0)
+}
+
+// Whether a file in a database's directory is one the database wrote.
+fn is_db_file(name: &str) -> bool {
+    const FIXED: [&str; 7] = [
+        "CURRENT",
+        "CURRENT.tmp",
+        "meta.json",
+        "LOCK",
+        "log",
+        "consumers.json",
+        "consumers.json.tmp",
+    ];
+    let numbered = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    FIXED.contains(&name)
+        || match name.split_once(['.', '-']) {
+            Some(("log" | "MANIFEST", n)) => numbered(n),
+            Some((n, "sst" | "meta")) => numbered(n),
+            _ => false,
+        }
+}
+
+// Whether a file in a database's directory is one the manifest should list:
+// a table, a log, or the manifest itself.
+fn is_table_or_log(name: &str) -> bool {
+    const UNLISTED: [&str; 4] = [
+        "CURRENT.tmp",
+        "LOCK",
+        "consumers.json",
+        "consumers.json.tmp",
+    ];
+    is_db_file(name) && !UNLISTED.contains(&name)
+}
+
+// Takes the advisory lock on `dir`'s LOCK file, so only one `Db` writes to it
+// at a time.
+fn lock_dir(dir: &Path) -> Result<std::fs::File, NdbError> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join("LOCK"))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(std::fs::TryLockError::WouldBlock) => Err(NdbError::AlreadyLocked),
+        Err(std::fs::TryLockError::Error(err)) => Err(err.into()),
+    }
+}
+
+impl Drop for Db {
+    fn drop(&mut self) {
+        // The frozen memtable's writes are still in its logs.
+        let flushing = self.flushing.get_mut().unwrap();
+        if let Some(task) = flushing.as_ref().and_then(|f| f.task.as_ref()) {
+            task.abort();
+        }
+        if let Some(task) = self.vacuuming.get_mut().unwrap() {
+            task.abort();
+        }
+    }
+}
+
+// Hard links `from` to `to`, which is much quicker than copying, or copies
+// it where that isn't possible: across devices, or off local storage.
+async fn link_or_copy(storage: &dyn Storage, from: &Path, to: &Path) -> Result<(), NdbError> {
+    if storage.is_local() {
+        match tokio::fs::hard_link(from, to).await {
+            Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {}
+            linked => return Ok(linked?),
+        }
+    }
+    write_synced(storage, to, &storage.read(from).await?).await
+}
+
+// The same error, for every writer in a failed group commit.
+fn shared_error(err: &NdbError) -> NdbError {
+    match err {
+        NdbError::Io(err) => NdbError::Io(std::io::Error::new(err.kind(), err.to_string())),
+        err => NdbError::Io(std::io::Error::other(err.to_string())),
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    alloc::{self, Layout},
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::NdbError;
+
+// Direct writes have to start at an offset, and come from an address, that
+// are multiples of the device's block size, and be a whole number of blocks
+// long. 4 KiB covers the block sizes in use.
+const ALIGN: usize = 4096;
+// How much is gathered before it's written out.
+const BUFFER_SIZE: usize = 1 << 20;
+
+// A table being written straight to the device, bypassing the page cache, so
+// that writing a big one doesn't push out the pages foreground reads want.
+// Writes are gathered in an aligned buffer and written out a whole number of
+// blocks at a time. The last, partial block is padded out, and the padding
+// cut off again once it's written.
+pub(crate) struct DirectFile {
+    file: Arc<File>,
+    path: PathBuf,
+    buf: Option<AlignedBuf>,
+    // Where in the file `buf` starts.
+    offset: u64,
+}
+
+impl DirectFile {
+    // Creates the file at `path`, or returns `None` if the file system it's
+    // on doesn't do direct I/O.
+    pub(crate) async fn create(path: &Path) -> Result<Option<DirectFile>, NdbError> {
+        let file = match open_direct(path).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::InvalidInput => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(DirectFile {
+            file: Arc::new(file),
+            path: path.into(),
+            buf: Some(AlignedBuf::new()),
+            offset: 0,
+        }))
+    }
+
+    pub(crate) async fn write_all(&mut self, mut data: &[u8]) -> Result<(), NdbError> {
+        while !data.is_empty() {
+            let buf = self.buf()?;
+            let n = buf.append(data);
+            data = &data[n..];
+            if buf.is_full() {
+                self.write_out(BUFFER_SIZE).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes out the whole blocks buffered so far and syncs them. Whatever's
+    // left over waits for the next write.
+    pub(crate) async fn sync_data(&mut self) -> Result<(), NdbError> {
+        let len = self.buf()?.len;
+        self.write_out(len - len % ALIGN).await?;
+        let file = self.file.clone();
+        blocking(move || file.sync_data()).await
+    }
+
+    // Writes out everything left, syncs the file, and opens it again to be
+    // read, since reads through the direct handle would need to be aligned
+    // too.
+    pub(crate) async fn finish(mut self) -> Result<File, NdbError> {
+        let len = self.buf()?.len;
+        let end = self.offset + len as u64;
+        let padded = len.next_multiple_of(ALIGN);
+        self.buf()?.len = padded;
+        self.write_out(padded).await?;
+        let file = self.file.clone();
+        blocking(move || {
+            file.set_len(end)?;
+            file.sync_all()
+        })
+        .await?;
+        Ok(tokio::fs::File::open(&self.path).await?.into_std().await)
+    }
+
+    // The buffer, unless it was lost along with a write out of it that
+    // panicked.
+    fn buf(&mut self) -> Result<&mut AlignedBuf, NdbError> {
+        self.buf.as_mut().ok_or_else(|| {
+            let message = format!("an earlier write to {} failed", self.path.display());
+            NdbError::Io(io::Error::other(message))
+        })
+    }
+
+    // Writes the first `len` buffered bytes, which must be a whole number of
+    // blocks, and moves the rest to the front of the buffer. If the write
+    // fails, the buffer's left as it was, to be written again.
+    async fn write_out(&mut self, len: usize) -> Result<(), NdbError> {
+        if len == 0 {
+            return Ok(());
+        }
+        self.buf()?;
+        let file = self.file.clone();
+        let offset = self.offset;
+        let mut buf = self.buf.take().unwrap();
+        let (buf, written) = tokio::task::spawn_blocking(move || {
+            let written = write_all_at(&file, &buf.as_slice()[..len], offset);
+            if written.is_ok() {
+                buf.consume(len);
+            }
+            (buf, written)
+        })
+        .await
+        .map_err(|err| NdbError::Io(io::Error::other(err)))?;
+        self.buf = Some(buf);
+        written?;
+        self.offset += len as u64;
+        Ok(())
+    }
+}
+
+async fn blocking(f: impl FnOnce() -> io::Result<()> + Send + 'static) -> Result<(), NdbError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| NdbError::Io(io::Error::other(err)))??;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+async fn open_direct(path: &Path) -> io::Result<File> {
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+        .await?;
+    Ok(file.into_std().await)
+}
+
+// There's no O_DIRECT to ask for.
+#[cfg(not(target_os = "linux"))]
+async fn open_direct(_path: &Path) -> io::Result<File> {
+    Err(io::ErrorKind::InvalidInput.into())
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+// A buffer of `BUFFER_SIZE` bytes starting at an `ALIGN`ed address, the
+// first `len` of which are in use.
+struct AlignedBuf {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// Safety: the buffer is only ever reached through its owner.
+unsafe impl Send for AlignedBuf {}
+
+impl AlignedBuf {
+    fn layout() -> Layout {
+        Layout::from_size_align(BUFFER_SIZE, ALIGN).unwrap()
+    }
+
+    fn new() -> AlignedBuf {
+        // Zeroed, so padding out the last block writes zeroes.
+        let ptr = unsafe { alloc::alloc_zeroed(Self::layout()) };
+        if ptr.is_null() {
+            alloc::handle_alloc_error(Self::layout());
+        }
+        AlignedBuf { ptr, len: 0 }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, BUFFER_SIZE) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, BUFFER_SIZE) }
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == BUFFER_SIZE
+    }
+
+    // Copies as much of `data` in as fits, and returns how much that was.
+    fn append(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(BUFFER_SIZE - self.len);
+        let len = self.len;
+        self.as_mut_slice()[len..len + n].copy_from_slice(&data[..n]);
+        self.len += n;
+        n
+    }
+
+    // Drops the first `n` bytes, moving the rest to the front and zeroing
+    // what they leave behind.
+    fn consume(&mut self, n: usize) {
+        let len = self.len;
+        let slice = self.as_mut_slice();
+        slice.copy_within(n..len, 0);
+        slice[len - n..len].fill(0);
+        self.len = len - n;
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, Self::layout()) }
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use crate::{StallReport, ValidationError, WriteStallReason};
+
+#[derive(Debug)]
+pub enum NdbError {
+    Io(std::io::Error),
+    Serde(serde_json::Error),
+    /// Something on disk isn't what it should be. `file` and `offset` say
+    /// where, when it was found in a particular file, e.g. the start of the
+    /// table block or log record that couldn't be read.
+    Corruption {
+        file: Option<PathBuf>,
+        offset: Option<u64>,
+        detail: String,
+    },
+    /// There's no database, backup, etc. where one was asked for.
+    NotFound(String),
+    Validation(ValidationError),
+    InvalidArgument(String),
+    ReadOnly,
+    /// Another `Db` already has the database open for writing, in this
+    /// process or another one.
+    AlreadyLocked,
+    Stalled(Box<StallReport>),
+    /// The wall clock went back from `previous` to `now`, both in unix
+    /// seconds.
+    ClockSkew {
+        previous: u64,
+        now: u64,
+    },
+    /// A [`Transaction`](crate::Transaction) read a key that was written
+    /// before it committed. Retrying it from the start may succeed.
+    Conflict,
+    /// A transaction gave up waiting for a key lock, possibly because of a
+    /// deadlock. See [`DbOptions::lock_timeout`](crate::DbOptions::lock_timeout).
+    LockTimeout,
+    /// A write was rejected because the database's files already take up
+    /// `used` bytes, more than
+    /// [`DbOptions::max_total_bytes`](crate::DbOptions::max_total_bytes).
+    QuotaExceeded {
+        used: u64,
+        limit: u64,
+    },
+    /// An earlier write failed partway through writing to the log, so the
+    /// log may end in a partial record, and anything appended after it
+    /// would be lost when the database is reopened. Every write fails with
+    /// this until it is.
+    Poisoned(String),
+    /// A write was rejected because tables have piled up past a stop
+    /// threshold in
+    /// [`DbOptions::write_stall`](crate::DbOptions::write_stall). Writes go
+    /// ahead again once they've been merged.
+    WritesStopped(Vec<WriteStallReason>),
+    /// `file` was written by a newer version of nulldb, in a format newer
+    /// than the `supported` one this version can read.
+    UnsupportedVersion {
+        file: PathBuf,
+        version: u32,
+        supported: u32,
+    },
+}
+
+impl NdbError {
+    pub(crate) fn corruption(detail: impl Into<String>) -> NdbError {
+        NdbError::Corruption {
+            file: None,
+            offset: None,
+            detail: detail.into(),
+        }
+    }
+
+    // Says which file corruption was found in, if it doesn't already.
+    pub(crate) fn in_file(mut self, path: &Path) -> NdbError {
+        if let NdbError::Corruption {
+            file: file @ None, ..
+        } = &mut self
+        {
+            *file = Some(path.into());
+        }
+        self
+    }
+
+    // Says where in its file corruption was found, if it doesn't already.
+    pub(crate) fn at_offset(mut self, at: u64) -> NdbError {
+        if let NdbError::Corruption {
+            offset: offset @ None,
+            ..
+        } = &mut self
+        {
+            *offset = Some(at);
+        }
+        self
+    }
+
+    /// Whether trying the same thing again might succeed without anything
+    /// else changing: another writer may let go of a lock, or a conflicting
+    /// transaction may have finished.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            NdbError::Io(err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::TimedOut
+            ),
+            NdbError::AlreadyLocked
+            | NdbError::Stalled(_)
+            | NdbError::Conflict
+            | NdbError::LockTimeout => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the database can't be trusted to carry on, and has to be
+    /// reopened, or repaired or restored from a backup, first.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, NdbError::Corruption { .. } | NdbError::Poisoned(_))
+    }
+}
+
+impl Display for NdbError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            NdbError::Io(err) => write!(f, "IO error: {}", err),
+            NdbError::Serde(err) => write!(f, "Serde error: {}", err),
+            NdbError::Corruption {
+                file,
+                offset,
+                detail,
+            } => {
+                write!(f, "Corruption")?;
+                if let Some(file) = file {
+                    write!(f, " in {}", file.display())?;
+                }
+                if let Some(offset) = offset {
+                    write!(f, " at offset {}", offset)?;
+                }
+                write!(f, ": {}", detail)
+            }
+            NdbError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            NdbError::Validation(err) => write!(f, "Validation error: {}", err),
+            NdbError::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
+            NdbError::ReadOnly => write!(f, "Database is read-only"),
+            NdbError::AlreadyLocked => write!(f, "Database is already open for writing"),
+            NdbError::Stalled(report) => write!(
+                f,
+                "{:?} job stalled for {:?} at step {:?}",
+                report.job, report.stalled_for, report.step
+            ),
+            NdbError::ClockSkew { previous, now } => {
+                write!(f, "Clock went backwards from {} to {}", previous, now)
+            }
+            NdbError::Conflict => write!(f, "Transaction conflicted with another write"),
+            NdbError::LockTimeout => write!(f, "Timed out waiting for a key lock"),
+            NdbError::QuotaExceeded { used, limit } => write!(
+                f,
+                "Database is using {} bytes, over its limit of {}",
+                used, limit
+            ),
+            NdbError::Poisoned(cause) => {
+                write!(f, "Database is unusable after a failed write: {}", cause)
+            }
+            NdbError::WritesStopped(reasons) => {
+                write!(
+                    f,
+                    "Writes are stopped until tables are merged: {:?}",
+                    reasons
+                )
+            }
+            NdbError::UnsupportedVersion {
+                file,
+                version,
+                supported,
+            } => write!(
+                f,
+                "{} is in format version {}, but only versions up to {} are supported",
+                file.display(),
+                version,
+                supported
+            ),
+        }
+    }
+}
+
+impl Error for NdbError {}
+
+impl From<std::io::Error> for NdbError {
+    fn from(err: std::io::Error) -> NdbError {
+        NdbError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for NdbError {
+    fn from(err: serde_json::Error) -> NdbError {
+        NdbError::Serde(err)
+    }
+}
+
+impl From<ValidationError> for NdbError {
+    fn from(err: ValidationError) -> NdbError {
+        NdbError::Validation(err)
+    }
+}
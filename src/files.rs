@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use crate::{NdbError, Storage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// The database's `CURRENT` file, or the manifest it names, which
+    /// between them list every other live file. `meta.json`, for a database
+    /// written by an older version that hasn't been opened for writing since.
+    DbMeta,
+    /// A write-ahead log: the one the memtable is being rebuilt from, or an
+    /// older one kept for the change feed.
+    Wal,
+    /// An SSTable's data file.
+    Table,
+    /// The metadata file that went with an SSTable, in a database written
+    /// by an older version that hasn't been opened for writing since.
+    TableMeta,
+    /// The positions change-feed consumers have acknowledged.
+    Consumers,
+}
+
+/// A file that the database currently depends on. Copying every file
+/// returned by [`crate::Db::live_files`] gives a consistent copy of the
+/// database.
+#[derive(Debug, Clone)]
+pub struct LiveFile {
+    pub path: PathBuf,
+    pub kind: FileKind,
+    /// The LSM level of a table; `None` for anything else.
+    pub level: Option<u32>,
+    pub size: u64,
+    /// The first and last keys in a table. `None` for an empty table, and
+    /// for anything that isn't a table.
+    pub smallest_key: Option<Vec<u8>>,
+    pub largest_key: Option<Vec<u8>>,
+    /// CRC32C of a table's data file, as recorded when it was written.
+    pub checksum: Option<u32>,
+    /// The lowest and highest sequence numbers of the writes in a table.
+    /// `None` for anything that isn't a table, and for tables written
+    /// before these were recorded.
+    pub sequence_range: Option<(u64, u64)>,
+}
+
+/// A table block that couldn't be read, as found by
+/// [`crate::Db::verify_integrity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptBlock {
+    /// The table's data file.
+    pub path: PathBuf,
+    /// Where the block starts in the file.
+    pub offset: u64,
+    pub reason: String,
+}
+
+impl LiveFile {
+    // Anything other than a table data file.
+    pub(crate) async fn other(
+        storage: &dyn Storage,
+        path: PathBuf,
+        kind: FileKind,
+    ) -> Result<LiveFile, NdbError> {
+        let size = storage.size(&path).await?;
+        Ok(LiveFile {
+            path,
+            kind,
+            level: None,
+            size,
+            smallest_key: None,
+            largest_key: None,
+            checksum: None,
+            sequence_range: None,
+        })
+    }
+}
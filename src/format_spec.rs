@@ -0,0 +1,302 @@
+use std::fmt::Write;
+
+use crate::{
+    block::{self, BlockBuilder, BlockHandle, BLOCK_TRAILER_SIZE, RESTART_INTERVAL},
+    bloom, compression,
+    db::RetainedWal,
+    index::IndexSearch,
+    log::{LogEntry, LogRecord},
+    manifest::VersionEdit,
+    range_del::RangeTombstones,
+    sstable::{Footer, SSTableMetadata, FOOTER_SIZE, MAGIC},
+    value::{self, Value},
+    version::{Header, FORMAT_VERSION, UNVERSIONED},
+};
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let line: Vec<_> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        writeln!(out, "    {:04x}: {}", i * 16, line.join(" ")).unwrap();
+    }
+    out
+}
+
+fn json(value: &impl serde::Serialize) -> String {
+    format!("    {}\n", serde_json::to_string(value).unwrap())
+}
+
+/// Describes the byte layout of every file nulldb writes, with examples
+/// produced by the same code that writes the real thing, for anyone reading
+/// the files from another language.
+pub fn format_spec() -> String {
+    let mut spec = String::new();
+    let s = &mut spec;
+
+    writeln!(s, "nulldb storage format\n").unwrap();
+    writeln!(
+        s,
+        "Fixed-width integers (fixed32, fixed64) are little endian. Varints are\n\
+         LEB128: seven bits per byte, low bits first, high bit set on every byte\n\
+         but the last. JSON byte strings are arrays of numbers.\n"
+    )
+    .unwrap();
+    writeln!(
+        s,
+        "This is format version {}. Tables, logs and manifests are stamped with\n\
+         the version they were written in. Files from before they were stamped\n\
+         are version {}, and differ where noted. A file with a newer version\n\
+         than the reader supports must not be read.\n",
+        FORMAT_VERSION, UNVERSIONED,
+    )
+    .unwrap();
+
+    writeln!(s, "== Database metadata: CURRENT and MANIFEST-<n> ==\n").unwrap();
+    writeln!(
+        s,
+        "CURRENT holds the name of the manifest in use, followed by a newline. It's\n\
+         written to CURRENT.tmp and renamed over CURRENT when a new manifest is\n\
+         started, on every open for writing and when the manifest grows too big.\n\
+         A manifest starts with a header line holding its format version; older\n\
+         ones have none. The rest is one JSON array of edits per line, applied\n\
+         in order; the first describes the whole database. A last line without\n\
+         its newline was cut off while being written and is ignored.\n\
+         \n\
+         AddTable adds a table. checksum is the crc32c of its whole data file.\n\
+         range_tombstones, when present, lists the ranges deleted by the table.\n\
+         index_search is only a hint for searching the index. sequence_range\n\
+         holds the lowest and highest sequence numbers written to the table.\n\
+         Fields added later may be missing from older tables. DeleteTable\n\
+         removes the table with the given data file.\n\
+         \n\
+         Wals replaces the database's logs. wal is the current WAL.\n\
+         wal_segments, when present, lists the earlier segments of the WAL that\n\
+         haven't been flushed yet, oldest first, with the last sequence number in\n\
+         each; the memtable is replayed from them and then wal. retained_wals,\n\
+         when present, lists older WALs kept for the change feed, oldest first,\n\
+         also with the last sequence number in each. pruned_through is the last\n\
+         sequence number in any WAL that has been deleted or archived.\n\
+         \n\
+         Databases written by older versions have a meta.json instead, naming a\n\
+         <timestamp>.meta file holding each table's AddTable object. They're\n\
+         moved to a manifest the next time they're opened for writing.\n"
+    )
+    .unwrap();
+    s.push_str(&json(&Header {
+        format_version: FORMAT_VERSION,
+    }));
+    let table = SSTableMetadata {
+        written_timestamp: 1_700_000_000,
+        data_path: "db/1700000000.sst".into(),
+        smallest_key: Some(b"apple".to_vec()),
+        largest_key: Some(b"apricot".to_vec()),
+        checksum: Some(0x1234_5678),
+        range_tombstones: RangeTombstones::default(),
+        index_search: IndexSearch::Binary,
+        index_key_len: None,
+        sequence_range: Some((1, 42)),
+    };
+    s.push_str(&json(&[
+        VersionEdit::AddTable(table),
+        VersionEdit::Wals {
+            wal: "db/log-1700000003".into(),
+            wal_segments: vec![RetainedWal {
+                path: "db/log-1700000002".into(),
+                last_seq: Some(50),
+            }],
+            retained_wals: vec![RetainedWal {
+                path: "db/log-1700000001".into(),
+                last_seq: Some(42),
+            }],
+            pruned_through: 17,
+        },
+    ]));
+    s.push_str(&json(&[VersionEdit::DeleteTable(
+        "db/1690000000.sst".into(),
+    )]));
+
+    writeln!(s, "\n== WAL ==\n").unwrap();
+    writeln!(
+        s,
+        "A header line holding the log's format version, which older logs don't\n\
+         have, and then one JSON record per line, in commit order. A log copied\n\
+         together from several can have a header partway through. seq is the\n\
+         record's sequence number, which goes up by at least one per record. A\n\
+         Batch record is one atomic write and is replayed all together or not at\n\
+         all. expires_at is in unix milliseconds. A Prepare record holds a batch\n\
+         that isn't applied until a Commit record with the same id, which holds\n\
+         the writes again, or is dropped by a Rollback. Prepares still waiting\n\
+         when the memtable is flushed are logged again in the next log.\n\
+         WriteBatch::to_bytes encodes a batch the same way, without seq or the\n\
+         newline.\n"
+    )
+    .unwrap();
+    let entries = [
+        LogEntry::Put {
+            key: b"k".to_vec(),
+            value: b"v".to_vec(),
+        },
+        LogEntry::PutUntil {
+            key: b"k".to_vec(),
+            value: b"v".to_vec(),
+            expires_at: 1_700_000_000_000,
+        },
+        LogEntry::Delete { key: b"k".to_vec() },
+        LogEntry::DeleteRange {
+            start: b"a".to_vec(),
+            end: b"b".to_vec(),
+        },
+        LogEntry::Merge {
+            key: b"k".to_vec(),
+            operand: b"+".to_vec(),
+        },
+        LogEntry::Batch(vec![
+            LogEntry::Put {
+                key: b"a".to_vec(),
+                value: b"1".to_vec(),
+            },
+            LogEntry::Delete { key: b"b".to_vec() },
+        ]),
+        LogEntry::Prepare {
+            id: 7,
+            ops: vec![LogEntry::Put {
+                key: b"c".to_vec(),
+                value: b"3".to_vec(),
+            }],
+        },
+        LogEntry::Commit {
+            id: 7,
+            ops: vec![LogEntry::Put {
+                key: b"c".to_vec(),
+                value: b"3".to_vec(),
+            }],
+        },
+        LogEntry::Rollback { id: 8 },
+    ];
+    s.push_str(&json(&Header {
+        format_version: FORMAT_VERSION,
+    }));
+    for (seq, entry) in (1..).zip(&entries) {
+        s.push_str(&json(&LogRecord { seq, entry }));
+    }
+
+    writeln!(s, "\n== SSTable data file: <timestamp>.sst ==\n").unwrap();
+    writeln!(
+        s,
+        "  data block 1 .. data block n | filter block | index block | footer\n"
+    )
+    .unwrap();
+
+    writeln!(s, "-- Blocks --\n").unwrap();
+    writeln!(
+        s,
+        "  entry: shared key len (varint) | unshared key len (varint) |\n\
+         \x20        value len (varint) | unshared key bytes | value bytes\n\
+         \x20 restarts: restart offset (fixed32) * num_restarts\n\
+         \x20 num_restarts: fixed32\n\n\
+         Every {}th entry is a restart point and stores its whole key.\n\n\
+         Each block is followed by a {}-byte trailer:\n\n\
+         \x20 type (u8) | crc32c of the stored block and the type byte (fixed32)\n\n\
+         type is the block's compression: {} none, {} snappy (raw), {} lz4\n\
+         (size-prefixed), {} zstd. Block handles point at the stored bytes, not\n\
+         counting the trailer:\n\n\
+         \x20 offset (varint) | size (varint)\n",
+        RESTART_INTERVAL,
+        BLOCK_TRAILER_SIZE,
+        compression::NONE,
+        compression::SNAPPY,
+        compression::LZ4,
+        compression::ZSTD,
+    )
+    .unwrap();
+
+    writeln!(s, "-- Values in data blocks --\n").unwrap();
+    writeln!(
+        s,
+        "A type byte followed by its payload:\n\n\
+         \x20 {} put: the value\n\
+         \x20 {} delete: nothing\n\
+         \x20 {} put with expiry: expires_at in unix milliseconds (fixed64) | value\n\
+         \x20 {} merge operands, oldest first: (len (varint) | operand) *\n",
+        value::TYPE_PUT,
+        value::TYPE_DELETE,
+        value::TYPE_PUT_UNTIL,
+        value::TYPE_MERGE,
+    )
+    .unwrap();
+
+    let mut builder = BlockBuilder::default();
+    builder.add(b"apple", &Value::Put(b"red".to_vec()).encode());
+    builder.add(b"apricot", &Value::Delete.encode());
+    let contents = builder.finish();
+    let mut stored = contents.clone();
+    block::write_trailer(&mut stored, &contents, compression::NONE);
+    writeln!(
+        s,
+        "A block holding apple = put \"red\" and apricot = delete, with its trailer:\n"
+    )
+    .unwrap();
+    s.push_str(&hex(&stored));
+
+    writeln!(s, "\n-- Filter block --\n").unwrap();
+    writeln!(
+        s,
+        "A bloom filter over every key in the table, {} bits per key: the bit\n\
+         array, then the number of probes (u8). A key's hash h is 32-bit FNV-1a;\n\
+         probe i tests bit (h + i * rotate_right(h, 17)) mod bits, wrapping at\n\
+         2^32, where bit n is bit n % 8 of byte n / 8. \"k\" hashes to {:#010x}.\n",
+        bloom::BITS_PER_KEY,
+        bloom::hash(b"k"),
+    )
+    .unwrap();
+
+    writeln!(s, "-- Index block --\n").unwrap();
+    writeln!(
+        s,
+        "A block mapping the last key of each data block, truncated if the\n\
+         table's metadata has an index_key_len, to the data block's handle.\n"
+    )
+    .unwrap();
+
+    writeln!(s, "-- Footer --\n").unwrap();
+    writeln!(
+        s,
+        "The last {} bytes of the file:\n\n\
+         \x20 index offset (fixed64) | index size (fixed64) |\n\
+         \x20 filter offset (fixed64) | filter size (fixed64) |\n\
+         \x20 format version (fixed64) | magic (fixed64)\n\n\
+         The magic number is {:#x}. Tables written before format version 2 have\n\
+         no format version field, and the magic number 0x6e756c6c64627373.\n\
+         For example:\n",
+        FOOTER_SIZE, MAGIC,
+    )
+    .unwrap();
+    let footer = Footer {
+        index: BlockHandle {
+            offset: 4200,
+            size: 30,
+        },
+        filter: BlockHandle {
+            offset: 4096,
+            size: 99,
+        },
+        version: FORMAT_VERSION,
+    };
+    s.push_str(&hex(&footer.encode()));
+
+    writeln!(s, "\n== Consumer acks: consumers.json ==\n").unwrap();
+    writeln!(
+        s,
+        "A JSON object from consumer name to the last position it acknowledged."
+    )
+    .unwrap();
+
+    writeln!(s, "\n== Lock file: LOCK ==\n").unwrap();
+    writeln!(
+        s,
+        "Empty. Whoever has the database open for writing holds an advisory lock on it."
+    )
+    .unwrap();
+
+    spec
+}
@@ -0,0 +1,119 @@
+// Entry points for the fuzz targets in fuzz/, which feed them arbitrary
+// bytes. Not a stable API.
+//
+// Whatever the bytes, each has to return without panicking, hanging or
+// allocating much more than it was given, and any error it returns has to be
+// one a corrupt file could cause; see `is_clean`.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use tokio::runtime::Runtime;
+
+use crate::{
+    block::{Block, BlockHandle},
+    compression,
+    memtable::Memtable,
+    pread::FileIo,
+    sstable::SSTable,
+    stats::IoCounters,
+    table_cache::TableCache,
+    value::Value,
+    DbOptions, MemStorage, MergeOperator, NdbError, Queryable, Storage, SystemClock, WalRecovery,
+};
+
+fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME
+        .get_or_init(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap()
+        })
+        .block_on(future)
+}
+
+async fn write_file(storage: &MemStorage, path: &Path, data: &[u8]) -> Result<(), NdbError> {
+    let mut file = storage.create(path).await?;
+    file.write(data).await
+}
+
+// Whether `err` is what a corrupt file should fail with, rather than a sign
+// that something went wrong reading it.
+pub fn is_clean(err: &NdbError) -> bool {
+    matches!(
+        err,
+        NdbError::Corruption { .. } | NdbError::UnsupportedVersion { .. }
+    )
+}
+
+// Decodes `data` as a block's contents: a codec id byte, then the stored
+// bytes, without the checksum that would otherwise turn away nearly every
+// input. Every entry is decoded both as a table's value and as an index's
+// block handle, and then sought.
+pub fn block(data: &[u8]) -> Result<(), NdbError> {
+    let Some((&codec, stored)) = data.split_first() else {
+        return Ok(());
+    };
+    let block = Block::new(compression::decompress(codec, stored.to_vec())?)?;
+    let mut keys = Vec::new();
+    for entry in block.iter() {
+        let (key, mut value) = entry?;
+        // Only one of these can be right.
+        let _ = Value::decode(value);
+        let _ = BlockHandle::decode_from(&mut value);
+        keys.push(key);
+    }
+    for key in keys {
+        block.seek(&key)?;
+    }
+    Ok(())
+}
+
+// Opens `data` as a table file, reads every block in it, and looks up the
+// last key of each.
+pub fn sstable(data: &[u8]) -> Result<(), NdbError> {
+    block_on(async {
+        let storage = MemStorage::new();
+        let path = Path::new("/fuzz/1.sst");
+        write_file(&storage, path, data).await?;
+        let options = DbOptions {
+            storage: Arc::new(storage),
+            ..DbOptions::default()
+        };
+        let io = Arc::new(IoCounters::default());
+        let files = Arc::new(TableCache::new(
+            1,
+            io.clone(),
+            options.storage.clone(),
+            FileIo::Blocking,
+        ));
+        let table = SSTable::open_unrecorded(path, &options, &io, &files).await?;
+        for i in 0..table.block_count() {
+            if let Some((key, _)) = table.block_entries(i).await?.pop() {
+                table.get(&key).await?;
+            }
+        }
+        Ok(())
+    })
+}
+
+// Replays `data` as the newest log.
+pub fn wal(data: &[u8], recovery: WalRecovery) -> Result<(), NdbError> {
+    block_on(async {
+        let storage = MemStorage::new();
+        let paths = [PathBuf::from("/fuzz/log")];
+        write_file(&storage, &paths[0], data).await?;
+        // Concatenates operands onto the value, so merges replay too.
+        let merge: MergeOperator = Arc::new(|_, base, operands| {
+            let mut value = base.unwrap_or_default().to_vec();
+            operands.iter().for_each(|operand| value.extend(operand));
+            value
+        });
+        let clock = Arc::new(SystemClock);
+        Memtable::hydrate(&storage, &paths, Some(merge), clock, recovery).await?;
+        Ok(())
+    })
+}
@@ -0,0 +1,183 @@
+//! A gRPC interface to a [`Db`], so it can be used over the network from any
+//! language. Needs the `grpc` feature.
+//!
+//! The interface is in proto/nulldb.proto, for generating clients in other
+//! languages. [`NdbService`] serves it, once added to a
+//! `tonic::transport::Server` with [`NdbService::into_server`], and
+//! [`NulldbClient`] is a client for it.
+
+use std::{ops::Bound, pin::Pin, sync::Arc};
+
+use futures::{stream, Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+use crate::{Db, DbIterator, NdbError, WriteBatch};
+
+/// The messages and the generated service and client, from
+/// proto/nulldb.proto.
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("nulldb.v1");
+}
+
+use proto::{
+    nulldb_server::{Nulldb, NulldbServer},
+    write::Op,
+    BatchWriteRequest, BatchWriteResponse, DeleteRequest, DeleteResponse, GetRequest, GetResponse,
+    KeyValue, PutRequest, PutResponse, ScanRequest, ScanResponse,
+};
+
+pub use proto::nulldb_client::NulldbClient;
+
+/// The most entries a `Scan` returns by default.
+const MAX_SCAN: usize = 1000;
+
+/// How many bytes of keys and values a `Scan` stops at, so its response is
+/// under tonic's default limit of 4MiB a message.
+const MAX_SCAN_BYTES: usize = 3 << 20;
+
+/// Serves a [`Db`] through the `Nulldb` gRPC service.
+#[derive(Clone)]
+pub struct NdbService {
+    db: Arc<Db>,
+    max_scan: usize,
+}
+
+impl NdbService {
+    pub fn new(db: Arc<Db>) -> NdbService {
+        NdbService {
+            db,
+            max_scan: MAX_SCAN,
+        }
+    }
+
+    /// Sets the most entries a `Scan` returns at once, 1000 by default.
+    /// `StreamScan` isn't limited.
+    pub fn with_max_scan(mut self, max_scan: usize) -> NdbService {
+        self.max_scan = max_scan.max(1);
+        self
+    }
+
+    /// Wraps the service up to be added to a `tonic::transport::Server`.
+    pub fn into_server(self) -> NulldbServer<NdbService> {
+        NulldbServer::new(self)
+    }
+
+    async fn iter(&self, request: &ScanRequest) -> Result<DbIterator, NdbError> {
+        let start = request
+            .start
+            .clone()
+            .map_or(Bound::Unbounded, Bound::Included);
+        let end = request
+            .end
+            .clone()
+            .map_or(Bound::Unbounded, Bound::Excluded);
+        match request.reverse {
+            true => self.db.scan_rev((start, end)).await,
+            false => self.db.scan((start, end)).await,
+        }
+    }
+}
+
+fn status(err: NdbError) -> Status {
+    let message = err.to_string();
+    match err {
+        NdbError::InvalidArgument(_) | NdbError::Validation(_) => Status::invalid_argument(message),
+        NdbError::NotFound(_) => Status::not_found(message),
+        NdbError::ReadOnly | NdbError::Poisoned(_) => Status::failed_precondition(message),
+        NdbError::Conflict | NdbError::LockTimeout => Status::aborted(message),
+        NdbError::QuotaExceeded { .. } => Status::resource_exhausted(message),
+        NdbError::Stalled(_) | NdbError::WritesStopped(_) => Status::unavailable(message),
+        NdbError::Corruption { .. } => Status::data_loss(message),
+        _ => Status::internal(message),
+    }
+}
+
+type KeyValueStream = Pin<Box<dyn Stream<Item = Result<KeyValue, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Nulldb for NdbService {
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let value = self.db.get(&request.get_ref().key).await.map_err(status)?;
+        Ok(Response::new(GetResponse { value }))
+    }
+
+    async fn put(&self, request: Request<PutRequest>) -> Result<Response<PutResponse>, Status> {
+        let PutRequest { key, value } = request.into_inner();
+        self.db.put(&key, &value).await.map_err(status)?;
+        Ok(Response::new(PutResponse {}))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        self.db
+            .delete(&request.get_ref().key)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(DeleteResponse {}))
+    }
+
+    async fn batch_write(
+        &self,
+        request: Request<BatchWriteRequest>,
+    ) -> Result<Response<BatchWriteResponse>, Status> {
+        let mut batch = WriteBatch::new();
+        for write in request.into_inner().writes {
+            match write.op {
+                Some(Op::Put(put)) => batch.put(&put.key, &put.value),
+                Some(Op::Delete(delete)) => batch.delete(&delete.key),
+                Some(Op::DeleteRange(range)) => batch.delete_range(&range.start, &range.end),
+                None => return Err(Status::invalid_argument("write with no op")),
+            }
+        }
+        self.db.write(batch).await.map_err(status)?;
+        Ok(Response::new(BatchWriteResponse {}))
+    }
+
+    async fn scan(&self, request: Request<ScanRequest>) -> Result<Response<ScanResponse>, Status> {
+        let limit = match request.get_ref().limit {
+            0 => self.max_scan,
+            limit => (limit as usize).min(self.max_scan),
+        };
+        let mut iter = self.iter(request.get_ref()).await.map_err(status)?;
+        let (mut entries, mut bytes) = (Vec::new(), 0);
+        while let Some((key, value)) = iter.next().await.map_err(status)? {
+            if entries.len() == limit
+                || (bytes > 0 && bytes + key.len() + value.len() > MAX_SCAN_BYTES)
+            {
+                return Ok(Response::new(ScanResponse {
+                    entries,
+                    more: true,
+                }));
+            }
+            bytes += key.len() + value.len();
+            entries.push(KeyValue { key, value });
+        }
+        Ok(Response::new(ScanResponse {
+            entries,
+            more: false,
+        }))
+    }
+
+    type StreamScanStream = KeyValueStream;
+
+    async fn stream_scan(
+        &self,
+        request: Request<ScanRequest>,
+    ) -> Result<Response<KeyValueStream>, Status> {
+        let limit = match request.get_ref().limit {
+            0 => usize::MAX,
+            limit => limit as usize,
+        };
+        let iter = self.iter(request.get_ref()).await.map_err(status)?;
+        // Entries are read as the client takes them, so a slow client holds
+        // the iterator open rather than piling them up in memory.
+        let entries = stream::try_unfold(iter, |mut iter| async move {
+            let entry = iter.next().await.map_err(status)?;
+            Ok(entry.map(|(key, value)| (KeyValue { key, value }, iter)))
+        });
+        Ok(Response::new(Box::pin(entries.take(limit))))
+    }
+}
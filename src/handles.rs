@@ -0,0 +1,188 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{sstable::SSTable, Clock};
+
+/// What's holding on to an old state of the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HandleKind {
+    View,
+    Iterator,
+}
+
+/// A [`DbView`](crate::DbView) or [`DbIterator`](crate::DbIterator) that's
+/// still open, as listed in [`HandleStats`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenHandle {
+    pub kind: HandleKind,
+    pub age: Duration,
+    /// The size of the tables it reads that the database has since deleted,
+    /// which can't be freed until it's dropped.
+    pub obsolete_bytes: u64,
+    /// Where it was created. Only captured with the `handle-backtraces`
+    /// feature, since it's slow.
+    pub backtrace: Option<String>,
+}
+
+/// The views and iterators open on the database, which keep the tables they
+/// read from being freed. One that's been around for a long time is likely
+/// to have been leaked.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HandleStats {
+    pub views: usize,
+    pub iterators: usize,
+    /// Oldest first.
+    pub open: Vec<OpenHandle>,
+}
+
+/// When to warn about an old view or iterator keeping deleted tables around.
+#[derive(Debug, Clone)]
+pub struct HandleWarning {
+    pub min_age: Duration,
+    pub min_obsolete_bytes: u64,
+}
+
+impl Default for HandleWarning {
+    fn default() -> HandleWarning {
+        HandleWarning {
+            min_age: Duration::from_secs(10 * 60),
+            min_obsolete_bytes: 64 << 20,
+        }
+    }
+}
+
+struct Entry {
+    kind: HandleKind,
+    created: Instant,
+    tables: Vec<(PathBuf, u64)>,
+    backtrace: Option<String>,
+    warned: bool,
+}
+
+// Every view and iterator open on a database.
+pub(crate) struct Handles {
+    clock: Arc<dyn Clock>,
+    next_id: AtomicU64,
+    open: Mutex<BTreeMap<u64, Entry>>,
+}
+
+// Stops tracking its view or iterator when dropped.
+pub(crate) struct Handle {
+    handles: Arc<Handles>,
+    id: u64,
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.handles.open.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Handles {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Handles {
+        Handles {
+            clock,
+            next_id: AtomicU64::new(0),
+            open: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub(crate) fn track<'a>(
+        self: &Arc<Self>,
+        kind: HandleKind,
+        tables: impl IntoIterator<Item = &'a Arc<SSTable>>,
+    ) -> Handle {
+        let tables = tables
+            .into_iter()
+            .map(|table| (table.meta.data_path.clone(), table.size))
+            .collect();
+        #[cfg(feature = "handle-backtraces")]
+        let backtrace = Some(std::backtrace::Backtrace::force_capture().to_string());
+        #[cfg(not(feature = "handle-backtraces"))]
+        let backtrace = None;
+        let entry = Entry {
+            kind,
+            created: self.clock.instant(),
+            tables,
+            backtrace,
+            warned: false,
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.open.lock().unwrap().insert(id, entry);
+        Handle {
+            handles: self.clone(),
+            id,
+        }
+    }
+
+    // `live` is every table the database is still using.
+    pub(crate) fn stats<'a>(
+        &self,
+        live: impl IntoIterator<Item = &'a Arc<SSTable>>,
+    ) -> HandleStats {
+        let live: BTreeSet<_> = live.into_iter().map(|t| &t.meta.data_path).collect();
+        let now = self.clock.instant();
+        let mut stats = HandleStats::default();
+        // Ids go up, so this is oldest first.
+        for entry in self.open.lock().unwrap().values() {
+            match entry.kind {
+                HandleKind::View => stats.views += 1,
+                HandleKind::Iterator => stats.iterators += 1,
+            }
+            stats.open.push(entry.describe(&live, now));
+        }
+        stats
+    }
+
+    // Warns, once each, about handles past `warning`'s limits.
+    pub(crate) fn warn<'a>(
+        &self,
+        live: impl IntoIterator<Item = &'a Arc<SSTable>>,
+        warning: &HandleWarning,
+    ) {
+        let live: BTreeSet<_> = live.into_iter().map(|t| &t.meta.data_path).collect();
+        let now = self.clock.instant();
+        for entry in self.open.lock().unwrap().values_mut() {
+            let handle = entry.describe(&live, now);
+            if entry.warned
+                || handle.age < warning.min_age
+                || handle.obsolete_bytes < warning.min_obsolete_bytes
+            {
+                continue;
+            }
+            entry.warned = true;
+            let created_at = handle
+                .backtrace
+                .map_or(String::new(), |bt| format!(", created at:\n{}", bt));
+            warn!(
+                kind = ?handle.kind,
+                age = ?handle.age,
+                obsolete_bytes = handle.obsolete_bytes,
+                "a handle is keeping deleted tables from being freed{}",
+                created_at,
+            );
+        }
+    }
+}
+
+impl Entry {
+    fn describe(&self, live: &BTreeSet<&PathBuf>, now: Instant) -> OpenHandle {
+        let obsolete = self.tables.iter().filter(|(path, _)| !live.contains(path));
+        OpenHandle {
+            kind: self.kind,
+            age: now.saturating_duration_since(self.created),
+            obsolete_bytes: obsolete.map(|(_, size)| size).sum(),
+            backtrace: self.backtrace.clone(),
+        }
+    }
+}
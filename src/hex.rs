@@ -0,0 +1,22 @@
+// Hex digits for keys and values written as text, shared by `ndb` and the
+// HTTP server. Not a stable API.
+
+/// `0x` and `bytes` in lowercase hex digits.
+pub fn encode(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", digits)
+}
+
+/// The bytes a string of hex digits, without the `0x`, stands for.
+pub fn decode(digits: &str) -> Result<Vec<u8>, &'static str> {
+    if !digits.len().is_multiple_of(2) {
+        return Err("odd number of hex digits");
+    }
+    // By bytes, since a non-ASCII character would put a pair of them across
+    // a char boundary.
+    let digit = |b: u8| (b as char).to_digit(16);
+    (digits.as_bytes().chunks(2))
+        .map(|pair| Some((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+        .collect::<Option<_>>()
+        .ok_or("bad hex")
+}
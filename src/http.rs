@@ -0,0 +1,212 @@
+//! An HTTP interface to a [`Db`], for poking at it with curl and hooking it
+//! up to dashboards. Needs the `http` feature.
+//!
+//! [`router`] returns an axum `Router` serving:
+//!
+//! - `GET /kv/{key}`: `{"key": ..., "value": ...}`, or a 404.
+//! - `PUT /kv/{key}` with `{"value": ...}`, and `DELETE /kv/{key}`.
+//! - `GET /scan?start=&end=&limit=`: `{"entries": [{"key": ..., "value":
+//!   ...}, ...], "more": ...}`, from `start`, included, to `end`, excluded,
+//!   either of which can be left out. `more` says whether the range has
+//!   entries past the ones returned, which are at most `limit`, or
+//!   [`HttpOptions::max_scan`] if that's lower.
+//! - `POST /flush`, `POST /compact`, which vacuums, and `GET /stats`, the
+//!   [`DbStats`](crate::DbStats).
+//! - `POST /backup`, into [`HttpOptions::backup_dir`].
+//!
+//! Keys and values are strings where they're printable UTF-8, and `0x`
+//! followed by hex digits otherwise, as they are in `ndb`; keys in paths
+//! and queries are too, percent-encoded. Errors are `{"error": ...}`.
+
+use std::{collections::HashMap, ops::Bound, path::PathBuf, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+
+use crate::{hex, BackupEngine, Db, NdbError};
+
+pub struct HttpOptions {
+    /// The most entries a scan returns at once.
+    pub max_scan: usize,
+    /// Where `POST /backup` backs up to, with a [`BackupEngine`]. `None`
+    /// turns backups off.
+    pub backup_dir: Option<PathBuf>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> HttpOptions {
+        HttpOptions {
+            max_scan: 1000,
+            backup_dir: None,
+        }
+    }
+}
+
+struct Shared {
+    // Reads and writes share it; flushes, compactions and backups need it to
+    // themselves.
+    db: Arc<RwLock<Db>>,
+    options: HttpOptions,
+}
+
+type AppState = State<Arc<Shared>>;
+
+/// Routes serving `db`, as described in the [module docs](self).
+pub fn router(db: Arc<RwLock<Db>>, options: HttpOptions) -> Router {
+    Router::new()
+        .route("/kv/{key}", get(get_key).put(put_key).delete(delete_key))
+        .route("/scan", get(scan))
+        .route("/flush", post(flush))
+        .route("/compact", post(compact))
+        .route("/stats", get(stats))
+        .route("/backup", post(backup))
+        .with_state(Arc::new(Shared { db, options }))
+}
+
+struct Error(StatusCode, String);
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<NdbError> for Error {
+    fn from(err: NdbError) -> Error {
+        let status = match err {
+            NdbError::InvalidArgument(_) | NdbError::Validation(_) => StatusCode::BAD_REQUEST,
+            NdbError::NotFound(_) => StatusCode::NOT_FOUND,
+            NdbError::ReadOnly | NdbError::Poisoned(_) | NdbError::Conflict => StatusCode::CONFLICT,
+            NdbError::QuotaExceeded { .. } => StatusCode::INSUFFICIENT_STORAGE,
+            NdbError::Stalled(_) | NdbError::WritesStopped(_) | NdbError::LockTimeout => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Error(status, err.to_string())
+    }
+}
+
+fn bad_request(message: impl Into<String>) -> Error {
+    Error(StatusCode::BAD_REQUEST, message.into())
+}
+
+// `0x` and hex digits, or the string itself if it's printable. Strings that
+// start with 0x are written in hex too, so that they read back the same.
+fn text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) if !s.starts_with("0x") && !s.chars().any(char::is_control) => s.into(),
+        _ => hex::encode(bytes),
+    }
+}
+
+fn bytes(text: &str) -> Result<Vec<u8>, Error> {
+    let Some(digits) = text.strip_prefix("0x") else {
+        return Ok(text.as_bytes().to_vec());
+    };
+    hex::decode(digits).map_err(|err| bad_request(format!("{} in {}", err, text)))
+}
+
+async fn get_key(State(shared): AppState, Path(key): Path<String>) -> Result<Json<Value>, Error> {
+    let key = bytes(&key)?;
+    let db = shared.db.read().await;
+    match db.get(&key).await? {
+        Some(value) => Ok(Json(json!({ "key": text(&key), "value": text(&value) }))),
+        None => Err(Error(
+            StatusCode::NOT_FOUND,
+            format!("no key {}", text(&key)),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct PutBody {
+    value: String,
+}
+
+async fn put_key(
+    State(shared): AppState,
+    Path(key): Path<String>,
+    Json(body): Json<PutBody>,
+) -> Result<StatusCode, Error> {
+    let db = shared.db.read().await;
+    db.put(&bytes(&key)?, &bytes(&body.value)?).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_key(State(shared): AppState, Path(key): Path<String>) -> Result<StatusCode, Error> {
+    let db = shared.db.read().await;
+    db.delete(&bytes(&key)?).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn scan(
+    State(shared): AppState,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, Error> {
+    let bound = |name| params.get(name).map(|key| bytes(key)).transpose();
+    let (start, end) = (bound("start")?, bound("end")?);
+    let limit = match params.get("limit") {
+        Some(limit) => limit
+            .parse::<usize>()
+            .map_err(|_| bad_request(format!("bad limit {}", limit)))?
+            .min(shared.options.max_scan),
+        None => shared.options.max_scan,
+    };
+    let range = (
+        start.map_or(Bound::Unbounded, Bound::Included),
+        end.map_or(Bound::Unbounded, Bound::Excluded),
+    );
+    let db = shared.db.read().await;
+    let mut iter = db.scan(range).await?;
+    let mut entries = Vec::new();
+    while let Some((key, value)) = iter.next().await? {
+        if entries.len() == limit {
+            return Ok(Json(json!({ "entries": entries, "more": true })));
+        }
+        entries.push(json!({ "key": text(&key), "value": text(&value) }));
+    }
+    Ok(Json(json!({ "entries": entries, "more": false })))
+}
+
+async fn flush(State(shared): AppState) -> Result<StatusCode, Error> {
+    shared.db.write().await.flush_memtable().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn compact(State(shared): AppState) -> Result<Json<Value>, Error> {
+    let merged = shared.db.write().await.vacuum().await?;
+    Ok(Json(json!({ "tables_merged": merged })))
+}
+
+async fn stats(State(shared): AppState) -> Json<Value> {
+    let stats = shared.db.read().await.stats();
+    Json(serde_json::to_value(stats).expect("stats serialize"))
+}
+
+async fn backup(State(shared): AppState) -> Result<Json<Value>, Error> {
+    let Some(dir) = &shared.options.backup_dir else {
+        return Err(Error(
+            StatusCode::NOT_FOUND,
+            "backups aren't turned on".into(),
+        ));
+    };
+    let engine = BackupEngine::open(dir).await?;
+    let mut db = shared.db.write().await;
+    let info = engine.create_backup(&mut db).await?;
+    Ok(Json(json!({
+        "id": info.id,
+        "timestamp": info.timestamp,
+        "sequence": info.sequence,
+        "size": info.size,
+        "tables": info.tables,
+    })))
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+use crate::block::BlockHandle;
+
+// A binary search over a smaller index only takes a handful of steps, so
+// there's nothing to win by guessing.
+const MIN_INTERPOLATION_ENTRIES: usize = 64;
+
+// How a table's index is searched for the block that might hold a key. Chosen
+// when the table is written, from how its index keys are spread out.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum IndexSearch {
+    #[default]
+    Binary,
+    // Past a prefix they all share, the index keys are spread evenly enough
+    // that a key's position can be guessed from its value, to within
+    // `max_error` entries. Typical of big-endian integer keys.
+    Interpolation {
+        prefix_len: usize,
+        max_error: usize,
+    },
+}
+
+// The first eight bytes of `key` past the shared prefix, as a number. Keys
+// that sort lower never map to a bigger number.
+fn numeric(key: &[u8], prefix_len: usize) -> u64 {
+    let rest = &key[prefix_len.min(key.len())..];
+    let mut buf = [0; 8];
+    let n = rest.len().min(8);
+    buf[..n].copy_from_slice(&rest[..n]);
+    u64::from_be_bytes(buf)
+}
+
+// Where `key` would be in an index of `len` keys running evenly from `first`
+// to `last`.
+fn guess(key: u64, first: u64, last: u64, len: usize) -> f64 {
+    let key = key.clamp(first, last);
+    (key - first) as f64 / (last - first) as f64 * (len - 1) as f64
+}
+
+impl IndexSearch {
+    pub(crate) fn choose(index: &[(Vec<u8>, BlockHandle)]) -> IndexSearch {
+        let len = index.len();
+        if len < MIN_INTERPOLATION_ENTRIES {
+            return IndexSearch::Binary;
+        }
+        let (first, last) = (&index[0].0, &index[len - 1].0);
+        let prefix_len = first
+            .iter()
+            .zip(last.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let (lo, hi) = (numeric(first, prefix_len), numeric(last, prefix_len));
+        if lo == hi {
+            return IndexSearch::Binary;
+        }
+
+        let max_error = index
+            .iter()
+            .enumerate()
+            .map(|(i, (key, _))| {
+                let guess = guess(numeric(key, prefix_len), lo, hi, len);
+                (guess - i as f64).abs().ceil() as usize
+            })
+            .max()
+            .unwrap_or(0);
+        // Only worth it if searching around the guess takes a good few fewer
+        // steps than searching the whole index.
+        if (2 * max_error + 3) * 8 > len {
+            return IndexSearch::Binary;
+        }
+        IndexSearch::Interpolation {
+            prefix_len,
+            max_error,
+        }
+    }
+
+    // Returns the position of the first index key >= `target`.
+    pub(crate) fn find(&self, index: &[(Vec<u8>, BlockHandle)], target: &[u8]) -> usize {
+        let binary = || index.partition_point(|(last, _)| last.as_slice() < target);
+        let IndexSearch::Interpolation {
+            prefix_len,
+            max_error,
+        } = *self
+        else {
+            return binary();
+        };
+        let len = index.len();
+        let (first, last) = (&index[0].0, &index[len - 1].0);
+        if target.get(..prefix_len) != first.get(..prefix_len) {
+            return binary();
+        }
+
+        let guess = guess(
+            numeric(target, prefix_len),
+            numeric(first, prefix_len),
+            numeric(last, prefix_len),
+            len,
+        )
+        .round() as usize;
+        // A target between two index keys lands at most one past their error.
+        let lo = guess.saturating_sub(max_error + 1);
+        let hi = (guess + max_error + 2).min(len);
+        let found = lo + index[lo..hi].partition_point(|(last, _)| last.as_slice() < target);
+
+        // The guess can't be trusted for a table that was written wrongly, so
+        // check the answer and fall back if it's off.
+        let after_smaller = found == 0 || index[found - 1].0.as_slice() < target;
+        let at_larger = found == len || index[found].0.as_slice() >= target;
+        if after_smaller && at_larger {
+            found
+        } else {
+            binary()
+        }
+    }
+}
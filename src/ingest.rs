@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{sstable::TableBuilder, value::Value, DbOptions, NdbError};
+
+/// Writes a table file outside of any database, for bulk loading: keys are
+/// added in order, and the finished file is added to a database whole with
+/// [`Db::ingest_sst`](crate::Db::ingest_sst), without going through its log
+/// or memtable.
+///
+/// The file is written through `options.storage`, with its block size and
+/// compression. It has to be ingested into a database using the same
+/// storage.
+pub struct SstWriter {
+    builder: TableBuilder,
+    info: SstInfo,
+}
+
+/// What's in a table file written by [`SstWriter`] or
+/// [`Db::export_range`](crate::Db::export_range), e.g. to send along with it
+/// when moving a shard.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SstInfo {
+    pub path: PathBuf,
+    /// Puts and deletes, one per key.
+    pub entries: u64,
+    /// Both `None` if there are no entries.
+    pub smallest_key: Option<Vec<u8>>,
+    pub largest_key: Option<Vec<u8>>,
+    /// The size of the file.
+    pub bytes: u64,
+    /// CRC32C of the whole file.
+    pub checksum: u32,
+}
+
+impl SstWriter {
+    pub async fn create(
+        path: impl AsRef<Path>,
+        options: &DbOptions,
+    ) -> Result<SstWriter, NdbError> {
+        // Ingesting works out everything else about the table from the file,
+        // but not how its index keys were cut down.
+        let options = DbOptions {
+            max_index_key_len: None,
+            ..options.clone()
+        };
+        Ok(SstWriter {
+            builder: TableBuilder::create(path.as_ref(), &options).await?,
+            info: SstInfo {
+                path: path.as_ref().into(),
+                entries: 0,
+                smallest_key: None,
+                largest_key: None,
+                bytes: 0,
+                checksum: 0,
+            },
+        })
+    }
+
+    /// Adds `key`, which has to come after every key added before it.
+    pub async fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), NdbError> {
+        self.add(key, Value::Put(value.to_vec())).await
+    }
+
+    /// Adds a delete of `key`, hiding it in the tables already in the
+    /// database it's ingested into. It has to come after every key added
+    /// before it.
+    pub async fn delete(&mut self, key: &[u8]) -> Result<(), NdbError> {
+        self.add(key, Value::Delete).await
+    }
+
+    pub(crate) async fn add(&mut self, key: &[u8], value: Value) -> Result<(), NdbError> {
+        let info = &mut self.info;
+        if info.largest_key.as_deref().is_some_and(|last| last >= key) {
+            return Err(NdbError::InvalidArgument(
+                "keys must be added to an SstWriter in increasing order".into(),
+            ));
+        }
+        self.builder.add(key, &value.encode()).await?;
+        info.smallest_key.get_or_insert_with(|| key.to_vec());
+        info.largest_key = Some(key.to_vec());
+        info.entries += 1;
+        Ok(())
+    }
+
+    /// Writes the rest of the file and syncs it.
+    pub async fn finish(mut self) -> Result<SstInfo, NdbError> {
+        let (file, checksum, _) = self.builder.finish().await?;
+        self.info.bytes = file.size().await?;
+        self.info.checksum = checksum;
+        Ok(self.info)
+    }
+}
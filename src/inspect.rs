@@ -0,0 +1,198 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    block::BlockHandle, pread::FileIo, sstable::SSTable, stats::IoCounters,
+    table_cache::TableCache, value::Value, DbOptions, NdbError,
+};
+
+/// What's in a table file and how it's laid out, as read by
+/// [`inspect_sst`], for debugging corruption.
+#[derive(Debug, Clone)]
+pub struct SstReport {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub format_version: u32,
+    /// CRC32C of the whole file, which a database's manifest records for
+    /// each of its tables.
+    pub checksum: u32,
+    /// Where the footer says the index and filter blocks are.
+    pub index: BlockLocation,
+    pub filter: FilterReport,
+    pub blocks: Vec<SstBlock>,
+    /// Across every block that could be read.
+    pub entries: u64,
+    pub smallest_key: Option<Vec<u8>>,
+    pub largest_key: Option<Vec<u8>>,
+}
+
+/// Where a block is in a table file, not counting its trailer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLocation {
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// A table's bloom filter.
+#[derive(Debug, Clone)]
+pub struct FilterReport {
+    pub location: BlockLocation,
+    pub bits: u64,
+    /// How many bits are set for each key.
+    pub probes: u8,
+}
+
+/// A data block, as listed in a table's index.
+#[derive(Debug, Clone)]
+pub struct SstBlock {
+    pub location: BlockLocation,
+    /// The key the index has for the block: its last key, or the start of it
+    /// if the table was written with
+    /// [`DbOptions::max_index_key_len`].
+    pub index_key: Vec<u8>,
+    pub entries: u64,
+    pub first_key: Option<Vec<u8>>,
+    pub last_key: Option<Vec<u8>>,
+    /// Why the block couldn't be read, or why its keys can't be right. Its
+    /// entries aren't counted if it couldn't be read.
+    pub corrupt: Option<String>,
+    /// Every record in the block, if asked for.
+    pub records: Vec<SstRecord>,
+}
+
+/// A record in a table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SstRecord {
+    pub key: Vec<u8>,
+    pub value: SstValue,
+}
+
+/// What a table records for a key. Values are as stored, before any
+/// [`DbOptions::value_transforms`] are undone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SstValue {
+    Put(Vec<u8>),
+    PutUntil {
+        value: Vec<u8>,
+        expires_at: SystemTime,
+    },
+    Delete,
+    /// Merge operands, oldest first.
+    Merge(Vec<Vec<u8>>),
+}
+
+impl From<Value> for SstValue {
+    fn from(value: Value) -> SstValue {
+        match value {
+            Value::Put(value) => SstValue::Put(value),
+            Value::PutUntil { value, expires_at } => SstValue::PutUntil {
+                value,
+                expires_at: UNIX_EPOCH + Duration::from_millis(expires_at),
+            },
+            Value::Delete => SstValue::Delete,
+            Value::Merge(operands) => SstValue::Merge(operands),
+        }
+    }
+}
+
+impl From<BlockHandle> for BlockLocation {
+    fn from(handle: BlockHandle) -> BlockLocation {
+        BlockLocation {
+            offset: handle.offset,
+            size: handle.size,
+        }
+    }
+}
+
+/// Reads the table file at `path` through `options.storage`, and reports
+/// on its layout and, with `records`, everything in it. A corrupt footer,
+/// index or filter is an error, but corrupt data blocks are reported and
+/// skipped.
+pub async fn inspect_sst(
+    path: impl AsRef<Path>,
+    options: &DbOptions,
+    records: bool,
+) -> Result<SstReport, NdbError> {
+    let path = path.as_ref();
+    let io = Arc::new(IoCounters::default());
+    let file_io = FileIo::new(options)?;
+    let files = Arc::new(TableCache::new(
+        1,
+        io.clone(),
+        options.storage.clone(),
+        file_io.clone(),
+    ));
+    let data_file = files.get(path).await?;
+    let (footer, index, filter, bytes) = SSTable::read_footer(&file_io, &data_file)
+        .await
+        .map_err(|err| err.in_file(path))?;
+    let table = SSTable::open_unrecorded(path, options, &io, &files).await?;
+
+    let mut blocks: Vec<SstBlock> = Vec::new();
+    for (i, (index_key, handle)) in index.into_iter().enumerate() {
+        let mut block = SstBlock {
+            location: handle.into(),
+            index_key,
+            entries: 0,
+            first_key: None,
+            last_key: None,
+            corrupt: None,
+            records: Vec::new(),
+        };
+        let entries = match table.block_entries(i).await {
+            Ok(entries) => entries,
+            Err(NdbError::Corruption { detail, .. }) => {
+                block.corrupt = Some(detail);
+                blocks.push(block);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        let last_before = blocks.iter().rev().find_map(|block| block.last_key.clone());
+        let mut last = last_before.as_deref();
+        for (key, _) in &entries {
+            if last.is_some_and(|last| last >= key.as_slice()) {
+                block.corrupt = Some("keys out of order".into());
+            }
+            last = Some(key);
+        }
+        let last_key = entries.last().map(|(key, _)| key.as_slice());
+        if last_key.is_some_and(|last| !last.starts_with(&block.index_key)) {
+            block.corrupt = Some("last key doesn't match the index".into());
+        }
+        block.entries = entries.len() as u64;
+        block.first_key = entries.first().map(|(key, _)| key.clone());
+        block.last_key = entries.last().map(|(key, _)| key.clone());
+        if records {
+            block.records = entries
+                .into_iter()
+                .map(|(key, value)| SstRecord {
+                    key,
+                    value: value.into(),
+                })
+                .collect();
+        }
+        blocks.push(block);
+    }
+
+    let probes = filter.last().copied().unwrap_or(0);
+    Ok(SstReport {
+        path: path.into(),
+        bytes,
+        format_version: footer.version,
+        checksum: table.file_checksum().await?,
+        index: footer.index.into(),
+        filter: FilterReport {
+            location: footer.filter.into(),
+            bits: filter.len().saturating_sub(1) as u64 * 8,
+            probes,
+        },
+        entries: blocks.iter().map(|block| block.entries).sum(),
+        smallest_key: blocks.iter().find_map(|block| block.first_key.clone()),
+        largest_key: blocks.iter().rev().find_map(|block| block.last_key.clone()),
+        blocks,
+    })
+}
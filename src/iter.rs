@@ -0,0 +1,307 @@
+use std::{
+    ops::Bound,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    handles::Handle,
+    merge::{self, MergeOperator},
+    range_del::RangeTombstones,
+    sstable::TableIter,
+    transform::{self, ValueTransform},
+    value::Value,
+    KeyValue, NdbError,
+};
+
+// Anything that yields entries in key order. Sources that are merged together
+// must all go in the same direction.
+pub(crate) trait KvSource {
+    type Value;
+
+    async fn next(&mut self) -> Result<Option<(Vec<u8>, Self::Value)>, NdbError>;
+}
+
+// Merges a list of sorted sources. Each step yields the smallest key any
+// source has (the largest, if the sources are in descending order), along with
+// the value from every source that has that key, in source order.
+pub(crate) struct MergingSources<S: KvSource> {
+    sources: Vec<S>,
+    reverse: bool,
+    // The next entry from each source, filled in on the first call to next.
+    heads: Option<Vec<Head<S::Value>>>,
+}
+
+type Head<V> = Option<(Vec<u8>, V)>;
+
+impl<S: KvSource> MergingSources<S> {
+    pub(crate) fn new(sources: Vec<S>, reverse: bool) -> MergingSources<S> {
+        MergingSources {
+            sources,
+            reverse,
+            heads: None,
+        }
+    }
+
+    pub(crate) async fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<S::Value>)>, NdbError> {
+        let MergingSources {
+            sources,
+            reverse,
+            heads,
+        } = self;
+        if heads.is_none() {
+            let mut initial = Vec::with_capacity(sources.len());
+            for source in sources.iter_mut() {
+                initial.push(source.next().await?);
+            }
+            *heads = Some(initial);
+        }
+        let heads = heads.as_mut().unwrap();
+
+        let keys = heads.iter().flatten().map(|(k, _)| k);
+        let next = if *reverse { keys.max() } else { keys.min() };
+        let Some(next) = next.cloned() else {
+            return Ok(None);
+        };
+        let mut values = Vec::new();
+        for (head, source) in heads.iter_mut().zip(sources.iter_mut()) {
+            if matches!(head, Some((k, _)) if *k == next) {
+                let (_, value) = head.take().unwrap();
+                values.push(value);
+                *head = source.next().await?;
+            }
+        }
+        Ok(Some((next, values)))
+    }
+}
+
+pub(crate) enum Entries {
+    Memtable(std::vec::IntoIter<(Vec<u8>, Value)>),
+    Table(TableIter),
+}
+
+// The entries of a memtable or table, minus anything deleted by a range
+// tombstone in a newer one.
+pub(crate) struct Source {
+    entries: Entries,
+    deleted: Arc<RangeTombstones>,
+}
+
+impl Source {
+    pub(crate) fn new(entries: Entries, deleted: Arc<RangeTombstones>) -> Source {
+        Source { entries, deleted }
+    }
+}
+
+impl KvSource for Source {
+    type Value = Value;
+
+    async fn next(&mut self) -> Result<Option<(Vec<u8>, Value)>, NdbError> {
+        loop {
+            let entry = match &mut self.entries {
+                Entries::Memtable(entries) => entries.next(),
+                Entries::Table(iter) => iter.next().await?,
+            };
+            match entry {
+                Some((key, _)) if self.deleted.covers(&key) => continue,
+                entry => return Ok(entry),
+            }
+        }
+    }
+}
+
+// Whether `key` is past the bound an iterator stops at: the end of the range
+// going forwards, or the start of it in reverse.
+fn past_end(key: &[u8], end: &Bound<Vec<u8>>, reverse: bool) -> bool {
+    match (end, reverse) {
+        (Bound::Included(end), false) => key > end.as_slice(),
+        (Bound::Excluded(end), false) => key >= end.as_slice(),
+        (Bound::Included(start), true) => key < start.as_slice(),
+        (Bound::Excluded(start), true) => key <= start.as_slice(),
+        (Bound::Unbounded, _) => false,
+    }
+}
+
+/// Iterates over a range of a [`Db`](crate::Db) in ascending key order, or
+/// descending order for [`Db::scan_rev`](crate::Db::scan_rev), as of when the
+/// iterator was created.
+pub struct DbIterator {
+    // Newest first.
+    merged: MergingSources<Source>,
+    end: Bound<Vec<u8>>,
+    reverse: bool,
+    // Values that expire by this time, in unix milliseconds, are skipped.
+    now: u64,
+    merge: Option<MergeOperator>,
+    transforms: Vec<(Vec<u8>, Arc<dyn ValueTransform>)>,
+    _handle: Option<Handle>,
+    done: bool,
+}
+
+impl DbIterator {
+    pub(crate) fn new(
+        sources: Vec<Source>,
+        end: Bound<Vec<u8>>,
+        now: u64,
+        merge: Option<MergeOperator>,
+    ) -> DbIterator {
+        DbIterator {
+            merged: MergingSources::new(sources, false),
+            end,
+            reverse: false,
+            now,
+            merge,
+            transforms: Vec::new(),
+            _handle: None,
+            done: false,
+        }
+    }
+
+    // `sources` must be in descending order, and `start` is where to stop.
+    pub(crate) fn new_rev(
+        sources: Vec<Source>,
+        start: Bound<Vec<u8>>,
+        now: u64,
+        merge: Option<MergeOperator>,
+    ) -> DbIterator {
+        DbIterator {
+            merged: MergingSources::new(sources, true),
+            end: start,
+            reverse: true,
+            now,
+            merge,
+            transforms: Vec::new(),
+            _handle: None,
+            done: false,
+        }
+    }
+
+    // Decodes the values it returns with `transforms`.
+    pub(crate) fn with_transforms(
+        mut self,
+        transforms: Vec<(Vec<u8>, Arc<dyn ValueTransform>)>,
+    ) -> DbIterator {
+        self.transforms = transforms;
+        self
+    }
+
+    pub(crate) fn tracked(mut self, handle: Handle) -> DbIterator {
+        self._handle = Some(handle);
+        self
+    }
+
+    /// Whether this iterator yields keys in descending order.
+    pub fn is_reverse(&self) -> bool {
+        self.reverse
+    }
+
+    pub async fn next(&mut self) -> Result<Option<KeyValue>, NdbError> {
+        let Some((key, value)) = self.next_value().await? else {
+            return Ok(None);
+        };
+        let value = value.live(self.now).expect("only live values are returned");
+        let value = transform::decode(&self.transforms, &key, value)?;
+        Ok(Some((key, value)))
+    }
+
+    /// Like [`DbIterator::next`], but also returns when the value expires,
+    /// if it was written with a TTL.
+    pub async fn next_with_expiry(
+        &mut self,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>, Option<SystemTime>)>, NdbError> {
+        let Some((key, value)) = self.next_value().await? else {
+            return Ok(None);
+        };
+        let expires_at = match value {
+            Value::PutUntil { expires_at, .. } => {
+                Some(UNIX_EPOCH + Duration::from_millis(expires_at))
+            }
+            _ => None,
+        };
+        let value = value.live(self.now).expect("only live values are returned");
+        let value = transform::decode(&self.transforms, &key, value)?;
+        Ok(Some((key, value, expires_at)))
+    }
+
+    // Like `next`, but keeps a value's expiry time, if it has one.
+    pub(crate) async fn next_value(&mut self) -> Result<Option<(Vec<u8>, Value)>, NdbError> {
+        while !self.done {
+            match self.merged.next().await? {
+                Some((key, _)) if past_end(&key, &self.end, self.reverse) => self.done = true,
+                Some((key, values)) => {
+                    // The newest version wins, and hides the key if it was
+                    // deleted or has expired.
+                    let value = merge::resolve(self.merge.as_ref(), &key, values, self.now)?;
+                    if let Some(value) = value.filter(|value| value.is_live(self.now)) {
+                        return Ok(Some((key, value)));
+                    }
+                }
+                None => self.done = true,
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl KvSource for DbIterator {
+    type Value = Vec<u8>;
+
+    async fn next(&mut self) -> Result<Option<KeyValue>, NdbError> {
+        DbIterator::next(self).await
+    }
+}
+
+/// Called with a key and every conflicting value for it, in iterator order.
+/// Returning `None` drops the key from the output.
+pub type Resolver = Arc<dyn Fn(&[u8], Vec<Vec<u8>>) -> Option<Vec<u8>> + Send + Sync>;
+
+/// How [`MergeIterator`] picks a value when several of its inputs have the
+/// same key.
+#[derive(Clone)]
+pub enum ConflictResolution {
+    /// The value from the earliest iterator in the list wins.
+    FirstWins,
+    /// The value from the latest iterator in the list wins.
+    LastWins,
+    Custom(Resolver),
+}
+
+/// Merges iterators from several databases, e.g. shards, or a base bundle and
+/// an overlay, into one sorted stream. The iterators must all go in the same
+/// direction, and the merged stream goes that way too.
+pub struct MergeIterator {
+    merged: MergingSources<DbIterator>,
+    resolution: ConflictResolution,
+}
+
+impl MergeIterator {
+    /// # Panics
+    ///
+    /// If some of `iters` are reversed and some aren't.
+    pub fn new(iters: Vec<DbIterator>, resolution: ConflictResolution) -> MergeIterator {
+        let reverse = iters.first().is_some_and(DbIterator::is_reverse);
+        assert!(
+            iters.iter().all(|iter| iter.is_reverse() == reverse),
+            "can't merge forward and reverse iterators"
+        );
+        MergeIterator {
+            merged: MergingSources::new(iters, reverse),
+            resolution,
+        }
+    }
+
+    pub async fn next(&mut self) -> Result<Option<KeyValue>, NdbError> {
+        while let Some((key, mut values)) = self.merged.next().await? {
+            let value = match &self.resolution {
+                ConflictResolution::FirstWins => Some(values.swap_remove(0)),
+                ConflictResolution::LastWins => values.pop(),
+                ConflictResolution::Custom(_) if values.len() == 1 => values.pop(),
+                ConflictResolution::Custom(resolve) => resolve(&key, values),
+            };
+            if let Some(value) = value {
+                return Ok(Some((key, value)));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,611 @@
+// An encoding of serde values whose byte order matches the values' own
+// order, for keys. It isn't self-describing, so keys have to be decoded as
+// the type they were encoded from.
+//
+// Integers are big endian, with the sign bit flipped for signed ones, and
+// floats have their sign bit flipped, or every bit if they're negative. Chars
+// are their u32. Strings and byte strings end in 0x00 0x00, with any 0x00 in
+// them escaped as 0x00 0xff, so a string sorts before any longer one it's a
+// prefix of. Sequences and maps put 0x01 before each element and 0x00 after
+// the last. Options are 0x00 for `None` and 0x01 before the value for `Some`.
+// Enums start with their variant's index, as a u32. Structs and tuples are
+// just their fields, one after another.
+
+use std::fmt::{self, Display};
+
+use serde::{
+    de::{
+        self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+        SeqAccess, VariantAccess, Visitor,
+    },
+    ser::{self, Serialize},
+};
+
+use crate::NdbError;
+
+pub(crate) fn encode<T: Serialize + ?Sized>(value: &T, out: &mut Vec<u8>) -> Result<(), NdbError> {
+    value
+        .serialize(&mut Encoder { out })
+        .map_err(|err| NdbError::InvalidArgument(format!("can't encode key: {}", err)))
+}
+
+// Decodes all of `bytes`.
+pub(crate) fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, NdbError> {
+    let mut decoder = Decoder { input: bytes };
+    let decoded = T::deserialize(&mut decoder).and_then(|value| match decoder.input {
+        [] => Ok(value),
+        _ => Err(Error("trailing bytes".into())),
+    });
+    decoded.map_err(|err| NdbError::corruption(format!("can't decode key: {}", err)))
+}
+
+// Encodes `bytes` as a byte string on its own, without serde.
+pub(crate) fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    Encoder { out }.escaped(bytes);
+}
+
+// Decodes the byte string `bytes` starts with, returning it and the rest of
+// `bytes`.
+pub(crate) fn decode_bytes(bytes: &[u8]) -> Result<(Vec<u8>, &[u8]), NdbError> {
+    let mut decoder = Decoder { input: bytes };
+    let decoded = decoder
+        .escaped()
+        .map_err(|err| NdbError::corruption(format!("can't decode key: {}", err)))?;
+    Ok((decoded, decoder.input))
+}
+
+#[derive(Debug)]
+struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Error {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Error {
+        Error(msg.to_string())
+    }
+}
+
+const END: u8 = 0x00;
+const MORE: u8 = 0x01;
+const ESCAPE: u8 = 0xff;
+
+struct Encoder<'a> {
+    out: &'a mut Vec<u8>,
+}
+
+impl Encoder<'_> {
+    fn escaped(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.out.push(byte);
+            if byte == 0 {
+                self.out.push(ESCAPE);
+            }
+        }
+        self.out.extend_from_slice(&[0, END]);
+    }
+}
+
+macro_rules! encode_ints {
+    ($($method:ident: $ty:ty => $flip:expr),*) => {$(
+        fn $method(self, v: $ty) -> Result<(), Error> {
+            self.out.extend_from_slice(&(v ^ $flip).to_be_bytes());
+            Ok(())
+        }
+    )*};
+}
+
+impl<'a> ser::Serializer for &mut Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    encode_ints!(
+        serialize_u8: u8 => 0,
+        serialize_u16: u16 => 0,
+        serialize_u32: u32 => 0,
+        serialize_u64: u64 => 0,
+        serialize_u128: u128 => 0,
+        serialize_i8: i8 => i8::MIN,
+        serialize_i16: i16 => i16::MIN,
+        serialize_i32: i32 => i32::MIN,
+        serialize_i64: i64 => i64::MIN,
+        serialize_i128: i128 => i128::MIN
+    );
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.out.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        let bits = v.to_bits();
+        let flip = if bits >> 31 == 1 { u32::MAX } else { 1 << 31 };
+        self.serialize_u32(bits ^ flip)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        let bits = v.to_bits();
+        let flip = if bits >> 63 == 1 { u64::MAX } else { 1 << 63 };
+        self.serialize_u64(bits ^ flip)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.escaped(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.escaped(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.out.push(END);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        self.out.push(MORE);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+    ) -> Result<(), Error> {
+        self.serialize_u32(index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.serialize_u32(index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, Error> {
+        self.serialize_u32(index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, Error> {
+        self.serialize_u32(index)?;
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut Encoder<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.out.push(MORE);
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.out.push(END);
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut Encoder<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.out.push(MORE);
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.out.push(END);
+        Ok(())
+    }
+}
+
+macro_rules! encode_fields {
+    ($($trait:ident::$method:ident($($name:ident)?)),*) => {$(
+        impl ser::$trait for &mut Encoder<'_> {
+            type Ok = ();
+            type Error = Error;
+
+            fn $method<T: Serialize + ?Sized>(
+                &mut self,
+                $($name: &'static str,)?
+                value: &T,
+            ) -> Result<(), Error> {
+                $(let _ = $name;)?
+                value.serialize(&mut **self)
+            }
+
+            fn end(self) -> Result<(), Error> {
+                Ok(())
+            }
+        }
+    )*};
+}
+
+encode_fields!(
+    SerializeTuple::serialize_element(),
+    SerializeTupleStruct::serialize_field(),
+    SerializeTupleVariant::serialize_field(),
+    SerializeStruct::serialize_field(name),
+    SerializeStructVariant::serialize_field(name)
+);
+
+struct Decoder<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> Decoder<'de> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        if self.input.len() < N {
+            return Err(Error("key ends early".into()));
+        }
+        let (taken, rest) = self.input.split_at(N);
+        self.input = rest;
+        Ok(taken.try_into().unwrap())
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    // Whether another element follows, in a sequence or an option.
+    fn more(&mut self) -> Result<bool, Error> {
+        match self.byte()? {
+            END => Ok(false),
+            MORE => Ok(true),
+            tag => Err(Error(format!("bad tag {:#x}", tag))),
+        }
+    }
+
+    fn escaped(&mut self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.byte()? {
+                0 => match self.byte()? {
+                    END => return Ok(bytes),
+                    ESCAPE => bytes.push(0),
+                    byte => return Err(Error(format!("bad escape {:#x}", byte))),
+                },
+                byte => bytes.push(byte),
+            }
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.take()?))
+    }
+}
+
+macro_rules! decode_ints {
+    ($($method:ident: $ty:ident => $visit:ident, $flip:expr),*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.$visit($ty::from_be_bytes(self.take()?) ^ $flip)
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for &mut Decoder<'de> {
+    type Error = Error;
+
+    decode_ints!(
+        deserialize_u8: u8 => visit_u8, 0,
+        deserialize_u16: u16 => visit_u16, 0,
+        deserialize_u32: u32 => visit_u32, 0,
+        deserialize_u64: u64 => visit_u64, 0,
+        deserialize_u128: u128 => visit_u128, 0,
+        deserialize_i8: i8 => visit_i8, i8::MIN,
+        deserialize_i16: i16 => visit_i16, i16::MIN,
+        deserialize_i32: i32 => visit_i32, i32::MIN,
+        deserialize_i64: i64 => visit_i64, i64::MIN,
+        deserialize_i128: i128 => visit_i128, i128::MIN
+    );
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+        Err(Error("keys can only be decoded as a known type".into()))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.byte()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            byte => Err(Error(format!("bad bool {:#x}", byte))),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let bits = self.u32()?;
+        let flip = if bits >> 31 == 1 { 1 << 31 } else { u32::MAX };
+        visitor.visit_f32(f32::from_bits(bits ^ flip))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let bits = self.u64()?;
+        let flip = if bits >> 63 == 1 { 1 << 63 } else { u64::MAX };
+        visitor.visit_f64(f64::from_bits(bits ^ flip))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let c = self.u32()?;
+        let c = char::from_u32(c).ok_or_else(|| Error(format!("bad char {:#x}", c)))?;
+        visitor.visit_char(c)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let s = String::from_utf8(self.escaped()?).map_err(|err| Error(err.to_string()))?;
+        visitor.visit_string(s)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_byte_buf(self.escaped()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.more()? {
+            false => visitor.visit_none(),
+            true => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Elements {
+            decoder: self,
+            left: None,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Elements {
+            decoder: self,
+            left: Some(len),
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(Elements {
+            decoder: self,
+            left: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+        Err(Error("keys don't hold names".into()))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// The elements of a sequence or map, which ends with `END`, or of a tuple or
+// struct, which has `left` of them.
+struct Elements<'a, 'de> {
+    decoder: &'a mut Decoder<'de>,
+    left: Option<usize>,
+}
+
+impl Elements<'_, '_> {
+    fn more(&mut self) -> Result<bool, Error> {
+        match &mut self.left {
+            Some(0) => Ok(false),
+            Some(left) => {
+                *left -= 1;
+                Ok(true)
+            }
+            None => self.decoder.more(),
+        }
+    }
+}
+
+impl<'de> SeqAccess<'de> for Elements<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.more()? {
+            true => seed.deserialize(&mut *self.decoder).map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for Elements<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.more()? {
+            true => seed.deserialize(&mut *self.decoder).map(Some),
+            false => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.decoder)
+    }
+}
+
+impl<'de> EnumAccess<'de> for &mut Decoder<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let index = self.u32()?;
+        let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(index))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for &mut Decoder<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
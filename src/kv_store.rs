@@ -0,0 +1,165 @@
+use std::{future::Future, ops::Bound};
+
+use futures::{
+    future::{self, BoxFuture},
+    stream::{self, BoxStream},
+    FutureExt, StreamExt, TryFutureExt,
+};
+
+use crate::{Db, DbIterator, DbView, KeyValue, MergeIterator, NdbError, ShardedDb, WriteBatch};
+
+/// The basic key-value operations as an object-safe trait, for code that
+/// wants to hold a `Box<dyn DynKvStore>`, e.g. to swap in a mock in tests.
+/// Implemented for [`Db`], for [`ShardedDb`], for [`DbView`], whose writes
+/// fail with [`NdbError::ReadOnly`], and for references and smart pointers to
+/// any implementation.
+pub trait DynKvStore: Send + Sync {
+    fn get<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<Option<Vec<u8>>, NdbError>>;
+
+    fn put<'a>(&'a self, key: &'a [u8], value: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>>;
+
+    fn delete<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>>;
+
+    fn write(&self, batch: WriteBatch) -> BoxFuture<'_, Result<(), NdbError>>;
+
+    /// The keys between `start` and `end`, in ascending order.
+    fn scan(
+        &self,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> BoxStream<'_, Result<KeyValue, NdbError>>;
+}
+
+fn stream_iter<'a>(
+    iter: impl Future<Output = Result<DbIterator, NdbError>> + Send + 'a,
+) -> BoxStream<'a, Result<KeyValue, NdbError>> {
+    let entries = |iter| {
+        stream::try_unfold(iter, |mut iter: DbIterator| async move {
+            Ok(iter.next().await?.map(|entry| (entry, iter)))
+        })
+    };
+    iter.map_ok(entries).try_flatten_stream().boxed()
+}
+
+impl DynKvStore for Db {
+    fn get<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<Option<Vec<u8>>, NdbError>> {
+        Db::get(self, key).boxed()
+    }
+
+    fn put<'a>(&'a self, key: &'a [u8], value: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        Db::put(self, key, value).boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        Db::delete(self, key).boxed()
+    }
+
+    fn write(&self, batch: WriteBatch) -> BoxFuture<'_, Result<(), NdbError>> {
+        Db::write(self, batch).boxed()
+    }
+
+    fn scan(
+        &self,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> BoxStream<'_, Result<KeyValue, NdbError>> {
+        stream_iter(Db::scan(self, (start, end)))
+    }
+}
+
+impl DynKvStore for ShardedDb {
+    fn get<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<Option<Vec<u8>>, NdbError>> {
+        ShardedDb::get(self, key).boxed()
+    }
+
+    fn put<'a>(&'a self, key: &'a [u8], value: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        ShardedDb::put(self, key, value).boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        ShardedDb::delete(self, key).boxed()
+    }
+
+    fn write(&self, batch: WriteBatch) -> BoxFuture<'_, Result<(), NdbError>> {
+        ShardedDb::write(self, batch).boxed()
+    }
+
+    fn scan(
+        &self,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> BoxStream<'_, Result<KeyValue, NdbError>> {
+        let entries = |iter| {
+            stream::try_unfold(iter, |mut iter: MergeIterator| async move {
+                Ok(iter.next().await?.map(|entry| (entry, iter)))
+            })
+        };
+        let iter = ShardedDb::scan(self, (start, end));
+        iter.map_ok(entries).try_flatten_stream().boxed()
+    }
+}
+
+impl DynKvStore for DbView {
+    fn get<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<Option<Vec<u8>>, NdbError>> {
+        DbView::get(self, key).boxed()
+    }
+
+    fn put<'a>(&'a self, _: &'a [u8], _: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        future::ready(Err(NdbError::ReadOnly)).boxed()
+    }
+
+    fn delete<'a>(&'a self, _: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        future::ready(Err(NdbError::ReadOnly)).boxed()
+    }
+
+    fn write(&self, _: WriteBatch) -> BoxFuture<'_, Result<(), NdbError>> {
+        future::ready(Err(NdbError::ReadOnly)).boxed()
+    }
+
+    fn scan(
+        &self,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> BoxStream<'_, Result<KeyValue, NdbError>> {
+        stream_iter(DbView::scan(self, (start, end)))
+    }
+}
+
+macro_rules! forward {
+    ($($ptr:ty),*) => {$(
+        impl<T: DynKvStore + ?Sized> DynKvStore for $ptr {
+            fn get<'a>(
+                &'a self,
+                key: &'a [u8],
+            ) -> BoxFuture<'a, Result<Option<Vec<u8>>, NdbError>> {
+                (**self).get(key)
+            }
+
+            fn put<'a>(
+                &'a self,
+                key: &'a [u8],
+                value: &'a [u8],
+            ) -> BoxFuture<'a, Result<(), NdbError>> {
+                (**self).put(key, value)
+            }
+
+            fn delete<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+                (**self).delete(key)
+            }
+
+            fn write(&self, batch: WriteBatch) -> BoxFuture<'_, Result<(), NdbError>> {
+                (**self).write(batch)
+            }
+
+            fn scan(
+                &self,
+                start: Bound<Vec<u8>>,
+                end: Bound<Vec<u8>>,
+            ) -> BoxStream<'_, Result<KeyValue, NdbError>> {
+                (**self).scan(start, end)
+            }
+        }
+    )*};
+}
+
+forward!(&T, Box<T>, std::sync::Arc<T>);
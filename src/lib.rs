@@ -0,0 +1,129 @@
+mod arena;
+mod backup;
+mod batch;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+mod block;
+mod bloom;
+mod changes;
+mod clock;
+mod coding;
+mod compression;
+mod conflict;
+mod consumers;
+mod counter;
+mod cursor;
+mod db;
+mod direct;
+mod error;
+mod files;
+mod format_spec;
+#[cfg(feature = "fuzz")]
+#[doc(hidden)]
+pub mod fuzz;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod handles;
+#[doc(hidden)]
+pub mod hex;
+#[cfg(feature = "http")]
+pub mod http;
+mod index;
+mod ingest;
+mod inspect;
+mod iter;
+mod key_codec;
+mod kv_store;
+mod locks;
+mod log;
+mod manifest;
+mod mem_storage;
+mod memtable;
+mod merge;
+pub mod metrics;
+mod mmap;
+#[cfg(feature = "object-store")]
+mod object_storage;
+mod pread;
+mod range_del;
+mod rate_limiter;
+mod repair;
+mod secondary_index;
+mod shard;
+mod sim_storage;
+mod sstable;
+mod stats;
+mod storage;
+mod table;
+mod table_cache;
+mod tables;
+mod tier;
+mod transaction;
+mod transform;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+mod vacuum;
+mod validation;
+mod value;
+mod verify;
+mod version;
+mod view;
+mod watchdog;
+mod write_buffer;
+mod write_stall;
+
+pub use backup::{BackupEngine, BackupInfo};
+pub use batch::{BatchOp, PreparedToken, WriteBatch, WriteOptions};
+pub use changes::{Update, WatchEvent};
+pub use clock::{Clock, ClockSkewAction, ManualClock, SystemClock};
+pub use compression::Compression;
+pub use counter::counter_merge_operator;
+pub use cursor::ScanToken;
+pub use db::{Db, DbOptions, MayExist};
+pub use error::NdbError;
+pub use files::{CorruptBlock, FileKind, LiveFile};
+pub use format_spec::format_spec;
+pub use handles::{HandleKind, HandleStats, HandleWarning, OpenHandle};
+pub use ingest::{SstInfo, SstWriter};
+pub use inspect::{
+    inspect_sst, BlockLocation, FilterReport, SstBlock, SstRecord, SstReport, SstValue,
+};
+pub use iter::{ConflictResolution, DbIterator, MergeIterator, Resolver};
+pub use kv_store::DynKvStore;
+pub use log::WalRecovery;
+pub use mem_storage::MemStorage;
+pub use merge::MergeOperator;
+pub use mmap::{MmapAdvice, MmapOptions};
+#[cfg(feature = "object-store")]
+pub use object_storage::{ObjectStorage, ObjectStorageOptions};
+pub use rate_limiter::RateLimiter;
+pub use repair::RepairReport;
+pub use secondary_index::{IndexExtractor, IndexIterator, SecondaryIndex};
+pub use shard::ShardedDb;
+pub use sim_storage::{SimFaults, SimStorage};
+pub use stats::{
+    ConflictStats, DbStats, IoStats, LevelStats, MemtableStats, OpenStats, TableOpenTiming,
+    TierStats, WriteCounters, WriteStallStats, WriteStats,
+};
+pub use storage::{LocalStorage, ReadableFile, Storage, WritableFile};
+pub use table::{Table, TableIterator};
+pub use tier::{ColdTier, StorageTier};
+pub use transaction::Transaction;
+pub use transform::ValueTransform;
+pub use vacuum::VacuumOptions;
+pub use validation::{ValidationError, Validator};
+pub use verify::{VerifyProblem, VerifyReport};
+pub use version::FORMAT_VERSION;
+pub use view::DbView;
+pub use watchdog::{JobKind, StallAction, StallReport, WatchdogOptions};
+pub use write_buffer::WriteBufferManager;
+pub use write_stall::{WriteStallOptions, WriteStallReason, WriteStallState};
+
+pub type KeyValue = (Vec<u8>, Vec<u8>);
+
+// Returns `Some(Value::Delete)` if the key was deleted, so callers know to
+// stop looking in older places.
+trait Queryable {
+    async fn get(&self, key: &[u8]) -> Result<Option<value::Value>, NdbError>;
+}
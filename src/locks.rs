@@ -0,0 +1,72 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{sync::Notify, time::Instant};
+
+use crate::NdbError;
+
+// Exclusive per-key locks for transactions. There's no deadlock detection:
+// a transaction that waits too long for a lock gives up, which also breaks
+// any cycle it was part of.
+pub(crate) struct KeyLocks {
+    // Who holds each locked key.
+    held: Mutex<HashMap<Vec<u8>, u64>>,
+    released: Notify,
+    next_owner: AtomicU64,
+    timeout: Duration,
+}
+
+impl KeyLocks {
+    pub(crate) fn new(timeout: Duration) -> KeyLocks {
+        KeyLocks {
+            held: Mutex::new(HashMap::new()),
+            released: Notify::new(),
+            next_owner: AtomicU64::new(0),
+            timeout,
+        }
+    }
+
+    pub(crate) fn new_owner(&self) -> u64 {
+        self.next_owner.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // Takes the lock on `key` for `owner`, waiting a while for whoever holds
+    // it now. Taking a lock that's already held by `owner` is a no-op.
+    // Returns whether the lock was newly taken.
+    pub(crate) async fn lock(&self, key: &[u8], owner: u64) -> Result<bool, NdbError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            // Register for wakeups before looking, so a release in between
+            // isn't missed.
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            match self.held.lock().unwrap().entry(key.to_vec()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(owner);
+                    return Ok(true);
+                }
+                Entry::Occupied(entry) if *entry.get() == owner => return Ok(false),
+                Entry::Occupied(_) => {}
+            }
+            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                return Err(NdbError::LockTimeout);
+            }
+        }
+    }
+
+    pub(crate) fn unlock<'a>(&self, keys: impl IntoIterator<Item = &'a Vec<u8>>) {
+        let mut held = self.held.lock().unwrap();
+        for key in keys {
+            held.remove(key);
+        }
+        self.released.notify_waiters();
+    }
+}
@@ -0,0 +1,218 @@
+use std::path::Path;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use crate::uring::Ring;
+use crate::{metrics, pread::FileIo, version, NdbError, Storage, WritableFile};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) enum LogEntry {
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    // `expires_at` is in unix milliseconds.
+    PutUntil {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        expires_at: u64,
+    },
+    Delete {
+        key: Vec<u8>,
+    },
+    DeleteRange {
+        start: Vec<u8>,
+        end: Vec<u8>,
+    },
+    Merge {
+        key: Vec<u8>,
+        operand: Vec<u8>,
+    },
+    // Several of the above, written as one line so they're replayed all
+    // together or not at all.
+    Batch(Vec<LogEntry>),
+    // A batch prepared with `Db::prepare`, which isn't applied until it's
+    // committed. `id` is its token.
+    Prepare {
+        id: u64,
+        ops: Vec<LogEntry>,
+    },
+    // The writes of prepared batch `id`, applied like a `Batch`.
+    Commit {
+        id: u64,
+        ops: Vec<LogEntry>,
+    },
+    // Prepared batch `id`, dropped without being applied.
+    Rollback {
+        id: u64,
+    },
+}
+
+impl LogEntry {
+    // The individual writes in this entry.
+    pub(crate) fn ops(&self) -> &[LogEntry] {
+        match self {
+            LogEntry::Batch(entries) | LogEntry::Commit { ops: entries, .. } => entries,
+            // Nothing's written until it's committed.
+            LogEntry::Prepare { .. } | LogEntry::Rollback { .. } => &[],
+            entry => std::slice::from_ref(entry),
+        }
+    }
+
+    pub(crate) fn has_merge(&self) -> bool {
+        self.ops()
+            .iter()
+            .any(|op| matches!(op, LogEntry::Merge { .. }))
+    }
+
+    // The key and value a write is counted against in the stats. Deleting a
+    // range counts as deleting its start key.
+    pub(crate) fn stats_key(&self) -> (&[u8], Option<&[u8]>) {
+        match self {
+            LogEntry::Put { key, value }
+            | LogEntry::PutUntil { key, value, .. }
+            | LogEntry::Merge {
+                key,
+                operand: value,
+            } => (key, Some(value)),
+            LogEntry::Delete { key } => (key, None),
+            LogEntry::DeleteRange { start, .. } => (start, None),
+            LogEntry::Batch(_)
+            | LogEntry::Prepare { .. }
+            | LogEntry::Commit { .. }
+            | LogEntry::Rollback { .. } => unreachable!("stats are recorded per op"),
+        }
+    }
+}
+
+/// How opening a database treats log records that are corrupt, or were only
+/// partly written before a crash. Only the memtable's logs are replayed, so
+/// this doesn't affect logs that have already been flushed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WalRecovery {
+    /// Drop corrupt records at the end of the newest log, which is what a
+    /// write cut off by a crash leaves behind, and cut the log back to before
+    /// them. Corruption anywhere else fails the open.
+    #[default]
+    TolerateCorruptedTailRecords,
+    /// Fail the open on any corrupt or partly written record.
+    AbsoluteConsistency,
+    /// Drop every corrupt record, wherever it is, and carry on replaying the
+    /// rest. Writes in the dropped records are lost, even if they were
+    /// acknowledged.
+    SkipAnyCorruptedRecords,
+}
+
+// A line of the log: an entry and the sequence number it was committed at.
+// Logs written before sequence numbers were have none, so read as zero.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LogRecord<E> {
+    #[serde(default)]
+    pub(crate) seq: u64,
+    #[serde(flatten)]
+    pub(crate) entry: E,
+}
+
+pub(crate) struct Log {
+    log: Box<dyn WritableFile>,
+    // How many bytes have been written to the file, including by earlier
+    // opens.
+    size: u64,
+    // The ring to append through, and the file again for it to write to,
+    // if the database has one and the log is a local file. `log` is then
+    // only used for the header.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    uring: Option<(Arc<Ring>, Arc<std::fs::File>)>,
+}
+
+impl Log {
+    // Opens the log at `path` to append to, starting it with a header if
+    // it's new.
+    #[cfg_attr(
+        not(all(feature = "io-uring", target_os = "linux")),
+        allow(unused_variables)
+    )]
+    pub(crate) async fn open(
+        storage: &dyn Storage,
+        path: impl AsRef<Path>,
+        file_io: &FileIo,
+    ) -> Result<Log, NdbError> {
+        let path = path.as_ref();
+        let mut log = storage.append(path).await?;
+        let mut size = storage.size(path).await?;
+        if size == 0 {
+            let header = version::header_line();
+            log.write(&header).await?;
+            log.sync().await?;
+            // A new log is about to be recorded as where writes go, so it has
+            // to be there after a crash.
+            if let Some(dir) = path.parent() {
+                storage.sync_dir(dir).await?;
+            }
+            size = header.len() as u64;
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        let uring = match file_io {
+            FileIo::Uring(ring) if storage.is_local() => {
+                let file = std::fs::OpenOptions::new().append(true).open(path)?;
+                Some((ring.clone(), Arc::new(file)))
+            }
+            _ => None,
+        };
+        Ok(Log {
+            log,
+            size,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            uring,
+        })
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    // Writes all of `records`, and then syncs once if `sync` is set.
+    // Otherwise they're only handed to the OS.
+    pub(crate) async fn append(
+        &mut self,
+        records: impl IntoIterator<Item = LogRecord<&LogEntry>>,
+        sync: bool,
+    ) -> Result<(), NdbError> {
+        // All in one write. The file is opened to append, so it lands at the
+        // end even if an append before it was cancelled partway.
+        let mut buf = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut buf, &record)?;
+            buf.push(b'\n');
+        }
+        let len = buf.len() as u64;
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some((ring, file)) = &self.uring {
+            ring.write_at(file, self.size, buf).await?;
+            self.size += len;
+            if sync {
+                self.sync().await?;
+            }
+            return Ok(());
+        }
+        self.log.write(&buf).await?;
+        self.log.flush().await?;
+        self.size += len;
+        if sync {
+            self.sync().await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn sync(&mut self) -> Result<(), NdbError> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some((ring, file)) = &self.uring {
+            metrics::timed(metrics::WAL_SYNC_SECONDS, ring.sync(file)).await?;
+            return Ok(());
+        }
+        metrics::timed(metrics::WAL_SYNC_SECONDS, self.log.sync()).await
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{DbMeta, RetainedWal},
+    sstable::SSTableMetadata,
+    storage::{self, remove_if_exists, write_synced},
+    version, NdbError, Storage, WritableFile,
+};
+
+// A database's tables and logs are recorded in a MANIFEST file: one JSON
+// array of edits per line, applied in order, each line written in one go so
+// that a change is recorded all together or not at all, after a header with
+// its format version. The first edits in a manifest are a snapshot of the
+// whole database. CURRENT holds the name of
+// the manifest in use, and is replaced atomically when a new one is started.
+//
+// Databases written before there was a manifest kept the same information
+// in meta.json and a .meta file next to each table. They're still read, and
+// moved over to a manifest the next time they're opened for writing.
+
+#[derive(Serialize, Deserialize)]
+pub(crate) enum VersionEdit {
+    AddTable(SSTableMetadata),
+    // A table's data file.
+    DeleteTable(PathBuf),
+    // A new log, or a change to the older ones.
+    Wals {
+        wal: PathBuf,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        wal_segments: Vec<RetainedWal>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        retained_wals: Vec<RetainedWal>,
+        #[serde(default)]
+        pruned_through: u64,
+    },
+}
+
+// meta.json, from before the manifest.
+#[derive(Deserialize)]
+struct LegacyMeta {
+    // The tables' .meta files.
+    sstables: Vec<PathBuf>,
+    wal: PathBuf,
+    #[serde(default)]
+    wal_segments: Vec<RetainedWal>,
+    #[serde(default)]
+    retained_wals: Vec<RetainedWal>,
+    #[serde(default)]
+    pruned_through: u64,
+}
+
+// Appends to a database's manifest.
+pub(crate) struct ManifestWriter {
+    storage: Arc<dyn Storage>,
+    dir: PathBuf,
+    file: Box<dyn WritableFile>,
+    size: u64,
+    max_bytes: u64,
+    // Whether a record failed partway through, leaving part of a line that
+    // whatever was appended next would be joined onto.
+    torn: bool,
+}
+
+impl ManifestWriter {
+    // Starts a new manifest holding `meta`, and points CURRENT at it. The old
+    // manifest, or the files from before there was one, are deleted once
+    // it's no longer current.
+    pub(crate) async fn create(
+        storage: &Arc<dyn Storage>,
+        dir: &Path,
+        meta: &DbMeta,
+        max_bytes: u64,
+    ) -> Result<ManifestWriter, NdbError> {
+        let fs = &**storage;
+        let old = current(fs, dir).await?;
+        let number = old.as_deref().map_or(0, number_of) + 1;
+        let name = manifest_name(number);
+        let mut contents = version::header_line();
+        contents.extend(serde_json::to_vec(&snapshot(meta))?);
+        contents.push(b'\n');
+        write_synced(fs, &dir.join(&name), &contents).await?;
+
+        let tmp = dir.join("CURRENT.tmp");
+        write_synced(fs, &tmp, format!("{}\n", name).as_bytes()).await?;
+        // CURRENT can't be left naming a manifest that didn't survive a crash.
+        fs.sync_dir(dir).await?;
+        fs.rename(&tmp, &dir.join("CURRENT")).await?;
+        // The old manifest can only go once nothing can bring back a CURRENT
+        // naming it.
+        fs.sync_dir(dir).await?;
+
+        match old {
+            Some(old) => remove_if_exists(fs, &dir.join(old)).await?,
+            None => {
+                remove_if_exists(fs, &dir.join("meta.json")).await?;
+                for table in &meta.sstables {
+                    remove_if_exists(fs, &table.data_path.with_extension("meta")).await?;
+                }
+            }
+        }
+
+        let file = fs.append(&dir.join(&name)).await?;
+        Ok(ManifestWriter {
+            storage: storage.clone(),
+            dir: dir.into(),
+            file,
+            size: contents.len() as u64,
+            max_bytes,
+            torn: false,
+        })
+    }
+
+    // Records the changes from `old` to `new`, durably. Once the manifest
+    // has grown past its limit, or a record to it has failed, a new one is
+    // started instead.
+    pub(crate) async fn record(&mut self, old: &DbMeta, new: &DbMeta) -> Result<(), NdbError> {
+        let edits = edits(old, new);
+        if edits.is_empty() {
+            return Ok(());
+        }
+        if self.size >= self.max_bytes || self.torn {
+            *self = ManifestWriter::create(&self.storage, &self.dir, new, self.max_bytes).await?;
+            return Ok(());
+        }
+        let mut line = serde_json::to_vec(&edits)?;
+        line.push(b'\n');
+        self.torn = true;
+        self.file.write(&line).await?;
+        self.file.sync().await?;
+        self.torn = false;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+// Whether `dir` holds a database.
+pub(crate) async fn exists(storage: &dyn Storage, dir: &Path) -> Result<bool, NdbError> {
+    Ok(storage.exists(&dir.join("CURRENT")).await?
+        || storage.exists(&dir.join("meta.json")).await?)
+}
+
+// Reads the database in `dir`, or returns `None` if there isn't one.
+pub(crate) async fn load(storage: &dyn Storage, dir: &Path) -> Result<Option<DbMeta>, NdbError> {
+    let Some(name) = current(storage, dir).await? else {
+        return load_legacy(storage, dir).await;
+    };
+    let path = dir.join(name);
+    let contents = storage.read(&path).await?;
+    let mut meta = DbMeta::new(dir.join("log"));
+    replay(&contents, &path, &mut meta, false)?;
+    Ok(Some(meta))
+}
+
+// Reads as much of the metadata in `dir` as can be read, for repairing the
+// database: the manifest CURRENT names, or failing that the newest one there,
+// up to its first corrupt record, or else meta.json. `None` if none of it can
+// be read.
+pub(crate) async fn salvage(storage: &dyn Storage, dir: &Path) -> Result<Option<DbMeta>, NdbError> {
+    let mut names: Vec<String> = storage
+        .list(dir)
+        .await?
+        .iter()
+        .filter_map(|path| path.file_name()?.to_str())
+        .filter(|name| name.starts_with("MANIFEST-"))
+        .map(String::from)
+        .collect();
+    names.sort_by_key(|name| std::cmp::Reverse(number_of(name)));
+    if let Ok(Some(current)) = current(storage, dir).await {
+        names.insert(0, current);
+    }
+    for name in names {
+        let path = dir.join(name);
+        let Ok(contents) = storage.read(&path).await else {
+            continue;
+        };
+        let mut meta = DbMeta::new(dir.join("log"));
+        if replay(&contents, &path, &mut meta, true)? > 0 {
+            return Ok(Some(meta));
+        }
+    }
+    Ok(load_legacy(storage, dir).await.ok().flatten())
+}
+
+// Applies the edits in a manifest to `meta`, returning how many lines of them
+// there were. A record that doesn't parse is an error, unless `lenient`, when
+// it's taken to be where the readable part of the manifest ends.
+fn replay(
+    contents: &[u8],
+    path: &Path,
+    meta: &mut DbMeta,
+    lenient: bool,
+) -> Result<usize, NdbError> {
+    let (mut offset, mut applied) = (0, 0);
+    // A last line without its newline was cut off partway through being
+    // written, so the change it held never happened.
+    for line in contents.split_inclusive(|&b| b == b'\n') {
+        let Some(line) = line.strip_suffix(b"\n") else {
+            break;
+        };
+        // Manifests from before format versions have no header.
+        if offset == 0 && version::parse_header(line, path)?.is_some() {
+            offset += line.len() as u64 + 1;
+            continue;
+        }
+        let edits: Vec<VersionEdit> = match serde_json::from_slice(line) {
+            Ok(edits) => edits,
+            Err(_) if lenient => break,
+            Err(err) => {
+                return Err(
+                    NdbError::corruption(format!("bad manifest record: {}", err))
+                        .in_file(path)
+                        .at_offset(offset),
+                )
+            }
+        };
+        for edit in edits {
+            apply(meta, edit);
+        }
+        offset += line.len() as u64 + 1;
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+// The files holding the database's metadata.
+pub(crate) async fn files(
+    storage: &dyn Storage,
+    dir: &Path,
+    meta: &DbMeta,
+) -> Result<Vec<PathBuf>, NdbError> {
+    if let Some(name) = current(storage, dir).await? {
+        return Ok(vec![dir.join("CURRENT"), dir.join(name)]);
+    }
+    let mut files = vec![dir.join("meta.json")];
+    for table in &meta.sstables {
+        files.push(table.data_path.with_extension("meta"));
+    }
+    Ok(files)
+}
+
+// The database's metadata as it is on disk, for noticing when another process
+// changes it.
+pub(crate) async fn fingerprint(storage: &dyn Storage, dir: &Path) -> Option<Vec<u8>> {
+    match current(storage, dir).await.ok()? {
+        Some(name) => {
+            let mut contents = storage.read(&dir.join(&name)).await.ok()?;
+            contents.extend(name.into_bytes());
+            Some(contents)
+        }
+        None => storage.read(&dir.join("meta.json")).await.ok(),
+    }
+}
+
+async fn load_legacy(storage: &dyn Storage, dir: &Path) -> Result<Option<DbMeta>, NdbError> {
+    let path = dir.join("meta.json");
+    let contents = match storage.read(&path).await {
+        Ok(contents) => contents,
+        Err(err) if storage::is_not_found(&err) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let legacy: LegacyMeta = serde_json::from_slice(&contents)
+        .map_err(|err| NdbError::corruption(err.to_string()).in_file(&path))?;
+    let mut sstables = Vec::new();
+    for meta_path in legacy.sstables {
+        let contents = storage.read(&meta_path).await?;
+        let table = serde_json::from_slice(&contents)
+            .map_err(|err| NdbError::corruption(err.to_string()).in_file(&meta_path))?;
+        sstables.push(table);
+    }
+    Ok(Some(DbMeta {
+        sstables,
+        wal: legacy.wal,
+        wal_segments: legacy.wal_segments,
+        retained_wals: legacy.retained_wals,
+        pruned_through: legacy.pruned_through,
+    }))
+}
+
+// The name of the manifest in use, if there is one.
+async fn current(storage: &dyn Storage, dir: &Path) -> Result<Option<String>, NdbError> {
+    match storage.read(&dir.join("CURRENT")).await {
+        Ok(name) => Ok(Some(String::from_utf8_lossy(&name).trim().to_string())),
+        Err(err) if storage::is_not_found(&err) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn manifest_name(number: u64) -> String {
+    format!("MANIFEST-{:06}", number)
+}
+
+fn number_of(name: &str) -> u64 {
+    name.trim_start_matches("MANIFEST-").parse().unwrap_or(0)
+}
+
+// Every table in `meta`, and its logs.
+pub(crate) fn snapshot(meta: &DbMeta) -> Vec<VersionEdit> {
+    let tables = meta.sstables.iter().cloned().map(VersionEdit::AddTable);
+    tables.chain([wals(meta)]).collect()
+}
+
+fn wals(meta: &DbMeta) -> VersionEdit {
+    VersionEdit::Wals {
+        wal: meta.wal.clone(),
+        wal_segments: meta.wal_segments.clone(),
+        retained_wals: meta.retained_wals.clone(),
+        pruned_through: meta.pruned_through,
+    }
+}
+
+fn edits(old: &DbMeta, new: &DbMeta) -> Vec<VersionEdit> {
+    let has = |meta: &DbMeta, table: &SSTableMetadata| {
+        meta.sstables.iter().any(|t| t.data_path == table.data_path)
+    };
+    let mut edits = Vec::new();
+    for table in &old.sstables {
+        if !has(new, table) {
+            edits.push(VersionEdit::DeleteTable(table.data_path.clone()));
+        }
+    }
+    for table in &new.sstables {
+        if !has(old, table) {
+            edits.push(VersionEdit::AddTable(table.clone()));
+        }
+    }
+    if (
+        &old.wal,
+        &old.wal_segments,
+        &old.retained_wals,
+        old.pruned_through,
+    ) != (
+        &new.wal,
+        &new.wal_segments,
+        &new.retained_wals,
+        new.pruned_through,
+    ) {
+        edits.push(wals(new));
+    }
+    edits
+}
+
+fn apply(meta: &mut DbMeta, edit: VersionEdit) {
+    match edit {
+        VersionEdit::AddTable(table) => meta.sstables.push(table),
+        VersionEdit::DeleteTable(data_path) => meta.sstables.retain(|t| t.data_path != data_path),
+        VersionEdit::Wals {
+            wal,
+            wal_segments,
+            retained_wals,
+            pruned_through,
+        } => {
+            meta.wal = wal;
+            meta.wal_segments = wal_segments;
+            meta.retained_wals = retained_wals;
+            meta.pruned_through = pruned_through;
+        }
+    }
+}
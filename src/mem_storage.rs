@@ -0,0 +1,200 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::{NdbError, ReadableFile, Storage, WritableFile};
+
+// The contents of a file, shared by its name and everything that has it open,
+// so it can still be read and written after it's removed or renamed.
+type Contents = Arc<RwLock<Vec<u8>>>;
+
+/// Files kept in memory, for tests, benchmarks and databases that don't need
+/// to outlive the process. See [`DbOptions::in_memory`](crate::DbOptions::in_memory).
+///
+/// Clones share the same files, so a database can be closed and reopened by
+/// passing the same storage both times. Directories aren't kept: any path can
+/// be created, and a directory lists whatever files are directly in it.
+/// Everything written is durable as soon as it's written, until the last
+/// clone is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct MemStorage {
+    files: Arc<Mutex<BTreeMap<PathBuf, Contents>>>,
+}
+
+impl MemStorage {
+    pub fn new() -> MemStorage {
+        MemStorage::default()
+    }
+
+    /// The bytes held by every file that still has a name.
+    pub fn size_bytes(&self) -> u64 {
+        let files = self.files.lock().unwrap();
+        files
+            .values()
+            .map(|contents| contents.read().unwrap().len() as u64)
+            .sum()
+    }
+
+    fn contents(&self, path: &Path) -> Result<Contents, NdbError> {
+        let files = self.files.lock().unwrap();
+        files.get(path).cloned().ok_or_else(|| not_found(path))
+    }
+}
+
+fn not_found(path: &Path) -> NdbError {
+    NdbError::Io(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found", path.display()),
+    ))
+}
+
+// Writes go straight into the file's contents, so there's nothing to flush.
+struct MemWriter(Contents);
+
+struct MemFile(Contents);
+
+impl Storage for MemStorage {
+    fn create<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        async move {
+            let contents = Contents::default();
+            let mut files = self.files.lock().unwrap();
+            files.insert(path.to_path_buf(), contents.clone());
+            Ok(Box::new(MemWriter(contents)) as Box<dyn WritableFile>)
+        }
+        .boxed()
+    }
+
+    fn append<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        async move {
+            let mut files = self.files.lock().unwrap();
+            let contents = files.entry(path.to_path_buf()).or_default().clone();
+            Ok(Box::new(MemWriter(contents)) as Box<dyn WritableFile>)
+        }
+        .boxed()
+    }
+
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Arc<dyn ReadableFile>, NdbError>> {
+        async move { Ok(Arc::new(MemFile(self.contents(path)?)) as Arc<dyn ReadableFile>) }.boxed()
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            let mut files = self.files.lock().unwrap();
+            let contents = files.remove(from).ok_or_else(|| not_found(from))?;
+            files.insert(to.to_path_buf(), contents);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            let mut files = self.files.lock().unwrap();
+            files
+                .remove(path)
+                .map(|_| ())
+                .ok_or_else(|| not_found(path))
+        }
+        .boxed()
+    }
+
+    fn truncate<'a>(&'a self, path: &'a Path, len: u64) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            let contents = self.contents(path)?;
+            contents.write().unwrap().resize(len as usize, 0);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Vec<PathBuf>, NdbError>> {
+        async move {
+            let files = self.files.lock().unwrap();
+            Ok(files
+                .keys()
+                .filter(|path| path.parent() == Some(dir))
+                .cloned()
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn size<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<u64, NdbError>> {
+        async move { Ok(self.contents(path)?.read().unwrap().len() as u64) }.boxed()
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool, NdbError>> {
+        async move { Ok(self.files.lock().unwrap().contains_key(path)) }.boxed()
+    }
+
+    fn create_dir_all<'a>(&'a self, _dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move { Ok(()) }.boxed()
+    }
+
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, NdbError>> {
+        async move { Ok(self.contents(path)?.read().unwrap().clone()) }.boxed()
+    }
+}
+
+impl WritableFile for MemWriter {
+    fn write<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            self.0.write().unwrap().extend_from_slice(data);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        async move { Ok(()) }.boxed()
+    }
+
+    fn sync(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        async move { Ok(()) }.boxed()
+    }
+}
+
+impl ReadableFile for MemFile {
+    fn read_at(
+        &self,
+        offset: u64,
+        len: usize,
+        mut buf: Vec<u8>,
+    ) -> BoxFuture<'_, Result<Vec<u8>, NdbError>> {
+        async move {
+            let contents = self.0.read().unwrap();
+            let start = offset as usize;
+            let bytes = start
+                .checked_add(len)
+                .and_then(|end| contents.get(start..end))
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "read past the end of the file",
+                    )
+                })?;
+            buf.clear();
+            buf.extend_from_slice(bytes);
+            Ok(buf)
+        }
+        .boxed()
+    }
+
+    fn size(&self) -> BoxFuture<'_, Result<u64, NdbError>> {
+        async move { Ok(self.0.read().unwrap().len() as u64) }.boxed()
+    }
+}
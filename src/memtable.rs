@@ -0,0 +1,491 @@
+use std::{
+    collections::BTreeMap,
+    ops::RangeBounds,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::Bytes;
+use crossbeam_skiplist::SkipMap;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    arena::Arena,
+    log::{LogEntry, LogRecord, WalRecovery},
+    merge::{self, MergeOperator},
+    range_del::RangeTombstones,
+    storage::{self, LineReader},
+    value::Value,
+    version, Clock, NdbError, Storage,
+};
+
+// A write to a key: its sequence number, how many writes came before it in
+// the memtable, which orders writes within a batch, and its encoded value.
+type Version = (u64, u64, Bytes);
+
+// A deleted range, tagged the same way, and its start and end.
+type DeletedRange = (u64, u64, Bytes, Bytes);
+
+// What the skiplist takes for each key besides the entry itself: a
+// reference count and height, and about two pointers in its tower on
+// average.
+const NODE_OVERHEAD: usize = 3 * size_of::<usize>();
+
+// Every version of every key applied, so reads can be as of any sequence
+// number since the memtable was started. Keys are in a concurrent skiplist,
+// each with its own lock on its versions, so writes to different keys don't
+// wait for each other, and reads don't wait for writes at all: they read as
+// of the last sequence number published, so a write that's partway through
+// being applied isn't seen. The keys and values themselves are in an arena.
+pub(crate) struct Memtable {
+    // Each key's versions, oldest first.
+    keys: SkipMap<Bytes, Mutex<Vec<Version>>>,
+    range_history: Mutex<Vec<DeletedRange>>,
+    // How many ranges have been deleted, so that reads only take the lock on
+    // `range_history` if there are any. Counted before the deletes' sequence
+    // numbers are published, so a read as of one always sees it.
+    deleted_ranges: AtomicUsize,
+    merge_operator: Option<MergeOperator>,
+    // For whether a value has expired when a merge is folded into it.
+    clock: Arc<dyn Clock>,
+    arena: Arena,
+    applied: AtomicU64,
+    // The lowest and highest sequence numbers applied, `u64::MAX` and 0
+    // until anything is.
+    lowest: AtomicU64,
+    highest: AtomicU64,
+    // The key and value bytes of everything applied.
+    bytes: AtomicU64,
+    // Memory taken up by everything but the arena: the skiplist's nodes and
+    // the lists of versions.
+    index_bytes: AtomicU64,
+    // Batches prepared in the memtable's logs and not yet committed or
+    // rolled back, by id.
+    prepared: Mutex<BTreeMap<u64, Vec<LogEntry>>>,
+}
+
+impl Memtable {
+    pub(crate) fn new(merge_operator: Option<MergeOperator>, clock: Arc<dyn Clock>) -> Memtable {
+        Memtable {
+            keys: SkipMap::new(),
+            range_history: Mutex::new(Vec::new()),
+            deleted_ranges: AtomicUsize::new(0),
+            merge_operator,
+            clock,
+            arena: Arena::new(),
+            applied: AtomicU64::new(0),
+            lowest: AtomicU64::new(u64::MAX),
+            highest: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            index_bytes: AtomicU64::new(0),
+            prepared: Mutex::default(),
+        }
+    }
+
+    // The batches that are prepared and waiting to be committed or rolled
+    // back, by id.
+    pub(crate) fn prepared(&self) -> BTreeMap<u64, Vec<LogEntry>> {
+        self.prepared.lock().unwrap().clone()
+    }
+
+    // The writes in prepared batch `id`, if it's still waiting.
+    pub(crate) fn prepared_batch(&self, id: u64) -> Option<Vec<LogEntry>> {
+        self.prepared.lock().unwrap().get(&id).cloned()
+    }
+
+    // The lowest and highest sequence numbers applied.
+    pub(crate) fn sequences(&self) -> Option<(u64, u64)> {
+        let lowest = self.lowest.load(Ordering::Acquire);
+        (lowest != u64::MAX).then(|| (lowest, self.highest.load(Ordering::Acquire)))
+    }
+
+    pub(crate) fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    // The memory the memtable has allocated, which is what it holds on to
+    // until it's dropped.
+    pub(crate) fn memory_usage(&self) -> u64 {
+        self.arena.allocated() + self.index_bytes.load(Ordering::Relaxed)
+    }
+
+    // How many keys have been written, including deleted ones.
+    pub(crate) fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    // The newest version of `key` as of sequence number `seq`, which may be
+    // a tombstone.
+    pub(crate) fn lookup(&self, key: &[u8], seq: u64) -> Option<Value> {
+        let entry = self.keys.get(key);
+        let versions = entry.as_ref().map(|entry| entry.value().lock().unwrap());
+        self.newest(key, versions.as_deref().map_or(&[], |v| v), seq)
+    }
+
+    fn newest(&self, key: &[u8], versions: &[Version], seq: u64) -> Option<Value> {
+        let version = versions.iter().rev().find(|(s, _, _)| *s <= seq);
+        let deleted = match self.deleted_ranges.load(Ordering::Acquire) {
+            0 => None,
+            _ => (self.range_history.lock().unwrap().iter().rev())
+                .find(|(s, _, start, end)| *s <= seq && start.as_ref() <= key && key < end.as_ref())
+                .map(|(_, d, _, _)| *d),
+        };
+        match (version, deleted) {
+            (Some((_, n, _)), Some(d)) if d > *n => Some(Value::Delete),
+            (Some((_, _, value)), _) => Some(decode(value)),
+            (None, Some(_)) => Some(Value::Delete),
+            (None, None) => None,
+        }
+    }
+
+    // The keys in `range` as they were as of sequence number `seq`, minus any
+    // that a range deleted since, in order.
+    pub(crate) fn entries(
+        &self,
+        seq: u64,
+        range: &impl RangeBounds<Vec<u8>>,
+    ) -> impl DoubleEndedIterator<Item = (Vec<u8>, Value)> + '_ {
+        let deleted: Vec<_> = (self.range_history.lock().unwrap().iter())
+            .filter(|d| d.0 <= seq)
+            .cloned()
+            .collect();
+        let bound = |bound: std::ops::Bound<&Vec<u8>>| bound.map(|b| Bytes::copy_from_slice(b));
+        let range = (bound(range.start_bound()), bound(range.end_bound()));
+        self.keys.range(range).filter_map(move |entry| {
+            let key = entry.key();
+            let versions = entry.value().lock().unwrap();
+            let (_, n, value) = versions.iter().rev().find(|(s, _, _)| *s <= seq)?;
+            // Deleting a range removes the keys it covers.
+            let covered = deleted
+                .iter()
+                .any(|(_, d, start, end)| d > n && start <= key && key < end);
+            (!covered).then(|| (key.to_vec(), decode(value)))
+        })
+    }
+
+    // The ranges deleted as of sequence number `seq`.
+    pub(crate) fn range_tombstones(&self, seq: u64) -> RangeTombstones {
+        let mut tombstones = RangeTombstones::default();
+        for (s, _, start, end) in self.range_history.lock().unwrap().iter() {
+            if *s <= seq {
+                tombstones.add(start, end);
+            }
+        }
+        tombstones
+    }
+
+    // Whether the newest write to `key` here put exactly `value`.
+    pub(crate) fn holds(&self, key: &[u8], value: &[u8]) -> bool {
+        matches!(self.lookup(key, u64::MAX), Some(Value::Put(held)) if held == value)
+    }
+
+    // Merges are folded in as soon as the key's value is known. Until then,
+    // the operands are kept for reads to fold into older versions.
+    fn merge(&self, key: &[u8], base: Option<Value>, operand: Vec<u8>) -> Value {
+        match base {
+            Some(Value::Merge(mut operands)) => {
+                operands.push(operand);
+                Value::Merge(operands)
+            }
+            None => Value::Merge(vec![operand]),
+            Some(base) => {
+                let merge = self
+                    .merge_operator
+                    .as_ref()
+                    .expect("merges are only applied with a merge operator");
+                let now = self.clock.unix_millis();
+                merge::fold(merge, key, Some(&base), &[operand], now)
+            }
+        }
+    }
+
+    // Applies `entry`, which was committed at sequence number `seq`. Writes
+    // to the same key must be applied in order, but otherwise this can be
+    // called from several tasks at once.
+    pub(crate) fn apply(&self, entry: LogEntry, seq: u64) {
+        self.apply_op(entry, seq);
+        self.lowest.fetch_min(seq, Ordering::AcqRel);
+        self.highest.fetch_max(seq, Ordering::AcqRel);
+    }
+
+    fn apply_op(&self, entry: LogEntry, seq: u64) {
+        let (key, value) = match entry {
+            LogEntry::Put { key, value } => (key, Ok(Value::Put(value))),
+            LogEntry::PutUntil {
+                key,
+                value,
+                expires_at,
+            } => (key, Ok(Value::PutUntil { value, expires_at })),
+            LogEntry::Delete { key } => (key, Ok(Value::Delete)),
+            LogEntry::Merge { key, operand } => (key, Err(operand)),
+            LogEntry::DeleteRange { start, end } => {
+                let applied = self.applied.fetch_add(1, Ordering::Relaxed) + 1;
+                self.bytes
+                    .fetch_add((start.len() + end.len()) as u64, Ordering::Relaxed);
+                if start < end {
+                    let (start, end) = (self.arena.alloc(&start), self.arena.alloc(&end));
+                    let mut range_history = self.range_history.lock().unwrap();
+                    let capacity = range_history.capacity();
+                    range_history.push((seq, applied, start, end));
+                    self.grew::<DeletedRange>(capacity, range_history.capacity());
+                    (self.deleted_ranges).store(range_history.len(), Ordering::Release);
+                }
+                return;
+            }
+            LogEntry::Batch(entries) => {
+                for entry in entries {
+                    self.apply_op(entry, seq);
+                }
+                return;
+            }
+            LogEntry::Prepare { id, ops } => {
+                self.prepared.lock().unwrap().insert(id, ops);
+                return;
+            }
+            LogEntry::Commit { id, ops } => {
+                self.prepared.lock().unwrap().remove(&id);
+                for op in ops {
+                    self.apply_op(op, seq);
+                }
+                return;
+            }
+            LogEntry::Rollback { id } => {
+                self.prepared.lock().unwrap().remove(&id);
+                return;
+            }
+        };
+        let entry = match self.keys.get(key.as_slice()) {
+            Some(entry) => entry,
+            None => (self.keys).get_or_insert_with(self.arena.alloc(&key), Default::default),
+        };
+        let mut versions = entry.value().lock().unwrap();
+        // Whoever gets here first for a new key counts its node.
+        if versions.is_empty() {
+            // Most keys are only written once, so there's room for one
+            // version to start with.
+            versions.reserve_exact(1);
+            let node = size_of::<(Bytes, Mutex<Vec<Version>>)>() + NODE_OVERHEAD;
+            let node = node + size_of::<Version>();
+            self.index_bytes.fetch_add(node as u64, Ordering::Relaxed);
+        }
+        let applied = self.applied.fetch_add(1, Ordering::Relaxed) + 1;
+        let value = match value {
+            Ok(value) => value,
+            Err(operand) => {
+                let base = self.newest(&key, &versions, u64::MAX);
+                self.merge(&key, base, operand)
+            }
+        };
+        self.bytes
+            .fetch_add((key.len() + value.size()) as u64, Ordering::Relaxed);
+        let capacity = versions.capacity();
+        versions.push((seq, applied, self.arena.alloc(&value.encode())));
+        self.grew::<Version>(capacity, versions.capacity());
+    }
+
+    // Counts a list of `T`s growing from `from` to `to` entries.
+    fn grew<T>(&self, from: usize, to: usize) {
+        let grown = (to - from) * size_of::<T>();
+        self.index_bytes.fetch_add(grown as u64, Ordering::Relaxed);
+    }
+}
+
+// Values are only ever encoded by the memtable itself.
+fn decode(value: &[u8]) -> Value {
+    Value::decode(value).expect("memtable values are well formed")
+}
+
+// What replaying the logs dropped along the way.
+#[derive(Default)]
+pub(crate) struct Replayed {
+    // Corrupt records skipped over.
+    pub(crate) dropped: u64,
+    // Where to cut the newest log back to, to get rid of corrupt records at
+    // its end before anything more is appended to it.
+    pub(crate) truncate_at: Option<u64>,
+    // Where the records replayed from the newest log end, before any corrupt
+    // ones.
+    pub(crate) end: u64,
+}
+
+impl Memtable {
+    // Replays the logs in `paths`, oldest first, dealing with corrupt records
+    // as `recovery` says. A log that doesn't exist yet is empty.
+    pub(crate) async fn hydrate(
+        storage: &dyn Storage,
+        paths: &[PathBuf],
+        merge_operator: Option<MergeOperator>,
+        clock: Arc<dyn Clock>,
+        recovery: WalRecovery,
+    ) -> Result<(Memtable, Replayed), NdbError> {
+        let memtable = Memtable::new(merge_operator, clock);
+        let mut replayed = Replayed::default();
+        let mut seq = 0;
+        for (i, path) in paths.iter().enumerate() {
+            let newest = i + 1 == paths.len();
+            memtable
+                .replay(storage, path, &mut seq, recovery, newest, &mut replayed)
+                .await?;
+        }
+        Ok((memtable, replayed))
+    }
+
+    // `seq` is the sequence number of the last record replayed so far.
+    #[instrument(skip_all, fields(path = %path.display()))]
+    async fn replay(
+        &self,
+        storage: &dyn Storage,
+        path: &Path,
+        seq: &mut u64,
+        recovery: WalRecovery,
+        newest: bool,
+        replayed: &mut Replayed,
+    ) -> Result<(), NdbError> {
+        let mut reader = match storage.open(path).await {
+            Ok(file) => LineReader::new(file),
+            Err(err) if storage::is_not_found(&err) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let mut line = Vec::new();
+        let (mut offset, mut number, mut applied) = (0, 0, 0);
+        // The first corrupt record since the last good one, where it starts,
+        // and how many there have been.
+        let mut corrupt: Option<(NdbError, u64, u64)> = None;
+        let mut dropped = 0;
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line).await? as u64;
+            if read == 0 {
+                break;
+            }
+            // A log copied together from several has a header from each.
+            if let Some(header) = line.strip_suffix(b"\n") {
+                if version::parse_header(header, path)?.is_some() {
+                    offset += read;
+                    number += 1;
+                    continue;
+                }
+            }
+            // A record without its newline was cut off partway through
+            // being written.
+            let record = match line.strip_suffix(b"\n") {
+                Some(record) => parse_record(record),
+                None => Err("incomplete record".to_string()),
+            };
+            let start = offset;
+            offset += read;
+            number += 1;
+            let record = match record {
+                Ok(record) => record,
+                Err(err) => {
+                    let err =
+                        NdbError::corruption(format!("bad log record {}: {}", number - 1, err))
+                            .in_file(path)
+                            .at_offset(start);
+                    if recovery == WalRecovery::AbsoluteConsistency {
+                        return Err(err);
+                    }
+                    corrupt.get_or_insert((err, start, 0)).2 += 1;
+                    continue;
+                }
+            };
+            if let Some((err, _, count)) = corrupt.take() {
+                // Good records after corrupt ones mean the corruption isn't
+                // just a write cut off by a crash.
+                if recovery == WalRecovery::TolerateCorruptedTailRecords {
+                    return Err(err);
+                }
+                dropped += count;
+            }
+            if self.merge_operator.is_none() && record.entry.has_merge() {
+                return Err(merge::no_operator());
+            }
+            // Records from before sequence numbers were logged are numbered
+            // in order.
+            *seq = if record.seq == 0 {
+                *seq + 1
+            } else {
+                record.seq
+            };
+            self.apply(record.entry, *seq);
+            applied += 1;
+        }
+        if newest {
+            replayed.end = corrupt.as_ref().map_or(offset, |(_, start, _)| *start);
+        }
+        if let Some((err, start, count)) = corrupt {
+            // Only the newest log can have been cut off by a crash; older
+            // segments were synced before the next one was started.
+            if recovery == WalRecovery::TolerateCorruptedTailRecords && !newest {
+                return Err(err);
+            }
+            dropped += count;
+            if newest {
+                replayed.truncate_at = Some(start);
+            }
+        }
+        if dropped > 0 {
+            warn!(dropped, "dropped corrupt log records");
+            replayed.dropped += dropped;
+        }
+        info!(records = applied, bytes = offset, "replayed log");
+        Ok(())
+    }
+
+    // Applies the records appended to the log at `path` after `offset`, for
+    // a database following another process's, and moves `offset` past them.
+    // A record that's still being written is left for next time. `seq` is
+    // the sequence number of the last record applied.
+    pub(crate) async fn tail(
+        &self,
+        storage: &dyn Storage,
+        path: &Path,
+        offset: &mut u64,
+        seq: &mut u64,
+    ) -> Result<(), NdbError> {
+        let mut reader = match storage.open(path).await {
+            Ok(file) => LineReader::at(file, *offset),
+            Err(err) if storage::is_not_found(&err) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line).await? as u64;
+            let Some(record) = line.strip_suffix(b"\n") else {
+                return Ok(());
+            };
+            if version::parse_header(record, path)?.is_none() {
+                let record = parse_record(record).map_err(|err| {
+                    NdbError::corruption(format!("bad log record: {}", err))
+                        .in_file(path)
+                        .at_offset(*offset)
+                })?;
+                if self.merge_operator.is_none() && record.entry.has_merge() {
+                    return Err(merge::no_operator());
+                }
+                *seq = if record.seq == 0 {
+                    *seq + 1
+                } else {
+                    record.seq
+                };
+                self.apply(record.entry, *seq);
+            }
+            *offset += read;
+        }
+    }
+}
+
+// A log record, without its newline.
+fn parse_record(record: &[u8]) -> Result<LogRecord<LogEntry>, String> {
+    serde_json::from_slice::<LogRecord<LogEntry>>(record)
+        .map_err(|err| err.to_string())
+        .and_then(|record| match record.seq {
+            // Nothing could be written after it.
+            u64::MAX => Err("sequence number out of range".to_string()),
+            _ => Ok(record),
+        })
+}
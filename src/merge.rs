@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use crate::{value::Value, NdbError};
+
+/// Combines merge operands into a key's value: called with the key, its
+/// existing value if it has one, and the operands written since, oldest
+/// first. Set with [`DbOptions::merge_operator`](crate::DbOptions::merge_operator).
+pub type MergeOperator = Arc<dyn Fn(&[u8], Option<&[u8]>, &[Vec<u8>]) -> Vec<u8> + Send + Sync>;
+
+// Folds `operands`, oldest first, into `base`, the newest value of the key
+// that isn't a merge. Merging into a value with a TTL keeps its expiry time,
+// unless it's expired as of `now`, in unix milliseconds, when the operands
+// start afresh as if there were no value at all.
+pub(crate) fn fold(
+    merge: &MergeOperator,
+    key: &[u8],
+    base: Option<&Value>,
+    operands: &[Vec<u8>],
+    now: u64,
+) -> Value {
+    match base {
+        Some(Value::Put(value)) => Value::Put(merge(key, Some(value), operands)),
+        Some(Value::PutUntil { value, expires_at }) if now < *expires_at => Value::PutUntil {
+            value: merge(key, Some(value), operands),
+            expires_at: *expires_at,
+        },
+        Some(Value::PutUntil { .. } | Value::Delete) | None => {
+            Value::Put(merge(key, None, operands))
+        }
+        Some(Value::Merge(_)) => unreachable!("a merge is never the base of another"),
+    }
+}
+
+// Resolves the versions of `key`, newest first, to a single value as of
+// `now`. Only needs to look as far back as the first version that isn't a
+// merge.
+pub(crate) fn resolve(
+    merge: Option<&MergeOperator>,
+    key: &[u8],
+    versions: impl IntoIterator<Item = Value>,
+    now: u64,
+) -> Result<Option<Value>, NdbError> {
+    let mut stacks = Vec::new();
+    let mut base = None;
+    for value in versions {
+        match value {
+            Value::Merge(operands) => stacks.push(operands),
+            value => {
+                base = Some(value);
+                break;
+            }
+        }
+    }
+    if stacks.is_empty() {
+        return Ok(base);
+    }
+    let merge = merge.ok_or_else(no_operator)?;
+    let operands: Vec<_> = stacks.into_iter().rev().flatten().collect();
+    Ok(Some(fold(merge, key, base.as_ref(), &operands, now)))
+}
+
+pub(crate) fn no_operator() -> NdbError {
+    NdbError::InvalidArgument("merging requires a merge operator".into())
+}
@@ -0,0 +1,40 @@
+//! The names of the metrics reported with the `metrics` feature. Without it,
+//! nothing is recorded.
+//!
+//! Latencies are histograms in seconds, and everything else is a counter.
+//! Nothing is labelled, so several databases in one process add up.
+
+use std::future::Future;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+/// Memtables flushed to tables.
+pub const FLUSHES: &str = "nulldb_flushes_total";
+/// Bytes of tables written by flushes.
+pub const FLUSH_BYTES: &str = "nulldb_flush_bytes_total";
+/// Bytes of tables written by vacuums and compactions into a base.
+pub const COMPACTION_BYTES: &str = "nulldb_compaction_bytes_total";
+/// How long syncing the WAL takes.
+pub const WAL_SYNC_SECONDS: &str = "nulldb_wal_sync_seconds";
+/// How long [`Db::get`](crate::Db::get) takes.
+pub const GET_SECONDS: &str = "nulldb_get_seconds";
+/// How long writes take, from [`Db::put`](crate::Db::put) and every other
+/// write that goes through [`Db::write_opt`](crate::Db::write_opt).
+pub const WRITE_SECONDS: &str = "nulldb_write_seconds";
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn count(name: &'static str, n: u64) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(name).increment(n);
+}
+
+// Runs `f`, recording how long it took in the histogram `name`.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) async fn timed<T>(name: &'static str, f: impl Future<Output = T>) -> T {
+    #[cfg(feature = "metrics")]
+    let started = Instant::now();
+    let result = f.await;
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(name).record(started.elapsed());
+    result
+}
@@ -0,0 +1,76 @@
+use std::fs::File;
+
+use memmap2::Mmap;
+
+use crate::NdbError;
+
+/// Which tables [`DbOptions::mmap`](crate::DbOptions::mmap) maps into memory,
+/// and how their pages are expected to be used.
+#[derive(Debug, Clone, Default)]
+pub struct MmapOptions {
+    /// Only map tables whose data files are at most this big, and read the
+    /// rest as usual. Mapping every table can take up a lot of address space
+    /// in a large database.
+    pub max_table_bytes: Option<u64>,
+    /// Passed on to the OS with `madvise` once a table is mapped. Ignored
+    /// where there's no `madvise`.
+    pub advice: Option<MmapAdvice>,
+}
+
+/// How a mapped table's pages are expected to be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmapAdvice {
+    /// No particular way, which is what happens without advice.
+    Normal,
+    /// In no particular order, as point reads do, so reading ahead is wasted.
+    Random,
+    /// In order, as scans do, so pages can be read well ahead.
+    Sequential,
+    /// Soon, so they're read in as soon as the table is mapped.
+    WillNeed,
+}
+
+impl MmapOptions {
+    // Whether a table this big should be mapped.
+    pub(crate) fn maps(&self, size: u64) -> bool {
+        self.max_table_bytes.is_none_or(|max| size <= max)
+    }
+}
+
+// Maps a table's data file. Tables are never written to once they're
+// finished, so the mapping stays valid, and it outlives the file being
+// closed or deleted.
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub(crate) fn map(file: &File, options: &MmapOptions) -> Result<Mmap, NdbError> {
+    // Safety: nothing writes to or truncates a finished table. A process
+    // that did anyway would break far more than this.
+    let map = unsafe { Mmap::map(file)? };
+    #[cfg(unix)]
+    if let Some(advice) = options.advice {
+        use memmap2::Advice;
+        map.advise(match advice {
+            MmapAdvice::Normal => Advice::Normal,
+            MmapAdvice::Random => Advice::Random,
+            MmapAdvice::Sequential => Advice::Sequential,
+            MmapAdvice::WillNeed => Advice::WillNeed,
+        })?;
+    }
+    Ok(map)
+}
+
+// Copies `len` bytes at `offset` out of the mapping into `buf`, replacing
+// what was in it.
+pub(crate) fn read_at(
+    map: &Mmap,
+    offset: u64,
+    len: usize,
+    mut buf: Vec<u8>,
+) -> Result<Vec<u8>, NdbError> {
+    let start = offset as usize;
+    let raw = map
+        .get(start..start + len)
+        .ok_or_else(|| NdbError::corruption("block runs past the end of the table"))?;
+    buf.clear();
+    buf.extend_from_slice(raw);
+    Ok(buf)
+}
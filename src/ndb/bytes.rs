@@ -0,0 +1,63 @@
+// How keys and values are read from the command line and printed.
+
+use nulldb::hex;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // Strings where they're printable, hex otherwise.
+    Auto,
+    String,
+    Hex,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Result<Format, String> {
+        match name {
+            "auto" => Ok(Format::Auto),
+            "string" => Ok(Format::String),
+            "hex" => Ok(Format::Hex),
+            _ => Err(format!("unknown format {}", name)),
+        }
+    }
+}
+
+// For people: strings are quoted, so they can't be mistaken for hex.
+pub fn render(bytes: &[u8], format: Format) -> String {
+    match text(bytes, format) {
+        Text::String(s) => format!("{:?}", s),
+        Text::Hex(hex) => hex,
+    }
+}
+
+// For JSON, where strings are quoted anyway: 0x and hex digits, or the string
+// itself. Strings that start with 0x are written in hex too, so that what's
+// printed can be read back with `parse_bytes`.
+pub fn json(bytes: &[u8], format: Format) -> serde_json::Value {
+    match text(bytes, format) {
+        Text::String(s) if format == Format::Auto && s.starts_with("0x") => {
+            hex::encode(bytes).into()
+        }
+        Text::String(s) | Text::Hex(s) => s.into(),
+    }
+}
+
+enum Text {
+    String(String),
+    Hex(String),
+}
+
+fn text(bytes: &[u8], format: Format) -> Text {
+    match (format, std::str::from_utf8(bytes)) {
+        (Format::Hex, _) => Text::Hex(hex::encode(bytes)),
+        (Format::String, _) => Text::String(String::from_utf8_lossy(bytes).into_owned()),
+        (Format::Auto, Ok(s)) if !s.chars().any(char::is_control) => Text::String(s.to_string()),
+        (Format::Auto, _) => Text::Hex(hex::encode(bytes)),
+    }
+}
+
+pub fn parse_bytes(arg: &str) -> Result<Vec<u8>, String> {
+    let Some(digits) = arg.strip_prefix("0x") else {
+        return Ok(arg.as_bytes().to_vec());
+    };
+    hex::decode(digits).map_err(|err| format!("{} in {}", err, arg))
+}
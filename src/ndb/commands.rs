@@ -0,0 +1,206 @@
+use std::error::Error;
+
+use nulldb::{Db, DbOptions};
+use serde_json::json;
+
+use crate::{
+    bytes::{self, parse_bytes, render, Format},
+    dump, repair, sst, verify,
+};
+
+// One operation on a database, run straight from the command line.
+pub struct Command {
+    dir: String,
+    op: Op,
+    json: bool,
+    format: Format,
+}
+
+enum Op {
+    Get(Vec<u8>),
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    Scan {
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        limit: Option<usize>,
+    },
+    Stats,
+    Flush,
+    Compact,
+    // To stdout if there's no path.
+    Dump {
+        path: Option<String>,
+        binary: bool,
+    },
+    // From stdin if there's no path.
+    Load(Option<String>),
+    Verify,
+    // Rebuilds the manifest, without opening the database.
+    Repair,
+    // A table file rather than a database directory.
+    Sst {
+        records: bool,
+    },
+}
+
+impl Op {
+    // Reads open the database read-only, so they work on one another process
+    // has open.
+    fn writes(&self) -> bool {
+        matches!(
+            self,
+            Op::Put(..) | Op::Delete(_) | Op::Flush | Op::Compact | Op::Load(_)
+        )
+    }
+}
+
+// Parses `<command> <dir> <args>...`, with flags anywhere among them.
+pub fn parse(args: &[&str]) -> Result<Command, String> {
+    let mut json = false;
+    let mut format = Format::Auto;
+    let mut limit = None;
+    let mut binary = false;
+    let mut records = false;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg {
+            "--json" => json = true,
+            "--format" => format = Format::parse(value()?)?,
+            "--limit" => {
+                let n = value()?;
+                limit = Some(n.parse().map_err(|_| format!("bad limit {}", n))?);
+            }
+            "--binary" => binary = true,
+            "--records" => records = true,
+            _ => positional.push(arg),
+        }
+    }
+    let (dir, op) = match positional[..] {
+        ["get", dir, key] => (dir, Op::Get(parse_bytes(key)?)),
+        ["put", dir, key, value] => (dir, Op::Put(parse_bytes(key)?, parse_bytes(value)?)),
+        ["delete", dir, key] => (dir, Op::Delete(parse_bytes(key)?)),
+        ["scan", dir, ref bounds @ ..] if bounds.len() <= 2 => {
+            let start = bounds.first().map(|b| parse_bytes(b)).transpose()?;
+            let end = bounds.get(1).map(|b| parse_bytes(b)).transpose()?;
+            (dir, Op::Scan { start, end, limit })
+        }
+        ["stats", dir] => (dir, Op::Stats),
+        ["flush", dir] => (dir, Op::Flush),
+        ["compact", dir] => (dir, Op::Compact),
+        ["dump", dir, ref path @ ..] if path.len() <= 1 => {
+            let path = path.first().map(|path| path.to_string());
+            (dir, Op::Dump { path, binary })
+        }
+        ["verify", dir] => (dir, Op::Verify),
+        ["repair", dir] => (dir, Op::Repair),
+        ["sst", path] => (path, Op::Sst { records }),
+        ["load", dir, ref path @ ..] if path.len() <= 1 => {
+            (dir, Op::Load(path.first().map(|path| path.to_string())))
+        }
+        _ => return Err(crate::USAGE.into()),
+    };
+    Ok(Command {
+        dir: dir.to_string(),
+        op,
+        json,
+        format,
+    })
+}
+
+impl Command {
+    pub async fn run(self) -> Result<(), Box<dyn Error>> {
+        if let Op::Sst { records } = self.op {
+            return sst::inspect(&self.dir, records, self.json, self.format).await;
+        }
+        if let Op::Repair = self.op {
+            return repair::repair(&self.dir, self.json).await;
+        }
+        if !self.op.writes() {
+            let db = Db::open_read_only(&self.dir, DbOptions::default()).await?;
+            return self.read(&db).await;
+        }
+        let mut db = Db::new(&self.dir).await?;
+        match &self.op {
+            Op::Put(key, value) => db.put(key, value).await?,
+            Op::Delete(key) => db.delete(key).await?,
+            Op::Flush => db.flush_memtable().await?,
+            Op::Compact => {
+                let merged = db.vacuum().await?;
+                match self.json {
+                    true => println!("{}", json!({ "merged_tables": merged })),
+                    false => println!("merged {} tables", merged),
+                }
+            }
+            Op::Load(path) => {
+                let loaded = dump::load(&mut db, path.as_deref()).await?;
+                match self.json {
+                    true => println!("{}", json!({ "loaded": loaded })),
+                    false => println!("loaded {} entries", loaded),
+                }
+            }
+            _ => unreachable!("not a write"),
+        }
+        db.close().await?;
+        Ok(())
+    }
+
+    async fn read(&self, db: &Db) -> Result<(), Box<dyn Error>> {
+        match &self.op {
+            Op::Get(key) => {
+                let value = db.get(key).await?;
+                if self.json {
+                    let value = value.map(|value| bytes::json(&value, self.format));
+                    let key = bytes::json(key, self.format);
+                    println!("{}", json!({ "key": key, "value": value }));
+                    return Ok(());
+                }
+                match value {
+                    Some(value) => println!("{}", render(&value, self.format)),
+                    None => println!("(not found)"),
+                }
+            }
+            Op::Scan { start, end, limit } => {
+                let mut iter = match (start.clone(), end.clone()) {
+                    (None, _) => db.scan(..).await?,
+                    (Some(start), None) => db.scan(start..).await?,
+                    (Some(start), Some(end)) => db.scan(start..end).await?,
+                };
+                let mut printed = 0;
+                while limit.is_none_or(|limit| printed < limit) {
+                    let Some((key, value)) = iter.next().await? else {
+                        break;
+                    };
+                    match self.json {
+                        // A line per entry, so big scans can be streamed.
+                        true => println!(
+                            "{}",
+                            json!({
+                                "key": bytes::json(&key, self.format),
+                                "value": bytes::json(&value, self.format),
+                            })
+                        ),
+                        false => println!(
+                            "{} = {}",
+                            render(&key, self.format),
+                            render(&value, self.format)
+                        ),
+                    }
+                    printed += 1;
+                }
+            }
+            Op::Stats => match self.json {
+                true => println!("{}", serde_json::to_string_pretty(&db.stats())?),
+                false => println!("{:#?}", db.stats()),
+            },
+            Op::Dump { path, binary } => {
+                dump::dump(db, path.as_deref(), *binary, self.format).await?
+            }
+            Op::Verify => verify::verify(db, self.json).await?,
+            _ => unreachable!("not a read"),
+        }
+        Ok(())
+    }
+}
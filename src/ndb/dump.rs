@@ -0,0 +1,186 @@
+// `ndb dump` and `ndb load`: every live key in a database, with its value and
+// expiry time, in a file that doesn't depend on the table or log formats.
+//
+// The JSON dump has an object per line, e.g.
+//
+//   {"key":"a","value":"0x00ff","expires_at_ms":1767225600000}
+//
+// with keys and values as strings or 0x and hex digits, as `ndb` prints them
+// elsewhere, and `expires_at_ms` (unix milliseconds) left out for values
+// without a TTL. The binary dump starts with MAGIC, followed by each entry as
+// a little-endian u32 length and the key, a u32 length and the value, and a
+// u64 expiry time in unix milliseconds, 0 if there's none.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use nulldb::{Db, WriteBatch};
+use serde_json::json;
+
+use crate::{
+    bytes::{self, parse_bytes, Format},
+    sst,
+};
+
+const MAGIC: &[u8; 8] = b"NDBDUMP\x01";
+
+// How many entries go into each write while loading.
+const LOAD_BATCH: usize = 1000;
+
+struct Entry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    expires_at_ms: Option<u64>,
+}
+
+pub async fn dump(
+    db: &Db,
+    path: Option<&str>,
+    binary: bool,
+    format: Format,
+) -> Result<(), Box<dyn Error>> {
+    if format == Format::String {
+        return Err("dumps can't use --format string, which loses bytes that aren't UTF-8".into());
+    }
+    let out: Box<dyn Write> = match path {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let mut out = BufWriter::new(out);
+    if binary {
+        out.write_all(MAGIC)?;
+    }
+    let mut iter = db.scan(..).await?;
+    while let Some((key, value, expires_at)) = iter.next_with_expiry().await? {
+        let expires_at_ms = expires_at.map(sst::unix_millis);
+        if binary {
+            for bytes in [&key, &value] {
+                out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+                out.write_all(bytes)?;
+            }
+            out.write_all(&expires_at_ms.unwrap_or(0).to_le_bytes())?;
+            continue;
+        }
+        let mut line = json!({
+            "key": bytes::json(&key, format),
+            "value": bytes::json(&value, format),
+        });
+        if let Some(ms) = expires_at_ms {
+            line["expires_at_ms"] = ms.into();
+        }
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// Loads a dump of either kind into `db`, which has to be empty. Returns how
+// many entries were loaded.
+pub async fn load(db: &mut Db, path: Option<&str>) -> Result<u64, Box<dyn Error>> {
+    if db.scan(..).await?.next().await?.is_some() {
+        return Err("can only load into an empty database".into());
+    }
+    let input: Box<dyn Read> = match path {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(io::stdin()),
+    };
+    let mut input = BufReader::new(input);
+    // JSON dumps start with an object, or are empty.
+    let binary = input.fill_buf()?.first().is_some_and(|&b| b != b'{');
+    if binary {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("not an ndb dump".into());
+        }
+    }
+
+    let mut loaded = 0;
+    let mut line = 0;
+    let mut batch = WriteBatch::new();
+    loop {
+        let entry = match binary {
+            true => read_binary(&mut input)?,
+            false => {
+                line += 1;
+                read_json(&mut input).map_err(|err| format!("line {}: {}", line, err))?
+            }
+        };
+        let Some(entry) = entry else {
+            break;
+        };
+        match entry.expires_at_ms {
+            Some(ms) => batch.put_until(
+                &entry.key,
+                &entry.value,
+                UNIX_EPOCH + Duration::from_millis(ms),
+            ),
+            None => batch.put(&entry.key, &entry.value),
+        }
+        loaded += 1;
+        if batch.len() == LOAD_BATCH {
+            db.write(std::mem::take(&mut batch)).await?;
+        }
+    }
+    if !batch.is_empty() {
+        db.write(batch).await?;
+    }
+    db.flush_memtable().await?;
+    Ok(loaded)
+}
+
+fn read_binary(input: &mut impl BufRead) -> Result<Option<Entry>, Box<dyn Error>> {
+    if input.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let truncated = |err: io::Error| -> Box<dyn Error> {
+        match err.kind() {
+            ErrorKind::UnexpectedEof => "the dump ends partway through an entry".into(),
+            _ => err.into(),
+        }
+    };
+    let read_bytes = |input: &mut dyn Read| -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut len = [0; 4];
+        input.read_exact(&mut len).map_err(truncated)?;
+        let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+        input.read_exact(&mut bytes).map_err(truncated)?;
+        Ok(bytes)
+    };
+    let key = read_bytes(input)?;
+    let value = read_bytes(input)?;
+    let mut expires_at_ms = [0; 8];
+    input.read_exact(&mut expires_at_ms).map_err(truncated)?;
+    let expires_at_ms = Some(u64::from_le_bytes(expires_at_ms)).filter(|&ms| ms != 0);
+    Ok(Some(Entry {
+        key,
+        value,
+        expires_at_ms,
+    }))
+}
+
+fn read_json(input: &mut impl BufRead) -> Result<Option<Entry>, Box<dyn Error>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let object: serde_json::Value = serde_json::from_str(&line)?;
+    let bytes = |field: &str| -> Result<Vec<u8>, Box<dyn Error>> {
+        match object[field].as_str() {
+            Some(s) => Ok(parse_bytes(s)?),
+            None => Err(format!("no {} string", field).into()),
+        }
+    };
+    let expires_at_ms = match &object["expires_at_ms"] {
+        serde_json::Value::Null => None,
+        ms => Some(ms.as_u64().ok_or("expires_at_ms isn't a number")?),
+    };
+    Ok(Some(Entry {
+        key: bytes("key")?,
+        value: bytes("value")?,
+        expires_at_ms,
+    }))
+}
@@ -0,0 +1,74 @@
+mod bytes;
+mod commands;
+mod dump;
+mod repair;
+mod shell;
+mod sst;
+mod verify;
+
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: ndb shell <dir>
+       ndb format-spec
+       ndb [--json] [--format auto|string|hex] <command> <dir> [<args>]
+
+commands:
+  get <dir> <key>
+  put <dir> <key> <value>
+  delete <dir> <key>
+  scan [--limit <n>] <dir> [<start> [<end>]]
+  stats <dir>
+  flush <dir>
+  compact <dir>
+  dump [--binary] <dir> [<file>]
+  load <dir> [<file>]
+  sst [--records] <file.sst>
+  verify <dir>
+  repair <dir>
+
+Keys and values are read as strings, or as raw bytes if written as 0x followed
+by hex digits (which is also how to pass one that starts with --). --json prints an object per line instead (the stats as one
+object), with keys and values as strings in the chosen format.
+
+dump writes every key, with its value and expiry time, to a file or stdout as
+JSON lines, or in a binary format with --binary. load reads either kind from a
+file or stdin into an empty database.
+
+sst prints a table file's footer, bloom filter, key range and blocks, marking
+any that are corrupt, and with --records everything in them.
+
+verify reads every table block and log record, checking checksums and key
+order, and checks the manifest against the files there. It exits with 1 if
+anything's wrong.
+
+repair rebuilds a database's manifest from its tables and logs, if it's been
+lost or corrupted. Files that can't be read are moved into a lost directory,
+and log records that can't be read are dropped.";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args[..] {
+        ["shell", dir] => shell::run(dir).await,
+        ["format-spec"] => {
+            print!("{}", nulldb::format_spec());
+            Ok(())
+        }
+        _ => match commands::parse(&args) {
+            Ok(command) => command.run().await,
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::from(2);
+            }
+        },
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("ndb: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,44 @@
+// `ndb repair`: rebuilds a database's manifest from the files it has left.
+
+use std::error::Error;
+
+use nulldb::{Db, DbOptions};
+use serde_json::json;
+
+pub async fn repair(dir: &str, as_json: bool) -> Result<(), Box<dyn Error>> {
+    let report = Db::repair(dir, DbOptions::default()).await?;
+    if as_json {
+        let quarantined: Vec<_> = report
+            .quarantined
+            .iter()
+            .map(|(path, reason)| json!({ "path": path, "reason": reason }))
+            .collect();
+        let out = json!({
+            "salvaged_manifest": report.salvaged_manifest,
+            "tables": report.tables,
+            "logs": report.logs,
+            "quarantined": quarantined,
+            "dropped_log_records": report.dropped_log_records,
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+    match report.salvaged_manifest {
+        true => println!("salvaged what could be read of the manifest"),
+        false => {
+            println!("no manifest could be read; tables are ordered by when they were written")
+        }
+    }
+    println!(
+        "kept {} tables and {} logs",
+        report.tables.len(),
+        report.logs.len()
+    );
+    for (path, reason) in &report.quarantined {
+        println!("moved {} to lost: {}", path.display(), reason);
+    }
+    if report.dropped_log_records > 0 {
+        println!("dropped {} log records", report.dropped_log_records);
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use std::{error::Error, path::PathBuf};
+
+use nulldb::Db;
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::bytes::{parse_bytes, render, Format};
+
+const HELP: &str = "\
+commands:
+  get <key>
+  put <key> <value>
+  delete <key>
+  scan [<start> [<end>]]
+  stats
+  flush
+  compact
+  format auto|string|hex
+  help
+  quit
+
+Keys and values are read as strings. Quote them to include spaces, or write
+0x followed by hex digits for raw bytes.";
+
+// How many entries a scan prints before stopping.
+const SCAN_LIMIT: usize = 50;
+
+// Splits a line on whitespace, keeping double-quoted words together. Inside
+// quotes, a backslash escapes the next character.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return Ok(tokens);
+        };
+        let mut token = String::new();
+        if first == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => token.extend(chars.next()),
+                    Some(c) => token.push(c),
+                    None => return Err("unterminated quote".into()),
+                }
+            }
+        } else {
+            token.push(first);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+        }
+        tokens.push(token);
+    }
+}
+
+struct Shell {
+    db: Db,
+    format: Format,
+}
+
+impl Shell {
+    // Returns false once the shell should exit.
+    async fn execute(&mut self, line: &str) -> Result<bool, Box<dyn Error>> {
+        let tokens = tokenize(line)?;
+        let args: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        match args[..] {
+            [] => {}
+            ["get", key] => match self.db.get(&parse_bytes(key)?).await? {
+                Some(value) => println!("{}", render(&value, self.format)),
+                None => println!("(not found)"),
+            },
+            ["put", key, value] => {
+                self.db
+                    .put(&parse_bytes(key)?, &parse_bytes(value)?)
+                    .await?
+            }
+            ["delete", key] => self.db.delete(&parse_bytes(key)?).await?,
+            ["scan", ref bounds @ ..] if bounds.len() <= 2 => {
+                let start = bounds.first().map(|b| parse_bytes(b)).transpose()?;
+                let end = bounds.get(1).map(|b| parse_bytes(b)).transpose()?;
+                self.scan(start, end).await?;
+            }
+            ["stats"] => println!("{:#?}", self.db.stats()),
+            ["flush"] => self.db.flush_memtable().await?,
+            ["compact"] => println!("merged {} tables", self.db.vacuum().await?),
+            ["format", format] => self.format = Format::parse(format)?,
+            ["help"] => println!("{}", HELP),
+            ["quit"] | ["exit"] => return Ok(false),
+            _ => return Err(format!("can't parse {:?}; try help", line.trim()).into()),
+        }
+        Ok(true)
+    }
+
+    async fn scan(
+        &self,
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut iter = match (start, end) {
+            (None, _) => self.db.scan(..).await?,
+            (Some(start), None) => self.db.scan(start..).await?,
+            (Some(start), Some(end)) => self.db.scan(start..end).await?,
+        };
+        let mut printed = 0;
+        while let Some((key, value)) = iter.next().await? {
+            if printed == SCAN_LIMIT {
+                println!("(more; continue with scan {})", render(&key, Format::Hex));
+                return Ok(());
+            }
+            println!(
+                "{} = {}",
+                render(&key, self.format),
+                render(&value, self.format)
+            );
+            printed += 1;
+        }
+        println!("({} entries)", printed);
+        Ok(())
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ndb_history"))
+}
+
+pub async fn run(dir: &str) -> Result<(), Box<dyn Error>> {
+    let mut shell = Shell {
+        db: Db::new(dir).await?,
+        format: Format::Auto,
+    };
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(history) = &history {
+        // There's no history the first time round.
+        let _ = editor.load_history(history);
+    }
+
+    loop {
+        let line = match editor.readline("ndb> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str())?;
+        }
+        match shell.execute(&line).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => eprintln!("error: {}", err),
+        }
+    }
+
+    if let Some(history) = &history {
+        editor.save_history(history)?;
+    }
+    Ok(())
+}
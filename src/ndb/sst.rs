@@ -0,0 +1,164 @@
+// `ndb sst`: what's in a table file, like RocksDB's sst_dump.
+
+use std::{
+    error::Error,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use nulldb::{DbOptions, SstRecord, SstReport, SstValue};
+use serde_json::json;
+
+use crate::bytes::{self, render, Format};
+
+pub async fn inspect(
+    path: &str,
+    records: bool,
+    as_json: bool,
+    format: Format,
+) -> Result<(), Box<dyn Error>> {
+    let report = nulldb::inspect_sst(path, &DbOptions::default(), records).await?;
+    match as_json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&to_json(&report, format))?
+        ),
+        false => print(&report, format),
+    }
+    Ok(())
+}
+
+fn print(report: &SstReport, format: Format) {
+    let key = |key: &Option<Vec<u8>>| match key {
+        Some(key) => render(key, format),
+        None => "-".into(),
+    };
+    println!("path: {}", report.path.display());
+    println!(
+        "size: {} bytes, format version {}, checksum {:#010x}",
+        report.bytes, report.format_version, report.checksum
+    );
+    println!(
+        "index: {} bytes at {}",
+        report.index.size, report.index.offset
+    );
+    println!(
+        "filter: {} bytes at {}, {} bits, {} probes",
+        report.filter.location.size,
+        report.filter.location.offset,
+        report.filter.bits,
+        report.filter.probes
+    );
+    println!(
+        "keys: {} entries, {} to {}",
+        report.entries,
+        key(&report.smallest_key),
+        key(&report.largest_key)
+    );
+    println!("blocks: {}", report.blocks.len());
+    for (i, block) in report.blocks.iter().enumerate() {
+        print!(
+            "  {}: {} bytes at {}, index key {}",
+            i,
+            block.location.size,
+            block.location.offset,
+            render(&block.index_key, format)
+        );
+        match &block.corrupt {
+            Some(reason) => println!(", CORRUPT: {}", reason),
+            None => println!(
+                ", {} entries, {} to {}",
+                block.entries,
+                key(&block.first_key),
+                key(&block.last_key)
+            ),
+        }
+        for record in &block.records {
+            println!("    {}", render_record(record, format));
+        }
+    }
+}
+
+fn render_record(record: &SstRecord, format: Format) -> String {
+    let key = render(&record.key, format);
+    match &record.value {
+        SstValue::Put(value) => format!("{} = {}", key, render(value, format)),
+        SstValue::PutUntil { value, expires_at } => format!(
+            "{} = {} until {}ms",
+            key,
+            render(value, format),
+            unix_millis(*expires_at)
+        ),
+        SstValue::Delete => format!("{} deleted", key),
+        SstValue::Merge(operands) => {
+            let operands: Vec<_> = operands.iter().map(|op| render(op, format)).collect();
+            format!("{} merge [{}]", key, operands.join(", "))
+        }
+    }
+}
+
+fn to_json(report: &SstReport, format: Format) -> serde_json::Value {
+    let key = |key: &Option<Vec<u8>>| key.as_ref().map(|key| bytes::json(key, format));
+    let blocks: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|block| {
+            let mut out = json!({
+                "offset": block.location.offset,
+                "size": block.location.size,
+                "index_key": bytes::json(&block.index_key, format),
+                "entries": block.entries,
+                "first_key": key(&block.first_key),
+                "last_key": key(&block.last_key),
+                "corrupt": block.corrupt,
+            });
+            if !block.records.is_empty() {
+                out["records"] = block
+                    .records
+                    .iter()
+                    .map(|record| record_json(record, format))
+                    .collect();
+            }
+            out
+        })
+        .collect();
+    json!({
+        "path": report.path,
+        "bytes": report.bytes,
+        "format_version": report.format_version,
+        "checksum": report.checksum,
+        "index": { "offset": report.index.offset, "size": report.index.size },
+        "filter": {
+            "offset": report.filter.location.offset,
+            "size": report.filter.location.size,
+            "bits": report.filter.bits,
+            "probes": report.filter.probes,
+        },
+        "entries": report.entries,
+        "smallest_key": key(&report.smallest_key),
+        "largest_key": key(&report.largest_key),
+        "blocks": blocks,
+    })
+}
+
+fn record_json(record: &SstRecord, format: Format) -> serde_json::Value {
+    let key = bytes::json(&record.key, format);
+    match &record.value {
+        SstValue::Put(value) => json!({ "key": key, "value": bytes::json(value, format) }),
+        SstValue::PutUntil { value, expires_at } => json!({
+            "key": key,
+            "value": bytes::json(value, format),
+            "expires_at_ms": unix_millis(*expires_at),
+        }),
+        SstValue::Delete => json!({ "key": key, "deleted": true }),
+        SstValue::Merge(operands) => {
+            let operands: Vec<_> = operands.iter().map(|op| bytes::json(op, format)).collect();
+            json!({ "key": key, "merge": operands })
+        }
+    }
+}
+
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
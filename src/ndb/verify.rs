@@ -0,0 +1,131 @@
+// `ndb verify`: checks a database's files, and fails if anything's wrong.
+
+use std::error::Error;
+
+use nulldb::{Db, VerifyProblem, VerifyReport};
+use serde_json::json;
+
+pub async fn verify(db: &Db, as_json: bool) -> Result<(), Box<dyn Error>> {
+    let report = db.verify_checksums().await?;
+    match as_json {
+        true => println!("{}", serde_json::to_string_pretty(&to_json(&report))?),
+        false => {
+            println!(
+                "checked {} tables ({} blocks) and {} log records",
+                report.tables, report.blocks, report.log_records
+            );
+            for problem in &report.problems {
+                println!("{}", describe(problem));
+            }
+        }
+    }
+    match report.problems.len() {
+        0 => Ok(()),
+        n => Err(format!("found {} problems", n).into()),
+    }
+}
+
+fn describe(problem: &VerifyProblem) -> String {
+    match problem {
+        VerifyProblem::CorruptBlock(block) => format!(
+            "corrupt block in {} at {}: {}",
+            block.path.display(),
+            block.offset,
+            block.reason
+        ),
+        VerifyProblem::ChecksumMismatch {
+            path,
+            recorded,
+            actual,
+        } => format!(
+            "checksum mismatch in {}: recorded {:#010x}, found {:#010x}",
+            path.display(),
+            recorded,
+            actual
+        ),
+        VerifyProblem::BadKey {
+            path,
+            offset,
+            reason,
+        } => format!(
+            "bad key in {}, block at {}: {}",
+            path.display(),
+            offset,
+            reason
+        ),
+        VerifyProblem::CorruptLogRecord {
+            path,
+            offset,
+            reason,
+        } => format!(
+            "corrupt log record in {} at {}: {}",
+            path.display(),
+            offset,
+            reason
+        ),
+        VerifyProblem::MissingFile { path, kind } => {
+            format!("missing {:?} file {}", kind, path.display())
+        }
+        VerifyProblem::OrphanFile(path) => format!("orphan file {}", path.display()),
+    }
+}
+
+fn to_json(report: &VerifyReport) -> serde_json::Value {
+    let problems: Vec<_> = report
+        .problems
+        .iter()
+        .map(|problem| match problem {
+            VerifyProblem::CorruptBlock(block) => json!({
+                "problem": "corrupt_block",
+                "path": block.path,
+                "offset": block.offset,
+                "reason": block.reason,
+            }),
+            VerifyProblem::ChecksumMismatch {
+                path,
+                recorded,
+                actual,
+            } => json!({
+                "problem": "checksum_mismatch",
+                "path": path,
+                "recorded": recorded,
+                "actual": actual,
+            }),
+            VerifyProblem::BadKey {
+                path,
+                offset,
+                reason,
+            } => json!({
+                "problem": "bad_key",
+                "path": path,
+                "offset": offset,
+                "reason": reason,
+            }),
+            VerifyProblem::CorruptLogRecord {
+                path,
+                offset,
+                reason,
+            } => json!({
+                "problem": "corrupt_log_record",
+                "path": path,
+                "offset": offset,
+                "reason": reason,
+            }),
+            VerifyProblem::MissingFile { path, kind } => json!({
+                "problem": "missing_file",
+                "path": path,
+                "kind": format!("{:?}", kind),
+            }),
+            VerifyProblem::OrphanFile(path) => json!({
+                "problem": "orphan_file",
+                "path": path,
+            }),
+        })
+        .collect();
+    json!({
+        "tables": report.tables,
+        "blocks": report.blocks,
+        "log_records": report.log_records,
+        "problems": problems,
+    })
+}
@@ -0,0 +1,108 @@
+// Latencies, bucketed finely enough for percentiles to be within about 6% of
+// the real value: 16 buckets to each power of two nanoseconds, past the first
+// 16 nanoseconds, which get one each.
+
+use std::time::Duration;
+
+const SUB_BUCKETS: u64 = 16;
+const SUB_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+const BUCKETS: usize = ((64 - SUB_BITS + 1) * SUB_BUCKETS as u32) as usize;
+
+#[derive(Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum_nanos: u128,
+    max_nanos: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram {
+            counts: vec![0; BUCKETS],
+            count: 0,
+            sum_nanos: 0,
+            max_nanos: 0,
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = latency.as_nanos().min(u64::MAX as u128) as u64;
+        self.counts[bucket(nanos)] += 1;
+        self.count += 1;
+        self.sum_nanos += nanos as u128;
+        self.max_nanos = self.max_nanos.max(nanos);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.sum_nanos += other.sum_nanos;
+        self.max_nanos = self.max_nanos.max(other.max_nanos);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            n => Duration::from_nanos((self.sum_nanos / n as u128) as u64),
+        }
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max_nanos)
+    }
+
+    // The latency `p` (0 to 100) percent of operations took no longer than,
+    // as the middle of its bucket.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let rank = ((p / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let (low, high) = bounds(i);
+                let middle = low + (high - low) / 2;
+                return Duration::from_nanos(middle.min(self.max_nanos));
+            }
+        }
+        self.max()
+    }
+
+    // Each non-empty bucket's range and count, fastest first.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, Duration, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| {
+                let (low, high) = bounds(i);
+                (Duration::from_nanos(low), Duration::from_nanos(high), count)
+            })
+    }
+}
+
+fn bucket(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS {
+        return nanos as usize;
+    }
+    let exp = 63 - nanos.leading_zeros();
+    let sub = (nanos >> (exp - SUB_BITS)) & (SUB_BUCKETS - 1);
+    ((exp - SUB_BITS + 1) as u64 * SUB_BUCKETS + sub) as usize
+}
+
+// The lowest latency in bucket `i`, and the lowest past it.
+fn bounds(i: usize) -> (u64, u64) {
+    let i = i as u64;
+    if i < SUB_BUCKETS {
+        return (i, i + 1);
+    }
+    let shift = (i / SUB_BUCKETS - 1) as u32;
+    let low = (SUB_BUCKETS + i % SUB_BUCKETS) << shift;
+    (low, low.saturating_add(1 << shift))
+}
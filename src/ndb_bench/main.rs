@@ -0,0 +1,291 @@
+// `ndb-bench`: YCSB- and db_bench-style workloads against a database, with
+// throughput and latency percentiles for each, to compare releases by.
+
+mod histogram;
+mod workload;
+
+use std::{error::Error, path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
+
+use nulldb::{Db, DbOptions};
+use serde_json::json;
+
+use crate::{
+    histogram::Histogram,
+    workload::{Benchmark, Outcome, Zipfian},
+};
+
+const USAGE: &str = "\
+usage: ndb-bench [<options>]
+
+options:
+  --benchmarks <list>     comma-separated, run in order on the same database
+                          (default fillseq,readrandom,fillrandom,readwhilewriting,scan)
+  --db <dir>              database to use, kept afterwards (default a temporary one)
+  --num <n>               keys in the key space, and writes by each fill (default 100000)
+  --reads <n>             reads or scans by each read benchmark (default --num)
+  --tasks <n>             tasks running operations at once (default 1)
+  --key-size <bytes>      at least 16 (default 16)
+  --value-size <bytes>    (default 100)
+  --scan-length <n>       entries read by each scan (default 100)
+  --distribution <d>      uniform or zipfian, for random keys (default uniform)
+  --zipf-theta <t>        how skewed zipfian keys are, below 1 (default 0.99)
+  --write-buffer-size <bytes>
+                          memtable size that starts a flush (default 4194304)
+  --seed <n>              for random keys and values (default 1)
+  --histogram             print each benchmark's latency histogram
+  --json                  print an object per benchmark instead
+
+benchmarks:
+  fillseq             writes every key in order
+  fillrandom          writes --num random keys
+  readrandom          reads random keys
+  readwhilewriting    reads random keys while another task writes them
+  scan                reads --scan-length entries from random keys";
+
+// What to run, from the command line.
+pub struct Config {
+    benchmarks: Vec<Benchmark>,
+    db: Option<PathBuf>,
+    pub num: u64,
+    pub reads: u64,
+    pub tasks: u64,
+    pub key_size: usize,
+    pub value_size: usize,
+    pub scan_length: usize,
+    // `None` for uniform keys.
+    pub zipfian: Option<Arc<Zipfian>>,
+    write_buffer_size: u64,
+    pub seed: u64,
+    histogram: bool,
+    json: bool,
+}
+
+fn parse(args: &[String]) -> Result<Config, String> {
+    let mut benchmarks = "fillseq,readrandom,fillrandom,readwhilewriting,scan".to_string();
+    let mut db = None;
+    let (mut num, mut reads, mut tasks) = (100_000, None, 1);
+    let (mut key_size, mut value_size, mut scan_length) = (16, 100, 100);
+    let (mut zipfian, mut theta) = (false, 0.99);
+    let (mut write_buffer_size, mut seed) = (4 << 20, 1);
+    let (mut histogram, mut json) = (false, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        fn number<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("bad {} {}", arg.trim_start_matches('-'), value))
+        }
+        match arg.as_str() {
+            "--benchmarks" => benchmarks = value()?.clone(),
+            "--db" => db = Some(PathBuf::from(value()?)),
+            "--num" => num = number(arg, value()?)?,
+            "--reads" => reads = Some(number(arg, value()?)?),
+            "--tasks" => tasks = number(arg, value()?)?,
+            "--key-size" => key_size = number(arg, value()?)?,
+            "--value-size" => value_size = number(arg, value()?)?,
+            "--scan-length" => scan_length = number(arg, value()?)?,
+            "--distribution" => match value()?.as_str() {
+                "uniform" => zipfian = false,
+                "zipfian" => zipfian = true,
+                other => return Err(format!("unknown distribution {}", other)),
+            },
+            "--zipf-theta" => theta = number(arg, value()?)?,
+            "--write-buffer-size" => write_buffer_size = number(arg, value()?)?,
+            "--seed" => seed = number(arg, value()?)?,
+            "--histogram" => histogram = true,
+            "--json" => json = true,
+            _ => return Err(USAGE.into()),
+        }
+    }
+    if key_size < 16 {
+        return Err("--key-size has to be at least 16".into());
+    }
+    if tasks == 0 {
+        return Err("--tasks has to be at least 1".into());
+    }
+    if !(0.0..1.0).contains(&theta) {
+        return Err("--zipf-theta has to be at least 0 and below 1".into());
+    }
+    Ok(Config {
+        benchmarks: benchmarks
+            .split(',')
+            .map(Benchmark::parse)
+            .collect::<Result<_, _>>()?,
+        db,
+        num,
+        reads: reads.unwrap_or(num),
+        tasks,
+        key_size,
+        value_size,
+        scan_length,
+        zipfian: zipfian.then(|| Arc::new(Zipfian::new(num, theta))),
+        write_buffer_size,
+        seed,
+        histogram,
+        json,
+    })
+}
+
+async fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let config = Arc::new(config);
+    // A temporary database is removed once the benchmarks are done.
+    let temp = match &config.db {
+        Some(_) => None,
+        None => Some(std::env::temp_dir().join(format!("ndb-bench-{}", std::process::id()))),
+    };
+    let dir = config.db.clone().or(temp.clone()).unwrap();
+    let options = DbOptions {
+        write_buffer_size: Some(config.write_buffer_size),
+        ..DbOptions::default()
+    };
+    let db = Arc::new(Db::with_options(&dir, options).await?);
+    if !config.json {
+        println!(
+            "keys: {} bytes, values: {} bytes, key space: {}, tasks: {}, distribution: {}",
+            config.key_size,
+            config.value_size,
+            config.num,
+            config.tasks,
+            match config.zipfian {
+                Some(_) => "zipfian",
+                None => "uniform",
+            }
+        );
+    }
+    for &benchmark in &config.benchmarks {
+        let outcome = workload::run(&db, benchmark, &config).await?;
+        match config.json {
+            true => println!("{}", to_json(benchmark, &outcome, config.histogram)),
+            false => print(benchmark, &outcome, config.histogram),
+        }
+    }
+    match Arc::try_unwrap(db) {
+        Ok(db) => db.close().await?,
+        Err(_) => unreachable!("every task is done with the database"),
+    }
+    if let Some(temp) = temp {
+        Db::destroy(&temp).await?;
+    }
+    Ok(())
+}
+
+fn micros(latency: Duration) -> f64 {
+    latency.as_nanos() as f64 / 1000.0
+}
+
+fn throughput(outcome: &Outcome) -> (f64, f64) {
+    let secs = outcome.elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+    let ops = outcome.latencies.count() as f64 / secs;
+    let mb = outcome.bytes as f64 / (1 << 20) as f64 / secs;
+    (ops, mb)
+}
+
+const PERCENTILES: [(&str, f64); 4] =
+    [("p50", 50.0), ("p90", 90.0), ("p99", 99.0), ("p99.9", 99.9)];
+
+fn print(benchmark: Benchmark, outcome: &Outcome, histogram: bool) {
+    let latencies = &outcome.latencies;
+    let (ops, mb) = throughput(outcome);
+    print!(
+        "{:<18}: {:>10.3} micros/op {:>10.0} ops/sec {:>8.1} MB/s",
+        benchmark.name(),
+        micros(latencies.mean()),
+        ops,
+        mb
+    );
+    match benchmark {
+        Benchmark::ReadRandom | Benchmark::ReadWhileWriting => {
+            print!(" ({} of {} found)", outcome.found, latencies.count())
+        }
+        Benchmark::Scan => print!(" ({} entries)", outcome.found),
+        _ => {}
+    }
+    if benchmark == Benchmark::ReadWhileWriting {
+        print!(" ({} writes in the background)", outcome.background_writes);
+    }
+    println!();
+    let percentiles: Vec<_> = PERCENTILES
+        .iter()
+        .map(|&(name, p)| format!("{} {:.1}", name, micros(latencies.percentile(p))))
+        .collect();
+    println!(
+        "{:<18}  latency (us): {}, max {:.1}",
+        "",
+        percentiles.join(", "),
+        micros(latencies.max())
+    );
+    if histogram {
+        print_histogram(latencies);
+    }
+}
+
+fn print_histogram(latencies: &Histogram) {
+    let total = latencies.count().max(1) as f64;
+    let mut seen = 0;
+    println!(
+        "{:>14} {:>14} {:>10} {:>7} {:>7}",
+        "from (us)", "to (us)", "count", "%", "cum %"
+    );
+    for (low, high, count) in latencies.buckets() {
+        seen += count;
+        println!(
+            "{:>14.3} {:>14.3} {:>10} {:>7.2} {:>7.2}",
+            micros(low),
+            micros(high),
+            count,
+            count as f64 * 100.0 / total,
+            seen as f64 * 100.0 / total
+        );
+    }
+}
+
+fn to_json(benchmark: Benchmark, outcome: &Outcome, histogram: bool) -> serde_json::Value {
+    let latencies = &outcome.latencies;
+    let (ops, mb) = throughput(outcome);
+    let mut latency = json!({
+        "mean": micros(latencies.mean()),
+        "max": micros(latencies.max()),
+    });
+    for (name, p) in PERCENTILES {
+        latency[name] = micros(latencies.percentile(p)).into();
+    }
+    let mut out = json!({
+        "benchmark": benchmark.name(),
+        "ops": latencies.count(),
+        "elapsed_secs": outcome.elapsed.as_secs_f64(),
+        "ops_per_sec": ops,
+        "mb_per_sec": mb,
+        "found": outcome.found,
+        "background_writes": outcome.background_writes,
+        "latency_us": latency,
+    });
+    if histogram {
+        out["histogram"] = latencies
+            .buckets()
+            .map(|(low, high, count)| {
+                json!({ "from_us": micros(low), "to_us": micros(high), "count": count })
+            })
+            .collect();
+    }
+    out
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match parse(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(2);
+        }
+    };
+    match run(config).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("ndb-bench: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
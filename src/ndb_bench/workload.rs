@@ -0,0 +1,273 @@
+// The benchmarks, and the keys and values they use.
+
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use nulldb::{Db, NdbError};
+
+use crate::{histogram::Histogram, Config};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Benchmark {
+    FillSeq,
+    FillRandom,
+    ReadRandom,
+    ReadWhileWriting,
+    Scan,
+}
+
+impl Benchmark {
+    pub fn parse(name: &str) -> Result<Benchmark, String> {
+        match name {
+            "fillseq" => Ok(Benchmark::FillSeq),
+            "fillrandom" => Ok(Benchmark::FillRandom),
+            "readrandom" => Ok(Benchmark::ReadRandom),
+            "readwhilewriting" => Ok(Benchmark::ReadWhileWriting),
+            "scan" => Ok(Benchmark::Scan),
+            _ => Err(format!("unknown benchmark {}", name)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Benchmark::FillSeq => "fillseq",
+            Benchmark::FillRandom => "fillrandom",
+            Benchmark::ReadRandom => "readrandom",
+            Benchmark::ReadWhileWriting => "readwhilewriting",
+            Benchmark::Scan => "scan",
+        }
+    }
+}
+
+// What a benchmark did, across all its tasks. Writes made in the background
+// by readwhilewriting are counted in `background_writes`, not `latencies`.
+pub struct Outcome {
+    pub latencies: Histogram,
+    pub elapsed: Duration,
+    pub bytes: u64,
+    pub found: u64,
+    pub background_writes: u64,
+}
+
+// The operations one task did.
+struct TaskOutcome {
+    latencies: Histogram,
+    bytes: u64,
+    found: u64,
+}
+
+pub async fn run(
+    db: &Arc<Db>,
+    benchmark: Benchmark,
+    config: &Arc<Config>,
+) -> Result<Outcome, Box<dyn Error>> {
+    let ops = match benchmark {
+        Benchmark::FillSeq | Benchmark::FillRandom => config.num,
+        _ => config.reads,
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    let writer = (benchmark == Benchmark::ReadWhileWriting).then(|| {
+        let (db, config, stop) = (db.clone(), config.clone(), stop.clone());
+        tokio::spawn(async move { write_until(&db, &config, &stop).await })
+    });
+
+    let start = Instant::now();
+    let mut tasks = Vec::new();
+    for task in 0..config.tasks {
+        // Ops are shared out as evenly as they go.
+        let share = ops / config.tasks + u64::from(task < ops % config.tasks);
+        let first = task * (ops / config.tasks) + task.min(ops % config.tasks);
+        let (db, config) = (db.clone(), config.clone());
+        tasks.push(tokio::spawn(async move {
+            let mut keys = Keys::new(&config, task);
+            let mut out = TaskOutcome {
+                latencies: Histogram::new(),
+                bytes: 0,
+                found: 0,
+            };
+            for i in first..first + share {
+                let key = match benchmark {
+                    Benchmark::FillSeq => key(i, config.key_size),
+                    _ => keys.next(),
+                };
+                let started = Instant::now();
+                match benchmark {
+                    Benchmark::FillSeq | Benchmark::FillRandom => {
+                        let value = keys.value(config.value_size);
+                        db.put(&key, &value).await?;
+                        out.bytes += (key.len() + value.len()) as u64;
+                    }
+                    Benchmark::ReadRandom | Benchmark::ReadWhileWriting => {
+                        if let Some(value) = db.get(&key).await? {
+                            out.found += 1;
+                            out.bytes += (key.len() + value.len()) as u64;
+                        }
+                    }
+                    Benchmark::Scan => {
+                        let mut iter = db.scan(key..).await?;
+                        for _ in 0..config.scan_length {
+                            let Some((key, value)) = iter.next().await? else {
+                                break;
+                            };
+                            out.found += 1;
+                            out.bytes += (key.len() + value.len()) as u64;
+                        }
+                    }
+                }
+                out.latencies.record(started.elapsed());
+            }
+            Ok::<_, NdbError>(out)
+        }));
+    }
+
+    let mut outcome = Outcome {
+        latencies: Histogram::new(),
+        elapsed: Duration::ZERO,
+        bytes: 0,
+        found: 0,
+        background_writes: 0,
+    };
+    for task in tasks {
+        let task = task.await??;
+        outcome.latencies.merge(&task.latencies);
+        outcome.bytes += task.bytes;
+        outcome.found += task.found;
+    }
+    outcome.elapsed = start.elapsed();
+    stop.store(true, Ordering::Relaxed);
+    if let Some(writer) = writer {
+        outcome.background_writes = writer.await??;
+    }
+    Ok(outcome)
+}
+
+// Writes random keys as fast as it can until told to stop, returning how many
+// it wrote.
+async fn write_until(db: &Db, config: &Config, stop: &AtomicBool) -> Result<u64, NdbError> {
+    // Its keys are independent of every reader's.
+    let mut keys = Keys::new(config, config.tasks);
+    let mut written = 0;
+    while !stop.load(Ordering::Relaxed) {
+        let key = keys.next();
+        db.put(&key, &keys.value(config.value_size)).await?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+// Key `i` of the key space, padded out to `size` bytes where it's shorter, so
+// keys sort in numeric order.
+pub fn key(i: u64, size: usize) -> Vec<u8> {
+    let key = format!("user{:012}", i);
+    format!("{:0<size$}", key).into_bytes()
+}
+
+// A task's random keys, drawn from the configured distribution, and values.
+struct Keys {
+    rng: SplitMix64,
+    zipfian: Option<Arc<Zipfian>>,
+    num: u64,
+    key_size: usize,
+}
+
+impl Keys {
+    fn new(config: &Config, task: u64) -> Keys {
+        Keys {
+            rng: SplitMix64(config.seed.wrapping_add(task.wrapping_mul(0x9e37_79b9))),
+            zipfian: config.zipfian.clone(),
+            num: config.num.max(1),
+            key_size: config.key_size,
+        }
+    }
+
+    fn next(&mut self) -> Vec<u8> {
+        let i = match &self.zipfian {
+            None => self.rng.next() % self.num,
+            // Scrambled, as in YCSB, so the popular keys aren't all together
+            // at the start of the key space.
+            Some(zipfian) => fnv(zipfian.next(&mut self.rng)) % self.num,
+        };
+        key(i, self.key_size)
+    }
+
+    fn value(&mut self, size: usize) -> Vec<u8> {
+        let mut value = Vec::with_capacity(size + 8);
+        while value.len() < size {
+            value.extend_from_slice(&self.rng.next().to_le_bytes());
+        }
+        value.truncate(size);
+        value
+    }
+}
+
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn fnv(i: u64) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in i.to_le_bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+// Picks items 0..n with the first far more likely than the last, as in "Quickly
+// Generating Billion-Record Synthetic Databases" (Gray et al.), which YCSB
+// uses. Working out `zeta_n` takes a pass over every item, so it's done once
+// and shared between tasks.
+pub struct Zipfian {
+    n: u64,
+    theta: f64,
+    alpha: f64,
+    zeta_n: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    pub fn new(n: u64, theta: f64) -> Zipfian {
+        let n = n.max(1);
+        let zeta = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let zeta_n = zeta(n);
+        Zipfian {
+            n,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zeta_n,
+            eta: (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta(2) / zeta_n),
+        }
+    }
+
+    fn next(&self, rng: &mut SplitMix64) -> u64 {
+        let u = rng.next_f64();
+        let uz = u * self.zeta_n;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.n - 1);
+        }
+        let i = self.n as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha);
+        (i as u64).min(self.n - 1)
+    }
+}
@@ -0,0 +1,219 @@
+// `ndb-server`: serves a database over TCP, to any number of clients at once,
+// in the protocol described in protocol.rs, and optionally the Redis one too,
+// as described in resp.rs, and gRPC, as described in proto/nulldb.proto.
+
+mod protocol;
+mod resp;
+mod security;
+mod server;
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
+
+use nulldb::Db;
+use security::Acceptor;
+use server::Protocol;
+use tokio::{net::TcpListener, sync::watch};
+
+const USAGE: &str = "\
+usage: ndb-server [<options>] <dir>
+
+options:
+  --addr <host:port>        address to listen on (default 127.0.0.1:7878)
+  --resp-addr <host:port>   address to also listen on for Redis clients
+  --grpc-addr <host:port>   address to also listen on for gRPC clients (needs
+                            the grpc feature)
+  --max-connections <n>     clients served at once on each address but the
+                            gRPC one; more wait to be accepted (default 1024)
+  --max-frame-bytes <n>     biggest request accepted (default 67108864)
+  --max-scan <n>            most entries a scan returns at once (default 1000)
+  --tls-cert <file>         serve over TLS, with the certificate chain in this
+                            PEM file (needs the tls feature)
+  --tls-key <file>          the PEM file with the certificate's private key
+  --auth-token-file <file>  make clients authenticate with the token in this
+                            file before anything else
+
+Serves get, put, delete and scan on the database in <dir>, which is created
+if it doesn't exist, until interrupted. Prints the address it's listening on
+once it is, and then the ones for Redis and gRPC clients if there are any.
+
+With a token, clients send it in an auth request, with Redis's AUTH command,
+or as a gRPC \"authorization: Bearer <token>\" header.";
+
+pub struct Config {
+    dir: PathBuf,
+    addr: String,
+    resp_addr: Option<String>,
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    grpc_addr: Option<String>,
+    pub max_connections: usize,
+    pub max_frame_bytes: u32,
+    pub max_scan: usize,
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub tls_cert: Option<PathBuf>,
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub tls_key: Option<PathBuf>,
+    auth_token_file: Option<PathBuf>,
+    // Loaded from the files above once the arguments have been checked.
+    pub tls: Option<Acceptor>,
+    pub token: Option<String>,
+}
+
+fn parse(args: &[String]) -> Result<Config, String> {
+    let mut dir = None;
+    let mut addr = "127.0.0.1:7878".to_string();
+    let (mut resp_addr, mut grpc_addr) = (None, None);
+    let (mut tls_cert, mut tls_key, mut auth_token_file) = (None, None, None);
+    let (mut max_connections, mut max_frame_bytes, mut max_scan) = (1024, 64 << 20, 1000);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        fn number<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("bad {} {}", arg.trim_start_matches('-'), value))
+        }
+        match arg.as_str() {
+            "--addr" => addr = value()?.clone(),
+            "--resp-addr" => resp_addr = Some(value()?.clone()),
+            "--grpc-addr" if cfg!(feature = "grpc") => grpc_addr = Some(value()?.clone()),
+            "--grpc-addr" => return Err("ndb-server was built without the grpc feature".into()),
+            "--max-connections" => max_connections = number(arg, value()?)?,
+            "--max-frame-bytes" => max_frame_bytes = number(arg, value()?)?,
+            "--max-scan" => max_scan = number(arg, value()?)?,
+            "--tls-cert" | "--tls-key" if !cfg!(feature = "tls") => {
+                return Err("ndb-server was built without the tls feature".into())
+            }
+            "--tls-cert" => tls_cert = Some(PathBuf::from(value()?)),
+            "--tls-key" => tls_key = Some(PathBuf::from(value()?)),
+            "--auth-token-file" => auth_token_file = Some(PathBuf::from(value()?)),
+            _ if !arg.starts_with("--") && dir.is_none() => dir = Some(PathBuf::from(arg)),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    if max_connections == 0 || max_scan == 0 {
+        return Err("--max-connections and --max-scan have to be at least 1".into());
+    }
+    if tls_cert.is_some() != tls_key.is_some() {
+        return Err("--tls-cert and --tls-key go together".into());
+    }
+    Ok(Config {
+        dir: dir.ok_or(USAGE)?,
+        addr,
+        resp_addr,
+        grpc_addr,
+        max_connections,
+        max_frame_bytes,
+        max_scan,
+        tls_cert,
+        tls_key,
+        auth_token_file,
+        tls: None,
+        token: None,
+    })
+}
+
+// The token in `path`, without any whitespace around it, e.g. a newline at
+// the end.
+fn read_token(path: &Path) -> Result<String, Box<dyn Error>> {
+    let token = std::fs::read_to_string(path)
+        .map_err(|err| format!("{}: {}", path.display(), err))?
+        .trim()
+        .to_string();
+    if token.is_empty() {
+        return Err(format!("{} is empty", path.display()).into());
+    }
+    Ok(token)
+}
+
+// Resolves once the process is asked to stop.
+async fn interrupted() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("signal handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn run(mut config: Config) -> Result<(), Box<dyn Error>> {
+    if let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) {
+        config.tls = Some(security::acceptor(cert, key)?);
+    }
+    config.token = config
+        .auth_token_file
+        .as_deref()
+        .map(read_token)
+        .transpose()?;
+    let config = Arc::new(config);
+    let db = Arc::new(Db::new(&config.dir).await?);
+    let listener = TcpListener::bind(&config.addr).await?;
+    println!("listening on {}", listener.local_addr()?);
+    let mut listeners = vec![(listener, Protocol::Binary)];
+    if let Some(addr) = &config.resp_addr {
+        let listener = TcpListener::bind(addr).await?;
+        println!("listening for resp on {}", listener.local_addr()?);
+        listeners.push((listener, Protocol::Resp));
+    }
+
+    let (stop, shutdown) = watch::channel(false);
+    let mut servers = Vec::new();
+    for (listener, protocol) in listeners {
+        servers.push(tokio::spawn(server::serve(
+            listener,
+            protocol,
+            db.clone(),
+            config.clone(),
+            shutdown.clone(),
+        )));
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = &config.grpc_addr {
+        let listener = TcpListener::bind(addr).await?;
+        println!("listening for grpc on {}", listener.local_addr()?);
+        servers.push(tokio::spawn(server::serve_grpc(
+            listener,
+            db.clone(),
+            config.clone(),
+            shutdown.clone(),
+        )));
+    }
+    interrupted().await;
+    stop.send_replace(true);
+    for server in servers {
+        server.await?;
+    }
+    match Arc::try_unwrap(db) {
+        Ok(db) => db.close().await?,
+        Err(_) => unreachable!("every connection is done with the database"),
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match parse(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(2);
+        }
+    };
+    match run(config).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("ndb-server: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
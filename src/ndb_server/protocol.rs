@@ -0,0 +1,193 @@
+// The protocol ndb-server speaks. Every message, each way, is a frame: its
+// length as a little-endian u32, then that many bytes. A client sends
+// requests and gets a response to each, in the order it sent them, and can
+// send more before reading the responses to earlier ones. The nulldb-client
+// crate, in client/, is a client for it.
+//
+// A request is an opcode byte followed by its fields, and a response a status
+// byte followed by its. Byte strings are a u32 length and then the bytes, and
+// every integer is little endian.
+//
+//   get     1 | key                 -> ok | value, or not found
+//   put     2 | key | value         -> ok
+//   delete  3 | key                 -> ok
+//   scan    4 | flags | [start] | [end] | limit (u32)
+//                                   -> ok | more (u8) | count (u32) |
+//                                      (key | value) * count
+//   auth    5 | token               -> ok
+//
+// A scan's flags are 1 if it has a start key, which is included, and 2 if it
+// has an end key, which isn't. It returns at most `limit` entries, or the
+// server's maximum if that's lower or `limit` is 0, and `more` is 1 if the
+// range has entries past them, which a client can ask for by scanning again
+// from just after the last key.
+//
+// A server started with a token answers every request but auth with an
+// error until it's been sent, and closes the connection if it's wrong.
+//
+// Statuses are 0 for ok, 1 for not found and 2 for an error, which is
+// followed by a message saying what went wrong.
+
+use std::io;
+
+use nulldb::KeyValue;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const GET: u8 = 1;
+const PUT: u8 = 2;
+const DELETE: u8 = 3;
+const SCAN: u8 = 4;
+const AUTH: u8 = 5;
+
+const OK: u8 = 0;
+const NOT_FOUND: u8 = 1;
+const ERROR: u8 = 2;
+
+const HAS_START: u8 = 1;
+const HAS_END: u8 = 2;
+
+pub enum Request {
+    Get(Vec<u8>),
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    Scan {
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        limit: u32,
+    },
+    Auth(Vec<u8>),
+}
+
+pub enum Response {
+    Ok,
+    Value(Vec<u8>),
+    NotFound,
+    Entries { entries: Vec<KeyValue>, more: bool },
+    Error(String),
+}
+
+// Takes fields off the front of a request.
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let (&byte, rest) = self.0.split_first().ok_or("request cut short")?;
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let (n, rest) = self.0.split_first_chunk().ok_or("request cut short")?;
+        self.0 = rest;
+        Ok(u32::from_le_bytes(*n))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.u32()? as usize;
+        if len > self.0.len() {
+            return Err("request cut short".into());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes.to_vec())
+    }
+}
+
+impl Request {
+    pub fn decode(frame: &[u8]) -> Result<Request, String> {
+        let mut fields = Fields(frame);
+        let request = match fields.byte()? {
+            GET => Request::Get(fields.bytes()?),
+            PUT => Request::Put(fields.bytes()?, fields.bytes()?),
+            DELETE => Request::Delete(fields.bytes()?),
+            SCAN => {
+                let flags = fields.byte()?;
+                let start = match flags & HAS_START {
+                    0 => None,
+                    _ => Some(fields.bytes()?),
+                };
+                let end = match flags & HAS_END {
+                    0 => None,
+                    _ => Some(fields.bytes()?),
+                };
+                Request::Scan {
+                    start,
+                    end,
+                    limit: fields.u32()?,
+                }
+            }
+            AUTH => Request::Auth(fields.bytes()?),
+            op => return Err(format!("unknown request type {}", op)),
+        };
+        if !fields.0.is_empty() {
+            return Err("request has trailing bytes".into());
+        }
+        Ok(request)
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+impl Response {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Response::Ok => buf.push(OK),
+            Response::Value(value) => {
+                buf.push(OK);
+                put_bytes(&mut buf, value);
+            }
+            Response::NotFound => buf.push(NOT_FOUND),
+            Response::Entries { entries, more } => {
+                buf.push(OK);
+                buf.push(u8::from(*more));
+                buf.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+                for (key, value) in entries {
+                    put_bytes(&mut buf, key);
+                    put_bytes(&mut buf, value);
+                }
+            }
+            Response::Error(message) => {
+                buf.push(ERROR);
+                put_bytes(&mut buf, message.as_bytes());
+            }
+        }
+        buf
+    }
+}
+
+pub enum FrameError {
+    // Refused without reading it, so the connection can't carry on.
+    TooLarge(u32),
+    Io(io::Error),
+}
+
+// Reads the next frame, or `None` if the connection was closed between
+// frames.
+pub async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+    max_bytes: u32,
+) -> Result<Option<Vec<u8>>, FrameError> {
+    let len = match reader.read_u32_le().await {
+        Ok(len) => len,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(FrameError::Io(err)),
+    };
+    if len > max_bytes {
+        return Err(FrameError::TooLarge(len));
+    }
+    let mut frame = vec![0; len as usize];
+    reader
+        .read_exact(&mut frame)
+        .await
+        .map_err(FrameError::Io)?;
+    Ok(Some(frame))
+}
+
+pub async fn write_frame(writer: &mut (impl AsyncWrite + Unpin), frame: &[u8]) -> io::Result<()> {
+    writer.write_u32_le(frame.len() as u32).await?;
+    writer.write_all(frame).await
+}
@@ -0,0 +1,567 @@
+// The Redis protocol (RESP2), for redis-cli and Redis client libraries. Keys
+// and values are Redis strings; the commands are GET, SET (with EX, PX, NX
+// or XX), DEL, EXISTS, SCAN (with MATCH and COUNT), TTL and PTTL, and enough
+// of PING, ECHO, SELECT, CLIENT, COMMAND and QUIT for clients to connect.
+//
+// With a token, clients have to send it with AUTH before anything else.
+//
+// Commands come as arrays of bulk strings, or as a line of words for anyone
+// typing them in by hand. A reply to each goes back in the order they came
+// in, so clients can pipeline them.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Bound,
+    sync::Mutex,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use nulldb::{Db, NdbError, WriteBatch};
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader, BufWriter,
+    },
+    sync::watch,
+};
+
+use crate::{security, Config};
+
+// The most SCAN cursors kept at once. Past it, the oldest is forgotten, and a
+// client still using it gets an error.
+const MAX_CURSORS: usize = 10_000;
+
+enum Reply {
+    Ok,
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            Reply::Ok => buf.extend_from_slice(b"+OK\r\n"),
+            Reply::Simple(s) => buf.extend_from_slice(format!("+{}\r\n", s).as_bytes()),
+            // A newline would end the error early.
+            Reply::Error(message) => {
+                let message = message.replace(['\r', '\n'], " ");
+                buf.extend_from_slice(format!("-{}\r\n", message).as_bytes());
+            }
+            Reply::Integer(n) => buf.extend_from_slice(format!(":{}\r\n", n).as_bytes()),
+            Reply::Bulk(None) => buf.extend_from_slice(b"$-1\r\n"),
+            Reply::Bulk(Some(bytes)) => {
+                buf.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+                buf.extend_from_slice(bytes);
+                buf.extend_from_slice(b"\r\n");
+            }
+            Reply::Array(replies) => {
+                buf.extend_from_slice(format!("*{}\r\n", replies.len()).as_bytes());
+                for reply in replies {
+                    reply.encode_to(buf);
+                }
+            }
+        }
+    }
+}
+
+impl From<NdbError> for Reply {
+    fn from(err: NdbError) -> Reply {
+        Reply::Error(format!("ERR {}", err))
+    }
+}
+
+// SCAN cursors, shared by every connection, since a client can carry on
+// scanning on another one. Each is a number standing for the key to carry
+// on from, since that's all many clients accept for a cursor.
+#[derive(Default)]
+pub struct Cursors(Mutex<CursorTable>);
+
+#[derive(Default)]
+struct CursorTable {
+    next: u64,
+    keys: HashMap<u64, Vec<u8>>,
+    // Oldest first.
+    order: VecDeque<u64>,
+}
+
+impl Cursors {
+    fn add(&self, key: Vec<u8>) -> u64 {
+        let mut table = self.0.lock().unwrap();
+        // 0 is where every scan starts and ends.
+        table.next += 1;
+        let cursor = table.next;
+        table.keys.insert(cursor, key);
+        table.order.push_back(cursor);
+        if table.order.len() > MAX_CURSORS {
+            let oldest = table.order.pop_front().unwrap();
+            table.keys.remove(&oldest);
+        }
+        cursor
+    }
+
+    fn get(&self, cursor: u64) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().keys.get(&cursor).cloned()
+    }
+}
+
+// Why the connection can't carry on.
+enum ProtocolError {
+    Invalid(String),
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for ProtocolError {
+    fn from(err: std::io::Error) -> ProtocolError {
+        ProtocolError::Io(err)
+    }
+}
+
+// Reads a line ending in \r\n, or just \n, without it, and without reading
+// more than `max` bytes looking for its end. `None` if the connection was
+// closed first.
+async fn read_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    max: usize,
+) -> Result<Option<Vec<u8>>, ProtocolError> {
+    let mut line = Vec::new();
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            return match line.is_empty() {
+                true => Ok(None),
+                false => Err(ProtocolError::Invalid("connection closed mid-line".into())),
+            };
+        }
+        let (end, found) = match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (buf.len(), false),
+        };
+        line.extend_from_slice(&buf[..end]);
+        reader.consume(end);
+        if found {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            return Ok(Some(line));
+        }
+        if line.len() > max {
+            return Err(ProtocolError::Invalid("line too long".into()));
+        }
+    }
+}
+
+fn number(line: &[u8]) -> Result<i64, ProtocolError> {
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| ProtocolError::Invalid("bad length".into()))
+}
+
+// Reads the next command, or `None` if the connection was closed between
+// commands. An empty line is an empty command.
+async fn read_command(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    max_bytes: usize,
+) -> Result<Option<Vec<Vec<u8>>>, ProtocolError> {
+    let Some(line) = read_line(reader, max_bytes).await? else {
+        return Ok(None);
+    };
+    let Some(count) = line.strip_prefix(b"*") else {
+        let words = line.split(|b| b.is_ascii_whitespace());
+        return Ok(Some(
+            words
+                .filter(|w| !w.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect(),
+        ));
+    };
+    let count = number(count)?;
+    if count < 0 || count as usize > max_bytes {
+        return Err(ProtocolError::Invalid("bad array length".into()));
+    }
+    let mut args = Vec::with_capacity(count.min(1024) as usize);
+    let mut total = 0;
+    for _ in 0..count {
+        let line = read_line(reader, max_bytes)
+            .await?
+            .ok_or_else(|| ProtocolError::Invalid("connection closed mid-command".into()))?;
+        let len = match line.strip_prefix(b"$") {
+            Some(len) => number(len)?,
+            None => return Err(ProtocolError::Invalid("expected a bulk string".into())),
+        };
+        total += len.max(0) as usize;
+        if len < 0 || total > max_bytes {
+            return Err(ProtocolError::Invalid("bad bulk length".into()));
+        }
+        let mut arg = vec![0; len as usize + 2];
+        reader.read_exact(&mut arg).await?;
+        if !arg.ends_with(b"\r\n") {
+            return Err(ProtocolError::Invalid(
+                "bulk string not followed by CRLF".into(),
+            ));
+        }
+        arg.truncate(len as usize);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+pub async fn connection(
+    stream: impl AsyncRead + AsyncWrite,
+    db: &Db,
+    config: &Config,
+    cursors: &Cursors,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), std::io::Error> {
+    let (reader, writer) = tokio::io::split(stream);
+    let (mut reader, mut writer) = (BufReader::new(reader), BufWriter::new(writer));
+    let mut authenticated = config.token.is_none();
+    let mut buf = Vec::new();
+    loop {
+        let command = tokio::select! {
+            command = read_command(&mut reader, config.max_frame_bytes as usize) => command,
+            _ = shutdown.changed() => break,
+        };
+        let (reply, quit) = match command {
+            Ok(Some(args)) if args.is_empty() => continue,
+            Ok(Some(args)) => match args[0].to_ascii_uppercase().as_slice() {
+                b"QUIT" => (Reply::Ok, true),
+                b"AUTH" => (auth(config, &args, &mut authenticated), false),
+                _ if !authenticated => (
+                    Reply::Error("NOAUTH Authentication required.".into()),
+                    false,
+                ),
+                _ => (handle(db, cursors, &args).await, false),
+            },
+            Ok(None) => break,
+            Err(ProtocolError::Invalid(message)) => (
+                Reply::Error(format!("ERR Protocol error: {}", message)),
+                true,
+            ),
+            Err(ProtocolError::Io(err)) => return Err(err),
+        };
+        buf.clear();
+        reply.encode_to(&mut buf);
+        writer.write_all(&buf).await?;
+        if quit {
+            break;
+        }
+        // Replies to commands that were sent together go back together.
+        if reader.buffer().is_empty() {
+            writer.flush().await?;
+        }
+    }
+    writer.flush().await
+}
+
+fn wrong_args(command: &[u8]) -> Reply {
+    Reply::Error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        String::from_utf8_lossy(command).to_lowercase()
+    ))
+}
+
+fn syntax_error() -> Reply {
+    Reply::Error("ERR syntax error".into())
+}
+
+fn invalid_expire_time() -> Reply {
+    Reply::Error("ERR invalid expire time in 'set' command".into())
+}
+
+// AUTH [username] password. There's only the one user, so any username
+// will do.
+fn auth(config: &Config, args: &[Vec<u8>], authenticated: &mut bool) -> Reply {
+    let password = match args {
+        [_, password] | [_, _, password] => password,
+        _ => return wrong_args(&args[0]),
+    };
+    let Some(token) = &config.token else {
+        return Reply::Error(
+            "ERR AUTH <password> called without any password configured for the default user. \
+             Are you sure your configuration is correct?"
+                .into(),
+        );
+    };
+    match security::token_matches(token, password) {
+        true => {
+            *authenticated = true;
+            Reply::Ok
+        }
+        false => {
+            Reply::Error("WRONGPASS invalid username-password pair or user is disabled.".into())
+        }
+    }
+}
+
+fn integer(arg: &[u8]) -> Option<i64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+async fn handle(db: &Db, cursors: &Cursors, args: &[Vec<u8>]) -> Reply {
+    let name = args[0].to_ascii_uppercase();
+    let result = match (name.as_slice(), &args[1..]) {
+        (b"GET", [key]) => db.get(key).await.map(Reply::Bulk),
+        (b"SET", [key, value, options @ ..]) => set(db, key, value, options).await,
+        (b"DEL", keys) if !keys.is_empty() => del(db, keys).await,
+        (b"EXISTS", keys) if !keys.is_empty() => exists(db, keys).await,
+        (b"SCAN", [cursor, options @ ..]) => scan(db, cursors, cursor, options).await,
+        (b"TTL", [key]) => ttl(db, key, Duration::from_secs(1)).await,
+        (b"PTTL", [key]) => ttl(db, key, Duration::from_millis(1)).await,
+        (b"PING", []) => Ok(Reply::Simple("PONG")),
+        (b"PING" | b"ECHO", [message]) => Ok(Reply::Bulk(Some(message.clone()))),
+        (b"SELECT", [index]) => Ok(match integer(index) {
+            Some(0) => Reply::Ok,
+            _ => Reply::Error("ERR DB index is out of range".into()),
+        }),
+        // Client names and the like are accepted, and ignored.
+        (b"CLIENT", [_, ..]) => Ok(Reply::Ok),
+        // What redis-cli asks for to give hints; it does without.
+        (b"COMMAND", _) => Ok(Reply::Array(Vec::new())),
+        (
+            b"GET" | b"SET" | b"DEL" | b"EXISTS" | b"SCAN" | b"TTL" | b"PTTL" | b"PING" | b"ECHO"
+            | b"SELECT" | b"CLIENT",
+            _,
+        ) => Ok(wrong_args(&name)),
+        _ => Ok(Reply::Error(format!(
+            "ERR unknown command '{}'",
+            String::from_utf8_lossy(&args[0])
+        ))),
+    };
+    result.unwrap_or_else(Reply::from)
+}
+
+// SET key value [NX | XX] [EX seconds | PX milliseconds], with the options
+// in any order.
+async fn set(db: &Db, key: &[u8], value: &[u8], options: &[Vec<u8>]) -> Result<Reply, NdbError> {
+    let (mut ttl, mut nx, mut xx) = (None, false, false);
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_uppercase().as_slice() {
+            unit @ (b"EX" | b"PX") if ttl.is_none() => {
+                let Some(n) = options.next().and_then(|n| integer(n)).filter(|&n| n > 0) else {
+                    return Ok(invalid_expire_time());
+                };
+                let duration = match unit {
+                    b"EX" => Duration::from_secs(n as u64),
+                    _ => Duration::from_millis(n as u64),
+                };
+                // Redis keeps expiries in i64 unix milliseconds, and turns
+                // away any that don't fit.
+                let expires_at = db.options().clock.now().checked_add(duration);
+                let since_epoch = expires_at.and_then(|at| at.duration_since(UNIX_EPOCH).ok());
+                if since_epoch.is_none_or(|since| i64::try_from(since.as_millis()).is_err()) {
+                    return Ok(invalid_expire_time());
+                }
+                ttl = Some(duration);
+            }
+            b"NX" if !xx => nx = true,
+            b"XX" if !nx => xx = true,
+            _ => return Ok(syntax_error()),
+        }
+    }
+    if !nx && !xx {
+        match ttl {
+            Some(ttl) => db.put_with_ttl(key, value, ttl).await?,
+            None => db.put(key, value).await?,
+        }
+        return Ok(Reply::Ok);
+    }
+    // Tried again whenever the key's written between the check and the write.
+    loop {
+        let mut txn = db.transaction();
+        // NX only sets a key that isn't there, and XX one that is, whatever
+        // its value.
+        if txn.get(key).await?.is_some() == nx {
+            return Ok(Reply::Bulk(None));
+        }
+        match ttl {
+            Some(ttl) => txn.put_with_ttl(key, value, ttl),
+            None => txn.put(key, value),
+        }
+        match txn.commit().await {
+            Ok(()) => return Ok(Reply::Ok),
+            Err(NdbError::Conflict) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// How many of `keys` there are, counting repeats each time.
+async fn count_existing(db: &Db, keys: &[Vec<u8>]) -> Result<i64, NdbError> {
+    let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+    let values = db.multi_get(&keys).await?;
+    Ok(values.iter().filter(|value| value.is_some()).count() as i64)
+}
+
+async fn del(db: &Db, keys: &[Vec<u8>]) -> Result<Reply, NdbError> {
+    let mut unique = keys.to_vec();
+    unique.sort();
+    unique.dedup();
+    let deleted = count_existing(db, &unique).await?;
+    let mut batch = WriteBatch::new();
+    for key in &unique {
+        batch.delete(key);
+    }
+    db.write(batch).await?;
+    Ok(Reply::Integer(deleted))
+}
+
+async fn exists(db: &Db, keys: &[Vec<u8>]) -> Result<Reply, NdbError> {
+    Ok(Reply::Integer(count_existing(db, keys).await?))
+}
+
+// -2 if there's no such key, -1 if it doesn't expire, and otherwise how long
+// until it does, rounded to the nearest `unit`.
+async fn ttl(db: &Db, key: &[u8], unit: Duration) -> Result<Reply, NdbError> {
+    let remaining = match db.get_with_expiry(key).await? {
+        None => return Ok(Reply::Integer(-2)),
+        Some((_, None)) => return Ok(Reply::Integer(-1)),
+        Some((_, Some(expires_at))) => expires_at
+            .duration_since(db.options().clock.now())
+            .unwrap_or_default(),
+    };
+    let rounded = (remaining + unit / 2).as_nanos() / unit.as_nanos();
+    Ok(Reply::Integer(rounded as i64))
+}
+
+// SCAN cursor [MATCH pattern] [COUNT count]. Looks at up to `count` keys
+// from where the cursor left off, and replies with a cursor to carry on
+// from, or 0 if that was the last of them, and those that match.
+async fn scan(
+    db: &Db,
+    cursors: &Cursors,
+    cursor: &[u8],
+    options: &[Vec<u8>],
+) -> Result<Reply, NdbError> {
+    let (mut pattern, mut count) = (None, 10);
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match (option.to_ascii_uppercase().as_slice(), options.next()) {
+            (b"MATCH", Some(glob)) => pattern = Some(glob),
+            (b"COUNT", Some(n)) => match integer(n).filter(|&n| n > 0) {
+                Some(n) => count = n as usize,
+                None => return Ok(syntax_error()),
+            },
+            // Every key is a string.
+            (b"TYPE", Some(kind)) if kind.eq_ignore_ascii_case(b"string") => {}
+            (b"TYPE", Some(_)) => return Ok(Reply::Array(vec![bulk(b"0"), Reply::Array(vec![])])),
+            _ => return Ok(syntax_error()),
+        }
+    }
+    let start = match integer(cursor) {
+        Some(0) => Bound::Unbounded,
+        Some(n) if n > 0 => match cursors.get(n as u64) {
+            Some(key) => Bound::Included(key),
+            None => return Ok(Reply::Error("ERR invalid cursor".into())),
+        },
+        _ => return Ok(Reply::Error("ERR invalid cursor".into())),
+    };
+    let mut iter = db.scan((start, Bound::Unbounded)).await?;
+    let mut keys = Vec::new();
+    let mut next = 0;
+    for _ in 0..count {
+        let Some((key, _)) = iter.next().await? else {
+            break;
+        };
+        if pattern.is_none_or(|pattern| glob_match(pattern, &key)) {
+            keys.push(bulk(&key));
+        }
+    }
+    if let Some((key, _)) = iter.next().await? {
+        next = cursors.add(key);
+    }
+    Ok(Reply::Array(vec![
+        bulk(next.to_string().as_bytes()),
+        Reply::Array(keys),
+    ]))
+}
+
+fn bulk(bytes: &[u8]) -> Reply {
+    Reply::Bulk(Some(bytes.to_vec()))
+}
+
+// Whether `text` matches a Redis glob: `*` for any run of bytes, `?` for any
+// one, `[...]` for one of a set, with `^` to negate it and `a-z` for a range,
+// and `\` to match the byte after it as is.
+//
+// A `*` that turns out to have stopped too soon is only ever extended, by
+// going back to just after it, rather than trying every split of the text
+// for every `*`, which would take exponential time on patterns like
+// `*a*a*a*b`. Only the last `*` needs to be gone back to: anything the ones
+// before it could have covered, it can cover instead.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The pattern just after the last `*`, and where in the text it's tried
+    // from.
+    let mut star = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, t));
+            continue;
+        }
+        if let Some(rest) = match_one(&pattern[p..], text[t]) {
+            p = pattern.len() - rest.len();
+            t += 1;
+            continue;
+        }
+        let Some((after_star, from)) = star else {
+            return false;
+        };
+        star = Some((after_star, from + 1));
+        (p, t) = (after_star, from + 1);
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// Matches `t` against whatever's at the start of `pattern`, other than a
+// `*`, and returns the rest of the pattern if it matches.
+fn match_one(pattern: &[u8], t: u8) -> Option<&[u8]> {
+    let (&p, rest) = pattern.split_first()?;
+    let (matched, rest) = match p {
+        b'?' => (true, rest),
+        b'[' => class_match(rest, t),
+        b'\\' if !rest.is_empty() => (rest[0] == t, &rest[1..]),
+        p => (p == t, rest),
+    };
+    matched.then_some(rest)
+}
+
+// Matches `t` against the set at the start of `class`, just after its `[`,
+// and returns whether it's in it and the pattern after the set.
+fn class_match(class: &[u8], t: u8) -> (bool, &[u8]) {
+    let (negated, mut class) = match class.split_first() {
+        Some((b'^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    loop {
+        match class {
+            // An unclosed set runs to the end of the pattern.
+            [] => break,
+            [b']', rest @ ..] => {
+                class = rest;
+                break;
+            }
+            [b'\\', c, rest @ ..] => {
+                matched |= *c == t;
+                class = rest;
+            }
+            [lo, b'-', hi, rest @ ..] if *hi != b']' => {
+                let (lo, hi) = ((*lo).min(*hi), (*lo).max(*hi));
+                matched |= (lo..=hi).contains(&t);
+                class = rest;
+            }
+            [c, rest @ ..] => {
+                matched |= *c == t;
+                class = rest;
+            }
+        }
+    }
+    (matched != negated, class)
+}
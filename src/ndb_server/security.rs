@@ -0,0 +1,69 @@
+// TLS, with the `tls` feature, and authentication for the server's
+// listeners. Without the feature, an `Acceptor` can't be made, so a server
+// never has one.
+
+use std::{error::Error, io, path::Path};
+
+use tokio::net::TcpStream;
+
+#[cfg(feature = "tls")]
+pub type Acceptor = tokio_rustls::TlsAcceptor;
+
+#[cfg(not(feature = "tls"))]
+#[derive(Clone)]
+pub enum Acceptor {}
+
+// Accepts TLS connections with the certificate chain in `cert` and the
+// private key in `key`, both PEM files.
+#[cfg(feature = "tls")]
+pub fn acceptor(cert: &Path, key: &Path) -> Result<Acceptor, Box<dyn Error>> {
+    use std::sync::Arc;
+    use tokio_rustls::rustls::{
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    };
+
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("{}: {}", cert.display(), err))?;
+    let key =
+        PrivateKeyDer::from_pem_file(key).map_err(|err| format!("{}: {}", key.display(), err))?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)?;
+    Ok(Acceptor::from(Arc::new(config)))
+}
+
+#[cfg(not(feature = "tls"))]
+pub fn acceptor(_cert: &Path, _key: &Path) -> Result<Acceptor, Box<dyn Error>> {
+    Err("ndb-server was built without the tls feature".into())
+}
+
+// Finishes the TLS handshake on a connection that's just been accepted, as
+// long as the client doesn't take too long about it.
+#[cfg(feature = "tls")]
+pub async fn handshake(
+    acceptor: &Acceptor,
+    stream: TcpStream,
+) -> io::Result<tokio_rustls::server::TlsStream<TcpStream>> {
+    use std::time::Duration;
+    tokio::time::timeout(Duration::from_secs(10), acceptor.accept(stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))?
+}
+
+#[cfg(not(feature = "tls"))]
+pub async fn handshake(acceptor: &Acceptor, _stream: TcpStream) -> io::Result<TcpStream> {
+    match *acceptor {}
+}
+
+// Whether `given` is the token clients have to authenticate with. It takes
+// as long to say no however much of the token is right.
+pub fn token_matches(token: &str, given: &[u8]) -> bool {
+    let token = token.as_bytes();
+    let differences = token
+        .iter()
+        .zip(given)
+        .fold(0, |differences, (a, b)| differences | (a ^ b));
+    token.len() == given.len() && differences == 0
+}
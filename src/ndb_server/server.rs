@@ -0,0 +1,258 @@
+// Accepts connections and answers their requests, each connection in a task
+// of its own, in either of the protocols the server speaks.
+
+use std::{ops::Bound, sync::Arc};
+
+use nulldb::{Db, NdbError};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    net::TcpListener,
+    sync::{watch, Semaphore},
+    task::JoinSet,
+};
+
+use crate::{
+    protocol::{self, FrameError, Request, Response},
+    resp::{self, Cursors},
+    security, Config,
+};
+
+#[derive(Clone, Copy)]
+pub enum Protocol {
+    // The one in protocol.rs.
+    Binary,
+    // The Redis one, in resp.rs.
+    Resp,
+}
+
+// Serves connections until `shutdown` is set. Requests already being handled
+// are answered first; nothing more is read from any connection after that.
+pub async fn serve(
+    listener: TcpListener,
+    protocol: Protocol,
+    db: Arc<Db>,
+    config: Arc<Config>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let cursors = Arc::new(Cursors::default());
+    let slots = Arc::new(Semaphore::new(config.max_connections));
+    let mut connections = JoinSet::new();
+    loop {
+        // Past the limit, connections wait to be accepted until one closes.
+        let slot = tokio::select! {
+            slot = slots.clone().acquire_owned() => slot.expect("never closed"),
+            _ = shutdown.changed() => break,
+        };
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    eprintln!("ndb-server: couldn't accept a connection: {}", err);
+                    continue;
+                }
+            },
+            _ = shutdown.changed() => break,
+        };
+        let (db, config, shutdown) = (db.clone(), config.clone(), shutdown.clone());
+        let cursors = cursors.clone();
+        connections.spawn(async move {
+            let served = async {
+                stream.set_nodelay(true)?;
+                let Some(acceptor) = &config.tls else {
+                    return serve_stream(protocol, stream, &db, &config, &cursors, shutdown).await;
+                };
+                let stream = security::handshake(acceptor, stream).await?;
+                serve_stream(protocol, stream, &db, &config, &cursors, shutdown).await
+            };
+            if let Err(err) = served.await {
+                eprintln!("ndb-server: connection from {}: {}", peer, err);
+            }
+            drop(slot);
+        });
+        // Reap the ones that have finished as we go.
+        while connections.try_join_next().is_some() {}
+    }
+    while connections.join_next().await.is_some() {}
+}
+
+async fn serve_stream(
+    protocol: Protocol,
+    stream: impl AsyncRead + AsyncWrite,
+    db: &Db,
+    config: &Config,
+    cursors: &Cursors,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(), std::io::Error> {
+    match protocol {
+        Protocol::Binary => connection(stream, db, config, shutdown).await,
+        Protocol::Resp => resp::connection(stream, db, config, cursors, shutdown).await,
+    }
+}
+
+// Serves the gRPC service until `shutdown` is set, then finishes the calls
+// already in progress.
+#[cfg(feature = "grpc")]
+pub async fn serve_grpc(
+    listener: TcpListener,
+    db: Arc<Db>,
+    config: Arc<Config>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    use nulldb::grpc::NdbService;
+    use tonic::{
+        service::interceptor::InterceptedService,
+        transport::{server::TcpIncoming, Server},
+        Request, Status,
+    };
+
+    let token = config.token.clone();
+    let authenticate = move |request: Request<()>| {
+        let Some(token) = &token else {
+            return Ok(request);
+        };
+        let given = request
+            .metadata()
+            .get("authorization")
+            .map(|value| value.as_bytes());
+        match given.and_then(|given| given.strip_prefix(b"Bearer ")) {
+            Some(given) if security::token_matches(token, given) => Ok(request),
+            _ => Err(Status::unauthenticated("a valid bearer token is required")),
+        }
+    };
+    let service = NdbService::new(db)
+        .with_max_scan(config.max_scan)
+        .into_server()
+        .max_decoding_message_size(config.max_frame_bytes as usize);
+    let service = InterceptedService::new(service, authenticate);
+    let incoming = TcpIncoming::from(listener).with_nodelay(Some(true));
+    let stopped = async move {
+        let _ = shutdown.changed().await;
+    };
+    let mut server = Server::builder();
+    #[cfg(feature = "tls")]
+    if let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) {
+        use tonic::transport::{Identity, ServerTlsConfig};
+        let identity = match (std::fs::read(cert), std::fs::read(key)) {
+            (Ok(cert), Ok(key)) => Identity::from_pem(cert, key),
+            (Err(err), _) | (_, Err(err)) => {
+                eprintln!("ndb-server: grpc: couldn't read the certificate: {}", err);
+                return;
+            }
+        };
+        server = match server.tls_config(ServerTlsConfig::new().identity(identity)) {
+            Ok(server) => server,
+            Err(err) => {
+                eprintln!("ndb-server: grpc: {}", err);
+                return;
+            }
+        };
+    }
+    if let Err(err) = server
+        .add_service(service)
+        .serve_with_incoming_shutdown(incoming, stopped)
+        .await
+    {
+        eprintln!("ndb-server: grpc: {}", err);
+    }
+}
+
+async fn connection(
+    stream: impl AsyncRead + AsyncWrite,
+    db: &Db,
+    config: &Config,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), std::io::Error> {
+    let (reader, writer) = tokio::io::split(stream);
+    let (mut reader, mut writer) = (BufReader::new(reader), BufWriter::new(writer));
+    let mut authenticated = config.token.is_none();
+    loop {
+        let frame = tokio::select! {
+            frame = protocol::read_frame(&mut reader, config.max_frame_bytes) => frame,
+            _ = shutdown.changed() => break,
+        };
+        let response = match frame {
+            Ok(Some(frame)) => match Request::decode(&frame) {
+                Ok(Request::Auth(given)) => match &config.token {
+                    Some(token) if !security::token_matches(token, &given) => {
+                        // No more guesses on this connection.
+                        let response = Response::Error("wrong token".into());
+                        protocol::write_frame(&mut writer, &response.encode()).await?;
+                        break;
+                    }
+                    _ => {
+                        authenticated = true;
+                        Response::Ok
+                    }
+                },
+                Ok(_) if !authenticated => Response::Error("authentication required".into()),
+                Ok(request) => handle(db, config, request).await,
+                Err(message) => Response::Error(message),
+            },
+            Ok(None) => break,
+            Err(FrameError::TooLarge(len)) => {
+                let message = format!(
+                    "request of {} bytes is over the limit of {}",
+                    len, config.max_frame_bytes
+                );
+                protocol::write_frame(&mut writer, &Response::Error(message).encode()).await?;
+                break;
+            }
+            Err(FrameError::Io(err)) => return Err(err),
+        };
+        protocol::write_frame(&mut writer, &response.encode()).await?;
+        // Responses to requests that were sent together go back together.
+        if reader.buffer().is_empty() {
+            writer.flush().await?;
+        }
+    }
+    writer.flush().await
+}
+
+async fn handle(db: &Db, config: &Config, request: Request) -> Response {
+    let result = match request {
+        Request::Get(key) => db.get(&key).await.map(|value| match value {
+            Some(value) => Response::Value(value),
+            None => Response::NotFound,
+        }),
+        Request::Put(key, value) => db.put(&key, &value).await.map(|()| Response::Ok),
+        Request::Delete(key) => db.delete(&key).await.map(|()| Response::Ok),
+        Request::Auth(_) => unreachable!("handled by the connection"),
+        Request::Scan { start, end, limit } => {
+            let limit = match limit {
+                0 => config.max_scan,
+                limit => (limit as usize).min(config.max_scan),
+            };
+            scan(db, start, end, limit, config.max_frame_bytes as usize).await
+        }
+    };
+    result.unwrap_or_else(|err| Response::Error(err.to_string()))
+}
+
+// Up to `limit` entries from the range, stopping early once they come to
+// `max_bytes`, so the response isn't too big to send.
+async fn scan(
+    db: &Db,
+    start: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+    limit: usize,
+    max_bytes: usize,
+) -> Result<Response, NdbError> {
+    let start = start.map_or(Bound::Unbounded, Bound::Included);
+    let end = end.map_or(Bound::Unbounded, Bound::Excluded);
+    let mut iter = db.scan((start, end)).await?;
+    let (mut entries, mut bytes) = (Vec::new(), 0);
+    while let Some((key, value)) = iter.next().await? {
+        if entries.len() == limit || (bytes > 0 && bytes + key.len() + value.len() > max_bytes) {
+            return Ok(Response::Entries {
+                entries,
+                more: true,
+            });
+        }
+        bytes += key.len() + value.len();
+        entries.push((key, value));
+    }
+    Ok(Response::Entries {
+        entries,
+        more: false,
+    })
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use object_store::{buffered, path::Path as ObjectPath, ObjectStore};
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+use crate::{storage::is_not_found, LocalStorage, NdbError, ReadableFile, Storage, WritableFile};
+
+/// Keeps a database's tables in an object store bucket (S3, GCS, Azure, ...,
+/// through the [`object_store`] crate) and everything else — the log, the
+/// manifest, consumer positions — in another [`Storage`], the local file
+/// system by default. Needs the `object-store` feature.
+///
+/// Tables are written to a local cache directory and uploaded whole once
+/// they're finished, so a table only becomes durable when it's in the
+/// bucket. Tables are read from the cache too, and downloaded into it the
+/// first time they're opened after being evicted or on a new node.
+///
+/// Only tables are in the bucket, so a database can't be opened from the
+/// bucket alone: the directory with its log and manifest has to survive
+/// too, or be restored from a checkpoint.
+pub struct ObjectStorage {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+    local: Arc<dyn Storage>,
+    cache: ReadCache,
+}
+
+pub struct ObjectStorageOptions {
+    /// Where in the bucket tables are kept. The table at
+    /// `/data/db/1700000000.sst` is kept at
+    /// `<prefix>/data/db/1700000000.sst`.
+    pub prefix: String,
+    /// Evict the least recently opened tables from the cache once it holds
+    /// more than this many bytes. Tables that are open stay readable until
+    /// they're closed. `None` never evicts.
+    pub cache_bytes: Option<u64>,
+    /// Where everything other than tables is kept.
+    pub local: Arc<dyn Storage>,
+}
+
+impl Default for ObjectStorageOptions {
+    fn default() -> ObjectStorageOptions {
+        ObjectStorageOptions {
+            prefix: String::new(),
+            cache_bytes: None,
+            local: Arc::new(LocalStorage),
+        }
+    }
+}
+
+// Tables in the cache directory, by how recently they were opened.
+struct ReadCache {
+    dir: PathBuf,
+    capacity: Option<u64>,
+    files: Mutex<CachedFiles>,
+    // Tells apart the partial downloads of a table being opened twice at
+    // once.
+    downloads: AtomicU64,
+}
+
+#[derive(Default)]
+struct CachedFiles {
+    files: HashMap<PathBuf, CachedFile>,
+    bytes: u64,
+    uses: u64,
+}
+
+struct CachedFile {
+    size: u64,
+    // `CachedFiles::uses` when it was last used, so the smallest is the least
+    // recent.
+    used: u64,
+}
+
+impl ObjectStorage {
+    /// Keeps tables in `store`, caching them in `cache_dir`.
+    pub fn new(
+        store: Arc<dyn ObjectStore>,
+        cache_dir: impl Into<PathBuf>,
+        options: ObjectStorageOptions,
+    ) -> ObjectStorage {
+        ObjectStorage {
+            store,
+            prefix: ObjectPath::from(options.prefix.as_str()),
+            local: options.local,
+            cache: ReadCache {
+                dir: cache_dir.into(),
+                capacity: options.cache_bytes,
+                files: Mutex::new(CachedFiles::default()),
+                downloads: AtomicU64::new(0),
+            },
+        }
+    }
+
+    /// The bytes of tables in the cache directory that have been opened or
+    /// written since the storage was created.
+    pub fn cached_bytes(&self) -> u64 {
+        self.cache.files.lock().unwrap().bytes
+    }
+
+    fn key(&self, path: &Path) -> ObjectPath {
+        path.components()
+            .filter_map(|c| match c {
+                Component::Normal(part) => Some(part.to_string_lossy()),
+                _ => None,
+            })
+            .fold(self.prefix.clone(), |key, part| key.child(part.as_ref()))
+    }
+
+    fn cache_path(&self, key: &ObjectPath) -> PathBuf {
+        self.cache.dir.join(key.as_ref())
+    }
+
+    async fn download(&self, key: &ObjectPath, to: &Path) -> Result<(), NdbError> {
+        let n = self.cache.downloads.fetch_add(1, Ordering::Relaxed);
+        let partial = to.with_extension(format!("download-{}", n));
+        tokio::fs::create_dir_all(to.parent().unwrap()).await?;
+        let mut stream = self.store.get(key).await.map_err(io_error)?.into_stream();
+        let mut file = tokio::fs::File::create(&partial).await?;
+        while let Some(bytes) = stream.next().await {
+            file.write_all(&bytes.map_err(io_error)?).await?;
+        }
+        file.sync_all().await?;
+        tokio::fs::rename(&partial, to).await?;
+        Ok(())
+    }
+}
+
+fn is_table(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "sst")
+}
+
+fn io_error(err: object_store::Error) -> NdbError {
+    NdbError::Io(err.into())
+}
+
+fn unsupported(path: &Path) -> NdbError {
+    NdbError::Io(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{} is in an object store, where it can't be changed",
+            path.display()
+        ),
+    ))
+}
+
+impl ReadCache {
+    // Notes that `path` was just used, and evicts whatever that pushes out.
+    async fn used(&self, path: &Path, size: u64) {
+        let evicted = {
+            let mut cached = self.files.lock().unwrap();
+            cached.uses += 1;
+            let used = cached.uses;
+            let file = CachedFile { size, used };
+            if let Some(old) = cached.files.insert(path.to_path_buf(), file) {
+                cached.bytes -= old.size;
+            }
+            cached.bytes += size;
+            let mut evicted = Vec::new();
+            while cached.bytes > self.capacity.unwrap_or(u64::MAX) {
+                let Some(lru) = cached
+                    .files
+                    .iter()
+                    .filter(|(p, _)| p.as_path() != path)
+                    .min_by_key(|(_, file)| file.used)
+                    .map(|(p, _)| p.clone())
+                else {
+                    break;
+                };
+                let file = cached.files.remove(&lru).unwrap();
+                cached.bytes -= file.size;
+                evicted.push(lru);
+            }
+            evicted
+        };
+        for path in evicted {
+            // Anything that still has it open keeps reading it.
+            let _ = tokio::fs::remove_file(path).await;
+        }
+    }
+
+    async fn forget(&self, path: &Path) -> Result<(), NdbError> {
+        {
+            let mut cached = self.files.lock().unwrap();
+            if let Some(file) = cached.files.remove(path) {
+                cached.bytes -= file.size;
+            }
+        }
+        match tokio::fs::remove_file(path).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// A table being written to the cache directory, to be uploaded when it's
+// finished.
+struct TableWriter {
+    file: BufWriter<tokio::fs::File>,
+    // Where it's written until it's uploaded.
+    partial: PathBuf,
+    cached: PathBuf,
+    key: ObjectPath,
+    store: Arc<dyn ObjectStore>,
+}
+
+impl Storage for ObjectStorage {
+    fn create<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        async move {
+            if !is_table(path) {
+                return self.local.create(path).await;
+            }
+            let key = self.key(path);
+            let cached = self.cache_path(&key);
+            let partial = cached.with_extension("partial");
+            tokio::fs::create_dir_all(cached.parent().unwrap()).await?;
+            let file = tokio::fs::File::create(&partial).await?;
+            Ok(Box::new(TableWriter {
+                file: BufWriter::new(file),
+                partial,
+                cached,
+                key,
+                store: self.store.clone(),
+            }) as Box<dyn WritableFile>)
+        }
+        .boxed()
+    }
+
+    fn append<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        async move {
+            if is_table(path) {
+                return Err(unsupported(path));
+            }
+            self.local.append(path).await
+        }
+        .boxed()
+    }
+
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Arc<dyn ReadableFile>, NdbError>> {
+        async move {
+            if !is_table(path) {
+                return self.local.open(path).await;
+            }
+            let key = self.key(path);
+            let cached = self.cache_path(&key);
+            let file = match LocalStorage.open(&cached).await {
+                Err(err) if is_not_found(&err) => {
+                    self.download(&key, &cached).await?;
+                    LocalStorage.open(&cached).await?
+                }
+                file => file?,
+            };
+            self.cache.used(&cached, file.size().await?).await;
+            Ok(file)
+        }
+        .boxed()
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            match (is_table(from), is_table(to)) {
+                (false, false) => self.local.rename(from, to).await,
+                (true, true) => {
+                    let (from, to) = (self.key(from), self.key(to));
+                    self.store.rename(&from, &to).await.map_err(io_error)?;
+                    self.cache.forget(&self.cache_path(&from)).await
+                }
+                _ => Err(unsupported(from)),
+            }
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            if !is_table(path) {
+                return self.local.remove(path).await;
+            }
+            let key = self.key(path);
+            self.store.delete(&key).await.map_err(io_error)?;
+            self.cache.forget(&self.cache_path(&key)).await
+        }
+        .boxed()
+    }
+
+    fn truncate<'a>(&'a self, path: &'a Path, len: u64) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            if is_table(path) {
+                return Err(unsupported(path));
+            }
+            self.local.truncate(path, len).await
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Vec<PathBuf>, NdbError>> {
+        async move {
+            let mut files = match self.local.list(dir).await {
+                Err(err) if is_not_found(&err) => Vec::new(),
+                files => files?,
+            };
+            let key = self.key(dir);
+            let tables = self
+                .store
+                .list_with_delimiter(Some(&key))
+                .await
+                .map_err(io_error)?;
+            files.extend(
+                tables
+                    .objects
+                    .iter()
+                    .filter_map(|table| table.location.filename())
+                    .map(|name| dir.join(name)),
+            );
+            Ok(files)
+        }
+        .boxed()
+    }
+
+    fn size<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<u64, NdbError>> {
+        async move {
+            if !is_table(path) {
+                return self.local.size(path).await;
+            }
+            let table = self.store.head(&self.key(path)).await.map_err(io_error)?;
+            Ok(table.size)
+        }
+        .boxed()
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool, NdbError>> {
+        async move {
+            if !is_table(path) {
+                return self.local.exists(path).await;
+            }
+            match self.store.head(&self.key(path)).await {
+                Ok(_) => Ok(true),
+                Err(object_store::Error::NotFound { .. }) => Ok(false),
+                Err(err) => Err(io_error(err)),
+            }
+        }
+        .boxed()
+    }
+
+    fn create_dir_all<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        self.local.create_dir_all(dir)
+    }
+
+    // Tables are durable in the bucket once they're written.
+    fn sync_dir<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        self.local.sync_dir(dir)
+    }
+}
+
+impl WritableFile for TableWriter {
+    fn write<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move { Ok(self.file.write_all(data).await?) }.boxed()
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        async move { Ok(self.file.flush().await?) }.boxed()
+    }
+
+    // Only syncs the copy in the cache. The table isn't durable until it's
+    // finished and uploaded.
+    fn sync(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        async move {
+            self.file.flush().await?;
+            Ok(self.file.get_ref().sync_all().await?)
+        }
+        .boxed()
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        async move {
+            self.sync().await?;
+            let mut file = tokio::fs::File::open(&self.partial).await?;
+            let mut upload = buffered::BufWriter::new(self.store.clone(), self.key.clone());
+            tokio::io::copy(&mut file, &mut upload).await?;
+            upload.shutdown().await?;
+            tokio::fs::rename(&self.partial, &self.cached).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    fs::File,
+    sync::{Arc, Mutex},
+};
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use crate::uring::Ring;
+use crate::{DbOptions, NdbError, ReadableFile};
+
+// Local table files are read at an offset rather than by seeking a shared
+// handle and then reading, so any number of reads can go on in one file at
+// once without taking turns. The reads are blocking calls, made on tokio's
+// blocking pool, unless the database has an io_uring to make them through.
+
+// Spare buffers kept for reuse, and the biggest one worth keeping.
+const MAX_SPARE_BUFFERS: usize = 32;
+const MAX_SPARE_CAPACITY: usize = 256 << 10;
+
+// How a database reads its tables and appends to its logs.
+#[derive(Clone)]
+pub(crate) enum FileIo {
+    Blocking,
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    Uring(Arc<Ring>),
+}
+
+impl FileIo {
+    #[cfg_attr(
+        not(all(feature = "io-uring", target_os = "linux")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn new(options: &DbOptions) -> Result<FileIo, NdbError> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if options.io_uring && options.storage.is_local() {
+            return Ok(FileIo::Uring(Ring::new()?));
+        }
+        Ok(FileIo::Blocking)
+    }
+
+    // Reads `len` bytes at `offset` into `buf`, replacing what was in it.
+    // Only local files can be read through the ring.
+    pub(crate) async fn read_at(
+        &self,
+        file: &Arc<dyn ReadableFile>,
+        offset: u64,
+        len: usize,
+        buf: Vec<u8>,
+    ) -> Result<Vec<u8>, NdbError> {
+        match (self, file.as_file()) {
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            (FileIo::Uring(ring), Some(local)) => ring.read_at(local, offset, len, buf).await,
+            _ => file.read_at(offset, len, buf).await,
+        }
+    }
+}
+
+pub(crate) async fn read_at(
+    file: &Arc<File>,
+    offset: u64,
+    len: usize,
+    mut buf: Vec<u8>,
+) -> Result<Vec<u8>, NdbError> {
+    let file = file.clone();
+    buf.clear();
+    buf.resize(len, 0);
+    blocking(move || {
+        read_exact_at(&file, &mut buf, offset)?;
+        Ok(buf)
+    })
+    .await
+}
+
+pub(crate) async fn len(file: &Arc<File>) -> Result<u64, NdbError> {
+    let file = file.clone();
+    blocking(move || Ok(file.metadata()?.len())).await
+}
+
+async fn blocking<T: Send + 'static>(
+    read: impl FnOnce() -> Result<T, NdbError> + Send + 'static,
+) -> Result<T, NdbError> {
+    tokio::task::spawn_blocking(read)
+        .await
+        .map_err(|err| NdbError::Io(std::io::Error::other(err)))?
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+// Buffers for blocks that are only read long enough to look a key up in,
+// handed back once the lookup's done so the next one needn't allocate.
+#[derive(Default)]
+pub(crate) struct Buffers {
+    spare: Mutex<Vec<Vec<u8>>>,
+}
+
+impl Buffers {
+    pub(crate) fn take(&self) -> Vec<u8> {
+        self.spare.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    pub(crate) fn give(&self, buf: Vec<u8>) {
+        if buf.capacity() > MAX_SPARE_CAPACITY {
+            return;
+        }
+        let mut spare = self.spare.lock().unwrap();
+        if spare.len() < MAX_SPARE_BUFFERS {
+            spare.push(buf);
+        }
+    }
+}
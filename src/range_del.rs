@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+// A set of deleted key ranges, each covering `start..end`. Overlapping and
+// touching ranges are merged as they're added, so the list stays sorted and
+// disjoint and can be binary searched.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub(crate) struct RangeTombstones {
+    ranges: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl RangeTombstones {
+    pub(crate) fn add(&mut self, start: &[u8], end: &[u8]) {
+        if start >= end {
+            return;
+        }
+        // Everything from the first range that ends at or after `start`, up
+        // to the last one that begins at or before `end`, merges with it.
+        let first = self.ranges.partition_point(|(_, e)| e.as_slice() < start);
+        let last = self.ranges.partition_point(|(s, _)| s.as_slice() <= end);
+        let mut merged = (start.to_vec(), end.to_vec());
+        if first < last {
+            merged.0 = merged.0.min(self.ranges[first].0.clone());
+            merged.1 = merged.1.max(self.ranges[last - 1].1.clone());
+        }
+        self.ranges.splice(first..last, [merged]);
+    }
+
+    pub(crate) fn extend(&mut self, other: &RangeTombstones) {
+        for (start, end) in &other.ranges {
+            self.add(start, end);
+        }
+    }
+
+    pub(crate) fn covers(&self, key: &[u8]) -> bool {
+        let i = self
+            .ranges
+            .partition_point(|(_, end)| end.as_slice() <= key);
+        self.ranges
+            .get(i)
+            .is_some_and(|(start, _)| start.as_slice() <= key)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Caps how fast tables are written by flushes, vacuums and compactions, in
+/// every database it's shared with through
+/// [`DbOptions::rate_limiter`](crate::DbOptions::rate_limiter), so that
+/// heavy background work leaves the disk free enough for reads.
+///
+/// It's a token bucket: writes take tokens as they go, at most a tenth of a
+/// second's worth can be saved up while nothing's written, and a write that
+/// takes more than there are waits for the bucket to fill back up. Writes to
+/// the log aren't limited.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: AtomicU64,
+    bucket: Mutex<Bucket>,
+    bytes_written: AtomicU64,
+    // In nanoseconds.
+    waited: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    // Can go below zero, by however much the writes waiting have taken ahead
+    // of time.
+    tokens: f64,
+    filled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> RateLimiter {
+        RateLimiter {
+            bytes_per_sec: AtomicU64::new(bytes_per_sec),
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                filled_at: Instant::now(),
+            }),
+            bytes_written: AtomicU64::new(0),
+            waited: AtomicU64::new(0),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec.load(Ordering::Relaxed)
+    }
+
+    /// Changes the limit, e.g. to let background work catch up at quiet
+    /// times. Writes already waiting keep to the old one.
+    pub fn set_bytes_per_sec(&self, bytes_per_sec: u64) {
+        self.bytes_per_sec.store(bytes_per_sec, Ordering::Relaxed);
+    }
+
+    /// How many bytes have been written under the limit, by every database
+    /// sharing it.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// How long writes have spent waiting for the limit, added up.
+    pub fn time_waited(&self) -> Duration {
+        Duration::from_nanos(self.waited.load(Ordering::Relaxed))
+    }
+
+    // Waits until `bytes` can be written.
+    pub(crate) async fn request(&self, bytes: u64) {
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+        let rate = self.bytes_per_sec().max(1) as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = (now - bucket.filled_at).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refill).min(rate / 10.0);
+            bucket.filled_at = now;
+            bucket.tokens -= bytes as f64;
+            Duration::from_secs_f64((-bucket.tokens).max(0.0) / rate)
+        };
+        if !wait.is_zero() {
+            self.waited
+                .fetch_add(wait.as_nanos() as u64, Ordering::Relaxed);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    db::{DbMeta, RetainedWal},
+    log::{LogEntry, LogRecord},
+    manifest::{self, ManifestWriter},
+    pread::FileIo,
+    sstable::{SSTable, SSTableMetadata},
+    stats::IoCounters,
+    storage::{self, LineReader},
+    table_cache::TableCache,
+    tier::TieredStorage,
+    version, DbOptions, NdbError, Storage,
+};
+
+/// What [`Db::repair`](crate::Db::repair) found and did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Whether any of the old manifest (or meta.json) could be read. If so,
+    /// tables it didn't list are quarantined. If not, every table is kept,
+    /// ordered by when it was written, and range deletes recorded only in
+    /// the manifest are lost.
+    pub salvaged_manifest: bool,
+    /// The tables the repaired database has, newest first.
+    pub tables: Vec<PathBuf>,
+    /// The logs its memtable is replayed from, oldest first.
+    pub logs: Vec<PathBuf>,
+    /// Files moved into a `lost` directory beside them, and why. Corrupt
+    /// logs are copied there before their bad records are dropped.
+    pub quarantined: Vec<(PathBuf, String)>,
+    /// Log records that couldn't be read, and were dropped.
+    pub dropped_log_records: u64,
+}
+
+// The files in a database's directory, by kind.
+#[derive(Default)]
+struct Found {
+    // Each with the timestamp it's named after.
+    tables: Vec<(u64, PathBuf)>,
+    // `log` itself is numbered 0.
+    logs: Vec<(u64, PathBuf)>,
+    metadata: Vec<PathBuf>,
+}
+
+pub(crate) async fn repair(dir: &Path, options: &DbOptions) -> Result<RepairReport, NdbError> {
+    let mut options = options.clone();
+    if let Some(cold) = options.cold_tier.clone() {
+        options.storage = Arc::new(TieredStorage::new(options.storage, cold));
+    }
+    let storage = &*options.storage;
+    let mut report = RepairReport::default();
+
+    let mut found = Found::default();
+    find_files(storage, dir, &mut found).await?;
+    if let Some(cold) = &options.cold_tier {
+        find_files(storage, &cold.dir, &mut found).await?;
+    }
+    if found.tables.is_empty() && found.logs.is_empty() && found.metadata.is_empty() {
+        return Err(NdbError::NotFound(format!(
+            "{} is not a database",
+            dir.display()
+        )));
+    }
+
+    let salvaged = manifest::salvage(storage, dir).await?;
+    report.salvaged_manifest = salvaged.is_some();
+    let salvaged = salvaged.unwrap_or_else(|| DbMeta::new(dir.join("log")));
+    let known: HashMap<&Path, &SSTableMetadata> = salvaged
+        .sstables
+        .iter()
+        .map(|table| (table.data_path.as_path(), table))
+        .collect();
+
+    // Every table is read through, and kept only if all of it can be.
+    let io = Arc::new(IoCounters::default());
+    let files = Arc::new(TableCache::new(
+        1,
+        io.clone(),
+        options.storage.clone(),
+        FileIo::new(&options)?,
+    ));
+    let mut tables = Vec::new();
+    for (timestamp, path) in &found.tables {
+        // A table the manifest doesn't list may be one a vacuum merged away,
+        // which would bring back what it overwrote or deleted.
+        if report.salvaged_manifest && !known.contains_key(path.as_path()) {
+            let reason = "not in the salvaged manifest".to_string();
+            quarantine(storage, path, reason, &mut report).await?;
+            continue;
+        }
+        let meta = match SSTable::open_external(path, &options, &io, &files).await {
+            Ok(meta) => meta,
+            Err(err) if unreadable(&err) => {
+                quarantine(storage, path, err.to_string(), &mut report).await?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        let recorded = match known.get(path.as_path()) {
+            Some(&recorded) => Some(recorded.clone()),
+            None => legacy_table_meta(storage, path).await,
+        };
+        tables.push(match recorded {
+            // What only the manifest knew, like where a vacuum's output is
+            // ordered and the ranges deleted, is kept if it's for this file.
+            Some(recorded) if recorded.checksum.is_none_or(|c| Some(c) == meta.checksum) => {
+                SSTableMetadata {
+                    checksum: meta.checksum,
+                    ..recorded
+                }
+            }
+            _ => SSTableMetadata {
+                written_timestamp: *timestamp,
+                ..meta
+            },
+        });
+    }
+    tables.sort_by_key(|table| std::cmp::Reverse(table.written_timestamp));
+    report.tables = tables.iter().map(|t| t.data_path.clone()).collect();
+
+    // Which logs still hold writes that aren't in a table. Without the
+    // manifest to say, they're the ones written since the newest table, and
+    // the one before it, which took writes while that table was flushed.
+    // The rest are kept for the change feed, like logs that have been.
+    found.logs.sort();
+    let wal_number = |path: &Path| found.logs.iter().find(|(_, p)| p == path).map(|(n, _)| *n);
+    let replay_from = match report.salvaged_manifest {
+        true => salvaged
+            .memtable_wals()
+            .iter()
+            .filter_map(|path| wal_number(path))
+            .min()
+            .unwrap_or(u64::MAX),
+        false => {
+            let newest_table = found.tables.iter().map(|(n, _)| *n).max();
+            let before = found
+                .logs
+                .iter()
+                .map(|(n, _)| *n)
+                .filter(|&n| newest_table.is_some_and(|t| n < t))
+                .max();
+            before.unwrap_or(0)
+        }
+    };
+    let mut meta = DbMeta::new(dir.join("log"));
+    meta.sstables = tables;
+    meta.pruned_through = salvaged.pruned_through;
+    let mut memtable_wals = Vec::new();
+    for (number, path) in &found.logs {
+        let last_seq = clean_log(storage, path, &mut report).await?;
+        match *number >= replay_from {
+            true => memtable_wals.push(RetainedWal {
+                path: path.clone(),
+                last_seq,
+            }),
+            false => meta.retained_wals.push(RetainedWal {
+                path: path.clone(),
+                last_seq,
+            }),
+        }
+    }
+    meta.wal = match memtable_wals.pop() {
+        Some(wal) => wal.path,
+        None => {
+            let highest = found.logs.last().map_or(0, |(n, _)| *n);
+            let number = options.clock.unix_secs().max(highest + 1);
+            dir.join(format!("log-{}", number))
+        }
+    };
+    meta.wal_segments = memtable_wals;
+    report.logs = meta.memtable_wals();
+
+    // The old metadata is kept, in case anything more can be got from it.
+    for path in &found.metadata {
+        let reason = "replaced by the repaired manifest".to_string();
+        quarantine(storage, path, reason, &mut report).await?;
+    }
+    ManifestWriter::create(&options.storage, dir, &meta, options.max_manifest_bytes).await?;
+    Ok(report)
+}
+
+async fn find_files(storage: &dyn Storage, dir: &Path, found: &mut Found) -> Result<(), NdbError> {
+    let listed = match storage.list(dir).await {
+        Err(err) if storage::is_not_found(&err) => return Ok(()),
+        listed => listed?,
+    };
+    let numbered = |s: &str| s.parse::<u64>().ok();
+    for path in listed {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        match name.split_once(['.', '-']) {
+            _ if name == "log" => found.logs.push((0, path)),
+            _ if ["CURRENT", "CURRENT.tmp", "meta.json"].contains(&name) => {
+                found.metadata.push(path)
+            }
+            Some(("log", n)) => match numbered(n) {
+                Some(n) => found.logs.push((n, path)),
+                None => continue,
+            },
+            Some(("MANIFEST", n)) if numbered(n).is_some() => found.metadata.push(path),
+            Some((n, "sst")) => match numbered(n) {
+                Some(n) => found.tables.push((n, path)),
+                None => continue,
+            },
+            Some((n, "meta")) if numbered(n).is_some() => found.metadata.push(path),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Whether `err`, from reading a table through, means the file is damaged
+// rather than that it couldn't be got at.
+fn unreadable(err: &NdbError) -> bool {
+    match err {
+        NdbError::Corruption { .. } | NdbError::InvalidArgument(_) => true,
+        NdbError::Io(err) => err.kind() == std::io::ErrorKind::UnexpectedEof,
+        _ => false,
+    }
+}
+
+// The `.meta` file a table had before manifests, if it's there and readable.
+async fn legacy_table_meta(storage: &dyn Storage, path: &Path) -> Option<SSTableMetadata> {
+    let contents = storage.read(&path.with_extension("meta")).await.ok()?;
+    let meta: SSTableMetadata = serde_json::from_slice(&contents).ok()?;
+    (meta.data_path == path).then_some(meta)
+}
+
+// Moves `path` into the `lost` directory beside it.
+async fn quarantine(
+    storage: &dyn Storage,
+    path: &Path,
+    reason: String,
+    report: &mut RepairReport,
+) -> Result<(), NdbError> {
+    let lost = lost_path(storage, path).await?;
+    storage.rename(path, &lost).await?;
+    report.quarantined.push((path.into(), reason));
+    Ok(())
+}
+
+async fn lost_path(storage: &dyn Storage, path: &Path) -> Result<PathBuf, NdbError> {
+    let dir = path.parent().unwrap_or(Path::new(".")).join("lost");
+    storage.create_dir_all(&dir).await?;
+    Ok(dir.join(path.file_name().unwrap_or_default()))
+}
+
+// Drops the records of the log at `path` that can't be read, after copying it
+// into `lost`, and returns the last sequence number in what's left.
+async fn clean_log(
+    storage: &dyn Storage,
+    path: &Path,
+    report: &mut RepairReport,
+) -> Result<Option<u64>, NdbError> {
+    let mut reader = LineReader::new(storage.open(path).await?);
+    let (mut line, mut kept, mut dropped, mut last_seq) = (Vec::new(), Vec::new(), 0, None);
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            break;
+        }
+        let Some(record) = line.strip_suffix(b"\n") else {
+            dropped += 1;
+            continue;
+        };
+        if version::parse_header(record, path)?.is_none() {
+            match serde_json::from_slice::<LogRecord<LogEntry>>(record) {
+                Ok(record) => last_seq = last_seq.max(Some(record.seq)),
+                Err(_) => {
+                    dropped += 1;
+                    continue;
+                }
+            }
+        }
+        kept.extend_from_slice(&line);
+    }
+    if dropped > 0 {
+        let lost = lost_path(storage, path).await?;
+        storage::write_synced(storage, &lost, &storage.read(path).await?).await?;
+        storage::write_synced(storage, path, &kept).await?;
+        let reason = format!("{} records couldn't be read", dropped);
+        report.quarantined.push((path.into(), reason));
+        report.dropped_log_records += dropped;
+    }
+    Ok(last_seq)
+}
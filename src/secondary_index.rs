@@ -0,0 +1,183 @@
+use std::{
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+};
+
+use crate::{
+    key_codec,
+    log::LogEntry,
+    table::{self, KeyRange},
+    DbIterator, DbView, KeyValue, NdbError,
+};
+
+/// Picks the index key for a value, or `None` to leave the value out of the
+/// index. Set with [`SecondaryIndex::extract`].
+pub type IndexExtractor = Arc<dyn Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// An index over the values of the keys under a prefix, declared with
+/// [`DbOptions::secondary_indexes`](crate::DbOptions::secondary_indexes) and
+/// read with [`Db::index_scan`](crate::Db::index_scan).
+///
+/// Every indexed key has an entry under the index's own `prefix`, made of its
+/// index key and the key itself. Entries are added and removed in the same
+/// log record as the write that changes them, so after a crash the index
+/// agrees with the keys it covers. Only writes made while the index is
+/// declared are indexed: keys written before it was, or brought in with
+/// [`Db::ingest_sst`](crate::Db::ingest_sst), aren't in it until they're
+/// written again.
+#[derive(Clone)]
+pub struct SecondaryIndex {
+    /// What [`Db::index_scan`](crate::Db::index_scan) knows it by.
+    pub name: String,
+    /// The keys it indexes: those starting with this. Merges and deleted
+    /// ranges aren't allowed under it, since the values they leave behind
+    /// aren't known until they're read.
+    pub source_prefix: Vec<u8>,
+    /// Where its entries are kept. Nothing else should be written under it,
+    /// and it can't overlap any index's `source_prefix`, its own included.
+    pub prefix: Vec<u8>,
+    pub extract: IndexExtractor,
+}
+
+impl SecondaryIndex {
+    pub(crate) fn covers(&self, key: &[u8]) -> bool {
+        key.starts_with(&self.source_prefix)
+    }
+
+    // Whether the keys from `start` up to `end` include any it covers.
+    fn overlaps(&self, start: &[u8], end: &[u8]) -> bool {
+        let before_end = match table::prefix_end(&self.source_prefix) {
+            Bound::Excluded(prefix_end) => start < prefix_end.as_slice(),
+            _ => true,
+        };
+        start < end && before_end && end > self.source_prefix.as_slice()
+    }
+
+    // The entry for `key`, whose value has the index key `index_key`.
+    pub(crate) fn entry(&self, index_key: &[u8], key: &[u8]) -> Vec<u8> {
+        let mut entry = self.entry_prefix(index_key);
+        entry.extend_from_slice(key);
+        entry
+    }
+
+    // Where the entries for `index_key` start. Every one of them starts with
+    // this.
+    fn entry_prefix(&self, index_key: &[u8]) -> Vec<u8> {
+        let mut prefix = self.prefix.clone();
+        key_codec::encode_bytes(index_key, &mut prefix);
+        prefix
+    }
+
+    // Where the entries for `index_key` end. The encoded index key ends in
+    // 0x00 0x00, and any longer index key continues with 0x00 0xff, so
+    // 0x00 0x01 comes after the one and before the other.
+    fn entries_end(&self, index_key: &[u8]) -> Vec<u8> {
+        let mut end = self.entry_prefix(index_key);
+        *end.last_mut().unwrap() = 0x01;
+        end
+    }
+
+    // The index key and key of an entry.
+    fn split<'a>(&self, entry: &'a [u8]) -> Result<(Vec<u8>, &'a [u8]), NdbError> {
+        key_codec::decode_bytes(&entry[self.prefix.len()..])
+    }
+
+    // The entries for the index keys in `range`.
+    fn range(&self, range: impl RangeBounds<Vec<u8>>) -> KeyRange {
+        let start = match range.start_bound() {
+            Bound::Included(index_key) => Bound::Included(self.entry_prefix(index_key)),
+            Bound::Excluded(index_key) => Bound::Included(self.entries_end(index_key)),
+            Bound::Unbounded => Bound::Included(self.prefix.clone()),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(index_key) => Bound::Excluded(self.entries_end(index_key)),
+            Bound::Excluded(index_key) => Bound::Excluded(self.entry_prefix(index_key)),
+            Bound::Unbounded => table::prefix_end(&self.prefix),
+        };
+        (start, end)
+    }
+}
+
+// Checks that `indexes` have different names, and that none of their
+// entries are kept where they'd be indexed themselves.
+pub(crate) fn validate(indexes: &[SecondaryIndex]) -> Result<(), NdbError> {
+    for (i, index) in indexes.iter().enumerate() {
+        if indexes[..i].iter().any(|other| other.name == index.name) {
+            return Err(NdbError::InvalidArgument(format!(
+                "more than one secondary index named {}",
+                index.name
+            )));
+        }
+        let overlaps = |other: &SecondaryIndex| {
+            let (a, b) = (&index.prefix, &other.source_prefix);
+            a.starts_with(b) || b.starts_with(a)
+        };
+        if let Some(other) = indexes.iter().find(|other| overlaps(other)) {
+            return Err(NdbError::InvalidArgument(format!(
+                "secondary index {}'s prefix overlaps the source prefix of {}",
+                index.name, other.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn find<'a>(
+    indexes: &'a [SecondaryIndex],
+    name: &str,
+) -> Result<&'a SecondaryIndex, NdbError> {
+    (indexes.iter().find(|index| index.name == name))
+        .ok_or_else(|| NdbError::InvalidArgument(format!("no secondary index named {}", name)))
+}
+
+// Checks that `entry` can be indexed.
+pub(crate) fn check(indexes: &[SecondaryIndex], entry: &LogEntry) -> Result<(), NdbError> {
+    let covered = match entry {
+        LogEntry::Merge { key, .. } => indexes.iter().any(|index| index.covers(key)),
+        LogEntry::DeleteRange { start, end } => {
+            indexes.iter().any(|index| index.overlaps(start, end))
+        }
+        _ => false,
+    };
+    if covered {
+        return Err(NdbError::InvalidArgument(
+            "merge or deleted range under a secondary index's source prefix".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Iterates over the keys found through a [`SecondaryIndex`], with their
+/// values, in order of index key and then key. Started with
+/// [`Db::index_scan`](crate::Db::index_scan).
+pub struct IndexIterator {
+    index: SecondaryIndex,
+    view: DbView,
+    iter: DbIterator,
+}
+
+impl IndexIterator {
+    pub(crate) async fn new(
+        index: SecondaryIndex,
+        view: DbView,
+        range: impl RangeBounds<Vec<u8>>,
+    ) -> Result<IndexIterator, NdbError> {
+        let iter = view.scan(index.range(range)).await?;
+        Ok(IndexIterator { index, view, iter })
+    }
+
+    pub async fn next(&mut self) -> Result<Option<KeyValue>, NdbError> {
+        while let Some((entry, _)) = self.iter.next().await? {
+            let (index_key, key) = self.index.split(&entry)?;
+            // An entry can outlive its key's value when it expires, or is
+            // rewritten while the index isn't declared.
+            let Some(value) = self.view.get(key).await? else {
+                continue;
+            };
+            if (self.index.extract)(&value).as_deref() == Some(index_key.as_slice()) {
+                return Ok(Some((key.to_vec(), value)));
+            }
+        }
+        Ok(None)
+    }
+}
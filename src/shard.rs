@@ -0,0 +1,198 @@
+use std::{ops::RangeBounds, path::Path, time::Duration};
+
+use futures::future;
+
+use crate::{
+    BatchOp, ConflictResolution, Db, DbOptions, MergeIterator, NdbError, WriteBatch, WriteOptions,
+};
+
+/// A keyspace split across several databases by a hash of each key, so
+/// writes to different shards don't wait on the same log, and each shard
+/// flushes and vacuums on its own. The shards can be on different disks.
+///
+/// A write only touches the shard its key hashes to, and a batch is split up
+/// by shard, so it's atomic within each shard but not across them. Scans
+/// read every shard and merge what they find.
+pub struct ShardedDb {
+    shards: Vec<Db>,
+}
+
+impl ShardedDb {
+    /// Opens, or creates, a database in each of `dirs`, one per shard. Which
+    /// shard a key goes to depends on how many there are and where they come
+    /// in `dirs`, so the same directories have to be given in the same order
+    /// every time.
+    pub async fn open<P: AsRef<Path>>(
+        dirs: impl IntoIterator<Item = P>,
+        options: DbOptions,
+    ) -> Result<ShardedDb, NdbError> {
+        let shards = Db::open_many(dirs, options).await?;
+        if shards.is_empty() {
+            return Err(NdbError::InvalidArgument(
+                "a sharded database needs at least one shard".into(),
+            ));
+        }
+        Ok(ShardedDb { shards })
+    }
+
+    /// The shards, in the order their directories were given in.
+    pub fn shards(&self) -> &[Db] {
+        &self.shards
+    }
+
+    /// The index of the shard `key` goes to.
+    pub fn shard_for(&self, key: &[u8]) -> usize {
+        // crc32c rather than std's hasher, which is free to change between
+        // releases and move keys to other shards.
+        crc32c::crc32c(key) as usize % self.shards.len()
+    }
+
+    fn shard(&self, key: &[u8]) -> &Db {
+        &self.shards[self.shard_for(key)]
+    }
+
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, NdbError> {
+        self.shard(key).get(key).await
+    }
+
+    /// Looks up several keys at once, with one [`Db::multi_get`] per shard,
+    /// all run concurrently. The results are in the same order as `keys`.
+    pub async fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, NdbError> {
+        let mut by_shard = vec![Vec::new(); self.shards.len()];
+        for (i, key) in keys.iter().enumerate() {
+            by_shard[self.shard_for(key)].push(i);
+        }
+        let lookups = self.shards.iter().zip(&by_shard).map(|(db, indexes)| {
+            let keys: Vec<_> = indexes.iter().map(|&i| keys[i]).collect();
+            async move { db.multi_get(&keys).await }
+        });
+        let mut results = vec![None; keys.len()];
+        for (indexes, values) in by_shard.iter().zip(future::try_join_all(lookups).await?) {
+            for (&i, value) in indexes.iter().zip(values) {
+                results[i] = value;
+            }
+        }
+        Ok(results)
+    }
+
+    pub async fn put(&self, key: &[u8], value: &[u8]) -> Result<(), NdbError> {
+        self.shard(key).put(key, value).await
+    }
+
+    pub async fn put_opt(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        options: &WriteOptions,
+    ) -> Result<(), NdbError> {
+        self.shard(key).put_opt(key, value, options).await
+    }
+
+    /// Puts a value that reads as deleted once `ttl` has passed.
+    pub async fn put_with_ttl(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<(), NdbError> {
+        self.shard(key).put_with_ttl(key, value, ttl).await
+    }
+
+    /// Adds `operand` to the value of `key` with the shards'
+    /// [`DbOptions::merge_operator`].
+    pub async fn merge(&self, key: &[u8], operand: &[u8]) -> Result<(), NdbError> {
+        self.shard(key).merge(key, operand).await
+    }
+
+    pub async fn delete(&self, key: &[u8]) -> Result<(), NdbError> {
+        self.shard(key).delete(key).await
+    }
+
+    /// Deletes every key from `start` up to, but not including, `end`, in
+    /// every shard, since keys in the range can hash to any of them.
+    pub async fn delete_range(&self, start: &[u8], end: &[u8]) -> Result<(), NdbError> {
+        let mut batch = WriteBatch::new();
+        batch.delete_range(start, end);
+        self.write(batch).await
+    }
+
+    pub async fn write(&self, batch: WriteBatch) -> Result<(), NdbError> {
+        self.write_opt(batch, &WriteOptions::default()).await
+    }
+
+    /// Splits `batch` up by shard and writes the pieces concurrently. Each
+    /// shard's piece is applied atomically, but a crash can leave some
+    /// shards' pieces written and not others.
+    pub async fn write_opt(
+        &self,
+        batch: WriteBatch,
+        options: &WriteOptions,
+    ) -> Result<(), NdbError> {
+        let mut batches = vec![WriteBatch::new(); self.shards.len()];
+        for op in batch.ops() {
+            match op {
+                BatchOp::Put { key, value } => batches[self.shard_for(key)].put(key, value),
+                BatchOp::PutUntil {
+                    key,
+                    value,
+                    expires_at,
+                } => batches[self.shard_for(key)].put_until(key, value, expires_at),
+                BatchOp::Merge { key, operand } => batches[self.shard_for(key)].merge(key, operand),
+                BatchOp::Delete { key } => batches[self.shard_for(key)].delete(key),
+                BatchOp::DeleteRange { start, end } => {
+                    for batch in &mut batches {
+                        batch.delete_range(start, end);
+                    }
+                }
+            }
+        }
+        let writes = (self.shards.iter().zip(batches))
+            .filter(|(_, batch)| !batch.is_empty())
+            .map(|(db, batch)| db.write_opt(batch, options));
+        future::try_join_all(writes).await?;
+        Ok(())
+    }
+
+    /// Returns an iterator over the keys in `range`, in ascending order,
+    /// across every shard.
+    pub async fn scan(
+        &self,
+        range: impl RangeBounds<Vec<u8>> + Clone,
+    ) -> Result<MergeIterator, NdbError> {
+        let scans = self.shards.iter().map(|db| db.scan(range.clone()));
+        let iters = future::try_join_all(scans).await?;
+        // A key is only ever in one shard, so there's nothing to resolve.
+        Ok(MergeIterator::new(iters, ConflictResolution::FirstWins))
+    }
+
+    /// Returns an iterator over the keys in `range`, in descending order,
+    /// across every shard.
+    pub async fn scan_rev(
+        &self,
+        range: impl RangeBounds<Vec<u8>> + Clone,
+    ) -> Result<MergeIterator, NdbError> {
+        let scans = self.shards.iter().map(|db| db.scan_rev(range.clone()));
+        let iters = future::try_join_all(scans).await?;
+        Ok(MergeIterator::new(iters, ConflictResolution::FirstWins))
+    }
+
+    /// Flushes every shard's memtable, concurrently.
+    pub async fn flush_memtable(&mut self) -> Result<(), NdbError> {
+        let flushes = self.shards.iter_mut().map(Db::flush_memtable);
+        future::try_join_all(flushes).await?;
+        Ok(())
+    }
+
+    /// Vacuums every shard, concurrently, and returns how many tables were
+    /// merged away in all.
+    pub async fn vacuum(&mut self) -> Result<usize, NdbError> {
+        let vacuums = self.shards.iter_mut().map(Db::vacuum);
+        Ok(future::try_join_all(vacuums).await?.into_iter().sum())
+    }
+
+    /// Closes every shard. See [`Db::close`].
+    pub async fn close(self) -> Result<(), NdbError> {
+        future::try_join_all(self.shards.into_iter().map(Db::close)).await?;
+        Ok(())
+    }
+}
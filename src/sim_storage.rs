@@ -0,0 +1,474 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::{NdbError, ReadableFile, Storage, WritableFile};
+
+/// The faults a [`SimStorage`] injects. None of them by default.
+#[derive(Debug, Clone, Default)]
+pub struct SimFaults {
+    /// In a crash, keep a random part of what was written to each file since
+    /// it was last synced, as if the crash came partway through writing it.
+    /// Without this, all of it is lost.
+    pub torn_writes: bool,
+    /// Files created, renamed and removed are only sure to stay that way
+    /// through a crash once their directory is synced with
+    /// [`Storage::sync_dir`]. Until then, each may or may not, independently
+    /// of the others, so a rename can survive when one before it doesn't.
+    /// Without this, they're durable as soon as they're done.
+    pub reorder_renames: bool,
+    /// Up to how many times each sync yields to other tasks before it
+    /// finishes, so writes and syncs from different tasks interleave
+    /// differently from seed to seed.
+    pub max_sync_delay: u32,
+}
+
+/// Files kept in memory, like [`MemStorage`](crate::MemStorage), that can
+/// simulate a crash, for testing that a database survives one. Written data
+/// is only durable once it's synced, and a crash keeps what's durable,
+/// along with whatever else [`SimFaults`] allows. Every choice it makes comes
+/// from its seed, so a run on a single-threaded runtime with a
+/// [`ManualClock`](crate::ManualClock) can be repeated exactly.
+///
+/// A crash can be set to happen at any operation with
+/// [`crash_after`](SimStorage::crash_after). That operation and every one
+/// after it fail, as if the process had died there, and
+/// [`restart`](SimStorage::restart) gives the storage a new process would
+/// find. Clones share the same files.
+#[derive(Clone)]
+pub struct SimStorage {
+    state: Arc<Mutex<SimState>>,
+}
+
+struct SimState {
+    rng: u64,
+    faults: SimFaults,
+    ops: u64,
+    crash_at: Option<u64>,
+    crashed: bool,
+    // Each file, by an id that stays with it through renames, like an inode.
+    files: HashMap<u64, SimFile>,
+    next_id: u64,
+    names: BTreeMap<PathBuf, u64>,
+    // The names that would survive a crash, and the changes made to them
+    // since, oldest first, with `SimFaults::reorder_renames`.
+    durable_names: BTreeMap<PathBuf, u64>,
+    pending: Vec<NameChange>,
+}
+
+#[derive(Default)]
+struct SimFile {
+    data: Vec<u8>,
+    durable: Vec<u8>,
+}
+
+enum NameChange {
+    Create(PathBuf, u64),
+    Rename(PathBuf, PathBuf, u64),
+    Remove(PathBuf, u64),
+}
+
+impl NameChange {
+    fn dir(&self) -> Option<&Path> {
+        match self {
+            NameChange::Create(path, _)
+            | NameChange::Rename(_, path, _)
+            | NameChange::Remove(path, _) => path.parent(),
+        }
+    }
+
+    fn apply(&self, names: &mut BTreeMap<PathBuf, u64>) {
+        match self {
+            NameChange::Create(path, id) => {
+                names.insert(path.clone(), *id);
+            }
+            NameChange::Rename(from, to, id) => {
+                if names.get(from) == Some(id) {
+                    names.remove(from);
+                }
+                names.insert(to.clone(), *id);
+            }
+            NameChange::Remove(path, id) => {
+                if names.get(path) == Some(id) {
+                    names.remove(path);
+                }
+            }
+        }
+    }
+}
+
+impl SimStorage {
+    pub fn new(seed: u64, faults: SimFaults) -> SimStorage {
+        SimStorage {
+            state: Arc::new(Mutex::new(SimState {
+                rng: seed,
+                faults,
+                ops: 0,
+                crash_at: None,
+                crashed: false,
+                files: HashMap::new(),
+                next_id: 0,
+                names: BTreeMap::new(),
+                durable_names: BTreeMap::new(),
+                pending: Vec::new(),
+            })),
+        }
+    }
+
+    /// How many operations there have been, counting every call on the
+    /// storage and the files it's opened.
+    pub fn ops(&self) -> u64 {
+        self.state.lock().unwrap().ops
+    }
+
+    /// Crashes once there have been `ops` more operations, failing that one
+    /// and everything after.
+    pub fn crash_after(&self, ops: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.crash_at = Some(state.ops + ops.max(1));
+    }
+
+    /// Crashes now, if it hasn't already.
+    pub fn crash(&self) {
+        self.state.lock().unwrap().crash();
+    }
+
+    pub fn crashed(&self) -> bool {
+        self.state.lock().unwrap().crashed
+    }
+
+    /// Crashes, if it hasn't already, and returns what a new process would
+    /// find: the files that survived, fully durable, with the same faults
+    /// and no crash set. Anything still holding this storage keeps failing.
+    pub fn restart(&self) -> SimStorage {
+        let mut state = self.state.lock().unwrap();
+        state.crash();
+        let mut files = HashMap::new();
+        for &id in state.durable_names.values() {
+            let durable = state.files[&id].durable.clone();
+            files.insert(
+                id,
+                SimFile {
+                    data: durable.clone(),
+                    durable,
+                },
+            );
+        }
+        let rng = state.next_rng();
+        SimStorage {
+            state: Arc::new(Mutex::new(SimState {
+                rng,
+                faults: state.faults.clone(),
+                ops: 0,
+                crash_at: None,
+                crashed: false,
+                files,
+                next_id: state.next_id,
+                names: state.durable_names.clone(),
+                durable_names: state.durable_names.clone(),
+                pending: Vec::new(),
+            })),
+        }
+    }
+
+    // Counts an operation and runs `f` on the state, unless it's crashed.
+    fn op<T>(&self, f: impl FnOnce(&mut SimState) -> Result<T, NdbError>) -> Result<T, NdbError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.crashed {
+            state.ops += 1;
+            if state.crash_at == Some(state.ops) {
+                state.crash();
+            }
+        }
+        if state.crashed {
+            return Err(NdbError::Io(io::Error::other("simulated crash")));
+        }
+        f(&mut state)
+    }
+}
+
+impl SimState {
+    // SplitMix64.
+    fn next_rng(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Decides what survives: what's durable, some of each file's unsynced
+    // writes with `torn_writes`, and some of the unsynced name changes with
+    // `reorder_renames`.
+    fn crash(&mut self) {
+        if self.crashed {
+            return;
+        }
+        self.crashed = true;
+        for change in std::mem::take(&mut self.pending) {
+            if self.next_rng().is_multiple_of(2) {
+                change.apply(&mut self.durable_names);
+            }
+        }
+        if self.faults.torn_writes {
+            let mut ids: Vec<u64> = self.files.keys().copied().collect();
+            ids.sort();
+            for id in ids {
+                let kept = self.next_rng();
+                let file = self.files.get_mut(&id).unwrap();
+                // Only appends are torn: a file rewritten since its last sync
+                // reverts.
+                if file.data.starts_with(&file.durable) {
+                    let unsynced = (file.data.len() - file.durable.len()) as u64;
+                    let end = file.durable.len() + (kept % (unsynced + 1)) as usize;
+                    file.durable = file.data[..end].to_vec();
+                }
+            }
+        }
+    }
+
+    fn id(&self, path: &Path) -> Result<u64, NdbError> {
+        self.names.get(path).copied().ok_or_else(|| {
+            NdbError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found", path.display()),
+            ))
+        })
+    }
+
+    fn change_names(&mut self, change: NameChange) {
+        change.apply(&mut self.names);
+        match self.faults.reorder_renames {
+            true => self.pending.push(change),
+            false => change.apply(&mut self.durable_names),
+        }
+    }
+
+    // The file at `path`, created empty if it isn't there.
+    fn open_or_create(&mut self, path: &Path) -> u64 {
+        if let Some(&id) = self.names.get(path) {
+            return id;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.files.insert(id, SimFile::default());
+        self.change_names(NameChange::Create(path.into(), id));
+        id
+    }
+}
+
+struct SimWriter {
+    storage: SimStorage,
+    id: u64,
+}
+
+struct SimReader {
+    storage: SimStorage,
+    id: u64,
+}
+
+impl Storage for SimStorage {
+    fn create<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        async move {
+            // Like opening with O_TRUNC, a file that's there is emptied.
+            let id = self.op(|state| {
+                let id = state.open_or_create(path);
+                state.files.get_mut(&id).unwrap().data.clear();
+                Ok(id)
+            })?;
+            let writer = SimWriter {
+                storage: self.clone(),
+                id,
+            };
+            Ok(Box::new(writer) as Box<dyn WritableFile>)
+        }
+        .boxed()
+    }
+
+    fn append<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        async move {
+            let id = self.op(|state| Ok(state.open_or_create(path)))?;
+            let writer = SimWriter {
+                storage: self.clone(),
+                id,
+            };
+            Ok(Box::new(writer) as Box<dyn WritableFile>)
+        }
+        .boxed()
+    }
+
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Arc<dyn ReadableFile>, NdbError>> {
+        async move {
+            let id = self.op(|state| state.id(path))?;
+            let reader = SimReader {
+                storage: self.clone(),
+                id,
+            };
+            Ok(Arc::new(reader) as Arc<dyn ReadableFile>)
+        }
+        .boxed()
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            self.op(|state| {
+                let id = state.id(from)?;
+                state.change_names(NameChange::Rename(from.into(), to.into(), id));
+                Ok(())
+            })
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            self.op(|state| {
+                let id = state.id(path)?;
+                state.change_names(NameChange::Remove(path.into(), id));
+                Ok(())
+            })
+        }
+        .boxed()
+    }
+
+    fn truncate<'a>(&'a self, path: &'a Path, len: u64) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            self.op(|state| {
+                let id = state.id(path)?;
+                let file = state.files.get_mut(&id).unwrap();
+                file.data.resize(len as usize, 0);
+                file.durable = file.data.clone();
+                Ok(())
+            })
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Vec<PathBuf>, NdbError>> {
+        async move {
+            self.op(|state| {
+                Ok(state
+                    .names
+                    .keys()
+                    .filter(|path| path.parent() == Some(dir))
+                    .cloned()
+                    .collect())
+            })
+        }
+        .boxed()
+    }
+
+    fn size<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<u64, NdbError>> {
+        async move { self.op(|state| Ok(state.files[&state.id(path)?].data.len() as u64)) }.boxed()
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool, NdbError>> {
+        async move { self.op(|state| Ok(state.names.contains_key(path))) }.boxed()
+    }
+
+    fn create_dir_all<'a>(&'a self, _dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move { self.op(|_| Ok(())) }.boxed()
+    }
+
+    fn sync_dir<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            self.op(|state| {
+                let (synced, pending) = std::mem::take(&mut state.pending)
+                    .into_iter()
+                    .partition(|change| change.dir() == Some(dir));
+                state.pending = pending;
+                for change in synced {
+                    change.apply(&mut state.durable_names);
+                }
+                Ok(())
+            })
+        }
+        .boxed()
+    }
+}
+
+impl WritableFile for SimWriter {
+    fn write<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            self.storage.op(|state| {
+                let file = state.files.get_mut(&self.id).unwrap();
+                file.data.extend_from_slice(data);
+                Ok(())
+            })
+        }
+        .boxed()
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        async move { self.storage.op(|_| Ok(())) }.boxed()
+    }
+
+    fn sync(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        async move {
+            let delay = self.storage.op(|state| {
+                let max = state.faults.max_sync_delay as u64;
+                Ok(state.next_rng() % (max + 1))
+            })?;
+            for _ in 0..delay {
+                tokio::task::yield_now().await;
+            }
+            self.storage.op(|state| {
+                let file = state.files.get_mut(&self.id).unwrap();
+                file.durable = file.data.clone();
+                Ok(())
+            })
+        }
+        .boxed()
+    }
+}
+
+impl ReadableFile for SimReader {
+    fn read_at(
+        &self,
+        offset: u64,
+        len: usize,
+        mut buf: Vec<u8>,
+    ) -> BoxFuture<'_, Result<Vec<u8>, NdbError>> {
+        async move {
+            self.storage.op(|state| {
+                let data = &state.files[&self.id].data;
+                let start = offset as usize;
+                let bytes = start
+                    .checked_add(len)
+                    .and_then(|end| data.get(start..end))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "read past the end of the file",
+                        )
+                    })?;
+                buf.clear();
+                buf.extend_from_slice(bytes);
+                Ok(buf)
+            })
+        }
+        .boxed()
+    }
+
+    fn size(&self) -> BoxFuture<'_, Result<u64, NdbError>> {
+        async move {
+            self.storage
+                .op(|state| Ok(state.files[&self.id].data.len() as u64))
+        }
+        .boxed()
+    }
+}
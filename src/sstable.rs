@@ -0,0 +1,1000 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Bound, Range},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use crate::{
+    block::{self, Block, BlockBuilder, BlockHandle, BLOCK_TRAILER_SIZE},
+    bloom::{self, BloomFilterBuilder},
+    coding::{decode_fixed64, put_fixed64},
+    compression::Compression,
+    direct::DirectFile,
+    index::IndexSearch,
+    iter::KvSource,
+    mmap::{self, MmapOptions},
+    pread::FileIo,
+    range_del::RangeTombstones,
+    rate_limiter::RateLimiter,
+    stats::IoCounters,
+    storage::LocalFile,
+    table_cache::TableCache,
+    tables,
+    value::Value,
+    version::{self, FORMAT_VERSION},
+    DbOptions, NdbError, Queryable, ReadableFile, Storage, WritableFile,
+};
+
+// An SSTable data file is laid out as:
+//
+//   data block 1 .. data block n | filter block | index block | footer
+//
+// The index block maps the last key of each data block, truncated to
+// `index_key_len` if the table has one, to that block's handle. The footer is fixed size so it can be found from the end of the
+// file:
+//
+//   index offset (fixed64) | index size (fixed64) |
+//   filter offset (fixed64) | filter size (fixed64) |
+//   format version (fixed64) | magic (fixed64)
+//
+// Tables written before they were stamped with a version have no version
+// field, and a different magic number.
+
+pub(crate) const FOOTER_SIZE: usize = 48;
+pub(crate) const MAGIC: u64 = 0x6e75_6c6c_6462_7332;
+const UNVERSIONED_FOOTER_SIZE: usize = 40;
+const UNVERSIONED_MAGIC: u64 = 0x6e75_6c6c_6462_7373;
+// How much of an external table is read at a time to checksum it.
+const CHECKSUM_CHUNK: u64 = 1 << 20;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct SSTableMetadata {
+    pub(crate) written_timestamp: u64,
+    pub(crate) data_path: PathBuf,
+    // Both `None` for an empty table.
+    #[serde(default)]
+    pub(crate) smallest_key: Option<Vec<u8>>,
+    #[serde(default)]
+    pub(crate) largest_key: Option<Vec<u8>>,
+    // CRC32C of the whole data file. Missing for tables written before this
+    // was recorded.
+    #[serde(default)]
+    pub(crate) checksum: Option<u32>,
+    // Deleted ranges, which hide keys in older tables but not the entries in
+    // this one.
+    #[serde(default, skip_serializing_if = "RangeTombstones::is_empty")]
+    pub(crate) range_tombstones: RangeTombstones,
+    // Picked from the index keys when the table is written.
+    #[serde(default)]
+    pub(crate) index_search: IndexSearch,
+    // Index keys are cut down to this many bytes, so neighbouring blocks can
+    // share one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) index_key_len: Option<usize>,
+    // The lowest and highest sequence numbers of the writes in the table.
+    // Missing for tables written before they were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) sequence_range: Option<(u64, u64)>,
+}
+
+pub(crate) struct SSTable {
+    pub(crate) meta: SSTableMetadata,
+    // Where the data file is opened when it's read.
+    files: Arc<TableCache>,
+    // The data file, held open for as long as the table is around once it's
+    // been deleted, so views still reading it can.
+    pinned: std::sync::Mutex<Option<Arc<dyn ReadableFile>>>,
+    // The data file mapped into memory, with `DbOptions::mmap`. Blocks are
+    // then copied out of it rather than read from the file.
+    mapped: Option<Mmap>,
+    index: Vec<(Vec<u8>, BlockHandle)>,
+    filter: Vec<u8>,
+    // The size of the data file.
+    pub(crate) size: u64,
+    // The format version the table was written in.
+    pub(crate) version: u32,
+    skip_corrupt: bool,
+    io: Arc<IoCounters>,
+    // With `skip_corrupt`, the data blocks found to be corrupt, by offset,
+    // and what was wrong with them. They aren't read again.
+    corrupt: std::sync::Mutex<BTreeMap<u64, String>>,
+}
+
+pub(crate) struct Footer {
+    pub(crate) index: BlockHandle,
+    pub(crate) filter: BlockHandle,
+    pub(crate) version: u32,
+}
+
+impl Footer {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(FOOTER_SIZE);
+        put_fixed64(&mut buf, self.index.offset);
+        put_fixed64(&mut buf, self.index.size);
+        put_fixed64(&mut buf, self.filter.offset);
+        put_fixed64(&mut buf, self.filter.size);
+        put_fixed64(&mut buf, self.version.into());
+        put_fixed64(&mut buf, MAGIC);
+        buf
+    }
+
+    // Decodes the footer at the end of `tail`, returning it and its size.
+    fn decode(tail: &[u8]) -> Result<(Footer, usize), NdbError> {
+        let magic = decode_fixed64(&tail[tail.len() - 8..]);
+        let (size, version) = match magic {
+            MAGIC if tail.len() >= FOOTER_SIZE => {
+                let version = decode_fixed64(&tail[tail.len() - 16..]);
+                (FOOTER_SIZE, u32::try_from(version).unwrap_or(u32::MAX))
+            }
+            UNVERSIONED_MAGIC => (UNVERSIONED_FOOTER_SIZE, version::UNVERSIONED),
+            _ => return Err(NdbError::corruption("bad sstable magic number")),
+        };
+        let buf = &tail[tail.len() - size..];
+        let footer = Footer {
+            index: BlockHandle {
+                offset: decode_fixed64(buf),
+                size: decode_fixed64(&buf[8..]),
+            },
+            filter: BlockHandle {
+                offset: decode_fixed64(&buf[16..]),
+                size: decode_fixed64(&buf[24..]),
+            },
+            version,
+        };
+        Ok((footer, size))
+    }
+}
+
+// Reads the block at `handle` into `buf`.
+async fn read_block(
+    file_io: &FileIo,
+    file: &Arc<dyn ReadableFile>,
+    handle: BlockHandle,
+    buf: Vec<u8>,
+) -> Result<Vec<u8>, NdbError> {
+    let len = handle.size as usize + BLOCK_TRAILER_SIZE;
+    let raw = file_io.read_at(file, handle.offset, len, buf).await?;
+    block::verify_trailer(raw)
+}
+
+// Maps a table's data file into memory, if `options` say to and it's a
+// local file. The file is closed once it's mapped, since it's no longer read.
+fn map_table(
+    files: &TableCache,
+    path: &Path,
+    file: &Arc<dyn ReadableFile>,
+    options: Option<&MmapOptions>,
+    size: u64,
+) -> Result<Option<Mmap>, NdbError> {
+    match (options, file.as_file()) {
+        (Some(options), Some(file)) if options.maps(size) => {
+            let map = mmap::map(file, options)?;
+            files.remove(path);
+            Ok(Some(map))
+        }
+        _ => Ok(None),
+    }
+}
+
+impl SSTable {
+    #[instrument(skip_all, fields(path = %meta.data_path.display()))]
+    pub(crate) async fn open(
+        meta: SSTableMetadata,
+        options: &DbOptions,
+        io: &Arc<IoCounters>,
+        files: &Arc<TableCache>,
+    ) -> Result<SSTable, NdbError> {
+        let data_file = files.get(&meta.data_path).await?;
+        let (footer, index, filter, size) = SSTable::read_footer(&files.file_io, &data_file)
+            .await
+            .map_err(|err| err.in_file(&meta.data_path))?;
+        version::check(&meta.data_path, footer.version)?;
+        let mapped = map_table(
+            files,
+            &meta.data_path,
+            &data_file,
+            options.mmap.as_ref(),
+            size,
+        )?;
+        debug!(
+            bytes = size,
+            blocks = index.len(),
+            mapped = mapped.is_some(),
+            "opened table"
+        );
+
+        Ok(SSTable {
+            meta,
+            files: files.clone(),
+            pinned: Default::default(),
+            mapped,
+            index,
+            filter,
+            size,
+            version: footer.version,
+            skip_corrupt: options.skip_corrupt_blocks,
+            io: io.clone(),
+            corrupt: Default::default(),
+        })
+    }
+
+    pub(crate) async fn read_footer(
+        file_io: &FileIo,
+        data_file: &Arc<dyn ReadableFile>,
+    ) -> Result<(Footer, Vec<(Vec<u8>, BlockHandle)>, Vec<u8>, u64), NdbError> {
+        let file_len = data_file.size().await?;
+        if file_len < UNVERSIONED_FOOTER_SIZE as u64 {
+            return Err(NdbError::corruption("sstable too short"));
+        }
+        let tail_len = file_len.min(FOOTER_SIZE as u64);
+        let tail_start = file_len - tail_len;
+        let tail = file_io
+            .read_at(data_file, tail_start, tail_len as usize, Vec::new())
+            .await?;
+        let (footer, footer_size) =
+            Footer::decode(&tail).map_err(|err| err.at_offset(tail_start))?;
+        let footer_start = file_len - footer_size as u64;
+
+        // Check every handle before trusting it with a read, so a corrupt
+        // size can't turn into a huge allocation.
+        let in_bounds = |handle: &BlockHandle, end: u64| {
+            handle
+                .offset
+                .checked_add(handle.size)
+                .and_then(|n| n.checked_add(BLOCK_TRAILER_SIZE as u64))
+                .is_some_and(|n| n <= end)
+        };
+        if !in_bounds(&footer.index, footer_start) || !in_bounds(&footer.filter, footer_start) {
+            return Err(
+                NdbError::corruption("footer block handle out of range").at_offset(footer_start)
+            );
+        }
+
+        let in_index = |err: NdbError| err.at_offset(footer.index.offset);
+        let index_block = read_block(file_io, data_file, footer.index, Vec::new())
+            .await
+            .and_then(Block::new)
+            .map_err(in_index)?;
+        let mut index: Vec<(Vec<u8>, BlockHandle)> = Vec::new();
+        for entry in index_block.iter() {
+            let (key, mut value) = entry.map_err(in_index)?;
+            let handle = BlockHandle::decode_from(&mut value)
+                .ok_or_else(|| in_index(NdbError::corruption("bad index entry")))?;
+            if !in_bounds(&handle, footer.filter.offset) {
+                return Err(in_index(NdbError::corruption(
+                    "index block handle out of range",
+                )));
+            }
+            // Lookups binary search the index, which only works if it's
+            // sorted. Truncated keys can repeat.
+            if index.last().is_some_and(|(prev, _)| *prev > key) {
+                return Err(in_index(NdbError::corruption("index keys out of order")));
+            }
+            index.push((key, handle));
+        }
+
+        let filter = read_block(file_io, data_file, footer.filter, Vec::new())
+            .await
+            .map_err(|err| err.at_offset(footer.filter.offset))?;
+
+        Ok((footer, index, filter, file_len))
+    }
+}
+
+fn truncate(key: &[u8], len: Option<usize>) -> &[u8] {
+    &key[..len.map_or(key.len(), |len| len.min(key.len()))]
+}
+
+impl SSTable {
+    // Whether `key` falls within the range of keys written to this table, or
+    // one of its deleted ranges.
+    pub(crate) fn may_contain_key(&self, key: &[u8]) -> bool {
+        self.in_key_range(key) || self.meta.range_tombstones.covers(key)
+    }
+
+    // Whether this table might have an entry for `key`, going only by what's
+    // in memory.
+    pub(crate) fn may_have_entry(&self, key: &[u8]) -> bool {
+        self.in_key_range(key) && bloom::may_contain(&self.filter, key)
+    }
+
+    // The smallest and largest keys in the table, if it's recorded them.
+    pub(crate) fn key_range(&self) -> Option<(&[u8], &[u8])> {
+        match (&self.meta.smallest_key, &self.meta.largest_key) {
+            (Some(smallest), Some(largest)) => Some((smallest, largest)),
+            _ => None,
+        }
+    }
+
+    // Whether the table might have keys between `start` and `end`.
+    pub(crate) fn overlaps(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+        match self.key_range() {
+            Some((smallest, largest)) => {
+                tables::after_start(largest, start) && tables::before_end(smallest, end)
+            }
+            // Written before key ranges were recorded.
+            None => !self.index.is_empty(),
+        }
+    }
+
+    fn in_key_range(&self, key: &[u8]) -> bool {
+        match self.key_range() {
+            Some((smallest, largest)) => smallest <= key && key <= largest,
+            // Written before key ranges were recorded.
+            _ => !self.index.is_empty(),
+        }
+    }
+}
+
+impl Queryable for SSTable {
+    async fn get(&self, key: &[u8]) -> Result<Option<Value>, NdbError> {
+        let deleted = self
+            .meta
+            .range_tombstones
+            .covers(key)
+            .then_some(Value::Delete);
+        self.io.bloom_checks.fetch_add(1, Ordering::Relaxed);
+        if !bloom::may_contain(&self.filter, key) {
+            self.io.bloom_negatives.fetch_add(1, Ordering::Relaxed);
+            return Ok(deleted);
+        }
+
+        // If there are no blocks to look in, either because the key is past
+        // the end of the table or the table is empty, it's not here. A key
+        // before the start of the table lands on the first block and then
+        // isn't found in it.
+        for i in self.find_blocks(key) {
+            let found = async {
+                let block = self.data_block(i, self.files.buffers.take()).await?;
+                let found = block.seek(key)?;
+                self.files.buffers.give(block.into_data());
+                match found {
+                    Some((found, value)) if found == key => Ok(Some(Value::decode(&value)?)),
+                    _ => Ok(None),
+                }
+            };
+            if let Some(value) = self.corrupt_at(i, found.await)? {
+                return Ok(Some(value));
+            }
+        }
+        self.io
+            .bloom_false_positives
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(deleted)
+    }
+}
+
+// Yields the entries of a table in order, or in reverse order, one block at a
+// time.
+pub(crate) struct TableIter {
+    table: Arc<SSTable>,
+    // The blocks that haven't been loaded yet.
+    blocks: Range<usize>,
+    reverse: bool,
+    entries: std::vec::IntoIter<(Vec<u8>, Value)>,
+}
+
+impl TableIter {
+    pub(crate) async fn seek(
+        table: Arc<SSTable>,
+        start: Bound<&[u8]>,
+    ) -> Result<TableIter, NdbError> {
+        let first_block = match start {
+            Bound::Included(start) | Bound::Excluded(start) => table.find_blocks(start).start,
+            Bound::Unbounded => 0,
+        };
+        let blocks = first_block..table.index.len();
+        TableIter::positioned(table, blocks, false, |key| match start {
+            Bound::Included(start) => key >= start,
+            Bound::Excluded(start) => key > start,
+            Bound::Unbounded => true,
+        })
+        .await
+    }
+
+    // Like `seek`, but yields the entries up to `end` in descending order.
+    pub(crate) async fn seek_rev(
+        table: Arc<SSTable>,
+        end: Bound<&[u8]>,
+    ) -> Result<TableIter, NdbError> {
+        let blocks = match end {
+            Bound::Included(end) | Bound::Excluded(end) => 0..table.find_blocks(end).end,
+            Bound::Unbounded => 0..table.index.len(),
+        };
+        TableIter::positioned(table, blocks, true, |key| match end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        })
+        .await
+    }
+
+    // Loads blocks until one has something in range, dropping whatever
+    // before that is outside it. With truncated index keys, the first few
+    // blocks can be entirely out of range.
+    async fn positioned(
+        table: Arc<SSTable>,
+        blocks: Range<usize>,
+        reverse: bool,
+        in_range: impl Fn(&[u8]) -> bool,
+    ) -> Result<TableIter, NdbError> {
+        let mut iter = TableIter {
+            table,
+            blocks,
+            reverse,
+            entries: Vec::new().into_iter(),
+        };
+        while iter.load_next_block().await? {
+            let entries: Vec<_> = std::mem::take(&mut iter.entries)
+                .filter(|(key, _)| in_range(key))
+                .collect();
+            iter.entries = entries.into_iter();
+            if iter.entries.len() > 0 {
+                break;
+            }
+        }
+        Ok(iter)
+    }
+
+    async fn load_next_block(&mut self) -> Result<bool, NdbError> {
+        let next = if self.reverse {
+            self.blocks.next_back()
+        } else {
+            self.blocks.next()
+        };
+        let Some(i) = next else {
+            return Ok(false);
+        };
+        let entries = self.table.block_entries(i).await;
+        let mut entries = self.table.corrupt_at(i, entries)?;
+        if self.reverse {
+            entries.reverse();
+        }
+        self.entries = entries.into_iter();
+        Ok(true)
+    }
+}
+
+impl KvSource for TableIter {
+    type Value = Value;
+
+    async fn next(&mut self) -> Result<Option<(Vec<u8>, Value)>, NdbError> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Ok(Some(entry));
+            }
+            if !self.load_next_block().await? {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+impl PartialOrd for SSTable {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SSTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.meta.written_timestamp == other.meta.written_timestamp
+    }
+}
+
+impl Eq for SSTable {}
+
+impl Ord for SSTable {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.meta
+            .written_timestamp
+            .cmp(&other.meta.written_timestamp)
+            .reverse()
+    }
+}
+
+// Where a table's written to.
+enum TableFile {
+    Storage {
+        file: Box<dyn WritableFile>,
+        storage: Arc<dyn Storage>,
+        path: PathBuf,
+    },
+    // With `DbOptions::direct_io_writes`, on local storage.
+    Direct(DirectFile),
+}
+
+impl TableFile {
+    async fn create(path: &Path, options: &DbOptions) -> Result<TableFile, NdbError> {
+        let storage = &options.storage;
+        if options.direct_io_writes && storage.is_local() {
+            if let Some(file) = DirectFile::create(path).await? {
+                return Ok(TableFile::Direct(file));
+            }
+        }
+        Ok(TableFile::Storage {
+            file: storage.create(path).await?,
+            storage: storage.clone(),
+            path: path.into(),
+        })
+    }
+
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), NdbError> {
+        match self {
+            TableFile::Storage { file, .. } => file.write(buf).await,
+            TableFile::Direct(file) => file.write_all(buf).await,
+        }
+    }
+
+    async fn sync_data(&mut self) -> Result<(), NdbError> {
+        match self {
+            TableFile::Storage { file, .. } => file.sync().await,
+            TableFile::Direct(file) => file.sync_data().await,
+        }
+    }
+
+    // Makes the whole file durable and opens it to be read.
+    async fn finish(self) -> Result<Arc<dyn ReadableFile>, NdbError> {
+        match self {
+            TableFile::Storage {
+                mut file,
+                storage,
+                path,
+            } => {
+                file.finish().await?;
+                storage.open(&path).await
+            }
+            TableFile::Direct(file) => Ok(Arc::new(LocalFile::new(file.finish().await?))),
+        }
+    }
+}
+
+pub(crate) struct TableBuilder {
+    file: TableFile,
+    offset: u64,
+    // Running CRC32C of everything written so far.
+    checksum: u32,
+    block_size: usize,
+    compression: Compression,
+    index_key_len: Option<usize>,
+    data_block: BlockBuilder,
+    index_block: BlockBuilder,
+    filter: BloomFilterBuilder,
+    sync_bytes: Option<u64>,
+    synced_offset: u64,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    // How many times the file has been synced so far.
+    syncs: u64,
+}
+
+impl TableBuilder {
+    pub(crate) async fn create(
+        path: impl AsRef<Path>,
+        options: &DbOptions,
+    ) -> Result<TableBuilder, NdbError> {
+        let file = TableFile::create(path.as_ref(), options).await?;
+        Ok(TableBuilder {
+            file,
+            offset: 0,
+            checksum: 0,
+            block_size: options.block_size,
+            compression: options.compression,
+            index_key_len: options.max_index_key_len,
+            data_block: BlockBuilder::default(),
+            index_block: BlockBuilder::default(),
+            filter: BloomFilterBuilder::default(),
+            sync_bytes: options.table_sync_bytes,
+            synced_offset: 0,
+            rate_limiter: options.rate_limiter.clone(),
+            syncs: 0,
+        })
+    }
+
+    // Keys must be added in sorted order.
+    pub(crate) async fn add(&mut self, key: &[u8], value: &[u8]) -> Result<(), NdbError> {
+        self.data_block.add(key, value);
+        self.filter.add(key);
+        if self.data_block.estimated_size() >= self.block_size {
+            self.flush_data_block().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush_data_block(&mut self) -> Result<(), NdbError> {
+        if self.data_block.is_empty() {
+            return Ok(());
+        }
+        let last_key = truncate(self.data_block.last_key(), self.index_key_len).to_vec();
+        let contents = self.data_block.finish();
+        let handle = self.write_block(&contents).await?;
+        let mut encoded = Vec::new();
+        handle.encode_to(&mut encoded);
+        self.index_block.add(&last_key, &encoded);
+        Ok(())
+    }
+
+    async fn write_block(&mut self, contents: &[u8]) -> Result<BlockHandle, NdbError> {
+        let (compression, stored) = self.compression.compress(contents)?;
+        let mut trailer = Vec::with_capacity(BLOCK_TRAILER_SIZE);
+        block::write_trailer(&mut trailer, &stored, compression);
+        let handle = BlockHandle {
+            offset: self.offset,
+            size: stored.len() as u64,
+        };
+        self.write(&stored).await?;
+        self.write(&trailer).await?;
+        Ok(handle)
+    }
+
+    async fn write(&mut self, buf: &[u8]) -> Result<(), NdbError> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.request(buf.len() as u64).await;
+        }
+        self.file.write_all(buf).await?;
+        self.offset += buf.len() as u64;
+        self.checksum = crc32c::crc32c_append(self.checksum, buf);
+        if self
+            .sync_bytes
+            .is_some_and(|every| self.offset - self.synced_offset >= every)
+        {
+            // Only the data, since the size is synced at the end anyway.
+            self.file.sync_data().await?;
+            self.synced_offset = self.offset;
+            self.syncs += 1;
+        }
+        Ok(())
+    }
+
+    // Returns the finished file along with its checksum and how many times
+    // it was synced, counting the final sync.
+    pub(crate) async fn finish(mut self) -> Result<(Arc<dyn ReadableFile>, u32, u64), NdbError> {
+        self.flush_data_block().await?;
+
+        let filter = std::mem::take(&mut self.filter).finish();
+        let filter = self.write_block(&filter).await?;
+        let index = self.index_block.finish();
+        let index = self.write_block(&index).await?;
+        let footer = Footer {
+            index,
+            filter,
+            version: FORMAT_VERSION,
+        };
+        self.write(&footer.encode()).await?;
+
+        let file = self.file.finish().await?;
+        Ok((file, self.checksum, self.syncs + 1))
+    }
+}
+
+// The path in `dir` for a table named after `timestamp`, or the next free
+// timestamp after it if that one's taken, along with the timestamp used.
+pub(crate) async fn free_table_path(
+    dir: &Path,
+    storage: &dyn Storage,
+    timestamp: u64,
+) -> Result<(PathBuf, u64), NdbError> {
+    let mut now = timestamp;
+    while storage.exists(&dir.join(format!("{}.sst", now))).await? {
+        now += 1;
+    }
+    Ok((dir.join(format!("{}.sst", now)), now))
+}
+
+// Writes a new table into a database directory. Its metadata is recorded in
+// the database's manifest once it's finished.
+pub(crate) struct SSTableWriter {
+    builder: TableBuilder,
+    meta: SSTableMetadata,
+    skip_corrupt: bool,
+    mmap: Option<MmapOptions>,
+    io: Arc<IoCounters>,
+    files: Arc<TableCache>,
+}
+
+impl SSTableWriter {
+    // The table is named after `timestamp`, which also orders it against the
+    // other tables. If that name is somehow taken already, the next free one
+    // is used.
+    pub(crate) async fn create(
+        dir: impl AsRef<Path>,
+        options: &DbOptions,
+        io: &Arc<IoCounters>,
+        files: &Arc<TableCache>,
+        timestamp: u64,
+    ) -> Result<SSTableWriter, NdbError> {
+        let (data_path, now) = free_table_path(dir.as_ref(), &*options.storage, timestamp).await?;
+        let builder = TableBuilder::create(&data_path, options).await?;
+
+        Ok(SSTableWriter {
+            builder,
+            skip_corrupt: options.skip_corrupt_blocks,
+            mmap: options.mmap.clone(),
+            io: io.clone(),
+            files: files.clone(),
+            meta: SSTableMetadata {
+                data_path,
+                written_timestamp: now,
+                smallest_key: None,
+                largest_key: None,
+                checksum: None,
+                range_tombstones: RangeTombstones::default(),
+                index_search: IndexSearch::default(),
+                index_key_len: options.max_index_key_len,
+                sequence_range: None,
+            },
+        })
+    }
+
+    // Widens the table's sequence range to include `range`.
+    pub(crate) fn add_sequences(&mut self, range: Option<(u64, u64)>) {
+        self.meta.sequence_range = match (self.meta.sequence_range, range) {
+            (Some((lo, hi)), Some((lo2, hi2))) => Some((lo.min(lo2), hi.max(hi2))),
+            (a, b) => a.or(b),
+        };
+    }
+
+    // Orders the table as if it were written at `timestamp`, to take the
+    // place of older tables it replaces.
+    pub(crate) fn order_as(&mut self, timestamp: u64) {
+        self.meta.written_timestamp = timestamp;
+    }
+
+    pub(crate) fn add_range_tombstones(&mut self, tombstones: &RangeTombstones) {
+        self.meta.range_tombstones.extend(tombstones);
+    }
+
+    // Keys must be added in sorted order.
+    pub(crate) async fn add(&mut self, key: &[u8], value: &Value) -> Result<(), NdbError> {
+        if self.meta.smallest_key.is_none() {
+            self.meta.smallest_key = Some(key.to_vec());
+        }
+        let largest = self.meta.largest_key.get_or_insert_with(Vec::new);
+        largest.clear();
+        largest.extend_from_slice(key);
+        self.builder.add(key, &value.encode()).await
+    }
+
+    pub(crate) fn data_path(&self) -> &Path {
+        &self.meta.data_path
+    }
+
+    // How much of the data file has been written so far.
+    pub(crate) fn offset(&self) -> u64 {
+        self.builder.offset
+    }
+
+    pub(crate) async fn finish(mut self) -> Result<SSTable, NdbError> {
+        let (data_file, checksum, syncs) = self.builder.finish().await?;
+        self.io.table_syncs.fetch_add(syncs, Ordering::Relaxed);
+        // The table's name has to survive a crash as well as its contents
+        // before the manifest can record it.
+        if let Some(dir) = self.meta.data_path.parent() {
+            self.files.storage.sync_dir(dir).await?;
+        }
+        self.meta.checksum = Some(checksum);
+        self.files.insert(&self.meta.data_path, data_file.clone());
+        let (footer, index, filter, size) =
+            SSTable::read_footer(&self.files.file_io, &data_file).await?;
+        let mapped = map_table(
+            &self.files,
+            &self.meta.data_path,
+            &data_file,
+            self.mmap.as_ref(),
+            size,
+        )?;
+        self.meta.index_search = IndexSearch::choose(&index);
+        self.io
+            .table_bytes_written
+            .fetch_add(size, Ordering::Relaxed);
+        debug!(
+            table = %self.meta.data_path.display(),
+            bytes = size,
+            blocks = index.len(),
+            syncs,
+            "wrote table",
+        );
+
+        Ok(SSTable {
+            meta: self.meta,
+            files: self.files,
+            pinned: Default::default(),
+            mapped,
+            index,
+            filter,
+            size,
+            version: footer.version,
+            skip_corrupt: self.skip_corrupt,
+            io: self.io,
+            corrupt: Default::default(),
+        })
+    }
+}
+
+impl SSTable {
+    // Opens a table file written outside the database, e.g. by `SstWriter`,
+    // and reads it through, checking its keys are in order and working out
+    // the metadata the database would have recorded if it had written it.
+    pub(crate) async fn open_external(
+        path: &Path,
+        options: &DbOptions,
+        io: &Arc<IoCounters>,
+        files: &Arc<TableCache>,
+    ) -> Result<SSTableMetadata, NdbError> {
+        // Every block is read, so corruption anywhere is an error.
+        let table = SSTable::open_unrecorded(path, options, io, files).await?;
+        let (mut smallest, mut largest) = (None, None::<Vec<u8>>);
+        let mut index_key_len = None;
+        for i in 0..table.block_count() {
+            for (key, _) in table.corrupt_at(i, table.block_entries(i).await)? {
+                if largest.as_ref().is_some_and(|last| *last >= key) {
+                    return Err(NdbError::InvalidArgument(format!(
+                        "{} has keys out of order",
+                        path.display()
+                    )));
+                }
+                smallest.get_or_insert_with(|| key.clone());
+                largest = Some(key);
+            }
+            // Index keys are the blocks' last keys, unless the table was
+            // written with them cut short.
+            let index_key = &table.index[i].0;
+            if largest
+                .as_ref()
+                .is_some_and(|last| index_key.len() < last.len())
+            {
+                index_key_len = Some(index_key.len());
+            }
+        }
+
+        let checksum = table.file_checksum().await?;
+        files.remove(path);
+
+        let mut meta = table.meta.clone();
+        meta.smallest_key = smallest;
+        meta.largest_key = largest;
+        meta.checksum = Some(checksum);
+        meta.index_search = IndexSearch::choose(&table.index);
+        meta.index_key_len = index_key_len;
+        Ok(meta)
+    }
+
+    // Opens a table file that isn't in any manifest, with nothing known about
+    // it but what's in the file. Corrupt blocks are errors.
+    pub(crate) async fn open_unrecorded(
+        path: &Path,
+        options: &DbOptions,
+        io: &Arc<IoCounters>,
+        files: &Arc<TableCache>,
+    ) -> Result<SSTable, NdbError> {
+        let meta = SSTableMetadata {
+            data_path: path.into(),
+            written_timestamp: 0,
+            smallest_key: None,
+            largest_key: None,
+            checksum: None,
+            range_tombstones: RangeTombstones::default(),
+            index_search: IndexSearch::default(),
+            index_key_len: None,
+            sequence_range: None,
+        };
+        let options = DbOptions {
+            skip_corrupt_blocks: false,
+            mmap: None,
+            ..options.clone()
+        };
+        SSTable::open(meta, &options, io, files).await
+    }
+
+    // CRC32C of the whole data file, as recorded in the manifest.
+    pub(crate) async fn file_checksum(&self) -> Result<u32, NdbError> {
+        let file = self.data_file().await?;
+        let (mut checksum, mut offset, mut buf) = (0, 0, Vec::new());
+        while offset < self.size {
+            let len = (self.size - offset).min(CHECKSUM_CHUNK);
+            buf = file.read_at(offset, len as usize, buf).await?;
+            checksum = crc32c::crc32c_append(checksum, &buf);
+            offset += len;
+        }
+        Ok(checksum)
+    }
+
+    // Reads the `i`th data block into `buf`.
+    async fn data_block(&self, i: usize, buf: Vec<u8>) -> Result<Block, NdbError> {
+        let handle = self.index[i].1;
+        if let Some(reason) = self.corrupt.lock().unwrap().get(&handle.offset) {
+            return Err(NdbError::corruption(reason.clone()));
+        }
+        let block = match &self.mapped {
+            Some(map) => {
+                let len = handle.size as usize + BLOCK_TRAILER_SIZE;
+                block::verify_trailer(mmap::read_at(map, handle.offset, len, buf)?)?
+            }
+            None => read_block(&self.files.file_io, &self.data_file().await?, handle, buf).await?,
+        };
+        let read = handle.size + BLOCK_TRAILER_SIZE as u64;
+        self.io.table_bytes_read.fetch_add(read, Ordering::Relaxed);
+        Block::new(block)
+    }
+
+    // Every entry in the `i`th data block, decoded.
+    pub(crate) async fn block_entries(&self, i: usize) -> Result<Vec<(Vec<u8>, Value)>, NdbError> {
+        let block = self.data_block(i, Vec::new()).await?;
+        block
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                Ok((key, Value::decode(value)?))
+            })
+            .collect()
+    }
+
+    // Says where corruption found while reading the `i`th data block is,
+    // and with `skip_corrupt_blocks`, remembers the block so it isn't read
+    // again. It's never read as empty: what it hid in older tables, or the
+    // keys in it, would come back wrong or be compacted away.
+    pub(crate) fn corrupt_at<T>(
+        &self,
+        i: usize,
+        result: Result<T, NdbError>,
+    ) -> Result<T, NdbError> {
+        let offset = self.index[i].1.offset;
+        match &result {
+            Err(NdbError::Corruption { detail, .. }) if self.skip_corrupt => {
+                self.corrupt.lock().unwrap().insert(offset, detail.clone());
+            }
+            _ => {}
+        }
+        result.map_err(|err| err.in_file(&self.meta.data_path).at_offset(offset))
+    }
+
+    pub(crate) fn block_count(&self) -> usize {
+        self.index.len()
+    }
+
+    pub(crate) fn block_offset(&self, i: usize) -> u64 {
+        self.index[i].1.offset
+    }
+
+    // The blocks that might hold `key`: the first whose index key is >=
+    // it, and if index keys are truncated, any that follow while the one
+    // before shares the key's truncated form.
+    fn find_blocks(&self, key: &[u8]) -> Range<usize> {
+        let key = truncate(key, self.meta.index_key_len);
+        let start = self.meta.index_search.find(&self.index, key);
+        let mut end = start;
+        if self.meta.index_key_len.is_some() {
+            end += self.index[start..]
+                .iter()
+                .take_while(|(index_key, _)| index_key.as_slice() == key)
+                .count();
+        }
+        start..(end + 1).min(self.index.len())
+    }
+
+    async fn data_file(&self) -> Result<Arc<dyn ReadableFile>, NdbError> {
+        let pinned = self.pinned.lock().unwrap().clone();
+        match pinned {
+            Some(file) => Ok(file),
+            None => self.files.get(&self.meta.data_path).await,
+        }
+    }
+
+    pub(crate) fn is_mapped(&self) -> bool {
+        self.mapped.is_some()
+    }
+
+    pub(crate) async fn remove_file(&self) -> Result<(), NdbError> {
+        // The mapping is still there to read, file or no file.
+        if self.mapped.is_none() {
+            let file = self.files.get(&self.meta.data_path).await?;
+            *self.pinned.lock().unwrap() = Some(file);
+        }
+        self.files.remove(&self.meta.data_path);
+        self.files.storage.remove(&self.meta.data_path).await
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{HandleStats, StorageTier, WriteStallReason, WriteStallState};
+
+/// A snapshot of how the database is doing, from [`Db::stats`](crate::Db::stats).
+/// Counters are since the database was opened, and cover an overlay's base
+/// too.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DbStats {
+    pub open: OpenStats,
+    pub writes: WriteStats,
+    pub conflicts: ConflictStats,
+    pub handles: HandleStats,
+    pub memtable: MemtableStats,
+    /// The tables in each level that has any, top level first. Every table
+    /// is in level 0, as in [`LiveFile::level`](crate::LiveFile::level).
+    pub levels: Vec<LevelStats>,
+    /// The tables in each storage tier, hot first. Empty unless
+    /// [`DbOptions::cold_tier`](crate::DbOptions::cold_tier) is set.
+    #[serde(default)]
+    pub tiers: Vec<TierStats>,
+    pub io: IoStats,
+    /// Bytes written to the WAL and to tables, for each byte of keys and
+    /// values written. 0 until something's been written.
+    pub write_amplification: f64,
+    /// The small tables [`Db::vacuum`](crate::Db::vacuum) would merge if it
+    /// ran now.
+    pub vacuum_backlog: LevelStats,
+    #[serde(default)]
+    pub write_stall: WriteStallStats,
+}
+
+/// Whether writes are being held back because tables are piling up, and
+/// how often they have been. See
+/// [`DbOptions::write_stall`](crate::DbOptions::write_stall).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WriteStallStats {
+    pub state: WriteStallState,
+    /// Empty while the state is `Normal`.
+    pub reasons: Vec<WriteStallReason>,
+    pub delayed_writes: u64,
+    /// How long delayed writes were held up, added up.
+    pub delay: Duration,
+    /// Writes rejected with
+    /// [`NdbError::WritesStopped`](crate::NdbError::WritesStopped).
+    pub stopped_writes: u64,
+    /// Times a write waited for the memtable before to be flushed.
+    pub flush_waits: u64,
+    /// Times a write past a stop threshold waited for a vacuum to merge
+    /// tables, rather than being rejected.
+    #[serde(default)]
+    pub vacuum_waits: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemtableStats {
+    /// Keys held, including deleted ones.
+    pub keys: u64,
+    /// Roughly how much memory the writes applied to it take up, counting
+    /// every version of each key, since they're kept for reads as of an
+    /// earlier sequence number.
+    pub bytes: u64,
+    /// The memory the memtables have actually allocated, including what's
+    /// set aside for later writes and the index over their keys. This is
+    /// what [`DbOptions::write_buffer_size`](crate::DbOptions) is held to.
+    #[serde(default)]
+    pub memory_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LevelStats {
+    pub level: u32,
+    pub tables: u64,
+    /// The size of the tables' data files.
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TierStats {
+    pub tier: StorageTier,
+    pub tables: u64,
+    /// The size of the tables' data files.
+    pub bytes: u64,
+}
+
+/// Reads and writes of the database's files.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IoStats {
+    /// Data blocks read from tables.
+    pub table_bytes_read: u64,
+    /// Tables written by flushes, compactions and vacuums.
+    pub table_bytes_written: u64,
+    /// Syncs of tables being written, including the ones along the way set
+    /// by [`DbOptions::table_sync_bytes`](crate::DbOptions::table_sync_bytes).
+    pub table_syncs: u64,
+    pub wal_bytes_written: u64,
+    /// Lookups in a table that checked its bloom filter.
+    pub bloom_checks: u64,
+    /// Checks that ruled the table out without reading it. The filter's hit
+    /// rate is `bloom_negatives / bloom_checks`.
+    pub bloom_negatives: u64,
+    /// Checks that said the key might be there when it wasn't.
+    pub bloom_false_positives: u64,
+    /// Times a table's data file was opened, including again after being
+    /// closed to stay under
+    /// [`DbOptions::max_open_files`](crate::DbOptions::max_open_files).
+    #[serde(default)]
+    pub table_file_opens: u64,
+    /// Table data files open right now.
+    #[serde(default)]
+    pub table_files_open: u64,
+    /// Tables mapped into memory by
+    /// [`DbOptions::mmap`](crate::DbOptions::mmap), whose files don't need
+    /// to be open.
+    #[serde(default)]
+    pub tables_mapped: u64,
+}
+
+// Shared with every table, so reads through views count too.
+#[derive(Default)]
+pub(crate) struct IoCounters {
+    pub(crate) table_bytes_read: AtomicU64,
+    pub(crate) table_bytes_written: AtomicU64,
+    pub(crate) table_syncs: AtomicU64,
+    pub(crate) wal_bytes_written: AtomicU64,
+    pub(crate) bloom_checks: AtomicU64,
+    pub(crate) bloom_negatives: AtomicU64,
+    pub(crate) bloom_false_positives: AtomicU64,
+    pub(crate) table_file_opens: AtomicU64,
+}
+
+impl IoCounters {
+    pub(crate) fn snapshot(&self) -> IoStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        IoStats {
+            table_bytes_read: load(&self.table_bytes_read),
+            table_bytes_written: load(&self.table_bytes_written),
+            table_syncs: load(&self.table_syncs),
+            wal_bytes_written: load(&self.wal_bytes_written),
+            bloom_checks: load(&self.bloom_checks),
+            bloom_negatives: load(&self.bloom_negatives),
+            bloom_false_positives: load(&self.bloom_false_positives),
+            table_file_opens: load(&self.table_file_opens),
+            // Filled in by the database, from its table cache and tables.
+            table_files_open: 0,
+            tables_mapped: 0,
+        }
+    }
+}
+
+/// How long each part of opening the database took.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpenStats {
+    pub total: Duration,
+    pub wal_replay: Duration,
+    pub sstables: Vec<TableOpenTiming>,
+    /// Corrupt log records dropped while replaying, as allowed by
+    /// [`DbOptions::wal_recovery`](crate::DbOptions::wal_recovery).
+    #[serde(default)]
+    pub wal_records_dropped: u64,
+    /// Tables written in an older format version, which
+    /// [`Db::upgrade`](crate::Db::upgrade) would rewrite.
+    #[serde(default)]
+    pub outdated_tables: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableOpenTiming {
+    pub path: PathBuf,
+    pub duration: Duration,
+}
+
+/// Counts of writes since the database was opened, overall and for each of
+/// [`DbOptions::metric_prefixes`](crate::DbOptions::metric_prefixes). Rates
+/// can be found by diffing two snapshots.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WriteStats {
+    pub total: WriteCounters,
+    /// In the same order as the prefixes were given. A write is only counted
+    /// under the longest prefix that matches its key.
+    pub by_prefix: Vec<(Vec<u8>, WriteCounters)>,
+    /// How many times the WAL was synced. Concurrent writes are committed
+    /// together, so this can be well below `total.batches`.
+    pub wal_syncs: u64,
+    /// Puts dropped by
+    /// [`DbOptions::skip_identical_puts`](crate::DbOptions::skip_identical_puts).
+    /// They aren't counted anywhere else.
+    pub skipped_puts: u64,
+}
+
+/// The recent writes kept for conflict checks, and how the checks went. See
+/// [`DbOptions::conflict_window`](crate::DbOptions::conflict_window).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConflictStats {
+    /// Commits currently remembered.
+    pub tracked_commits: u64,
+    /// Approximate memory held by the remembered keys.
+    pub memory_bytes: u64,
+    pub checks: u64,
+    /// Checks that found a conflict, including `window_conflicts`.
+    pub conflicts: u64,
+    /// Checks that reached back past the window and so had to assume a
+    /// conflict. Some of these may be false, so `window_conflicts / checks`
+    /// bounds the false-abort rate.
+    pub window_conflicts: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteCounters {
+    /// Atomic writes that touched at least one matching key. The average
+    /// batch size is `(puts + deletes) / batches`.
+    pub batches: u64,
+    pub puts: u64,
+    pub deletes: u64,
+    /// Key and value bytes written.
+    pub bytes: u64,
+}
+
+impl WriteStats {
+    pub(crate) fn new(prefixes: &[Vec<u8>]) -> WriteStats {
+        WriteStats {
+            total: WriteCounters::default(),
+            wal_syncs: 0,
+            skipped_puts: 0,
+            by_prefix: prefixes
+                .iter()
+                .map(|prefix| (prefix.clone(), WriteCounters::default()))
+                .collect(),
+        }
+    }
+
+    // Records one atomic write. A `None` value is a delete.
+    pub(crate) fn record_batch<'a>(
+        &mut self,
+        ops: impl IntoIterator<Item = (&'a [u8], Option<&'a [u8]>)>,
+    ) {
+        let mut touched = vec![false; self.by_prefix.len()];
+        let mut any = false;
+        for (key, value) in ops {
+            let prefix = self
+                .by_prefix
+                .iter()
+                .enumerate()
+                .filter(|(_, (prefix, _))| key.starts_with(prefix))
+                .max_by_key(|(_, (prefix, _))| prefix.len())
+                .map(|(i, _)| i);
+            self.total.record(key, value);
+            if let Some(i) = prefix {
+                touched[i] = true;
+                self.by_prefix[i].1.record(key, value);
+            }
+            any = true;
+        }
+        if any {
+            self.total.batches += 1;
+        }
+        for (touched, (_, counters)) in touched.into_iter().zip(&mut self.by_prefix) {
+            if touched {
+                counters.batches += 1;
+            }
+        }
+    }
+}
+
+impl WriteCounters {
+    fn record(&mut self, key: &[u8], value: Option<&[u8]>) {
+        match value {
+            Some(value) => {
+                self.puts += 1;
+                self.bytes += (key.len() + value.len()) as u64;
+            }
+            None => {
+                self.deletes += 1;
+                self.bytes += key.len() as u64;
+            }
+        }
+    }
+}
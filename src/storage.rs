@@ -0,0 +1,387 @@
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+use crate::{pread, NdbError};
+
+// How much of a file `LineReader` reads at a time.
+const READ_CHUNK: usize = 64 << 10;
+
+/// Where a database keeps its files. Every table, log, manifest and
+/// consumer file is created, read, renamed and deleted through this, so
+/// another backend can be plugged in with
+/// [`DbOptions::storage`](crate::DbOptions::storage) without the rest of the
+/// database knowing. [`LocalStorage`], the local file system, is the default.
+///
+/// Paths are the ones the database would use on the local file system: its
+/// directory joined with a file name. A missing file is reported as an
+/// [`NdbError::Io`] of kind [`io::ErrorKind::NotFound`].
+pub trait Storage: Send + Sync {
+    /// Creates the file at `path`, emptying it if it's already there, to be
+    /// written from the start.
+    fn create<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>>;
+
+    /// Opens the file at `path` to append to, creating it if it isn't there.
+    fn append<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>>;
+
+    /// Opens the file at `path` to be read at any offset. Reads see what's
+    /// appended to it later, and the file can still be read after it's been
+    /// removed.
+    fn open<'a>(&'a self, path: &'a Path)
+        -> BoxFuture<'a, Result<Arc<dyn ReadableFile>, NdbError>>;
+
+    /// Replaces `to` with `from`, atomically.
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<(), NdbError>>;
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NdbError>>;
+
+    /// Cuts the file at `path` down to `len` bytes, durably.
+    fn truncate<'a>(&'a self, path: &'a Path, len: u64) -> BoxFuture<'a, Result<(), NdbError>>;
+
+    /// The files directly in `dir`, in no particular order.
+    fn list<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Vec<PathBuf>, NdbError>>;
+
+    fn size<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<u64, NdbError>>;
+
+    fn exists<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool, NdbError>>;
+
+    /// Makes sure files can be created in `dir`.
+    fn create_dir_all<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>>;
+
+    /// Makes the files created, renamed and removed in `dir` so far stay
+    /// that way through a crash. Does nothing by default, for backends where
+    /// they're durable as soon as they're done.
+    fn sync_dir<'a>(&'a self, _dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move { Ok(()) }.boxed()
+    }
+
+    /// The whole file at `path`.
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, NdbError>> {
+        async move {
+            let file = self.open(path).await?;
+            let len = file.size().await?;
+            file.read_at(0, len as usize, Vec::new()).await
+        }
+        .boxed()
+    }
+
+    /// Whether paths are files on the local file system, so they can also be
+    /// read through an io_uring or a memory mapping, written with `O_DIRECT`,
+    /// and locked against other processes. Those options are ignored
+    /// otherwise.
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+/// A file being written by a [`Storage`], from start to end.
+pub trait WritableFile: Send + Sync {
+    /// Writes all of `data` after what's been written so far. It may only be
+    /// buffered until the next `flush` or `sync`.
+    fn write<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>>;
+
+    /// Hands everything written so far on, so reads see it, though it may
+    /// not survive a crash yet.
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), NdbError>>;
+
+    /// Flushes, and makes everything written so far durable.
+    fn sync(&mut self) -> BoxFuture<'_, Result<(), NdbError>>;
+
+    /// Makes the whole file durable once nothing more will be written to it.
+    /// Backends that can only store whole files keep it here; the rest just
+    /// sync.
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        self.sync()
+    }
+}
+
+/// A file opened by a [`Storage`] to be read at any offset, by any number of
+/// reads at once.
+pub trait ReadableFile: Send + Sync {
+    /// Reads `len` bytes at `offset` into `buf`, replacing what was in it,
+    /// and gives it back. Reading past the end is an error.
+    fn read_at(
+        &self,
+        offset: u64,
+        len: usize,
+        buf: Vec<u8>,
+    ) -> BoxFuture<'_, Result<Vec<u8>, NdbError>>;
+
+    fn size(&self) -> BoxFuture<'_, Result<u64, NdbError>>;
+
+    /// The file on the local file system, for
+    /// [`Storage::is_local`] backends.
+    fn as_file(&self) -> Option<&Arc<File>> {
+        None
+    }
+}
+
+/// Files on the local file system, through tokio.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalStorage;
+
+// A local file being written. Files being created are written through a
+// buffer. Appends aren't buffered, so each write is handed to the OS whole,
+// and finishes even if whoever wrote it stops waiting.
+enum LocalWriter {
+    Buffered(BufWriter<tokio::fs::File>),
+    Appending(tokio::fs::File),
+}
+
+// A local file being read, at an offset on tokio's blocking pool.
+pub(crate) struct LocalFile(Arc<File>);
+
+impl LocalFile {
+    pub(crate) fn new(file: File) -> LocalFile {
+        LocalFile(Arc::new(file))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn create<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        async move {
+            let file = tokio::fs::File::create(path).await?;
+            Ok(Box::new(LocalWriter::Buffered(BufWriter::new(file))) as Box<dyn WritableFile>)
+        }
+        .boxed()
+    }
+
+    fn append<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        async move {
+            let file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .await?;
+            Ok(Box::new(LocalWriter::Appending(file)) as Box<dyn WritableFile>)
+        }
+        .boxed()
+    }
+
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Arc<dyn ReadableFile>, NdbError>> {
+        async move {
+            let file = tokio::fs::File::open(path).await?.into_std().await;
+            Ok(Arc::new(LocalFile::new(file)) as Arc<dyn ReadableFile>)
+        }
+        .boxed()
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move { Ok(tokio::fs::rename(from, to).await?) }.boxed()
+    }
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move { Ok(tokio::fs::remove_file(path).await?) }.boxed()
+    }
+
+    fn truncate<'a>(&'a self, path: &'a Path, len: u64) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            let file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+            file.set_len(len).await?;
+            file.sync_all().await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Vec<PathBuf>, NdbError>> {
+        async move {
+            let mut entries = tokio::fs::read_dir(dir).await?;
+            let mut files = Vec::new();
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_file() {
+                    files.push(entry.path());
+                }
+            }
+            Ok(files)
+        }
+        .boxed()
+    }
+
+    fn size<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<u64, NdbError>> {
+        async move { Ok(tokio::fs::metadata(path).await?.len()) }.boxed()
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool, NdbError>> {
+        async move { Ok(tokio::fs::try_exists(path).await?) }.boxed()
+    }
+
+    fn create_dir_all<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move { Ok(tokio::fs::create_dir_all(dir).await?) }.boxed()
+    }
+
+    fn sync_dir<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            // Directories can't be opened, let alone synced, on Windows, which
+            // makes renames durable by itself.
+            if cfg!(unix) {
+                tokio::fs::File::open(dir).await?.sync_all().await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, NdbError>> {
+        async move { Ok(tokio::fs::read(path).await?) }.boxed()
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+impl WritableFile for LocalWriter {
+    fn write<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            match self {
+                LocalWriter::Buffered(file) => file.write_all(data).await?,
+                LocalWriter::Appending(file) => file.write_all(data).await?,
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        async move {
+            match self {
+                LocalWriter::Buffered(file) => file.flush().await?,
+                LocalWriter::Appending(file) => file.flush().await?,
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn sync(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        async move {
+            self.flush().await?;
+            let file = match self {
+                LocalWriter::Buffered(file) => file.get_ref(),
+                LocalWriter::Appending(file) => file,
+            };
+            file.sync_all().await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+impl ReadableFile for LocalFile {
+    fn read_at(
+        &self,
+        offset: u64,
+        len: usize,
+        buf: Vec<u8>,
+    ) -> BoxFuture<'_, Result<Vec<u8>, NdbError>> {
+        pread::read_at(&self.0, offset, len, buf).boxed()
+    }
+
+    fn size(&self) -> BoxFuture<'_, Result<u64, NdbError>> {
+        pread::len(&self.0).boxed()
+    }
+
+    fn as_file(&self) -> Option<&Arc<File>> {
+        Some(&self.0)
+    }
+}
+
+pub(crate) fn is_not_found(err: &NdbError) -> bool {
+    matches!(err, NdbError::Io(err) if err.kind() == io::ErrorKind::NotFound)
+}
+
+// Writes a whole file at once, and syncs it.
+pub(crate) async fn write_synced(
+    storage: &dyn Storage,
+    path: &Path,
+    contents: &[u8],
+) -> Result<(), NdbError> {
+    let mut file = storage.create(path).await?;
+    file.write(contents).await?;
+    file.finish().await
+}
+
+// Removes the file at `path`, if there is one.
+pub(crate) async fn remove_if_exists(storage: &dyn Storage, path: &Path) -> Result<(), NdbError> {
+    match storage.remove(path).await {
+        Err(err) if !is_not_found(&err) => Err(err),
+        _ => Ok(()),
+    }
+}
+
+// Reads a file a line at a time, picking up what's appended to it as it's
+// written.
+pub(crate) struct LineReader {
+    file: Arc<dyn ReadableFile>,
+    // Where in the file `buf` ends.
+    offset: u64,
+    buf: Vec<u8>,
+    // How much of `buf` has been read.
+    pos: usize,
+}
+
+impl LineReader {
+    pub(crate) fn new(file: Arc<dyn ReadableFile>) -> LineReader {
+        LineReader::at(file, 0)
+    }
+
+    // Reads from `offset` on.
+    pub(crate) fn at(file: Arc<dyn ReadableFile>, offset: u64) -> LineReader {
+        LineReader {
+            file,
+            offset,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    // Appends the rest of the current line to `line`, including its newline
+    // if it's been written yet, and returns how many bytes that was. 0 means
+    // the end of the file.
+    pub(crate) async fn read_line(&mut self, line: &mut Vec<u8>) -> Result<usize, NdbError> {
+        let mut read = 0;
+        loop {
+            let rest = &self.buf[self.pos..];
+            if let Some(end) = rest.iter().position(|&b| b == b'\n') {
+                line.extend_from_slice(&rest[..=end]);
+                self.pos += end + 1;
+                return Ok(read + end + 1);
+            }
+            line.extend_from_slice(rest);
+            read += rest.len();
+            self.pos = self.buf.len();
+
+            let len = self.file.size().await?;
+            if self.offset >= len {
+                return Ok(read);
+            }
+            let chunk = (len - self.offset).min(READ_CHUNK as u64) as usize;
+            let buf = std::mem::take(&mut self.buf);
+            self.buf = self.file.read_at(self.offset, chunk, buf).await?;
+            self.offset += chunk as u64;
+            self.pos = 0;
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{key_codec, Db, DbIterator, NdbError};
+
+// A range of encoded keys.
+pub(crate) type KeyRange = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+/// Typed access to the keys in a [`Db`] under a prefix, started with
+/// [`Db::table`]. Keys are encoded so that they sort in the same order as
+/// `K`'s derived `Ord`, which is the order scans return them in, and values
+/// are stored as JSON.
+///
+/// Key encoding isn't self-describing: a key has to be read back as the type
+/// it was written as, so changing `K`'s fields or variants, other than adding
+/// variants to the end of an enum, makes existing keys unreadable. Give each
+/// table its own prefix, and no prefix that's the start of another's.
+pub struct Table<'a, K, V> {
+    db: &'a Db,
+    prefix: Vec<u8>,
+    types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<'a, K, V> Table<'a, K, V>
+where
+    K: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    pub(crate) fn new(db: &'a Db, prefix: &[u8]) -> Table<'a, K, V> {
+        Table {
+            db,
+            prefix: prefix.to_vec(),
+            types: PhantomData,
+        }
+    }
+
+    pub async fn get(&self, key: &K) -> Result<Option<V>, NdbError> {
+        let value = self.db.get(&self.key(key)?).await?;
+        value
+            .map(|value| Ok(serde_json::from_slice(&value)?))
+            .transpose()
+    }
+
+    pub async fn put(&self, key: &K, value: &V) -> Result<(), NdbError> {
+        let value = serde_json::to_vec(value)?;
+        self.db.put(&self.key(key)?, &value).await
+    }
+
+    pub async fn delete(&self, key: &K) -> Result<(), NdbError> {
+        self.db.delete(&self.key(key)?).await
+    }
+
+    /// Returns an iterator over the keys in `range`, in ascending order.
+    pub async fn scan(&self, range: impl RangeBounds<K>) -> Result<TableIterator<K, V>, NdbError> {
+        let iter = self.db.scan(self.range(range)?).await?;
+        Ok(TableIterator::new(iter, self.prefix.len()))
+    }
+
+    /// Returns an iterator over the keys in `range`, in descending order.
+    pub async fn scan_rev(
+        &self,
+        range: impl RangeBounds<K>,
+    ) -> Result<TableIterator<K, V>, NdbError> {
+        let iter = self.db.scan_rev(self.range(range)?).await?;
+        Ok(TableIterator::new(iter, self.prefix.len()))
+    }
+
+    // `key` encoded, under the table's prefix.
+    fn key(&self, key: &K) -> Result<Vec<u8>, NdbError> {
+        let mut encoded = self.prefix.clone();
+        key_codec::encode(key, &mut encoded)?;
+        Ok(encoded)
+    }
+
+    // The encoded keys `range` covers. Unbounded ends stop at the edges of
+    // the prefix.
+    fn range(&self, range: impl RangeBounds<K>) -> Result<KeyRange, NdbError> {
+        let start = match range.start_bound() {
+            Bound::Included(key) => Bound::Included(self.key(key)?),
+            Bound::Excluded(key) => Bound::Excluded(self.key(key)?),
+            Bound::Unbounded => Bound::Included(self.prefix.clone()),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => Bound::Included(self.key(key)?),
+            Bound::Excluded(key) => Bound::Excluded(self.key(key)?),
+            Bound::Unbounded => prefix_end(&self.prefix),
+        };
+        Ok((start, end))
+    }
+}
+
+// Where the keys starting with `prefix` end.
+pub(crate) fn prefix_end(prefix: &[u8]) -> Bound<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Bound::Excluded(end);
+        }
+    }
+    Bound::Unbounded
+}
+
+/// Iterates over a [`Table`]'s keys and values, decoded.
+pub struct TableIterator<K, V> {
+    iter: DbIterator,
+    prefix_len: usize,
+    types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K: DeserializeOwned, V: DeserializeOwned> TableIterator<K, V> {
+    fn new(iter: DbIterator, prefix_len: usize) -> TableIterator<K, V> {
+        TableIterator {
+            iter,
+            prefix_len,
+            types: PhantomData,
+        }
+    }
+
+    pub async fn next(&mut self) -> Result<Option<(K, V)>, NdbError> {
+        let Some((key, value)) = self.iter.next().await? else {
+            return Ok(None);
+        };
+        let key = key_codec::decode(&key[self.prefix_len..])?;
+        Ok(Some((key, serde_json::from_slice(&value)?)))
+    }
+}
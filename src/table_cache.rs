@@ -0,0 +1,108 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use crate::{
+    pread::{Buffers, FileIo},
+    stats::IoCounters,
+    NdbError, ReadableFile, Storage,
+};
+
+// The data files of a database's tables, with at most
+// `DbOptions::max_open_files` of them open at once. Tables keep their index
+// and filter in memory, so only reading a data block needs the file; one
+// that's been closed is opened again on its next read, and the one read least
+// recently is closed to make room. A file is only really closed once the
+// reads going on in it are done.
+pub(crate) struct TableCache {
+    capacity: usize,
+    io: Arc<IoCounters>,
+    open: Mutex<Open>,
+    pub(crate) buffers: Buffers,
+    // Where the files are opened, and how they're read.
+    pub(crate) storage: Arc<dyn Storage>,
+    pub(crate) file_io: FileIo,
+}
+
+#[derive(Default)]
+struct Open {
+    // Each file, and when it was last used.
+    files: HashMap<PathBuf, (Arc<dyn ReadableFile>, u64)>,
+    by_use: BTreeMap<u64, PathBuf>,
+    uses: u64,
+}
+
+impl Open {
+    fn touch(&mut self, path: &Path) -> Option<Arc<dyn ReadableFile>> {
+        let (file, used) = self.files.get_mut(path)?;
+        self.by_use.remove(used);
+        self.uses += 1;
+        *used = self.uses;
+        self.by_use.insert(self.uses, path.into());
+        Some(file.clone())
+    }
+
+    fn insert(&mut self, path: &Path, file: Arc<dyn ReadableFile>, capacity: usize) {
+        self.uses += 1;
+        self.files.insert(path.into(), (file, self.uses));
+        self.by_use.insert(self.uses, path.into());
+        while self.files.len() > capacity.max(1) {
+            let (_, oldest) = self.by_use.pop_first().unwrap();
+            self.files.remove(&oldest);
+        }
+    }
+}
+
+impl TableCache {
+    pub(crate) fn new(
+        capacity: usize,
+        io: Arc<IoCounters>,
+        storage: Arc<dyn Storage>,
+        file_io: FileIo,
+    ) -> TableCache {
+        TableCache {
+            capacity,
+            io,
+            open: Default::default(),
+            buffers: Buffers::default(),
+            storage,
+            file_io,
+        }
+    }
+
+    // The file at `path`, opened if it isn't already.
+    pub(crate) async fn get(&self, path: &Path) -> Result<Arc<dyn ReadableFile>, NdbError> {
+        if let Some(file) = self.open.lock().unwrap().touch(path) {
+            return Ok(file);
+        }
+        let file = self.storage.open(path).await?;
+        self.io.table_file_opens.fetch_add(1, Ordering::Relaxed);
+        let mut open = self.open.lock().unwrap();
+        // Another read might have opened it in the meantime.
+        if let Some(file) = open.touch(path) {
+            return Ok(file);
+        }
+        open.insert(path, file.clone(), self.capacity);
+        Ok(file)
+    }
+
+    // Adds a file that's already open, e.g. a table that's just been written.
+    pub(crate) fn insert(&self, path: &Path, file: Arc<dyn ReadableFile>) {
+        let mut open = self.open.lock().unwrap();
+        open.insert(path, file, self.capacity);
+    }
+
+    pub(crate) fn remove(&self, path: &Path) {
+        let mut open = self.open.lock().unwrap();
+        if let Some((_, used)) = open.files.remove(path) {
+            open.by_use.remove(&used);
+        }
+    }
+
+    // How many files are open, not counting ones being closed.
+    pub(crate) fn len(&self) -> usize {
+        self.open.lock().unwrap().files.len()
+    }
+}
@@ -0,0 +1,153 @@
+use std::{ops::Bound, ops::Deref, sync::Arc};
+
+use crate::sstable::SSTable;
+
+// A database's tables, newest first, along with an index of their key ranges
+// so a scan can find the few it overlaps without checking every one of
+// them.
+//
+// The index is an interval tree laid out over the tables sorted by smallest
+// key: each table in that order is the root of the span around it, and
+// knows the largest key of any table in its span. A span whose largest key
+// is before the scan starts can be skipped whole, and so can everything
+// after a table whose smallest key is past the scan's end.
+#[derive(Clone, Default)]
+pub(crate) struct Tables {
+    tables: Vec<Arc<SSTable>>,
+    // Positions in `tables` of the ones with key ranges, by smallest key.
+    by_smallest: Vec<usize>,
+    // For each span root in `by_smallest`, the position of the table with
+    // the largest key in its span.
+    largest_in_span: Vec<usize>,
+    // Positions of the tables every scan has to look at: those written before
+    // key ranges were recorded, and those with range tombstones, which hide
+    // keys in older tables outside their own range.
+    always: Vec<usize>,
+}
+
+impl Tables {
+    pub(crate) fn new(tables: Vec<Arc<SSTable>>) -> Tables {
+        let mut by_smallest = Vec::new();
+        let mut always = Vec::new();
+        for (i, table) in tables.iter().enumerate() {
+            if table.key_range().is_some() {
+                by_smallest.push(i);
+            }
+            if table.key_range().is_none() && table.block_count() > 0
+                || !table.meta.range_tombstones.is_empty()
+            {
+                always.push(i);
+            }
+        }
+        by_smallest.sort_by(|&a, &b| smallest(&tables[a]).cmp(smallest(&tables[b])));
+        let mut index = Tables {
+            tables,
+            largest_in_span: vec![0; by_smallest.len()],
+            by_smallest,
+            always,
+        };
+        index.build(0, index.by_smallest.len());
+        index
+    }
+
+    // Fills in `largest_in_span` for the span `lo..hi`, and returns the
+    // position of the table with the largest key in it.
+    fn build(&mut self, lo: usize, hi: usize) -> Option<usize> {
+        if lo >= hi {
+            return None;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let candidates = [
+            Some(self.by_smallest[mid]),
+            self.build(lo, mid),
+            self.build(mid + 1, hi),
+        ];
+        let tables = &self.tables;
+        let found = candidates
+            .into_iter()
+            .flatten()
+            .max_by(|&a, &b| largest(&tables[a]).cmp(largest(&tables[b])));
+        self.largest_in_span[mid] = found.unwrap();
+        found
+    }
+
+    // Positions of the tables a scan from `start` to `end` has to look at,
+    // in order: every one whose key range overlaps it, and any others that
+    // have to be looked at anyway. See `always`.
+    pub(crate) fn for_scan(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<usize> {
+        let mut found = self.always.clone();
+        self.search(0, self.by_smallest.len(), start, end, &mut found);
+        found.sort();
+        found.dedup();
+        found
+    }
+
+    fn search(
+        &self,
+        lo: usize,
+        hi: usize,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        found: &mut Vec<usize>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if !after_start(largest(&self.tables[self.largest_in_span[mid]]), start) {
+            return;
+        }
+        self.search(lo, mid, start, end, found);
+        let table = self.by_smallest[mid];
+        if !before_end(smallest(&self.tables[table]), end) {
+            return;
+        }
+        if after_start(largest(&self.tables[table]), start) {
+            found.push(table);
+        }
+        self.search(mid + 1, hi, start, end, found);
+    }
+}
+
+impl Deref for Tables {
+    type Target = [Arc<SSTable>];
+
+    fn deref(&self) -> &[Arc<SSTable>] {
+        &self.tables
+    }
+}
+
+impl<'a> IntoIterator for &'a Tables {
+    type Item = &'a Arc<SSTable>;
+    type IntoIter = std::slice::Iter<'a, Arc<SSTable>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tables.iter()
+    }
+}
+
+// Whether a table ending at `largest` reaches a scan from `start`.
+pub(crate) fn after_start(largest: &[u8], start: Bound<&[u8]>) -> bool {
+    match start {
+        Bound::Included(start) => largest >= start,
+        Bound::Excluded(start) => largest > start,
+        Bound::Unbounded => true,
+    }
+}
+
+// Whether a table starting at `smallest` reaches a scan up to `end`.
+pub(crate) fn before_end(smallest: &[u8], end: Bound<&[u8]>) -> bool {
+    match end {
+        Bound::Included(end) => smallest <= end,
+        Bound::Excluded(end) => smallest < end,
+        Bound::Unbounded => true,
+    }
+}
+
+fn smallest(table: &SSTable) -> &[u8] {
+    table.key_range().unwrap().0
+}
+
+fn largest(table: &SSTable) -> &[u8] {
+    table.key_range().unwrap().1
+}
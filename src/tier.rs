@@ -0,0 +1,151 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sstable::SSTableMetadata, storage::write_synced, DbOptions, NdbError, ReadableFile, Storage,
+    WritableFile,
+};
+
+/// A second, cheaper place for tables that haven't been written in a while,
+/// e.g. a hard disk behind an NVMe drive, or an object store behind the
+/// local disk. See [`DbOptions::cold_tier`](crate::DbOptions::cold_tier).
+///
+/// [`Db::vacuum`](crate::Db::vacuum) moves tables there once they're old
+/// enough, and merges runs of tables that are all old enough straight into
+/// it. Reads find a table in whichever tier it's in. The log, the manifest
+/// and newer tables stay in the database's directory.
+#[derive(Clone)]
+pub struct ColdTier {
+    /// The directory cold tables are kept in. Each database needs one of its
+    /// own, and [`Db::destroy`](crate::Db::destroy) leaves it alone.
+    pub dir: PathBuf,
+    /// Where `dir` is.
+    pub storage: Arc<dyn Storage>,
+    /// How long after a table is written it goes cold.
+    pub after: Duration,
+}
+
+/// Which tier a table is in, in [`TierStats`](crate::TierStats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageTier {
+    /// The database's own directory, in
+    /// [`DbOptions::storage`](crate::DbOptions::storage).
+    Hot,
+    /// [`DbOptions::cold_tier`](crate::DbOptions::cold_tier).
+    Cold,
+}
+
+impl ColdTier {
+    pub(crate) fn tier_of(&self, path: &Path) -> StorageTier {
+        match path.starts_with(&self.dir) {
+            true => StorageTier::Cold,
+            false => StorageTier::Hot,
+        }
+    }
+
+    // Whether the table has been around long enough to go cold.
+    pub(crate) fn is_cold(&self, options: &DbOptions, meta: &SSTableMetadata) -> bool {
+        let age = options
+            .clock
+            .unix_secs()
+            .saturating_sub(meta.written_timestamp);
+        age >= self.after.as_secs()
+    }
+}
+
+// Sends everything in the cold tier's directory to its storage, and the rest
+// to the database's.
+pub(crate) struct TieredStorage {
+    hot: Arc<dyn Storage>,
+    cold: ColdTier,
+}
+
+impl TieredStorage {
+    pub(crate) fn new(hot: Arc<dyn Storage>, cold: ColdTier) -> TieredStorage {
+        TieredStorage { hot, cold }
+    }
+
+    fn pick(&self, path: &Path) -> &dyn Storage {
+        match self.cold.tier_of(path) {
+            StorageTier::Hot => &*self.hot,
+            StorageTier::Cold => &*self.cold.storage,
+        }
+    }
+}
+
+impl Storage for TieredStorage {
+    fn create<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        self.pick(path).create(path)
+    }
+
+    fn append<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        self.pick(path).append(path)
+    }
+
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Arc<dyn ReadableFile>, NdbError>> {
+        self.pick(path).open(path)
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            if self.cold.tier_of(from) == self.cold.tier_of(to) {
+                return self.pick(from).rename(from, to).await;
+            }
+            let contents = self.pick(from).read(from).await?;
+            write_synced(self.pick(to), to, &contents).await?;
+            self.pick(from).remove(from).await
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        self.pick(path).remove(path)
+    }
+
+    fn truncate<'a>(&'a self, path: &'a Path, len: u64) -> BoxFuture<'a, Result<(), NdbError>> {
+        self.pick(path).truncate(path, len)
+    }
+
+    fn list<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Vec<PathBuf>, NdbError>> {
+        self.pick(dir).list(dir)
+    }
+
+    fn size<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<u64, NdbError>> {
+        self.pick(path).size(path)
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool, NdbError>> {
+        self.pick(path).exists(path)
+    }
+
+    fn create_dir_all<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        self.pick(dir).create_dir_all(dir)
+    }
+
+    fn sync_dir<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        self.pick(dir).sync_dir(dir)
+    }
+
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, NdbError>> {
+        self.pick(path).read(path)
+    }
+
+    fn is_local(&self) -> bool {
+        self.hot.is_local() && self.cold.storage.is_local()
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+use crate::{clock, Db, DbView, NdbError, WriteBatch};
+
+// `None` for a delete, and the value and when it expires, if it does, for a
+// put.
+type Write = Option<(Vec<u8>, Option<SystemTime>)>;
+
+/// A set of reads and writes that commits atomically, or not at all if
+/// anything it read was written by someone else in the meantime. Started with
+/// [`Db::transaction`].
+///
+/// Writes are buffered until [`Transaction::commit`], and are seen by the
+/// transaction's own reads. Other reads see the database as it was when the
+/// transaction started, however long it runs, and a transaction only
+/// commits if none of the keys it read have changed since.
+///
+/// Keys that many transactions update at once can be read with
+/// [`Transaction::get_for_update`] instead, which locks them so that the
+/// transactions take turns rather than failing and retrying.
+pub struct Transaction<'a> {
+    db: &'a Db,
+    id: u64,
+    // The database when the transaction started, which reads go through.
+    view: DbView,
+    // Each key read, and the sequence number it must not have been written
+    // since: the start, or when it was locked.
+    reads: BTreeMap<Vec<u8>, u64>,
+    writes: BTreeMap<Vec<u8>, Write>,
+    // Released when the transaction commits or is dropped.
+    locked: Vec<Vec<u8>>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(db: &'a Db) -> Transaction<'a> {
+        Transaction {
+            db,
+            id: db.locks().new_owner(),
+            view: db.freeze_view(),
+            reads: BTreeMap::new(),
+            writes: BTreeMap::new(),
+            locked: Vec::new(),
+        }
+    }
+
+    pub async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, NdbError> {
+        if let Some(write) = self.writes.get(key) {
+            return Ok(write.as_ref().map(|(value, _)| value.clone()));
+        }
+        let start = self.view.sequence();
+        match *self.reads.entry(key.to_vec()).or_insert(start) {
+            // Locked since by `get_for_update`, which read it as it is now.
+            seq if seq != start => self.db.get(key).await,
+            _ => self.view.get(key).await,
+        }
+    }
+
+    /// Like [`Transaction::get`], but first locks `key` until the
+    /// transaction finishes, waiting if another transaction holds it. Returns
+    /// [`NdbError::LockTimeout`] if that takes longer than
+    /// [`DbOptions::lock_timeout`](crate::DbOptions::lock_timeout). The lock
+    /// only holds off other transactions' `get_for_update`, not plain writes,
+    /// which are still caught at commit. The value read is the one when the
+    /// lock was taken, rather than when the transaction started.
+    pub async fn get_for_update(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, NdbError> {
+        if let Some(write) = self.writes.get(key) {
+            return Ok(write.as_ref().map(|(value, _)| value.clone()));
+        }
+        if self.db.locks().lock(key, self.id).await? {
+            self.locked.push(key.to_vec());
+        }
+        // Nothing else locking the key can have written it while we waited,
+        // so it only needs to be unchanged from now on.
+        let locked_at = self.db.latest_sequence();
+        self.reads.entry(key.to_vec()).or_insert(locked_at);
+        self.db.get(key).await
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.writes
+            .insert(key.to_vec(), Some((value.to_vec(), None)));
+    }
+
+    /// Puts a value that reads as deleted once `ttl` has passed, counted from
+    /// now rather than from when the transaction commits. A `ttl` too long to
+    /// store never expires.
+    pub fn put_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) {
+        let expires_at = clock::expiry(&*self.db.options().clock, ttl);
+        (self.writes).insert(key.to_vec(), Some((value.to_vec(), expires_at)));
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+
+    /// Applies the transaction's writes atomically, or returns
+    /// [`NdbError::Conflict`] without applying any of them if a key it read
+    /// has been written since it started. Only the last
+    /// [`DbOptions::conflict_window`](crate::DbOptions::conflict_window)
+    /// writes are remembered, so a transaction that runs for longer than
+    /// that always conflicts.
+    pub async fn commit(self) -> Result<(), NdbError> {
+        let mut batch = WriteBatch::new();
+        for (key, write) in &self.writes {
+            match write {
+                Some((value, None)) => batch.put(key, value),
+                Some((value, Some(expires_at))) => batch.put_until(key, value, *expires_at),
+                None => batch.delete(key),
+            }
+        }
+        self.db.commit_unless_written(&self.reads, batch).await
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.locked.is_empty() {
+            self.db.locks().unlock(&self.locked);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use crate::{
+    coding::{get_varint32, put_varint32},
+    NdbError,
+};
+
+/// Changes values on their way into the database and back on their way out,
+/// e.g. to encrypt each tenant's values with its own key. Registered for a
+/// key prefix with [`DbOptions::value_transforms`](crate::DbOptions::value_transforms).
+///
+/// Values are stored encoded, prefixed with the transform's id, and
+/// compaction never decodes them. The change feed and backups see them as
+/// stored too.
+pub trait ValueTransform: Send + Sync {
+    /// Stored with every value this encodes, to find the transform that
+    /// decodes it even after its prefix has moved on to another one. It has
+    /// to stay the same for as long as any such value is around.
+    fn id(&self) -> u32;
+
+    fn encode(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>, NdbError>;
+
+    fn decode(&self, key: &[u8], encoded: &[u8]) -> Result<Vec<u8>, NdbError>;
+}
+
+pub(crate) type Transforms = [(Vec<u8>, Arc<dyn ValueTransform>)];
+
+// The transform for the longest prefix of `key`, if any.
+pub(crate) fn for_key<'a>(
+    transforms: &'a Transforms,
+    key: &[u8],
+) -> Option<&'a dyn ValueTransform> {
+    transforms
+        .iter()
+        .filter(|(prefix, _)| key.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, transform)| &**transform)
+}
+
+pub(crate) fn encode(
+    transforms: &Transforms,
+    key: &[u8],
+    value: &[u8],
+) -> Result<Vec<u8>, NdbError> {
+    let Some(transform) = for_key(transforms, key) else {
+        return Ok(value.to_vec());
+    };
+    let mut encoded = Vec::new();
+    put_varint32(&mut encoded, transform.id());
+    encoded.extend(transform.encode(key, value)?);
+    Ok(encoded)
+}
+
+pub(crate) fn decode(
+    transforms: &Transforms,
+    key: &[u8],
+    stored: Vec<u8>,
+) -> Result<Vec<u8>, NdbError> {
+    if for_key(transforms, key).is_none() {
+        return Ok(stored);
+    }
+    let mut rest = stored.as_slice();
+    let id = get_varint32(&mut rest)
+        .ok_or_else(|| NdbError::corruption("value is missing its transform id"))?;
+    let transform = transforms
+        .iter()
+        .find(|(_, transform)| transform.id() == id)
+        .ok_or_else(|| NdbError::corruption(format!("no value transform with id {}", id)))?;
+    transform.1.decode(key, rest)
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io,
+    os::fd::AsRawFd,
+    sync::{mpsc, Arc},
+};
+
+use io_uring::{opcode, squeue, types, IoUring};
+use tokio::sync::oneshot;
+
+use crate::NdbError;
+
+// How many requests can be handed to the kernel at once. More than this wait
+// their turn on the ring's thread.
+const RING_ENTRIES: u32 = 256;
+
+// An io_uring shared by a database's table reads and log appends. A thread of
+// its own hands requests to the kernel as they come in, and gives each one's
+// buffer back once it's done, so nothing the kernel is using can be freed
+// while it is.
+pub(crate) struct Ring {
+    requests: mpsc::Sender<Request>,
+}
+
+enum Op {
+    Read,
+    Write,
+    Sync,
+}
+
+struct Request {
+    op: Op,
+    // Held so the descriptor stays open until the kernel is done with it.
+    file: Arc<File>,
+    offset: u64,
+    buf: Vec<u8>,
+    // How much of `buf` has been read or written so far.
+    done: usize,
+    reply: oneshot::Sender<io::Result<Vec<u8>>>,
+}
+
+impl Request {
+    fn entry(&mut self, id: u64) -> squeue::Entry {
+        let fd = types::Fd(self.file.as_raw_fd());
+        let rest = &mut self.buf[self.done..];
+        let offset = self.offset + self.done as u64;
+        let entry = match self.op {
+            Op::Read => opcode::Read::new(fd, rest.as_mut_ptr(), rest.len() as u32)
+                .offset(offset)
+                .build(),
+            Op::Write => opcode::Write::new(fd, rest.as_ptr(), rest.len() as u32)
+                .offset(offset)
+                .build(),
+            Op::Sync => opcode::Fsync::new(fd).build(),
+        };
+        entry.user_data(id)
+    }
+
+    // Takes in how one attempt at the request went. Reads and writes can come
+    // up short, and are retried from where they left off.
+    fn complete(mut self, result: i32, retry: &mut VecDeque<Request>) {
+        let outcome = match (result, &self.op) {
+            (err, _) if err < 0 => Err(io::Error::from_raw_os_error(-err)),
+            (_, Op::Sync) => Ok(()),
+            (0, Op::Read) => Err(io::ErrorKind::UnexpectedEof.into()),
+            (0, Op::Write) => Err(io::ErrorKind::WriteZero.into()),
+            (n, _) => {
+                self.done += n as usize;
+                if self.done < self.buf.len() {
+                    retry.push_back(self);
+                    return;
+                }
+                Ok(())
+            }
+        };
+        // Whoever asked may have stopped waiting.
+        let _ = self.reply.send(outcome.map(|()| self.buf));
+    }
+}
+
+impl Ring {
+    pub(crate) fn new() -> Result<Arc<Ring>, NdbError> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let (requests, received) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("nulldb-uring".into())
+            .spawn(move || run(ring, received))?;
+        Ok(Arc::new(Ring { requests }))
+    }
+
+    async fn submit(
+        &self,
+        op: Op,
+        file: &Arc<File>,
+        offset: u64,
+        buf: Vec<u8>,
+    ) -> Result<Vec<u8>, NdbError> {
+        let (reply, done) = oneshot::channel();
+        let request = Request {
+            op,
+            file: file.clone(),
+            offset,
+            buf,
+            done: 0,
+            reply,
+        };
+        let stopped = || NdbError::Io(io::Error::other("io_uring thread stopped"));
+        self.requests.send(request).map_err(|_| stopped())?;
+        Ok(done.await.map_err(|_| stopped())??)
+    }
+
+    // Reads `len` bytes at `offset` into `buf`, replacing what was in it.
+    pub(crate) async fn read_at(
+        &self,
+        file: &Arc<File>,
+        offset: u64,
+        len: usize,
+        mut buf: Vec<u8>,
+    ) -> Result<Vec<u8>, NdbError> {
+        buf.clear();
+        buf.resize(len, 0);
+        self.submit(Op::Read, file, offset, buf).await
+    }
+
+    // Writes all of `buf` at `offset`, or at the end of a file opened to
+    // append to. Gives `buf` back to be reused.
+    pub(crate) async fn write_at(
+        &self,
+        file: &Arc<File>,
+        offset: u64,
+        buf: Vec<u8>,
+    ) -> Result<Vec<u8>, NdbError> {
+        self.submit(Op::Write, file, offset, buf).await
+    }
+
+    pub(crate) async fn sync(&self, file: &Arc<File>) -> Result<(), NdbError> {
+        self.submit(Op::Sync, file, 0, Vec::new()).await?;
+        Ok(())
+    }
+}
+
+// Runs until the ring is dropped and everything in flight has finished.
+fn run(mut ring: IoUring, requests: mpsc::Receiver<Request>) {
+    let mut waiting = VecDeque::new();
+    let mut in_flight: HashMap<u64, Request> = HashMap::new();
+    let mut next_id = 0;
+    loop {
+        if in_flight.is_empty() && waiting.is_empty() {
+            match requests.recv() {
+                Ok(request) => waiting.push_back(request),
+                Err(_) => return,
+            }
+        }
+        waiting.extend(requests.try_iter());
+
+        let mut submission = ring.submission();
+        while let Some(mut request) = waiting.pop_front() {
+            let entry = request.entry(next_id);
+            // Safety: the request's file and buffer are kept in `in_flight`
+            // until the kernel says it's done with them.
+            if unsafe { submission.push(&entry) }.is_err() {
+                waiting.push_front(request);
+                break;
+            }
+            in_flight.insert(next_id, request);
+            next_id += 1;
+        }
+        drop(submission);
+
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => {
+                // The ring itself is broken, so fail everything it holds.
+                // The kernel might still get to what's in flight, so its
+                // buffers and files are leaked rather than freed.
+                let failed = || Err(io::Error::new(err.kind(), err.to_string()));
+                for request in in_flight.into_values() {
+                    let _ = request.reply.send(failed());
+                    std::mem::forget((request.buf, request.file));
+                }
+                for request in waiting {
+                    let _ = request.reply.send(failed());
+                }
+                return;
+            }
+        }
+        for completed in ring.completion() {
+            if let Some(request) = in_flight.remove(&completed.user_data()) {
+                request.complete(completed.result(), &mut waiting);
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    ops::{Bound, Range},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    clock::Timestamps,
+    iter::{Entries, MergingSources, Source},
+    merge,
+    range_del::RangeTombstones,
+    sstable::{SSTable, SSTableWriter, TableIter},
+    stats::IoCounters,
+    table_cache::TableCache,
+    value::Value,
+    watchdog::Progress,
+    DbOptions, NdbError,
+};
+
+/// When [`Db::vacuum`](crate::Db::vacuum) merges small tables together.
+#[derive(Debug, Clone)]
+pub struct VacuumOptions {
+    /// Tables with data files smaller than this count as small.
+    pub small_table_bytes: u64,
+    /// Merge runs of at least this many small tables next to each other.
+    pub min_tables: usize,
+    /// Roughly how big the merged tables are.
+    pub target_table_bytes: u64,
+}
+
+impl Default for VacuumOptions {
+    fn default() -> VacuumOptions {
+        VacuumOptions {
+            small_table_bytes: 1 << 20,
+            min_tables: 4,
+            target_table_bytes: 8 << 20,
+        }
+    }
+}
+
+// The runs of small tables worth merging, given each table's size in order.
+pub(crate) fn small_runs(sizes: &[u64], options: &VacuumOptions) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for (i, &size) in sizes.iter().chain([&u64::MAX]).enumerate() {
+        if size >= options.small_table_bytes {
+            if i - start >= options.min_tables.max(2) {
+                runs.push(start..i);
+            }
+            start = i + 1;
+        }
+    }
+    runs
+}
+
+// Merges `run`, tables that are next to each other, newest first, into
+// tables of about `target_table_bytes`. The merged tables take the run's
+// place in the order, so nothing outside the run has to change. Everything
+// the run still needs to say is kept, including deletes and merge operands
+// with nothing under them yet, since there may be older tables.
+pub(crate) async fn merge_run(
+    dir: &Path,
+    options: &DbOptions,
+    io: &Arc<IoCounters>,
+    files: &Arc<TableCache>,
+    timestamps: &Timestamps,
+    run: &[Arc<SSTable>],
+    progress: &Progress,
+) -> Result<Vec<SSTable>, NdbError> {
+    let target = options
+        .vacuum
+        .clone()
+        .unwrap_or_default()
+        .target_table_bytes;
+    let mut deleted = RangeTombstones::default();
+    let mut sources = Vec::new();
+    for sstable in run {
+        let iter = TableIter::seek(sstable.clone(), Bound::Unbounded).await?;
+        sources.push(Source::new(Entries::Table(iter), Arc::new(deleted.clone())));
+        deleted.extend(&sstable.meta.range_tombstones);
+    }
+    let mut merged = MergingSources::new(sources, false);
+
+    // The run's places in the order, oldest first. The oldest merged table
+    // gets all the range tombstones, so they don't hide anything merged in
+    // with them.
+    let mut places: Vec<_> = run.iter().map(|t| t.meta.written_timestamp).collect();
+    places.sort();
+    let create = async |place: u64| {
+        let mut writer =
+            SSTableWriter::create(dir, options, io, files, timestamps.next(options)?).await?;
+        writer.order_as(place);
+        progress.set_file(writer.data_path().into());
+        Ok::<_, NdbError>(writer)
+    };
+    let mut places = places.into_iter();
+    let mut writer = create(places.next().unwrap()).await?;
+    writer.add_range_tombstones(&deleted);
+    for sstable in run {
+        writer.add_sequences(sstable.meta.sequence_range);
+    }
+
+    let mut tables = Vec::new();
+    while let Some((key, versions)) = merged.next().await? {
+        let value = if versions.iter().all(|v| matches!(v, Value::Merge(_))) {
+            // What they merge into may be in an older table.
+            let operands = versions.into_iter().rev().flat_map(|v| match v {
+                Value::Merge(operands) => operands,
+                _ => unreachable!(),
+            });
+            Value::Merge(operands.collect())
+        } else {
+            let merge = options.merge_operator.as_ref();
+            let now = options.clock.unix_millis();
+            merge::resolve(merge, &key, versions, now)?.expect("a version isn't a merge")
+        };
+        if writer.offset() >= target {
+            if let Some(place) = places.next() {
+                tables.push(writer.finish().await?);
+                writer = create(place).await?;
+                for sstable in run {
+                    writer.add_sequences(sstable.meta.sequence_range);
+                }
+            }
+        }
+        writer.add(&key, &value).await?;
+        progress.advance(writer.offset());
+    }
+    progress.next_step();
+    tables.push(writer.finish().await?);
+    Ok(tables)
+}
@@ -0,0 +1,45 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+/// Why a write was rejected by a [`Validator`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    KeyTooLong { len: usize, max: usize },
+    ValueTooLong { len: usize, max: usize },
+    InvalidKey(String),
+    InvalidValue(String),
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ValidationError::KeyTooLong { len, max } => {
+                write!(f, "key is {} bytes, the maximum is {}", len, max)
+            }
+            ValidationError::ValueTooLong { len, max } => {
+                write!(f, "value is {} bytes, the maximum is {}", len, max)
+            }
+            ValidationError::InvalidKey(msg) => write!(f, "invalid key: {}", msg),
+            ValidationError::InvalidValue(msg) => write!(f, "invalid value: {}", msg),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Checked against every write before it reaches the WAL. Any
+/// `Fn(&[u8], &[u8]) -> Result<(), ValidationError>` closure is a validator.
+pub trait Validator: Send + Sync {
+    fn validate(&self, key: &[u8], value: &[u8]) -> Result<(), ValidationError>;
+}
+
+impl<F> Validator for F
+where
+    F: Fn(&[u8], &[u8]) -> Result<(), ValidationError> + Send + Sync,
+{
+    fn validate(&self, key: &[u8], value: &[u8]) -> Result<(), ValidationError> {
+        self(key, value)
+    }
+}
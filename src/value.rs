@@ -0,0 +1,104 @@
+use crate::{
+    coding::{decode_fixed64, get_varint32, put_fixed64, put_varint32},
+    NdbError,
+};
+
+// What a key maps to inside the database. Deletes are recorded as tombstones
+// so they shadow older versions of the key in SSTables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Value {
+    Put(Vec<u8>),
+    // A put that reads as a delete from `expires_at`, in unix milliseconds.
+    PutUntil { value: Vec<u8>, expires_at: u64 },
+    Delete,
+    // Merge operands, oldest first, still to be folded into whatever older
+    // version of the key there is.
+    Merge(Vec<Vec<u8>>),
+}
+
+pub(crate) const TYPE_PUT: u8 = 0;
+pub(crate) const TYPE_DELETE: u8 = 1;
+pub(crate) const TYPE_PUT_UNTIL: u8 = 2;
+pub(crate) const TYPE_MERGE: u8 = 3;
+
+impl Value {
+    // Whether this is a value that hasn't expired as of `now`, in unix
+    // milliseconds. Merges have to be resolved first.
+    pub(crate) fn is_live(&self, now: u64) -> bool {
+        match self {
+            Value::Put(_) => true,
+            Value::PutUntil { expires_at, .. } => now < *expires_at,
+            Value::Delete | Value::Merge(_) => false,
+        }
+    }
+
+    pub(crate) fn live(self, now: u64) -> Option<Vec<u8>> {
+        match self {
+            Value::Put(value) | Value::PutUntil { value, .. } if self.is_live(now) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Roughly how much memory the value takes up.
+    pub(crate) fn size(&self) -> usize {
+        match self {
+            Value::Put(value) => value.len(),
+            Value::PutUntil { value, .. } => value.len() + 8,
+            Value::Delete => 0,
+            Value::Merge(operands) => operands.iter().map(Vec::len).sum(),
+        }
+    }
+
+    // In an SSTable, a value is a type byte followed by its payload.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        match self {
+            Value::Put(value) => {
+                let mut buf = Vec::with_capacity(value.len() + 1);
+                buf.push(TYPE_PUT);
+                buf.extend_from_slice(value);
+                buf
+            }
+            Value::PutUntil { value, expires_at } => {
+                let mut buf = Vec::with_capacity(value.len() + 9);
+                buf.push(TYPE_PUT_UNTIL);
+                put_fixed64(&mut buf, *expires_at);
+                buf.extend_from_slice(value);
+                buf
+            }
+            Value::Delete => vec![TYPE_DELETE],
+            // Each operand is length-prefixed.
+            Value::Merge(operands) => {
+                let mut buf = vec![TYPE_MERGE];
+                for operand in operands {
+                    put_varint32(&mut buf, operand.len() as u32);
+                    buf.extend_from_slice(operand);
+                }
+                buf
+            }
+        }
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Value, NdbError> {
+        match buf.split_first() {
+            Some((&TYPE_PUT, value)) => Ok(Value::Put(value.to_vec())),
+            Some((&TYPE_DELETE, [])) => Ok(Value::Delete),
+            Some((&TYPE_PUT_UNTIL, rest)) if rest.len() >= 8 => Ok(Value::PutUntil {
+                value: rest[8..].to_vec(),
+                expires_at: decode_fixed64(rest),
+            }),
+            Some((&TYPE_MERGE, mut rest)) => {
+                let mut operands = Vec::new();
+                while !rest.is_empty() {
+                    let len = get_varint32(&mut rest)
+                        .filter(|&len| len as usize <= rest.len())
+                        .ok_or_else(|| NdbError::corruption("bad merge operand"))?;
+                    let (operand, tail) = rest.split_at(len as usize);
+                    operands.push(operand.to_vec());
+                    rest = tail;
+                }
+                Ok(Value::Merge(operands))
+            }
+            _ => Err(NdbError::corruption("bad value type")),
+        }
+    }
+}
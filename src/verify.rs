@@ -0,0 +1,179 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    log::{LogEntry, LogRecord},
+    sstable::SSTable,
+    storage::{self, LineReader},
+    version, CorruptBlock, FileKind, NdbError, Storage,
+};
+
+/// What [`Db::verify_checksums`](crate::Db::verify_checksums) checked, and
+/// everything it found wrong.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub tables: u64,
+    pub blocks: u64,
+    pub log_records: u64,
+    pub problems: Vec<VerifyProblem>,
+}
+
+impl VerifyReport {
+    /// Whether nothing was found wrong.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Something wrong with a database's files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyProblem {
+    /// A data block that fails its checksum or can't be decoded.
+    CorruptBlock(CorruptBlock),
+    /// A table whose data file doesn't match the checksum recorded for it
+    /// when it was written.
+    ChecksumMismatch {
+        path: PathBuf,
+        recorded: u32,
+        actual: u32,
+    },
+    /// A key that isn't after the one before it, or is outside the range
+    /// recorded for its table, in the block starting at `offset`.
+    BadKey {
+        path: PathBuf,
+        offset: u64,
+        reason: String,
+    },
+    /// A log record that can't be read. Log records have no checksums, so
+    /// this is one that doesn't parse, was cut off, or is out of sequence.
+    CorruptLogRecord {
+        path: PathBuf,
+        offset: u64,
+        reason: String,
+    },
+    /// A file the manifest lists that isn't there.
+    MissingFile { path: PathBuf, kind: FileKind },
+    /// A table or log in the database's directory, or the cold tier's, that
+    /// the manifest doesn't list. A table being written by a flush or vacuum
+    /// that's still going shows up as one too.
+    OrphanFile(PathBuf),
+}
+
+// Reads every block of `table`, checking its checksums and that its keys are
+// in order and within the range recorded for it.
+pub(crate) async fn verify_table(
+    table: &SSTable,
+    report: &mut VerifyReport,
+) -> Result<(), NdbError> {
+    let meta = &table.meta;
+    let path = &meta.data_path;
+    report.tables += 1;
+    if let Some(recorded) = meta.checksum {
+        let actual = table.file_checksum().await?;
+        if actual != recorded {
+            report.problems.push(VerifyProblem::ChecksumMismatch {
+                path: path.clone(),
+                recorded,
+                actual,
+            });
+        }
+    }
+
+    let mut last: Option<Vec<u8>> = None;
+    for i in 0..table.block_count() {
+        report.blocks += 1;
+        let offset = table.block_offset(i);
+        let entries = match table.block_entries(i).await {
+            Ok(entries) => entries,
+            Err(NdbError::Corruption { detail, .. }) => {
+                report
+                    .problems
+                    .push(VerifyProblem::CorruptBlock(CorruptBlock {
+                        path: path.clone(),
+                        offset,
+                        reason: detail,
+                    }));
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        let bad_key = |reason: &str| VerifyProblem::BadKey {
+            path: path.clone(),
+            offset,
+            reason: reason.into(),
+        };
+        for (key, _) in entries {
+            if last.as_ref().is_some_and(|last| *last >= key) {
+                report.problems.push(bad_key("keys out of order"));
+                break;
+            }
+            let below = meta.smallest_key.as_ref().is_some_and(|min| key < *min);
+            let above = meta.largest_key.as_ref().is_some_and(|max| key > *max);
+            if below || above {
+                report
+                    .problems
+                    .push(bad_key("key outside the table's range"));
+                break;
+            }
+            last = Some(key);
+        }
+    }
+    Ok(())
+}
+
+// Reads every record of the log at `path`, checking each parses and that
+// sequence numbers go up. `newest` is whether it's the log still being
+// written, which needn't exist yet.
+pub(crate) async fn verify_log(
+    storage: &dyn Storage,
+    path: &Path,
+    newest: bool,
+    report: &mut VerifyReport,
+) -> Result<(), NdbError> {
+    let mut reader = match storage.open(path).await {
+        Ok(file) => LineReader::new(file),
+        Err(err) if storage::is_not_found(&err) => {
+            if !newest {
+                report.problems.push(VerifyProblem::MissingFile {
+                    path: path.into(),
+                    kind: FileKind::Wal,
+                });
+            }
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    };
+    let (mut line, mut offset, mut last_seq) = (Vec::new(), 0, 0);
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).await? as u64;
+        if read == 0 {
+            return Ok(());
+        }
+        let start = offset;
+        offset += read;
+        let problem = |reason: String| VerifyProblem::CorruptLogRecord {
+            path: path.into(),
+            offset: start,
+            reason,
+        };
+        let Some(line) = line.strip_suffix(b"\n") else {
+            report.problems.push(problem("incomplete record".into()));
+            continue;
+        };
+        if version::parse_header(line, path)?.is_some() {
+            continue;
+        }
+        report.log_records += 1;
+        match serde_json::from_slice::<LogRecord<LogEntry>>(line) {
+            Err(err) => report.problems.push(problem(err.to_string())),
+            // Records from before sequence numbers were logged have none.
+            Ok(record) if record.seq != 0 && record.seq <= last_seq => {
+                report.problems.push(problem(format!(
+                    "sequence number {} after {}",
+                    record.seq, last_seq
+                )));
+            }
+            Ok(record) => last_seq = last_seq.max(record.seq),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::NdbError;
+
+/// The version of the on-disk format this build writes. Tables, logs and
+/// manifests are each stamped with the version they were written in, and
+/// ones from before they were stamped count as version 1. Older versions
+/// are still read; see [`Db::upgrade`](crate::Db::upgrade) to rewrite them.
+pub const FORMAT_VERSION: u32 = 2;
+
+// Files written before they were stamped with a version.
+pub(crate) const UNVERSIONED: u32 = 1;
+
+// The first line of a log or manifest.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Header {
+    pub(crate) format_version: u32,
+}
+
+// The header for a file written now, with its newline.
+pub(crate) fn header_line() -> Vec<u8> {
+    let mut line = serde_json::to_vec(&Header {
+        format_version: FORMAT_VERSION,
+    })
+    .unwrap();
+    line.push(b'\n');
+    line
+}
+
+// The version in `line`, without its newline, if it's a header, after
+// checking it's one this build can read.
+pub(crate) fn parse_header(line: &[u8], path: &Path) -> Result<Option<u32>, NdbError> {
+    // Most lines are records, which are quicker to rule out like this.
+    if !line.starts_with(b"{\"format_version\"") {
+        return Ok(None);
+    }
+    let Ok(header) = serde_json::from_slice::<Header>(line) else {
+        return Ok(None);
+    };
+    check(path, header.format_version)?;
+    Ok(Some(header.format_version))
+}
+
+// Fails if `path` was written by a newer version of nulldb than this one.
+pub(crate) fn check(path: &Path, version: u32) -> Result<(), NdbError> {
+    if version > FORMAT_VERSION {
+        return Err(NdbError::UnsupportedVersion {
+            file: path.into(),
+            version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    Ok(())
+}
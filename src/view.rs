@@ -0,0 +1,195 @@
+use std::{ops::RangeBounds, sync::Arc};
+
+use crate::{
+    handles::{Handle, HandleKind, Handles},
+    iter::{Entries, Source},
+    memtable::Memtable,
+    merge::{self, MergeOperator},
+    range_del::RangeTombstones,
+    sstable::TableIter,
+    tables::Tables,
+    transform::{self, ValueTransform},
+    value::Value,
+    Clock, DbIterator, NdbError, Queryable,
+};
+
+// One database's worth of data to read from: its memtables and its tables.
+// Reads go through a list of these, newest first, as of a sequence number.
+#[derive(Clone)]
+pub(crate) struct Layer {
+    // The memtable being written to, then any being flushed, newest first.
+    pub(crate) memtables: Vec<Arc<Memtable>>,
+    pub(crate) sstables: Arc<Tables>,
+}
+
+// Returns the newest version of `key` as of sequence number `seq`, which
+// may be a tombstone, with any merges folded in. The tables must not have
+// anything newer than `seq`.
+pub(crate) async fn get_value(
+    layers: &[Layer],
+    key: &[u8],
+    seq: u64,
+    merge: Option<&MergeOperator>,
+    now: u64,
+) -> Result<Option<Value>, NdbError> {
+    // Merges need the versions under them too.
+    let mut versions = Vec::new();
+    'layers: for layer in layers {
+        for memtable in &layer.memtables {
+            if let Some(value) = memtable.lookup(key, seq) {
+                let merge = matches!(value, Value::Merge(_));
+                versions.push(value);
+                if !merge {
+                    break 'layers;
+                }
+            }
+        }
+        for sstable in layer.sstables.iter() {
+            if !sstable.may_contain_key(key) {
+                continue;
+            }
+            if let Some(value) = sstable.get(key).await? {
+                let merge = matches!(value, Value::Merge(_));
+                versions.push(value);
+                if !merge {
+                    break 'layers;
+                }
+            }
+        }
+    }
+
+    merge::resolve(merge, key, versions, now)
+}
+
+// Like `get_value`, for every key in `range`.
+pub(crate) async fn sources(
+    layers: &[Layer],
+    range: &impl RangeBounds<Vec<u8>>,
+    seq: u64,
+    reverse: bool,
+) -> Result<Vec<Source>, NdbError> {
+    let start = range.start_bound().map(|k| k.as_slice());
+    let end = range.end_bound().map(|k| k.as_slice());
+
+    let mut sources = Vec::new();
+    // Everything deleted by the layers seen so far, which all hide the ones
+    // that come after them.
+    let mut deleted = RangeTombstones::default();
+    for layer in layers {
+        for memtable in &layer.memtables {
+            let entries = memtable.entries(seq, range);
+            let entries: Vec<_> = if reverse {
+                entries.rev().collect()
+            } else {
+                entries.collect()
+            };
+            let entries = Entries::Memtable(entries.into_iter());
+            sources.push(Source::new(entries, Arc::new(deleted.clone())));
+            deleted.extend(&memtable.range_tombstones(seq));
+        }
+
+        for i in layer.sstables.for_scan(start, end) {
+            let sstable = &layer.sstables[i];
+            if sstable.overlaps(start, end) {
+                let iter = if reverse {
+                    TableIter::seek_rev(sstable.clone(), end).await?
+                } else {
+                    TableIter::seek(sstable.clone(), start).await?
+                };
+                sources.push(Source::new(Entries::Table(iter), Arc::new(deleted.clone())));
+            }
+            deleted.extend(&sstable.meta.range_tombstones);
+        }
+    }
+
+    Ok(sources)
+}
+
+/// A read-only copy of a [`Db`](crate::Db) as it was when
+/// [`Db::freeze_view`](crate::Db::freeze_view) was called. Later writes,
+/// flushes and compactions don't show up in it, and the files it reads from
+/// stay readable for as long as it's around. Cheap to clone and to send to
+/// other threads, e.g. for long-running scans.
+#[derive(Clone)]
+pub struct DbView {
+    // Newest first.
+    layers: Arc<[Layer]>,
+    // The last write the view sees. The memtables are shared with the
+    // database, which goes on writing to the newest.
+    seq: u64,
+    clock: Arc<dyn Clock>,
+    merge_operator: Option<MergeOperator>,
+    transforms: Vec<(Vec<u8>, Arc<dyn ValueTransform>)>,
+    handles: Arc<Handles>,
+    // Shared by the clones, so it's tracked until the last one is dropped.
+    _handle: Arc<Handle>,
+}
+
+impl DbView {
+    pub(crate) fn new(
+        layers: Vec<Layer>,
+        seq: u64,
+        clock: Arc<dyn Clock>,
+        merge_operator: Option<MergeOperator>,
+        transforms: Vec<(Vec<u8>, Arc<dyn ValueTransform>)>,
+        handles: Arc<Handles>,
+    ) -> DbView {
+        let tables = layers.iter().flat_map(|layer| layer.sstables.iter());
+        let handle = Arc::new(handles.track(HandleKind::View, tables));
+        DbView {
+            layers: layers.into(),
+            seq,
+            clock,
+            merge_operator,
+            transforms,
+            handles,
+            _handle: handle,
+        }
+    }
+
+    // The last write the view sees.
+    pub(crate) fn sequence(&self) -> u64 {
+        self.seq
+    }
+
+    fn track_iter(&self) -> Handle {
+        let tables = self.layers.iter().flat_map(|layer| layer.sstables.iter());
+        self.handles.track(HandleKind::Iterator, tables)
+    }
+
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, NdbError> {
+        let now = self.clock.unix_millis();
+        let merge = self.merge_operator.as_ref();
+        let value = get_value(&self.layers, key, self.seq, merge, now).await?;
+        let value = value.and_then(|value| value.live(now));
+        value
+            .map(|value| transform::decode(&self.transforms, key, value))
+            .transpose()
+    }
+
+    /// Returns an iterator over the keys in `range`, in ascending order.
+    pub async fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Result<DbIterator, NdbError> {
+        let end = range.end_bound().cloned();
+        Ok(DbIterator::new(
+            sources(&self.layers, &range, self.seq, false).await?,
+            end,
+            self.clock.unix_millis(),
+            self.merge_operator.clone(),
+        )
+        .with_transforms(self.transforms.clone())
+        .tracked(self.track_iter()))
+    }
+
+    /// Returns an iterator over the keys in `range`, in descending order.
+    pub async fn scan_rev(&self, range: impl RangeBounds<Vec<u8>>) -> Result<DbIterator, NdbError> {
+        let start = range.start_bound().cloned();
+        Ok(DbIterator::new_rev(
+            sources(&self.layers, &range, self.seq, true).await?,
+            start,
+            self.clock.unix_millis(),
+            self.merge_operator.clone(),
+        )
+        .with_transforms(self.transforms.clone())
+        .tracked(self.track_iter()))
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+use crate::{storage, Clock, DbOptions, NdbError};
+
+#[derive(Debug, Clone)]
+pub struct WatchdogOptions {
+    /// How long a job can go without making progress before it counts as
+    /// stalled.
+    pub stall_threshold: Duration,
+    /// How often to check on running jobs.
+    pub check_interval: Duration,
+    pub action: StallAction,
+}
+
+impl Default for WatchdogOptions {
+    fn default() -> WatchdogOptions {
+        WatchdogOptions {
+            stall_threshold: Duration::from_secs(60),
+            check_interval: Duration::from_secs(1),
+            action: StallAction::Log,
+        }
+    }
+}
+
+/// What the watchdog does once it has reported a stalled job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallAction {
+    /// Leave the job running.
+    Log,
+    /// Cancel the job and fail with [`NdbError::Stalled`].
+    Abort,
+    /// Cancel the job and start it again, up to `attempts` times, before
+    /// failing with [`NdbError::Stalled`].
+    Retry { attempts: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    Flush,
+    CompactIntoBase,
+    Vacuum,
+    Upgrade,
+}
+
+/// A snapshot of what a stalled job was doing.
+#[derive(Debug, Clone)]
+pub struct StallReport {
+    pub job: JobKind,
+    pub step: &'static str,
+    /// The steps the job still has to do after this one.
+    pub pending: Vec<&'static str>,
+    /// The file being written, and how far into it the job had got.
+    pub file: Option<PathBuf>,
+    pub offset: u64,
+    pub entries: u64,
+    pub stalled_for: Duration,
+}
+
+// Shared between a running job, which updates it, and the watchdog, which
+// reads it.
+pub(crate) struct Progress {
+    job: JobKind,
+    clock: Arc<dyn Clock>,
+    state: Mutex<ProgressState>,
+}
+
+struct ProgressState {
+    // The current step is the last one; pending steps come before it.
+    steps: Vec<&'static str>,
+    file: Option<PathBuf>,
+    offset: u64,
+    entries: u64,
+    last_progress: Instant,
+}
+
+impl Progress {
+    fn new(job: JobKind, clock: Arc<dyn Clock>, steps: &[&'static str]) -> Progress {
+        let last_progress = clock.instant();
+        Progress {
+            job,
+            clock,
+            state: Mutex::new(ProgressState {
+                steps: steps.iter().rev().copied().collect(),
+                file: None,
+                offset: 0,
+                entries: 0,
+                last_progress,
+            }),
+        }
+    }
+
+    pub(crate) fn set_file(&self, file: PathBuf) {
+        let mut state = self.state.lock().unwrap();
+        state.file = Some(file);
+        state.last_progress = self.clock.instant();
+    }
+
+    // Records that another entry was written, leaving the file at `offset`.
+    pub(crate) fn advance(&self, offset: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.offset = offset;
+        state.entries += 1;
+        state.last_progress = self.clock.instant();
+    }
+
+    pub(crate) fn next_step(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.steps.pop();
+        state.last_progress = self.clock.instant();
+    }
+
+    fn report(&self) -> StallReport {
+        let state = self.state.lock().unwrap();
+        let mut steps = state.steps.iter().rev();
+        StallReport {
+            job: self.job,
+            step: steps.next().copied().unwrap_or("done"),
+            pending: steps.copied().collect(),
+            file: state.file.clone(),
+            offset: state.offset,
+            entries: state.entries,
+            stalled_for: self.clock.instant() - state.last_progress,
+        }
+    }
+}
+
+// Runs `job`, going through `steps` in order, under the watchdog if one is
+// configured. The job may be dropped partway through and run again, so it
+// mustn't change anything that's visible until it has finished. It's handed
+// its progress rather than lending it, so a job can run in a background
+// task.
+pub(crate) async fn watch<T, F>(
+    options: &DbOptions,
+    kind: JobKind,
+    steps: &[&'static str],
+    mut job: impl FnMut(Arc<Progress>) -> F,
+) -> Result<T, NdbError>
+where
+    F: Future<Output = Result<T, NdbError>>,
+{
+    let Some(watchdog) = &options.watchdog else {
+        return job(Arc::new(Progress::new(kind, options.clock.clone(), steps))).await;
+    };
+
+    let mut attempts = 0;
+    loop {
+        let progress = Arc::new(Progress::new(kind, options.clock.clone(), steps));
+        let mut running = Box::pin(job(progress.clone()));
+        let mut reported = false;
+        let report = loop {
+            tokio::select! {
+                result = &mut running => return result,
+                _ = tokio::time::sleep(watchdog.check_interval) => {}
+            }
+            let report = progress.report();
+            if report.stalled_for < watchdog.stall_threshold {
+                reported = false;
+                continue;
+            }
+            // Only report each stall once, rather than on every check.
+            if !reported {
+                warn!(job = ?report.job, ?report, "job stalled");
+                reported = true;
+            }
+            if watchdog.action != StallAction::Log {
+                break report;
+            }
+        };
+        drop(running);
+
+        // The job won't finish the table it was writing, so don't leave it
+        // lying around.
+        if let Some(file) = &report.file {
+            storage::remove_if_exists(&*options.storage, file).await?;
+        }
+
+        attempts += 1;
+        match watchdog.action {
+            StallAction::Retry { attempts: max } if attempts <= max => continue,
+            _ => return Err(NdbError::Stalled(Box::new(report))),
+        }
+    }
+}
@@ -138,6 +138,6 @@ impl Db {
     }
 
     async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, NdbError> {
-        Ok(self.log.get(key).await?)
+        self.log.get(key).await
     }
 }
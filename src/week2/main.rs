@@ -143,7 +143,7 @@ impl Db {
     }
 
     async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, NdbError> {
-        Ok(self.memtable.get(key).await?)
+        self.memtable.get(key).await
     }
 }
 
@@ -0,0 +1,127 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+/// A memory budget for the memtables of every database it's shared with,
+/// through [`DbOptions::write_buffer_manager`](crate::DbOptions).
+///
+/// Once the memtables add up to more than the limit, the database with the
+/// largest one is asked to flush it. A write to that database freezes it
+/// straight away and writes it to a table in the background, the same way
+/// [`DbOptions::write_buffer_size`](crate::DbOptions) does. So does
+/// [`Db::flush_when_asked`](crate::Db::flush_when_asked), for a database
+/// that isn't being written to; without it, such a database goes on holding
+/// its memtable until the next write to it.
+#[derive(Debug)]
+pub struct WriteBufferManager {
+    limit: u64,
+    next_id: AtomicU64,
+    dbs: Mutex<BTreeMap<u64, Usage>>,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    // The memtable being written to.
+    active: u64,
+    // The memtable being flushed, if there is one.
+    frozen: u64,
+    flush_requested: bool,
+    // Wakes `Db::flush_when_asked` when a flush is requested, or the
+    // database is dropped.
+    asked: Arc<Notify>,
+}
+
+// A database's share of the budget, given up when it's dropped.
+pub(crate) struct WriteBuffer {
+    manager: Arc<WriteBufferManager>,
+    id: u64,
+}
+
+impl Drop for WriteBuffer {
+    fn drop(&mut self) {
+        if let Some(usage) = self.manager.dbs.lock().unwrap().remove(&self.id) {
+            usage.asked.notify_one();
+        }
+    }
+}
+
+impl WriteBufferManager {
+    pub fn new(limit: u64) -> WriteBufferManager {
+        WriteBufferManager {
+            limit,
+            next_id: AtomicU64::new(0),
+            dbs: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// How much memory the memtables of every database sharing the budget
+    /// take up, including ones being flushed.
+    pub fn memory_usage(&self) -> u64 {
+        let dbs = self.dbs.lock().unwrap();
+        dbs.values().map(|usage| usage.active + usage.frozen).sum()
+    }
+
+    pub(crate) fn register(self: &Arc<Self>) -> WriteBuffer {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.dbs.lock().unwrap().insert(id, Usage::default());
+        WriteBuffer {
+            manager: self.clone(),
+            id,
+        }
+    }
+}
+
+impl WriteBuffer {
+    // Records how much memory the database's memtables take up, and returns
+    // whether it should freeze the one being written to, either because it's
+    // been asked to or because it's the largest and the budget's been gone
+    // over. If another database's is the largest, that one's asked to.
+    pub(crate) fn update(&self, active: u64, frozen: u64) -> bool {
+        let mut dbs = self.manager.dbs.lock().unwrap();
+        let usage = dbs.get_mut(&self.id).unwrap();
+        usage.active = active;
+        usage.frozen = frozen;
+        if usage.flush_requested {
+            return true;
+        }
+        let total: u64 = dbs.values().map(|usage| usage.active + usage.frozen).sum();
+        if total <= self.manager.limit {
+            return false;
+        }
+        let (&largest, usage) = dbs
+            .iter_mut()
+            .max_by_key(|(_, usage)| usage.active)
+            .unwrap();
+        if largest == self.id {
+            return true;
+        }
+        if !usage.flush_requested {
+            usage.flush_requested = true;
+            usage.asked.notify_one();
+        }
+        false
+    }
+
+    // Notified whenever the database is asked to flush.
+    pub(crate) fn asked(&self) -> Arc<Notify> {
+        let dbs = self.manager.dbs.lock().unwrap();
+        dbs[&self.id].asked.clone()
+    }
+
+    // The database's memtable has been frozen, so it's done what it was
+    // asked.
+    pub(crate) fn froze(&self) {
+        let mut dbs = self.manager.dbs.lock().unwrap();
+        dbs.get_mut(&self.id).unwrap().flush_requested = false;
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// When writes are slowed down, and then stopped, because tables are piling
+/// up faster than [`Db::vacuum`](crate::Db::vacuum) and
+/// [`Db::compact_into_base`](crate::Db::compact_into_base) merge them. Every
+/// read that misses the memtable checks every table, so without this reads
+/// get slower without bound.
+///
+/// Past a slowdown threshold each write is delayed, by `delay` at the
+/// threshold and growing towards `max_delay` as the stop threshold nears.
+/// Past a stop threshold, a write waits for a vacuum to merge tables, if
+/// [`DbOptions::vacuum`](crate::DbOptions::vacuum) is on. If it's off, or
+/// there's nothing left to merge, writes are rejected with
+/// [`NdbError::WritesStopped`](crate::NdbError::WritesStopped) until the
+/// tables have been merged by hand, with [`Db::vacuum`](crate::Db::vacuum)
+/// or [`Db::compact_into_base`](crate::Db::compact_into_base). Separately, a write that finds the memtable
+/// full while the one before it is still being flushed waits for that
+/// flush, rather than the memtable growing past
+/// [`DbOptions::write_buffer_size`](crate::DbOptions::write_buffer_size).
+#[derive(Debug, Clone)]
+pub struct WriteStallOptions {
+    pub slowdown_tables: usize,
+    pub stop_tables: usize,
+    /// Thresholds on the tables' total size, on top of their number.
+    pub slowdown_table_bytes: Option<u64>,
+    pub stop_table_bytes: Option<u64>,
+    pub delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for WriteStallOptions {
+    fn default() -> WriteStallOptions {
+        WriteStallOptions {
+            slowdown_tables: 20,
+            stop_tables: 36,
+            slowdown_table_bytes: None,
+            stop_table_bytes: None,
+            delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Whether writes are going ahead as usual.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WriteStallState {
+    #[default]
+    Normal,
+    Delayed,
+    /// Writes are being rejected, or, if it's only because of a flush
+    /// that's behind, made to wait for it.
+    Stopped,
+}
+
+/// Why writes are being delayed or stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WriteStallReason {
+    /// There are `tables` tables, at least the `threshold` crossed.
+    Tables { tables: u64, threshold: u64 },
+    /// The tables add up to `bytes`, at least the `threshold` crossed.
+    TableBytes { bytes: u64, threshold: u64 },
+    /// The memtable is full and the one before it is still being flushed.
+    FlushBehind,
+}
+
+// How far past each threshold the tables are, and what writes should do
+// about it.
+#[derive(Debug, Default)]
+pub(crate) struct Stall {
+    pub(crate) state: WriteStallState,
+    pub(crate) reasons: Vec<WriteStallReason>,
+    // How long to delay a write by, when it's delayed.
+    pub(crate) delay: Duration,
+}
+
+impl Stall {
+    pub(crate) fn check(
+        options: &WriteStallOptions,
+        tables: u64,
+        bytes: u64,
+        flush_behind: bool,
+    ) -> Stall {
+        let mut stall = Stall::default();
+        if flush_behind {
+            stall.stop(WriteStallReason::FlushBehind);
+        }
+        type Reason = fn(u64, u64) -> WriteStallReason;
+        let thresholds: [(u64, Option<u64>, Option<u64>, Reason); 2] = [
+            (
+                tables,
+                Some(options.slowdown_tables as u64),
+                Some(options.stop_tables as u64),
+                |tables, threshold| WriteStallReason::Tables { tables, threshold },
+            ),
+            (
+                bytes,
+                options.slowdown_table_bytes,
+                options.stop_table_bytes,
+                |bytes, threshold| WriteStallReason::TableBytes { bytes, threshold },
+            ),
+        ];
+        // How far each write is delayed between `delay` and `max_delay`, by
+        // whichever is furthest from its slowdown threshold to its stop one.
+        let mut progress: f64 = 0.0;
+        for (value, slowdown, stop, reason) in thresholds {
+            if let Some(stop) = stop.filter(|&stop| value >= stop) {
+                stall.stop(reason(value, stop));
+            } else if let Some(slowdown) = slowdown.filter(|&slowdown| value >= slowdown) {
+                stall.reasons.push(reason(value, slowdown));
+                if stall.state == WriteStallState::Normal {
+                    stall.state = WriteStallState::Delayed;
+                }
+                progress = progress.max(match stop {
+                    Some(stop) if stop > slowdown => {
+                        (value - slowdown) as f64 / (stop - slowdown) as f64
+                    }
+                    _ => 1.0,
+                });
+            }
+        }
+        if stall.state == WriteStallState::Delayed {
+            let extra = options.max_delay.saturating_sub(options.delay);
+            stall.delay = options.delay + extra.mul_f64(progress);
+        }
+        stall
+    }
+
+    fn stop(&mut self, reason: WriteStallReason) {
+        self.state = WriteStallState::Stopped;
+        self.reasons.push(reason);
+    }
+
+    // Whether writes are rejected, rather than only waiting for a flush.
+    pub(crate) fn rejects(&self) -> bool {
+        self.state == WriteStallState::Stopped
+            && self
+                .reasons
+                .iter()
+                .any(|reason| *reason != WriteStallReason::FlushBehind)
+    }
+}
@@ -0,0 +1,71 @@
+use nulldb::{Db, DbOptions, NdbError, WriteOptions};
+use tempfile::TempDir;
+
+fn options() -> DbOptions {
+    DbOptions {
+        write_buffer_size: Some(4096),
+        ..DbOptions::default()
+    }
+}
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key-{:04}", i).into_bytes()
+}
+
+fn tables(db: &Db) -> u64 {
+    db.stats().levels.iter().map(|level| level.tables).sum()
+}
+
+#[tokio::test]
+async fn flushes_as_the_memtable_fills() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::with_options(dir.path(), options()).await?;
+    for i in 0..2000 {
+        db.put(&key(i), &[b'v'; 20]).await?;
+        // Whether it's in the memtable, a frozen one or a table by now.
+        assert_eq!(db.get(&key(i)).await?, Some(vec![b'v'; 20]));
+        assert_eq!(db.get(&key(i / 2)).await?, Some(vec![b'v'; 20]));
+    }
+    assert!(tables(&db) > 1, "{} tables", tables(&db));
+    Ok(())
+}
+
+// Writes that skip the WAL fill the memtable all the same.
+#[tokio::test]
+async fn flushes_as_unlogged_writes_fill_it() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::with_options(dir.path(), options()).await?;
+    let no_wal = WriteOptions {
+        disable_wal: true,
+        ..WriteOptions::default()
+    };
+    for i in 0..2000 {
+        db.put_opt(&key(i), &[b'v'; 20], &no_wal).await?;
+        assert_eq!(db.get(&key(i)).await?, Some(vec![b'v'; 20]));
+    }
+    assert!(tables(&db) > 1, "{} tables", tables(&db));
+    Ok(())
+}
+
+#[tokio::test]
+async fn keeps_every_write_across_a_reopen() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::with_options(dir.path(), options()).await?;
+    for i in 0..1000 {
+        db.put(&key(i), &[b'v'; 20]).await?;
+    }
+    drop(db);
+
+    let db = Db::with_options(dir.path(), options()).await?;
+    assert_eq!(db.latest_sequence(), 1000);
+    for i in 0..1000 {
+        assert_eq!(db.get(&key(i)).await?, Some(vec![b'v'; 20]));
+    }
+    db.close().await?;
+
+    let mut db = Db::new(dir.path()).await?;
+    db.flush_memtable().await?;
+    assert_eq!(db.stats().memtable.bytes, 0);
+    assert_eq!(db.get(&key(999)).await?, Some(vec![b'v'; 20]));
+    Ok(())
+}
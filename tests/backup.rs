@@ -0,0 +1,78 @@
+use nulldb::{BackupEngine, Db, NdbError};
+use tempfile::TempDir;
+
+fn shared_files(backups: &TempDir) -> Result<usize, NdbError> {
+    Ok(std::fs::read_dir(backups.path().join("shared"))?.count())
+}
+
+#[tokio::test]
+async fn backups_share_unchanged_tables() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let backups = TempDir::new()?;
+    let engine = BackupEngine::open(backups.path()).await?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    db.put(b"b", b"1").await?;
+    let first = engine.create_backup(&mut db).await?;
+    assert_eq!((first.id, first.sequence, first.tables), (1, 2, 1));
+    assert_eq!(shared_files(&backups)?, 1);
+
+    db.put(b"a", b"2").await?;
+    let second = engine.create_backup(&mut db).await?;
+    assert_eq!((second.id, second.sequence, second.tables), (2, 3, 2));
+    // Only the new table was copied.
+    assert_eq!(shared_files(&backups)?, 2);
+    assert_eq!(engine.list_backups().await?, vec![first, second]);
+
+    let old = TempDir::new()?;
+    engine.restore_backup_to(1, old.path()).await?;
+    let latest = TempDir::new()?;
+    engine.restore_to(latest.path()).await?;
+    drop(db);
+    std::fs::remove_dir_all(dir.path())?;
+
+    let old = Db::new(old.path()).await?;
+    assert_eq!(old.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(old.latest_sequence(), 2);
+    let latest = Db::new(latest.path()).await?;
+    assert_eq!(latest.get(b"a").await?, Some(b"2".to_vec()));
+    assert_eq!(latest.get(b"b").await?, Some(b"1".to_vec()));
+    assert_eq!(latest.latest_sequence(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn purge_removes_unused_files() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let backups = TempDir::new()?;
+    let engine = BackupEngine::open(backups.path()).await?;
+    let mut db = Db::new(dir.path()).await?;
+    for i in 0..4u8 {
+        db.put(b"k", &[i]).await?;
+        engine.create_backup(&mut db).await?;
+    }
+    // Merging the tables means the last backup shares nothing with the others.
+    assert_eq!(db.vacuum().await?, 3);
+    engine.create_backup(&mut db).await?;
+    assert_eq!(shared_files(&backups)?, 5);
+
+    engine.purge_old(1).await?;
+    let left = engine.list_backups().await?;
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].id, 5);
+    assert_eq!(shared_files(&backups)?, 1);
+    assert_eq!(
+        std::fs::read_dir(backups.path().join("private"))?.count(),
+        1
+    );
+
+    let restored = TempDir::new()?;
+    engine.restore_to(restored.path()).await?;
+    let db = Db::new(restored.path()).await?;
+    assert_eq!(db.get(b"k").await?, Some(vec![3]));
+    assert!(matches!(
+        engine.restore_to(restored.path()).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use nulldb::{BatchOp, Db, NdbError, WriteBatch};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn ships_batches_between_databases() -> Result<(), NdbError> {
+    let mut batch = WriteBatch::new();
+    batch.put(b"a", b"1");
+    batch.delete(b"b");
+    batch.delete_range(b"c", b"d");
+    let bytes = batch.to_bytes();
+    let decoded = WriteBatch::from_bytes(&bytes)?;
+    assert_eq!(
+        decoded.ops().collect::<Vec<_>>(),
+        batch.ops().collect::<Vec<_>>()
+    );
+
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.write(decoded).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    drop(db);
+
+    // The same bytes as in the log, less the sequence number.
+    let log = std::fs::read_to_string(dir.path().join("log"))?;
+    let line = log.lines().last().unwrap().replacen("\"seq\":1,", "", 1);
+    assert_eq!(line.as_bytes(), bytes);
+    Ok(())
+}
+
+#[test]
+fn single_writes_and_bad_input() -> Result<(), NdbError> {
+    let mut batch = WriteBatch::new();
+    batch.put(b"k", b"v");
+    let decoded = WriteBatch::from_bytes(&batch.to_bytes())?;
+    assert_eq!(
+        decoded.ops().collect::<Vec<_>>(),
+        [BatchOp::Put {
+            key: b"k",
+            value: b"v"
+        }]
+    );
+    assert!(WriteBatch::from_bytes(&WriteBatch::new().to_bytes())?.is_empty());
+
+    assert!(matches!(
+        WriteBatch::from_bytes(b"{\"Put\""),
+        Err(NdbError::Serde(_))
+    ));
+    assert!(matches!(
+        WriteBatch::from_bytes(br#"{"Batch":[{"Batch":[]}]}"#),
+        Err(NdbError::Corruption { .. })
+    ));
+    Ok(())
+}
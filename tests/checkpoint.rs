@@ -0,0 +1,60 @@
+use nulldb::{Db, DbOptions, FileKind, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn copies_a_consistent_snapshot() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let backups = TempDir::new()?;
+    let checkpoint = backups.path().join("1");
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+
+    db.checkpoint(&checkpoint).await?;
+    db.put(b"a", b"changed").await?;
+    db.put(b"c", b"3").await?;
+    assert!(matches!(
+        db.checkpoint(&checkpoint).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+
+    let copy = Db::new(&checkpoint).await?;
+    assert_eq!(copy.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(copy.get(b"b").await?, Some(b"2".to_vec()));
+    assert_eq!(copy.get(b"c").await?, None);
+    assert_eq!(copy.latest_sequence(), 2);
+
+    // The tables are shared with the original rather than copied.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let files = copy.live_files().await?;
+        for table in files.iter().filter(|f| f.kind == FileKind::Table) {
+            assert!(table.path.starts_with(&checkpoint));
+            assert_eq!(std::fs::metadata(&table.path)?.nlink(), 2);
+        }
+    }
+
+    // Each can be written to without affecting the other.
+    copy.put(b"d", b"4").await?;
+    assert_eq!(db.get(b"d").await?, None);
+    drop(db);
+    std::fs::remove_dir_all(dir.path())?;
+    assert_eq!(copy.get(b"a").await?, Some(b"1".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn overlays_are_rejected() -> Result<(), NdbError> {
+    let base = TempDir::new()?;
+    let delta = TempDir::new()?;
+    let checkpoint = TempDir::new()?;
+    Db::new(base.path()).await?;
+    let mut db = Db::open_overlay(base.path(), delta.path(), DbOptions::default()).await?;
+    assert!(matches!(
+        db.checkpoint(checkpoint.path()).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::{
+    io::{BufRead, BufReader},
+    net::TcpListener,
+    path::Path,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use nulldb_client::{ClientError, ClientOptions, NulldbClient, Scan};
+use tempfile::TempDir;
+
+// A server, killed when it's dropped.
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    fn start(dir: &Path, addr: &str, args: &[&str]) -> Server {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ndb-server"))
+            .args(["--addr", addr])
+            .args(args)
+            .arg(dir)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let addr = line.trim().strip_prefix("listening on ").unwrap().into();
+        Server { child, addr }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_and_writes_over_a_pool() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path(), "127.0.0.1:0", &["--max-scan", "100"]);
+    let options = ClientOptions {
+        connections: 2,
+        ..ClientOptions::default()
+    };
+    let client = NulldbClient::connect_with(&server.addr, options)
+        .await
+        .unwrap();
+
+    client.put(b"a", b"1").await.unwrap();
+    assert_eq!(client.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+    client.delete(b"a").await.unwrap();
+    assert_eq!(client.get(b"a").await.unwrap(), None);
+
+    // Many tasks' requests at once, sharing the two connections.
+    let tasks: Vec<_> = (0..8)
+        .map(|t| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                for i in 0..100 {
+                    let key = format!("{}-{:03}", t, i);
+                    client.put(key.as_bytes(), b"v").await.unwrap();
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let page = client.scan(Some(b"7-"), None, 2).await.unwrap();
+    let expected = [
+        (b"7-000".to_vec(), b"v".to_vec()),
+        (b"7-001".to_vec(), b"v".to_vec()),
+    ];
+    assert_eq!(
+        page,
+        Scan {
+            entries: expected.to_vec(),
+            more: true
+        }
+    );
+    // Past the server's maximum, a page at a time.
+    assert_eq!(client.scan_all(None, None).await.unwrap().len(), 800);
+    assert_eq!(
+        client.scan_all(Some(b"3"), Some(b"5")).await.unwrap().len(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn reconnects_after_the_server_restarts() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path(), "127.0.0.1:0", &[]);
+    let addr = server.addr.clone();
+    let client = NulldbClient::connect(&addr).await.unwrap();
+    client.put(b"key", b"value").await.unwrap();
+
+    // The client's connection breaks, and the request is tried again on a
+    // new one.
+    drop(server);
+    let _server = Server::start(dir.path(), &addr, &[]);
+    assert_eq!(client.get(b"key").await.unwrap(), Some(b"value".to_vec()));
+}
+
+#[tokio::test]
+async fn gives_up_on_a_server_that_doesnt_answer() {
+    // Connections are accepted, and then nothing is ever read or written.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let options = ClientOptions {
+        timeout: Duration::from_millis(100),
+        retries: 2,
+        retry_backoff: Duration::from_millis(10),
+        ..ClientOptions::default()
+    };
+    let addr = listener.local_addr().unwrap().to_string();
+    let client = NulldbClient::connect_with(addr, options).await.unwrap();
+    let started = Instant::now();
+    let err = client.get(b"key").await.unwrap_err();
+    assert!(matches!(err, ClientError::Timeout), "{}", err);
+    assert!(started.elapsed() >= Duration::from_millis(300));
+}
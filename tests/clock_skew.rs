@@ -0,0 +1,71 @@
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use nulldb::{ClockSkewAction, Db, DbOptions, ManualClock, NdbError};
+use tempfile::TempDir;
+
+fn options(clock: &Arc<ManualClock>, on_clock_skew: ClockSkewAction) -> DbOptions {
+    DbOptions {
+        clock: clock.clone(),
+        on_clock_skew,
+        ..DbOptions::default()
+    }
+}
+
+// A table flushed after the clock went backwards still shadows the older
+// ones, before and after reopening, and doesn't clobber any of them.
+#[tokio::test]
+async fn tables_stay_ordered_when_the_clock_goes_backwards() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+    let mut db = Db::with_options(dir.path(), options(&clock, ClockSkewAction::Warn)).await?;
+
+    db.put(b"a", b"1").await?;
+    db.put(b"b", b"1").await?;
+    db.flush_memtable().await?;
+    clock.set(UNIX_EPOCH + Duration::from_secs(500));
+    db.put(b"a", b"2").await?;
+    db.flush_memtable().await?;
+    // Same second as the last flush.
+    db.put(b"b", b"3").await?;
+    db.flush_memtable().await?;
+
+    assert_eq!(db.get(b"a").await?, Some(b"2".to_vec()));
+    assert_eq!(db.get(b"b").await?, Some(b"3".to_vec()));
+    drop(db);
+
+    let mut db = Db::with_options(dir.path(), options(&clock, ClockSkewAction::Warn)).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"2".to_vec()));
+    assert_eq!(db.get(b"b").await?, Some(b"3".to_vec()));
+    db.put(b"a", b"4").await?;
+    db.flush_memtable().await?;
+    assert_eq!(db.get(b"a").await?, Some(b"4".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn skew_can_be_an_error() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+    let mut db = Db::with_options(dir.path(), options(&clock, ClockSkewAction::Error)).await?;
+
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    clock.set(UNIX_EPOCH + Duration::from_secs(500));
+    db.put(b"a", b"2").await?;
+    assert!(matches!(
+        db.flush_memtable().await,
+        Err(NdbError::ClockSkew {
+            previous: 1000,
+            now: 500
+        })
+    ));
+    // Nothing was lost, and once the clock catches up the flush goes through.
+    assert_eq!(db.get(b"a").await?, Some(b"2".to_vec()));
+    clock.set(UNIX_EPOCH + Duration::from_secs(1001));
+    db.flush_memtable().await?;
+    assert_eq!(db.get(b"a").await?, Some(b"2".to_vec()));
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use nulldb::{Db, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn close_releases_the_database() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    db.close().await?;
+
+    let mut db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+    db.close().await?;
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(db.get(b"b").await?, Some(b"2".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn dropping_keeps_writes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    for i in 0..100u32 {
+        db.put(&i.to_be_bytes(), b"v").await?;
+    }
+    drop(db);
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(&99u32.to_be_bytes()).await?, Some(b"v".to_vec()));
+    Ok(())
+}
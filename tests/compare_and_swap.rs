@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use nulldb::{Db, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn swaps_only_on_a_match() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+
+    assert!(db.compare_and_swap(b"k", None, Some(b"1")).await?);
+    assert!(!db.compare_and_swap(b"k", None, Some(b"2")).await?);
+    assert!(!db.compare_and_swap(b"k", Some(b"0"), Some(b"2")).await?);
+    assert_eq!(db.get(b"k").await?, Some(b"1".to_vec()));
+
+    assert!(db.compare_and_swap(b"k", Some(b"1"), Some(b"2")).await?);
+    assert!(db.compare_and_swap(b"k", Some(b"2"), None).await?);
+    assert_eq!(db.get(b"k").await?, None);
+    Ok(())
+}
+
+// Increments that race through compare_and_swap never lose an update.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_increments() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Arc::new(Db::new(dir.path()).await?);
+
+    let workers: Vec<_> = (0..8)
+        .map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for _ in 0..25 {
+                    loop {
+                        let current = db.get(b"counter").await?;
+                        let n = current
+                            .as_deref()
+                            .map_or(0, |v| u16::from_be_bytes([v[0], v[1]]));
+                        let next = (n + 1).to_be_bytes();
+                        if db
+                            .compare_and_swap(b"counter", current.as_deref(), Some(&next))
+                            .await?
+                        {
+                            break;
+                        }
+                    }
+                }
+                Ok::<_, NdbError>(())
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.await.unwrap()?;
+    }
+
+    assert_eq!(db.get(b"counter").await?, Some(vec![0, 200]));
+    Ok(())
+}
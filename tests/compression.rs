@@ -0,0 +1,130 @@
+use nulldb::{inspect_sst, Compression, Db, DbOptions, FileKind, NdbError};
+use tempfile::TempDir;
+
+const CODECS: [Compression; 4] = [
+    Compression::None,
+    Compression::Snappy,
+    Compression::Lz4,
+    Compression::Zstd { level: 3 },
+];
+
+fn key(i: usize) -> Vec<u8> {
+    format!("doc{:05}", i).into_bytes()
+}
+
+fn value(i: usize) -> Vec<u8> {
+    format!(
+        r#"{{"id":{},"kind":"order","status":"shipped","items":[{{"sku":"A-{}","qty":1}}]}}"#,
+        i,
+        i % 7
+    )
+    .into_bytes()
+}
+
+async fn table_size(db: &Db) -> Result<u64, NdbError> {
+    Ok(db
+        .live_files()
+        .await?
+        .iter()
+        .filter(|file| file.kind == FileKind::Table)
+        .map(|file| file.size)
+        .sum())
+}
+
+// Each codec writes a table that reads back the same and, for compressible
+// values, is smaller than the uncompressed one.
+#[tokio::test]
+async fn every_codec_round_trips() -> Result<(), NdbError> {
+    let mut uncompressed = None;
+    for compression in CODECS {
+        let dir = TempDir::new()?;
+        let options = DbOptions {
+            compression,
+            ..DbOptions::default()
+        };
+        let mut db = Db::with_options(dir.path(), options).await?;
+        for i in 0..1000 {
+            db.put(&key(i), &value(i)).await?;
+        }
+        db.flush_memtable().await?;
+
+        for i in 0..1000 {
+            assert_eq!(db.get(&key(i)).await?, Some(value(i)), "{:?}", compression);
+        }
+        let mut iter = db.scan(..).await?;
+        let mut count = 0;
+        while let Some((key, _)) = iter.next().await? {
+            assert_eq!(key, self::key(count));
+            count += 1;
+        }
+        assert_eq!(count, 1000);
+
+        let size = table_size(&db).await?;
+        match uncompressed {
+            None => uncompressed = Some(size),
+            Some(uncompressed) => assert!(size * 2 < uncompressed, "{:?}", compression),
+        }
+    }
+    Ok(())
+}
+
+// The codec is recorded per block, so changing it between opens leaves the
+// older tables readable.
+#[tokio::test]
+async fn mixed_codecs() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    for (n, compression) in CODECS.into_iter().enumerate() {
+        let options = DbOptions {
+            compression,
+            ..DbOptions::default()
+        };
+        let mut db = Db::with_options(dir.path(), options).await?;
+        for i in n * 100..(n + 1) * 100 {
+            db.put(&key(i), &value(i)).await?;
+        }
+        db.flush_memtable().await?;
+    }
+
+    let db = Db::new(dir.path()).await?;
+    for i in 0..CODECS.len() * 100 {
+        assert_eq!(db.get(&key(i)).await?, Some(value(i)));
+    }
+    Ok(())
+}
+
+// A block claiming to decompress to far more than it could is corrupt, and
+// the claimed size isn't allocated.
+#[tokio::test]
+async fn bogus_decompressed_size() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        compression: Compression::Lz4,
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options.clone()).await?;
+    for i in 0..1000 {
+        db.put(&key(i), &value(i)).await?;
+    }
+    db.flush_memtable().await?;
+    let files = db.live_files().await?;
+    let table = files.iter().find(|f| f.kind == FileKind::Table).unwrap();
+    let report = inspect_sst(&table.path, &options, false).await?;
+    drop(db);
+
+    // lz4 blocks start with their decompressed size. Give the first one a
+    // huge size, and a checksum to match.
+    let size = report.blocks[0].location.size as usize;
+    let mut data = std::fs::read(&table.path)?;
+    data[..4].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+    let crc = crc32c::crc32c_append(crc32c::crc32c(&data[..size]), &data[size..size + 1]);
+    data[size + 1..size + 5].copy_from_slice(&crc.to_le_bytes());
+    std::fs::write(&table.path, data)?;
+
+    let db = Db::with_options(dir.path(), options).await?;
+    match db.get(&key(0)).await {
+        Err(NdbError::Corruption { detail, .. }) => assert!(detail.contains("4294967280")),
+        other => panic!("expected corruption, got {:?}", other),
+    }
+    assert_eq!(db.get(&key(999)).await?, Some(value(999)));
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use nulldb::{Db, NdbError, WriteBatch};
+use tempfile::TempDir;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn reads_never_see_half_a_batch() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Arc::new(Db::new(dir.path()).await?);
+    db.put(b"a", b"0").await?;
+    db.put(b"b", b"0").await?;
+
+    let writer = {
+        let db = db.clone();
+        tokio::spawn(async move {
+            for i in 1..=500 {
+                let value = i.to_string();
+                let mut batch = WriteBatch::new();
+                batch.put(b"a", value.as_bytes());
+                batch.put(b"b", value.as_bytes());
+                db.write(batch).await?;
+            }
+            Ok::<_, NdbError>(())
+        })
+    };
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for _ in 0..200 {
+                    let view = db.freeze_view();
+                    let a = view.get(b"a").await?;
+                    let b = view.get(b"b").await?;
+                    assert_eq!(a, b);
+                    let mut iter = db.scan(..).await?;
+                    let mut values = Vec::new();
+                    while let Some((_, value)) = iter.next().await? {
+                        values.push(value);
+                    }
+                    assert_eq!(values[0], values[1]);
+                }
+                Ok::<_, NdbError>(())
+            })
+        })
+        .collect();
+    writer.await.unwrap()?;
+    for reader in readers {
+        reader.await.unwrap()?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn views_read_past_later_writes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    for key in [b"a", b"b", b"c"] {
+        db.put(key, b"old").await?;
+    }
+    let view = db.freeze_view();
+    db.put(b"a", b"new").await?;
+    db.delete_range(b"b", b"d").await?;
+    db.put(b"d", b"new").await?;
+
+    for key in [b"a", b"b", b"c"] {
+        assert_eq!(view.get(key).await?, Some(b"old".to_vec()));
+    }
+    assert_eq!(view.get(b"d").await?, None);
+    let mut iter = view.scan(..).await?;
+    let mut keys = Vec::new();
+    while let Some((key, _)) = iter.next().await? {
+        keys.push(key);
+    }
+    assert_eq!(keys, [b"a", b"b", b"c"]);
+
+    assert_eq!(db.get(b"a").await?, Some(b"new".to_vec()));
+    assert_eq!(db.get(b"b").await?, None);
+    Ok(())
+}
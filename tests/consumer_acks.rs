@@ -0,0 +1,28 @@
+use nulldb::{Db, FileKind, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn acks_survive_reopen() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.acked("indexer").await, None);
+    assert_eq!(db.min_acked().await, None);
+
+    db.ack("indexer", 10).await?;
+    db.ack("mailer", 4).await?;
+    // Acks don't go backwards.
+    db.ack("indexer", 7).await?;
+    assert_eq!(db.acked("indexer").await, Some(10));
+    assert_eq!(db.min_acked().await, Some(4));
+
+    let files = db.live_files().await?;
+    assert!(files.iter().any(|file| file.kind == FileKind::Consumers));
+    drop(db);
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.acked("indexer").await, Some(10));
+    assert_eq!(db.acked("mailer").await, Some(4));
+    db.ack("mailer", 12).await?;
+    assert_eq!(db.min_acked().await, Some(10));
+    Ok(())
+}
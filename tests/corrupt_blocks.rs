@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+
+use nulldb::{Db, DbOptions, FileKind, NdbError, VacuumOptions};
+use tempfile::TempDir;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key-{:03}", i).into_bytes()
+}
+
+// Writes a table of 100 keys over many small blocks, and flips a byte in the
+// first block. An older table underneath has a stale value for the first key.
+async fn corrupt_table(dir: &TempDir) -> Result<PathBuf, NdbError> {
+    let options = DbOptions {
+        block_size: 64,
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    db.put(&key(0), b"stale").await?;
+    db.flush_memtable().await?;
+    for i in 0..100 {
+        db.put(&key(i), b"value").await?;
+    }
+    db.flush_memtable().await?;
+    let files = db.live_files().await?;
+    let table = files
+        .into_iter()
+        .filter(|f| f.kind == FileKind::Table)
+        .max_by_key(|f| f.size)
+        .unwrap();
+    let mut data = std::fs::read(&table.path)?;
+    data[10] ^= 0xff;
+    std::fs::write(&table.path, data)?;
+    Ok(table.path)
+}
+
+#[tokio::test]
+async fn fails_reads_by_default() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let path = corrupt_table(&dir).await?;
+    let db = Db::new(dir.path()).await?;
+    match db.get(&key(0)).await {
+        Err(err @ NdbError::Corruption { .. }) => {
+            assert!(err.is_fatal() && !err.is_retryable());
+            let NdbError::Corruption { file, offset, .. } = err else {
+                unreachable!()
+            };
+            assert_eq!((file, offset), (Some(path.clone()), Some(0)));
+        }
+        other => panic!("expected corruption, got {:?}", other),
+    }
+    assert_eq!(db.get(&key(99)).await?, Some(b"value".to_vec()));
+
+    let corrupt = db.verify_integrity().await?;
+    assert_eq!(corrupt.len(), 1);
+    assert_eq!((&corrupt[0].path, corrupt[0].offset), (&path, 0));
+    Ok(())
+}
+
+fn is_corruption<T>(result: Result<T, NdbError>) -> bool {
+    matches!(result, Err(NdbError::Corruption { .. }))
+}
+
+// Remembered corrupt blocks still fail reads inside them, rather than
+// letting what they replaced show through, and can't be vacuumed away.
+#[tokio::test]
+async fn remembers_corrupt_blocks() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    corrupt_table(&dir).await?;
+    let options = DbOptions {
+        skip_corrupt_blocks: true,
+        vacuum: Some(VacuumOptions {
+            small_table_bytes: u64::MAX,
+            min_tables: 2,
+            target_table_bytes: 1 << 20,
+        }),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    for _ in 0..2 {
+        assert!(is_corruption(db.get(&key(0)).await));
+        assert!(is_corruption(db.multi_get(&[&key(0)]).await));
+    }
+    assert_eq!(db.get(&key(99)).await?, Some(b"value".to_vec()));
+
+    // The first block is read as the scan starts.
+    assert!(is_corruption(db.scan(..).await));
+    assert!(is_corruption(db.scan(..=key(5)).await));
+    let mut iter = db.scan(key(90)..).await?;
+    assert_eq!(iter.next().await?.map(|(key, _)| key), Some(key(90)));
+
+    assert!(is_corruption(db.vacuum().await));
+    assert_eq!(db.get(&key(99)).await?, Some(b"value".to_vec()));
+    assert_eq!(db.verify_integrity().await?.len(), 1);
+    Ok(())
+}
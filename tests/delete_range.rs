@@ -0,0 +1,73 @@
+use nulldb::{Db, DbIterator, NdbError};
+use tempfile::TempDir;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key{:03}", i).into_bytes()
+}
+
+async fn keys(mut iter: DbIterator) -> Result<Vec<Vec<u8>>, NdbError> {
+    let mut keys = Vec::new();
+    while let Some((k, _)) = iter.next().await? {
+        keys.push(k);
+    }
+    Ok(keys)
+}
+
+// Checks that exactly the keys in `live` out of 0..100 are visible.
+async fn check(db: &Db, live: &[usize]) -> Result<(), NdbError> {
+    for i in 0..100 {
+        let expected = live.contains(&i).then(|| b"v".to_vec());
+        assert_eq!(db.get(&key(i)).await?, expected, "key {}", i);
+    }
+    let expected: Vec<_> = live.iter().map(|&i| key(i)).collect();
+    assert_eq!(keys(db.scan(..).await?).await?, expected);
+    let mut reversed = keys(db.scan_rev(..).await?).await?;
+    reversed.reverse();
+    assert_eq!(reversed, expected);
+
+    let all: Vec<_> = (0..100).map(key).collect();
+    let all: Vec<&[u8]> = all.iter().map(|k| k.as_slice()).collect();
+    let found = db.multi_get(&all).await?;
+    let found: Vec<_> = (0..100).filter(|&i| found[i].is_some()).collect();
+    assert_eq!(found, live);
+    Ok(())
+}
+
+#[tokio::test]
+async fn delete_range_hides_older_writes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    for i in 0..50 {
+        db.put(&key(i), b"v").await?;
+    }
+    db.flush_memtable().await?;
+    for i in 50..100 {
+        db.put(&key(i), b"v").await?;
+    }
+
+    // Spans the flushed table and the memtable.
+    db.delete_range(&key(40), &key(60)).await?;
+    // Writes after the delete are visible again.
+    db.put(&key(45), b"v").await?;
+    let mut live: Vec<_> = (0..40).chain([45]).chain(60..100).collect();
+    check(&db, &live).await?;
+
+    db.flush_memtable().await?;
+    check(&db, &live).await?;
+
+    db.delete_range(&key(0), &key(10)).await?;
+    db.put(&key(5), b"v").await?;
+    live.retain(|&i| i >= 10 || i == 5);
+    check(&db, &live).await?;
+
+    // The range tombstone in the log is replayed on reopen.
+    drop(db);
+    let db = Db::new(dir.path()).await?;
+    check(&db, &live).await?;
+
+    assert!(matches!(
+        db.delete_range(&key(2), &key(1)).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    Ok(())
+}
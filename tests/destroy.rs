@@ -0,0 +1,42 @@
+use nulldb::{Db, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn deletes_everything_the_database_wrote() -> Result<(), NdbError> {
+    let parent = TempDir::new()?;
+    let dir = parent.path().join("db");
+    let mut db = Db::new(&dir).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+    db.ack("reader", 1).await?;
+    assert!(matches!(
+        Db::destroy(&dir).await,
+        Err(NdbError::AlreadyLocked)
+    ));
+    drop(db);
+
+    Db::destroy(&dir).await?;
+    assert!(!dir.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn leaves_other_files_alone() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    std::fs::write(dir.path().join("notes.txt"), "keep me")?;
+    assert!(matches!(
+        Db::destroy(dir.path()).await,
+        Err(NdbError::NotFound(_))
+    ));
+
+    let db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    drop(db);
+    Db::destroy(dir.path()).await?;
+    let left: Vec<_> = std::fs::read_dir(dir.path())?
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(left, ["notes.txt"]);
+    Ok(())
+}
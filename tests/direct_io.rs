@@ -0,0 +1,49 @@
+use nulldb::{Db, DbOptions, NdbError, VacuumOptions};
+use tempfile::TempDir;
+
+fn value(i: u32) -> Vec<u8> {
+    format!("value-{:06}", i).repeat(20).into_bytes()
+}
+
+async fn check(db: &Db) -> Result<(), NdbError> {
+    assert_eq!(db.verify_integrity().await?, vec![]);
+    assert_eq!(db.get(&7u32.to_be_bytes()).await?, Some(b"newer".to_vec()));
+    for i in (0..8_000u32).step_by(397).skip(1) {
+        assert_eq!(db.get(&i.to_be_bytes()).await?, Some(value(i)));
+    }
+    assert_eq!(db.get(&7_999u32.to_be_bytes()).await?, Some(value(7_999)));
+    Ok(())
+}
+
+// Tables written with direct I/O read back the same as any other, whether
+// they're read through the handle they were written with or after reopening.
+#[tokio::test]
+async fn writes_tables_around_the_page_cache() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = || DbOptions {
+        direct_io_writes: true,
+        // Syncs partway through leave partial blocks behind to carry on from.
+        table_sync_bytes: Some(100_000),
+        vacuum: Some(VacuumOptions {
+            min_tables: 2,
+            small_table_bytes: u64::MAX,
+            ..VacuumOptions::default()
+        }),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    // Well over the size of the writes' buffer.
+    for i in 0..8_000u32 {
+        db.put(&i.to_be_bytes(), &value(i)).await?;
+    }
+    db.flush_memtable().await?;
+    db.put(&7u32.to_be_bytes(), b"newer").await?;
+    // Vacuumed into the first table.
+    db.flush_memtable().await?;
+    assert_eq!(db.stats().levels[0].tables, 1);
+
+    check(&db).await?;
+    drop(db);
+    check(&Db::with_options(dir.path(), options()).await?).await?;
+    Ok(())
+}
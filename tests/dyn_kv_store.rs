@@ -0,0 +1,105 @@
+use std::{collections::BTreeMap, ops::Bound, sync::Arc, sync::Mutex};
+
+use futures::{
+    future::{self, BoxFuture},
+    stream::{self, BoxStream},
+    FutureExt, StreamExt, TryStreamExt,
+};
+use nulldb::{Db, DbOptions, DynKvStore, KeyValue, NdbError, ShardedDb, WriteBatch};
+use tempfile::TempDir;
+
+// Just enough of a store to stand in for a database.
+#[derive(Default)]
+struct Mock(Mutex<BTreeMap<Vec<u8>, Vec<u8>>>);
+
+impl DynKvStore for Mock {
+    fn get<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<Option<Vec<u8>>, NdbError>> {
+        future::ready(Ok(self.0.lock().unwrap().get(key).cloned())).boxed()
+    }
+
+    fn put<'a>(&'a self, key: &'a [u8], value: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        self.0.lock().unwrap().insert(key.to_vec(), value.to_vec());
+        future::ready(Ok(())).boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        self.0.lock().unwrap().remove(key);
+        future::ready(Ok(())).boxed()
+    }
+
+    fn write(&self, _: WriteBatch) -> BoxFuture<'_, Result<(), NdbError>> {
+        unimplemented!()
+    }
+
+    fn scan(
+        &self,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> BoxStream<'_, Result<KeyValue, NdbError>> {
+        let map = self.0.lock().unwrap();
+        let entries: Vec<_> = map
+            .range((start, end))
+            .map(|(k, v)| Ok((k.clone(), v.clone())))
+            .collect();
+        stream::iter(entries).boxed()
+    }
+}
+
+async fn exercise(store: &dyn DynKvStore) -> Result<Vec<KeyValue>, NdbError> {
+    store.put(b"a", b"1").await?;
+    store.put(b"b", b"2").await?;
+    store.put(b"c", b"3").await?;
+    store.delete(b"b").await?;
+    assert_eq!(store.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(store.get(b"b").await?, None);
+    store
+        .scan(Bound::Included(b"a".to_vec()), Bound::Unbounded)
+        .try_collect()
+        .await
+}
+
+#[tokio::test]
+async fn db_and_mock_behave_alike() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let stores: Vec<Box<dyn DynKvStore>> = vec![
+        Box::new(Db::new(dir.path().join("db")).await?),
+        Box::new(
+            ShardedDb::open(
+                [dir.path().join("shard0"), dir.path().join("shard1")],
+                DbOptions::default(),
+            )
+            .await?,
+        ),
+        Box::new(Arc::new(Mock::default())),
+    ];
+    for store in &stores {
+        let entries = exercise(store.as_ref()).await?;
+        assert_eq!(
+            entries,
+            [
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"c".to_vec(), b"3".to_vec())
+            ]
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn views_are_read_only() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"k", b"v").await?;
+    let view: Box<dyn DynKvStore> = Box::new(db.freeze_view());
+    assert_eq!(view.get(b"k").await?, Some(b"v".to_vec()));
+    assert!(matches!(
+        view.put(b"k", b"w").await,
+        Err(NdbError::ReadOnly)
+    ));
+    let entries: Vec<_> = view
+        .scan(Bound::Unbounded, Bound::Unbounded)
+        .try_collect()
+        .await?;
+    assert_eq!(entries, [(b"k".to_vec(), b"v".to_vec())]);
+    Ok(())
+}
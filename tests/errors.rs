@@ -0,0 +1,67 @@
+use std::io::Write;
+
+use nulldb::{BackupEngine, Db, DbOptions, NdbError, WalRecovery};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn corrupt_log_records_say_where_they_are() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    db.put(b"b", b"2").await?;
+    drop(db);
+
+    let log = dir.path().join("log");
+    let first_record = std::fs::read_to_string(&log)?.find('\n').unwrap() as u64 + 1;
+    let mut contents = std::fs::read(&log)?;
+    contents.truncate(first_record as usize);
+    contents.extend_from_slice(b"not a record\n");
+    std::fs::File::create(&log)?.write_all(&contents)?;
+
+    // By default a corrupt record at the end of the log is dropped.
+    let options = DbOptions {
+        wal_recovery: WalRecovery::AbsoluteConsistency,
+        ..DbOptions::default()
+    };
+    match Db::with_options(dir.path(), options).await {
+        Err(NdbError::Corruption {
+            file,
+            offset,
+            detail,
+        }) => {
+            assert_eq!((file, offset), (Some(log), Some(first_record)));
+            assert!(detail.starts_with("bad log record 1:"), "{}", detail);
+        }
+        Err(err) => panic!("expected corruption, got {}", err),
+        Ok(_) => panic!("opened a database with a corrupt log"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn missing_things_are_not_found() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let engine = BackupEngine::open(dir.path()).await?;
+    let restored = dir.path().join("restored");
+    for err in [
+        engine.restore_to(&restored).await.unwrap_err(),
+        engine.restore_backup_to(3, &restored).await.unwrap_err(),
+        Db::destroy(&restored).await.unwrap_err(),
+    ] {
+        assert!(matches!(err, NdbError::NotFound(_)), "{}", err);
+        assert!(!err.is_retryable() && !err.is_fatal());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn lock_contention_is_retryable() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let err = Db::new(dir.path()).await.err().unwrap();
+    assert!(matches!(err, NdbError::AlreadyLocked));
+    assert!(err.is_retryable() && !err.is_fatal());
+    drop(db);
+    Db::new(dir.path()).await?;
+    Ok(())
+}
@@ -0,0 +1,33 @@
+// Runs each example in examples/, which check their own results, so they
+// keep working as the API changes.
+
+#[path = "../examples/backups.rs"]
+mod backups;
+#[path = "../examples/basics.rs"]
+mod basics;
+#[path = "../examples/counters.rs"]
+mod counters;
+#[path = "../examples/transactions.rs"]
+mod transactions;
+
+use nulldb::NdbError;
+
+#[test]
+fn basics() -> Result<(), NdbError> {
+    basics::main()
+}
+
+#[test]
+fn transactions() -> Result<(), NdbError> {
+    transactions::main()
+}
+
+#[test]
+fn backups() -> Result<(), NdbError> {
+    backups::main()
+}
+
+#[test]
+fn counters() -> Result<(), NdbError> {
+    counters::main()
+}
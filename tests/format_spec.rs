@@ -0,0 +1,26 @@
+use nulldb::{Db, FileKind, NdbError};
+use tempfile::TempDir;
+
+// The spec's footer example ends in the magic number real tables are
+// written with.
+#[tokio::test]
+async fn spec_matches_written_tables() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"k", b"v").await?;
+    db.flush_memtable().await?;
+
+    let files = db.live_files().await?;
+    let table = files.iter().find(|f| f.kind == FileKind::Table).unwrap();
+    let data = std::fs::read(&table.path)?;
+    let magic = u64::from_le_bytes(data[data.len() - 8..].try_into().unwrap());
+
+    let spec = nulldb::format_spec();
+    assert!(spec.contains(&format!("The magic number is {:#x}.", magic)));
+    let footer_tail: Vec<_> = data[data.len() - 8..]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert!(spec.contains(&footer_tail.join(" ")));
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+
+use nulldb::{Db, FileKind, NdbError, FORMAT_VERSION};
+use tempfile::TempDir;
+
+const UNVERSIONED_MAGIC: u64 = 0x6e75_6c6c_6462_7373;
+
+async fn files(db: &Db, kind: FileKind) -> Result<Vec<PathBuf>, NdbError> {
+    let files = db.live_files().await?;
+    Ok(files
+        .into_iter()
+        .filter(|f| f.kind == kind)
+        .map(|f| f.path)
+        .collect())
+}
+
+fn table_version(path: &Path) -> u64 {
+    let data = std::fs::read(path).unwrap();
+    u64::from_le_bytes(data[data.len() - 16..data.len() - 8].try_into().unwrap())
+}
+
+fn first_line(path: &Path) -> String {
+    let contents = std::fs::read_to_string(path).unwrap();
+    contents.lines().next().unwrap().to_string()
+}
+
+// A database with a flushed table and a write in its log.
+async fn populated(dir: &Path) -> Result<(PathBuf, PathBuf, PathBuf), NdbError> {
+    let mut db = Db::new(dir).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+    let table = files(&db, FileKind::Table).await?.remove(0);
+    let wal = files(&db, FileKind::Wal).await?.remove(0);
+    let manifest = files(&db, FileKind::DbMeta).await?.remove(1);
+    Ok((table, wal, manifest))
+}
+
+fn expect_unsupported(result: Result<Db, NdbError>, path: &Path) {
+    match result {
+        Err(NdbError::UnsupportedVersion {
+            file,
+            version,
+            supported,
+        }) => {
+            assert_eq!(file, path);
+            assert_eq!(version, FORMAT_VERSION + 1);
+            assert_eq!(supported, FORMAT_VERSION);
+        }
+        Err(err) => panic!("expected an unsupported version, got {:?}", err),
+        Ok(_) => panic!("expected an unsupported version"),
+    }
+}
+
+#[tokio::test]
+async fn stamps_every_file() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let (table, wal, manifest) = populated(dir.path()).await?;
+    let header = format!(r#"{{"format_version":{}}}"#, FORMAT_VERSION);
+    assert_eq!(table_version(&table), FORMAT_VERSION as u64);
+    assert_eq!(first_line(&wal), header);
+    assert_eq!(first_line(&manifest), header);
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_newer_tables() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let (table, _, _) = populated(dir.path()).await?;
+    let mut data = std::fs::read(&table)?;
+    let at = data.len() - 16;
+    data[at..at + 8].copy_from_slice(&(FORMAT_VERSION as u64 + 1).to_le_bytes());
+    std::fs::write(&table, data)?;
+    expect_unsupported(Db::new(dir.path()).await, &table);
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_newer_logs_and_manifests() -> Result<(), NdbError> {
+    let newer = format!(r#"{{"format_version":{}}}"#, FORMAT_VERSION + 1);
+    let stamp = |path: &Path| {
+        let contents = std::fs::read_to_string(path).unwrap();
+        let (_, rest) = contents.split_once('\n').unwrap();
+        std::fs::write(path, format!("{}\n{}", newer, rest)).unwrap();
+    };
+
+    let dir = TempDir::new()?;
+    let (_, wal, _) = populated(dir.path()).await?;
+    stamp(&wal);
+    expect_unsupported(Db::new(dir.path()).await, &wal);
+
+    let dir = TempDir::new()?;
+    let (_, _, manifest) = populated(dir.path()).await?;
+    stamp(&manifest);
+    expect_unsupported(Db::new(dir.path()).await, &manifest);
+    Ok(())
+}
+
+#[tokio::test]
+async fn reads_and_upgrades_unversioned_files() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let (table, wal, _) = populated(dir.path()).await?;
+    // Back to how they were written before they had versions.
+    let mut data = std::fs::read(&table)?;
+    let footer_start = data.len() - 48;
+    data.truncate(footer_start + 32);
+    data.extend(UNVERSIONED_MAGIC.to_le_bytes());
+    std::fs::write(&table, data)?;
+    let log = std::fs::read_to_string(&wal)?;
+    std::fs::write(&wal, log.split_once('\n').unwrap().1)?;
+
+    let mut db = Db::new(dir.path()).await?;
+    assert_eq!(db.stats().open.outdated_tables, 1);
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(db.get(b"b").await?, Some(b"2".to_vec()));
+
+    assert_eq!(db.upgrade().await?, 1);
+    for table in files(&db, FileKind::Table).await? {
+        assert_eq!(table_version(&table), FORMAT_VERSION as u64);
+    }
+    drop(db);
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.stats().open.outdated_tables, 0);
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(db.get(b"b").await?, Some(b"2".to_vec()));
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use nulldb::{Db, DbIterator, DbOptions, NdbError};
+use tempfile::TempDir;
+
+async fn keys(mut iter: DbIterator) -> Result<Vec<Vec<u8>>, NdbError> {
+    let mut keys = Vec::new();
+    while let Some((key, _)) = iter.next().await? {
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+#[tokio::test]
+async fn isolated_from_later_writes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+
+    let view = db.freeze_view();
+    db.put(b"a", b"changed").await?;
+    db.delete(b"b").await?;
+    db.put(b"c", b"3").await?;
+    db.flush_memtable().await?;
+
+    assert_eq!(view.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(view.get(b"b").await?, Some(b"2".to_vec()));
+    assert_eq!(view.get(b"c").await?, None);
+    assert_eq!(keys(view.scan(..).await?).await?, [b"a", b"b"]);
+    assert_eq!(keys(view.scan_rev(..).await?).await?, [b"b", b"a"]);
+
+    assert_eq!(db.get(b"a").await?, Some(b"changed".to_vec()));
+    assert_eq!(db.get(b"b").await?, None);
+    Ok(())
+}
+
+// Compacting an overlay into its base deletes the tables a view reads from.
+#[tokio::test]
+async fn survives_compaction() -> Result<(), NdbError> {
+    let base = TempDir::new()?;
+    let delta = TempDir::new()?;
+    {
+        let mut db = Db::new(base.path()).await?;
+        db.put(b"k", b"base").await?;
+        db.flush_memtable().await?;
+    }
+    let mut db = Db::open_overlay(base.path(), delta.path(), DbOptions::default()).await?;
+    db.put(b"k", b"delta").await?;
+    db.flush_memtable().await?;
+
+    let view = db.freeze_view();
+    db.delete(b"k").await?;
+    db.compact_into_base().await?;
+    assert_eq!(db.get(b"k").await?, None);
+
+    let scan = tokio::spawn(async move { keys(view.scan(..).await?).await });
+    assert_eq!(scan.await.unwrap()?, [b"k"]);
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use futures::TryStreamExt;
+use nulldb::{
+    grpc::{
+        proto::{
+            write::Op, BatchWriteRequest, DeleteRangeRequest, DeleteRequest, GetRequest, KeyValue,
+            PutRequest, ScanRequest, Write,
+        },
+        NdbService, NulldbClient,
+    },
+    Db,
+};
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use tonic::transport::{server::TcpIncoming, Channel, Server};
+
+// Serves `db` on a free port in the background, and connects to it.
+async fn serve(db: Db, max_scan: usize) -> NulldbClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let service = NdbService::new(Arc::new(db)).with_max_scan(max_scan);
+    tokio::spawn(
+        Server::builder()
+            .add_service(service.into_server())
+            .serve_with_incoming(TcpIncoming::from(listener)),
+    );
+    NulldbClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+fn put(key: &str, value: &str) -> Write {
+    Write {
+        op: Some(Op::Put(PutRequest {
+            key: key.into(),
+            value: value.into(),
+        })),
+    }
+}
+
+fn keys(entries: &[KeyValue]) -> Vec<&[u8]> {
+    entries.iter().map(|entry| entry.key.as_slice()).collect()
+}
+
+#[tokio::test]
+async fn serves_reads_and_writes() {
+    let dir = TempDir::new().unwrap();
+    let mut client = serve(Db::new(dir.path()).await.unwrap(), 3).await;
+    let get = |key: &str| GetRequest { key: key.into() };
+
+    client
+        .put(PutRequest {
+            key: b"a".to_vec(),
+            value: b"1".to_vec(),
+        })
+        .await
+        .unwrap();
+    let value = client.get(get("a")).await.unwrap().into_inner().value;
+    assert_eq!(value.as_deref(), Some(&b"1"[..]));
+    client
+        .delete(DeleteRequest { key: b"a".to_vec() })
+        .await
+        .unwrap();
+    assert_eq!(client.get(get("a")).await.unwrap().into_inner().value, None);
+
+    let writes = ["b", "c", "d", "e", "f"].map(|key| put(key, "v")).to_vec();
+    client
+        .batch_write(BatchWriteRequest { writes })
+        .await
+        .unwrap();
+    let delete_range = Write {
+        op: Some(Op::DeleteRange(DeleteRangeRequest {
+            start: b"c".to_vec(),
+            end: b"e".to_vec(),
+        })),
+    };
+    let writes = vec![put("g", "v"), delete_range];
+    client
+        .batch_write(BatchWriteRequest { writes })
+        .await
+        .unwrap();
+
+    // Scans stop at the service's maximum.
+    let response = client
+        .scan(ScanRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(keys(&response.entries), [b"b", b"e", b"f"]);
+    assert!(response.more);
+    let request = ScanRequest {
+        start: Some(b"f\0".to_vec()),
+        ..ScanRequest::default()
+    };
+    let response = client.scan(request).await.unwrap().into_inner();
+    assert_eq!(keys(&response.entries), [b"g"]);
+    assert!(!response.more);
+
+    let status = client
+        .batch_write(BatchWriteRequest {
+            writes: vec![Write { op: None }],
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn streams_scans() {
+    let mut client = serve(
+        Db::with_options("db", nulldb::DbOptions::in_memory())
+            .await
+            .unwrap(),
+        10,
+    )
+    .await;
+    let writes = (0..2000).map(|i| put(&format!("{:04}", i), "v")).collect();
+    client
+        .batch_write(BatchWriteRequest { writes })
+        .await
+        .unwrap();
+
+    // Streamed scans aren't limited by the service's maximum.
+    let entries: Vec<_> = client
+        .stream_scan(ScanRequest::default())
+        .await
+        .unwrap()
+        .into_inner()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 2000);
+
+    let request = ScanRequest {
+        end: Some(b"1000".to_vec()),
+        limit: 2,
+        reverse: true,
+        ..ScanRequest::default()
+    };
+    let entries: Vec<_> = client
+        .stream_scan(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(keys(&entries), [b"0999", b"0998"]);
+}
+
+#[tokio::test]
+async fn ndb_server_serves_grpc() {
+    let dir = TempDir::new().unwrap();
+    let token_file = dir.path().join("token");
+    std::fs::write(&token_file, "s3cret").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ndb-server"))
+        .args(["--addr", "127.0.0.1:0", "--grpc-addr", "127.0.0.1:0"])
+        .arg("--auth-token-file")
+        .args([token_file, dir.path().join("db")])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    let addr = line.trim().strip_prefix("listening for grpc on ").unwrap();
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let get = GetRequest {
+        key: b"key".to_vec(),
+    };
+    let status = NulldbClient::new(channel.clone())
+        .get(get.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+    let mut client = NulldbClient::with_interceptor(channel, |mut request: tonic::Request<()>| {
+        let token = "Bearer s3cret".parse().unwrap();
+        request.metadata_mut().insert("authorization", token);
+        Ok(request)
+    });
+    client
+        .batch_write(BatchWriteRequest {
+            writes: vec![put("key", "value")],
+        })
+        .await
+        .unwrap();
+    let response = client.get(get).await.unwrap();
+    assert_eq!(response.into_inner().value.as_deref(), Some(&b"value"[..]));
+    let _ = child.kill();
+    let _ = child.wait();
+}
@@ -0,0 +1,56 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use nulldb::{Db, DbOptions, HandleKind, ManualClock, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn tracks_open_views_and_iterators() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let options = DbOptions {
+        clock: clock.clone(),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    for i in 0..4u8 {
+        db.put(&[i], b"value").await?;
+        db.flush_memtable().await?;
+    }
+
+    let view = db.freeze_view();
+    clock.advance(Duration::from_secs(60));
+    let iter = view.scan(..).await?;
+    let stats = db.stats().handles;
+    assert_eq!((stats.views, stats.iterators), (1, 1));
+    assert_eq!(stats.open[0].kind, HandleKind::View);
+    assert_eq!(stats.open[0].age, Duration::from_secs(60));
+    assert_eq!(stats.open[1].age, Duration::ZERO);
+    assert_eq!(stats.open[0].obsolete_bytes, 0);
+    // Backtraces are only captured with the `handle-backtraces` feature.
+    assert_eq!(
+        stats.open[0].backtrace.is_some(),
+        cfg!(feature = "handle-backtraces")
+    );
+
+    // Merging the tables leaves the view holding the only copy of the old
+    // ones.
+    assert_eq!(db.vacuum().await?, 3);
+    let stats = db.stats().handles;
+    assert!(stats.open[0].obsolete_bytes > 0);
+    assert_eq!(stats.open[0].obsolete_bytes, stats.open[1].obsolete_bytes);
+
+    let clone = view.clone();
+    drop(view);
+    assert_eq!(db.stats().handles.views, 1);
+    drop((clone, iter));
+    let scan = db.scan(..).await?;
+    let stats = db.stats().handles;
+    assert_eq!((stats.views, stats.iterators), (0, 1));
+    assert_eq!(stats.open[0].obsolete_bytes, 0);
+    drop(scan);
+    assert!(db.stats().handles.open.is_empty());
+    Ok(())
+}
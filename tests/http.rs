@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use nulldb::{
+    http::{router, HttpOptions},
+    BackupEngine, Db, DbOptions,
+};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+};
+
+// Serves `db` on a free port in the background, and returns the address.
+async fn serve(db: Db, options: HttpOptions) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let app = router(Arc::new(RwLock::new(db)), options);
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    addr
+}
+
+// Makes a request over a connection of its own, and returns the status and
+// the body, if there is one.
+async fn request(addr: &str, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let body = body.map_or(String::new(), |body| body.to_string());
+    let request = format!(
+        "{} {} HTTP/1.1\r\nhost: {}\r\nconnection: close\r\ncontent-type: application/json\r\n\
+         content-length: {}\r\n\r\n{}",
+        method,
+        path,
+        addr,
+        body.len(),
+        body
+    );
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    let body = serde_json::from_str(body).unwrap_or(Value::Null);
+    (status, body)
+}
+
+#[tokio::test]
+async fn serves_keys_and_scans() {
+    let db = Db::with_options("db", DbOptions::in_memory())
+        .await
+        .unwrap();
+    let options = HttpOptions {
+        max_scan: 2,
+        ..HttpOptions::default()
+    };
+    let addr = serve(db, options).await;
+
+    for key in ["a", "b", "c", "0x00ff"] {
+        let (status, _) = request(
+            &addr,
+            "PUT",
+            &format!("/kv/{}", key),
+            Some(json!({"value": key})),
+        )
+        .await;
+        assert_eq!(status, 204);
+    }
+    let (status, body) = request(&addr, "GET", "/kv/b", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, json!({"key": "b", "value": "b"}));
+    // Keys that aren't printable are in hex, however they were written.
+    let (_, body) = request(&addr, "GET", "/kv/0x00FF", None).await;
+    assert_eq!(body, json!({"key": "0x00ff", "value": "0x00ff"}));
+
+    assert_eq!(request(&addr, "DELETE", "/kv/b", None).await.0, 204);
+    let (status, body) = request(&addr, "GET", "/kv/b", None).await;
+    assert_eq!(status, 404);
+    assert!(body["error"].as_str().unwrap().contains("no key b"));
+    assert_eq!(request(&addr, "GET", "/kv/0xfff", None).await.0, 400);
+    // Non-ASCII where a hex digit should be.
+    assert_eq!(request(&addr, "GET", "/kv/0xa%C3%A9b", None).await.0, 400);
+
+    // Scans stop at the maximum.
+    let (_, body) = request(&addr, "GET", "/scan", None).await;
+    assert_eq!(
+        body["entries"],
+        json!([{"key": "0x00ff", "value": "0x00ff"}, {"key": "a", "value": "a"}])
+    );
+    assert_eq!(body["more"], true);
+    let (_, body) = request(&addr, "GET", "/scan?start=a%00&end=d&limit=10", None).await;
+    assert_eq!(body["entries"], json!([{"key": "c", "value": "c"}]));
+    assert_eq!(body["more"], false);
+}
+
+#[tokio::test]
+async fn runs_admin_commands() {
+    let dir = TempDir::new().unwrap();
+    let db = Db::new(dir.path().join("db")).await.unwrap();
+    let options = HttpOptions {
+        backup_dir: Some(dir.path().join("backups")),
+        ..HttpOptions::default()
+    };
+    let addr = serve(db, options).await;
+
+    // Enough small tables for a vacuum to merge them.
+    for i in 0..4 {
+        let path = format!("/kv/key-{}", i);
+        request(&addr, "PUT", &path, Some(json!({"value": "v"}))).await;
+        assert_eq!(request(&addr, "POST", "/flush", None).await.0, 204);
+    }
+    let (_, stats) = request(&addr, "GET", "/stats", None).await;
+    assert_eq!(stats["levels"][0]["tables"], 4);
+    let (status, body) = request(&addr, "POST", "/compact", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["tables_merged"], 3);
+
+    let (status, body) = request(&addr, "POST", "/backup", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["id"], 1);
+    let backups = BackupEngine::open(dir.path().join("backups"))
+        .await
+        .unwrap();
+    assert_eq!(backups.list_backups().await.unwrap().len(), 1);
+}
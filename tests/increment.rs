@@ -0,0 +1,56 @@
+use nulldb::{counter_merge_operator, Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+fn options() -> DbOptions {
+    DbOptions {
+        merge_operator: Some(counter_merge_operator()),
+        ..DbOptions::default()
+    }
+}
+
+#[tokio::test]
+async fn counts_across_writes_and_reopens() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    assert_eq!(db.increment(b"hits", 5).await?, 5);
+    assert_eq!(db.increment(b"hits", -2).await?, 3);
+    assert_eq!(db.get(b"hits").await?, Some(3i64.to_le_bytes().to_vec()));
+
+    let bumps = (0..100).map(|_| db.increment(b"hits", 1));
+    let mut seen = futures::future::try_join_all(bumps).await?;
+    seen.sort();
+    assert_eq!(seen, (4..=103).collect::<Vec<_>>());
+
+    // Other writes to a counter are picked up.
+    db.put(b"hits", &10i64.to_le_bytes()).await?;
+    assert_eq!(db.increment(b"hits", 1).await?, 11);
+    db.delete_range(b"a", b"z").await?;
+    assert_eq!(db.increment(b"hits", 1).await?, 1);
+    db.flush_memtable().await?;
+    assert_eq!(db.increment(b"hits", 1).await?, 2);
+
+    drop(db);
+    let db = Db::with_options(dir.path(), options()).await?;
+    assert_eq!(db.increment(b"hits", 1).await?, 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_other_values() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::with_options(dir.path(), options()).await?;
+    db.put(b"name", b"not a number").await?;
+    assert!(matches!(
+        db.increment(b"name", 1).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    assert_eq!(db.get(b"name").await?, Some(b"not a number".to_vec()));
+
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    assert!(matches!(
+        db.increment(b"hits", 1).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use nulldb::{Db, DbOptions, ManualClock, NdbError, SstWriter, VacuumOptions};
+use tempfile::TempDir;
+
+async fn write_sst(path: &Path, keys: impl IntoIterator<Item = u32>) -> Result<(), NdbError> {
+    let mut writer = SstWriter::create(path, &DbOptions::default()).await?;
+    for i in keys {
+        writer.put(&i.to_be_bytes(), b"ingested").await?;
+    }
+    writer.finish().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn ingests_tables_on_top() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let files = TempDir::new()?;
+    let mut db = Db::new(dir.path().join("db")).await?;
+    db.put(&5u32.to_be_bytes(), b"old").await?;
+    db.flush_memtable().await?;
+    db.put(&6u32.to_be_bytes(), b"unflushed").await?;
+    db.delete(&7u32.to_be_bytes()).await?;
+
+    let (a, b) = (files.path().join("a.sst"), files.path().join("b.sst"));
+    write_sst(&a, 0..10).await?;
+    let mut writer = SstWriter::create(&b, &DbOptions::default()).await?;
+    writer.put(&100u32.to_be_bytes(), b"ingested").await?;
+    writer.delete(&200u32.to_be_bytes()).await?;
+    writer.finish().await?;
+    db.put(&200u32.to_be_bytes(), b"old").await?;
+    db.ingest_sst([&b, &a]).await?;
+
+    for i in [0u32, 5, 6, 7, 9, 100] {
+        assert_eq!(db.get(&i.to_be_bytes()).await?, Some(b"ingested".to_vec()));
+    }
+    assert_eq!(db.get(&200u32.to_be_bytes()).await?, None);
+    // Writes after the ingest win over it.
+    db.put(&5u32.to_be_bytes(), b"new").await?;
+    assert_eq!(db.get(&5u32.to_be_bytes()).await?, Some(b"new".to_vec()));
+    assert!(a.exists());
+    drop(db);
+
+    let db = Db::new(dir.path().join("db")).await?;
+    assert_eq!(db.get(&5u32.to_be_bytes()).await?, Some(b"new".to_vec()));
+    assert_eq!(
+        db.get(&9u32.to_be_bytes()).await?,
+        Some(b"ingested".to_vec())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn comes_after_the_latest_write() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let files = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(&1u32.to_be_bytes(), b"old").await?;
+    let before = db.latest_sequence();
+    let token = db.scan_token(..);
+    let path = files.path().join("a.sst");
+    write_sst(&path, 0..10).await?;
+    db.ingest_sst([&path]).await?;
+
+    // Reads as of before the ingest can't see it, so can't be made at all.
+    assert_eq!(db.latest_sequence(), before + 1);
+    assert!(db.get_at(&1u32.to_be_bytes(), before).await.is_err());
+    assert!(db.scan_from_token(&token).await.is_err());
+    db.put(&2u32.to_be_bytes(), b"new").await?;
+    drop(db);
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.latest_sequence(), before + 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn vacuums_what_it_ingests() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let files = TempDir::new()?;
+    let options = DbOptions {
+        vacuum: Some(VacuumOptions {
+            min_tables: 3,
+            ..VacuumOptions::default()
+        }),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options.clone()).await?;
+    for n in 0..4 {
+        let path = files.path().join(format!("{}.sst", n));
+        write_sst(&path, n * 10..n * 10 + 10).await?;
+        db.ingest_sst([&path]).await?;
+    }
+    // Closing waits for the vacuum.
+    db.close().await?;
+
+    let db = Db::with_options(dir.path(), options).await?;
+    let tables: u64 = db.stats().levels.iter().map(|level| level.tables).sum();
+    assert!(tables < 4, "{} tables", tables);
+    for i in 0u32..40 {
+        assert_eq!(db.get(&i.to_be_bytes()).await?, Some(b"ingested".to_vec()));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_bad_files() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let files = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"k", b"v").await?;
+
+    let mut writer = SstWriter::create(files.path().join("x.sst"), &DbOptions::default()).await?;
+    writer.put(b"b", b"v").await?;
+    assert!(matches!(
+        writer.put(b"a", b"v").await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+
+    let (a, b) = (files.path().join("a.sst"), files.path().join("b.sst"));
+    write_sst(&a, 0..10).await?;
+    write_sst(&b, 9..20).await?;
+    assert!(matches!(
+        db.ingest_sst([&a, &b]).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    let empty = files.path().join("empty.sst");
+    write_sst(&empty, []).await?;
+    assert!(matches!(
+        db.ingest_sst([&empty]).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    std::fs::write(
+        files.path().join("junk.sst"),
+        b"not a table at all, not even close",
+    )?;
+    assert!(db
+        .ingest_sst([files.path().join("junk.sst")])
+        .await
+        .is_err());
+
+    // Nothing was added, and nothing's been flushed.
+    assert_eq!(db.stats().levels.len(), 0);
+    assert_eq!(db.get(&0u32.to_be_bytes()).await?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn exports_a_range_for_another_db() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let files = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+    let options = DbOptions {
+        clock: clock.clone(),
+        ..Default::default()
+    };
+    let mut from = Db::with_options(dir.path().join("from"), options.clone()).await?;
+    for i in 0u32..20 {
+        from.put(&i.to_be_bytes(), b"flushed").await?;
+    }
+    from.flush_memtable().await?;
+    from.put(&5u32.to_be_bytes(), b"unflushed").await?;
+    from.delete(&6u32.to_be_bytes()).await?;
+    from.put_with_ttl(&7u32.to_be_bytes(), b"expiring", Duration::from_secs(60))
+        .await?;
+
+    let path = files.path().join("export.sst");
+    let info = from
+        .export_range(&5u32.to_be_bytes(), &10u32.to_be_bytes(), &path)
+        .await?;
+    assert_eq!(info.entries, 4);
+    assert_eq!(info.smallest_key, Some(5u32.to_be_bytes().to_vec()));
+    assert_eq!(info.largest_key, Some(9u32.to_be_bytes().to_vec()));
+    assert_eq!(info.bytes, std::fs::metadata(&path)?.len());
+
+    let mut to = Db::with_options(dir.path().join("to"), options).await?;
+    to.ingest_sst([&path]).await?;
+    assert_eq!(to.get(&4u32.to_be_bytes()).await?, None);
+    assert_eq!(
+        to.get(&5u32.to_be_bytes()).await?,
+        Some(b"unflushed".to_vec())
+    );
+    assert_eq!(to.get(&6u32.to_be_bytes()).await?, None);
+    assert_eq!(
+        to.get(&9u32.to_be_bytes()).await?,
+        Some(b"flushed".to_vec())
+    );
+    assert_eq!(to.get(&10u32.to_be_bytes()).await?, None);
+    // Expiry times come along.
+    assert_eq!(
+        to.get(&7u32.to_be_bytes()).await?,
+        Some(b"expiring".to_vec())
+    );
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(to.get(&7u32.to_be_bytes()).await?, None);
+    Ok(())
+}
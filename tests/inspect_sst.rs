@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use nulldb::{inspect_sst, DbOptions, NdbError, SstValue, SstWriter};
+use tempfile::TempDir;
+
+async fn write_table(path: &Path, options: &DbOptions) -> Result<(), NdbError> {
+    let mut writer = SstWriter::create(path, options).await?;
+    for i in 0u32..300 {
+        match i % 10 {
+            0 => writer.delete(&i.to_be_bytes()).await?,
+            _ => writer.put(&i.to_be_bytes(), &[b'v'; 20]).await?,
+        }
+    }
+    writer.finish().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_layout_and_records() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("t.sst");
+    let options = DbOptions {
+        block_size: 512,
+        ..Default::default()
+    };
+    write_table(&path, &options).await?;
+
+    let report = inspect_sst(&path, &options, true).await?;
+    assert_eq!(report.bytes, std::fs::metadata(&path)?.len());
+    assert_eq!(report.entries, 300);
+    assert_eq!(report.smallest_key, Some(0u32.to_be_bytes().to_vec()));
+    assert_eq!(report.largest_key, Some(299u32.to_be_bytes().to_vec()));
+    assert!(report.blocks.len() > 1);
+    assert!(report.filter.bits >= 3000);
+    assert!(report.filter.probes > 0);
+    let mut next = report.index.offset;
+    for block in report.blocks.iter().rev() {
+        assert!(block.corrupt.is_none());
+        assert!(block.location.offset + block.location.size < next);
+        next = block.location.offset;
+    }
+    let records: Vec<_> = report.blocks.iter().flat_map(|b| &b.records).collect();
+    assert_eq!(records.len(), 300);
+    assert_eq!(records[0].value, SstValue::Delete);
+    assert_eq!(records[1].value, SstValue::Put(vec![b'v'; 20]));
+
+    let report = inspect_sst(&path, &options, false).await?;
+    assert!(report.blocks.iter().all(|block| block.records.is_empty()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_corrupt_blocks() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("t.sst");
+    let options = DbOptions {
+        block_size: 512,
+        ..Default::default()
+    };
+    write_table(&path, &options).await?;
+    let report = inspect_sst(&path, &options, false).await?;
+    let (second, lost) = (report.blocks[1].location, report.blocks[1].entries);
+
+    let mut data = std::fs::read(&path)?;
+    data[second.offset as usize + 3] ^= 0xff;
+    std::fs::write(&path, &data)?;
+    let report = inspect_sst(&path, &options, false).await?;
+    assert!(report.blocks[0].corrupt.is_none());
+    assert!(report.blocks[1].corrupt.is_some());
+    assert!(report.blocks[2].corrupt.is_none());
+    assert_eq!(report.entries, 300 - lost);
+
+    // Without a footer there's nothing to go on.
+    let len = data.len();
+    data[len - 1] ^= 0xff;
+    std::fs::write(&path, &data)?;
+    assert!(matches!(
+        inspect_sst(&path, &options, false).await,
+        Err(NdbError::Corruption { .. })
+    ));
+    Ok(())
+}
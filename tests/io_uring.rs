@@ -0,0 +1,46 @@
+#![cfg(target_os = "linux")]
+
+use nulldb::{Db, DbOptions, NdbError, WriteOptions};
+use tempfile::TempDir;
+
+fn options() -> DbOptions {
+    DbOptions {
+        io_uring: true,
+        ..DbOptions::default()
+    }
+}
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key-{:04}", i).into_bytes()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_tables_and_appends_to_the_log() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    for i in 0..500 {
+        db.put(&key(i), format!("value-{}", i).as_bytes()).await?;
+    }
+    db.flush_memtable().await?;
+    let sync = WriteOptions {
+        sync: true,
+        ..WriteOptions::default()
+    };
+    db.put_opt(&key(0), b"logged", &sync).await?;
+    db.delete(&key(1)).await?;
+    drop(db);
+
+    // The log's replayed and the table read back, through the ring and
+    // without it.
+    for options in [options(), DbOptions::default()] {
+        let db = Db::with_options(dir.path(), options).await?;
+        assert_eq!(db.get(&key(0)).await?, Some(b"logged".to_vec()));
+        assert_eq!(db.get(&key(1)).await?, None);
+        for i in 2..500 {
+            let value = format!("value-{}", i).into_bytes();
+            assert_eq!(db.get(&key(i)).await?, Some(value));
+        }
+        assert_eq!(db.get(b"missing").await?, None);
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use nulldb::{Db, DbOptions, MayExist, NdbError, ValueTransform};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn key_may_exist() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"flushed", b"1").await?;
+    db.put(b"deleted", b"1").await?;
+    db.flush_memtable().await?;
+    db.put(b"memtable", b"2").await?;
+    db.delete(b"deleted").await?;
+
+    assert_eq!(
+        db.key_may_exist(b"memtable")?,
+        MayExist::Maybe(Some(b"2".to_vec()))
+    );
+    assert_eq!(db.key_may_exist(b"flushed")?, MayExist::Maybe(None));
+    assert_eq!(db.key_may_exist(b"deleted")?, MayExist::DefinitelyNot);
+    // Outside the table's key range, so the bloom filter isn't even needed.
+    assert_eq!(db.key_may_exist(b"zzz")?, MayExist::DefinitelyNot);
+
+    db.delete_range(b"a", b"g").await?;
+    db.flush_memtable().await?;
+    assert_eq!(db.key_may_exist(b"flushed")?, MayExist::DefinitelyNot);
+    assert_eq!(db.key_may_exist(b"memtable")?, MayExist::Maybe(None));
+    Ok(())
+}
+
+struct Identity;
+
+impl ValueTransform for Identity {
+    fn id(&self) -> u32 {
+        1
+    }
+
+    fn encode(&self, _key: &[u8], value: &[u8]) -> Result<Vec<u8>, NdbError> {
+        Ok(value.to_vec())
+    }
+
+    fn decode(&self, _key: &[u8], encoded: &[u8]) -> Result<Vec<u8>, NdbError> {
+        Ok(encoded.to_vec())
+    }
+}
+
+#[tokio::test]
+async fn fails_on_values_it_cannot_decode() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"key", b"untransformed").await?;
+    drop(db);
+
+    // The stored value has no transform id, so it reads as corrupt.
+    let options = DbOptions {
+        value_transforms: vec![(b"key".to_vec(), Arc::new(Identity))],
+        ..DbOptions::default()
+    };
+    let db = Db::with_options(dir.path(), options).await?;
+    assert!(matches!(
+        db.get(b"key").await,
+        Err(NdbError::Corruption { .. })
+    ));
+    assert!(matches!(
+        db.key_may_exist(b"key"),
+        Err(NdbError::Corruption { .. })
+    ));
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use nulldb::{Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn one_writer_at_a_time() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"k", b"v").await?;
+    assert!(matches!(
+        Db::new(dir.path()).await,
+        Err(NdbError::AlreadyLocked)
+    ));
+
+    // Readers don't need the lock.
+    let reader = Db::open_read_only(dir.path(), DbOptions::default()).await?;
+    assert_eq!(reader.get(b"k").await?, Some(b"v".to_vec()));
+
+    drop(db);
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"k").await?, Some(b"v".to_vec()));
+    Ok(())
+}
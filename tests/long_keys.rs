@@ -0,0 +1,56 @@
+use nulldb::{Db, DbIterator, DbOptions, NdbError};
+use tempfile::TempDir;
+
+// Long keys that only differ well past where the index cuts them off, so
+// whole runs of blocks share an index key.
+fn key(i: usize) -> Vec<u8> {
+    let mut key = vec![b'p'; 100];
+    key.extend(format!("{:05}", i * 2).bytes());
+    key
+}
+
+async fn keys(mut iter: DbIterator) -> Result<Vec<Vec<u8>>, NdbError> {
+    let mut keys = Vec::new();
+    while let Some((key, _)) = iter.next().await? {
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+#[tokio::test]
+async fn truncated_index_keys() -> Result<(), NdbError> {
+    for index_key_len in [0, 8, 101, 103] {
+        let dir = TempDir::new()?;
+        let options = DbOptions {
+            block_size: 256,
+            max_index_key_len: Some(index_key_len),
+            ..DbOptions::default()
+        };
+        let mut db = Db::with_options(dir.path(), options).await?;
+        for i in 0..200 {
+            db.put(&key(i), format!("{}", i).as_bytes()).await?;
+        }
+        db.put(b"q", b"after").await?;
+        db.flush_memtable().await?;
+
+        for i in 0..200 {
+            assert_eq!(db.get(&key(i)).await?, Some(format!("{}", i).into_bytes()));
+            // The odd numbers fall between keys.
+            let mut missing = key(i);
+            *missing.last_mut().unwrap() += 1;
+            assert_eq!(db.get(&missing).await?, None);
+        }
+        assert_eq!(db.get(b"q").await?, Some(b"after".to_vec()));
+        assert_eq!(db.get(b"p").await?, None);
+
+        let all: Vec<_> = (0..200).map(key).collect();
+        assert_eq!(
+            keys(db.scan(key(50)..key(150)).await?).await?,
+            &all[50..150]
+        );
+        let mut rev = keys(db.scan_rev(key(50)..=key(150)).await?).await?;
+        rev.reverse();
+        assert_eq!(rev, &all[50..=150]);
+    }
+    Ok(())
+}
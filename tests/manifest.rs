@@ -0,0 +1,312 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use nulldb::{
+    Db, DbOptions, FileKind, LocalStorage, NdbError, ReadableFile, Storage, WritableFile,
+};
+use tempfile::TempDir;
+
+fn manifests(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with("MANIFEST"))
+        .collect();
+    names.sort();
+    names
+}
+
+fn current(dir: &Path) -> String {
+    std::fs::read_to_string(dir.join("CURRENT"))
+        .unwrap()
+        .trim()
+        .to_string()
+}
+
+fn tables(db: &Db) -> u64 {
+    db.stats().levels.iter().map(|level| level.tables).sum()
+}
+
+#[tokio::test]
+async fn records_flushes_and_vacuums() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    for i in 0..4 {
+        db.put(format!("key{}", i).as_bytes(), b"v").await?;
+        db.flush_memtable().await?;
+    }
+    let name = current(dir.path());
+    assert_eq!(manifests(dir.path()), [name.as_str()]);
+    // A header, a snapshot, and two edits per flush: one starting a new log
+    // for later writes, and one adding the table.
+    let lines = std::fs::read_to_string(dir.path().join(&name))?;
+    assert_eq!(lines.lines().count(), 10);
+    drop(db);
+
+    let mut db = Db::new(dir.path()).await?;
+    assert_eq!(tables(&db), 4);
+    db.vacuum().await?;
+    drop(db);
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(tables(&db), 1);
+    for i in 0..4 {
+        let key = format!("key{}", i);
+        assert_eq!(db.get(key.as_bytes()).await?, Some(b"v".to_vec()));
+    }
+    assert_eq!(manifests(dir.path()).len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rolls_over_to_a_new_manifest() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        max_manifest_bytes: 1,
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options.clone()).await?;
+    let first = current(dir.path());
+    for i in 0..3 {
+        db.put(format!("key{}", i).as_bytes(), b"v").await?;
+        db.flush_memtable().await?;
+    }
+    assert_ne!(current(dir.path()), first);
+    assert_eq!(manifests(dir.path()), [current(dir.path())]);
+    let lines = std::fs::read_to_string(dir.path().join(current(dir.path())))?;
+    assert_eq!(lines.lines().count(), 2);
+    drop(db);
+
+    let db = Db::with_options(dir.path(), options).await?;
+    assert_eq!(tables(&db), 3);
+    assert_eq!(db.get(b"key2").await?, Some(b"v".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn ignores_a_torn_last_edit() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    drop(db);
+
+    let path = dir.path().join(current(dir.path()));
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+    file.write_all(br#"[{"DeleteTable":"#)?;
+    drop(file);
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    Ok(())
+}
+
+// Local files, except that while `fail` is set, writes to a manifest only
+// get halfway before failing.
+#[derive(Default)]
+struct FailingManifest {
+    fail: Arc<AtomicBool>,
+}
+
+struct FailingWriter {
+    file: Box<dyn WritableFile>,
+    fail: Arc<AtomicBool>,
+}
+
+impl WritableFile for FailingWriter {
+    fn write<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, Result<(), NdbError>> {
+        async move {
+            if !self.fail.load(Ordering::SeqCst) {
+                return self.file.write(data).await;
+            }
+            self.file.write(&data[..data.len() / 2]).await?;
+            self.file.flush().await?;
+            Err(std::io::Error::other("injected failure").into())
+        }
+        .boxed()
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        self.file.flush()
+    }
+
+    fn sync(&mut self) -> BoxFuture<'_, Result<(), NdbError>> {
+        self.file.sync()
+    }
+}
+
+impl Storage for FailingManifest {
+    fn create<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        LocalStorage.create(path)
+    }
+
+    fn append<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        async move {
+            let file = LocalStorage.append(path).await?;
+            let name = path.file_name().unwrap().to_string_lossy();
+            if !name.starts_with("MANIFEST") {
+                return Ok(file);
+            }
+            let fail = self.fail.clone();
+            Ok(Box::new(FailingWriter { file, fail }) as Box<dyn WritableFile>)
+        }
+        .boxed()
+    }
+
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Arc<dyn ReadableFile>, NdbError>> {
+        LocalStorage.open(path)
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        LocalStorage.rename(from, to)
+    }
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        LocalStorage.remove(path)
+    }
+
+    fn truncate<'a>(&'a self, path: &'a Path, len: u64) -> BoxFuture<'a, Result<(), NdbError>> {
+        LocalStorage.truncate(path, len)
+    }
+
+    fn list<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Vec<PathBuf>, NdbError>> {
+        LocalStorage.list(dir)
+    }
+
+    fn size<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<u64, NdbError>> {
+        LocalStorage.size(path)
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool, NdbError>> {
+        LocalStorage.exists(path)
+    }
+
+    fn create_dir_all<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        LocalStorage.create_dir_all(dir)
+    }
+}
+
+// A record that fails partway through doesn't leave the manifest unreadable:
+// the next one starts a new manifest rather than being joined onto it.
+#[tokio::test]
+async fn starts_over_after_a_failed_edit() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let storage = Arc::new(FailingManifest::default());
+    let options = DbOptions {
+        storage: storage.clone(),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    let before = current(dir.path());
+
+    storage.fail.store(true, Ordering::SeqCst);
+    db.put(b"b", b"2").await?;
+    assert!(db.flush_memtable().await.is_err());
+    storage.fail.store(false, Ordering::SeqCst);
+    db.put(b"c", b"3").await?;
+    db.flush_memtable().await?;
+    assert_ne!(current(dir.path()), before);
+    assert_eq!(manifests(dir.path()), [current(dir.path())]);
+    drop(db);
+
+    let db = Db::new(dir.path()).await?;
+    for (key, value) in [(b"a", b"1"), (b"b", b"2"), (b"c", b"3")] {
+        assert_eq!(db.get(key).await?, Some(value.to_vec()));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn corrupt_edits_fail_the_open() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    drop(Db::new(dir.path()).await?);
+
+    let path = dir.path().join(current(dir.path()));
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+    file.write_all(b"not json\n")?;
+    drop(file);
+
+    match Db::new(dir.path()).await {
+        Err(NdbError::Corruption { file, offset, .. }) => {
+            assert_eq!(file, Some(path));
+            assert!(offset.is_some());
+        }
+        Err(err) => panic!("expected corruption, got {:?}", err),
+        Ok(_) => panic!("expected corruption"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn lists_the_manifest_as_live() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let files = db.live_files().await?;
+    let meta: Vec<_> = files
+        .iter()
+        .filter(|f| f.kind == FileKind::DbMeta)
+        .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(meta, ["CURRENT", "MANIFEST-000001"]);
+    Ok(())
+}
+
+// A database from before the manifest, with its tables listed in meta.json
+// and described in .meta files.
+#[tokio::test]
+async fn moves_old_databases_to_a_manifest() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+    drop(db);
+
+    let name = current(dir.path());
+    let manifest = std::fs::read_to_string(dir.path().join(&name))?;
+    // The edit recording the flush.
+    let edits: serde_json::Value = serde_json::from_str(manifest.lines().last().unwrap())?;
+    let table = &edits[0]["AddTable"];
+    let data_path = Path::new(table["data_path"].as_str().unwrap());
+    let meta_path = data_path.with_extension("meta");
+    std::fs::write(&meta_path, table.to_string())?;
+    let meta = serde_json::json!({
+        "sstables": [meta_path],
+        "wal": edits[1]["Wals"]["wal"],
+    });
+    std::fs::write(dir.path().join("meta.json"), meta.to_string())?;
+    std::fs::remove_file(dir.path().join("CURRENT"))?;
+    std::fs::remove_file(dir.path().join(&name))?;
+
+    let db = Db::open_read_only(dir.path(), DbOptions::default()).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    let kinds: Vec<_> = db.live_files().await?.iter().map(|f| f.kind).collect();
+    assert!(kinds.contains(&FileKind::TableMeta));
+    drop(db);
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(db.get(b"b").await?, Some(b"2".to_vec()));
+    assert!(dir.path().join("CURRENT").exists());
+    assert!(!dir.path().join("meta.json").exists());
+    assert!(!meta_path.exists());
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use nulldb::{Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn rejects_writes_over_the_limit() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        max_total_bytes: Some(1000),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options.clone()).await?;
+    let value = [b'x'; 100];
+    let mut written = 0;
+    let err = loop {
+        match db.put(format!("{:03}", written).as_bytes(), &value).await {
+            Ok(()) => written += 1,
+            Err(err) => break err,
+        }
+    };
+    assert!(matches!(err, NdbError::QuotaExceeded { limit: 1000, .. }));
+    assert!(written > 0);
+    assert!(db.total_bytes() > 1000);
+    assert_eq!(db.get(b"000").await?, Some(value.to_vec()));
+
+    assert!(matches!(
+        db.delete(b"000").await,
+        Err(NdbError::QuotaExceeded { .. })
+    ));
+
+    // Tables are more compact than the log, so flushing makes some room.
+    let used = db.total_bytes();
+    db.flush_memtable().await?;
+    assert!(db.total_bytes() < used);
+    db.put(b"more", &value).await?;
+    drop(db);
+
+    let db = Db::with_options(dir.path(), options).await?;
+    assert_eq!(db.get(b"more").await?, Some(value.to_vec()));
+    assert!(db.total_bytes() > 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn compacting_an_overlay_frees_it() -> Result<(), NdbError> {
+    let base = TempDir::new()?;
+    let delta = TempDir::new()?;
+    Db::new(base.path()).await?;
+    let options = DbOptions {
+        max_total_bytes: Some(500),
+        ..DbOptions::default()
+    };
+    let mut db = Db::open_overlay(base.path(), delta.path(), options).await?;
+    let empty = db.total_bytes();
+    while db.put(b"k", &[b'x'; 100]).await.is_ok() {}
+    db.compact_into_base().await?;
+    assert_eq!(db.total_bytes(), empty);
+    db.put(b"k", b"small").await?;
+    Ok(())
+}
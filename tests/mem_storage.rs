@@ -0,0 +1,43 @@
+use std::{path::Path, sync::Arc};
+
+use nulldb::{Db, DbOptions, MemStorage, NdbError, Storage, VacuumOptions};
+
+// Somewhere that doesn't exist on disk, so anything written outside the
+// storage would fail.
+const DIR: &str = "/nulldb-in-memory/db";
+
+#[tokio::test]
+async fn keeps_a_database_in_memory() -> Result<(), NdbError> {
+    let storage = Arc::new(MemStorage::new());
+    let options = DbOptions {
+        storage: storage.clone(),
+        vacuum: Some(VacuumOptions::default()),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(DIR, options.clone()).await?;
+    for i in 0..3u32 {
+        db.put(&i.to_be_bytes(), b"v").await?;
+        db.flush_memtable().await?;
+    }
+    db.vacuum().await?;
+    db.put(b"unflushed", b"v").await?;
+    drop(db);
+    assert!(!Path::new(DIR).exists());
+    assert!(storage.exists(&Path::new(DIR).join("CURRENT")).await?);
+    assert!(storage.size_bytes() > 0);
+
+    let db = Db::with_options(DIR, options).await?;
+    assert_eq!(db.get(&2u32.to_be_bytes()).await?, Some(b"v".to_vec()));
+    assert_eq!(db.get(b"unflushed").await?, Some(b"v".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn in_memory_databases_are_separate() -> Result<(), NdbError> {
+    let a = Db::with_options(DIR, DbOptions::in_memory()).await?;
+    let b = Db::with_options(DIR, DbOptions::in_memory()).await?;
+    a.put(b"k", b"a").await?;
+    assert_eq!(b.get(b"k").await?, None);
+    assert_eq!(a.get(b"k").await?, Some(b"a".to_vec()));
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use nulldb::{Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key-{:04}", i).into_bytes()
+}
+
+#[tokio::test]
+async fn accounts_for_memory_as_writes_come_in() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    assert_eq!(db.stats().memtable.memory_bytes, 0);
+
+    let mut last = 0;
+    for i in 0..1000 {
+        db.put(&key(i), &[b'v'; 1000]).await?;
+        let stats = db.stats().memtable;
+        assert!(stats.memory_bytes >= stats.bytes);
+        assert!(stats.memory_bytes >= last);
+        last = stats.memory_bytes;
+    }
+    // Not much more than the data itself once the blocks are large.
+    let bytes = db.stats().memtable.bytes;
+    assert!(last < bytes * 2, "{} bytes in {} of memory", bytes, last);
+
+    db.flush_memtable().await?;
+    assert_eq!(db.stats().memtable.memory_bytes, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn flushes_once_the_memory_is_used() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        write_buffer_size: Some(64 << 10),
+        ..DbOptions::default()
+    };
+    let db = Db::with_options(dir.path(), options).await?;
+    for i in 0..2000 {
+        db.put(&key(i), &[b'v'; 100]).await?;
+        // The memtable being written and the one being flushed, each of
+        // which can go past the limit by the block that filled it.
+        assert!(db.stats().memtable.memory_bytes < 4 * (64 << 10));
+    }
+    let tables: u64 = db.stats().levels.iter().map(|level| level.tables).sum();
+    assert!(tables > 1, "{} tables", tables);
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use nulldb::{Db, DbOptions, ManualClock, MergeOperator, NdbError};
+use tempfile::TempDir;
+
+// Adds up little-endian u64s.
+fn counter() -> MergeOperator {
+    Arc::new(|_key, existing, operands| {
+        let decode = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().unwrap());
+        let total = existing.map_or(0, decode) + operands.iter().map(|op| decode(op)).sum::<u64>();
+        total.to_le_bytes().to_vec()
+    })
+}
+
+fn options() -> DbOptions {
+    DbOptions {
+        merge_operator: Some(counter()),
+        ..DbOptions::default()
+    }
+}
+
+async fn count(db: &Db, key: &[u8]) -> Result<Option<u64>, NdbError> {
+    let value = db.get(key).await?;
+    Ok(value.map(|value| u64::from_le_bytes(value.try_into().unwrap())))
+}
+
+async fn add(db: &Db, key: &[u8], n: u64) -> Result<(), NdbError> {
+    db.merge(key, &n.to_le_bytes()).await
+}
+
+#[tokio::test]
+async fn merges_fold_across_tables() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+
+    db.put(b"hits", &10u64.to_le_bytes()).await?;
+    db.flush_memtable().await?;
+    add(&db, b"hits", 1).await?;
+    add(&db, b"hits", 2).await?;
+    db.flush_memtable().await?;
+    add(&db, b"hits", 3).await?;
+    // With nothing underneath, the first merge starts from no value.
+    add(&db, b"new", 5).await?;
+
+    assert_eq!(count(&db, b"hits").await?, Some(16));
+    assert_eq!(count(&db, b"new").await?, Some(5));
+    let values = db.multi_get(&[b"hits", b"new", b"none"]).await?;
+    assert_eq!(values[0], Some(16u64.to_le_bytes().to_vec()));
+    assert_eq!(values[2], None);
+
+    let mut iter = db.scan(..).await?;
+    assert_eq!(
+        iter.next().await?,
+        Some((b"hits".to_vec(), 16u64.to_le_bytes().to_vec()))
+    );
+    assert_eq!(
+        iter.next().await?,
+        Some((b"new".to_vec(), 5u64.to_le_bytes().to_vec()))
+    );
+    assert_eq!(iter.next().await?, None);
+
+    // Merges written since the last flush are replayed from the log.
+    drop(iter);
+    drop(db);
+    let db = Db::with_options(dir.path(), options()).await?;
+    assert_eq!(count(&db, b"hits").await?, Some(16));
+    Ok(())
+}
+
+#[tokio::test]
+async fn merges_start_over_after_deletes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+
+    add(&db, b"a", 1).await?;
+    add(&db, b"b", 1).await?;
+    db.flush_memtable().await?;
+    db.delete(b"a").await?;
+    add(&db, b"a", 7).await?;
+    db.delete_range(b"b", b"c").await?;
+    db.flush_memtable().await?;
+    add(&db, b"b", 9).await?;
+
+    assert_eq!(count(&db, b"a").await?, Some(7));
+    assert_eq!(count(&db, b"b").await?, Some(9));
+    Ok(())
+}
+
+// A put replaces every operand under it, wherever they are, and a vacuum
+// keeps only the put.
+#[tokio::test]
+async fn puts_replace_merges_underneath() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    add(&db, b"k", 1).await?;
+    for n in [2, 3, 4] {
+        db.flush_memtable().await?;
+        add(&db, b"k", n).await?;
+    }
+    assert_eq!(count(&db, b"k").await?, Some(10));
+
+    db.put(b"k", &7u64.to_le_bytes()).await?;
+    assert_eq!(count(&db, b"k").await?, Some(7));
+    db.flush_memtable().await?;
+    assert_eq!(db.vacuum().await?, 3);
+    assert_eq!(count(&db, b"k").await?, Some(7));
+    add(&db, b"k", 1).await?;
+    assert_eq!(count(&db, b"k").await?, Some(8));
+    Ok(())
+}
+
+// Merging into a value that has already expired starts from nothing, and
+// doesn't inherit the expiry.
+#[tokio::test]
+async fn merges_start_over_after_expiry() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+    let options = DbOptions {
+        clock: clock.clone(),
+        ..options()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+
+    let ttl = Duration::from_secs(10);
+    db.put_with_ttl(b"a", &5u64.to_le_bytes(), ttl).await?;
+    db.put_with_ttl(b"b", &5u64.to_le_bytes(), ttl).await?;
+    db.flush_memtable().await?;
+    db.put_with_ttl(b"a", &5u64.to_le_bytes(), ttl).await?;
+    db.put_with_ttl(b"c", &5u64.to_le_bytes(), ttl).await?;
+    // Before it expires, the merge keeps the expiry.
+    add(&db, b"c", 1).await?;
+    clock.advance(ttl);
+
+    // Folded as it's written, into a value in the memtable, and as it's
+    // read, into one in a table.
+    add(&db, b"a", 1).await?;
+    add(&db, b"b", 2).await?;
+    assert_eq!(count(&db, b"a").await?, Some(1));
+    assert_eq!(count(&db, b"b").await?, Some(2));
+    assert_eq!(count(&db, b"c").await?, None);
+    db.flush_memtable().await?;
+    clock.advance(ttl);
+    assert_eq!(count(&db, b"a").await?, Some(1));
+    assert_eq!(count(&db, b"b").await?, Some(2));
+    Ok(())
+}
+
+#[tokio::test]
+async fn compaction_folds_merges() -> Result<(), NdbError> {
+    let base = TempDir::new()?;
+    let delta = TempDir::new()?;
+    Db::with_options(base.path(), options()).await?;
+    let mut db = Db::open_overlay(base.path(), delta.path(), options()).await?;
+    add(&db, b"k", 2).await?;
+    db.flush_memtable().await?;
+    add(&db, b"k", 3).await?;
+    db.compact_into_base().await?;
+    drop(db);
+
+    // The base holds the folded value, so it reads fine without an operator.
+    let db = Db::new(base.path()).await?;
+    assert_eq!(count(&db, b"k").await?, Some(5));
+    Ok(())
+}
+
+#[tokio::test]
+async fn merging_needs_an_operator() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    assert!(matches!(
+        db.merge(b"k", b"x").await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    drop(db);
+
+    let db = Db::with_options(dir.path(), options()).await?;
+    add(&db, b"k", 1).await?;
+    drop(db);
+    assert!(matches!(
+        Db::new(dir.path()).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use nulldb::{ConflictResolution, Db, MergeIterator, NdbError};
+use tempfile::TempDir;
+
+// Three databases, each with "a" and "c", and "b" only in the second.
+async fn dbs(dir: &TempDir) -> Result<Vec<Db>, NdbError> {
+    let mut dbs = Vec::new();
+    for name in ["one", "two", "three"] {
+        let db = Db::new(dir.path().join(name)).await?;
+        db.put(b"a", name.as_bytes()).await?;
+        db.put(b"c", name.as_bytes()).await?;
+        if name == "two" {
+            db.put(b"b", name.as_bytes()).await?;
+        }
+        dbs.push(db);
+    }
+    Ok(dbs)
+}
+
+async fn merged(
+    dbs: &[Db],
+    resolution: ConflictResolution,
+    reverse: bool,
+) -> Result<Vec<(String, String)>, NdbError> {
+    let mut iters = Vec::new();
+    for db in dbs {
+        iters.push(match reverse {
+            true => db.scan_rev(..).await?,
+            false => db.scan(..).await?,
+        });
+    }
+    let mut iter = MergeIterator::new(iters, resolution);
+    let mut pairs = Vec::new();
+    while let Some((key, value)) = iter.next().await? {
+        pairs.push((
+            String::from_utf8(key).unwrap(),
+            String::from_utf8(value).unwrap(),
+        ));
+    }
+    Ok(pairs)
+}
+
+fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+    let pair = |(key, value): &(&str, &str)| (key.to_string(), value.to_string());
+    expected.iter().map(pair).collect()
+}
+
+#[tokio::test]
+async fn first_wins() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let dbs = dbs(&dir).await?;
+    let expected = pairs(&[("a", "one"), ("b", "two"), ("c", "one")]);
+    assert_eq!(
+        merged(&dbs, ConflictResolution::FirstWins, false).await?,
+        expected
+    );
+    let reversed: Vec<_> = expected.into_iter().rev().collect();
+    assert_eq!(
+        merged(&dbs, ConflictResolution::FirstWins, true).await?,
+        reversed
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn last_wins() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let dbs = dbs(&dir).await?;
+    let expected = pairs(&[("a", "three"), ("b", "two"), ("c", "three")]);
+    assert_eq!(
+        merged(&dbs, ConflictResolution::LastWins, false).await?,
+        expected
+    );
+    let reversed: Vec<_> = expected.into_iter().rev().collect();
+    assert_eq!(
+        merged(&dbs, ConflictResolution::LastWins, true).await?,
+        reversed
+    );
+    Ok(())
+}
+
+// The resolver sees every value in iterator order, can drop the key, and
+// isn't called for keys only one iterator has.
+#[tokio::test]
+async fn custom_resolves_conflicts() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let dbs = dbs(&dir).await?;
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let resolution = ConflictResolution::Custom(Arc::new(move |key, values| {
+        counted.fetch_add(1, Ordering::Relaxed);
+        (key != b"c").then(|| values.join(&b"+"[..]))
+    }));
+    let expected = pairs(&[("a", "one+two+three"), ("b", "two")]);
+    assert_eq!(merged(&dbs, resolution, false).await?, expected);
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+    Ok(())
+}
+
+#[tokio::test]
+#[should_panic(expected = "can't merge forward and reverse iterators")]
+async fn rejects_mixed_directions() {
+    let dir = TempDir::new().unwrap();
+    let dbs = dbs(&dir).await.unwrap();
+    let iters = vec![
+        dbs[0].scan(..).await.unwrap(),
+        dbs[1].scan_rev(..).await.unwrap(),
+    ];
+    MergeIterator::new(iters, ConflictResolution::FirstWins);
+}
@@ -0,0 +1,41 @@
+use nulldb::{Db, DbOptions, NdbError, WriteBatch, WriteCounters};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn counts_writes_under_the_longest_prefix() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        metric_prefixes: vec![
+            b"user/".to_vec(),
+            b"user/admin/".to_vec(),
+            b"order/".to_vec(),
+        ],
+        ..DbOptions::default()
+    };
+    let db = Db::with_options(dir.path(), options).await?;
+    let mut batch = WriteBatch::new();
+    batch.put(b"user/1", b"abc");
+    batch.put(b"user/admin/1", b"x");
+    batch.delete(b"order/9");
+    batch.put(b"other", b"y");
+    db.write(batch).await?;
+    db.put(b"user/2", b"v").await?;
+
+    let writes = db.stats().writes;
+    let counters = |batches, puts, deletes, bytes| WriteCounters {
+        batches,
+        puts,
+        deletes,
+        bytes,
+    };
+    assert_eq!(writes.total, counters(2, 4, 1, 9 + 13 + 7 + 6 + 7));
+    assert_eq!(
+        writes.by_prefix,
+        [
+            (b"user/".to_vec(), counters(2, 2, 0, 9 + 7)),
+            (b"user/admin/".to_vec(), counters(1, 1, 0, 13)),
+            (b"order/".to_vec(), counters(1, 0, 1, 7)),
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use nulldb::{metrics, Db, DbOptions, NdbError, VacuumOptions, WriteOptions};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn reports_flushes_compactions_and_latencies() -> Result<(), NdbError> {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    recorder.install().unwrap();
+
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        vacuum: Some(VacuumOptions {
+            min_tables: 2,
+            ..VacuumOptions::default()
+        }),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    let sync = WriteOptions {
+        sync: true,
+        ..WriteOptions::default()
+    };
+    db.put_opt(b"a", b"1", &sync).await?;
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+    // The second flush vacuums the two tables into one.
+    db.flush_memtable().await?;
+    db.get(b"a").await?;
+
+    let mut counters = BTreeMap::new();
+    let mut histograms = BTreeMap::new();
+    for (key, _, _, value) in snapshotter.snapshot().into_vec() {
+        let name = key.key().name().to_string();
+        match value {
+            DebugValue::Counter(n) => counters.insert(name, n),
+            DebugValue::Histogram(values) => histograms.insert(name, values.len() as u64),
+            DebugValue::Gauge(_) => None,
+        };
+    }
+    assert_eq!(counters[metrics::FLUSHES], 2);
+    let table_bytes = db.stats().levels[0].bytes;
+    assert!(counters[metrics::FLUSH_BYTES] > 0);
+    assert_eq!(counters[metrics::COMPACTION_BYTES], table_bytes);
+    assert_eq!(histograms[metrics::WRITE_SECONDS], 2);
+    assert_eq!(histograms[metrics::GET_SECONDS], 1);
+    assert!(histograms[metrics::WAL_SYNC_SECONDS] >= 1);
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use nulldb::{Db, DbOptions, MmapAdvice, MmapOptions, NdbError};
+use tempfile::TempDir;
+
+fn options(max_table_bytes: Option<u64>) -> DbOptions {
+    DbOptions {
+        max_open_files: 1,
+        mmap: Some(MmapOptions {
+            max_table_bytes,
+            advice: Some(MmapAdvice::Random),
+        }),
+        ..DbOptions::default()
+    }
+}
+
+// Writes a table of `keys` keys per prefix.
+async fn write_tables(db: &mut Db, prefixes: &[&str], keys: u32) -> Result<(), NdbError> {
+    for prefix in prefixes {
+        for i in 0..keys {
+            let key = format!("{}{:04}", prefix, i);
+            db.put(key.as_bytes(), key.as_bytes()).await?;
+        }
+        db.flush_memtable().await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn reads_tables_from_the_mapping() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options(None)).await?;
+    write_tables(&mut db, &["a", "b", "c"], 200).await?;
+    drop(db);
+
+    let mut db = Db::with_options(dir.path(), options(None)).await?;
+    let stats = db.stats().io;
+    assert_eq!(stats.tables_mapped, 3);
+    assert_eq!(stats.table_files_open, 0);
+    for prefix in ["a", "b", "c"] {
+        for i in (0..200).step_by(7) {
+            let key = format!("{}{:04}", prefix, i);
+            assert_eq!(db.get(key.as_bytes()).await?, Some(key.into_bytes()));
+        }
+    }
+    assert_eq!(db.get(b"b9999").await?, None);
+    // Nothing's opened again, for all that there's only room for one file.
+    assert_eq!(db.stats().io.table_file_opens, stats.table_file_opens);
+
+    let mut iter = db.scan(b"b".to_vec()..b"c".to_vec()).await?;
+    let mut scanned = 0;
+    while iter.next().await?.is_some() {
+        scanned += 1;
+    }
+    assert_eq!(scanned, 200);
+
+    // A view still reads tables vacuumed away since.
+    let view = db.freeze_view();
+    db.vacuum().await?;
+    assert_eq!(view.get(b"a0100").await?, Some(b"a0100".to_vec()));
+    assert_eq!(db.get(b"c0199").await?, Some(b"c0199".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_maps_tables_up_to_the_limit() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    write_tables(&mut db, &["small"], 10).await?;
+    write_tables(&mut db, &["large"], 2000).await?;
+    drop(db);
+
+    let db = Db::with_options(dir.path(), options(Some(4096))).await?;
+    assert_eq!(db.stats().io.tables_mapped, 1);
+    assert_eq!(db.get(b"small0009").await?, Some(b"small0009".to_vec()));
+    assert_eq!(db.get(b"large1999").await?, Some(b"large1999".to_vec()));
+    Ok(())
+}
@@ -0,0 +1,195 @@
+// Applies random sequences of operations to a database and to a `BTreeMap`
+// model of it side by side, and checks every read agrees. Crashes come from
+// a `SimStorage`, between operations, and can lose anything not yet synced,
+// so after one the database has to hold what the model held after some
+// operation since the last sync. Failing sequences are shrunk to the fewest
+// operations that still fail; PROPTEST_CASES sets how many to try.
+
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use nulldb::{
+    Db, DbOptions, ManualClock, NdbError, SimFaults, SimStorage, VacuumOptions, WriteOptions,
+};
+use proptest::prelude::*;
+
+const DIR: &str = "/model/db";
+
+type Model = BTreeMap<Vec<u8>, Vec<u8>>;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Put { key: u8, value: Vec<u8>, sync: bool },
+    Delete(u8),
+    Scan { start: Option<u8>, end: Option<u8> },
+    Flush,
+    Compact,
+    Reopen,
+    Crash,
+}
+
+// Keys are drawn from a few, so later operations keep hitting the same ones.
+fn key(i: u8) -> Vec<u8> {
+    format!("key-{:02}", i).into_bytes()
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let key = 0..24u8;
+    prop_oneof![
+        8 => (key.clone(), prop::collection::vec(any::<u8>(), 0..300), any::<bool>())
+            .prop_map(|(key, value, sync)| Op::Put { key, value, sync }),
+        3 => key.clone().prop_map(Op::Delete),
+        2 => (prop::option::of(key.clone()), prop::option::of(key))
+            .prop_map(|(start, end)| Op::Scan { start, end }),
+        2 => Just(Op::Flush),
+        1 => Just(Op::Compact),
+        1 => Just(Op::Reopen),
+        1 => Just(Op::Crash),
+    ]
+}
+
+fn options(storage: &SimStorage) -> DbOptions {
+    DbOptions {
+        storage: Arc::new(storage.clone()),
+        clock: Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1 << 30))),
+        block_size: 256,
+        write_buffer_size: Some(2048),
+        wal_segment_bytes: Some(1024),
+        vacuum: Some(VacuumOptions {
+            min_tables: 3,
+            ..VacuumOptions::default()
+        }),
+        ..DbOptions::default()
+    }
+}
+
+async fn scan(
+    db: &Db,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    rev: bool,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>, NdbError> {
+    let mut iter = match rev {
+        false => db.scan((start, end)).await?,
+        true => db.scan_rev((start, end)).await?,
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = iter.next().await? {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+// Reads every key and the whole database both ways, and checks them against
+// the model.
+async fn check(db: &Db, model: &Model) -> Result<(), TestCaseError> {
+    for i in 0..24 {
+        let got = db.get(&key(i)).await.unwrap();
+        prop_assert_eq!(got.as_ref(), model.get(&key(i)), "get {}", i);
+    }
+    let mut expected: Vec<_> = model.clone().into_iter().collect();
+    let forward = scan(db, Bound::Unbounded, Bound::Unbounded, false);
+    prop_assert_eq!(&forward.await.unwrap(), &expected, "scan");
+    expected.reverse();
+    let backward = scan(db, Bound::Unbounded, Bound::Unbounded, true);
+    prop_assert_eq!(&backward.await.unwrap(), &expected, "scan_rev");
+    Ok(())
+}
+
+async fn contents(db: &Db) -> Model {
+    let all = scan(db, Bound::Unbounded, Bound::Unbounded, false);
+    all.await.unwrap().into_iter().collect()
+}
+
+async fn run(ops: Vec<Op>) -> Result<(), TestCaseError> {
+    let mut storage = SimStorage::new(0, SimFaults::default());
+    let mut db = Db::with_options(DIR, options(&storage)).await.unwrap();
+    let mut model = Model::new();
+    // What the model held after each operation since everything was last
+    // synced, any of which a crash could leave.
+    let mut since_sync = vec![model.clone()];
+    for op in ops {
+        match op {
+            Op::Put {
+                key: i,
+                value,
+                sync,
+            } => {
+                let options = WriteOptions {
+                    sync,
+                    ..WriteOptions::default()
+                };
+                db.put_opt(&key(i), &value, &options).await.unwrap();
+                model.insert(key(i), value);
+                if sync {
+                    since_sync.clear();
+                }
+            }
+            Op::Delete(i) => {
+                db.delete(&key(i)).await.unwrap();
+                model.remove(&key(i));
+                since_sync.clear();
+            }
+            Op::Scan { start, end } => {
+                let start = start.map_or(Bound::Unbounded, |i| Bound::Included(key(i)));
+                let end = end.map_or(Bound::Unbounded, |i| Bound::Excluded(key(i)));
+                if let (Bound::Included(start), Bound::Excluded(end)) = (&start, &end) {
+                    if start > end {
+                        continue;
+                    }
+                }
+                let expected: Vec<_> = model
+                    .range((start.clone(), end.clone()))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                let got = scan(&db, start, end, false).await.unwrap();
+                prop_assert_eq!(got, expected);
+            }
+            Op::Flush => {
+                db.flush_memtable().await.unwrap();
+                since_sync.clear();
+            }
+            Op::Compact => {
+                db.vacuum().await.unwrap();
+            }
+            Op::Reopen => {
+                db.close().await.unwrap();
+                db = Db::with_options(DIR, options(&storage)).await.unwrap();
+                since_sync.clear();
+            }
+            Op::Crash => {
+                storage.crash();
+                drop(db);
+                storage = storage.restart();
+                db = Db::with_options(DIR, options(&storage)).await.unwrap();
+                let recovered = contents(&db).await;
+                prop_assert!(
+                    since_sync.contains(&recovered) || recovered == model,
+                    "recovered {:?} isn't what any write since the last sync left",
+                    recovered.keys().collect::<Vec<_>>()
+                );
+                model = recovered;
+                since_sync.clear();
+            }
+        }
+        since_sync.push(model.clone());
+        check(&db, &model).await?;
+    }
+    db.close().await.unwrap();
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn matches_a_btreemap(ops in prop::collection::vec(op(), 1..80)) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(run(ops))?;
+    }
+}
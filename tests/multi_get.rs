@@ -0,0 +1,43 @@
+use nulldb::{Db, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn reads_the_memtable_and_tables_at_once() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"old", b"table").await?;
+    db.put(b"overwritten", b"table").await?;
+    db.put(b"deleted", b"table").await?;
+    db.flush_memtable().await?;
+    db.put(b"older", b"table").await?;
+    db.flush_memtable().await?;
+    db.put(b"new", b"memtable").await?;
+    db.put(b"overwritten", b"memtable").await?;
+    db.delete(b"deleted").await?;
+
+    let keys: [&[u8]; 7] = [
+        b"new",
+        b"missing",
+        b"old",
+        b"overwritten",
+        b"deleted",
+        b"older",
+        b"new",
+    ];
+    let values = db.multi_get(&keys).await?;
+    let expected: [Option<&[u8]>; 7] = [
+        Some(b"memtable"),
+        None,
+        Some(b"table"),
+        Some(b"memtable"),
+        None,
+        Some(b"table"),
+        Some(b"memtable"),
+    ];
+    assert_eq!(values, expected.map(|value| value.map(<[u8]>::to_vec)));
+    for (key, value) in keys.iter().zip(values) {
+        assert_eq!(db.get(key).await?, value);
+    }
+    assert!(db.multi_get(&[]).await?.is_empty());
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::{
+    path::Path,
+    process::{Command, Output},
+    time::Duration,
+};
+
+use nulldb::{Db, DbOptions, NdbError, SstWriter};
+use tempfile::TempDir;
+
+fn ndb(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ndb"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(args: &[&str]) -> String {
+    let output = ndb(args);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn commands_work_on_a_database_directory() -> Result<(), NdbError> {
+    let dir = TempDir::new().unwrap();
+    let dir = dir.path().to_str().unwrap();
+    stdout(&["put", dir, "a", "1"]);
+    stdout(&["put", dir, "0x0001", "two"]);
+    stdout(&["put", dir, "c", "3"]);
+    stdout(&["delete", dir, "c"]);
+    stdout(&["flush", dir]);
+
+    assert_eq!(stdout(&["get", dir, "a"]), "\"1\"\n");
+    assert_eq!(stdout(&["get", dir, "c"]), "(not found)\n");
+    assert_eq!(stdout(&["scan", dir]), "0x0001 = \"two\"\n\"a\" = \"1\"\n");
+    assert_eq!(
+        stdout(&["--limit", "1", "scan", dir, "a"]),
+        "\"a\" = \"1\"\n"
+    );
+    assert!(stdout(&["compact", dir]).starts_with("merged "));
+    assert!(stdout(&["verify", dir]).starts_with("checked 1 tables"));
+    std::fs::write(Path::new(dir).join("123.sst"), b"orphan")?;
+    let verify = ndb(&["verify", dir]);
+    assert_eq!(verify.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&verify.stdout).contains("orphan file"));
+    // Repair moves the unreadable table out of the way.
+    assert!(stdout(&["repair", dir]).contains("123.sst to lost"));
+    assert!(stdout(&["verify", dir]).starts_with("checked 1 tables"));
+    assert_eq!(stdout(&["get", dir, "a"]), "\"1\"\n");
+
+    // Bad arguments are a usage error.
+    assert_eq!(ndb(&["get", dir]).status.code(), Some(2));
+    assert_eq!(ndb(&["get", dir, "0x1"]).status.code(), Some(2));
+    assert_eq!(ndb(&["get", dir, "0xaéb"]).status.code(), Some(2));
+    Ok(())
+}
+
+#[test]
+fn prints_json() {
+    let dir = TempDir::new().unwrap();
+    let dir = dir.path().to_str().unwrap();
+    stdout(&["put", dir, "a", "1"]);
+    stdout(&["put", dir, "b", "0x00ff"]);
+
+    let get: serde_json::Value =
+        serde_json::from_str(&stdout(&["--json", "get", dir, "a"])).unwrap();
+    assert_eq!(get, serde_json::json!({ "key": "a", "value": "1" }));
+    let missing: serde_json::Value =
+        serde_json::from_str(&stdout(&["--json", "get", dir, "z"])).unwrap();
+    assert_eq!(missing["value"], serde_json::Value::Null);
+
+    let scan = stdout(&["--json", "--format", "hex", "scan", dir]);
+    let entries: Vec<serde_json::Value> = scan
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            serde_json::json!({ "key": "0x61", "value": "0x31" }),
+            serde_json::json!({ "key": "0x62", "value": "0x00ff" }),
+        ]
+    );
+
+    let stats: serde_json::Value =
+        serde_json::from_str(&stdout(&["--json", "stats", dir])).unwrap();
+    assert_eq!(stats["memtable"]["keys"], 2);
+}
+
+#[tokio::test]
+async fn dumps_and_loads() -> Result<(), NdbError> {
+    let dirs = TempDir::new()?;
+    let from = dirs.path().join("from");
+    let db = Db::new(&from).await?;
+    db.put(b"0x looks like hex", b"\x00\xff").await?;
+    db.put_with_ttl(b"expiring", b"v", Duration::from_secs(3600))
+        .await?;
+    db.put(b"plain", b"value").await?;
+    db.delete(b"plain").await?;
+    db.put(b"z", b"last").await?;
+    db.close().await?;
+    let from = from.to_str().unwrap();
+
+    let json = dirs.path().join("dump.json");
+    stdout(&["dump", from, json.to_str().unwrap()]);
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(&json)?
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["value"], "0x00ff");
+    assert!(lines[1]["expires_at_ms"].is_u64());
+    let binary = dirs.path().join("dump.bin");
+    stdout(&["dump", "--binary", from, binary.to_str().unwrap()]);
+
+    for (name, dump) in [("json", &json), ("binary", &binary)] {
+        let to = dirs.path().join(name);
+        let to = to.to_str().unwrap();
+        let loaded = stdout(&["load", to, dump.to_str().unwrap()]);
+        assert_eq!(loaded, "loaded 3 entries\n");
+        assert_eq!(
+            stdout(&["--json", "dump", to]),
+            std::fs::read_to_string(&json)?
+        );
+        // Only into an empty database.
+        assert_eq!(
+            ndb(&["load", to, dump.to_str().unwrap()]).status.code(),
+            Some(1)
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn inspects_table_files() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("t.sst");
+    let mut writer = SstWriter::create(&path, &DbOptions::default()).await?;
+    writer.put(b"a", b"1").await?;
+    writer.delete(b"b").await?;
+    writer.finish().await?;
+    let path = path.to_str().unwrap();
+
+    let text = stdout(&["sst", "--records", path]);
+    assert!(text.contains("keys: 2 entries, \"a\" to \"b\""));
+    assert!(text.contains("    \"a\" = \"1\"\n    \"b\" deleted\n"));
+    let report: serde_json::Value =
+        serde_json::from_str(&stdout(&["sst", "--json", path])).unwrap();
+    assert_eq!(report["entries"], 2);
+    assert_eq!(report["blocks"][0]["last_key"], "b");
+    assert_eq!(report["blocks"][0]["records"], serde_json::Value::Null);
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::process::Command;
+
+use tempfile::TempDir;
+
+fn bench(args: &[&str]) -> Vec<serde_json::Value> {
+    let output = Command::new(env!("CARGO_BIN_EXE_ndb-bench"))
+        .args(args)
+        .arg("--json")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn runs_every_benchmark() {
+    let dir = TempDir::new().unwrap();
+    let db = dir.path().join("db");
+    let args = [
+        "--db",
+        db.to_str().unwrap(),
+        "--num",
+        "200",
+        "--reads",
+        "50",
+        "--tasks",
+        "3",
+        "--scan-length",
+        "10",
+        "--write-buffer-size",
+        "4096",
+    ];
+    let results = bench(&args);
+    let names: Vec<_> = results.iter().map(|r| r["benchmark"].clone()).collect();
+    assert_eq!(
+        names,
+        [
+            "fillseq",
+            "readrandom",
+            "fillrandom",
+            "readwhilewriting",
+            "scan"
+        ]
+    );
+    assert_eq!(results[0]["ops"], 200);
+    // Every key was written by fillseq.
+    assert_eq!(results[1]["ops"], 50);
+    assert_eq!(results[1]["found"], 50);
+    // Scans starting near the end of the key space run out early.
+    let scanned = results[4]["found"].as_u64().unwrap();
+    assert!(scanned > 0 && scanned <= 50 * 10);
+    let latency = &results[1]["latency_us"];
+    assert!(latency["p50"].as_f64() <= latency["p99"].as_f64());
+    assert!(latency["p99"].as_f64() <= latency["max"].as_f64());
+
+    // The database is kept, to run more benchmarks against.
+    let reads = bench(&[&args[..], &["--benchmarks", "readrandom"]].concat());
+    assert_eq!(reads[0]["found"], 50);
+}
+
+#[test]
+fn zipfian_keys_and_histograms() {
+    let results = bench(&[
+        "--benchmarks",
+        "fillrandom",
+        "--num",
+        "100",
+        "--distribution",
+        "zipfian",
+        "--histogram",
+    ]);
+    let buckets = results[0]["histogram"].as_array().unwrap();
+    let counted: u64 = buckets.iter().map(|b| b["count"].as_u64().unwrap()).sum();
+    assert_eq!(counted, 100);
+}
@@ -0,0 +1,205 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+use tempfile::TempDir;
+
+// A server on a free port, killed when it's dropped.
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    fn start(dir: &Path, args: &[&str]) -> Server {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ndb-server"))
+            .args(["--addr", "127.0.0.1:0"])
+            .args(args)
+            .arg(dir)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let addr = line.trim().strip_prefix("listening on ").unwrap().into();
+        Server { child, addr }
+    }
+
+    fn connect(&self) -> Client {
+        Client(TcpStream::connect(&self.addr).unwrap())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn get(key: &[u8]) -> Vec<u8> {
+    let mut buf = vec![1];
+    put_bytes(&mut buf, key);
+    buf
+}
+
+fn put(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut buf = vec![2];
+    put_bytes(&mut buf, key);
+    put_bytes(&mut buf, value);
+    buf
+}
+
+fn delete(key: &[u8]) -> Vec<u8> {
+    let mut buf = vec![3];
+    put_bytes(&mut buf, key);
+    buf
+}
+
+fn scan(start: &[u8], limit: u32) -> Vec<u8> {
+    let mut buf = vec![4, 1];
+    put_bytes(&mut buf, start);
+    buf.extend_from_slice(&limit.to_le_bytes());
+    buf
+}
+
+type Entries = Vec<(Vec<u8>, Vec<u8>)>;
+
+struct Client(TcpStream);
+
+impl Client {
+    fn send(&mut self, request: &[u8]) {
+        let mut frame = (request.len() as u32).to_le_bytes().to_vec();
+        frame.extend_from_slice(request);
+        self.0.write_all(&frame).unwrap();
+    }
+
+    fn receive(&mut self) -> Vec<u8> {
+        let mut len = [0; 4];
+        self.0.read_exact(&mut len).unwrap();
+        let mut frame = vec![0; u32::from_le_bytes(len) as usize];
+        self.0.read_exact(&mut frame).unwrap();
+        frame
+    }
+
+    fn call(&mut self, request: &[u8]) -> Vec<u8> {
+        self.send(request);
+        self.receive()
+    }
+
+    // A scan's entries, and whether there are more.
+    fn scan(&mut self, start: &[u8], limit: u32) -> (Entries, bool) {
+        let response = self.call(&scan(start, limit));
+        assert_eq!(response[0], 0);
+        let more = response[1] == 1;
+        let mut rest = &response[6..];
+        let mut take = || {
+            let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+            let bytes = rest[4..4 + len].to_vec();
+            rest = &rest[4 + len..];
+            bytes
+        };
+        let count = u32::from_le_bytes(response[2..6].try_into().unwrap());
+        let entries = (0..count).map(|_| (take(), take())).collect();
+        (entries, more)
+    }
+}
+
+fn value(response: &[u8]) -> &[u8] {
+    assert_eq!(response[0], 0);
+    &response[5..]
+}
+
+#[test]
+fn serves_reads_and_writes() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path(), &["--max-scan", "3"]);
+    let mut client = server.connect();
+    for i in 0..5 {
+        let key = format!("key-{}", i);
+        assert_eq!(client.call(&put(key.as_bytes(), b"v")), [0]);
+    }
+    assert_eq!(value(&client.call(&get(b"key-2"))), b"v");
+    assert_eq!(client.call(&delete(b"key-2")), [0]);
+    assert_eq!(client.call(&get(b"key-2")), [1]);
+
+    // Scans are cut off at --max-scan, and carry on from after the last key.
+    let (entries, more) = client.scan(b"", 0);
+    let keys: Vec<_> = entries.iter().map(|(key, _)| key.as_slice()).collect();
+    assert_eq!(keys, [b"key-0", b"key-1", b"key-3"]);
+    assert!(more);
+    let (entries, more) = client.scan(b"key-3\0", 10);
+    assert_eq!(entries, [(b"key-4".to_vec(), b"v".to_vec())]);
+    assert!(!more);
+
+    // A bad request gets an error, and the connection carries on.
+    let response = client.call(&[9]);
+    assert_eq!(response[0], 2);
+    assert!(String::from_utf8_lossy(&response[5..]).contains("unknown request type"));
+    assert_eq!(value(&client.call(&get(b"key-4"))), b"v");
+
+    // It stops cleanly when asked to, even with a client still connected,
+    // and what's written is there when it's started again.
+    #[cfg(unix)]
+    {
+        let mut server = server;
+        let pid = server.child.id().to_string();
+        Command::new("kill").arg(&pid).status().unwrap();
+        assert!(server.child.wait().unwrap().success());
+    }
+    #[cfg(not(unix))]
+    drop(server);
+    let server = Server::start(dir.path(), &[]);
+    assert_eq!(value(&server.connect().call(&get(b"key-0"))), b"v");
+}
+
+#[test]
+fn serves_many_connections_at_once() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path(), &[]);
+    std::thread::scope(|scope| {
+        for t in 0..8 {
+            let mut client = server.connect();
+            scope.spawn(move || {
+                // Every request is sent before any response is read.
+                for i in 0..100 {
+                    client.send(&put(format!("{}-{:03}", t, i).as_bytes(), b"v"));
+                }
+                for _ in 0..100 {
+                    assert_eq!(client.receive(), [0]);
+                }
+            });
+        }
+    });
+    let (entries, more) = server.connect().scan(b"", 0);
+    assert_eq!(entries.len(), 800);
+    assert!(!more);
+}
+
+#[test]
+fn refuses_oversized_requests() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path(), &["--max-frame-bytes", "64"]);
+    let mut client = server.connect();
+    // Only the length goes out, since the server won't read any further.
+    client.0.write_all(&100u32.to_le_bytes()).unwrap();
+    let response = client.receive();
+    assert_eq!(response[0], 2);
+    assert!(String::from_utf8_lossy(&response[5..]).contains("over the limit of 64"));
+    // With the rest of the request unread, it can't carry on.
+    let mut rest = Vec::new();
+    client.0.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    let mut client = server.connect();
+    assert_eq!(client.call(&put(b"key", &[0; 10])), [0]);
+}
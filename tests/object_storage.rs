@@ -0,0 +1,111 @@
+use std::{path::Path, sync::Arc};
+
+use futures::TryStreamExt;
+use nulldb::{Db, DbOptions, NdbError, ObjectStorage, ObjectStorageOptions};
+use object_store::{memory::InMemory, ObjectStore};
+use tempfile::TempDir;
+
+fn options(storage: &Arc<ObjectStorage>) -> DbOptions {
+    DbOptions {
+        storage: storage.clone(),
+        ..DbOptions::default()
+    }
+}
+
+// The names of the tables in the bucket.
+async fn tables(store: &InMemory) -> Vec<String> {
+    let objects: Vec<_> = store.list(None).try_collect().await.unwrap();
+    objects
+        .iter()
+        .map(|o| o.location.filename().unwrap().to_string())
+        .collect()
+}
+
+fn has_tables(dir: &Path) -> bool {
+    walk(dir)
+        .iter()
+        .any(|f| f.extension().is_some_and(|ext| ext == "sst"))
+}
+
+fn walk(dir: &Path) -> Vec<std::path::PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flat_map(|entry| {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                walk(&path)
+            } else {
+                vec![path]
+            }
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn keeps_tables_in_the_bucket() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let cache = TempDir::new()?;
+    let store = Arc::new(InMemory::new());
+    let storage = |cache: &Path| {
+        let options = ObjectStorageOptions {
+            prefix: "tables".into(),
+            ..ObjectStorageOptions::default()
+        };
+        Arc::new(ObjectStorage::new(store.clone(), cache, options))
+    };
+
+    let objects = storage(cache.path());
+    let mut db = Db::with_options(dir.path(), options(&objects)).await?;
+    for i in 0..4u32 {
+        db.put(&i.to_be_bytes(), b"v").await?;
+        db.flush_memtable().await?;
+    }
+    assert_eq!(tables(&store).await.len(), 4);
+    db.vacuum().await?;
+    assert_eq!(tables(&store).await.len(), 1);
+    assert!(!has_tables(dir.path()));
+    assert!(has_tables(cache.path()));
+    drop(db);
+
+    // A new node, with the same directory but nothing cached.
+    let cache = TempDir::new()?;
+    let objects = storage(cache.path());
+    let db = Db::with_options(dir.path(), options(&objects)).await?;
+    assert_eq!(db.get(&2u32.to_be_bytes()).await?, Some(b"v".to_vec()));
+    assert!(has_tables(cache.path()));
+    assert!(objects.cached_bytes() > 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn evicts_from_the_cache() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let cache = TempDir::new()?;
+    let store = Arc::new(InMemory::new());
+    let options = ObjectStorageOptions {
+        cache_bytes: Some(1),
+        ..ObjectStorageOptions::default()
+    };
+    let objects = Arc::new(ObjectStorage::new(store.clone(), cache.path(), options));
+    let mut db = Db::with_options(dir.path(), self::options(&objects)).await?;
+    for i in 0..3u32 {
+        db.put(&i.to_be_bytes(), b"v").await?;
+        db.flush_memtable().await?;
+    }
+    // Only the newest table is left in the cache, but the others can still be
+    // read while they're open.
+    assert_eq!(
+        walk(cache.path())
+            .iter()
+            .filter(|f| f.extension().is_some_and(|ext| ext == "sst"))
+            .count(),
+        1
+    );
+    for i in 0..3u32 {
+        assert_eq!(db.get(&i.to_be_bytes()).await?, Some(b"v".to_vec()));
+    }
+    assert_eq!(tables(&store).await.len(), 3);
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use nulldb::{Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn opens_every_database_in_order() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let dirs: Vec<_> = (0..4)
+        .map(|i| dir.path().join(format!("shard-{}", i)))
+        .collect();
+    // Shard `i` has `i + 1` tables, and its number in its memtable.
+    for (i, path) in dirs.iter().enumerate() {
+        let mut db = Db::new(path).await?;
+        for table in 0..=i {
+            db.put(format!("key-{}", table).as_bytes(), b"flushed")
+                .await?;
+            db.flush_memtable().await?;
+        }
+        db.put(b"shard", i.to_string().as_bytes()).await?;
+    }
+    let options = DbOptions {
+        max_open_parallelism: 2,
+        ..DbOptions::default()
+    };
+
+    let mut dbs = Db::open_many(&dirs, options.clone()).await?;
+    assert_eq!(dbs.len(), 4);
+    for (i, db) in dbs.iter().enumerate() {
+        assert_eq!(db.get(b"shard").await?, Some(i.to_string().into_bytes()));
+        let open = db.stats().open;
+        assert_eq!(open.sstables.len(), i + 1);
+        assert!(open.sstables.iter().all(|t| t.path.starts_with(&dirs[i])));
+        assert!(open.total >= open.wal_replay);
+        assert_eq!(open.wal_records_dropped, 0);
+    }
+
+    // One that can't be opened fails them all, without holding on to the
+    // others.
+    let held = dbs.remove(2);
+    drop(dbs);
+    assert!(matches!(
+        Db::open_many(&dirs, options.clone()).await,
+        Err(NdbError::AlreadyLocked)
+    ));
+    drop(held);
+    assert_eq!(Db::open_many(&dirs, options).await?.len(), 4);
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use nulldb::{Db, NdbError};
+use tempfile::TempDir;
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key-{:04}", i).into_bytes()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn reads_one_table_from_many_tasks() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    for i in 0..1000 {
+        db.put(&key(i), format!("value-{}", i).as_bytes()).await?;
+    }
+    db.flush_memtable().await?;
+    let db = Arc::new(db);
+
+    let readers: Vec<_> = (0..8)
+        .map(|reader| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for i in (reader..1000).step_by(8) {
+                    let value = format!("value-{}", i).into_bytes();
+                    assert_eq!(db.get(&key(i)).await?, Some(value));
+                    assert_eq!(db.get(format!("missing-{}", i).as_bytes()).await?, None);
+                }
+                Ok::<_, NdbError>(())
+            })
+        })
+        .collect();
+    for reader in readers {
+        reader.await.unwrap()?;
+    }
+    assert!(db.stats().io.table_bytes_read > 0);
+    assert_eq!(db.stats().io.table_files_open, 1);
+    Ok(())
+}
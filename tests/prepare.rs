@@ -0,0 +1,120 @@
+use futures::StreamExt;
+use nulldb::{BatchOp, Db, DbOptions, NdbError, PreparedToken, WriteBatch};
+use tempfile::TempDir;
+
+fn batch(writes: &[(&[u8], &[u8])]) -> WriteBatch {
+    let mut batch = WriteBatch::new();
+    for (key, value) in writes {
+        batch.put(key, value);
+    }
+    batch
+}
+
+#[tokio::test]
+async fn commits_and_rolls_back() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let committed = db.prepare(batch(&[(b"a", b"1"), (b"b", b"2")])).await?;
+    let rolled_back = db.prepare(batch(&[(b"c", b"3")])).await?;
+    assert_ne!(committed, rolled_back);
+    assert_eq!(db.prepared(), [committed, rolled_back]);
+    assert_eq!(db.get(b"a").await?, None);
+
+    db.commit_prepared(committed).await?;
+    db.rollback_prepared(rolled_back).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(db.get(b"b").await?, Some(b"2".to_vec()));
+    assert_eq!(db.get(b"c").await?, None);
+    assert!(db.prepared().is_empty());
+
+    for token in [committed, rolled_back, PreparedToken::from_id(1000)] {
+        assert!(matches!(
+            db.commit_prepared(token).await,
+            Err(NdbError::NotFound(_))
+        ));
+        assert!(matches!(
+            db.rollback_prepared(token).await,
+            Err(NdbError::NotFound(_))
+        ));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn stays_prepared_across_reopening_and_flushes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"a", b"0").await?;
+    let token = db.prepare(batch(&[(b"a", b"1")])).await?;
+    let id = token.id();
+    drop(db);
+
+    let mut db = Db::new(dir.path()).await?;
+    assert_eq!(db.prepared(), [PreparedToken::from_id(id)]);
+    // The log it was prepared in is retired, but it was logged again.
+    db.flush_memtable().await?;
+    db.flush_memtable().await?;
+    assert_eq!(db.get(b"a").await?, Some(b"0".to_vec()));
+    drop(db);
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.prepared(), [token]);
+    let later = db.prepare(WriteBatch::new()).await?;
+    assert!(later.id() > id);
+    db.commit_prepared(token).await?;
+    db.rollback_prepared(later).await?;
+    drop(db);
+
+    let db = Db::new(dir.path()).await?;
+    assert!(db.prepared().is_empty());
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_commits_are_updates() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let committed = db.prepare(batch(&[(b"a", b"1")])).await?;
+    let rolled_back = db.prepare(batch(&[(b"b", b"2")])).await?;
+    db.rollback_prepared(rolled_back).await?;
+    db.commit_prepared(committed).await?;
+    db.put(b"c", b"3").await?;
+
+    let updates = db.updates_since(0)?;
+    futures::pin_mut!(updates);
+    let mut keys = Vec::new();
+    for _ in 0..2 {
+        let update = updates.next().await.unwrap()?;
+        for op in update.batch.ops() {
+            match op {
+                BatchOp::Put { key, .. } => keys.push(key.to_vec()),
+                op => panic!("unexpected {:?}", op),
+            }
+        }
+    }
+    assert_eq!(keys, [b"a", b"c"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn checks_batches_when_preparing() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let mut merge = WriteBatch::new();
+    merge.merge(b"k", b"+1");
+    assert!(matches!(
+        db.prepare(merge).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    assert!(db.prepared().is_empty());
+
+    db.prepare(batch(&[(b"k", b"v")])).await?;
+    let reader = Db::open_read_only(dir.path(), DbOptions::default()).await?;
+    assert_eq!(reader.prepared().len(), 1);
+    assert!(matches!(
+        reader.prepare(batch(&[(b"k", b"w")])).await,
+        Err(NdbError::ReadOnly)
+    ));
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::{sync::Arc, time::Instant};
+
+use nulldb::{Db, DbOptions, NdbError, RateLimiter};
+use tempfile::TempDir;
+
+const RATE: u64 = 1 << 20;
+
+fn options(limiter: &Arc<RateLimiter>) -> DbOptions {
+    DbOptions {
+        rate_limiter: Some(limiter.clone()),
+        ..DbOptions::default()
+    }
+}
+
+async fn fill(db: &mut Db, keys: u32) -> Result<(), NdbError> {
+    for i in 0..keys {
+        db.put(&i.to_be_bytes(), &[b'v'; 1000]).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn caps_table_writes_across_databases() -> Result<(), NdbError> {
+    let limiter = Arc::new(RateLimiter::new(RATE));
+    let dirs = [TempDir::new()?, TempDir::new()?];
+    let mut dbs = Vec::new();
+    for dir in &dirs {
+        let mut db = Db::with_options(dir.path(), options(&limiter)).await?;
+        fill(&mut db, 800).await?;
+        dbs.push(db);
+    }
+
+    let started = Instant::now();
+    let flushes = dbs.iter_mut().map(|db| db.flush_memtable());
+    futures::future::try_join_all(flushes).await?;
+    let elapsed = started.elapsed();
+
+    let written = limiter.bytes_written();
+    let table_bytes: u64 = dbs.iter().map(|db| db.stats().levels[0].bytes).sum();
+    assert_eq!(written, table_bytes);
+    // Everything but a tenth of a second's burst waited its turn.
+    let least = (written - RATE / 10) as f64 / RATE as f64;
+    assert!(elapsed.as_secs_f64() >= least, "{:?}", elapsed);
+    assert!(limiter.time_waited().as_secs_f64() >= least / 2.0);
+
+    assert_eq!(
+        dbs[1].get(&799u32.to_be_bytes()).await?,
+        Some(vec![b'v'; 1000])
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn limit_can_be_raised() -> Result<(), NdbError> {
+    let limiter = Arc::new(RateLimiter::new(1));
+    limiter.set_bytes_per_sec(1 << 40);
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options(&limiter)).await?;
+    fill(&mut db, 100).await?;
+    db.flush_memtable().await?;
+    assert!(limiter.time_waited().as_millis() < 100);
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use nulldb::{Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn reads_a_database_open_elsewhere() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+
+    let mut reader = Db::open_read_only(dir.path(), DbOptions::default()).await?;
+    assert_eq!(reader.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(reader.get(b"b").await?, Some(b"2".to_vec()));
+    assert_eq!(reader.latest_sequence(), 2);
+    assert!(matches!(
+        reader.put(b"c", b"3").await,
+        Err(NdbError::ReadOnly)
+    ));
+    assert!(matches!(reader.delete(b"a").await, Err(NdbError::ReadOnly)));
+    assert!(matches!(
+        reader.flush_memtable().await,
+        Err(NdbError::ReadOnly)
+    ));
+    assert!(matches!(reader.vacuum().await, Err(NdbError::ReadOnly)));
+
+    // It doesn't see later writes, and the writer carries on as before.
+    db.put(b"c", b"3").await?;
+    assert_eq!(reader.get(b"c").await?, None);
+    drop(db);
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"c").await?, Some(b"3".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn requires_an_existing_database() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let missing = dir.path().join("missing");
+    assert!(matches!(
+        Db::open_read_only(&missing, DbOptions::default()).await,
+        Err(NdbError::NotFound(_))
+    ));
+    assert!(!missing.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn catches_up_with_the_writer() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+
+    let reader = Db::open_read_only(dir.path(), DbOptions::default()).await?;
+    db.put(b"b", b"2").await?;
+    db.delete(b"a").await?;
+    assert_eq!(reader.get(b"b").await?, None);
+    reader.catch_up().await?;
+    assert_eq!(reader.get(b"a").await?, None);
+    assert_eq!(reader.get(b"b").await?, Some(b"2".to_vec()));
+    assert_eq!(reader.latest_sequence(), 3);
+
+    // The log it was following is gone after a flush, so it opens the new
+    // table instead.
+    db.put(b"c", b"3").await?;
+    db.flush_memtable().await?;
+    db.put(b"d", b"4").await?;
+    db.vacuum().await?;
+    reader.catch_up().await?;
+    assert_eq!(reader.get(b"c").await?, Some(b"3".to_vec()));
+    assert_eq!(reader.get(b"d").await?, Some(b"4".to_vec()));
+    assert_eq!(reader.latest_sequence(), 5);
+
+    // Nothing new is fine too.
+    reader.catch_up().await?;
+    assert_eq!(reader.latest_sequence(), 5);
+
+    assert!(matches!(
+        db.catch_up().await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn follows_the_writer() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let reader = Arc::new(Db::open_read_only(dir.path(), DbOptions::default()).await?);
+    let follower = Db::follow(&reader, Duration::from_millis(10));
+
+    db.put(b"a", b"1").await?;
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while reader.get(b"a").await?.is_none() {
+        assert!(Instant::now() < deadline, "never caught up");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // It stops once the database is dropped.
+    drop(reader);
+    tokio::time::timeout(Duration::from_secs(5), follower)
+        .await
+        .unwrap()
+        .unwrap();
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::io::Write;
+
+use nulldb::{Db, DbOptions, FileKind, NdbError, VacuumOptions};
+use tempfile::TempDir;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key-{:03}", i).into_bytes()
+}
+
+// A database with two tables, the newer overwriting some of the older, and
+// writes in its log that haven't been flushed.
+async fn fill(dir: &TempDir) -> Result<Db, NdbError> {
+    let options = DbOptions {
+        block_size: 64,
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    for i in 0..50 {
+        db.put(&key(i), b"old").await?;
+    }
+    db.flush_memtable().await?;
+    for i in 0..10 {
+        db.put(&key(i), b"new").await?;
+    }
+    db.delete(&key(10)).await?;
+    db.flush_memtable().await?;
+    db.put(b"unflushed", b"yes").await?;
+    Ok(db)
+}
+
+fn remove_manifests(dir: &TempDir) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir.path())? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if name == "CURRENT" || name.starts_with("MANIFEST-") {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn rebuilds_a_lost_manifest() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    drop(fill(&dir).await?);
+    remove_manifests(&dir)?;
+
+    let report = Db::repair(dir.path(), DbOptions::default()).await?;
+    assert!(!report.salvaged_manifest);
+    assert_eq!(report.tables.len(), 2);
+    assert!(report.quarantined.is_empty(), "{:?}", report.quarantined);
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(&key(3)).await?, Some(b"new".to_vec()));
+    assert_eq!(db.get(&key(10)).await?, None);
+    assert_eq!(db.get(&key(30)).await?, Some(b"old".to_vec()));
+    assert_eq!(db.get(b"unflushed").await?, Some(b"yes".to_vec()));
+    assert!(db.verify_checksums().await?.is_ok());
+    Ok(())
+}
+
+#[tokio::test]
+async fn quarantines_what_cannot_be_read() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = fill(&dir).await?;
+    let files = db.live_files().await?;
+    drop(db);
+    let tables: Vec<_> = files.iter().filter(|f| f.kind == FileKind::Table).collect();
+    let (newer, older) = (&tables[0].path, &tables[1].path);
+    let wal = &files.iter().find(|f| f.kind == FileKind::Wal).unwrap().path;
+
+    // A flipped byte in the newer table, garbage in the log, and a manifest
+    // that's unreadable from the start.
+    let mut data = std::fs::read(newer)?;
+    data[10] ^= 0xff;
+    std::fs::write(newer, data)?;
+    let mut log = std::fs::OpenOptions::new().append(true).open(wal)?;
+    log.write_all(b"not a record\n")?;
+    let current = std::fs::read_to_string(dir.path().join("CURRENT"))?;
+    std::fs::write(dir.path().join(current.trim()), b"garbage\n")?;
+
+    let report = Db::repair(dir.path(), DbOptions::default()).await?;
+    assert!(!report.salvaged_manifest);
+    assert_eq!(report.tables, vec![older.clone()]);
+    assert_eq!(report.dropped_log_records, 1);
+    let quarantined: Vec<_> = report.quarantined.iter().map(|(path, _)| path).collect();
+    assert!(quarantined.contains(&newer));
+    assert!(quarantined.contains(&wal));
+    assert!(dir
+        .path()
+        .join("lost")
+        .join(newer.file_name().unwrap())
+        .exists());
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(&key(3)).await?, Some(b"old".to_vec()));
+    assert_eq!(db.get(b"unflushed").await?, Some(b"yes".to_vec()));
+    assert!(db.verify_checksums().await?.is_ok());
+    Ok(())
+}
+
+#[tokio::test]
+async fn quarantines_tables_the_manifest_dropped() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    drop(fill(&dir).await?);
+    let options = DbOptions {
+        block_size: 64,
+        vacuum: Some(VacuumOptions {
+            min_tables: 2,
+            ..VacuumOptions::default()
+        }),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    let files = db.live_files().await?;
+    let older = files.iter().filter(|f| f.kind == FileKind::Table).nth(1);
+    let older = older.unwrap().path.clone();
+    let saved = std::fs::read(&older)?;
+    assert_eq!(db.vacuum().await?, 1);
+    drop(db);
+    // As if the vacuum had crashed before removing it.
+    std::fs::write(&older, saved)?;
+
+    let report = Db::repair(dir.path(), DbOptions::default()).await?;
+    assert!(report.salvaged_manifest);
+    assert_eq!(report.tables.len(), 1);
+    assert!(report.quarantined.iter().any(|(path, _)| *path == older));
+    assert!(dir
+        .path()
+        .join("lost")
+        .join(older.file_name().unwrap())
+        .exists());
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(&key(3)).await?, Some(b"new".to_vec()));
+    assert_eq!(db.get(&key(10)).await?, None);
+    assert_eq!(db.get(&key(30)).await?, Some(b"old".to_vec()));
+    assert_eq!(db.get(b"unflushed").await?, Some(b"yes".to_vec()));
+    Ok(())
+}
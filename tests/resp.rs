@@ -0,0 +1,286 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+use tempfile::TempDir;
+
+// A server listening for Redis clients on a free port, killed when it's
+// dropped.
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    fn start(dir: &Path, args: &[&str]) -> Server {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ndb-server"))
+            .args(["--addr", "127.0.0.1:0", "--resp-addr", "127.0.0.1:0"])
+            .args(args)
+            .arg(dir)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        line.clear();
+        stdout.read_line(&mut line).unwrap();
+        let addr = line.trim().strip_prefix("listening for resp on ").unwrap();
+        Server {
+            addr: addr.into(),
+            child,
+        }
+    }
+
+    fn connect(&self) -> Client {
+        Client(BufReader::new(TcpStream::connect(&self.addr).unwrap()))
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+fn bulk(bytes: &str) -> Reply {
+    Reply::Bulk(Some(bytes.into()))
+}
+
+fn ok() -> Reply {
+    Reply::Simple("OK".into())
+}
+
+struct Client(BufReader<TcpStream>);
+
+impl Client {
+    fn send(&mut self, args: &[&str]) {
+        let mut command = format!("*{}\r\n", args.len());
+        for arg in args {
+            command += &format!("${}\r\n{}\r\n", arg.len(), arg);
+        }
+        self.0.get_mut().write_all(command.as_bytes()).unwrap();
+    }
+
+    fn receive(&mut self) -> Reply {
+        let mut line = String::new();
+        self.0.read_line(&mut line).unwrap();
+        let line = line.strip_suffix("\r\n").unwrap();
+        let (kind, rest) = line.split_at(1);
+        match kind {
+            "+" => Reply::Simple(rest.into()),
+            "-" => Reply::Error(rest.into()),
+            ":" => Reply::Integer(rest.parse().unwrap()),
+            "$" if rest == "-1" => Reply::Bulk(None),
+            "$" => {
+                let mut bytes = vec![0; rest.parse::<usize>().unwrap() + 2];
+                self.0.read_exact(&mut bytes).unwrap();
+                bytes.truncate(bytes.len() - 2);
+                Reply::Bulk(Some(bytes))
+            }
+            "*" => Reply::Array((0..rest.parse().unwrap()).map(|_| self.receive()).collect()),
+            _ => panic!("bad reply {:?}", line),
+        }
+    }
+
+    fn call(&mut self, args: &[&str]) -> Reply {
+        self.send(args);
+        self.receive()
+    }
+}
+
+#[test]
+fn serves_redis_commands() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path(), &[]);
+    let mut client = server.connect();
+    assert_eq!(client.call(&["PING"]), Reply::Simple("PONG".into()));
+    assert_eq!(client.call(&["SET", "a", "1"]), ok());
+    assert_eq!(client.call(&["get", "a"]), bulk("1"));
+    assert_eq!(client.call(&["GET", "b"]), Reply::Bulk(None));
+
+    // NX only sets keys that aren't there, and XX only ones that are.
+    assert_eq!(client.call(&["SET", "a", "2", "NX"]), Reply::Bulk(None));
+    assert_eq!(client.call(&["SET", "b", "2", "XX"]), Reply::Bulk(None));
+    assert_eq!(client.call(&["SET", "a", "2", "XX"]), ok());
+    assert_eq!(client.call(&["SET", "b", "2", "NX"]), ok());
+    assert_eq!(client.call(&["GET", "a"]), bulk("2"));
+    // The lock idiom: options come in any order, and NX and XX go with a TTL.
+    assert_eq!(
+        client.call(&["SET", "lock", "1", "NX", "PX", "100000"]),
+        ok()
+    );
+    assert_eq!(
+        client.call(&["SET", "lock", "2", "EX", "100", "NX"]),
+        Reply::Bulk(None)
+    );
+    assert_eq!(client.call(&["SET", "lock", "3", "XX", "EX", "50"]), ok());
+    assert_eq!(client.call(&["TTL", "lock"]), Reply::Integer(50));
+    assert_eq!(client.call(&["GET", "lock"]), bulk("3"));
+    assert!(matches!(
+        client.call(&["SET", "lock", "4", "NX", "XX"]),
+        Reply::Error(e) if e.contains("syntax")
+    ));
+
+    assert_eq!(client.call(&["SET", "c", "3", "EX", "100"]), ok());
+    assert_eq!(client.call(&["TTL", "c"]), Reply::Integer(100));
+    assert!(matches!(client.call(&["PTTL", "c"]), Reply::Integer(ms) if ms > 99_000));
+    assert_eq!(client.call(&["TTL", "a"]), Reply::Integer(-1));
+    assert_eq!(client.call(&["TTL", "nope"]), Reply::Integer(-2));
+
+    assert_eq!(
+        client.call(&["EXISTS", "a", "a", "nope"]),
+        Reply::Integer(2)
+    );
+    assert_eq!(
+        client.call(&["DEL", "a", "c", "lock", "nope"]),
+        Reply::Integer(3)
+    );
+    assert_eq!(client.call(&["EXISTS", "a", "b", "c"]), Reply::Integer(1));
+
+    // Commands can be typed in, and sent before the replies to earlier ones
+    // are read.
+    client.0.get_mut().write_all(b"GET b\r\nPING\r\n").unwrap();
+    assert_eq!(client.receive(), bulk("2"));
+    assert_eq!(client.receive(), Reply::Simple("PONG".into()));
+
+    assert!(matches!(client.call(&["GET"]), Reply::Error(e) if e.contains("wrong number")));
+    assert!(
+        matches!(client.call(&["HSET", "h", "f", "v"]), Reply::Error(e) if e.contains("unknown"))
+    );
+    assert_eq!(client.call(&["QUIT"]), ok());
+    let mut rest = Vec::new();
+    client.0.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+// An expiry too far off for Redis is turned away, rather than taking down
+// the connection.
+#[test]
+fn rejects_expire_times_that_overflow() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path(), &[]);
+    let mut client = server.connect();
+    let max = i64::MAX.to_string();
+    for unit in ["EX", "PX"] {
+        for n in [max.as_str(), "0", "-1"] {
+            assert_eq!(
+                client.call(&["SET", "k", "v", unit, n]),
+                Reply::Error("ERR invalid expire time in 'set' command".into())
+            );
+        }
+    }
+    assert_eq!(client.call(&["GET", "k"]), Reply::Bulk(None));
+    assert_eq!(client.call(&["SET", "k", "v", "EX", "100"]), ok());
+    assert_eq!(client.call(&["TTL", "k"]), Reply::Integer(100));
+}
+
+#[test]
+fn scans_with_cursors() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path(), &[]);
+    let mut client = server.connect();
+    for i in 0..25 {
+        let key = format!("{}:{:02}", if i % 5 == 0 { "five" } else { "key" }, i);
+        assert_eq!(client.call(&["SET", &key, "v"]), ok());
+    }
+
+    // Every key comes back once, however the cursor is passed around.
+    let mut cursor = "0".to_string();
+    let mut keys = Vec::new();
+    loop {
+        let mut reply = match client.call(&["SCAN", &cursor, "MATCH", "key:*", "COUNT", "7"]) {
+            Reply::Array(reply) => reply,
+            reply => panic!("{:?}", reply),
+        };
+        let Reply::Array(batch) = reply.pop().unwrap() else {
+            panic!()
+        };
+        keys.extend(batch);
+        let Some(Reply::Bulk(Some(next))) = reply.pop() else {
+            panic!()
+        };
+        cursor = String::from_utf8(next).unwrap();
+        if cursor == "0" {
+            break;
+        }
+        client = server.connect();
+    }
+    assert_eq!(keys.len(), 20);
+    assert_eq!(keys[0], bulk("key:01"));
+    assert_eq!(keys[19], bulk("key:24"));
+
+    assert!(matches!(client.call(&["SCAN", "12345"]), Reply::Error(e) if e.contains("cursor")));
+
+    // Patterns match the way Redis's do, and ones that would take
+    // exponential time to backtrack through don't.
+    let long = "a".repeat(100);
+    assert_eq!(client.call(&["SET", &long, "v"]), ok());
+    let cases = [
+        ("*a*a*a*a*a*a*a*a*a*a*b", 0),
+        ("*a*a*a*a*a*a*a*a*a*a*", 1),
+        ("f?ve:[0-1]*", 4),
+        ("five:[^0]?", 3),
+        ("*:\\2?", 5),
+        ("key:0[1-4]", 4),
+        ("*", 26),
+    ];
+    for (pattern, expected) in cases {
+        let reply = client.call(&["SCAN", "0", "MATCH", pattern, "COUNT", "100"]);
+        let Reply::Array(mut reply) = reply else {
+            panic!("{:?}", reply)
+        };
+        let Some(Reply::Array(keys)) = reply.pop() else {
+            panic!()
+        };
+        assert_eq!(keys.len(), expected, "{}", pattern);
+    }
+}
+
+#[test]
+fn closes_connections_that_break_the_protocol() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path(), &[]);
+    let mut client = server.connect();
+    client.0.get_mut().write_all(b"*1\r\n:1\r\n").unwrap();
+    assert!(matches!(client.receive(), Reply::Error(e) if e.contains("Protocol error")));
+    let mut rest = Vec::new();
+    client.0.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(
+        server.connect().call(&["PING"]),
+        Reply::Simple("PONG".into())
+    );
+}
+
+#[test]
+fn requires_auth_with_a_token() {
+    let dir = TempDir::new().unwrap();
+    let token_file = dir.path().join("token");
+    std::fs::write(&token_file, "s3cret\n").unwrap();
+    let server = Server::start(
+        &dir.path().join("db"),
+        &["--auth-token-file", token_file.to_str().unwrap()],
+    );
+    let mut client = server.connect();
+    assert!(matches!(client.call(&["GET", "a"]), Reply::Error(e) if e.starts_with("NOAUTH")));
+    assert!(
+        matches!(client.call(&["AUTH", "nope"]), Reply::Error(e) if e.starts_with("WRONGPASS"))
+    );
+    assert_eq!(client.call(&["AUTH", "default", "s3cret"]), ok());
+    assert_eq!(client.call(&["SET", "a", "1"]), ok());
+    assert_eq!(client.call(&["GET", "a"]), bulk("1"));
+}
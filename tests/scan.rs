@@ -0,0 +1,53 @@
+use std::ops::Bound;
+
+use nulldb::{Db, DbIterator, NdbError};
+use tempfile::TempDir;
+
+async fn collect(mut iter: DbIterator) -> Result<Vec<(String, String)>, NdbError> {
+    let mut out = Vec::new();
+    while let Some((k, v)) = iter.next().await? {
+        out.push((String::from_utf8(k).unwrap(), String::from_utf8(v).unwrap()));
+    }
+    Ok(out)
+}
+
+#[tokio::test]
+async fn reverse_scan_merges_memtable_and_tables() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    for key in ["a", "b", "c", "d", "e"] {
+        db.put(key.as_bytes(), b"old").await?;
+    }
+    db.flush_memtable().await?;
+    db.put(b"b", b"new").await?;
+    db.delete(b"d").await?;
+    db.flush_memtable().await?;
+    db.put(b"c", b"newest").await?;
+    db.delete(b"e").await?;
+    db.put(b"f", b"newest").await?;
+
+    let expected = [("f", "newest"), ("c", "newest"), ("b", "new"), ("a", "old")];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    assert_eq!(collect(db.scan_rev(..).await?).await?, expected);
+
+    let mut forward = collect(db.scan(..).await?).await?;
+    forward.reverse();
+    assert_eq!(forward, expected);
+
+    assert_eq!(
+        collect(db.scan_rev(b"b".to_vec()..b"f".to_vec()).await?).await?,
+        expected[1..3]
+    );
+    assert_eq!(
+        collect(
+            db.scan_rev((Bound::Excluded(b"a".to_vec()), Bound::Unbounded))
+                .await?
+        )
+        .await?,
+        expected[..3]
+    );
+    Ok(())
+}
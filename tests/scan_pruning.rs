@@ -0,0 +1,91 @@
+use std::{collections::BTreeMap, ops::Bound};
+
+use nulldb::{Db, DbIterator, NdbError};
+use tempfile::TempDir;
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key-{:04}", i).into_bytes()
+}
+
+async fn collect(mut iter: DbIterator) -> Result<Vec<Vec<u8>>, NdbError> {
+    let mut keys = Vec::new();
+    while let Some((key, _)) = iter.next().await? {
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+// Scans pick out the tables they overlap, and have to still see the range
+// tombstones of the ones they don't.
+#[tokio::test]
+async fn scans_see_only_overlapping_tables() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    let mut expected = BTreeMap::new();
+    // Tables over runs of keys next to each other, and a few spanning many
+    // of them.
+    for t in 0..40 {
+        let keys: Vec<u32> = if t % 10 == 9 {
+            (0..10).map(|i| i * 100 + t).collect()
+        } else {
+            (t * 25..t * 25 + 20).collect()
+        };
+        for i in keys {
+            db.put(&key(i), &t.to_le_bytes()).await?;
+            expected.insert(key(i), ());
+        }
+        db.flush_memtable().await?;
+    }
+    // A table holding nothing but a range tombstone, outside its own key
+    // range.
+    db.delete_range(&key(300), &key(340)).await?;
+    db.flush_memtable().await?;
+    expected.retain(|k, _| k < &key(300) || k >= &key(340));
+    db.put(&key(2000), b"x").await?;
+    db.delete_range(&key(500), &key(510)).await?;
+    db.flush_memtable().await?;
+    expected.retain(|k, _| k < &key(500) || k >= &key(510));
+    expected.insert(key(2000), ());
+
+    let view = db.freeze_view();
+    for (start, end) in [(0, 1000), (290, 350), (305, 335), (499, 512), (990, 3000)] {
+        let range = (Bound::Included(key(start)), Bound::Excluded(key(end)));
+        let want: Vec<_> = expected
+            .range(range.clone())
+            .map(|(k, _)| k.clone())
+            .collect();
+        assert_eq!(collect(db.scan(range.clone()).await?).await?, want);
+        assert_eq!(collect(view.scan(range.clone()).await?).await?, want);
+        let mut rev = collect(db.scan_rev(range.clone()).await?).await?;
+        rev.reverse();
+        assert_eq!(rev, want);
+    }
+    assert_eq!(collect(db.scan(..).await?).await?.len(), expected.len());
+    Ok(())
+}
+
+// A get only looks at the tables whose key range holds the key, which is
+// the only time a table's bloom filter is checked.
+#[tokio::test]
+async fn gets_see_only_tables_holding_the_key() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    for t in 0..10 {
+        for i in t * 100..t * 100 + 50 {
+            db.put(&key(i), &t.to_le_bytes()).await?;
+        }
+        db.flush_memtable().await?;
+    }
+    // A table spanning all the others.
+    db.put(&key(0), b"first").await?;
+    db.put(&key(999), b"last").await?;
+    db.flush_memtable().await?;
+
+    let bloom_checks = |db: &Db| db.stats().io.bloom_checks;
+    for (i, checked) in [(310, 2), (375, 1), (2000, 0), (0, 1)] {
+        let before = bloom_checks(&db);
+        db.get(&key(i)).await?;
+        assert_eq!(bloom_checks(&db) - before, checked, "key {}", i);
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use nulldb::{Db, DbOptions, NdbError, ScanToken};
+use tempfile::TempDir;
+
+async fn take(db: &Db, token: &mut ScanToken, n: usize) -> Result<Vec<Vec<u8>>, NdbError> {
+    let mut iter = db.scan_from_token(token).await?;
+    let mut keys = Vec::new();
+    while keys.len() < n {
+        let Some((key, _)) = iter.next().await? else {
+            break;
+        };
+        token.advance(&key);
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+#[tokio::test]
+async fn resumes_after_reopening() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    for key in [b"a", b"b", b"c", b"d", b"e", b"f"] {
+        db.put(key, b"old").await?;
+    }
+    let mut token = db.scan_token(b"b".to_vec()..b"f".to_vec());
+    assert_eq!(take(&db, &mut token, 2).await?, [b"b", b"c"]);
+
+    // None of this is seen by the rest of the scan.
+    db.put(b"d", b"new").await?;
+    db.delete(b"e").await?;
+    db.put(b"dd", b"new").await?;
+    db.delete_range(b"a", b"z").await?;
+    let saved = token.to_bytes();
+    drop(db);
+
+    let mut db = Db::new(dir.path()).await?;
+    let mut token = ScanToken::from_bytes(&saved)?;
+    let mut iter = db.scan_from_token(&token).await?;
+    assert_eq!(iter.next().await?, Some((b"d".to_vec(), b"old".to_vec())));
+    assert_eq!(iter.next().await?, Some((b"e".to_vec(), b"old".to_vec())));
+    assert_eq!(iter.next().await?, None);
+    assert_eq!(take(&db, &mut token, 1).await?, [b"d"]);
+
+    // Flushing drops the history the token reads.
+    db.flush_memtable().await?;
+    assert!(matches!(
+        db.scan_from_token(&token).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn reads_tables_under_the_memtable() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"table").await?;
+    db.put(b"b", b"table").await?;
+    db.flush_memtable().await?;
+    db.delete(b"a").await?;
+    let mut token = db.scan_token(..);
+    db.put(b"a", b"back").await?;
+    db.delete_range(b"b", b"c").await?;
+    assert_eq!(take(&db, &mut token, 10).await?, [b"b"]);
+    assert_eq!(take(&db, &mut token, 10).await?, Vec::<Vec<u8>>::new());
+    Ok(())
+}
+
+// A scan that's open when the memtable is flushed in the background reads
+// on as of its token, but the token can't be resumed after.
+#[tokio::test]
+async fn outlasts_a_background_flush() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        write_buffer_size: Some(4096),
+        ..DbOptions::default()
+    };
+    let db = Db::with_options(dir.path(), options).await?;
+    let key = |i: usize| format!("key-{:02}", i).into_bytes();
+    for i in 0..10 {
+        db.put(&key(i), b"old").await?;
+    }
+    let mut token = db.scan_token(..);
+    let mut iter = db.scan_from_token(&token).await?;
+
+    let mut i = 0;
+    while db.oldest_readable_sequence() < token.sequence() {
+        db.put(&key(i % 20), b"new").await?;
+        i += 1;
+        assert!(i < 10_000, "never flushed");
+    }
+    for i in 0..10 {
+        assert_eq!(iter.next().await?, Some((key(i), b"old".to_vec())));
+        token.advance(&key(i));
+    }
+    assert_eq!(iter.next().await?, None);
+    assert!(matches!(
+        db.scan_from_token(&token).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use std::{ops::Bound, sync::Arc};
+
+use nulldb::{Db, DbOptions, NdbError, SecondaryIndex, WriteBatch, WriteOptions};
+use tempfile::TempDir;
+
+type Range<'a> = (Bound<&'a [u8]>, Bound<&'a [u8]>);
+
+// Users are stored as "city,name", and indexed by city.
+fn options() -> DbOptions {
+    DbOptions {
+        secondary_indexes: vec![SecondaryIndex {
+            name: "city".into(),
+            source_prefix: b"users/".to_vec(),
+            prefix: b"index/city/".to_vec(),
+            extract: Arc::new(|value: &[u8]| {
+                let city = value.split(|&b| b == b',').next()?;
+                (!city.is_empty()).then(|| city.to_vec())
+            }),
+        }],
+        ..DbOptions::default()
+    }
+}
+
+async fn find(db: &Db, range: Range<'_>) -> Result<Vec<(String, String)>, NdbError> {
+    let range = (range.0.map(<[u8]>::to_vec), range.1.map(<[u8]>::to_vec));
+    let mut iter = db.index_scan("city", range).await?;
+    let mut found = Vec::new();
+    while let Some((key, value)) = iter.next().await? {
+        found.push((
+            String::from_utf8(key).unwrap(),
+            String::from_utf8(value).unwrap(),
+        ));
+    }
+    Ok(found)
+}
+
+async fn in_city(db: &Db, city: &[u8]) -> Result<Vec<String>, NdbError> {
+    let found = find(db, (Bound::Included(city), Bound::Included(city))).await?;
+    Ok(found.into_iter().map(|(key, _)| key).collect())
+}
+
+async fn entries(db: &Db) -> Result<usize, NdbError> {
+    let mut iter = db.scan(b"index/".to_vec()..b"index0".to_vec()).await?;
+    let mut count = 0;
+    while iter.next().await?.is_some() {
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[tokio::test]
+async fn follows_writes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    db.put(b"users/1", b"paris,alice").await?;
+    db.put(b"users/2", b"oslo,bob").await?;
+    db.put(b"users/3", b"paris,carol").await?;
+    db.put(b"users/4", b",dave").await?;
+    db.put(b"other/1", b"paris,eve").await?;
+    assert_eq!(in_city(&db, b"paris").await?, ["users/1", "users/3"]);
+    assert_eq!(
+        find(&db, (Bound::Unbounded, Bound::Unbounded)).await?,
+        [
+            ("users/2".into(), "oslo,bob".into()),
+            ("users/1".into(), "paris,alice".into()),
+            ("users/3".into(), "paris,carol".into()),
+        ]
+    );
+
+    db.put(b"users/1", b"oslo,alice").await?;
+    db.delete(b"users/3").await?;
+    db.put(b"users/4", b"rome,dave").await?;
+    db.put(b"users/2", b"oslo,bob").await?;
+    for reopen in [false, true] {
+        if reopen {
+            db.flush_memtable().await?;
+            drop(db);
+            db = Db::with_options(dir.path(), options()).await?;
+        }
+        assert!(in_city(&db, b"paris").await?.is_empty());
+        assert_eq!(in_city(&db, b"oslo").await?, ["users/1", "users/2"]);
+        assert_eq!(in_city(&db, b"rome").await?, ["users/4"]);
+        // Old entries are removed, not just skipped.
+        assert_eq!(entries(&db).await?, 3);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn ranges_of_index_keys() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::with_options(dir.path(), options()).await?;
+    // Index keys that are prefixes of each other, or hold zero bytes.
+    for (key, value) in [
+        (&b"users/1"[..], &b"a,"[..]),
+        (b"users/2", b"a\0,"),
+        (b"users/3", b"a\0b,"),
+        (b"users/4", b"ab,"),
+        (b"users/5", b"b,"),
+    ] {
+        db.put(key, value).await?;
+    }
+    let keys = |found: Vec<(String, String)>| -> Vec<String> {
+        found.into_iter().map(|(key, _)| key).collect()
+    };
+    let all = find(&db, (Bound::Unbounded, Bound::Unbounded)).await?;
+    assert_eq!(
+        keys(all),
+        ["users/1", "users/2", "users/3", "users/4", "users/5"]
+    );
+    let cases: [(Range, &[&str]); 5] = [
+        (
+            (Bound::Excluded(b"a"), Bound::Unbounded),
+            &["users/2", "users/3", "users/4", "users/5"],
+        ),
+        (
+            (Bound::Included(b"a\0"), Bound::Excluded(b"ab")),
+            &["users/2", "users/3"],
+        ),
+        (
+            (Bound::Excluded(b"a\0"), Bound::Included(b"ab")),
+            &["users/3", "users/4"],
+        ),
+        ((Bound::Unbounded, Bound::Excluded(b"a\0")), &["users/1"]),
+        ((Bound::Included(b"c"), Bound::Unbounded), &[]),
+    ];
+    for (range, expected) in cases {
+        assert_eq!(keys(find(&db, range).await?), expected, "{:?}", range);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn every_kind_of_write_is_indexed() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::with_options(dir.path(), options()).await?;
+
+    // The last write to a key in a batch wins.
+    let mut batch = WriteBatch::new();
+    batch.put(b"users/1", b"paris,alice");
+    batch.put(b"users/1", b"oslo,alice");
+    batch.put(b"users/2", b"paris,bob");
+    batch.delete(b"users/2");
+    batch.put(b"users/3", b"paris,carol");
+    db.write(batch).await?;
+    assert_eq!(in_city(&db, b"oslo").await?, ["users/1"]);
+    assert_eq!(in_city(&db, b"paris").await?, ["users/3"]);
+
+    let mut txn = db.transaction();
+    txn.put(b"users/3", b"rome,carol");
+    txn.commit().await?;
+    assert!(
+        db.compare_and_swap(b"users/1", Some(b"oslo,alice"), Some(b"rome,alice"))
+            .await?
+    );
+    let token = {
+        let mut batch = WriteBatch::new();
+        batch.put(b"users/4", b"rome,dave");
+        db.prepare(batch).await?
+    };
+    assert_eq!(in_city(&db, b"rome").await?, ["users/1", "users/3"]);
+    db.commit_prepared(token).await?;
+    let unlogged = WriteOptions {
+        disable_wal: true,
+        ..WriteOptions::default()
+    };
+    db.put_opt(b"users/5", b"rome,erin", &unlogged).await?;
+
+    assert!(in_city(&db, b"oslo").await?.is_empty());
+    assert!(in_city(&db, b"paris").await?.is_empty());
+    assert_eq!(
+        in_city(&db, b"rome").await?,
+        ["users/1", "users/3", "users/4", "users/5"]
+    );
+    assert_eq!(entries(&db).await?, 4);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_what_it_cant_index() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::with_options(
+        dir.path(),
+        DbOptions {
+            merge_operator: Some(Arc::new(|_, _, operands| operands.concat())),
+            ..options()
+        },
+    )
+    .await?;
+    db.put(b"users/1", b"paris,alice").await?;
+    assert!(matches!(
+        db.merge(b"users/1", b"x").await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    for (start, end) in [(&b"a"[..], &b"z"[..]), (b"users/1", b"users/2")] {
+        assert!(matches!(
+            db.delete_range(start, end).await,
+            Err(NdbError::InvalidArgument(_))
+        ));
+    }
+    // Nowhere near the source prefix.
+    db.merge(b"other/1", b"x").await?;
+    db.delete_range(b"a", b"b").await?;
+    db.delete_range(b"users0", b"z").await?;
+    assert!(matches!(
+        db.index_scan("name", ..).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    assert_eq!(in_city(&db, b"paris").await?, ["users/1"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_conflicting_indexes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let index = options().secondary_indexes.remove(0);
+    let renamed = |name: &str, prefix: &[u8]| SecondaryIndex {
+        name: name.into(),
+        prefix: prefix.to_vec(),
+        ..index.clone()
+    };
+    let cases = [
+        // The same name twice.
+        vec![index.clone(), renamed("city", b"index/city2/")],
+        // Entries under its own source prefix.
+        vec![renamed("inside", b"users/index/")],
+        // A source prefix under its entries' prefix.
+        vec![renamed("around", b"user")],
+        // Entries under another index's source prefix.
+        vec![index.clone(), renamed("other", b"users/other/")],
+    ];
+    for secondary_indexes in cases {
+        let options = DbOptions {
+            secondary_indexes,
+            ..DbOptions::default()
+        };
+        assert!(matches!(
+            Db::with_options(dir.path(), options).await,
+            Err(NdbError::InvalidArgument(_))
+        ));
+    }
+    let options = DbOptions {
+        secondary_indexes: vec![index.clone(), renamed("also", b"index/also/")],
+        ..DbOptions::default()
+    };
+    Db::with_options(dir.path(), options).await?;
+    Ok(())
+}
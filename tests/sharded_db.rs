@@ -0,0 +1,115 @@
+use nulldb::{Db, DbOptions, NdbError, ShardedDb, WriteBatch};
+use tempfile::TempDir;
+
+fn shard_dirs(dir: &TempDir, shards: usize) -> Vec<std::path::PathBuf> {
+    (0..shards)
+        .map(|i| dir.path().join(format!("shard{}", i)))
+        .collect()
+}
+
+#[tokio::test]
+async fn spreads_keys_across_shards() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = ShardedDb::open(shard_dirs(&dir, 4), DbOptions::default()).await?;
+    for i in 0..100u32 {
+        db.put(format!("key{:03}", i).as_bytes(), &i.to_be_bytes())
+            .await?;
+    }
+    db.delete(b"key050").await?;
+    assert_eq!(db.get(b"key007").await?, Some(7u32.to_be_bytes().to_vec()));
+    assert_eq!(db.get(b"key050").await?, None);
+
+    // Every shard got some of them, and only the ones that hash to it.
+    for (i, shard) in db.shards().iter().enumerate() {
+        let mut iter = shard.scan(..).await?;
+        let mut keys = 0;
+        while let Some((key, _)) = iter.next().await? {
+            assert_eq!(db.shard_for(&key), i);
+            keys += 1;
+        }
+        assert!(keys > 0);
+    }
+
+    let keys = [b"key001".as_slice(), b"key050", b"key099", b"missing"];
+    assert_eq!(
+        db.multi_get(&keys).await?,
+        [
+            Some(1u32.to_be_bytes().to_vec()),
+            None,
+            Some(99u32.to_be_bytes().to_vec()),
+            None
+        ]
+    );
+
+    // Scans merge the shards back into key order.
+    db.flush_memtable().await?;
+    let mut iter = db.scan(b"key010".to_vec()..b"key020".to_vec()).await?;
+    let mut keys = Vec::new();
+    while let Some((key, _)) = iter.next().await? {
+        keys.push(String::from_utf8(key).unwrap());
+    }
+    let expected: Vec<_> = (10..20).map(|i| format!("key{:03}", i)).collect();
+    assert_eq!(keys, expected);
+
+    let mut iter = db.scan_rev(..).await?;
+    assert_eq!(iter.next().await?.unwrap().0, b"key099");
+    assert_eq!(iter.next().await?.unwrap().0, b"key098");
+    Ok(())
+}
+
+#[tokio::test]
+async fn splits_batches_by_shard() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = ShardedDb::open(shard_dirs(&dir, 3), DbOptions::default()).await?;
+    let mut batch = WriteBatch::new();
+    for key in [b"a", b"b", b"c", b"d", b"e", b"f"] {
+        batch.put(key, b"1");
+    }
+    batch.delete_range(b"b", b"d");
+    batch.put(b"c", b"2");
+    db.write(batch).await?;
+
+    let keys = [b"a".as_slice(), b"b", b"c", b"d", b"e", b"f"];
+    let values = db.multi_get(&keys).await?;
+    let values: Vec<_> = values.iter().map(|value| value.as_deref()).collect();
+    assert_eq!(
+        values,
+        [
+            Some(b"1".as_slice()),
+            None,
+            Some(b"2"),
+            Some(b"1"),
+            Some(b"1"),
+            Some(b"1")
+        ]
+    );
+
+    db.delete_range(b"a", b"z").await?;
+    assert!(db.scan(..).await?.next().await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn reopens_with_the_same_shards() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = ShardedDb::open(shard_dirs(&dir, 2), DbOptions::default()).await?;
+    db.put(b"k", b"v").await?;
+    let shard = db.shard_for(b"k");
+    db.close().await?;
+
+    let db = ShardedDb::open(shard_dirs(&dir, 2), DbOptions::default()).await?;
+    assert_eq!(db.get(b"k").await?, Some(b"v".to_vec()));
+    drop(db);
+    let only = Db::new(&shard_dirs(&dir, 2)[shard]).await?;
+    assert_eq!(only.get(b"k").await?, Some(b"v".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn needs_a_shard() -> Result<(), NdbError> {
+    assert!(matches!(
+        ShardedDb::open(Vec::<std::path::PathBuf>::new(), DbOptions::default()).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,266 @@
+// Runs random workloads against a `SimStorage` that crashes at a random
+// operation, and checks the database it recovers to: it has to open, and
+// hold exactly what the writes before some point in the workload left, with
+// nothing acknowledged as durable missing. A failure names the seed, which
+// SIM_SEED replays on its own; SIM_SEEDS sets how many seeds to try.
+
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use nulldb::{
+    Db, DbOptions, ManualClock, NdbError, SimFaults, SimStorage, VacuumOptions, VerifyProblem,
+    WriteBatch, WriteOptions,
+};
+
+const DIR: &str = "/sim/db";
+
+#[derive(Debug, Clone)]
+enum Op {
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        sync: bool,
+    },
+    Delete(Vec<u8>),
+    Batch(Vec<(Vec<u8>, Option<Vec<u8>>)>),
+    Flush,
+    Vacuum,
+    Reopen,
+}
+
+impl Op {
+    // Whether everything written up to and including it is durable once it
+    // returns. A vacuum only rewrites tables, so it doesn't sync the log.
+    fn syncs(&self) -> bool {
+        !matches!(self, Op::Put { sync: false, .. } | Op::Vacuum)
+    }
+}
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+fn workload(rng: &mut Rng, len: usize) -> Vec<Op> {
+    let key = |rng: &mut Rng| format!("key-{:02}", rng.below(24)).into_bytes();
+    let value = |rng: &mut Rng, i: usize| {
+        let mut value = format!("value-{}-", i).into_bytes();
+        value.resize(value.len() + rng.below(200) as usize, b'x');
+        value
+    };
+    (0..len)
+        .map(|i| match rng.below(100) {
+            0..=44 => Op::Put {
+                key: key(rng),
+                value: value(rng, i),
+                sync: rng.below(4) != 0,
+            },
+            45..=59 => Op::Delete(key(rng)),
+            60..=74 => Op::Batch(
+                (0..1 + rng.below(5))
+                    .map(|_| match rng.below(4) {
+                        0 => (key(rng), None),
+                        _ => (key(rng), Some(value(rng, i))),
+                    })
+                    .collect(),
+            ),
+            75..=89 => Op::Flush,
+            90..=94 => Op::Vacuum,
+            _ => Op::Reopen,
+        })
+        .collect()
+}
+
+fn apply(model: &mut BTreeMap<Vec<u8>, Vec<u8>>, op: &Op) {
+    match op {
+        Op::Put { key, value, .. } => {
+            model.insert(key.clone(), value.clone());
+        }
+        Op::Delete(key) => {
+            model.remove(key);
+        }
+        Op::Batch(writes) => {
+            for (key, value) in writes {
+                match value {
+                    Some(value) => model.insert(key.clone(), value.clone()),
+                    None => model.remove(key),
+                };
+            }
+        }
+        Op::Flush | Op::Vacuum | Op::Reopen => {}
+    }
+}
+
+fn options(storage: &SimStorage) -> DbOptions {
+    DbOptions {
+        storage: Arc::new(storage.clone()),
+        clock: Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1 << 30))),
+        block_size: 256,
+        write_buffer_size: Some(2048),
+        wal_segment_bytes: Some(1024),
+        vacuum: Some(VacuumOptions {
+            min_tables: 3,
+            ..VacuumOptions::default()
+        }),
+        ..DbOptions::default()
+    }
+}
+
+async fn run(db: &mut Option<Db>, storage: &SimStorage, op: &Op) -> Result<(), NdbError> {
+    if let Op::Reopen = op {
+        if let Some(db) = db.take() {
+            db.close().await?;
+        }
+        *db = Some(Db::with_options(DIR, options(storage)).await?);
+        return Ok(());
+    }
+    let db = db.as_mut().expect("open");
+    match op {
+        Op::Put { key, value, sync } => {
+            let options = WriteOptions {
+                sync: *sync,
+                ..WriteOptions::default()
+            };
+            db.put_opt(key, value, &options).await
+        }
+        Op::Delete(key) => db.delete(key).await,
+        Op::Batch(writes) => {
+            let mut batch = WriteBatch::new();
+            for (key, value) in writes {
+                match value {
+                    Some(value) => batch.put(key, value),
+                    None => batch.delete(key),
+                }
+            }
+            db.write(batch).await
+        }
+        Op::Flush => db.flush_memtable().await,
+        Op::Vacuum => db.vacuum().await.map(|_| ()),
+        Op::Reopen => unreachable!(),
+    }
+}
+
+async fn contents(db: &Db) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, NdbError> {
+    let mut iter = db.scan(..).await?;
+    let mut contents = BTreeMap::new();
+    while let Some((key, value)) = iter.next().await? {
+        contents.insert(key, value);
+    }
+    Ok(contents)
+}
+
+// Runs the workload until the storage crashes, returning how many ops were
+// started and how many are sure to have been made durable.
+async fn run_until_crash(storage: &SimStorage, ops: &[Op]) -> (usize, usize) {
+    let mut db = None;
+    let (mut started, mut durable) = (0, 0);
+    for (i, op) in std::iter::once(&Op::Reopen).chain(ops).enumerate() {
+        started = i;
+        let ran = run(&mut db, storage, op).await;
+        // Something in the background may have hit the crash first.
+        if ran.is_err() || storage.crashed() {
+            break;
+        }
+        if op.syncs() {
+            durable = i;
+        }
+    }
+    drop(db);
+    (started.min(ops.len()), durable)
+}
+
+async fn simulate(seed: u64, faults: &SimFaults) {
+    let mut rng = Rng(seed);
+    let ops = workload(&mut rng, 120);
+
+    // A run without a crash counts the operations, so the crash can come at
+    // any of them.
+    let dry = SimStorage::new(seed, faults.clone());
+    run_until_crash(&dry, &ops).await;
+    let total = dry.ops();
+
+    let storage = SimStorage::new(seed, faults.clone());
+    storage.crash_after(1 + rng.below(total));
+    let (started, durable) = run_until_crash(&storage, &ops).await;
+    assert!(storage.crashed(), "seed {}: didn't crash", seed);
+
+    let storage = storage.restart();
+    let db = Db::with_options(DIR, options(&storage))
+        .await
+        .unwrap_or_else(|err| panic!("seed {}: can't reopen: {}", seed, err));
+    let recovered = contents(&db).await.unwrap();
+    let mut model = BTreeMap::new();
+    let mut matched = false;
+    for (i, op) in ops[..started].iter().enumerate() {
+        if i >= durable && model == recovered {
+            matched = true;
+            break;
+        }
+        apply(&mut model, op);
+    }
+    matched |= model == recovered;
+    assert!(
+        matched,
+        "seed {}: recovered {:?} isn't the state after any of ops {}..={}",
+        seed,
+        recovered.keys().collect::<Vec<_>>(),
+        durable,
+        started
+    );
+
+    // Files left over by the crash are fine, but nothing can be corrupt.
+    let report = db.verify_checksums().await.unwrap();
+    let problems: Vec<_> = report
+        .problems
+        .iter()
+        .filter(|problem| !matches!(problem, VerifyProblem::OrphanFile(_)))
+        .collect();
+    assert!(problems.is_empty(), "seed {}: {:?}", seed, problems);
+
+    // And it carries on working.
+    db.put(b"after", b"crash").await.unwrap();
+    db.close().await.unwrap();
+    let db = Db::with_options(DIR, options(&storage)).await.unwrap();
+    assert_eq!(db.get(b"after").await.unwrap(), Some(b"crash".to_vec()));
+}
+
+fn seeds() -> std::ops::Range<u64> {
+    let var = |name| std::env::var(name).ok().and_then(|n| n.parse().ok());
+    match var("SIM_SEED") {
+        Some(seed) => seed..seed + 1,
+        None => 0..var("SIM_SEEDS").unwrap_or(100),
+    }
+}
+
+#[tokio::test]
+async fn survives_losing_unsynced_writes() {
+    for seed in seeds() {
+        simulate(seed, &SimFaults::default()).await;
+    }
+}
+
+#[tokio::test]
+async fn survives_torn_writes_and_reordered_renames() {
+    let faults = SimFaults {
+        torn_writes: true,
+        reorder_renames: true,
+        max_sync_delay: 3,
+    };
+    for seed in seeds() {
+        simulate(seed, &faults).await;
+    }
+}
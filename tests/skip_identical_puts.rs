@@ -0,0 +1,49 @@
+use nulldb::{Db, DbOptions, FileKind, NdbError};
+use tempfile::TempDir;
+
+async fn wal_size(db: &Db) -> Result<u64, NdbError> {
+    let files = db.live_files().await?;
+    Ok(files.iter().find(|f| f.kind == FileKind::Wal).unwrap().size)
+}
+
+#[tokio::test]
+async fn skip_identical_puts() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        skip_identical_puts: true,
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+
+    db.put(b"k", b"v1").await?;
+    let logged = wal_size(&db).await?;
+    db.put(b"k", b"v1").await?;
+    db.put(b"k", b"v1").await?;
+    assert_eq!(wal_size(&db).await?, logged);
+    assert_eq!(db.stats().writes.skipped_puts, 2);
+    assert_eq!(db.stats().writes.total.puts, 1);
+
+    // A different value, or one after a delete, is a real write.
+    db.put(b"k", b"v2").await?;
+    db.delete(b"k").await?;
+    db.put(b"k", b"v2").await?;
+    assert_eq!(db.stats().writes.skipped_puts, 2);
+    assert_eq!(db.get(b"k").await?, Some(b"v2".to_vec()));
+
+    // Only the memtable is checked.
+    db.flush_memtable().await?;
+    db.put(b"k", b"v2").await?;
+    assert_eq!(db.stats().writes.skipped_puts, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn off_by_default() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"k", b"v").await?;
+    db.put(b"k", b"v").await?;
+    assert_eq!(db.stats().writes.skipped_puts, 0);
+    assert_eq!(db.stats().writes.total.puts, 2);
+    Ok(())
+}
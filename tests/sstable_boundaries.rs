@@ -0,0 +1,201 @@
+use nulldb::{Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+// Even numbers only, so every odd number falls in a gap between two keys.
+fn key(i: usize) -> Vec<u8> {
+    format!("key{:05}", i * 2).into_bytes()
+}
+
+fn value(i: usize) -> Vec<u8> {
+    format!("value{}", i).into_bytes()
+}
+
+// Builds a single flushed table holding `count` keys.
+async fn table(count: usize, block_size: usize) -> Result<(TempDir, Db), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        block_size,
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    for i in 0..count {
+        db.put(&key(i), &value(i)).await?;
+    }
+    db.flush_memtable().await?;
+    Ok((dir, db))
+}
+
+// Covers one entry per block, a handful of entries per block, and everything
+// in a single block, with counts around the restart interval of 16.
+const BLOCK_SIZES: [usize; 4] = [1, 64, 200, 4096];
+const COUNTS: [usize; 7] = [1, 2, 15, 16, 17, 33, 100];
+
+#[tokio::test]
+async fn empty_table() -> Result<(), NdbError> {
+    let (_dir, db) = table(0, 4096).await?;
+    for probe in [&b""[..], b"a", b"key00000", b"zzz"] {
+        assert_eq!(db.get(probe).await?, None);
+    }
+    assert_eq!(db.scan(..).await?.next().await?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn every_key_is_found() -> Result<(), NdbError> {
+    for block_size in BLOCK_SIZES {
+        for count in COUNTS {
+            let (_dir, db) = table(count, block_size).await?;
+            for i in 0..count {
+                assert_eq!(
+                    db.get(&key(i)).await?,
+                    Some(value(i)),
+                    "key {} of {}, block size {}",
+                    i,
+                    count,
+                    block_size
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn keys_outside_the_table() -> Result<(), NdbError> {
+    for block_size in BLOCK_SIZES {
+        for count in COUNTS {
+            let (_dir, db) = table(count, block_size).await?;
+            let mut past_end = key(count - 1);
+            past_end.push(0);
+            for probe in [&b""[..], b"a", b"key", b"key0000", &past_end, b"zzz"] {
+                assert_eq!(
+                    db.get(probe).await?,
+                    None,
+                    "{:?} in {} keys, block size {}",
+                    String::from_utf8_lossy(probe),
+                    count,
+                    block_size
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn keys_between_entries() -> Result<(), NdbError> {
+    for block_size in BLOCK_SIZES {
+        for count in COUNTS {
+            let (_dir, db) = table(count, block_size).await?;
+            for i in 0..count {
+                let gap = format!("key{:05}", i * 2 + 1).into_bytes();
+                assert_eq!(db.get(&gap).await?, None);
+                let mut just_after = key(i);
+                just_after.push(0);
+                assert_eq!(db.get(&just_after).await?, None);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn scans_starting_at_every_boundary() -> Result<(), NdbError> {
+    for block_size in BLOCK_SIZES {
+        for count in COUNTS {
+            let (_dir, db) = table(count, block_size).await?;
+            for start in 0..=count {
+                // Starting exactly on a key, and just before it.
+                let on_key = key(start);
+                let before_key = format!("key{:05}", (start * 2).saturating_sub(1)).into_bytes();
+                for from in [on_key, before_key] {
+                    let mut iter = db.scan(from..).await?;
+                    let mut expected = start;
+                    while let Some((k, v)) = iter.next().await? {
+                        assert_eq!((k, v), (key(expected), value(expected)));
+                        expected += 1;
+                    }
+                    assert_eq!(expected, count, "{} keys, block size {}", count, block_size);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn reverse_scans_ending_at_every_boundary() -> Result<(), NdbError> {
+    for block_size in BLOCK_SIZES {
+        for count in COUNTS {
+            let (_dir, db) = table(count, block_size).await?;
+            for end in 0..=count {
+                let on_key = key(end);
+                let after_key = format!("key{:05}", end * 2 + 1).into_bytes();
+                let scans = [
+                    (db.scan_rev(..=on_key.clone()).await?, end + 1),
+                    (db.scan_rev(..=after_key).await?, end + 1),
+                    (db.scan_rev(..on_key).await?, end),
+                ];
+                for (mut iter, expected_len) in scans {
+                    // Counting down from just below the end of the range.
+                    let mut expected = expected_len.min(count);
+                    while let Some((k, v)) = iter.next().await? {
+                        expected -= 1;
+                        assert_eq!((k, v), (key(expected), value(expected)));
+                    }
+                    assert_eq!(expected, 0, "{} keys, block size {}", count, block_size);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Evenly spread integer keys are searched by interpolating into the index,
+// which has to land on the same blocks as a binary search would, including
+// around a run of keys that doesn't follow the spread.
+#[tokio::test]
+async fn interpolated_index_search() -> Result<(), NdbError> {
+    let mut ids: Vec<u64> = (0..1000).map(|i| i * 100).collect();
+    ids.extend((1..=40).map(|i| 50_000 + i * 2));
+    ids.sort();
+
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        block_size: 1,
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    for &id in &ids {
+        db.put(&id.to_be_bytes(), b"v").await?;
+    }
+    db.flush_memtable().await?;
+
+    let mut interpolated = false;
+    for entry in std::fs::read_dir(dir.path())? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("MANIFEST")
+        {
+            interpolated |= std::fs::read_to_string(path)?.contains("Interpolation");
+        }
+    }
+    assert!(interpolated);
+
+    for (n, &id) in ids.iter().enumerate() {
+        assert_eq!(db.get(&id.to_be_bytes()).await?, Some(b"v".to_vec()));
+        let gap = (id + 1).to_be_bytes().to_vec();
+        assert_eq!(db.get(&gap).await?, None);
+
+        let mut iter = db.scan(gap..).await?;
+        let next = iter.next().await?.map(|(key, _)| key);
+        assert_eq!(next, ids.get(n + 1).map(|id| id.to_be_bytes().to_vec()));
+    }
+    for probe in [&b""[..], b"\xff", &u64::MAX.to_be_bytes()] {
+        assert_eq!(db.get(probe).await?, None);
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use nulldb::{Db, DbStats, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn reports_tables_io_and_backlog() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    let stats = db.stats();
+    assert!(stats.levels.is_empty());
+    assert_eq!(stats.write_amplification, 0.0);
+
+    db.put(b"a", b"1").await?;
+    db.put(b"a", b"2").await?;
+    db.put(b"b", b"3").await?;
+    let stats = db.stats();
+    assert_eq!(stats.memtable.keys, 2);
+    assert_eq!(stats.memtable.bytes, 6);
+    assert!(stats.io.wal_bytes_written > 0);
+
+    for i in 0..3u8 {
+        db.flush_memtable().await?;
+        db.put(&[b'k', i], b"v").await?;
+    }
+    let stats = db.stats();
+    assert_eq!(stats.memtable.keys, 1);
+    assert_eq!(stats.levels.len(), 1);
+    assert_eq!(stats.levels[0].tables, 3);
+    let table_bytes = stats.levels[0].bytes;
+    assert_eq!(stats.io.table_bytes_written, table_bytes);
+    assert!(stats.write_amplification > 1.0);
+    // Three small tables aren't enough for a vacuum.
+    assert_eq!(stats.vacuum_backlog.tables, 0);
+    db.flush_memtable().await?;
+    let stats = db.stats();
+    assert_eq!(stats.vacuum_backlog.tables, 4);
+    assert_eq!(stats.vacuum_backlog.bytes, stats.levels[0].bytes);
+
+    // Lookups outside a table's key range don't get as far as its bloom
+    // filter.
+    assert_eq!(db.get(b"a").await?, Some(b"2".to_vec()));
+    assert_eq!(db.get(b"ab").await?, None);
+    let stats = db.stats();
+    assert_eq!(stats.io.bloom_checks, 2);
+    assert_eq!(stats.io.bloom_negatives + stats.io.bloom_false_positives, 1);
+    assert!(stats.io.table_bytes_read > 0);
+
+    let json = serde_json::to_string(&stats)?;
+    let parsed: DbStats = serde_json::from_str(&json)?;
+    assert_eq!(parsed.levels[0].tables, 4);
+    assert_eq!(parsed.io.bloom_checks, 2);
+    Ok(())
+}
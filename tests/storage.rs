@@ -0,0 +1,152 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::{future::BoxFuture, StreamExt};
+use nulldb::{
+    Db, DbOptions, LocalStorage, NdbError, ReadableFile, Storage, VacuumOptions, WritableFile,
+};
+use tempfile::TempDir;
+
+// Keeps the files of databases under a made-up directory in a real one, so
+// anything that went around the storage would fail to find them.
+struct Rooted {
+    root: PathBuf,
+}
+
+const VIRTUAL: &str = "/nulldb-virtual";
+
+impl Rooted {
+    fn real(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix(VIRTUAL).unwrap())
+    }
+
+    fn virtual_path(&self, path: PathBuf) -> PathBuf {
+        Path::new(VIRTUAL).join(path.strip_prefix(&self.root).unwrap())
+    }
+}
+
+impl Storage for Rooted {
+    fn create<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        Box::pin(async move { LocalStorage.create(&self.real(path)).await })
+    }
+
+    fn append<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Box<dyn WritableFile>, NdbError>> {
+        Box::pin(async move { LocalStorage.append(&self.real(path)).await })
+    }
+
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Arc<dyn ReadableFile>, NdbError>> {
+        Box::pin(async move { LocalStorage.open(&self.real(path)).await })
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        Box::pin(async move { LocalStorage.rename(&self.real(from), &self.real(to)).await })
+    }
+
+    fn remove<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        Box::pin(async move { LocalStorage.remove(&self.real(path)).await })
+    }
+
+    fn truncate<'a>(&'a self, path: &'a Path, len: u64) -> BoxFuture<'a, Result<(), NdbError>> {
+        Box::pin(async move { LocalStorage.truncate(&self.real(path), len).await })
+    }
+
+    fn list<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Vec<PathBuf>, NdbError>> {
+        Box::pin(async move {
+            let files = LocalStorage.list(&self.real(dir)).await?;
+            Ok(files.into_iter().map(|f| self.virtual_path(f)).collect())
+        })
+    }
+
+    fn size<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<u64, NdbError>> {
+        Box::pin(async move { LocalStorage.size(&self.real(path)).await })
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<bool, NdbError>> {
+        Box::pin(async move { LocalStorage.exists(&self.real(path)).await })
+    }
+
+    fn create_dir_all<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<(), NdbError>> {
+        Box::pin(async move { LocalStorage.create_dir_all(&self.real(dir)).await })
+    }
+}
+
+fn options(storage: &Arc<Rooted>) -> DbOptions {
+    DbOptions {
+        storage: storage.clone(),
+        vacuum: Some(VacuumOptions::default()),
+        ..DbOptions::default()
+    }
+}
+
+#[tokio::test]
+async fn every_file_goes_through_the_storage() -> Result<(), NdbError> {
+    let root = TempDir::new()?;
+    let storage = Arc::new(Rooted {
+        root: root.path().into(),
+    });
+    let dir = Path::new(VIRTUAL).join("db");
+    let mut db = Db::with_options(&dir, options(&storage)).await?;
+    // Keeps the logs around for `updates_since`.
+    db.ack("consumer", 0).await?;
+    for i in 0..3u32 {
+        db.put(&i.to_be_bytes(), b"v").await?;
+        db.flush_memtable().await?;
+    }
+    db.vacuum().await?;
+    db.put(b"unflushed", b"v").await?;
+    db.ack("consumer", 2).await?;
+    let updates: Vec<_> = db.updates_since(0)?.take(4).collect().await;
+    assert_eq!(updates.len(), 4);
+    let checkpoint = Path::new(VIRTUAL).join("checkpoint");
+    db.checkpoint(&checkpoint).await?;
+    assert!(db
+        .live_files()
+        .await?
+        .iter()
+        .all(|f| f.path.starts_with(VIRTUAL)));
+    drop(db);
+    assert!(root.path().join("db").join("CURRENT").exists());
+
+    let db = Db::with_options(&dir, options(&storage)).await?;
+    assert_eq!(db.get(&2u32.to_be_bytes()).await?, Some(b"v".to_vec()));
+    assert_eq!(db.get(b"unflushed").await?, Some(b"v".to_vec()));
+    assert_eq!(db.acked("consumer").await, Some(2));
+    let copy = Db::open_read_only(&checkpoint, options(&storage)).await?;
+    assert_eq!(copy.get(b"unflushed").await?, Some(b"v".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn recovers_a_cut_off_log() -> Result<(), NdbError> {
+    let root = TempDir::new()?;
+    let storage = Arc::new(Rooted {
+        root: root.path().into(),
+    });
+    let dir = Path::new(VIRTUAL).join("db");
+    let db = Db::with_options(&dir, options(&storage)).await?;
+    db.put(b"a", b"1").await?;
+    drop(db);
+    let mut log = storage.append(&dir.join("log")).await?;
+    log.write(b"{\"seq\":2,\"Put\":").await?;
+    log.sync().await?;
+
+    let db = Db::with_options(&dir, options(&storage)).await?;
+    assert_eq!(db.stats().open.wal_records_dropped, 1);
+    db.put(b"b", b"2").await?;
+    drop(db);
+    let db = Db::with_options(&dir, options(&storage)).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(db.get(b"b").await?, Some(b"2".to_vec()));
+    Ok(())
+}
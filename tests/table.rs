@@ -0,0 +1,161 @@
+use std::collections::BTreeSet;
+
+use nulldb::{Db, DbOptions, NdbError, Table};
+use proptest::prelude::*;
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum Region {
+    East,
+    West(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct UserId {
+    region: Region,
+    name: String,
+    id: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct User {
+    email: String,
+    logins: u32,
+}
+
+fn user_id(region: Region, name: &str, id: i64) -> UserId {
+    UserId {
+        region,
+        name: name.into(),
+        id,
+    }
+}
+
+async fn keys<K, V>(table: &Table<'_, K, V>, reverse: bool) -> Result<Vec<K>, NdbError>
+where
+    K: Serialize + serde::de::DeserializeOwned,
+    V: Serialize + serde::de::DeserializeOwned,
+{
+    let mut iter = match reverse {
+        false => table.scan(..).await?,
+        true => table.scan_rev(..).await?,
+    };
+    let mut keys = Vec::new();
+    while let Some((key, _)) = iter.next().await? {
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+#[tokio::test]
+async fn reads_and_writes_typed_values() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let users: Table<UserId, User> = db.table(b"users/");
+    let ada = user_id(Region::West(2), "ada", -4);
+    let user = User {
+        email: "ada@example.com".into(),
+        logins: 3,
+    };
+    users.put(&ada, &user).await?;
+    assert_eq!(users.get(&ada).await?, Some(user));
+    assert_eq!(users.get(&user_id(Region::East, "ada", -4)).await?, None);
+    users.delete(&ada).await?;
+    assert_eq!(users.get(&ada).await?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn scans_in_key_order() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let users: Table<UserId, u32> = db.table(b"users/");
+    let ids = [
+        user_id(Region::West(1), "bo", 0),
+        user_id(Region::East, "ab", 7),
+        user_id(Region::East, "a", 9),
+        user_id(Region::East, "a\0", -1),
+        user_id(Region::East, "a", -9),
+        user_id(Region::West(0), "", i64::MAX),
+    ];
+    for (i, id) in ids.iter().enumerate() {
+        users.put(id, &(i as u32)).await?;
+    }
+    // Neighbouring prefixes don't show up.
+    db.put(b"users.", b"x").await?;
+    db.put(b"users0", b"x").await?;
+    db.table::<String, u32>(b"user")
+        .put(&"s".into(), &0)
+        .await?;
+
+    let mut sorted = ids.to_vec();
+    sorted.sort();
+    assert_eq!(keys(&users, false).await?, sorted);
+    sorted.reverse();
+    assert_eq!(keys(&users, true).await?, sorted);
+
+    let start = user_id(Region::East, "a", 0);
+    let end = user_id(Region::West(1), "", 0);
+    let mut iter = users.scan(&start..&end).await?;
+    let mut found = Vec::new();
+    while let Some((key, value)) = iter.next().await? {
+        found.push((key.name, value));
+    }
+    assert_eq!(
+        found,
+        [
+            ("a".into(), 2),
+            ("a\0".into(), 3),
+            ("ab".into(), 1),
+            ("".into(), 5)
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_keys_of_the_wrong_type() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.table::<String, u32>(b"t/").put(&"k".into(), &1).await?;
+    let mut iter = db.table::<u64, u32>(b"t/").scan(..).await?;
+    assert!(matches!(
+        iter.next().await,
+        Err(NdbError::Corruption { .. })
+    ));
+    Ok(())
+}
+
+// Small alphabets, so keys often share prefixes, or are prefixes of each
+// other.
+fn key() -> impl Strategy<Value = (Option<i32>, Vec<u8>, Vec<String>, bool)> {
+    (
+        any::<Option<i32>>(),
+        prop::collection::vec(0..3u8, 0..3),
+        prop::collection::vec("[a\\x00b]{0,3}", 0..3),
+        any::<bool>(),
+    )
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn keys_sort_like_their_type(keys in prop::collection::vec(key(), 1..40)) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let db = Db::with_options("db", DbOptions::in_memory()).await?;
+            let table = db.table::<_, ()>(b"");
+            for key in &keys {
+                table.put(key, &()).await?;
+            }
+            let sorted: Vec<_> = keys.iter().cloned().collect::<BTreeSet<_>>().into_iter().collect();
+            assert_eq!(self::keys(&table, false).await?, sorted);
+            Ok::<_, NdbError>(())
+        }).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+use nulldb::{Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+fn options() -> DbOptions {
+    DbOptions {
+        max_open_files: 2,
+        ..DbOptions::default()
+    }
+}
+
+async fn write_tables(db: &mut Db, tables: u32) -> Result<(), NdbError> {
+    for i in 0..tables {
+        db.put(format!("key{}", i).as_bytes(), b"v").await?;
+        db.flush_memtable().await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn keeps_to_the_open_file_limit() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    write_tables(&mut db, 6).await?;
+    drop(db);
+
+    let db = Db::with_options(dir.path(), options()).await?;
+    assert_eq!(db.stats().io.table_files_open, 2);
+    let opened = db.stats().io.table_file_opens;
+    assert_eq!(opened, 6);
+    for _ in 0..2 {
+        for i in 0..6 {
+            let key = format!("key{}", i);
+            assert_eq!(db.get(key.as_bytes()).await?, Some(b"v".to_vec()));
+            assert!(db.stats().io.table_files_open <= 2);
+        }
+    }
+    // Read round robin, every table but the two still open is opened again
+    // each time around.
+    assert!(db.stats().io.table_file_opens >= opened + 8);
+    Ok(())
+}
+
+#[tokio::test]
+async fn views_read_tables_deleted_since() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    write_tables(&mut db, 4).await?;
+    let view = db.freeze_view();
+    db.vacuum().await?;
+    let ssts = std::fs::read_dir(dir.path())?
+        .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("sst".as_ref()))
+        .count();
+    assert_eq!(ssts, 1);
+
+    for i in 0..4 {
+        let key = format!("key{}", i);
+        assert_eq!(view.get(key.as_bytes()).await?, Some(b"v".to_vec()));
+    }
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use nulldb::{Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+async fn flush_syncs(table_sync_bytes: Option<u64>) -> Result<(u64, u64), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        table_sync_bytes,
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    for i in 0..1000u32 {
+        db.put(&i.to_be_bytes(), &[0; 100]).await?;
+    }
+    db.flush_memtable().await?;
+    assert_eq!(db.get(&7u32.to_be_bytes()).await?, Some(vec![0; 100]));
+    let stats = db.stats();
+    Ok((stats.io.table_syncs, stats.levels[0].bytes))
+}
+
+#[tokio::test]
+async fn syncs_tables_as_they_are_written() -> Result<(), NdbError> {
+    assert_eq!(flush_syncs(None).await?.0, 1);
+    let (syncs, bytes) = flush_syncs(Some(16 << 10)).await?;
+    // One every 16 KiB, and one at the end.
+    assert_eq!(syncs, bytes / (16 << 10) + 1);
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use nulldb::{
+    ColdTier, Db, DbOptions, LocalStorage, ManualClock, NdbError, StorageTier, TierStats,
+};
+use tempfile::TempDir;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+fn options(clock: &Arc<ManualClock>, cold: &Path) -> DbOptions {
+    DbOptions {
+        clock: clock.clone(),
+        cold_tier: Some(ColdTier {
+            dir: cold.into(),
+            storage: Arc::new(LocalStorage),
+            after: HOUR,
+        }),
+        ..DbOptions::default()
+    }
+}
+
+fn tables_in(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .is_some_and(|ext| ext == "sst")
+        })
+        .count()
+}
+
+fn tier(tiers: &[TierStats], tier: StorageTier) -> &TierStats {
+    tiers.iter().find(|t| t.tier == tier).unwrap()
+}
+
+#[tokio::test]
+async fn moves_cold_tables_to_the_cold_tier() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let cold = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH + 1000 * HOUR));
+    let mut db = Db::with_options(dir.path(), options(&clock, cold.path())).await?;
+    for i in 0..2u32 {
+        db.put(&i.to_be_bytes(), b"old").await?;
+        db.flush_memtable().await?;
+    }
+    clock.advance(2 * HOUR);
+    db.put(b"new", b"v").await?;
+    db.flush_memtable().await?;
+    assert_eq!(db.vacuum().await?, 0);
+
+    assert_eq!(tables_in(dir.path()), 1);
+    assert_eq!(tables_in(cold.path()), 2);
+    let stats = db.stats();
+    assert_eq!(tier(&stats.tiers, StorageTier::Hot).tables, 1);
+    assert_eq!(tier(&stats.tiers, StorageTier::Cold).tables, 2);
+    assert!(tier(&stats.tiers, StorageTier::Cold).bytes > 0);
+    assert_eq!(db.get(&0u32.to_be_bytes()).await?, Some(b"old".to_vec()));
+    drop(db);
+
+    let db = Db::with_options(dir.path(), options(&clock, cold.path())).await?;
+    assert_eq!(db.get(&1u32.to_be_bytes()).await?, Some(b"old".to_vec()));
+    assert_eq!(db.get(b"new").await?, Some(b"v".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn merges_cold_runs_into_the_cold_tier() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let cold = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH + 1000 * HOUR));
+    let mut db = Db::with_options(dir.path(), options(&clock, cold.path())).await?;
+    for i in 0..4u32 {
+        db.put(&i.to_be_bytes(), b"v").await?;
+        db.flush_memtable().await?;
+    }
+    clock.advance(2 * HOUR);
+    assert_eq!(db.vacuum().await?, 3);
+    assert_eq!(tables_in(dir.path()), 0);
+    assert_eq!(tables_in(cold.path()), 1);
+    for i in 0..4u32 {
+        assert_eq!(db.get(&i.to_be_bytes()).await?, Some(b"v".to_vec()));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn tiers_are_only_reported_when_set() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    assert!(db.stats().tiers.is_empty());
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::Arc,
+};
+
+use nulldb_client::{
+    rustls::{self, pki_types::CertificateDer, RootCertStore},
+    ClientError, ClientOptions, NulldbClient,
+};
+use tempfile::TempDir;
+
+// A server with a self-signed certificate for 127.0.0.1 and the token
+// "s3cret", killed when it's dropped.
+struct Server {
+    child: Child,
+    addr: String,
+    cert: CertificateDer<'static>,
+}
+
+impl Server {
+    fn start(dir: &Path) -> Server {
+        let certified = rcgen::generate_simple_self_signed(vec!["127.0.0.1".into()]).unwrap();
+        let (cert, key, token) = (dir.join("cert.pem"), dir.join("key.pem"), dir.join("token"));
+        std::fs::write(&cert, certified.cert.pem()).unwrap();
+        std::fs::write(&key, certified.signing_key.serialize_pem()).unwrap();
+        std::fs::write(&token, "s3cret\n").unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ndb-server"))
+            .args(["--addr", "127.0.0.1:0", "--tls-cert"])
+            .args([&cert, Path::new("--tls-key"), &key])
+            .args([Path::new("--auth-token-file"), &token])
+            .arg(dir.join("db"))
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let addr = line.trim().strip_prefix("listening on ").unwrap().into();
+        Server {
+            child,
+            addr,
+            cert: certified.cert.der().clone(),
+        }
+    }
+
+    // Options trusting the server's certificate, with `token`.
+    fn options(&self, token: Option<&str>) -> ClientOptions {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.cert.clone()).unwrap();
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        ClientOptions {
+            token: token.map(Into::into),
+            tls: Some(Arc::new(config)),
+            ..ClientOptions::default()
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[tokio::test]
+async fn serves_clients_over_tls() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path());
+    let options = server.options(Some("s3cret"));
+    let client = NulldbClient::connect_with(&server.addr, options)
+        .await
+        .unwrap();
+    client.put(b"key", b"value").await.unwrap();
+    assert_eq!(client.get(b"key").await.unwrap(), Some(b"value".to_vec()));
+
+    // Clients that don't speak TLS don't get anywhere.
+    let options = ClientOptions {
+        token: Some("s3cret".into()),
+        retries: 0,
+        ..ClientOptions::default()
+    };
+    assert!(NulldbClient::connect_with(&server.addr, options)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn turns_away_clients_without_the_token() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(dir.path());
+    let options = server.options(Some("nope"));
+    let err = NulldbClient::connect_with(&server.addr, options)
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&err, ClientError::Server(message) if message == "wrong token"),
+        "{}",
+        err
+    );
+
+    let client = NulldbClient::connect_with(&server.addr, server.options(None))
+        .await
+        .unwrap();
+    let err = client.get(b"key").await.unwrap_err();
+    assert!(
+        matches!(&err, ClientError::Server(message) if message.contains("authentication")),
+        "{}",
+        err
+    );
+}
@@ -0,0 +1,99 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use nulldb::{Db, DbOptions, ManualClock, NdbError};
+use tempfile::TempDir;
+use tracing::subscriber::DefaultGuard;
+use tracing_subscriber::util::SubscriberInitExt;
+
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+// Collects what's traced on this thread until the guard is dropped.
+fn capture() -> (Output, DefaultGuard) {
+    let output = Output::default();
+    let writer = output.clone();
+    let guard = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish()
+        .set_default();
+    (output, guard)
+}
+
+#[tokio::test]
+async fn traces_flushes_and_replays() -> Result<(), NdbError> {
+    let (output, _guard) = capture();
+
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+    drop(db);
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+
+    let output = output.text();
+    // The last line with `message`.
+    let line = |message: &str| {
+        output
+            .lines()
+            .rfind(|line| line.contains(message))
+            .unwrap_or_else(|| panic!("no {:?} in:\n{}", message, output))
+            .to_string()
+    };
+    let flushed = line("flushed memtable");
+    assert!(flushed.contains("flush_memtable"), "{}", flushed);
+    assert!(flushed.contains("keys=1"), "{}", flushed);
+    assert!(line("wrote table").contains("bytes="));
+    assert!(line("opened table").contains("blocks=1"));
+    assert!(line("replayed log").contains("records=1"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn warns_through_tracing() -> Result<(), NdbError> {
+    let (output, _guard) = capture();
+    let dir = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+    let options = DbOptions {
+        clock: clock.clone(),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    clock.set(UNIX_EPOCH + Duration::from_secs(500));
+    db.put(b"a", b"2").await?;
+    db.flush_memtable().await?;
+
+    let output = output.text();
+    let warning = (output.lines())
+        .find(|line| line.contains("clock went backwards"))
+        .unwrap_or_else(|| panic!("no warning in:\n{}", output));
+    assert!(warning.contains("WARN"), "{}", warning);
+    assert!(warning.contains("previous=1000"), "{}", warning);
+    assert!(warning.contains("now=500"), "{}", warning);
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::{sync::Arc, time::Duration};
+
+use nulldb::{Db, DbOptions, NdbError};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn reads_own_writes_and_commits() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+
+    let mut txn = db.transaction();
+    assert_eq!(txn.get(b"a").await?, Some(b"1".to_vec()));
+    txn.put(b"a", b"2");
+    txn.delete(b"b");
+    txn.put(b"c", b"3");
+    assert_eq!(txn.get(b"a").await?, Some(b"2".to_vec()));
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    txn.commit().await?;
+
+    assert_eq!(db.get(b"a").await?, Some(b"2".to_vec()));
+    assert_eq!(db.get(b"c").await?, Some(b"3".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn conflicts_when_a_read_changes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+
+    let mut txn = db.transaction();
+    assert_eq!(txn.get(b"a").await?, None);
+    txn.put(b"b", b"from txn");
+    db.put(b"a", b"1").await?;
+    assert!(matches!(txn.commit().await, Err(NdbError::Conflict)));
+    assert_eq!(db.get(b"b").await?, None);
+
+    // Blind writes to the same key aren't conflicts.
+    let mut txn = db.transaction();
+    txn.put(b"a", b"2");
+    db.put(b"a", b"3").await?;
+    txn.commit().await?;
+    assert_eq!(db.get(b"a").await?, Some(b"2".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn reads_as_of_the_start() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+
+    let mut txn = db.transaction();
+    db.put(b"b", b"1").await?;
+    assert_eq!(txn.get(b"b").await?, None);
+    db.put(b"a", b"2").await?;
+    assert_eq!(txn.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(txn.get(b"a").await?, Some(b"1".to_vec()));
+    assert!(matches!(txn.commit().await, Err(NdbError::Conflict)));
+    Ok(())
+}
+
+// Read-modify-write transactions that retry on conflict never lose an update.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_increments() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Arc::new(Db::new(dir.path()).await?);
+
+    let workers: Vec<_> = (0..8)
+        .map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for _ in 0..25 {
+                    loop {
+                        let mut txn = db.transaction();
+                        let n = txn
+                            .get(b"counter")
+                            .await?
+                            .map_or(0, |v| u16::from_be_bytes([v[0], v[1]]));
+                        txn.put(b"counter", &(n + 1).to_be_bytes());
+                        match txn.commit().await {
+                            Ok(()) => break,
+                            Err(NdbError::Conflict) => continue,
+                            Err(err) => return Err(err),
+                        }
+                    }
+                }
+                Ok::<_, NdbError>(())
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.await.unwrap()?;
+    }
+    assert_eq!(
+        db.get(b"counter").await?,
+        Some(200u16.to_be_bytes().to_vec())
+    );
+    Ok(())
+}
+
+// Transactions that lock the counter take turns, so none of them conflict.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn locked_increments_never_conflict() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Arc::new(Db::new(dir.path()).await?);
+
+    let workers: Vec<_> = (0..8)
+        .map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for _ in 0..25 {
+                    let mut txn = db.transaction();
+                    let n = txn
+                        .get_for_update(b"counter")
+                        .await?
+                        .map_or(0, |v| u16::from_be_bytes([v[0], v[1]]));
+                    txn.put(b"counter", &(n + 1).to_be_bytes());
+                    txn.commit().await?;
+                }
+                Ok::<_, NdbError>(())
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.await.unwrap()?;
+    }
+    assert_eq!(
+        db.get(b"counter").await?,
+        Some(200u16.to_be_bytes().to_vec())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn deadlocks_time_out() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        lock_timeout: Duration::from_millis(50),
+        ..DbOptions::default()
+    };
+    let db = Db::with_options(dir.path(), options).await?;
+
+    let mut first = db.transaction();
+    let mut second = db.transaction();
+    first.get_for_update(b"a").await?;
+    second.get_for_update(b"b").await?;
+    let (a, b) = tokio::join!(first.get_for_update(b"b"), second.get_for_update(b"a"));
+    assert!(matches!(a, Err(NdbError::LockTimeout)));
+    assert!(matches!(b, Err(NdbError::LockTimeout)));
+
+    // Dropping a transaction releases its locks.
+    drop(first);
+    second.get_for_update(b"a").await?;
+    second.commit().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn puts_with_a_ttl() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let mut txn = db.transaction();
+    txn.put_with_ttl(b"a", b"1", Duration::from_secs(100));
+    assert_eq!(txn.get(b"a").await?, Some(b"1".to_vec()));
+    txn.commit().await?;
+    let (value, expires_at) = db.get_with_expiry(b"a").await?.unwrap();
+    assert_eq!(value, b"1");
+    assert!(expires_at.is_some());
+    Ok(())
+}
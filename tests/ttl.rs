@@ -0,0 +1,133 @@
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use nulldb::{Db, DbOptions, ManualClock, NdbError};
+use tempfile::TempDir;
+
+fn options(clock: &Arc<ManualClock>) -> DbOptions {
+    DbOptions {
+        clock: clock.clone(),
+        ..DbOptions::default()
+    }
+}
+
+async fn keys(db: &Db) -> Result<Vec<Vec<u8>>, NdbError> {
+    let mut iter = db.scan(..).await?;
+    let mut keys = Vec::new();
+    while let Some((key, _)) = iter.next().await? {
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+#[tokio::test]
+async fn expired_values_read_as_absent() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+    let mut db = Db::with_options(dir.path(), options(&clock)).await?;
+
+    db.put(b"a", b"old").await?;
+    db.flush_memtable().await?;
+    db.put_with_ttl(b"a", b"new", Duration::from_secs(10))
+        .await?;
+    db.put_with_ttl(b"b", b"short", Duration::from_secs(5))
+        .await?;
+    db.put(b"c", b"forever").await?;
+
+    assert_eq!(db.get(b"a").await?, Some(b"new".to_vec()));
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(db.get(b"b").await?, None);
+    assert_eq!(keys(&db).await?, [b"a".to_vec(), b"c".to_vec()]);
+
+    // An expired value doesn't bring back the one it replaced, whether it's
+    // in the memtable or a table.
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(db.get(b"a").await?, None);
+    db.flush_memtable().await?;
+    assert_eq!(db.get(b"a").await?, None);
+    assert_eq!(
+        db.multi_get(&[b"a", b"c"]).await?,
+        [None, Some(b"forever".to_vec())]
+    );
+    assert_eq!(keys(&db).await?, [b"c".to_vec()]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn expiry_survives_reopen() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+    let db = Db::with_options(dir.path(), options(&clock)).await?;
+    db.put_with_ttl(b"k", b"v", Duration::from_secs(10)).await?;
+    drop(db);
+
+    let db = Db::with_options(dir.path(), options(&clock)).await?;
+    assert_eq!(db.get(b"k").await?, Some(b"v".to_vec()));
+    let expires_at = UNIX_EPOCH + Duration::from_secs(1010);
+    assert_eq!(
+        db.get_with_expiry(b"k").await?,
+        Some((b"v".to_vec(), Some(expires_at)))
+    );
+    db.put(b"forever", b"v").await?;
+    assert_eq!(
+        db.get_with_expiry(b"forever").await?,
+        Some((b"v".to_vec(), None))
+    );
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(db.get(b"k").await?, None);
+    assert_eq!(db.get_with_expiry(b"k").await?, None);
+    Ok(())
+}
+
+// Compacting drops what has expired and keeps the expiry of what hasn't.
+#[tokio::test]
+async fn compaction_drops_expired_values() -> Result<(), NdbError> {
+    let base = TempDir::new()?;
+    let delta = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+    Db::with_options(base.path(), options(&clock)).await?;
+
+    let mut db = Db::open_overlay(base.path(), delta.path(), options(&clock)).await?;
+    db.put_with_ttl(b"expired", b"v", Duration::from_secs(5))
+        .await?;
+    db.put_with_ttl(b"live", b"v", Duration::from_secs(20))
+        .await?;
+    clock.advance(Duration::from_secs(10));
+    db.compact_into_base().await?;
+    drop(db);
+
+    // Going back in time shows what the compacted table actually holds.
+    clock.set(UNIX_EPOCH + Duration::from_secs(1000));
+    let db = Db::with_options(base.path(), options(&clock)).await?;
+    assert_eq!(keys(&db).await?, [b"live".to_vec()]);
+    clock.set(UNIX_EPOCH + Duration::from_secs(1020));
+    assert_eq!(db.get(b"live").await?, None);
+    Ok(())
+}
+
+// However long the ttl, putting it doesn't overflow, and it doesn't wrap
+// around into an expiry that's already passed.
+#[tokio::test]
+async fn ttls_too_long_to_store_never_expire() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+    let db = Db::with_options(dir.path(), options(&clock)).await?;
+    let ttls = [Duration::MAX, Duration::from_secs(1 << 62)];
+    for (i, ttl) in ttls.into_iter().enumerate() {
+        db.put_with_ttl(format!("db-{}", i).as_bytes(), b"v", ttl)
+            .await?;
+        let mut txn = db.transaction();
+        txn.put_with_ttl(format!("txn-{}", i).as_bytes(), b"v", ttl);
+        txn.commit().await?;
+    }
+    clock.advance(Duration::from_secs(1 << 40));
+    for key in ["db-0", "db-1", "txn-0", "txn-1"] {
+        assert_eq!(
+            db.get_with_expiry(key.as_bytes()).await?,
+            Some((b"v".to_vec(), None))
+        );
+    }
+    Ok(())
+}
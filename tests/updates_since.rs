@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use nulldb::{BatchOp, Db, FileKind, LiveFile, NdbError, Update, WriteBatch};
+use tempfile::TempDir;
+
+fn keys(update: &Update) -> Vec<&[u8]> {
+    update
+        .batch
+        .ops()
+        .map(|op| match op {
+            BatchOp::Put { key, .. } | BatchOp::Delete { key } => key,
+            op => panic!("unexpected {:?}", op),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn follows_new_writes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    let mut batch = WriteBatch::new();
+    batch.put(b"b", b"2");
+    batch.delete(b"a");
+    db.write(batch).await?;
+
+    let updates = db.updates_since(0)?;
+    futures::pin_mut!(updates);
+    let first = updates.next().await.unwrap()?;
+    assert_eq!((first.seq, keys(&first)), (1, vec![&b"a"[..]]));
+    let second = updates.next().await.unwrap()?;
+    assert_eq!((second.seq, keys(&second)), (2, vec![&b"b"[..], b"a"]));
+
+    let (update, written) = tokio::join!(updates.next(), async {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        db.put(b"c", b"3").await
+    });
+    written?;
+    let third = update.unwrap()?;
+    assert_eq!((third.seq, keys(&third)), (3, vec![&b"c"[..]]));
+
+    let mut later = Box::pin(db.updates_since(2)?);
+    assert_eq!(later.next().await.unwrap()?.seq, 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn keeps_logs_until_acked() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.ack("indexer", 0).await?;
+    db.put(b"a", b"1").await?;
+    db.flush_memtable().await?;
+    db.put(b"b", b"2").await?;
+
+    let wals = |files: Vec<LiveFile>| {
+        let wals = files.into_iter().filter(|f| f.kind == FileKind::Wal);
+        wals.map(|f| f.path).collect::<Vec<_>>()
+    };
+    // The current log, then the one from before the flush.
+    let retained = wals(db.live_files().await?);
+    assert_eq!(retained.len(), 2);
+    let seqs: Vec<_> = db
+        .updates_since(0)?
+        .take(2)
+        .map(|update| update.map(|update| update.seq))
+        .collect()
+        .await;
+    assert_eq!(seqs.into_iter().collect::<Result<Vec<_>, _>>()?, [1, 2]);
+
+    db.ack("indexer", 1).await?;
+    db.flush_memtable().await?;
+    assert!(retained[0].exists());
+    assert!(!retained[1].exists());
+    assert!(matches!(
+        db.updates_since(0).map(|_| ()),
+        Err(NdbError::InvalidArgument(_))
+    ));
+    drop(db);
+
+    let db = Db::new(dir.path()).await?;
+    db.put(b"c", b"3").await?;
+    let mut updates = Box::pin(db.updates_since(1)?);
+    assert_eq!(updates.next().await.unwrap()?.seq, 2);
+    assert_eq!(updates.next().await.unwrap()?.seq, 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn deletes_logs_without_consumers() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    let files = db.live_files().await?;
+    let wal = files.iter().find(|f| f.kind == FileKind::Wal).unwrap();
+    db.flush_memtable().await?;
+    assert!(!wal.path.exists());
+    assert!(db.updates_since(0).is_err());
+    assert!(db.updates_since(1).is_ok());
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use nulldb::{Db, DbOptions, FileKind, NdbError, VacuumOptions};
+use tempfile::TempDir;
+
+async fn table_count(db: &Db) -> Result<usize, NdbError> {
+    let files = db.live_files().await?;
+    Ok(files.iter().filter(|f| f.kind == FileKind::Table).count())
+}
+
+#[tokio::test]
+async fn merges_small_tables_after_flushes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        vacuum: Some(VacuumOptions {
+            small_table_bytes: 1000,
+            min_tables: 3,
+            target_table_bytes: 1 << 20,
+        }),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    // A big table underneath, which is left alone but still has to be
+    // hidden by the deletes merged above it.
+    for i in 0..100 {
+        db.put(format!("big-{:03}", i).as_bytes(), b"old").await?;
+    }
+    db.flush_memtable().await?;
+
+    db.put(b"a", b"1").await?;
+    db.put(b"big-000", b"new").await?;
+    db.flush_memtable().await?;
+    db.delete(b"a").await?;
+    db.delete(b"big-001").await?;
+    db.flush_memtable().await?;
+    assert_eq!(table_count(&db).await?, 3);
+    db.delete_range(b"big-050", b"big-060").await?;
+    db.put(b"a", b"2").await?;
+    db.flush_memtable().await?;
+    assert_eq!(table_count(&db).await?, 2);
+
+    for reopen in [false, true] {
+        if reopen {
+            drop(db);
+            db = Db::new(dir.path()).await?;
+        }
+        assert_eq!(db.get(b"a").await?, Some(b"2".to_vec()));
+        assert_eq!(db.get(b"big-000").await?, Some(b"new".to_vec()));
+        assert_eq!(db.get(b"big-001").await?, None);
+        assert_eq!(db.get(b"big-002").await?, Some(b"old".to_vec()));
+        assert_eq!(db.get(b"big-055").await?, None);
+        assert_eq!(db.get(b"big-060").await?, Some(b"old".to_vec()));
+        let mut iter = db.scan(..).await?;
+        let mut count = 0;
+        while iter.next().await?.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 1 + 100 - 1 - 10);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn leaves_short_runs() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    for i in 0..3 {
+        db.put(b"k", &[i]).await?;
+        db.flush_memtable().await?;
+    }
+    // The default needs four small tables in a row.
+    assert_eq!(db.vacuum().await?, 0);
+    db.put(b"k", b"last").await?;
+    db.flush_memtable().await?;
+    assert_eq!(db.vacuum().await?, 3);
+    assert_eq!(table_count(&db).await?, 1);
+    assert_eq!(db.get(b"k").await?, Some(b"last".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn merges_in_the_background() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        write_buffer_size: Some(4096),
+        vacuum: Some(VacuumOptions {
+            min_tables: 3,
+            ..VacuumOptions::default()
+        }),
+        ..DbOptions::default()
+    };
+    let key = |i: u32| format!("key-{:04}", i).into_bytes();
+    let db = Db::with_options(dir.path(), options.clone()).await?;
+    let mut most = 0;
+    for i in 0..2000 {
+        db.put(&key(i), &[b'v'; 20]).await?;
+        let tables = db.stats().levels.iter().map(|level| level.tables).sum();
+        most = most.max(tables);
+    }
+    // Without merging, there'd be a table for every 4KiB of memtable.
+    assert!(most < 10, "{} tables", most);
+    db.close().await?;
+
+    let db = Db::with_options(dir.path(), options).await?;
+    for i in 0..2000 {
+        assert_eq!(db.get(&key(i)).await?, Some(vec![b'v'; 20]));
+    }
+    Ok(())
+}
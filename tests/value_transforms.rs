@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use nulldb::{Db, DbOptions, NdbError, ValueTransform};
+use tempfile::TempDir;
+
+// Stands in for encryption: XORs every byte with the tenant's key.
+struct Xor(u8);
+
+impl ValueTransform for Xor {
+    fn id(&self) -> u32 {
+        self.0 as u32
+    }
+
+    fn encode(&self, _key: &[u8], value: &[u8]) -> Result<Vec<u8>, NdbError> {
+        Ok(value.iter().map(|b| b ^ self.0).collect())
+    }
+
+    fn decode(&self, key: &[u8], encoded: &[u8]) -> Result<Vec<u8>, NdbError> {
+        self.encode(key, encoded)
+    }
+}
+
+fn options() -> DbOptions {
+    DbOptions {
+        value_transforms: vec![
+            (
+                b"tenant/".to_vec(),
+                Arc::new(Xor(1)) as Arc<dyn ValueTransform>,
+            ),
+            (b"tenant/b/".to_vec(), Arc::new(Xor(2))),
+        ],
+        ..DbOptions::default()
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[tokio::test]
+async fn values_are_stored_transformed() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    db.put(b"public", b"plain value").await?;
+    db.put(b"tenant/a/1", b"secret value a").await?;
+    db.put(b"tenant/b/1", b"secret value b").await?;
+
+    // The log holds values as JSON arrays of bytes.
+    let log = std::fs::read(dir.path().join("log"))?;
+    let json = |bytes: &[u8]| {
+        serde_json::to_string(bytes)
+            .unwrap()
+            .replace(['[', ']'], "")
+    };
+    assert!(contains(&log, json(b"plain value").as_bytes()));
+    assert!(!contains(&log, json(b"secret value").as_bytes()));
+
+    for reopen in [false, true] {
+        if reopen {
+            db.flush_memtable().await?;
+            drop(db);
+            db = Db::with_options(dir.path(), options()).await?;
+        }
+        assert_eq!(
+            db.get(b"tenant/a/1").await?,
+            Some(b"secret value a".to_vec())
+        );
+        let values = db.multi_get(&[b"public", b"tenant/b/1"]).await?;
+        assert_eq!(values[1], Some(b"secret value b".to_vec()));
+        let mut iter = db.scan(..).await?;
+        let mut values = Vec::new();
+        while let Some((_, value)) = iter.next().await? {
+            values.push(value);
+        }
+        assert_eq!(
+            values,
+            [&b"plain value"[..], b"secret value a", b"secret value b"]
+        );
+        let view = db.freeze_view();
+        assert_eq!(
+            view.get(b"tenant/b/1").await?,
+            Some(b"secret value b".to_vec())
+        );
+    }
+
+    for table in std::fs::read_dir(dir.path())? {
+        let path = table?.path();
+        if path.extension().is_some_and(|ext| ext == "sst") {
+            let data = std::fs::read(path)?;
+            assert!(contains(&data, b"plain value"));
+            assert!(!contains(&data, b"secret value"));
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn merges_are_rejected_under_a_transform() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        merge_operator: Some(nulldb::counter_merge_operator()),
+        ..options()
+    };
+    let db = Db::with_options(dir.path(), options).await?;
+    assert!(matches!(
+        db.merge(b"tenant/a/count", &1i64.to_le_bytes()).await,
+        Err(NdbError::InvalidArgument(_))
+    ));
+    db.merge(b"count", &1i64.to_le_bytes()).await?;
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::io::Write;
+
+use nulldb::{Db, DbOptions, FileKind, NdbError, VerifyProblem};
+use tempfile::TempDir;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key-{:03}", i).into_bytes()
+}
+
+async fn open(dir: &TempDir) -> Result<Db, NdbError> {
+    let options = DbOptions {
+        block_size: 64,
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    for i in 0..100 {
+        db.put(&key(i), b"value").await?;
+    }
+    db.flush_memtable().await?;
+    for i in 100..110 {
+        db.put(&key(i), b"value").await?;
+    }
+    Ok(db)
+}
+
+#[tokio::test]
+async fn passes_a_healthy_database() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = open(&dir).await?;
+    let report = db.verify_checksums().await?;
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(report.tables, 1);
+    assert!(report.blocks > 10);
+    assert_eq!(report.log_records, 10);
+
+    drop(db);
+    let db = Db::open_read_only(dir.path(), DbOptions::default()).await?;
+    assert!(db.verify_checksums().await?.is_ok());
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_what_is_wrong() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = open(&dir).await?;
+    db.put(b"z", b"in the second table").await?;
+    db.flush_memtable().await?;
+    db.put(b"k", b"v").await?;
+    let files = db.live_files().await?;
+    let tables: Vec<_> = files.iter().filter(|f| f.kind == FileKind::Table).collect();
+    let (fresh, old) = (tables[0].path.clone(), tables[1].path.clone());
+    let wal = files.iter().find(|f| f.kind == FileKind::Wal).unwrap();
+
+    // A flipped byte in the first block of one table, the other gone, a
+    // table nothing lists, and garbage in the log.
+    let mut data = std::fs::read(&old)?;
+    data[10] ^= 0xff;
+    std::fs::write(&old, data)?;
+    std::fs::remove_file(&fresh)?;
+    let orphan = dir.path().join("123.sst");
+    std::fs::write(&orphan, b"left behind")?;
+    std::fs::write(dir.path().join("unrelated.txt"), b"not ours")?;
+    let mut log = std::fs::OpenOptions::new().append(true).open(&wal.path)?;
+    log.write_all(b"{not json\n")?;
+
+    let problems = db.verify_checksums().await?.problems;
+    assert_eq!(problems.len(), 5, "{:?}", problems);
+    assert!(problems.iter().any(|p| matches!(p,
+        VerifyProblem::CorruptBlock(block) if block.path == old && block.offset == 0)));
+    assert!(problems.iter().any(|p| matches!(p,
+        VerifyProblem::ChecksumMismatch { path, .. } if *path == old)));
+    assert!(problems.contains(&VerifyProblem::MissingFile {
+        path: fresh,
+        kind: FileKind::Table,
+    }));
+    assert!(problems.contains(&VerifyProblem::OrphanFile(orphan)));
+    assert!(problems.iter().any(|p| matches!(p,
+        VerifyProblem::CorruptLogRecord { path, .. } if *path == wal.path)));
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::{io::Write, path::Path};
+
+use nulldb::{Db, DbOptions, NdbError, WalRecovery};
+use tempfile::TempDir;
+
+fn options(wal_recovery: WalRecovery) -> DbOptions {
+    DbOptions {
+        wal_recovery,
+        ..DbOptions::default()
+    }
+}
+
+// Writes three records, then rewrites the log with `damage` applied to its
+// lines.
+async fn damaged(damage: impl FnOnce(&mut Vec<String>)) -> Result<TempDir, NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    for key in [b"a", b"b", b"c"] {
+        db.put(key, b"1").await?;
+    }
+    drop(db);
+    let log = dir.path().join("log");
+    let contents = std::fs::read_to_string(&log)?;
+    let (header, records) = contents.split_once('\n').unwrap();
+    let mut lines: Vec<_> = records.lines().map(String::from).collect();
+    damage(&mut lines);
+    let mut file = std::fs::File::create(&log)?;
+    writeln!(file, "{}", header)?;
+    file.write_all(lines.concat().as_bytes())?;
+    Ok(dir)
+}
+
+fn newline_terminated(lines: &mut [String]) {
+    for line in lines {
+        line.push('\n');
+    }
+}
+
+async fn keys(dir: &Path, recovery: WalRecovery) -> Result<Vec<&'static str>, NdbError> {
+    let db = Db::with_options(dir, options(recovery)).await?;
+    let mut found = Vec::new();
+    for key in ["a", "b", "c", "d"] {
+        if db.get(key.as_bytes()).await?.is_some() {
+            found.push(key);
+        }
+    }
+    Ok(found)
+}
+
+#[tokio::test]
+async fn drops_a_torn_tail_and_keeps_writing() -> Result<(), NdbError> {
+    // The last record was cut off partway through.
+    let dir = damaged(|lines| {
+        newline_terminated(&mut lines[..2]);
+        lines[2].truncate(10);
+    })
+    .await?;
+    let err = keys(dir.path(), WalRecovery::AbsoluteConsistency).await;
+    assert!(matches!(err, Err(NdbError::Corruption { .. })));
+
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.stats().open.wal_records_dropped, 1);
+    assert_eq!(db.get(b"b").await?, Some(b"1".to_vec()));
+    assert_eq!(db.get(b"c").await?, None);
+    // What's written next isn't lost behind the torn record.
+    db.put(b"d", b"1").await?;
+    drop(db);
+    assert_eq!(
+        keys(dir.path(), WalRecovery::AbsoluteConsistency).await?,
+        ["a", "b", "d"]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn corruption_before_good_records() -> Result<(), NdbError> {
+    let dir = damaged(|lines| {
+        lines[1] = "garbage".into();
+        newline_terminated(lines);
+    })
+    .await?;
+    let err = keys(dir.path(), WalRecovery::TolerateCorruptedTailRecords).await;
+    assert!(matches!(err, Err(NdbError::Corruption { .. })));
+
+    let recovery = WalRecovery::SkipAnyCorruptedRecords;
+    let db = Db::with_options(dir.path(), options(recovery)).await?;
+    assert_eq!(db.stats().open.wal_records_dropped, 1);
+    drop(db);
+    assert_eq!(keys(dir.path(), recovery).await?, ["a", "c"]);
+    Ok(())
+}
+
+// Nothing could be written after a record with the highest sequence number
+// there is, so it's as corrupt as any other.
+#[tokio::test]
+async fn sequence_number_out_of_range() -> Result<(), NdbError> {
+    let dir = damaged(|lines| {
+        lines[1] = lines[1].replacen("\"seq\":2", &format!("\"seq\":{}", u64::MAX), 1);
+        newline_terminated(lines);
+    })
+    .await?;
+    let err = keys(dir.path(), WalRecovery::TolerateCorruptedTailRecords).await;
+    assert!(matches!(err, Err(NdbError::Corruption { .. })));
+
+    let recovery = WalRecovery::SkipAnyCorruptedRecords;
+    assert_eq!(keys(dir.path(), recovery).await?, ["a", "c"]);
+    let db = Db::with_options(dir.path(), options(recovery)).await?;
+    db.put(b"d", b"1").await?;
+    Ok(())
+}
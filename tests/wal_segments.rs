@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use futures::StreamExt;
+use nulldb::{Db, DbOptions, FileKind, NdbError};
+use tempfile::TempDir;
+
+fn options() -> DbOptions {
+    DbOptions {
+        wal_segment_bytes: Some(500),
+        ..DbOptions::default()
+    }
+}
+
+async fn wal_count(db: &Db) -> Result<usize, NdbError> {
+    let files = db.live_files().await?;
+    Ok(files.iter().filter(|f| f.kind == FileKind::Wal).count())
+}
+
+async fn put_keys(db: &Db, keys: std::ops::Range<u32>) -> Result<(), NdbError> {
+    for i in keys {
+        db.put(format!("key-{:03}", i).as_bytes(), &[b'v'; 20])
+            .await?;
+    }
+    Ok(())
+}
+
+fn file_count(dir: &Path) -> usize {
+    std::fs::read_dir(dir).map_or(0, |entries| entries.count())
+}
+
+#[tokio::test]
+async fn rotates_and_replays_segments() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::with_options(dir.path(), options()).await?;
+    put_keys(&db, 0..100).await?;
+    let segments = wal_count(&db).await?;
+    assert!(segments > 5, "{} segments", segments);
+    drop(db);
+
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    assert_eq!(wal_count(&db).await?, segments);
+    for i in [0, 50, 99] {
+        let key = format!("key-{:03}", i);
+        assert_eq!(db.get(key.as_bytes()).await?, Some(vec![b'v'; 20]));
+    }
+    assert_eq!(db.latest_sequence(), 100);
+
+    // Flushing retires every segment, and with no consumers they all go.
+    db.flush_memtable().await?;
+    assert_eq!(wal_count(&db).await?, 1);
+    drop(db);
+    let db = Db::with_options(dir.path(), options()).await?;
+    assert_eq!(db.get(b"key-050").await?, Some(vec![b'v'; 20]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn archives_instead_of_deleting() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let archive = TempDir::new()?;
+    let options = DbOptions {
+        wal_archive_dir: Some(archive.path().join("wals")),
+        ..options()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    put_keys(&db, 0..100).await?;
+    let segments = wal_count(&db).await?;
+    db.flush_memtable().await?;
+    assert_eq!(file_count(&archive.path().join("wals")), segments);
+    assert_eq!(wal_count(&db).await?, 1);
+
+    let archived: usize = std::fs::read_dir(archive.path().join("wals"))?
+        .map(|entry| {
+            let contents = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            // Less the header.
+            contents.lines().count() - 1
+        })
+        .sum();
+    assert_eq!(archived, 100);
+    Ok(())
+}
+
+#[tokio::test]
+async fn change_feed_follows_rotations() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    put_keys(&db, 0..10).await?;
+
+    {
+        let updates = db.updates_since(0)?;
+        let (seqs, written) = tokio::join!(
+            updates
+                .take(100)
+                .map(|update| update.unwrap().seq)
+                .collect::<Vec<_>>(),
+            put_keys(&db, 10..100),
+        );
+        written?;
+        assert_eq!(seqs, (1..=100).collect::<Vec<_>>());
+    }
+
+    // Segments a consumer hasn't finished with are kept past a flush.
+    db.ack("indexer", 60).await?;
+    db.flush_memtable().await?;
+    let updates = db.updates_since(60)?;
+    let seqs: Vec<_> = updates
+        .take(40)
+        .map(|update| update.unwrap().seq)
+        .collect()
+        .await;
+    assert_eq!(seqs, (61..=100).collect::<Vec<_>>());
+    assert!(db.updates_since(0).is_err());
+    Ok(())
+}
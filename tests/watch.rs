@@ -0,0 +1,62 @@
+use futures::StreamExt;
+use nulldb::{Db, NdbError, WatchEvent, WriteBatch, WriteOptions};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn sees_changes_under_the_prefix() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"user/0", b"before").await?;
+    let events = db.watch(b"user/");
+
+    db.put(b"user/1", b"a").await?;
+    db.put(b"other/1", b"b").await?;
+    let mut batch = WriteBatch::new();
+    batch.put(b"other/2", b"c");
+    batch.delete(b"user/1");
+    db.write(batch).await?;
+    db.delete_range(b"other/", b"other/z").await?;
+    db.delete_range(b"u", b"user/5").await?;
+    drop(db);
+
+    let events: Vec<_> = events.collect().await;
+    assert_eq!(
+        events,
+        [
+            WatchEvent::Put {
+                seq: 2,
+                key: b"user/1".to_vec(),
+                value: b"a".to_vec()
+            },
+            WatchEvent::Delete {
+                seq: 4,
+                key: b"user/1".to_vec()
+            },
+            WatchEvent::DeleteRange {
+                seq: 6,
+                start: b"u".to_vec(),
+                end: b"user/5".to_vec()
+            },
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_falling_behind() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    let mut events = Box::pin(db.watch(b""));
+    let options = WriteOptions {
+        sync: false,
+        disable_wal: true,
+    };
+    let mut batch = WriteBatch::new();
+    batch.put(b"k", b"v");
+    for _ in 0..5000 {
+        db.write_opt(batch.clone(), &options).await?;
+    }
+    assert!(matches!(events.next().await, Some(WatchEvent::Lagged(_))));
+    assert!(matches!(events.next().await, Some(WatchEvent::Put { .. })));
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use nulldb::{Db, DbOptions, JobKind, NdbError, StallAction, WatchdogOptions};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn aborted_flush_keeps_the_memtable() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    // With no threshold, every job counts as stalled on the first check.
+    let options = DbOptions {
+        watchdog: Some(WatchdogOptions {
+            stall_threshold: Duration::ZERO,
+            check_interval: Duration::from_micros(1),
+            action: StallAction::Abort,
+        }),
+        ..DbOptions::default()
+    };
+    let mut db = Db::with_options(dir.path(), options).await?;
+    for i in 0..10_000 {
+        db.put(format!("key{:05}", i).as_bytes(), b"value").await?;
+    }
+
+    match db.flush_memtable().await {
+        Err(NdbError::Stalled(report)) => assert_eq!(report.job, JobKind::Flush),
+        other => panic!("expected a stall, got {:?}", other),
+    }
+    assert_eq!(db.get(b"key00042").await?, Some(b"value".to_vec()));
+    // The half written table was cleaned up.
+    let tables = std::fs::read_dir(dir.path())?
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".sst"))
+        .count();
+    assert_eq!(tables, 0);
+
+    drop(db);
+    let mut db = Db::new(dir.path()).await?;
+    db.flush_memtable().await?;
+    assert_eq!(db.get(b"key00042").await?, Some(b"value".to_vec()));
+    Ok(())
+}
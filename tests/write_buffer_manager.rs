@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use nulldb::{Db, DbOptions, NdbError, WriteBufferManager};
+use tempfile::TempDir;
+
+fn options(manager: &Arc<WriteBufferManager>) -> DbOptions {
+    DbOptions {
+        write_buffer_manager: Some(manager.clone()),
+        ..DbOptions::default()
+    }
+}
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key-{:04}", i).into_bytes()
+}
+
+fn tables(db: &Db) -> u64 {
+    db.stats().levels.iter().map(|level| level.tables).sum()
+}
+
+#[tokio::test]
+async fn keeps_every_database_under_the_budget() -> Result<(), NdbError> {
+    let manager = Arc::new(WriteBufferManager::new(256 << 10));
+    let dirs = [TempDir::new()?, TempDir::new()?, TempDir::new()?];
+    let mut dbs = Vec::new();
+    for dir in &dirs {
+        dbs.push(Db::with_options(dir.path(), options(&manager)).await?);
+    }
+    for i in 0..3000 {
+        dbs[i as usize % 3].put(&key(i), &[b'v'; 200]).await?;
+        // A memtable being flushed still counts until it's written.
+        assert!(manager.memory_usage() < 3 * manager.limit());
+    }
+    for db in &dbs {
+        assert!(tables(db) > 0);
+    }
+    for (i, db) in (0..3000).zip(dbs.iter().cycle()) {
+        assert_eq!(db.get(&key(i)).await?, Some(vec![b'v'; 200]));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn flushes_the_largest_memtable() -> Result<(), NdbError> {
+    let manager = Arc::new(WriteBufferManager::new(48 << 10));
+    let (big_dir, small_dir) = (TempDir::new()?, TempDir::new()?);
+    let big = Db::with_options(big_dir.path(), options(&manager)).await?;
+    let small = Db::with_options(small_dir.path(), options(&manager)).await?;
+    for i in 0..100 {
+        big.put(&key(i), &[b'v'; 200]).await?;
+    }
+    let before = big.stats().memtable.memory_bytes;
+    assert_eq!(manager.memory_usage(), before);
+
+    // Going over the budget from the smaller database asks the bigger one
+    // to flush, which it does on its next write.
+    for i in 0..60 {
+        small.put(&key(i), &[b'v'; 200]).await?;
+    }
+    assert!(manager.memory_usage() > manager.limit());
+    assert_eq!(tables(&small), 0);
+    big.put(b"last", b"v").await?;
+    big.close().await?;
+    let big = Db::new(big_dir.path()).await?;
+    assert_eq!(tables(&big), 1);
+    assert_eq!(tables(&small), 0);
+    assert_eq!(manager.memory_usage(), small.stats().memtable.memory_bytes);
+    Ok(())
+}
+
+#[tokio::test]
+async fn flushes_an_idle_database_when_asked() -> Result<(), NdbError> {
+    let manager = Arc::new(WriteBufferManager::new(48 << 10));
+    let (idle_dir, busy_dir) = (TempDir::new()?, TempDir::new()?);
+    let idle = Arc::new(Db::with_options(idle_dir.path(), options(&manager)).await?);
+    let busy = Db::with_options(busy_dir.path(), options(&manager)).await?;
+    let task = Db::flush_when_asked(&idle);
+    for i in 0..100 {
+        idle.put(&key(i), &[b'v'; 200]).await?;
+    }
+    let before = manager.memory_usage();
+
+    // The busy database goes over the budget, and the idle one, with the
+    // largest memtable, flushes it without being written to again.
+    for i in 0..60 {
+        busy.put(&key(i), &[b'v'; 200]).await?;
+    }
+    for _ in 0..100 {
+        if tables(&idle) > 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(tables(&idle), 1);
+    assert!(manager.memory_usage() < before);
+    assert_eq!(idle.get(&key(99)).await?, Some(vec![b'v'; 200]));
+
+    // It stops once the database is dropped.
+    drop(idle);
+    task.await.unwrap();
+    Ok(())
+}
+
+// Writes go on while the idle-flush task waits for the flush it started,
+// without starting another flush of the same memtable.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn writes_during_a_flush_when_asked() -> Result<(), NdbError> {
+    let manager = Arc::new(WriteBufferManager::new(32 << 10));
+    let (asked_dir, other_dir) = (TempDir::new()?, TempDir::new()?);
+    let asked = Arc::new(Db::with_options(asked_dir.path(), options(&manager)).await?);
+    let other = Db::with_options(other_dir.path(), options(&manager)).await?;
+    let task = Db::flush_when_asked(&asked);
+    let writers: Vec<_> = (0..4)
+        .map(|writer| {
+            let db = asked.clone();
+            tokio::spawn(async move {
+                for i in 0..500 {
+                    db.put(&key(writer * 1000 + i), &[b'v'; 200]).await?;
+                }
+                Ok::<_, NdbError>(())
+            })
+        })
+        .collect();
+    for i in 0..2000 {
+        other.put(&key(i), &[b'v'; 20]).await?;
+    }
+    for writer in writers {
+        writer.await.unwrap()?;
+    }
+    assert!(tables(&asked) > 0);
+
+    drop(Arc::into_inner(asked).unwrap());
+    task.await.unwrap();
+    let asked = Db::new(asked_dir.path()).await?;
+    for writer in 0..4 {
+        for i in 0..500 {
+            let value = asked.get(&key(writer * 1000 + i)).await?;
+            assert_eq!(value, Some(vec![b'v'; 200]));
+        }
+    }
+    Ok(())
+}
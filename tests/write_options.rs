@@ -0,0 +1,99 @@
+use nulldb::{Db, DbOptions, NdbError, ValidationError, WriteBatch, WriteOptions};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn batches_are_applied_together() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"b", b"old").await?;
+
+    let mut batch = WriteBatch::new();
+    batch.put(b"a", b"1");
+    batch.delete(b"b");
+    batch.put(b"c", b"1");
+    batch.put(b"c", b"2");
+    db.write(batch).await?;
+    assert_eq!(db.get(b"a").await?, Some(b"1".to_vec()));
+    assert_eq!(db.get(b"b").await?, None);
+    assert_eq!(db.get(b"c").await?, Some(b"2".to_vec()));
+
+    drop(db);
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"b").await?, None);
+    assert_eq!(db.get(b"c").await?, Some(b"2".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_batches_write_nothing() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        validator: Some(std::sync::Arc::new(|key: &[u8], _: &[u8]| {
+            if key.is_empty() {
+                return Err(ValidationError::InvalidKey("empty".into()));
+            }
+            Ok(())
+        })),
+        ..DbOptions::default()
+    };
+    let db = Db::with_options(dir.path(), options).await?;
+    let mut batch = WriteBatch::new();
+    batch.put(b"a", b"1");
+    batch.put(b"", b"1");
+    assert!(matches!(
+        db.write(batch).await,
+        Err(NdbError::Validation(_))
+    ));
+    assert_eq!(db.get(b"a").await?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn unsynced_and_unlogged_writes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::new(dir.path()).await?;
+    let no_sync = WriteOptions {
+        sync: false,
+        ..WriteOptions::default()
+    };
+    let no_wal = WriteOptions {
+        disable_wal: true,
+        ..WriteOptions::default()
+    };
+    for i in 0..10 {
+        db.put_opt(format!("synced{}", i).as_bytes(), b"v", &no_sync)
+            .await?;
+    }
+    db.sync_wal().await?;
+    assert_eq!(db.stats().writes.wal_syncs, 1);
+
+    db.put_opt(b"unlogged", b"v", &no_wal).await?;
+    assert_eq!(db.get(b"unlogged").await?, Some(b"v".to_vec()));
+
+    // Without a flush, the write that skipped the WAL doesn't survive.
+    drop(db);
+    db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"synced9").await?, Some(b"v".to_vec()));
+    assert_eq!(db.get(b"unlogged").await?, None);
+
+    db.put_opt(b"unlogged", b"v", &no_wal).await?;
+    db.flush_memtable().await?;
+    drop(db);
+    let db = Db::new(dir.path()).await?;
+    assert_eq!(db.get(b"unlogged").await?, Some(b"v".to_vec()));
+    Ok(())
+}
+
+#[test]
+fn options_debug_without_their_hooks() {
+    let options = DbOptions {
+        block_size: 8192,
+        validator: Some(std::sync::Arc::new(|_: &[u8], _: &[u8]| Ok(()))),
+        ..DbOptions::default()
+    };
+    let debug = format!("{:?}", options);
+    assert!(debug.starts_with("DbOptions {"));
+    assert!(debug.contains("block_size: 8192"));
+    assert!(!debug.contains("validator"));
+    assert!(debug.ends_with(".. }"));
+}
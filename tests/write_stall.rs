@@ -0,0 +1,157 @@
+use std::time::{Duration, Instant};
+
+use nulldb::{
+    Db, DbOptions, NdbError, VacuumOptions, WriteStallOptions, WriteStallReason, WriteStallState,
+};
+use tempfile::TempDir;
+
+fn options() -> DbOptions {
+    DbOptions {
+        write_stall: Some(WriteStallOptions {
+            slowdown_tables: 2,
+            stop_tables: 4,
+            delay: Duration::from_millis(50),
+            max_delay: Duration::from_millis(150),
+            ..WriteStallOptions::default()
+        }),
+        ..DbOptions::default()
+    }
+}
+
+// Writes a key and flushes it into a table of its own.
+async fn add_table(db: &mut Db, i: u32) -> Result<(), NdbError> {
+    db.put(&i.to_be_bytes(), b"v").await?;
+    db.flush_memtable().await
+}
+
+#[tokio::test]
+async fn delays_then_stops_writes_as_tables_pile_up() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    add_table(&mut db, 0).await?;
+    assert_eq!(db.stats().write_stall.state, WriteStallState::Normal);
+    assert_eq!(db.stats().write_stall.delayed_writes, 0);
+
+    add_table(&mut db, 1).await?;
+    let stall = db.stats().write_stall;
+    assert_eq!(stall.state, WriteStallState::Delayed);
+    assert_eq!(
+        stall.reasons,
+        vec![WriteStallReason::Tables {
+            tables: 2,
+            threshold: 2
+        }]
+    );
+    let started = Instant::now();
+    db.put(b"delayed", b"v").await?;
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    assert_eq!(db.stats().write_stall.delayed_writes, 1);
+
+    // Closer to the stop threshold, writes are held up for longer.
+    db.flush_memtable().await?;
+    let started = Instant::now();
+    db.put(b"delayed", b"v").await?;
+    assert!(started.elapsed() >= Duration::from_millis(100));
+
+    add_table(&mut db, 3).await?;
+    assert_eq!(db.stats().write_stall.state, WriteStallState::Stopped);
+    let err = db.put(b"stopped", b"v").await.unwrap_err();
+    assert!(matches!(
+        &err,
+        NdbError::WritesStopped(reasons)
+            if reasons == &[WriteStallReason::Tables { tables: 4, threshold: 4 }]
+    ));
+    assert_eq!(db.stats().write_stall.stopped_writes, 1);
+
+    // Merging the tables lets writes go ahead again.
+    db.vacuum().await?;
+    let stall = db.stats().write_stall;
+    assert_eq!(stall.state, WriteStallState::Normal);
+    assert!(stall.reasons.is_empty());
+    db.put(b"stopped", b"v").await?;
+    assert_eq!(db.get(b"stopped").await?, Some(b"v".to_vec()));
+    assert_eq!(db.get(&3u32.to_be_bytes()).await?, Some(b"v".to_vec()));
+    Ok(())
+}
+
+// With vacuums on, a write past the stop threshold waits for one to merge
+// the tables instead, and is only rejected if there's nothing to merge.
+#[tokio::test]
+async fn waits_for_a_vacuum_instead_of_stopping() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut db = Db::with_options(dir.path(), options()).await?;
+    for i in 0..4 {
+        add_table(&mut db, i).await?;
+    }
+    drop(db);
+
+    let vacuuming = |small_table_bytes| DbOptions {
+        vacuum: Some(VacuumOptions {
+            small_table_bytes,
+            min_tables: 2,
+            ..VacuumOptions::default()
+        }),
+        ..options()
+    };
+    // Every table is too big to count as small.
+    let db = Db::with_options(dir.path(), vacuuming(1)).await?;
+    assert!(matches!(
+        db.put(b"stopped", b"v").await,
+        Err(NdbError::WritesStopped(_))
+    ));
+    drop(db);
+
+    let db = Db::with_options(dir.path(), vacuuming(1 << 20)).await?;
+    assert_eq!(db.stats().write_stall.state, WriteStallState::Stopped);
+    db.put(b"waited", b"v").await?;
+    let stall = db.stats().write_stall;
+    assert_eq!((stall.vacuum_waits, stall.stopped_writes), (1, 0));
+    assert_eq!(stall.state, WriteStallState::Normal);
+    assert_eq!(db.get(b"waited").await?, Some(b"v".to_vec()));
+    assert_eq!(db.get(&3u32.to_be_bytes()).await?, Some(b"v".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn stops_on_table_bytes() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let mut options = options();
+    let stall = options.write_stall.as_mut().unwrap();
+    stall.slowdown_tables = 100;
+    stall.stop_tables = 100;
+    stall.stop_table_bytes = Some(1);
+    let mut db = Db::with_options(dir.path(), options).await?;
+    add_table(&mut db, 0).await?;
+    let err = db.put(b"stopped", b"v").await.unwrap_err();
+    assert!(matches!(
+        &err,
+        NdbError::WritesStopped(reasons)
+            if matches!(reasons[..], [WriteStallReason::TableBytes { threshold: 1, .. }])
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn waits_for_a_flush_that_is_behind() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        write_buffer_size: Some(64 << 10),
+        write_stall: Some(WriteStallOptions::default()),
+        ..DbOptions::default()
+    };
+    let db = Db::with_options(dir.path(), options).await?;
+    for i in 0..2000u32 {
+        db.put(&i.to_be_bytes(), &[b'v'; 100]).await?;
+    }
+    let stats = db.stats();
+    // The memtable never grew much past its limit while the one before it
+    // was being written.
+    assert!(
+        stats.memtable.memory_bytes < 4 * (64 << 10),
+        "{:?}",
+        stats.memtable
+    );
+    assert_eq!(stats.write_stall.stopped_writes, 0);
+    assert_eq!(db.get(&1999u32.to_be_bytes()).await?, Some(vec![b'v'; 100]));
+    Ok(())
+}
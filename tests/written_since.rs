@@ -0,0 +1,52 @@
+use nulldb::{Db, DbOptions, NdbError, WriteBatch};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn sees_writes_after_the_sequence() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let db = Db::new(dir.path()).await?;
+    db.put(b"a", b"1").await?;
+    let seq = db.latest_sequence();
+    assert!(!db.written_since(seq, [&b"a"[..]]));
+
+    db.put(b"b", b"2").await?;
+    assert_eq!(db.latest_sequence(), seq + 1);
+    assert!(db.written_since(seq, [&b"b"[..]]));
+    assert!(!db.written_since(seq, [&b"a"[..], &b"c"[..]]));
+
+    let mut batch = WriteBatch::new();
+    batch.put(b"x", b"1");
+    batch.delete_range(b"c", b"e");
+    db.write(batch).await?;
+    assert_eq!(db.latest_sequence(), seq + 2);
+    assert!(db.written_since(seq + 1, [&b"d"[..]]));
+    assert!(!db.written_since(seq + 1, [&b"e"[..]]));
+
+    let stats = db.stats().conflicts;
+    assert_eq!(stats.tracked_commits, 3);
+    assert_eq!((stats.checks, stats.conflicts), (5, 2));
+    assert!(stats.memory_bytes > 0);
+    Ok(())
+}
+
+// Past the window, a check can't rule anything out.
+#[tokio::test]
+async fn assumes_a_conflict_beyond_the_window() -> Result<(), NdbError> {
+    let dir = TempDir::new()?;
+    let options = DbOptions {
+        conflict_window: 4,
+        ..DbOptions::default()
+    };
+    let db = Db::with_options(dir.path(), options).await?;
+    let seq = db.latest_sequence();
+    for i in 0..10u8 {
+        db.put(&[i], b"v").await?;
+    }
+    assert!(db.written_since(seq, [&b"untouched"[..]]));
+    assert!(!db.written_since(seq + 6, [&b"untouched"[..]]));
+
+    let stats = db.stats().conflicts;
+    assert_eq!(stats.tracked_commits, 4);
+    assert_eq!((stats.conflicts, stats.window_conflicts), (1, 1));
+    Ok(())
+}